@@ -0,0 +1,212 @@
+//! Checking installed versions against published vulnerabilities
+//!
+//! `gleam-pkg audit` asks the [OSV](https://osv.dev) database, which collects the GitHub
+//! advisories of the Hex ecosystem, whether any installed version, or with `--deps` any
+//! package in its resolved dependency tree, is affected by a known vulnerability. The OSV API
+//! can be pointed elsewhere, e.g. at an internal mirror:
+//!
+//! ```toml
+//! [audit]
+//! osv_api = "https://api.osv.dev/v1/"
+//! ```
+
+use crate::config::{AuditConfig, HttpConfig};
+use crate::error::GleamPkgError;
+use crate::http;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+/// The OSV ecosystem of packages published to hex.pm
+const ECOSYSTEM: &str = "Hex";
+
+/// A package at a version to look up
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Query {
+    pub package: String,
+    pub version: String,
+}
+
+/// A published vulnerability affecting a queried version
+#[derive(Debug, Clone)]
+pub struct Advisory {
+    /// The OSV id, e.g. `GHSA-xxxx-xxxx-xxxx`
+    pub id: String,
+    /// Other ids of the same vulnerability, e.g. its CVE
+    pub aliases: Vec<String>,
+    pub summary: String,
+    /// The severity as the advisory database rates it, e.g. `HIGH`
+    pub severity: Option<String>,
+    /// The oldest version newer than the queried one that fixes it
+    pub fixed_in: Option<String>,
+}
+
+/// Looks up every query in the OSV database
+///
+/// # Arguments
+///
+/// * `config` - Where the OSV API is
+/// * `http` - The HTTP settings
+/// * `queries` - The versions to look up
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or
+/// `GleamPkgError::InvalidResponse` if the OSV API cannot be asked
+///
+/// # Returns
+///
+/// The advisories affecting each query, in the order of `queries`
+pub fn check(
+    config: &AuditConfig,
+    http: &HttpConfig,
+    queries: &[Query],
+) -> Result<Vec<Vec<Advisory>>, GleamPkgError> {
+    let client = http::client(http)?;
+    let base = match config.osv_api.ends_with('/') {
+        true => config.osv_api.clone(),
+        false => format!("{}/", config.osv_api),
+    };
+    let get_json = |request: reqwest::blocking::RequestBuilder, url: &str| {
+        let response = http::send(request).map_err(|source| GleamPkgError::RequestFailed {
+            url: url.to_string(),
+            source,
+        })?;
+        if !response.status().is_success() {
+            return Err(GleamPkgError::HttpStatus {
+                url: url.to_string(),
+                status: response.status().as_u16(),
+            });
+        }
+        response
+            .json::<Value>()
+            .map_err(|source| GleamPkgError::InvalidResponse {
+                url: url.to_string(),
+                source,
+            })
+    };
+
+    let url = format!("{}querybatch", base);
+    let body = json!({
+        "queries": queries.iter().map(|query| json!({
+            "package": { "name": query.package, "ecosystem": ECOSYSTEM },
+            "version": query.version,
+        })).collect::<Vec<_>>(),
+    });
+    let batch = get_json(client.post(&url).json(&body), &url)?;
+
+    // the batch only names the vulnerabilities, their details are fetched once each
+    let mut details: BTreeMap<String, Value> = BTreeMap::new();
+    let mut results = Vec::new();
+    for (query, result) in queries
+        .iter()
+        .zip(batch["results"].as_array().into_iter().flatten())
+    {
+        let mut advisories = Vec::new();
+        for id in result["vulns"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|vuln| vuln["id"].as_str())
+        {
+            if !details.contains_key(id) {
+                let url = format!("{}vulns/{}", base, id);
+                details.insert(id.to_string(), get_json(client.get(&url), &url)?);
+            }
+            advisories.push(advisory(&details[id], query));
+        }
+        results.push(advisories);
+    }
+    results.resize(queries.len(), Vec::new());
+    Ok(results)
+}
+
+/// The advisory an OSV vulnerability document describes for `query`
+fn advisory(vuln: &Value, query: &Query) -> Advisory {
+    let strings = |value: &Value| -> Vec<String> {
+        value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|s| s.as_str().map(String::from))
+            .collect()
+    };
+    let id = vuln["id"].as_str().unwrap_or_default().to_string();
+    let summary = vuln["summary"]
+        .as_str()
+        .or_else(|| vuln["details"].as_str())
+        .unwrap_or_default()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    Advisory {
+        id,
+        aliases: strings(&vuln["aliases"]),
+        summary,
+        severity: vuln["database_specific"]["severity"]
+            .as_str()
+            .map(String::from),
+        fixed_in: fixed_in(vuln, query),
+    }
+}
+
+/// The oldest version fixing `vuln` that is newer than the queried one
+fn fixed_in(vuln: &Value, query: &Query) -> Option<String> {
+    let installed = semver::Version::parse(&query.version).ok();
+    vuln["affected"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|affected| {
+            affected["package"]["ecosystem"] == ECOSYSTEM
+                && affected["package"]["name"] == query.package.as_str()
+        })
+        .flat_map(|affected| affected["ranges"].as_array().into_iter().flatten())
+        .flat_map(|range| range["events"].as_array().into_iter().flatten())
+        .filter_map(|event| event["fixed"].as_str())
+        .filter_map(|fixed| semver::Version::parse(fixed).ok())
+        .filter(|fixed| installed.as_ref().is_none_or(|installed| fixed > installed))
+        .min()
+        .map(|fixed| fixed.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_the_oldest_newer_fix() {
+        let vuln = json!({
+            "id": "GHSA-aaaa-bbbb-cccc",
+            "aliases": ["CVE-2024-0001"],
+            "summary": "Denial of service in wisp",
+            "database_specific": { "severity": "HIGH" },
+            "affected": [
+                {
+                    "package": { "ecosystem": "Hex", "name": "wisp" },
+                    "ranges": [{
+                        "type": "SEMVER",
+                        "events": [
+                            { "introduced": "0" }, { "fixed": "0.9.1" },
+                            { "introduced": "1.0.0" }, { "fixed": "1.2.3" },
+                        ],
+                    }],
+                },
+                {
+                    "package": { "ecosystem": "Hex", "name": "other" },
+                    "ranges": [{ "type": "SEMVER", "events": [{ "fixed": "1.0.1" }] }],
+                },
+            ],
+        });
+        let query = |version: &str| Query {
+            package: "wisp".to_string(),
+            version: version.to_string(),
+        };
+
+        let advisory = advisory(&vuln, &query("1.0.0"));
+        assert_eq!(advisory.fixed_in.as_deref(), Some("1.2.3"));
+        assert_eq!(advisory.severity.as_deref(), Some("HIGH"));
+        assert_eq!(advisory.aliases, ["CVE-2024-0001"]);
+        assert_eq!(fixed_in(&vuln, &query("0.5.0")).as_deref(), Some("0.9.1"));
+    }
+}
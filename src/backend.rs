@@ -0,0 +1,1245 @@
+//! Build backends
+//!
+//! A backend turns the sources of a package into something runnable: it prepares the project to
+//! build, for Gleam packages a scratch project compiled for the backend's Gleam target, lays out
+//! the artifacts under `~/.gleam_pkgs/lib/<package>-<version>` and generates the wrapper script
+//! that ends up on `PATH`. Erlang and Elixir packages, whose tarballs declare rebar3 or mix as
+//! their build tool, are built into escripts by that tool instead.
+
+use crate::buildlog::BuildLog;
+use crate::error::GleamPkgError;
+use crate::limits::BuildLimits;
+use crate::runner::{Runner, describe_status};
+use crate::store::Store;
+use crate::{copy_dir_all, erlang, escript, output, stats, toolchain};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+/// Name of the scratch project each package is built through, which is also the name of its
+/// entry module
+pub const BUILD_PROJECT: &str = "gleam_pkg_build";
+
+/// Everything a backend needs to know about the package being built
+pub struct BuildContext<'a> {
+    pub package: &'a str,
+    pub version: &'a str,
+    /// The project the build runs in, see [`Backend::prepare`]
+    pub project_dir: &'a Path,
+    /// `~/.gleam_pkgs/lib/<package>-<version>`, empty when `build` is called
+    pub app_dir: &'a Path,
+    /// Where single-file artifacts are kept, see [`crate::store`]
+    pub store: &'a Store,
+    /// Where the OTP installations of gleam-pkg are kept, see [`crate::toolchains`]
+    pub toolchains: &'a Path,
+    pub limits: &'a BuildLimits,
+    /// How the wrapper runs an escript
+    pub wrapper: WrapperMode,
+}
+
+/// How the wrapper of an escript runs it, chosen with `--wrapper` or `wrapper` in config.toml;
+/// the builds of other backends are always run directly
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WrapperMode {
+    /// Embed the escript in the wrapper and run a copy of it in a temporary directory, so the
+    /// wrapper keeps working without the store
+    Embedded,
+    /// Run the escript in the store directly
+    #[default]
+    Exec,
+    /// Make the command a link to the escript in the store, which starts fastest but runs on
+    /// the `escript` on `PATH` without choosing a compatible runtime or the environment
+    Symlink,
+}
+
+/// The line of an embedded wrapper after which the escript follows
+pub const EMBEDDED_MARKER: &str = "__ESCRIPT__";
+
+/// The runnable result of a build
+pub struct Artifact {
+    /// The file the wrapper executes
+    pub path: PathBuf,
+    /// The runtime the artifact was built with, e.g. the Erlang system version
+    pub runtime: String,
+    /// The OTP release the artifact was compiled on, for backends running on the BEAM
+    pub otp_release: Option<u32>,
+    /// The command the package exposes: the `name` in its `gleam.toml`, or the name of the
+    /// escript rebar3 or mix built
+    pub binary: String,
+    /// The store blob `path` points at, for artifacts kept in the store
+    pub blob: Option<String>,
+    /// The root of the Erlang/OTP installation embedded with the artifact, which the wrapper
+    /// runs it on instead of looking for a runtime
+    pub erts: Option<PathBuf>,
+}
+
+/// A way of building and running a package
+pub trait Backend {
+    /// Name of the backend as accepted by `--target`
+    fn name(&self) -> &'static str;
+
+    /// The build tool packages built by this backend declare, see [`build_tools`]
+    fn build_tool(&self) -> &'static str;
+
+    /// Prepares the project `build` runs in for a package extracted to `extract_dir`, returning
+    /// its directory
+    fn prepare(&self, extract_dir: &Path, package: &str) -> Result<PathBuf, GleamPkgError>;
+
+    /// Checks that the runtime is available, returning a description of it
+    fn check_runtime(&self, limits: &BuildLimits) -> Result<String, GleamPkgError>;
+
+    /// Builds the scratch project and lays out its artifacts in `ctx.app_dir`
+    fn build(&self, ctx: &BuildContext, log: &mut BuildLog) -> Result<Artifact, GleamPkgError>;
+
+    /// Generates the wrapper script that runs `artifact`
+    fn wrapper(&self, ctx: &BuildContext, artifact: &Artifact) -> Result<String, GleamPkgError>;
+}
+
+/// Backends selectable with `--target`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// Bundle the package into an escript run by the Erlang VM
+    Erlang,
+    /// Compile to JavaScript and run it with Node.js
+    #[serde(alias = "nodejs")]
+    Node,
+    /// Compile to JavaScript and run it with Deno
+    Deno,
+    /// Build an Erlang package into an escript with `rebar3 escriptize`
+    Rebar3,
+    /// Build an Elixir package into an escript with `mix escript.build`
+    Mix,
+}
+
+impl Target {
+    pub fn backend(self) -> Box<dyn Backend> {
+        match self {
+            Target::Erlang => Box::new(ErlangEscript),
+            Target::Node => Box::new(NodeJs),
+            Target::Deno => Box::new(Deno),
+            Target::Rebar3 => Box::new(Rebar3Escript),
+            Target::Mix => Box::new(MixEscript),
+        }
+    }
+
+    /// Picks the target for a package extracted to `extract_dir` from the build tools its
+    /// tarball declares: rebar3 or mix for Erlang and Elixir packages, and for Gleam packages
+    /// the `target` field of their `gleam.toml`, defaulting to Erlang like the Gleam compiler
+    /// does
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::UnsupportedBuildTool` if the package is built with none of
+    /// gleam, rebar3 or mix, `GleamPkgError::Io` if its metadata or `gleam.toml` cannot be
+    /// read, or `GleamPkgError::InvalidManifest` if `gleam.toml` cannot be parsed
+    pub fn detect(
+        extract_dir: &Path,
+        package: &str,
+        version: &str,
+    ) -> Result<Target, GleamPkgError> {
+        let tools = build_tools(extract_dir)?;
+        let declares = |tool: &str| tools.iter().any(|t| t == tool);
+        if !tools.is_empty() && !declares("gleam") {
+            // Erlang packages may also ship a mix.exs, rebar3 builds them without Elixir
+            return match (declares("rebar3"), declares("mix")) {
+                (true, _) => Ok(Target::Rebar3),
+                (false, true) => Ok(Target::Mix),
+                (false, false) => Err(GleamPkgError::UnsupportedBuildTool {
+                    package: package.to_string(),
+                    version: version.to_string(),
+                    tools,
+                }),
+            };
+        }
+        let gleam_toml = extract_dir.join("contents").join("gleam.toml");
+        let manifest = fs::read_to_string(&gleam_toml)
+            .map_err(|source| GleamPkgError::Io {
+                action: "read package manifest",
+                path: gleam_toml.clone(),
+                source,
+            })?
+            .parse::<toml::Table>()
+            .map_err(|source| GleamPkgError::InvalidManifest {
+                path: gleam_toml.clone(),
+                source,
+            })?;
+        match manifest.get("target").and_then(|t| t.as_str()) {
+            Some("javascript") => Ok(Target::Node),
+            _ => Ok(Target::Erlang),
+        }
+    }
+}
+
+/// The build tools the `metadata.config` of an extracted hex tarball declares, e.g.
+/// `["rebar3"]` for `{<<"build_tools">>,[<<"rebar3">>]}.`
+///
+/// Tarballs from before hex recorded build tools, or without a `metadata.config`, declare none.
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if `metadata.config` exists but cannot be read
+pub fn build_tools(extract_dir: &Path) -> Result<Vec<String>, GleamPkgError> {
+    let path = extract_dir.join("metadata.config");
+    let config = match fs::read_to_string(&path) {
+        Ok(config) => config,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(source) => {
+            return Err(GleamPkgError::Io {
+                action: "read tarball metadata",
+                path,
+                source,
+            });
+        }
+    };
+    // the metadata is a list of Erlang terms, only the binaries in the build_tools list matter
+    let Some(list) = config
+        .split_once("<<\"build_tools\">>")
+        .and_then(|(_, rest)| rest.split_once('['))
+        .and_then(|(_, rest)| rest.split_once(']'))
+        .map(|(list, _)| list)
+    else {
+        return Ok(Vec::new());
+    };
+    Ok(list
+        .split('"')
+        .skip(1)
+        .step_by(2)
+        .map(String::from)
+        .collect())
+}
+
+/// Checks, before a package whose target is not known yet is downloaded, that something could
+/// run it: Erlang/OTP, which runs Gleam packages by default and Erlang and Elixir packages, or
+/// Node.js for Gleam packages targeting JavaScript
+///
+/// The runtime the detected target needs is checked once the package is extracted.
+///
+/// # Errors
+///
+/// Returns the error of the Erlang/OTP check, usually `GleamPkgError::ToolchainMissing`, if
+/// neither is available
+pub fn check_any_runtime(limits: &BuildLimits) -> Result<(), GleamPkgError> {
+    let erlang = ErlangEscript.check_runtime(limits);
+    if erlang.is_err() && NodeJs.check_runtime(limits).is_ok() {
+        return Ok(());
+    }
+    erlang.map(|_| ())
+}
+
+/// Checks that a package extracted to `extract_dir` is built with the build tool of `backend`,
+/// before e.g. a Gleam build of an Erlang package fails with a confusing `gleam` error
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::WrongBuildTool` if its tarball declares build tools, none of them
+/// the one of `backend`, or `GleamPkgError::Io` if they cannot be read
+pub fn check_build_tools(
+    extract_dir: &Path,
+    package: &str,
+    version: &str,
+    backend: &dyn Backend,
+) -> Result<(), GleamPkgError> {
+    let tools = build_tools(extract_dir)?;
+    if tools.is_empty() || tools.iter().any(|tool| tool == backend.build_tool()) {
+        return Ok(());
+    }
+    Err(GleamPkgError::WrongBuildTool {
+        package: package.to_string(),
+        version: version.to_string(),
+        tools,
+        backend: backend.name(),
+    })
+}
+
+/// Writes the scratch Gleam project used to build a package
+///
+/// The project depends on the extracted package by path and its entry module, provided by the
+/// backend, just calls the package's `main`, so the package sources are never modified.
+///
+/// # Arguments
+///
+/// * `extract_dir` - The directory the package tarball was extracted to
+/// * `package` - The name of the package
+/// * `gleam_target` - The Gleam compilation target of the project
+/// * `shim_module` - Source of the project's entry module, calling the package's `main`
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the project files cannot be written
+///
+/// # Returns
+///
+/// The directory of the scratch project
+///
+fn write_build_project(
+    extract_dir: &Path,
+    package: &str,
+    gleam_target: &str,
+    shim_module: &str,
+) -> Result<PathBuf, GleamPkgError> {
+    let project_dir = extract_dir.join(BUILD_PROJECT);
+    let src_dir = project_dir.join("src");
+    let gleam_toml = format!(
+        r#"name = "{BUILD_PROJECT}"
+version = "1.0.0"
+target = "{gleam_target}"
+
+[dependencies]
+{package} = {{ path = "../contents" }}
+"#
+    );
+    // start from a clean project so artifacts of a previous build with another target are gone
+    let _ = fs::remove_dir_all(&project_dir);
+    fs::create_dir_all(&src_dir)
+        .and_then(|_| fs::write(project_dir.join("gleam.toml"), gleam_toml))
+        .and_then(|_| fs::write(src_dir.join(format!("{BUILD_PROJECT}.gleam")), shim_module))
+        .map_err(|source| GleamPkgError::Io {
+            action: "write build project",
+            path: project_dir.clone(),
+            source,
+        })?;
+    Ok(project_dir)
+}
+
+/// Runs `gleam build` for `target` in the scratch project, recording the output in `log`
+fn gleam_build(ctx: &BuildContext, log: &mut BuildLog, target: &str) -> Result<(), GleamPkgError> {
+    let (cmd, limits) = toolchain::gleam_command(Some(ctx.project_dir), ctx.limits);
+    let output = Runner::new(cmd)
+        .args(["build", "--target", target])
+        .limits(&limits)
+        .describe(format!("`gleam build` in {}", ctx.project_dir.display()))
+        .tee()
+        .run()?;
+    log.record("gleam build", &output)?;
+    if !output.status.success() {
+        return Err(GleamPkgError::BuildFailed {
+            package: ctx.package.to_string(),
+            version: ctx.version.to_string(),
+            status: describe_status(&output.status),
+            log: log.path().to_path_buf(),
+        });
+    }
+    Ok(())
+}
+
+/// Bundles the package into an escript embedded in a shell wrapper
+pub struct ErlangEscript;
+
+impl Backend for ErlangEscript {
+    fn name(&self) -> &'static str {
+        "erlang"
+    }
+
+    fn build_tool(&self) -> &'static str {
+        "gleam"
+    }
+
+    fn prepare(&self, extract_dir: &Path, package: &str) -> Result<PathBuf, GleamPkgError> {
+        // escripts enter through main/1
+        let shim_module = format!(
+            r#"import {package}
+
+pub fn main(_args) {{
+  {package}.main()
+}}
+"#
+        );
+        write_build_project(extract_dir, package, "erlang", &shim_module)
+    }
+
+    fn check_runtime(&self, limits: &BuildLimits) -> Result<String, GleamPkgError> {
+        let otp_release = toolchain::check_otp(limits)?;
+        Ok(format!(
+            "Erlang/OTP {} (ERTS {})",
+            otp_release,
+            erlang::erts_version(limits)?
+        ))
+    }
+
+    fn build(&self, ctx: &BuildContext, log: &mut BuildLog) -> Result<Artifact, GleamPkgError> {
+        gleam_build(ctx, log, "erlang")?;
+
+        // now we need to get current running erlang vm's version and other info
+        // and embed it into the comment section of the escript
+
+        let (erlang_version, otp_release) = erlang_runtime(ctx.limits)?;
+
+        let escript_path = ctx.project_dir.join("build").join(BUILD_PROJECT);
+        stats::time("escript", || {
+            escript::build_escript(
+                &ctx.project_dir.join("build"),
+                BUILD_PROJECT,
+                &format!(
+                    "{} {} built by gleam-pkg on {}",
+                    ctx.package, ctx.version, erlang_version
+                ),
+                &escript_path,
+            )
+        })?;
+        store_escript(
+            ctx,
+            &escript_path,
+            gleam_name(ctx),
+            erlang_version,
+            otp_release,
+        )
+    }
+
+    fn wrapper(&self, ctx: &BuildContext, artifact: &Artifact) -> Result<String, GleamPkgError> {
+        escript_wrapper(ctx, artifact)
+    }
+}
+
+/// The system version and OTP release of the Erlang runtime escripts are built with
+fn erlang_runtime(limits: &BuildLimits) -> Result<(String, u32), GleamPkgError> {
+    let erlang_version = erlang::system_version(limits)?;
+    output::info(format!("Erlang system version: {}", erlang_version));
+    Ok((erlang_version, toolchain::check_otp(limits)?))
+}
+
+/// Moves a built escript into the store
+fn store_escript(
+    ctx: &BuildContext,
+    escript: &Path,
+    binary: String,
+    erlang_version: String,
+    otp_release: u32,
+) -> Result<Artifact, GleamPkgError> {
+    let blob = ctx.store.put(escript)?;
+    let stored = ctx.store.path(&blob);
+    output::info(format!("Escript stored as: {}", stored.display()));
+
+    Ok(Artifact {
+        path: stored,
+        runtime: erlang_version,
+        otp_release: Some(otp_release),
+        binary,
+        blob: Some(blob),
+        erts: None,
+    })
+}
+
+/// The Gleam versions a package extracted to `extract_dir` builds with, the `gleam` requirement
+/// in its `gleam.toml`, e.g. `>= 1.4.0`, or `None` if it declares none
+pub fn gleam_requirement(extract_dir: &Path) -> Option<String> {
+    let toml = fs::read_to_string(extract_dir.join("contents").join("gleam.toml")).ok()?;
+    let toml = toml.parse::<toml::Table>().ok()?;
+    Some(toml.get("gleam")?.as_str()?.to_string())
+}
+
+/// The `name` in the `gleam.toml` of a Gleam package, which it is published under, so usually
+/// the package name
+fn gleam_name(ctx: &BuildContext) -> String {
+    ctx.project_dir
+        .parent()
+        .and_then(|dir| fs::read_to_string(dir.join("contents").join("gleam.toml")).ok())
+        .and_then(|toml| toml.parse::<toml::Table>().ok())
+        .and_then(|toml| Some(toml.get("name")?.as_str()?.to_string()))
+        .unwrap_or_else(|| ctx.package.to_string())
+}
+
+/// The name of an escript, which is the command it is run as
+fn escript_name(escript: &Path) -> String {
+    escript
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Generates the wrapper of an escript in the store
+fn escript_wrapper(ctx: &BuildContext, artifact: &Artifact) -> Result<String, GleamPkgError> {
+    // this wrapper looks for an erl compatible with the OTP release the escript was
+    // compiled on among every installation it can find, and runs the stored escript with it
+    let erlang_version = sh_quote(&artifact.runtime);
+    let otp_release = artifact
+        .otp_release
+        .ok_or_else(|| GleamPkgError::UnknownOtpRelease {
+            package: ctx.package.to_string(),
+        })?;
+    let package = ctx.package;
+    if let Some(erts) = &artifact.erts {
+        return Ok(embedded_escript_wrapper(ctx, artifact, erts));
+    }
+    let toolchains = sh_quote_path(ctx.toolchains);
+    let check = check_escript(package, artifact);
+    let release_probe = sh_quote(&erlang::release_probe());
+    let (source, run) = escript_source(ctx, artifact);
+
+    Ok(format!(
+        r#"#!/bin/sh
+# This is a wrapper script for the escript generated by gleam-pkg
+
+{source}
+COMPILED_ERLANG_VERSION={erlang_version}
+COMPILED_OTP_RELEASE="{otp_release}"
+# BEAM files can be loaded by the OTP release they were compiled on and the two after it
+MAX_OTP_RELEASE=$((COMPILED_OTP_RELEASE + 2))
+
+{check}
+# Every erl that might be installed: explicit overrides first, then PATH, kerl, asdf, mise and
+# the installations of gleam-pkg
+candidates() {{
+    [ -n "$GLEAM_PKG_ERL" ] && echo "$GLEAM_PKG_ERL"
+    [ -n "$ERLANG_HOME" ] && echo "$ERLANG_HOME/bin/erl"
+    command -v erl
+    KERL_INSTALLATIONS="${{KERL_BASE_DIR:-$HOME/.kerl}}/otp_installations"
+    if [ -f "$KERL_INSTALLATIONS" ]; then
+        while read -r _ dir; do echo "$dir/bin/erl"; done < "$KERL_INSTALLATIONS"
+    fi
+    for dir in "${{ASDF_DATA_DIR:-$HOME/.asdf}}"/installs/erlang/* \
+        "${{MISE_DATA_DIR:-$HOME/.local/share/mise}}"/installs/erlang/* \
+        {toolchains}/otp-*; do
+        echo "$dir/bin/erl"
+    done
+}}
+
+# Pick the erl of the same OTP release, or else the closest newer compatible one
+SELECTED=""
+SELECTED_RELEASE=""
+FOUND=""
+while IFS= read -r candidate; do
+    [ -x "$candidate" ] || continue
+    release=$("$candidate" -noshell -eval {release_probe} 2>/dev/null)
+    case "$release" in
+        ''|*[!0-9]*) continue ;;
+    esac
+    FOUND="$FOUND
+    $candidate (OTP $release)"
+    if [ "$release" -eq "$COMPILED_OTP_RELEASE" ]; then
+        SELECTED="$candidate"
+        break
+    fi
+    if [ "$release" -gt "$COMPILED_OTP_RELEASE" ] && [ "$release" -le "$MAX_OTP_RELEASE" ]; then
+        if [ -z "$SELECTED" ] || [ "$release" -lt "$SELECTED_RELEASE" ]; then
+            SELECTED="$candidate"
+            SELECTED_RELEASE="$release"
+        fi
+    fi
+done <<CANDIDATES
+$(candidates)
+CANDIDATES
+
+if [ -z "$SELECTED" ]; then
+    echo "No compatible Erlang runtime found: {package} was built with $COMPILED_ERLANG_VERSION" >&2
+    echo "It needs OTP $COMPILED_OTP_RELEASE to $MAX_OTP_RELEASE, set GLEAM_PKG_ERL or ERLANG_HOME to choose one" >&2
+    if [ -n "$FOUND" ]; then
+        echo "Found runtimes:$FOUND" >&2
+    else
+        echo "No Erlang runtime was found" >&2
+    fi
+    echo "Install one with: gleam-pkg toolchain install-otp $COMPILED_OTP_RELEASE" >&2
+    exit 1
+fi
+ERL_BIN_DIR=$(dirname "$SELECTED")
+
+# Run the escript with the selected runtime
+{run}
+"#
+    ))
+}
+
+/// Where an escript wrapper gets the escript from and how it runs it, see [`WrapperMode`]
+fn escript_source(ctx: &BuildContext, artifact: &Artifact) -> (String, String) {
+    if ctx.wrapper != WrapperMode::Embedded {
+        return (
+            format!("ESCRIPT={}", sh_quote_path(&artifact.path)),
+            // nothing is left behind when the escript replaces the wrapper
+            r#"exec "$ERL_BIN_DIR/escript" "$ESCRIPT" "$@""#.to_string(),
+        );
+    }
+    let source = format!(
+        r#"# The escript follows the {marker} line, a copy of it runs and is removed afterwards,
+# also when the wrapper is interrupted
+GLEAM_PKG_TMP=$(mktemp -d "${{TMPDIR:-/tmp}}/gleam-pkg.XXXXXX") || exit 1
+trap 'rm -rf "$GLEAM_PKG_TMP"' EXIT
+trap 'exit 129' HUP
+trap 'exit 130' INT
+trap 'exit 143' TERM
+ESCRIPT="$GLEAM_PKG_TMP/{package}"
+tail -n +"$(awk '/^{marker}$/ {{ print NR + 1; exit }}' "$0")" "$0" > "$ESCRIPT""#,
+        marker = EMBEDDED_MARKER,
+        package = ctx.package,
+    );
+    // the shell stays to remove the copy
+    let run = format!(
+        r#""$ERL_BIN_DIR/escript" "$ESCRIPT" "$@"
+exit $?
+{EMBEDDED_MARKER}"#
+    );
+    (source, run)
+}
+
+/// The part of an escript wrapper that refuses to run an escript that is missing or is not the
+/// one gleam-pkg built, when `sha256sum` or `shasum` can tell
+fn check_escript(package: &str, artifact: &Artifact) -> String {
+    let mut check = format!(
+        r#"if [ ! -f "$ESCRIPT" ]; then
+    echo "{package}: $ESCRIPT is missing, reinstall {package} to restore it" >&2
+    exit 1
+fi
+"#
+    );
+    // blobs are named after the SHA-256 of their contents
+    if let Some(blob) = &artifact.blob {
+        check.push_str(&format!(
+            r#"ESCRIPT_SHA256=$(sha256sum < "$ESCRIPT" 2>/dev/null ||
+    shasum -a 256 < "$ESCRIPT" 2>/dev/null)
+ESCRIPT_SHA256="${{ESCRIPT_SHA256%% *}}"
+if [ -n "$ESCRIPT_SHA256" ] && [ "$ESCRIPT_SHA256" != "{blob}" ]; then
+    echo "{package}: $ESCRIPT is corrupt, reinstall {package} with --force to restore it" >&2
+    exit 1
+fi
+unset ESCRIPT_SHA256
+"#
+        ));
+    }
+    check
+}
+
+/// Generates the wrapper of an escript running on the Erlang/OTP installation embedded with it,
+/// which needs no other runtime
+fn embedded_escript_wrapper(ctx: &BuildContext, artifact: &Artifact, erts: &Path) -> String {
+    let package = ctx.package;
+    let (source, run) = escript_source(ctx, artifact);
+    format!(
+        r#"#!/bin/sh
+# This is a wrapper script for the escript generated by gleam-pkg
+# it runs on the Erlang/OTP installed with it
+
+{source}
+ERL_BIN_DIR={erl_bin_dir}
+
+{check}if [ ! -x "$ERL_BIN_DIR/escript" ]; then
+    echo "{package}: its Erlang/OTP is missing, reinstall {package} to restore it" >&2
+    exit 1
+fi
+
+{run}
+"#,
+        erl_bin_dir = sh_quote_path(&erts.join("bin")),
+        check = check_escript(package, artifact),
+    )
+}
+
+/// Shim module for the JavaScript targets, whose entry point takes no arguments
+fn javascript_shim_module(package: &str) -> String {
+    format!(
+        r#"import {package}
+
+pub fn main() {{
+  {package}.main()
+}}
+"#
+    )
+}
+
+/// Builds for JavaScript and copies the compiled modules into the app directory together with an
+/// `main.mjs` entry point calling the shim's `main`
+fn build_javascript(ctx: &BuildContext, log: &mut BuildLog) -> Result<PathBuf, GleamPkgError> {
+    gleam_build(ctx, log, "javascript")?;
+
+    let compiled = ctx.project_dir.join("build/dev/javascript");
+    let modules_dir = ctx.app_dir.join("javascript");
+    let entry = ctx.app_dir.join("main.mjs");
+    copy_dir_all(&compiled, &modules_dir)
+        .and_then(|_| {
+            fs::write(
+                &entry,
+                format!(
+                    "import {{ main }} from \"./javascript/{BUILD_PROJECT}/{BUILD_PROJECT}.mjs\";\n\
+                     main();\n"
+                ),
+            )
+        })
+        .map_err(|source| GleamPkgError::Io {
+            action: "install JavaScript build",
+            path: ctx.app_dir.to_path_buf(),
+            source,
+        })?;
+    output::info(format!(
+        "JavaScript modules installed to: {}",
+        modules_dir.display()
+    ));
+    Ok(entry)
+}
+
+/// Runs the JavaScript build with Node.js
+pub struct NodeJs;
+
+impl Backend for NodeJs {
+    fn name(&self) -> &'static str {
+        "node"
+    }
+
+    fn build_tool(&self) -> &'static str {
+        "gleam"
+    }
+
+    fn prepare(&self, extract_dir: &Path, package: &str) -> Result<PathBuf, GleamPkgError> {
+        write_build_project(
+            extract_dir,
+            package,
+            "javascript",
+            &javascript_shim_module(package),
+        )
+    }
+
+    fn check_runtime(&self, limits: &BuildLimits) -> Result<String, GleamPkgError> {
+        Ok(format!(
+            "Node.js {}",
+            toolchain::check_executable("node", limits)?
+        ))
+    }
+
+    fn build(&self, ctx: &BuildContext, log: &mut BuildLog) -> Result<Artifact, GleamPkgError> {
+        Ok(Artifact {
+            path: build_javascript(ctx, log)?,
+            runtime: toolchain::check_executable("node", ctx.limits)?,
+            otp_release: None,
+            binary: gleam_name(ctx),
+            blob: None,
+            erts: None,
+        })
+    }
+
+    fn wrapper(&self, _ctx: &BuildContext, artifact: &Artifact) -> Result<String, GleamPkgError> {
+        Ok(format!(
+            r#"#!/bin/sh
+# This is a wrapper script for the Node.js build generated by gleam-pkg
+# built with Node.js {}
+
+exec node {} "$@"
+"#,
+            artifact.runtime,
+            sh_quote_path(&artifact.path)
+        ))
+    }
+}
+
+/// Runs the JavaScript build with Deno
+pub struct Deno;
+
+impl Backend for Deno {
+    fn name(&self) -> &'static str {
+        "deno"
+    }
+
+    fn build_tool(&self) -> &'static str {
+        "gleam"
+    }
+
+    fn prepare(&self, extract_dir: &Path, package: &str) -> Result<PathBuf, GleamPkgError> {
+        write_build_project(
+            extract_dir,
+            package,
+            "javascript",
+            &javascript_shim_module(package),
+        )
+    }
+
+    fn check_runtime(&self, limits: &BuildLimits) -> Result<String, GleamPkgError> {
+        Ok(format!(
+            "Deno {}",
+            toolchain::check_executable("deno", limits)?
+        ))
+    }
+
+    fn build(&self, ctx: &BuildContext, log: &mut BuildLog) -> Result<Artifact, GleamPkgError> {
+        Ok(Artifact {
+            path: build_javascript(ctx, log)?,
+            runtime: toolchain::check_executable("deno", ctx.limits)?,
+            otp_release: None,
+            binary: gleam_name(ctx),
+            blob: None,
+            erts: None,
+        })
+    }
+
+    fn wrapper(&self, _ctx: &BuildContext, artifact: &Artifact) -> Result<String, GleamPkgError> {
+        Ok(format!(
+            r#"#!/bin/sh
+# This is a wrapper script for the Deno build generated by gleam-pkg
+# built with Deno {}
+
+exec deno run --allow-all {} "$@"
+"#,
+            artifact.runtime,
+            sh_quote_path(&artifact.path)
+        ))
+    }
+}
+
+/// Runs a step of a rebar3 or mix build in the package sources, recording its output in `log`
+fn build_tool_step(
+    ctx: &BuildContext,
+    log: &mut BuildLog,
+    tool: &str,
+    args: &[&str],
+) -> Result<Output, GleamPkgError> {
+    let step = format!("{} {}", tool, args.join(" "));
+    let (cmd, limits) = toolchain::build_tool_command(tool, ctx.project_dir, ctx.limits);
+    let output = Runner::new(cmd)
+        .args(args)
+        .env("MIX_ENV", "prod")
+        .limits(&limits)
+        .describe(format!("`{}` in {}", step, ctx.project_dir.display()))
+        .tee()
+        .run()?;
+    log.record(&step, &output)?;
+    if !output.status.success() {
+        return Err(GleamPkgError::BuildFailed {
+            package: ctx.package.to_string(),
+            version: ctx.version.to_string(),
+            status: describe_status(&output.status),
+            log: log.path().to_path_buf(),
+        });
+    }
+    Ok(output)
+}
+
+/// The escript a rebar3 or mix build wrote into `dir`: the one named after the package, or else
+/// the only one there
+fn find_escript(dir: &Path, package: &str) -> Option<PathBuf> {
+    let named = dir.join(package);
+    if named.is_file() {
+        return Some(named);
+    }
+    let mut escripts = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| is_escript(path));
+    match (escripts.next(), escripts.next()) {
+        (Some(escript), None) => Some(escript),
+        _ => None,
+    }
+}
+
+/// Whether the shebang of the file at `path` runs `escript`, like `#!/usr/bin/env escript`
+fn is_escript(path: &Path) -> bool {
+    let Ok(content) = fs::read(path) else {
+        return false;
+    };
+    let shebang = content.split(|b| *b == b'\n').next().unwrap_or_default();
+    shebang.starts_with(b"#!") && shebang.trim_ascii_end().ends_with(b"escript")
+}
+
+/// Builds an Erlang package with `rebar3 escriptize`, which fetches its dependencies itself
+pub struct Rebar3Escript;
+
+impl Backend for Rebar3Escript {
+    fn name(&self) -> &'static str {
+        "rebar3"
+    }
+
+    fn build_tool(&self) -> &'static str {
+        "rebar3"
+    }
+
+    fn prepare(&self, extract_dir: &Path, _package: &str) -> Result<PathBuf, GleamPkgError> {
+        Ok(extract_dir.join("contents"))
+    }
+
+    fn check_runtime(&self, limits: &BuildLimits) -> Result<String, GleamPkgError> {
+        let rebar3 = toolchain::check_executable("rebar3", limits)?;
+        Ok(format!(
+            "{} on Erlang/OTP {}",
+            rebar3,
+            toolchain::check_otp(limits)?
+        ))
+    }
+
+    fn build(&self, ctx: &BuildContext, log: &mut BuildLog) -> Result<Artifact, GleamPkgError> {
+        build_tool_step(ctx, log, "rebar3", &["escriptize"])?;
+        let (erlang_version, otp_release) = erlang_runtime(ctx.limits)?;
+        let bin_dir = ctx.project_dir.join("_build/default/bin");
+        let escript =
+            find_escript(&bin_dir, ctx.package).ok_or_else(|| GleamPkgError::NoEscript {
+                package: ctx.package.to_string(),
+                dir: bin_dir.clone(),
+            })?;
+        let binary = escript_name(&escript);
+        store_escript(ctx, &escript, binary, erlang_version, otp_release)
+    }
+
+    fn wrapper(&self, ctx: &BuildContext, artifact: &Artifact) -> Result<String, GleamPkgError> {
+        escript_wrapper(ctx, artifact)
+    }
+}
+
+/// Builds an Elixir package with `mix escript.build`, which embeds Elixir into the escript so
+/// only Erlang is needed to run it
+pub struct MixEscript;
+
+impl Backend for MixEscript {
+    fn name(&self) -> &'static str {
+        "mix"
+    }
+
+    fn build_tool(&self) -> &'static str {
+        "mix"
+    }
+
+    fn prepare(&self, extract_dir: &Path, _package: &str) -> Result<PathBuf, GleamPkgError> {
+        Ok(extract_dir.join("contents"))
+    }
+
+    fn check_runtime(&self, limits: &BuildLimits) -> Result<String, GleamPkgError> {
+        // `mix --version` starts with the Erlang banner, the Elixir version is on the last line
+        toolchain::check_executable("mix", limits)?;
+        Ok(format!(
+            "mix on Erlang/OTP {}",
+            toolchain::check_otp(limits)?
+        ))
+    }
+
+    fn build(&self, ctx: &BuildContext, log: &mut BuildLog) -> Result<Artifact, GleamPkgError> {
+        build_tool_step(ctx, log, "mix", &["deps.get", "--only", "prod"])?;
+        build_tool_step(ctx, log, "mix", &["escript.build"])?;
+        let (erlang_version, otp_release) = erlang_runtime(ctx.limits)?;
+        let escript =
+            find_escript(ctx.project_dir, ctx.package).ok_or_else(|| GleamPkgError::NoEscript {
+                package: ctx.package.to_string(),
+                dir: ctx.project_dir.to_path_buf(),
+            })?;
+        let binary = escript_name(&escript);
+        store_escript(ctx, &escript, binary, erlang_version, otp_release)
+    }
+
+    fn wrapper(&self, ctx: &BuildContext, artifact: &Artifact) -> Result<String, GleamPkgError> {
+        escript_wrapper(ctx, artifact)
+    }
+}
+
+/// Adds `export` lines for the variables of `env` after the shebang of a wrapper, so the package
+/// runs with the environment configured for it under `[packages.<name>.env]`
+///
+/// Variables whose names the shell cannot export are skipped with a warning.
+pub fn export_env<'a>(
+    wrapper: String,
+    env: impl IntoIterator<Item = (&'a String, &'a String)>,
+) -> String {
+    let mut exports = String::new();
+    for (name, value) in env {
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            output::warning(format!(
+                "{} is not a valid variable name, skipping it",
+                name
+            ));
+            continue;
+        }
+        exports.push_str(&format!("export {}={}\n", name, sh_quote(value)));
+    }
+    if exports.is_empty() {
+        return wrapper;
+    }
+    let (shebang, rest) = wrapper.split_once('\n').unwrap_or((&wrapper, ""));
+    format!(
+        "{}\n# Environment from [packages] in config.toml\n{}{}",
+        shebang, exports, rest
+    )
+}
+
+/// Quotes `value` for a shell, so it is neither split nor expanded
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Quotes `path` for a shell like [`sh_quote`], keeping the bytes of a path that is not UTF-8,
+/// e.g. below a home directory named in a legacy locale, as `printf` escapes
+fn sh_quote_path(path: &Path) -> String {
+    let mut quoted = String::new();
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        if !chunk.valid().is_empty() {
+            quoted.push_str(&sh_quote(chunk.valid()));
+        }
+        if !chunk.invalid().is_empty() {
+            quoted.push_str("\"$(printf '");
+            for byte in chunk.invalid() {
+                let _ = write!(quoted, "\\{:03o}", byte);
+            }
+            quoted.push_str("')\"");
+        }
+    }
+    match quoted.is_empty() {
+        true => sh_quote(""),
+        false => quoted,
+    }
+}
+
+/// Adds the shim to a wrapper in `apps` that makes the unversioned commands of a package run
+/// the version the nearest `.gleam-tools.toml` pins instead, see [`crate::project`]
+///
+/// Run as `<package>-<version>`, or with `GLEAM_PKG_GLOBAL` set, the wrapper always runs its own
+/// version. Otherwise it looks for `.gleam-tools.toml` from the working directory up, and if
+/// `gleam-pkg project install` installed the command next to it, runs that one. The wrappers of
+/// that installation skip themselves, so they run their own version inside their project.
+pub fn dispatch_to_project(wrapper: String, apps: &Path, package: &str, version: &str) -> String {
+    let apps = sh_quote_path(apps);
+    let shim = format!(
+        r#"# Run the version pinned by the nearest .gleam-tools.toml, if the project installed it
+if [ "${{0##*/}}" != "{package}-{version}" ] && [ -z "$GLEAM_PKG_GLOBAL" ]; then
+    gleam_pkg_dir="$PWD"
+    while :; do
+        if [ -f "$gleam_pkg_dir/.gleam-tools.toml" ]; then
+            gleam_pkg_apps="$gleam_pkg_dir/.gleam_pkgs/apps"
+            if ! [ "$gleam_pkg_apps" -ef {apps} ]; then
+                [ -x "$gleam_pkg_apps/${{0##*/}}" ] && exec "$gleam_pkg_apps/${{0##*/}}" "$@"
+                [ -x "$gleam_pkg_apps/{package}" ] && exec "$gleam_pkg_apps/{package}" "$@"
+            fi
+            break
+        fi
+        [ -z "$gleam_pkg_dir" ] && break
+        gleam_pkg_dir="${{gleam_pkg_dir%/*}}"
+    done
+    unset gleam_pkg_dir gleam_pkg_apps
+fi
+"#
+    );
+    let (shebang, rest) = wrapper.split_once('\n').unwrap_or((&wrapper, ""));
+    format!("{}\n{}{}", shebang, shim, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn exports_the_configured_environment() {
+        let wrapper = "#!/bin/sh\nexec node main.mjs \"$@\"\n".to_string();
+        assert_eq!(export_env(wrapper.clone(), &BTreeMap::new()), wrapper);
+
+        let env = BTreeMap::from([
+            ("THEME".to_string(), "it's dark".to_string()),
+            ("NOT-VALID".to_string(), "x".to_string()),
+            ("1ST".to_string(), "x".to_string()),
+        ]);
+        assert_eq!(
+            export_env(wrapper, &env),
+            "#!/bin/sh\n# Environment from [packages] in config.toml\n\
+             export THEME='it'\\''s dark'\nexec node main.mjs \"$@\"\n"
+        );
+    }
+
+    #[test]
+    fn quotes_paths_byte_for_byte() {
+        use std::ffi::OsStr;
+        use std::process::Command;
+
+        for path in [
+            OsStr::new("/home/Jane Doe/.gleam_pkgs"),
+            OsStr::new("/home/jürgen/it's"),
+            OsStr::from_bytes(b"/home/caf\xe9/\xff\xfe x"),
+            OsStr::new(""),
+        ] {
+            let quoted = sh_quote_path(Path::new(path));
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(format!("printf '%s' {}", quoted))
+                .output()
+                .unwrap();
+            assert_eq!(output.stdout, path.as_bytes(), "{}", quoted);
+        }
+    }
+
+    #[test]
+    fn escripts_run_on_their_embedded_runtime_unless_they_are_corrupt() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(&dir.path().join("store"));
+        let limits = BuildLimits {
+            timeout: std::time::Duration::from_secs(10),
+            memory_limit: None,
+            cpu_limit: None,
+            isolation: None,
+        };
+        let ctx = BuildContext {
+            package: "hello",
+            version: "1.0.0",
+            project_dir: Path::new("/build"),
+            app_dir: Path::new("/lib/hello-1.0.0"),
+            store: &store,
+            toolchains: Path::new("/toolchains"),
+            limits: &limits,
+            wrapper: WrapperMode::Exec,
+        };
+        // paths the shell must neither split nor expand
+        let escript = dir.path().join("it's $HOME");
+        fs::write(&escript, "escript").unwrap();
+        let erts = dir.path().join("erts");
+        fs::create_dir_all(erts.join("bin")).unwrap();
+        let erl_escript = erts.join("bin/escript");
+        fs::write(&erl_escript, "#!/bin/sh\necho ran \"$@\"\n").unwrap();
+        fs::set_permissions(&erl_escript, fs::Permissions::from_mode(0o755)).unwrap();
+        let artifact = Artifact {
+            path: escript.clone(),
+            runtime: "Erlang/OTP 27".to_string(),
+            otp_release: Some(27),
+            binary: "hello".to_string(),
+            blob: Some(crate::checksum::sha256_file(&escript).unwrap()),
+            erts: Some(erts),
+        };
+        let wrapper = dir.path().join("hello-1.0.0");
+        fs::write(&wrapper, escript_wrapper(&ctx, &artifact).unwrap()).unwrap();
+        let run = || {
+            std::process::Command::new("sh")
+                .arg(&wrapper)
+                .arg("an arg")
+                .output()
+                .unwrap()
+        };
+        let output = run();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            format!("ran {} an arg\n", escript.display())
+        );
+
+        fs::write(&escript, "tampered").unwrap();
+        let output = run();
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("is corrupt"));
+    }
+
+    #[test]
+    fn embedded_escripts_run_from_a_copy_removed_afterwards() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(&dir.path().join("store"));
+        let limits = BuildLimits {
+            timeout: std::time::Duration::from_secs(10),
+            memory_limit: None,
+            cpu_limit: None,
+            isolation: None,
+        };
+        let ctx = BuildContext {
+            package: "hello",
+            version: "1.0.0",
+            project_dir: Path::new("/build"),
+            app_dir: Path::new("/lib/hello-1.0.0"),
+            store: &store,
+            toolchains: Path::new("/toolchains"),
+            limits: &limits,
+            wrapper: WrapperMode::Embedded,
+        };
+        let escript = dir.path().join("escript");
+        fs::write(&escript, "escript\n\0binary\n").unwrap();
+        let erts = dir.path().join("erts");
+        fs::create_dir_all(erts.join("bin")).unwrap();
+        let erl_escript = erts.join("bin/escript");
+        fs::write(&erl_escript, "#!/bin/sh\ncat \"$1\"\nshift\necho \"$@\"\n").unwrap();
+        fs::set_permissions(&erl_escript, fs::Permissions::from_mode(0o755)).unwrap();
+        let artifact = Artifact {
+            path: escript.clone(),
+            runtime: "Erlang/OTP 27".to_string(),
+            otp_release: Some(27),
+            binary: "hello".to_string(),
+            blob: Some(crate::checksum::sha256_file(&escript).unwrap()),
+            erts: Some(erts),
+        };
+        let mut wrapper = escript_wrapper(&ctx, &artifact).unwrap().into_bytes();
+        wrapper.extend(fs::read(&escript).unwrap());
+        let wrapper_path = dir.path().join("hello-1.0.0");
+        fs::write(&wrapper_path, wrapper).unwrap();
+        fs::remove_file(&escript).unwrap();
+
+        let tmp = dir.path().join("tmp");
+        fs::create_dir_all(&tmp).unwrap();
+        let output = std::process::Command::new("sh")
+            .arg(&wrapper_path)
+            .arg("an arg")
+            .env("TMPDIR", &tmp)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(output.stdout, b"escript\n\0binary\nan arg\n");
+        assert!(fs::read_dir(&tmp).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn shims_dispatch_to_the_project_installation() {
+        let wrapper = "#!/bin/sh\nexec node main.mjs \"$@\"\n".to_string();
+        let shimmed = dispatch_to_project(wrapper, Path::new("/home/me/apps"), "hello", "1.0.0");
+        assert!(shimmed.starts_with("#!/bin/sh\n# Run the version pinned"));
+        assert!(shimmed.contains(r#"if [ "${0##*/}" != "hello-1.0.0" ]"#));
+        assert!(shimmed.contains(r#"-ef '/home/me/apps'"#));
+        assert!(shimmed.ends_with("fi\nexec node main.mjs \"$@\"\n"));
+    }
+
+    #[test]
+    fn reads_the_declared_build_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(build_tools(dir.path()).unwrap().is_empty());
+
+        fs::write(
+            dir.path().join("metadata.config"),
+            "{<<\"name\">>,<<\"rebar3_hex\">>}.\n\
+             {<<\"build_tools\">>,[<<\"rebar3\">>, <<\"mix\">>]}.\n\
+             {<<\"version\">>,<<\"7.0.8\">>}.\n",
+        )
+        .unwrap();
+        assert_eq!(build_tools(dir.path()).unwrap(), ["rebar3", "mix"]);
+        assert_eq!(
+            Target::detect(dir.path(), "rebar3_hex", "7.0.8").unwrap(),
+            Target::Rebar3
+        );
+        assert!(matches!(
+            check_build_tools(dir.path(), "rebar3_hex", "7.0.8", &NodeJs),
+            Err(GleamPkgError::WrongBuildTool { .. })
+        ));
+        assert!(check_build_tools(dir.path(), "rebar3_hex", "7.0.8", &MixEscript).is_ok());
+
+        let declare = |tool: &str| {
+            let config = format!("{{<<\"build_tools\">>,[<<\"{tool}\">>]}}.\n");
+            fs::write(dir.path().join("metadata.config"), config).unwrap();
+        };
+        declare("mix");
+        assert_eq!(
+            Target::detect(dir.path(), "credo", "1.7.0").unwrap(),
+            Target::Mix
+        );
+        declare("make");
+        assert!(matches!(
+            Target::detect(dir.path(), "cowboy", "2.12.0"),
+            Err(GleamPkgError::UnsupportedBuildTool { .. })
+        ));
+
+        declare("gleam");
+        assert!(check_build_tools(dir.path(), "wisp", "1.2.0", &ErlangEscript).is_ok());
+        fs::create_dir(dir.path().join("contents")).unwrap();
+        fs::write(
+            dir.path().join("contents/gleam.toml"),
+            "name = \"wisp\"\ntarget = \"javascript\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Target::detect(dir.path(), "wisp", "1.2.0").unwrap(),
+            Target::Node
+        );
+    }
+
+    #[test]
+    fn finds_the_built_escript() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("mix.exs"), "defmodule Credo.MixProject do").unwrap();
+        assert_eq!(find_escript(dir.path(), "credo"), None);
+
+        // mix writes a space after the #!
+        let escript = dir.path().join("credo_cli");
+        fs::write(&escript, "#! /usr/bin/env escript\n%%\n").unwrap();
+        assert_eq!(find_escript(dir.path(), "credo"), Some(escript));
+        fs::write(dir.path().join("credo"), "#!/usr/bin/env escript\n").unwrap();
+        assert_eq!(
+            find_escript(dir.path(), "credo"),
+            Some(dir.path().join("credo"))
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Builds kept for reinstalling
+//!
+//! Every escript built is recorded in the `builds` section of the package database under what
+//! went into it, e.g.:
+//!
+//! ```text
+//! internal:wonderful_cli@1.2.0 auto otp-27 gleam-1.6.2
+//! ```
+//!
+//! the release, the backend asked for with `--target` or `auto`, and the toolchain. Installing
+//! the same release with the same toolchain again, after `uninstall` or over the installed
+//! version, reuses the escript kept in the store, see [`crate::store`], instead of downloading
+//! and building it; `install --rebuild` builds it anyway.
+//!
+//! JavaScript builds, which are kept in `lib/<package>-<version>` and removed with the version,
+//! and builds with an embedded Erlang/OTP are not cached. `gleam-pkg gc` removes the blobs no
+//! installed version refers to, and with them their cached builds.
+
+use crate::backend::Target;
+use crate::db::{self, Database, InstalledVersion};
+use crate::error::GleamPkgError;
+use crate::limits::BuildLimits;
+use crate::paths::Paths;
+use crate::registry::Source;
+use crate::store::Store;
+use crate::toolchain;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What goes into a build, which names it in the cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildKey {
+    /// The release, e.g. `internal:wonderful_cli@1.2.0`
+    release: String,
+    /// The backend asked for, `None` if it was detected from the package
+    target: Option<Target>,
+    /// The versions of the tools building it, e.g. `otp-27 gleam-1.6.2`
+    toolchain: String,
+}
+
+impl BuildKey {
+    /// The key of a build of `package` at `version` with the toolchain in use
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Where the package comes from
+    /// * `package` - The name of the package
+    /// * `version` - The version to build
+    /// * `target` - The backend asked for, `None` if it is detected from the package
+    /// * `limits` - The limits of the version probes of the toolchain
+    ///
+    /// # Returns
+    ///
+    /// The key, or `None` if the build cannot be cached: it is a JavaScript build, or the
+    /// toolchain is missing, which the build reports
+    pub fn new(
+        source: &Source,
+        package: &str,
+        version: &str,
+        target: Option<Target>,
+        limits: &BuildLimits,
+    ) -> Option<BuildKey> {
+        if matches!(target, Some(Target::Node | Target::Deno)) {
+            return None;
+        }
+        let mut toolchain = format!("otp-{}", toolchain::check_otp(limits).ok()?);
+        if !matches!(target, Some(Target::Rebar3 | Target::Mix)) {
+            toolchain.push_str(&format!(" gleam-{}", toolchain::check_gleam(limits).ok()?));
+        }
+        Some(BuildKey {
+            release: format!("{}@{}", source.qualify(package), version),
+            target,
+            toolchain,
+        })
+    }
+
+    /// The key of the same build with its backend detected from the package
+    fn detected(&self) -> BuildKey {
+        BuildKey {
+            target: None,
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for BuildKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let target = self.target.map_or("auto", |target| target.backend().name());
+        write!(f, "{} {} {}", self.release, target, self.toolchain)
+    }
+}
+
+/// Finds the build cached under `key`, if its escript is still in the store
+///
+/// A build with its backend detected from the package is also found for the backend it was
+/// detected as.
+///
+/// # Errors
+///
+/// Returns the errors of [`Database::load`]
+pub fn find(paths: &Paths, key: &BuildKey) -> Result<Option<InstalledVersion>, GleamPkgError> {
+    let db = Database::load(&paths.db_file())?;
+    Ok(lookup(&db.builds, &Store::new(&paths.store()), key))
+}
+
+fn lookup(
+    builds: &BTreeMap<String, InstalledVersion>,
+    store: &Store,
+    key: &BuildKey,
+) -> Option<InstalledVersion> {
+    let detected = key.target.and_then(|target| {
+        builds
+            .get(&key.detected().to_string())
+            .filter(|build| build.target == target)
+    });
+    builds
+        .get(&key.to_string())
+        .or(detected)
+        .filter(|build| stored(build, store))
+        .cloned()
+}
+
+/// Whether the escript of `build` is in the store
+fn stored(build: &InstalledVersion, store: &Store) -> bool {
+    build
+        .blob
+        .as_ref()
+        .is_some_and(|blob| store.path(blob).is_file())
+}
+
+/// Caches the build of an installed version under `key`, unless it cannot be reused
+///
+/// # Errors
+///
+/// Returns the errors of [`Database::load`] and [`Database::save`]
+pub fn remember(
+    paths: &Paths,
+    key: &BuildKey,
+    installed: &InstalledVersion,
+) -> Result<(), GleamPkgError> {
+    if installed.blob.is_none() || installed.erts.is_some() {
+        return Ok(());
+    }
+    let db_path = paths.db_file();
+    let _lock = db::lock(&db_path);
+    let mut db = Database::load(&db_path)?;
+    db.builds.insert(key.to_string(), installed.clone());
+    db.save(&db_path)
+}
+
+/// Drops the cached builds whose escripts are gone from the store, e.g. after `gleam-pkg gc`
+///
+/// # Errors
+///
+/// Returns the errors of [`Database::load`] and [`Database::save`]
+///
+/// # Returns
+///
+/// How many builds were dropped
+pub fn prune(paths: &Paths) -> Result<usize, GleamPkgError> {
+    let db_path = paths.db_file();
+    let _lock = db::lock(&db_path);
+    let mut db = Database::load(&db_path)?;
+    let store = Store::new(&paths.store());
+    let before = db.builds.len();
+    db.builds.retain(|_, build| stored(build, &store));
+    let dropped = before - db.builds.len();
+    if dropped > 0 {
+        db.save(&db_path)?;
+    }
+    Ok(dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn key(target: Option<Target>) -> BuildKey {
+        BuildKey {
+            release: "hello@1.0.0".to_string(),
+            target,
+            toolchain: "otp-27 gleam-1.6.2".to_string(),
+        }
+    }
+
+    fn build(target: Target, blob: &str) -> InstalledVersion {
+        InstalledVersion {
+            target,
+            otp_release: Some(27),
+            blob: Some(blob.to_string()),
+            binary: None,
+            erts: None,
+            wrapper: None,
+            provenance: None,
+            dependencies: None,
+        }
+    }
+
+    #[test]
+    fn names_builds_after_what_went_into_them() {
+        assert_eq!(key(None).to_string(), "hello@1.0.0 auto otp-27 gleam-1.6.2");
+        assert_eq!(
+            key(Some(Target::Rebar3)).to_string(),
+            "hello@1.0.0 rebar3 otp-27 gleam-1.6.2"
+        );
+    }
+
+    #[test]
+    fn finds_builds_whose_escripts_are_stored() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path());
+        fs::write(store.path("abc"), "escript").unwrap();
+        let mut builds = BTreeMap::new();
+        builds.insert(key(None).to_string(), build(Target::Erlang, "abc"));
+
+        let found = lookup(&builds, &store, &key(None)).unwrap();
+        assert_eq!(found.blob.as_deref(), Some("abc"));
+        // detected as the backend asked for
+        assert!(lookup(&builds, &store, &key(Some(Target::Erlang))).is_some());
+        assert!(lookup(&builds, &store, &key(Some(Target::Rebar3))).is_none());
+        let mut other = key(None);
+        other.toolchain = "otp-26 gleam-1.6.2".to_string();
+        assert!(lookup(&builds, &store, &other).is_none());
+
+        fs::remove_file(store.path("abc")).unwrap();
+        assert!(lookup(&builds, &store, &key(None)).is_none());
+    }
+
+    #[test]
+    fn drops_builds_whose_escripts_are_gone() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = Paths::new(dir.path());
+        let store = Store::new(&paths.store());
+        fs::create_dir_all(paths.store()).unwrap();
+        fs::write(store.path("abc"), "escript").unwrap();
+        remember(&paths, &key(None), &build(Target::Erlang, "abc")).unwrap();
+        remember(
+            &paths,
+            &key(Some(Target::Erlang)),
+            &build(Target::Erlang, "def"),
+        )
+        .unwrap();
+
+        assert_eq!(prune(&paths).unwrap(), 1);
+        let db = Database::load(&paths.db_file()).unwrap();
+        assert_eq!(
+            db.builds.keys().collect::<Vec<_>>(),
+            ["hello@1.0.0 auto otp-27 gleam-1.6.2"]
+        );
+        assert!(find(&paths, &key(None)).unwrap().is_some());
+    }
+}
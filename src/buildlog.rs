@@ -0,0 +1,93 @@
+//! Persistent logs of package builds
+//!
+//! Every build writes the complete output of its external steps to
+//! `~/.gleam_pkgs/logs/<package>-<version>-<timestamp>.log`, so failures can be inspected after
+//! the terminal output is gone.
+
+use crate::error::GleamPkgError;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An open build log for a single package build
+pub struct BuildLog {
+    path: PathBuf,
+    file: fs::File,
+}
+
+impl BuildLog {
+    /// Creates a new, timestamped log file for `package` at `version` in `logs_dir`
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` if the log file cannot be created
+    pub fn create(logs_dir: &Path, package: &str, version: &str) -> Result<Self, GleamPkgError> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let path = logs_dir.join(format!("{}-{}-{}.log", package, version, timestamp));
+        let file = fs::File::create(&path).map_err(|source| GleamPkgError::Io {
+            action: "create build log",
+            path: path.clone(),
+            source,
+        })?;
+        Ok(BuildLog { path, file })
+    }
+
+    /// The location of the log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends the captured output of one build step to the log
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` if the log cannot be written
+    pub fn record(&mut self, step: &str, output: &Output) -> Result<(), GleamPkgError> {
+        let mut entry = format!("==> {} ({})\n", step, output.status).into_bytes();
+        entry.extend_from_slice(b"--- stdout ---\n");
+        entry.extend_from_slice(&output.stdout);
+        entry.extend_from_slice(b"\n--- stderr ---\n");
+        entry.extend_from_slice(&output.stderr);
+        entry.extend_from_slice(b"\n\n");
+        self.file
+            .write_all(&entry)
+            .map_err(|source| GleamPkgError::Io {
+                action: "write build log",
+                path: self.path.clone(),
+                source,
+            })
+    }
+}
+
+/// Finds the most recent build log of `package`, if any
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::IOErr` if the log directory cannot be read
+pub fn latest_log(logs_dir: &Path, package: &str) -> Result<Option<PathBuf>, GleamPkgError> {
+    let mut latest: Option<(u64, PathBuf)> = None;
+    for entry in fs::read_dir(logs_dir)? {
+        let path = entry?.path();
+        let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        // <package>-<version>-<timestamp>, hex package names never contain '-'
+        let mut parts = stem.rsplitn(2, '-');
+        let timestamp = parts.next().and_then(|t| t.parse::<u64>().ok());
+        let prefix = parts
+            .next()
+            .and_then(|p| p.split_once('-'))
+            .map(|(name, _)| name);
+        if let (Some(timestamp), Some(name)) = (timestamp, prefix) {
+            if name == package && latest.as_ref().is_none_or(|(t, _)| timestamp > *t) {
+                latest = Some((timestamp, path));
+            }
+        }
+    }
+    Ok(latest.map(|(_, path)| path))
+}
@@ -0,0 +1,351 @@
+//! Relocatable bundles of installed versions
+//!
+//! `gleam-pkg bundle <package>[@<version>]` packs an installed version into a gzipped tarball
+//! that `gleam-pkg install --file` installs on another machine, without a registry or a build:
+//!
+//! ```text
+//! bundle.json    what was bundled, see [`BundleManifest`]
+//! escript        the escript, for versions running on the BEAM
+//! lib/           the build, for versions running on Node.js or Deno
+//! otp/           with --with-erts, the Erlang/OTP installation the escript runs on
+//! ```
+//!
+//! Wrappers are not bundled, they name absolute paths of the installation; `install --file`
+//! writes new ones. A bundled OTP installation goes to `~/.gleam_pkgs/toolchains/otp-<version>`
+//! unless that one is installed already, where the wrappers look for runtimes, see
+//! [`crate::toolchains`]. The runtime of a version installed with `--bundle-erts` is always
+//! bundled, and embedded again.
+//!
+//! Escripts and JavaScript builds run anywhere, a bundled runtime only on the [`Platform`] it
+//! was built for, which the manifest records and `install --file` checks. `--target
+//! linux-aarch64` bundles the precompiled build of the same OTP release for that platform
+//! instead of the local installation, for deploying to machines other than the one building.
+
+use crate::db::InstalledVersion;
+use crate::error::GleamPkgError;
+use crate::registry::Source;
+use clap::ValueEnum;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// The version of the bundle layout, bundles of later versions are refused
+pub const FORMAT: u32 = 1;
+
+const MANIFEST: &str = "bundle.json";
+
+/// The escript in a bundle
+pub const ESCRIPT: &str = "escript";
+
+/// The build directory in a bundle
+pub const LIB: &str = "lib";
+
+/// The Erlang/OTP installation in a bundle
+pub const OTP: &str = "otp";
+
+/// The machines a bundle can be made for, selectable with `bundle --target`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum Platform {
+    #[value(name = "linux-x86_64")]
+    #[serde(rename = "linux-x86_64")]
+    LinuxX86_64,
+    #[value(name = "linux-aarch64")]
+    #[serde(rename = "linux-aarch64")]
+    LinuxAarch64,
+    #[value(name = "macos-x86_64")]
+    #[serde(rename = "macos-x86_64")]
+    MacosX86_64,
+    #[value(name = "macos-aarch64")]
+    #[serde(rename = "macos-aarch64")]
+    MacosAarch64,
+}
+
+impl Platform {
+    /// The platform of this machine, if bundles can be made for it
+    pub fn current() -> Option<Platform> {
+        Platform::value_variants().iter().copied().find(|platform| {
+            platform.os() == std::env::consts::OS && platform.arch() == std::env::consts::ARCH
+        })
+    }
+
+    /// The operating system, as `std::env::consts::OS` names it
+    pub fn os(self) -> &'static str {
+        match self {
+            Platform::LinuxX86_64 | Platform::LinuxAarch64 => "linux",
+            Platform::MacosX86_64 | Platform::MacosAarch64 => "macos",
+        }
+    }
+
+    /// The architecture, as `std::env::consts::ARCH` names it
+    pub fn arch(self) -> &'static str {
+        match self {
+            Platform::LinuxX86_64 | Platform::MacosX86_64 => "x86_64",
+            Platform::LinuxAarch64 | Platform::MacosAarch64 => "aarch64",
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.os(), self.arch())
+    }
+}
+
+/// What a bundle holds, `bundle.json`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format: u32,
+    pub package: String,
+    pub version: String,
+    /// Where the version was installed from
+    pub source: Source,
+    /// How the version was installed, as the package database records it
+    pub installed: InstalledVersion,
+    /// The version of the bundled Erlang/OTP installation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub otp: Option<String>,
+    /// The machines the bundle runs on, any if `None`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+}
+
+/// The build a bundle holds
+pub enum Contents<'a> {
+    /// An escript, for versions running on the BEAM
+    Escript(&'a Path),
+    /// A build directory, for versions running on Node.js or Deno
+    Lib(&'a Path),
+}
+
+/// Writes a bundle to `path`, which only appears once it is complete
+///
+/// # Arguments
+///
+/// * `manifest` - What is bundled
+/// * `contents` - The build of the version
+/// * `otp` - The root of the Erlang/OTP installation to bundle, if any
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if a file cannot be read or the bundle cannot be written
+pub fn write(
+    path: &Path,
+    manifest: &BundleManifest,
+    contents: Contents,
+    otp: Option<&Path>,
+) -> Result<(), GleamPkgError> {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    let io_error = |source| GleamPkgError::Io {
+        action: "write bundle",
+        path: path.to_path_buf(),
+        source,
+    };
+    let written = (|| {
+        let mut builder =
+            tar::Builder::new(GzEncoder::new(File::create(&part)?, Compression::default()));
+        // OTP installations link between their directories
+        builder.follow_symlinks(false);
+        let json = serde_json::to_vec_pretty(manifest).map_err(std::io::Error::other)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, MANIFEST, json.as_slice())?;
+        match contents {
+            Contents::Escript(escript) => builder.append_path_with_name(escript, ESCRIPT)?,
+            Contents::Lib(dir) => builder.append_dir_all(LIB, dir)?,
+        }
+        if let Some(otp) = otp {
+            builder.append_dir_all(OTP, otp)?;
+        }
+        builder.into_inner()?.finish()?;
+        fs::rename(&part, path)
+    })();
+    if let Err(source) = written {
+        let _ = fs::remove_file(&part);
+        return Err(io_error(source));
+    }
+    Ok(())
+}
+
+/// Unpacks the bundle at `path` into `dir`, replacing whatever is there
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the bundle cannot be unpacked, or
+/// `GleamPkgError::InvalidBundle` if it is not a bundle gleam-pkg can install
+pub fn unpack(path: &Path, dir: &Path) -> Result<BundleManifest, GleamPkgError> {
+    let invalid = |reason: &str| GleamPkgError::InvalidBundle {
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+    };
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(dir)
+        .and_then(|_| File::open(path))
+        // entries naming paths outside of `dir` are refused by `unpack`
+        .and_then(|file| tar::Archive::new(GzDecoder::new(file)).unpack(dir))
+        .map_err(|source| GleamPkgError::Io {
+            action: "unpack bundle",
+            path: path.to_path_buf(),
+            source,
+        })?;
+    let json = fs::read(dir.join(MANIFEST)).map_err(|_| invalid("it has no bundle.json"))?;
+    let manifest: BundleManifest =
+        serde_json::from_slice(&json).map_err(|e| invalid(&e.to_string()))?;
+    if manifest.format > FORMAT {
+        return Err(invalid(&format!(
+            "it has format {}, this gleam-pkg installs format {}, update it",
+            manifest.format, FORMAT
+        )));
+    }
+    let name = |s: &str| {
+        !s.is_empty()
+            && !s.starts_with('.')
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+'))
+    };
+    if !name(&manifest.package) || !name(&manifest.version) {
+        return Err(invalid("it names an invalid package or version"));
+    }
+    let build = match manifest.installed.blob {
+        Some(_) => dir.join(ESCRIPT).is_file(),
+        None => dir.join(LIB).is_dir(),
+    };
+    if !build {
+        return Err(invalid("the build is missing"));
+    }
+    if manifest.otp.is_some() && !dir.join(OTP).join("Install").is_file() {
+        return Err(invalid("the Erlang/OTP installation is incomplete"));
+    }
+    if manifest.installed.erts.is_some() && manifest.otp.is_none() {
+        return Err(invalid("the embedded Erlang/OTP installation is missing"));
+    }
+    if let Some(platform) = manifest
+        .platform
+        .filter(|p| Some(*p) != Platform::current())
+    {
+        return Err(invalid(&format!(
+            "it was made for {}, this machine runs {} on {}",
+            platform,
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        )));
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Target;
+
+    fn manifest(blob: Option<&str>) -> BundleManifest {
+        BundleManifest {
+            format: FORMAT,
+            package: "hello".to_string(),
+            version: "1.0.0".to_string(),
+            source: Source::default(),
+            installed: InstalledVersion {
+                target: if blob.is_some() {
+                    Target::Erlang
+                } else {
+                    Target::Node
+                },
+                otp_release: blob.map(|_| 27),
+                blob: blob.map(String::from),
+                binary: None,
+                erts: None,
+                wrapper: None,
+                provenance: None,
+                dependencies: None,
+            },
+            otp: None,
+            platform: None,
+        }
+    }
+
+    #[test]
+    fn bundles_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib = dir.path().join("hello-1.0.0");
+        fs::create_dir_all(lib.join("deps")).unwrap();
+        fs::write(lib.join("main.mjs"), "main()").unwrap();
+        let bundle = dir.path().join("hello.tar.gz");
+        write(&bundle, &manifest(None), Contents::Lib(&lib), None).unwrap();
+
+        let unpacked = dir.path().join("unpacked");
+        let read = unpack(&bundle, &unpacked).unwrap();
+        assert_eq!(
+            (read.package.as_str(), read.version.as_str()),
+            ("hello", "1.0.0")
+        );
+        assert_eq!(
+            fs::read_to_string(unpacked.join(LIB).join("main.mjs")).unwrap(),
+            "main()"
+        );
+
+        let escript = dir.path().join("escript");
+        fs::write(&escript, "#!/usr/bin/env escript").unwrap();
+        write(
+            &bundle,
+            &manifest(Some("abc")),
+            Contents::Escript(&escript),
+            None,
+        )
+        .unwrap();
+        assert!(unpack(&bundle, &unpacked).unwrap().installed.blob.is_some());
+        assert!(unpacked.join(ESCRIPT).is_file());
+        assert!(!unpacked.join(LIB).exists());
+    }
+
+    #[test]
+    fn refuses_what_it_cannot_install() {
+        let dir = tempfile::tempdir().unwrap();
+        let escript = dir.path().join("escript");
+        fs::write(&escript, "").unwrap();
+        let bundle = dir.path().join("hello.tar.gz");
+        let unpacked = dir.path().join("unpacked");
+        let refused = |manifest: BundleManifest, contents| {
+            write(&bundle, &manifest, contents, None).unwrap();
+            matches!(
+                unpack(&bundle, &unpacked),
+                Err(GleamPkgError::InvalidBundle { .. })
+            )
+        };
+        assert!(refused(manifest(None), Contents::Escript(&escript)));
+        let mut newer = manifest(Some("abc"));
+        newer.format = FORMAT + 1;
+        assert!(refused(newer, Contents::Escript(&escript)));
+        let mut escaping = manifest(Some("abc"));
+        escaping.package = "../hello".to_string();
+        assert!(refused(escaping, Contents::Escript(&escript)));
+        let mut with_otp = manifest(Some("abc"));
+        with_otp.otp = Some("27.1.2".to_string());
+        assert!(refused(with_otp, Contents::Escript(&escript)));
+        let mut elsewhere = manifest(Some("abc"));
+        elsewhere.platform = Platform::value_variants()
+            .iter()
+            .copied()
+            .find(|platform| Some(*platform) != Platform::current());
+        assert!(refused(elsewhere, Contents::Escript(&escript)));
+    }
+
+    #[test]
+    fn names_platforms_like_the_standard_library() {
+        assert_eq!(Platform::LinuxAarch64.to_string(), "linux-aarch64");
+        assert_eq!(
+            serde_json::to_string(&Platform::MacosX86_64).unwrap(),
+            r#""macos-x86_64""#
+        );
+        if let Some(current) = Platform::current() {
+            assert_eq!(current.os(), std::env::consts::OS);
+            assert_eq!(current.arch(), std::env::consts::ARCH);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! On-disk cache of hex.pm package metadata
+//!
+//! Responses of the packages API are stored in `~/.gleam_pkgs/cache/metadata/<package>.json`
+//! together with their `ETag` and `Last-Modified` headers. Within the configured TTL a cached
+//! entry is used as is; after that it is revalidated with a conditional request, so unchanged
+//! metadata costs a `304 Not Modified` instead of a full download. `--refresh` revalidates
+//! every entry regardless of its age, while `--offline` uses every entry regardless of its age.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static REFRESH: AtomicBool = AtomicBool::new(false);
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Decides once whether cached entries are revalidated or used regardless of their age
+///
+/// # Arguments
+///
+/// * `refresh` - Whether `--refresh` was passed
+/// * `offline` - Whether `--offline` was passed or `offline` is configured, which wins
+pub fn init(refresh: bool, offline: bool) {
+    REFRESH.store(refresh, Ordering::Relaxed);
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// Revalidates every entry from now on regardless of its age, like `--refresh`, e.g. once a
+/// release was published
+pub fn refresh() {
+    REFRESH.store(true, Ordering::Relaxed);
+}
+
+/// A cached metadata response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedMetadata {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Unix time the entry was last fetched or revalidated
+    pub fetched_at: u64,
+    pub body: serde_json::Value,
+}
+
+impl CachedMetadata {
+    /// Whether the entry is younger than `ttl` and can be used without revalidation, never
+    /// with `--refresh` and always with `--offline`
+    pub fn is_fresh(&self, ttl: Duration) -> bool {
+        is_fresh(self.fetched_at, ttl)
+    }
+
+    /// Builds an entry from a successful response
+    pub fn new(
+        etag: Option<String>,
+        last_modified: Option<String>,
+        body: serde_json::Value,
+    ) -> Self {
+        CachedMetadata {
+            etag,
+            last_modified,
+            fetched_at: now(),
+            body,
+        }
+    }
+
+    /// Marks the entry as just revalidated
+    pub fn touch(&mut self) {
+        self.fetched_at = now();
+    }
+}
+
+/// Whether something cached at `fetched_at`, in Unix time, is used without revalidation, like
+/// [`CachedMetadata::is_fresh`]
+pub fn is_fresh(fetched_at: u64, ttl: Duration) -> bool {
+    if OFFLINE.load(Ordering::Relaxed) {
+        return true;
+    }
+    !REFRESH.load(Ordering::Relaxed) && now().saturating_sub(fetched_at) < ttl.as_secs()
+}
+
+/// The metadata cache directory
+pub struct MetadataCache {
+    dir: PathBuf,
+}
+
+impl MetadataCache {
+    pub fn new(dir: &Path) -> Self {
+        MetadataCache {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn entry_path(&self, package: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", package))
+    }
+
+    /// Reads the cached entry of `package`, treating unreadable entries as missing
+    pub fn get(&self, package: &str) -> Option<CachedMetadata> {
+        let content = fs::read_to_string(self.entry_path(package)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Stores the entry of `package`
+    pub fn put(&self, package: &str, entry: &CachedMetadata) -> std::io::Result<()> {
+        let path = self.entry_path(package);
+        // organization packages are cached as `<org>/<package>.json`
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string(entry).map_err(std::io::Error::other)?;
+        fs::write(path, json)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,280 @@
+//! What changed between two releases of a package
+//!
+//! After `update`, every updated package is listed with links to what changed, as far as the
+//! registry knows them:
+//!
+//! ```text
+//! What changed
+//!   wisp 1.2.0 → 1.3.0
+//!     diff       https://diff.hex.pm/diff/wisp/1.2.0..1.3.0
+//!     release    https://github.com/gleam-wisp/wisp/releases/tag/v1.3.0
+//!     changelog  https://github.com/gleam-wisp/wisp/blob/main/CHANGELOG.md
+//! ```
+//!
+//! The diff is the one hex.pm renders for public packages. The release and the changelog come
+//! from the links in the metadata of the package: the release for a GitHub repository, tagged
+//! `v<version>` as Gleam projects do, and the changelog for a link named like one. With
+//! `update --notes` the changelog is fetched as well, and the sections of the versions after
+//! the old one up to the new one are printed below the links, see [`notes`].
+
+use crate::error::GleamPkgError;
+use crate::http;
+use crate::output::{self, Style};
+use crate::registry::Source;
+use semver::Version;
+
+/// At most this many lines of release notes are printed per package
+pub const MAX_NOTE_LINES: usize = 40;
+
+/// An updated package and where to read about what changed in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub package: String,
+    pub from: String,
+    pub to: String,
+    /// The diff between the two releases on hex.pm
+    pub diff: Option<String>,
+    /// The release of the new version on GitHub
+    pub release: Option<String>,
+    /// The changelog the package links to
+    pub changelog: Option<String>,
+    /// The sections of the changelog about the new versions, with `update --notes`
+    pub notes: Vec<String>,
+}
+
+impl Change {
+    /// The change of `package` from `from` to `to`, with the links found in its metadata
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Where the package comes from, only hex.pm renders diffs
+    /// * `package` - The name of the package
+    /// * `from` - The version updated from
+    /// * `to` - The version updated to
+    /// * `metadata` - The metadata of the package
+    pub fn new(
+        source: &Source,
+        package: &str,
+        from: &str,
+        to: &str,
+        metadata: &serde_json::Value,
+    ) -> Self {
+        let links: Vec<(&str, &str)> = metadata["meta"]["links"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(name, url)| Some((name.as_str(), url.as_str()?)))
+            .collect();
+        let changelog = links
+            .iter()
+            .find(|(name, _)| {
+                let name = name.to_lowercase();
+                name.contains("changelog") || name.contains("changes")
+            })
+            .map(|(_, url)| url.to_string());
+        let release = links
+            .iter()
+            .find_map(|(_, url)| github_repository(url))
+            .map(|repository| format!("{}/releases/tag/v{}", repository, to));
+        Change {
+            package: package.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+            diff: source
+                .is_hexpm()
+                .then(|| format!("https://diff.hex.pm/diff/{}/{}..{}", package, from, to)),
+            release,
+            changelog,
+            notes: Vec::new(),
+        }
+    }
+}
+
+/// The repository `url` points into on GitHub, as `https://github.com/<owner>/<repo>`
+fn github_repository(url: &str) -> Option<String> {
+    let path = url
+        .strip_prefix("https://github.com/")
+        .or_else(|| url.strip_prefix("http://github.com/"))?;
+    let mut parts = path.split('/').filter(|part| !part.is_empty());
+    let owner = parts.next()?;
+    let repo = parts.next()?.trim_end_matches(".git");
+    Some(format!("https://github.com/{}/{}", owner, repo))
+}
+
+/// Where to fetch the changelog at `url` as plain text: files on GitHub are fetched raw
+pub fn raw_url(url: &str) -> String {
+    let Some(path) = url.strip_prefix("https://github.com/") else {
+        return url.to_string();
+    };
+    match path.split_once("/blob/") {
+        Some((repository, file)) => {
+            format!("https://raw.githubusercontent.com/{}/{}", repository, file)
+        }
+        None => url.to_string(),
+    }
+}
+
+/// Fetches the changelog at `url`, see [`raw_url`]
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or
+/// `GleamPkgError::InvalidResponse` if it cannot be fetched
+pub fn fetch(config: &crate::config::HttpConfig, url: &str) -> Result<String, GleamPkgError> {
+    let url = raw_url(url);
+    let client = http::client(config)?;
+    let response = http::send(client.get(&url)).map_err(|source| GleamPkgError::RequestFailed {
+        url: url.clone(),
+        source,
+    })?;
+    if !response.status().is_success() {
+        return Err(GleamPkgError::HttpStatus {
+            url,
+            status: response.status().as_u16(),
+        });
+    }
+    response
+        .text()
+        .map_err(|source| GleamPkgError::InvalidResponse { url, source })
+}
+
+/// The version a changelog heading is about, e.g. `## [1.3.0] - 2025-01-10` or `# v1.3.0`
+fn heading_version(line: &str) -> Option<Version> {
+    if !line.starts_with('#') {
+        return None;
+    }
+    line.split_whitespace().find_map(|word| {
+        let word = word.trim_matches(|c: char| "#[]():".contains(c));
+        Version::parse(word.strip_prefix('v').unwrap_or(word)).ok()
+    })
+}
+
+/// The sections of `changelog` about the versions after `from` up to `to`, as lines
+///
+/// A section starts at a heading naming a version and ends at the next one; headings without
+/// a version, e.g. `## Unreleased`, end a section too.
+pub fn notes<'a>(changelog: &'a str, from: &str, to: &str) -> Vec<&'a str> {
+    let (Ok(from), Ok(to)) = (Version::parse(from), Version::parse(to)) else {
+        return Vec::new();
+    };
+    let mut lines = Vec::new();
+    let mut inside = false;
+    for line in changelog.lines() {
+        if line.starts_with('#') {
+            inside = heading_version(line).is_some_and(|v| from < v && v <= to);
+        }
+        if inside {
+            lines.push(line);
+        }
+    }
+    // no blank lines at the end
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Prints `changes` below a heading, each with its links and release notes
+pub fn print(changes: &[Change]) {
+    if changes.is_empty() {
+        return;
+    }
+    println!();
+    println!("{}", output::paint("What changed", Style::Bold));
+    for change in changes {
+        println!("  {} {} → {}", change.package, change.from, change.to);
+        let links = [
+            ("diff", &change.diff),
+            ("release", &change.release),
+            ("changelog", &change.changelog),
+        ];
+        for (name, link) in links {
+            if let Some(link) = link {
+                println!("    {:<9}  {}", name, link);
+            }
+        }
+        let lines = &change.notes;
+        if lines.is_empty() {
+            continue;
+        }
+        println!();
+        for line in lines.iter().take(MAX_NOTE_LINES) {
+            if line.trim().is_empty() {
+                println!();
+            } else if line.starts_with('#') {
+                let heading = line.trim_start_matches('#').trim();
+                println!("    {}", output::paint(heading, Style::Bold));
+            } else {
+                println!("    {}", line.trim_end());
+            }
+        }
+        if lines.len() > MAX_NOTE_LINES {
+            println!(
+                "    {}",
+                output::paint(
+                    format!(
+                        "… {} more lines in the changelog",
+                        lines.len() - MAX_NOTE_LINES
+                    ),
+                    Style::Dim
+                )
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn links_what_changed() {
+        let metadata = json!({"meta": {"links": {
+            "Repository": "https://github.com/gleam-wisp/wisp",
+            "Changelog": "https://github.com/gleam-wisp/wisp/blob/main/CHANGELOG.md",
+        }}});
+        let change = Change::new(&Source::default(), "wisp", "1.2.0", "1.3.0", &metadata);
+        assert_eq!(
+            change.diff.as_deref(),
+            Some("https://diff.hex.pm/diff/wisp/1.2.0..1.3.0")
+        );
+        assert_eq!(
+            change.release.as_deref(),
+            Some("https://github.com/gleam-wisp/wisp/releases/tag/v1.3.0")
+        );
+        assert_eq!(
+            raw_url(change.changelog.as_deref().unwrap()),
+            "https://raw.githubusercontent.com/gleam-wisp/wisp/main/CHANGELOG.md"
+        );
+
+        let private = Source {
+            repo: None,
+            organization: Some("myorg".to_string()),
+        };
+        let change = Change::new(&private, "tool", "1.0.0", "1.1.0", &json!({}));
+        assert_eq!(change.diff, None);
+        assert_eq!(change.release, None);
+        assert_eq!(change.changelog, None);
+    }
+
+    #[test]
+    fn picks_the_sections_between_two_versions() {
+        let changelog = "# Changelog\n\n## Unreleased\n\n- next\n\n## [1.3.0] - 2025-01-10\n\n\
+                         - new\n\n## v1.2.1\n\n- fixed\n\n## 1.2.0\n\n- old\n";
+        assert_eq!(
+            notes(changelog, "1.2.0", "1.3.0"),
+            [
+                "## [1.3.0] - 2025-01-10",
+                "",
+                "- new",
+                "",
+                "## v1.2.1",
+                "",
+                "- fixed"
+            ]
+        );
+        assert!(notes(changelog, "1.3.0", "1.3.0").is_empty());
+        assert!(notes(changelog, "not", "semver").is_empty());
+    }
+}
@@ -0,0 +1,46 @@
+//! SHA-256 checksums, as hex.pm publishes them for release tarballs
+
+use crate::error::GleamPkgError;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::path::Path;
+
+/// The lowercase hex SHA-256 checksum of the file at `path`
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the file cannot be read
+pub fn sha256_file(path: &Path) -> Result<String, GleamPkgError> {
+    let io_error = |source| GleamPkgError::Io {
+        action: "checksum file",
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut file = File::open(path).map_err(io_error)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(io_error)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Whether two hex checksums are the same, ignoring case; hex.pm uses uppercase in places
+pub fn matches(expected: &str, actual: &str) -> bool {
+    expected.eq_ignore_ascii_case(actual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc");
+        std::fs::write(&path, "abc").unwrap();
+        let checksum = sha256_file(&path).unwrap();
+        assert_eq!(
+            checksum,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(matches(&checksum.to_uppercase(), &checksum));
+    }
+}
@@ -0,0 +1,809 @@
+//! User configuration
+//!
+//! Settings are read from `~/.gleam_pkgs/config.toml`. Every key is optional and falls back to
+//! its default, so the file only needs to mention what differs:
+//!
+//! ```toml
+//! api_base = "https://hex.pm/api/"
+//! build_timeout_secs = 600
+//! # packages `update --all` updates at once, unless overridden with `--jobs`
+//! jobs = 1
+//! # colored output when stdout is a terminal, unless turned off with `--no-color`
+//! color = true
+//! # page long output through $PAGER when stdout is a terminal, as if `--pager` was passed
+//! pager = false
+//! # use cached metadata however old and never contact a registry, as if `--offline` was passed
+//! offline = false
+//! # print results in the format for scripts, as if `--porcelain` was passed
+//! porcelain = false
+//! # plain lines without color, symbols or progress bars, as if `--plain` was passed
+//! plain = false
+//! # offer to add the apps directory to the startup file of the shell when it is not on PATH
+//! path_prompt = true
+//! # the language of messages, "en" or "zh", picked from LANG by default, see `crate::i18n`
+//! locale = "zh"
+//! # how wrappers run escripts: "exec", "embedded" or "symlink", see `crate::backend`
+//! wrapper = "exec"
+//! # for packages of hex.pm organizations
+//! api_key = "..."
+//! # tried in order when repository_base fails, see `crate::mirrors`
+//! mirrors = ["https://hexpm.upyun.com/"]
+//!
+//! # packages installed as `internal:<package>` or with `--repo internal`
+//! [repos.internal]
+//! api_base = "https://hex.example.com/api/"
+//! repository_base = "https://repo.example.com/"
+//! api_key = "..."
+//! mirrors = ["https://repo-backup.example.com/"]
+//!
+//! [cache]
+//! metadata_ttl_secs = 300
+//! # see `crate::index`
+//! index_ttl_secs = 3600
+//! # see `crate::names`
+//! names_ttl_secs = 86400
+//! # see `crate::sharedcache`
+//! shared_dir = "/var/cache/gleam-pkg"
+//!
+//! # see `crate::downloads`
+//! [cache.downloads]
+//! keep_latest = 2
+//! max_size_mib = 500
+//! max_age_days = 30
+//! prune_after_install = true
+//!
+//! [http]
+//! connect_timeout_secs = 10
+//! read_timeout_secs = 60
+//! ca_bundle = "/etc/ssl/corporate-ca.pem"
+//! insecure = false
+//! retries = 2
+//! user_agent = "gleam-pkg/0.1.0 (linux; x86_64)"
+//! # bytes per second tarballs are downloaded at most, see `crate::http`
+//! limit_rate = "1M"
+//! # connect over "ipv4" or "ipv6" only
+//! ip_family = "ipv4"
+//! # host:port:address, like curl's --resolve
+//! resolve = ["repo.hex.pm:443:151.101.1.1"]
+//!
+//! # see `crate::licenses`
+//! [licenses]
+//! allow = ["MIT", "Apache-2.0"]
+//! deny = ["AGPL-3.0-only"]
+//! on_violation = "warn"
+//!
+//! # see `crate::trust`
+//! [trust]
+//! confirm_new = false
+//!
+//! # see `crate::updatecheck`
+//! [updates]
+//! notify = false
+//! check_interval_secs = 86400
+//!
+//! # see `crate::audit`
+//! [audit]
+//! osv_api = "https://api.osv.dev/v1/"
+//!
+//! # see `crate::toolchains`
+//! [toolchains]
+//! gleam_version = "1.6.2"
+//! gleam_releases = "https://github.com/gleam-lang/gleam/releases/download/"
+//! otp_builds = "https://builds.hex.pm/builds/otp/"
+//! otp_platform = "ubuntu-22.04"
+//!
+//! [docker]
+//! enabled = false
+//! image = "ghcr.io/gleam-lang/gleam:v1.6.3-erlang-alpine"
+//!
+//! # how freshly built commands are run once before they are installed, see `crate::smoke`
+//! [smoke_test]
+//! args = ["--help"]
+//! timeout_secs = 30
+//!
+//! # run for every package, see `crate::hooks`
+//! [hooks]
+//! post_install = "..."
+//!
+//! # run for one package only
+//! [hooks.wonderful_cli]
+//! post_install = "..."
+//!
+//! # what installs and updates of one package use unless flags say otherwise
+//! [packages.wonderful_cli]
+//! # the newest release meeting the requirement, instead of the newest one
+//! version = "~> 2.0"
+//! # as if `--target node` was passed, "nodejs" works too
+//! backend = "nodejs"
+//! # instead of `wrapper` above
+//! wrapper = "exec"
+//! # instead of [smoke_test] args, `[]` skips the smoke test
+//! smoke_test = ["--version"]
+//!
+//! # exported by the wrappers of the package and set by `gleam-pkg exec`, see `crate::exec`
+//! [packages.wonderful_cli.env]
+//! WONDERFUL_CLI_THEME = "dark"
+//! ```
+//!
+//! The environment variables of [`ENV_OVERRIDES`] override single settings of the file, e.g.
+//! `GLEAM_PKG_API_BASE` or `GLEAM_PKG_CONNECT_TIMEOUT_SECS`, and command-line flags override
+//! both: flags, then the environment, then `config.toml`, then the defaults. An empty variable
+//! counts as unset. Every setting holding a single value has a variable; lists and tables, like
+//! `mirrors`, `[repos]`, `[packages]`, `[hooks]`, `resolve` or the licenses allowed, do not, as a
+//! variable cannot spell them. The installation itself is moved with `GLEAM_PKG_ROOT`, see
+//! [`crate::paths`], and `gleam-pkg help environment` lists the variables.
+
+use crate::backend::{Target, WrapperMode};
+use crate::docker;
+use crate::error::GleamPkgError;
+use crate::http::{IpFamily, Rate, Resolve};
+use crate::licenses::LicensePolicy;
+use crate::registry::RepoConfig;
+use crate::trust::TrustConfig;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The type of the value of a setting overridden by an environment variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvValue {
+    String,
+    /// A non-negative integer
+    Integer,
+    /// `true`, `false`, `1`, `0`, `yes`, `no`, `on` or `off`
+    Bool,
+}
+
+/// The environment variables overriding settings, with the key they override and its type
+pub const ENV_OVERRIDES: [(&str, &str, EnvValue); 41] = [
+    ("GLEAM_PKG_API_BASE", "api_base", EnvValue::String),
+    (
+        "GLEAM_PKG_REPOSITORY_BASE",
+        "repository_base",
+        EnvValue::String,
+    ),
+    ("GLEAM_PKG_API_KEY", "api_key", EnvValue::String),
+    (
+        "GLEAM_PKG_BUILD_TIMEOUT_SECS",
+        "build_timeout_secs",
+        EnvValue::Integer,
+    ),
+    ("GLEAM_PKG_WRAPPER_MODE", "wrapper", EnvValue::String),
+    ("GLEAM_PKG_LOCALE", "locale", EnvValue::String),
+    ("GLEAM_PKG_JOBS", "jobs", EnvValue::Integer),
+    ("GLEAM_PKG_COLOR", "color", EnvValue::Bool),
+    ("GLEAM_PKG_PAGER", "pager", EnvValue::Bool),
+    ("GLEAM_PKG_PLAIN", "plain", EnvValue::Bool),
+    ("GLEAM_PKG_PORCELAIN", "porcelain", EnvValue::Bool),
+    ("GLEAM_PKG_PATH_PROMPT", "path_prompt", EnvValue::Bool),
+    ("GLEAM_PKG_OFFLINE", "offline", EnvValue::Bool),
+    (
+        "GLEAM_PKG_SHARED_CACHE_DIR",
+        "cache.shared_dir",
+        EnvValue::String,
+    ),
+    (
+        "GLEAM_PKG_DOWNLOADS_KEEP_LATEST",
+        "cache.downloads.keep_latest",
+        EnvValue::Integer,
+    ),
+    (
+        "GLEAM_PKG_DOWNLOADS_MAX_SIZE_MIB",
+        "cache.downloads.max_size_mib",
+        EnvValue::Integer,
+    ),
+    (
+        "GLEAM_PKG_DOWNLOADS_MAX_AGE_DAYS",
+        "cache.downloads.max_age_days",
+        EnvValue::Integer,
+    ),
+    (
+        "GLEAM_PKG_PRUNE_AFTER_INSTALL",
+        "cache.downloads.prune_after_install",
+        EnvValue::Bool,
+    ),
+    (
+        "GLEAM_PKG_METADATA_TTL_SECS",
+        "cache.metadata_ttl_secs",
+        EnvValue::Integer,
+    ),
+    (
+        "GLEAM_PKG_INDEX_TTL_SECS",
+        "cache.index_ttl_secs",
+        EnvValue::Integer,
+    ),
+    (
+        "GLEAM_PKG_NAMES_TTL_SECS",
+        "cache.names_ttl_secs",
+        EnvValue::Integer,
+    ),
+    (
+        "GLEAM_PKG_CONNECT_TIMEOUT_SECS",
+        "http.connect_timeout_secs",
+        EnvValue::Integer,
+    ),
+    (
+        "GLEAM_PKG_READ_TIMEOUT_SECS",
+        "http.read_timeout_secs",
+        EnvValue::Integer,
+    ),
+    ("GLEAM_PKG_RETRIES", "http.retries", EnvValue::Integer),
+    ("GLEAM_PKG_INSECURE", "http.insecure", EnvValue::Bool),
+    ("GLEAM_PKG_LIMIT_RATE", "http.limit_rate", EnvValue::String),
+    ("GLEAM_PKG_IP_FAMILY", "http.ip_family", EnvValue::String),
+    ("GLEAM_PKG_CA_BUNDLE", "http.ca_bundle", EnvValue::String),
+    ("GLEAM_PKG_USER_AGENT", "http.user_agent", EnvValue::String),
+    ("GLEAM_PKG_NOTIFY_UPDATES", "updates.notify", EnvValue::Bool),
+    (
+        "GLEAM_PKG_UPDATE_CHECK_INTERVAL_SECS",
+        "updates.check_interval_secs",
+        EnvValue::Integer,
+    ),
+    ("GLEAM_PKG_OSV_API", "audit.osv_api", EnvValue::String),
+    (
+        "GLEAM_PKG_LICENSE_ON_VIOLATION",
+        "licenses.on_violation",
+        EnvValue::String,
+    ),
+    ("GLEAM_PKG_CONFIRM_NEW", "trust.confirm_new", EnvValue::Bool),
+    (
+        "GLEAM_PKG_SMOKE_TEST_TIMEOUT_SECS",
+        "smoke_test.timeout_secs",
+        EnvValue::Integer,
+    ),
+    (
+        "GLEAM_PKG_GLEAM_VERSION",
+        "toolchains.gleam_version",
+        EnvValue::String,
+    ),
+    (
+        "GLEAM_PKG_GLEAM_RELEASES",
+        "toolchains.gleam_releases",
+        EnvValue::String,
+    ),
+    (
+        "GLEAM_PKG_OTP_BUILDS",
+        "toolchains.otp_builds",
+        EnvValue::String,
+    ),
+    (
+        "GLEAM_PKG_OTP_PLATFORM",
+        "toolchains.otp_platform",
+        EnvValue::String,
+    ),
+    ("GLEAM_PKG_DOCKER", "docker.enabled", EnvValue::Bool),
+    ("GLEAM_PKG_DOCKER_IMAGE", "docker.image", EnvValue::String),
+];
+
+/// Configuration for the Gleam package manager
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub api_base: String,
+    pub repository_base: String,
+    /// Sent as the `authorization` header to hex.pm, needed for packages of organizations
+    pub api_key: Option<String>,
+    /// Mirrors of `repository_base`, tried in order when it fails
+    pub mirrors: Vec<String>,
+    /// Additional repositories by name, see [`crate::registry`]
+    pub repos: BTreeMap<String, RepoConfig>,
+    /// Seconds a single build step may run, unless overridden with `--timeout`
+    pub build_timeout_secs: u64,
+    /// How wrappers run escripts, unless overridden with `--wrapper`
+    pub wrapper: WrapperMode,
+    /// How many packages `update --all` updates at once, unless overridden with `--jobs`
+    pub jobs: NonZeroUsize,
+    /// Whether output is colored when stdout is a terminal, see [`crate::output`]
+    pub color: bool,
+    /// Whether long output is paged when stdout is a terminal, see [`crate::output`]
+    pub pager: bool,
+    /// Whether results are printed in the porcelain format, as if `--porcelain` was passed
+    pub porcelain: bool,
+    /// Whether output is plain, for screen readers, as if `--plain` was passed
+    pub plain: bool,
+    /// Whether installs offer to add the apps directory to the startup file of the shell
+    pub path_prompt: bool,
+    /// The language of messages, picked from the environment if `None`, see [`crate::i18n`]
+    pub locale: Option<String>,
+    /// Never contact a registry and use cached metadata however old, as if `--offline` was
+    /// passed
+    pub offline: bool,
+    pub cache: CacheConfig,
+    pub http: HttpConfig,
+    pub audit: AuditConfig,
+    /// Licenses accepted for installed packages and their dependencies
+    pub licenses: LicensePolicy,
+    /// Whether new packages are confirmed before their first install
+    pub trust: TrustConfig,
+    pub updates: UpdatesConfig,
+    pub toolchains: ToolchainsConfig,
+    pub docker: DockerConfig,
+    pub smoke_test: SmokeTestConfig,
+    pub hooks: HooksConfig,
+    /// Settings of single packages, by package name
+    pub packages: BTreeMap<String, PackageConfig>,
+}
+
+/// Settings of the on-disk caches
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Seconds cached package metadata is used without asking hex.pm whether it changed
+    pub metadata_ttl_secs: u64,
+    /// Seconds a cached versions index is used without asking the repository whether it changed
+    pub index_ttl_secs: u64,
+    /// Seconds the kept package names of a repository are used before they are fetched again
+    pub names_ttl_secs: u64,
+    /// A directory of tarballs shared with other users and CI jobs, see [`crate::sharedcache`]
+    pub shared_dir: Option<PathBuf>,
+    /// How the downloaded tarballs and sources are pruned
+    pub downloads: DownloadsConfig,
+}
+
+/// Policies pruning the download cache, see [`crate::downloads`]; none is set by default
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct DownloadsConfig {
+    /// How many versions of each package the downloads are kept of, the newest
+    pub keep_latest: Option<usize>,
+    /// MiB the downloads may take up together, the least recently used are pruned first
+    pub max_size_mib: Option<u64>,
+    /// Days a download is kept after it was last used
+    pub max_age_days: Option<u64>,
+    /// Whether `install`, `ensure` and `update` prune the downloads when they are done
+    pub prune_after_install: bool,
+}
+
+/// Settings of the HTTP client talking to the registry
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Seconds to wait for a connection to the registry
+    pub connect_timeout_secs: u64,
+    /// Seconds to wait for a response, and then again for its body
+    pub read_timeout_secs: u64,
+    /// PEM certificates trusted in addition to the system roots
+    pub ca_bundle: Option<PathBuf>,
+    /// Skip TLS certificate verification, as if `--insecure` was passed
+    pub insecure: bool,
+    /// How often a request that could not connect or timed out is retried
+    pub retries: u32,
+    /// Sent instead of `gleam-pkg/<version> (<os>; <arch>)`
+    pub user_agent: Option<String>,
+    /// How fast tarballs are downloaded at most, as if `--limit-rate` was passed
+    pub limit_rate: Option<Rate>,
+    /// The IP family connections are held to, as if `--ipv4` or `--ipv6` was passed
+    pub ip_family: Option<IpFamily>,
+    /// Addresses host names resolve to instead of what DNS answers, as if passed with
+    /// `--resolve`
+    pub resolve: Vec<Resolve>,
+}
+
+/// Settings of the notices of available updates, see [`crate::updatecheck`]
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct UpdatesConfig {
+    /// Whether commands tell when installed packages have updates
+    pub notify: bool,
+    /// Seconds between two looks for updates
+    pub check_interval_secs: u64,
+}
+
+/// Settings of `gleam-pkg audit`
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// The OSV API vulnerabilities are looked up in
+    pub osv_api: String,
+}
+
+/// Shell commands run around installs and uninstalls, see [`crate::hooks`]
+#[derive(Debug, Default, Deserialize)]
+pub struct HooksConfig {
+    /// Hooks run for every package
+    #[serde(flatten)]
+    pub global: Hooks,
+    /// Hooks run for a single package, by package name
+    #[serde(flatten)]
+    pub packages: BTreeMap<String, Hooks>,
+}
+
+/// The hook commands of one scope
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    pub pre_install: Option<String>,
+    pub post_install: Option<String>,
+    pub pre_uninstall: Option<String>,
+    pub post_uninstall: Option<String>,
+}
+
+/// How freshly built commands are run before they are installed, see [`crate::smoke`]
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmokeTestConfig {
+    /// The arguments the command is run with, no smoke test is run if empty
+    pub args: Vec<String>,
+    /// Seconds the command may run
+    pub timeout_secs: u64,
+}
+
+/// Settings of one package
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PackageConfig {
+    /// The releases installs and updates pick from when no version is asked for, a hex
+    /// requirement like `~> 2.0`, see [`crate::releases::satisfies`]
+    pub version: Option<String>,
+    /// The backend to build and run the package with unless `--target` is passed
+    pub backend: Option<Target>,
+    /// How the wrapper runs an escript unless `--wrapper` is passed, instead of `wrapper`
+    pub wrapper: Option<WrapperMode>,
+    /// Environment variables the package runs with
+    pub env: BTreeMap<String, String>,
+    /// The arguments of its smoke test instead of `args` of [`SmokeTestConfig`]
+    pub smoke_test: Option<Vec<String>>,
+}
+
+/// Settings of the compilers gleam-pkg downloads, see [`crate::toolchains`]
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ToolchainsConfig {
+    /// The compiler release to build with, as if `--gleam-version` was passed
+    pub gleam_version: Option<String>,
+    /// Where the release assets of the compiler are published, by `v<version>` directory
+    pub gleam_releases: String,
+    /// Where precompiled OTP builds are published, by platform directory
+    pub otp_builds: String,
+    /// The OTP builds to install, e.g. `ubuntu-24.04`, detected by default
+    pub otp_platform: Option<String>,
+}
+
+/// Settings of builds in docker
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DockerConfig {
+    /// Build every package in docker, as if `--build-in-docker` was passed
+    pub enabled: bool,
+    /// The image providing `gleam` and `erl`
+    pub image: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            api_base: "https://hex.pm/api/".to_string(),
+            repository_base: "https://repo.hex.pm/".to_string(),
+            api_key: None,
+            mirrors: Vec::new(),
+            repos: BTreeMap::new(),
+            build_timeout_secs: 600,
+            wrapper: WrapperMode::default(),
+            jobs: NonZeroUsize::MIN,
+            color: true,
+            pager: false,
+            porcelain: false,
+            plain: false,
+            path_prompt: true,
+            locale: None,
+            offline: false,
+            cache: CacheConfig::default(),
+            http: HttpConfig::default(),
+            audit: AuditConfig::default(),
+            licenses: LicensePolicy::default(),
+            trust: TrustConfig::default(),
+            updates: UpdatesConfig::default(),
+            toolchains: ToolchainsConfig::default(),
+            docker: DockerConfig::default(),
+            smoke_test: SmokeTestConfig::default(),
+            hooks: HooksConfig::default(),
+            packages: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            metadata_ttl_secs: 300,
+            index_ttl_secs: 3600,
+            names_ttl_secs: 86400,
+            shared_dir: None,
+            downloads: DownloadsConfig::default(),
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            connect_timeout_secs: 10,
+            read_timeout_secs: 60,
+            ca_bundle: None,
+            insecure: false,
+            retries: 2,
+            user_agent: None,
+            limit_rate: None,
+            ip_family: None,
+            resolve: Vec::new(),
+        }
+    }
+}
+
+impl HttpConfig {
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout_secs)
+    }
+
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_secs(self.read_timeout_secs)
+    }
+}
+
+impl Default for SmokeTestConfig {
+    fn default() -> Self {
+        SmokeTestConfig {
+            args: vec!["--help".to_string()],
+            timeout_secs: 30,
+        }
+    }
+}
+
+impl Default for UpdatesConfig {
+    fn default() -> Self {
+        UpdatesConfig {
+            notify: false,
+            check_interval_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            osv_api: "https://api.osv.dev/v1/".to_string(),
+        }
+    }
+}
+
+impl Default for ToolchainsConfig {
+    fn default() -> Self {
+        ToolchainsConfig {
+            gleam_version: None,
+            gleam_releases: "https://github.com/gleam-lang/gleam/releases/download/".to_string(),
+            otp_builds: "https://builds.hex.pm/builds/otp/".to_string(),
+            otp_platform: None,
+        }
+    }
+}
+
+impl Default for DockerConfig {
+    fn default() -> Self {
+        DockerConfig {
+            enabled: false,
+            image: docker::DEFAULT_IMAGE.to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from `path`, overridden by the variables of [`ENV_OVERRIDES`]
+    /// set in the environment, using the defaults for what neither sets
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` if the file exists but cannot be read,
+    /// `GleamPkgError::InvalidEnvVar` if a variable does not hold a value of its type, or
+    /// `GleamPkgError::ConfigError` if the settings cannot be parsed
+    pub fn load(path: &Path) -> Result<Self, GleamPkgError> {
+        Config::layered(path, |name| std::env::var(name).ok())
+    }
+
+    /// Loads the configuration from `path`, overridden by the variables `env` looks up
+    fn layered(path: &Path, env: impl Fn(&str) -> Option<String>) -> Result<Self, GleamPkgError> {
+        let mut settings = match path.exists() {
+            true => {
+                let content = fs::read_to_string(path).map_err(|source| GleamPkgError::Io {
+                    action: "read configuration",
+                    path: path.to_path_buf(),
+                    source,
+                })?;
+                toml::from_str(&content).map_err(|source| GleamPkgError::ConfigError {
+                    path: path.to_path_buf(),
+                    source,
+                })?
+            }
+            false => toml::Table::new(),
+        };
+        for (name, key, kind) in ENV_OVERRIDES {
+            let Some(value) = env(name).filter(|value| !value.is_empty()) else {
+                continue;
+            };
+            override_key(&mut settings, key, env_value(name, &value, kind)?);
+        }
+        toml::Value::Table(settings)
+            .try_into()
+            .map_err(|source| GleamPkgError::ConfigError {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+
+    pub fn build_timeout(&self) -> Duration {
+        Duration::from_secs(self.build_timeout_secs)
+    }
+
+    pub fn metadata_ttl(&self) -> Duration {
+        Duration::from_secs(self.cache.metadata_ttl_secs)
+    }
+
+    pub fn index_ttl(&self) -> Duration {
+        Duration::from_secs(self.cache.index_ttl_secs)
+    }
+
+    pub fn names_ttl(&self) -> Duration {
+        Duration::from_secs(self.cache.names_ttl_secs)
+    }
+
+    /// The arguments the smoke test of `package` runs it with, none if it is skipped
+    pub fn smoke_test_args(&self, package: &str) -> &[String] {
+        self.packages
+            .get(package)
+            .and_then(|p| p.smoke_test.as_deref())
+            .unwrap_or(&self.smoke_test.args)
+    }
+
+    pub fn smoke_test_timeout(&self) -> Duration {
+        Duration::from_secs(self.smoke_test.timeout_secs)
+    }
+
+    /// The version requirement configured for `package`, if any
+    pub fn package_version(&self, package: &str) -> Option<&str> {
+        self.packages.get(package)?.version.as_deref()
+    }
+
+    /// The environment variables configured for `package`
+    pub fn package_env(&self, package: &str) -> impl Iterator<Item = (&String, &String)> {
+        self.packages.get(package).into_iter().flat_map(|p| &p.env)
+    }
+}
+
+/// Parses the value of the environment variable `name` as a setting of type `kind`
+fn env_value(
+    name: &'static str,
+    value: &str,
+    kind: EnvValue,
+) -> Result<toml::Value, GleamPkgError> {
+    let invalid = |expected| GleamPkgError::InvalidEnvVar {
+        name,
+        value: value.to_string(),
+        expected,
+    };
+    match kind {
+        EnvValue::String => Ok(toml::Value::String(value.to_string())),
+        EnvValue::Integer => value
+            .trim()
+            .parse::<u32>()
+            .map(|n| toml::Value::Integer(n.into()))
+            .map_err(|_| invalid("a non-negative integer")),
+        EnvValue::Bool => match value.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Ok(toml::Value::Boolean(true)),
+            "false" | "0" | "no" | "off" => Ok(toml::Value::Boolean(false)),
+            _ => Err(invalid("true or false")),
+        },
+    }
+}
+
+/// Sets the dotted `key` of `settings`, e.g. `http.retries`, creating the tables on the way
+fn override_key(settings: &mut toml::Table, key: &str, value: toml::Value) {
+    let (tables, last) = match key.rsplit_once('.') {
+        Some((tables, last)) => (tables.split('.').collect(), last),
+        None => (Vec::new(), key),
+    };
+    let mut table = settings;
+    for name in tables {
+        let entry = table
+            .entry(name)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        // a key of the wrong type is reported when the settings are parsed
+        let toml::Value::Table(inner) = entry else {
+            return;
+        };
+        table = inner;
+    }
+    table.insert(last.to_string(), value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn layered(content: Option<&str>, vars: &[(&str, &str)]) -> Result<Config, GleamPkgError> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        if let Some(content) = content {
+            fs::write(&path, content).unwrap();
+        }
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        Config::layered(&path, |name| vars.get(name).map(|v| v.to_string()))
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let file = "api_base = \"https://file.example/\"\njobs = 2\n[http]\nretries = 5\n";
+        let config = layered(
+            Some(file),
+            &[
+                ("GLEAM_PKG_API_BASE", "https://env.example/"),
+                ("GLEAM_PKG_CONNECT_TIMEOUT_SECS", "3"),
+                ("GLEAM_PKG_OFFLINE", "yes"),
+                ("GLEAM_PKG_COLOR", "0"),
+                ("GLEAM_PKG_PAGER", "1"),
+                ("GLEAM_PKG_PORCELAIN", "true"),
+                ("GLEAM_PKG_PATH_PROMPT", "0"),
+                ("GLEAM_PKG_JOBS", ""),
+                ("GLEAM_PKG_DOCKER", "on"),
+                ("GLEAM_PKG_DOWNLOADS_KEEP_LATEST", "2"),
+                ("GLEAM_PKG_CA_BUNDLE", "/etc/ssl/corporate.pem"),
+            ],
+        )
+        .unwrap();
+        assert!(config.docker.enabled);
+        assert_eq!(config.cache.downloads.keep_latest, Some(2));
+        assert_eq!(
+            config.http.ca_bundle.as_deref(),
+            Some(Path::new("/etc/ssl/corporate.pem"))
+        );
+        assert_eq!(config.api_base, "https://env.example/");
+        assert_eq!(config.http.connect_timeout_secs, 3);
+        assert!(config.offline);
+        assert!(!config.color);
+        assert!(config.pager);
+        assert!(config.porcelain);
+        assert!(!config.path_prompt);
+        // from the file, an empty variable counting as unset
+        assert_eq!(config.jobs.get(), 2);
+        assert_eq!(config.http.retries, 5);
+        // the defaults
+        assert_eq!(config.http.read_timeout_secs, 60);
+        assert_eq!(config.repository_base, "https://repo.hex.pm/");
+
+        let config = layered(None, &[("GLEAM_PKG_RETRIES", "0")]).unwrap();
+        assert_eq!(config.http.retries, 0);
+        assert_eq!(config.jobs.get(), 1);
+    }
+
+    #[test]
+    fn rejects_invalid_environment_values() {
+        let result = layered(None, &[("GLEAM_PKG_READ_TIMEOUT_SECS", "soon")]);
+        assert!(matches!(
+            result,
+            Err(GleamPkgError::InvalidEnvVar {
+                name: "GLEAM_PKG_READ_TIMEOUT_SECS",
+                ..
+            })
+        ));
+        let result = layered(None, &[("GLEAM_PKG_INSECURE", "maybe")]);
+        assert!(matches!(result, Err(GleamPkgError::InvalidEnvVar { .. })));
+        let result = layered(None, &[("GLEAM_PKG_JOBS", "0")]);
+        assert!(matches!(result, Err(GleamPkgError::ConfigError { .. })));
+    }
+
+    #[test]
+    fn reads_install_defaults_of_packages() {
+        let file = "[packages.wonderful_cli]\nversion = \"~> 2.0\"\nbackend = \"nodejs\"\n\
+                    wrapper = \"embedded\"\n";
+        let config = layered(Some(file), &[]).unwrap();
+        let package = &config.packages["wonderful_cli"];
+        assert_eq!(config.package_version("wonderful_cli"), Some("~> 2.0"));
+        assert_eq!(config.package_version("other_cli"), None);
+        assert_eq!(package.backend, Some(Target::Node));
+        assert_eq!(package.wrapper, Some(WrapperMode::Embedded));
+        let result = layered(Some("[packages.wonderful_cli]\nbackend = \"cobol\"\n"), &[]);
+        assert!(matches!(result, Err(GleamPkgError::ConfigError { .. })));
+    }
+}
@@ -0,0 +1,647 @@
+//! The local package database
+//!
+//! Installed packages are tracked in a document mapping each package name to the versions
+//! installed for it and which of them is the default. A [`Storage`] keeps the document:
+//! `~/.gleam_pkgs/db/metadata.sqlite` in SQLite, see [`crate::sqlite`], while a path ending in
+//! `.json` names a plain JSON file, which is how gleam-pkg kept it before.
+//!
+//! The document records the `schema` it is written in. When its format changes, [`SCHEMA`] is
+//! raised and a [`Migration`] appended to [`MIGRATIONS`] that turns documents of the previous
+//! schema into the new one, on the JSON itself. Databases of earlier schemas are migrated in
+//! memory as they are loaded, and written in the current one the next time they are saved, or
+//! right away by `gleam-pkg db migrate`. Databases of later schemas, written by a newer
+//! gleam-pkg, are refused rather than saved without what this one does not know about.
+
+use crate::backend::{Target, WrapperMode};
+use crate::error::GleamPkgError;
+use crate::history::Operation;
+use crate::manifest::Dependencies;
+use crate::paths::{self, Paths};
+use crate::registry::Source;
+use crate::sqlite::SqliteFile;
+use crate::stats::Stats;
+use crate::store::Store;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// The schema of the databases this gleam-pkg writes
+pub const SCHEMA: u32 = 1;
+
+/// A change of the database format
+pub struct Migration {
+    /// The schema the migration turns documents of the previous one into
+    pub to: u32,
+    /// What changes, shown by `gleam-pkg db migrate`
+    pub description: &'static str,
+    apply: fn(&mut Value) -> Result<(), String>,
+}
+
+/// The migrations in the order they apply, the last one is to [`SCHEMA`]
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    to: 1,
+    description: "record installed versions side by side, with a default version",
+    apply: side_by_side_versions,
+}];
+
+/// Where the database document is kept
+pub trait Storage {
+    /// The stored document, `None` if nothing is stored yet
+    fn read(&self) -> Result<Option<Value>, GleamPkgError>;
+
+    /// Replaces the stored document, completely or not at all
+    fn write(&self, doc: &Value) -> Result<(), GleamPkgError>;
+}
+
+/// The storage keeping the database at `path`: a JSON file if it ends in `.json`, otherwise
+/// SQLite
+pub fn storage(path: &Path) -> Box<dyn Storage> {
+    match path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        true => Box::new(JsonFile::new(path)),
+        false => Box::new(SqliteFile::new(path)),
+    }
+}
+
+/// A database kept as a single JSON document
+pub struct JsonFile {
+    path: PathBuf,
+}
+
+impl JsonFile {
+    pub fn new(path: &Path) -> Self {
+        JsonFile {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Storage for JsonFile {
+    fn read(&self) -> Result<Option<Value>, GleamPkgError> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&self.path).map_err(|source| GleamPkgError::Io {
+            action: "read package database",
+            path: self.path.clone(),
+            source,
+        })?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|source| GleamPkgError::DatabaseError {
+                path: self.path.clone(),
+                source,
+            })
+    }
+
+    /// The document is written to a temporary file first and renamed over the old one, so an
+    /// interrupted write never leaves a truncated database behind.
+    fn write(&self, doc: &Value) -> Result<(), GleamPkgError> {
+        let tmp = self.path.with_extension("json.tmp");
+        serde_json::to_string_pretty(doc)
+            .map_err(std::io::Error::other)
+            .and_then(|json| fs::write(&tmp, json))
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|source| GleamPkgError::Io {
+                action: "write package database",
+                path: self.path.clone(),
+                source,
+            })
+    }
+}
+
+/// Held from loading the database to saving it, see [`lock`]
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// The lock of the database taken by [`lock`], released when dropped
+pub struct DbLock {
+    _guard: MutexGuard<'static, ()>,
+    _file: Option<File>,
+}
+
+/// Serializes changes to the database at `path` between threads and between gleam-pkg
+/// processes, so parallel updates and concurrent commands do not overwrite each other's records;
+/// hold the guard from [`Database::load`] to [`Database::save`]
+///
+/// Processes take an advisory `flock` of `<path>.lock`. When that file cannot be opened, e.g.
+/// before the database directory exists, only the threads of this process are serialized.
+pub fn lock(path: &Path) -> DbLock {
+    let guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path)
+        .ok()
+        // the lock goes with the file descriptor, closing it unlocks
+        .filter(|file| unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0);
+    DbLock {
+        _guard: guard,
+        _file: file,
+    }
+}
+
+/// The schema a database document is written in, 0 for those written before it was recorded
+fn schema_of(doc: &Value) -> u32 {
+    doc.get("schema")
+        .and_then(Value::as_u64)
+        .map_or(0, |schema| schema.try_into().unwrap_or(u32::MAX))
+}
+
+/// Applies the migrations a database document of an earlier schema needs, in order
+///
+/// # Returns
+///
+/// The migrations applied, none if the document is in [`SCHEMA`] or a later one
+///
+/// # Errors
+///
+/// Returns what is wrong with the document if a migration cannot understand it
+pub fn migrate(doc: &mut Value) -> Result<Vec<&'static Migration>, String> {
+    let schema = schema_of(doc);
+    if !doc.is_object() {
+        return Err("it is not a JSON object".to_string());
+    }
+    let pending: Vec<_> = MIGRATIONS.iter().filter(|m| m.to > schema).collect();
+    for migration in &pending {
+        (migration.apply)(doc)?;
+        doc["schema"] = migration.to.into();
+    }
+    Ok(pending)
+}
+
+/// Schema 1: packages recorded one `version` and its `target` before versions could be
+/// installed side by side
+fn side_by_side_versions(doc: &mut Value) -> Result<(), String> {
+    let Some(packages) = doc.get_mut("packages").and_then(Value::as_object_mut) else {
+        return Ok(());
+    };
+    for (name, package) in packages.iter_mut() {
+        let entry = package
+            .as_object_mut()
+            .ok_or_else(|| format!("the entry of {} is not an object", name))?;
+        if entry.contains_key("versions") {
+            continue;
+        }
+        let Some(Value::String(version)) = entry.remove("version") else {
+            return Err(format!("{} records no installed version", name));
+        };
+        let target = entry
+            .remove("target")
+            .ok_or_else(|| format!("{} records no target", name))?;
+        let mut installed = Map::new();
+        installed.insert("target".to_string(), target);
+        let mut versions = Map::new();
+        versions.insert(version.clone(), Value::Object(installed));
+        entry.insert("versions".to_string(), Value::Object(versions));
+        entry.insert("default_version".to_string(), Value::String(version));
+    }
+    Ok(())
+}
+
+/// One installed version of a package
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstalledVersion {
+    /// The backend the version was built with
+    pub target: Target,
+    /// The OTP release the version was compiled on, for targets running on the BEAM
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub otp_release: Option<u32>,
+    /// The store blob the wrapper runs, see [`crate::store`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<String>,
+    /// The command the version exposes, unknown for versions installed before gleam-pkg
+    /// recorded it, see [`InstalledVersion::command`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary: Option<String>,
+    /// The version of the Erlang/OTP installation embedded in `lib/<package>-<version>/erts`,
+    /// which the wrapper runs the escript on, see `install --bundle-erts`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub erts: Option<String>,
+    /// How the wrapper runs the escript when `--wrapper` chose it, otherwise `wrapper` in
+    /// config.toml decides
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrapper: Option<WrapperMode>,
+    /// Where the version came from and what built it, unknown for versions installed before
+    /// gleam-pkg recorded it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// The dependencies the version was built with, read from its manifest when it was
+    /// installed; unknown for versions installed before gleam-pkg recorded them, or without a
+    /// manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dependencies: Option<Dependencies>,
+}
+
+/// Where an installed version came from and what built it, see `gleam-pkg info --installed`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Provenance {
+    /// The URL the release tarball was downloaded from, naming the mirror that served it
+    pub tarball_url: String,
+    /// The SHA-256 checksum of the tarball
+    pub checksum: String,
+    /// Whether `checksum` matched the one the registry published for the release
+    pub verified: bool,
+    /// Unix time of the install
+    pub installed_at: u64,
+    /// The versions of the tools that built it, by tool, e.g. `gleam` and the runtime
+    pub toolchain: BTreeMap<String, String>,
+}
+
+/// A package recorded as installed, with every version of it that is installed side by side
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstalledPackage {
+    /// Installed versions, each with its own `<package>-<version>` wrapper
+    pub versions: BTreeMap<String, InstalledVersion>,
+    /// The version the unversioned `<package>` wrapper points at
+    pub default_version: String,
+    /// Pinned packages are skipped by `update` and only replaced by `install --force`
+    #[serde(default)]
+    pub pinned: bool,
+    /// Extra command names linked to the unversioned wrapper
+    #[serde(default)]
+    pub aliases: BTreeSet<String>,
+    /// Whether the unversioned wrapper was left out because its name is taken, see
+    /// `install --as`; aliases then link to the wrapper of the default version
+    #[serde(default)]
+    pub unlinked: bool,
+    /// Where the package was installed from, so updates look in the same place
+    #[serde(default, flatten, skip_serializing_if = "Source::is_hexpm")]
+    pub source: Source,
+}
+
+impl InstalledVersion {
+    /// The command the version exposes, the name of its `package` unless it records another
+    pub fn command<'a>(&'a self, package: &'a str) -> &'a str {
+        self.binary.as_deref().unwrap_or(package)
+    }
+
+    /// The dependencies `package` at `version` was built with: those recorded when it was
+    /// installed, or for versions installed before they were recorded, those of its sources
+    /// still in the download directory of `paths`; `None` if neither is there
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` or `GleamPkgError::InvalidManifest` if the manifest of its
+    /// sources cannot be read
+    pub fn load_dependencies(
+        &self,
+        paths: &Paths,
+        package: &str,
+        version: &str,
+    ) -> Result<Option<Dependencies>, GleamPkgError> {
+        match &self.dependencies {
+            Some(dependencies) => Ok(Some(dependencies.clone())),
+            None => {
+                let extract_dir = paths.download().join(format!("{}-{}", package, version));
+                Dependencies::load(&extract_dir, package)
+            }
+        }
+    }
+}
+
+impl InstalledPackage {
+    /// What aliases and the command of the package link to: its unversioned wrapper, or the
+    /// wrapper of the default version if it is [`unlinked`](Self::unlinked)
+    pub fn link_target(&self, package: &str) -> String {
+        if self.unlinked {
+            format!("{}-{}", package, self.default_version)
+        } else {
+            package.to_string()
+        }
+    }
+
+    /// The default version and how it was installed
+    pub fn default_entry(&self) -> (&str, &InstalledVersion) {
+        let version = self.default_version.as_str();
+        (version, &self.versions[version])
+    }
+}
+
+/// The contents of the package database
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Database {
+    /// The format of the document, see [`SCHEMA`]
+    #[serde(default)]
+    pub schema: u32,
+    #[serde(default)]
+    pub packages: BTreeMap<String, InstalledPackage>,
+    /// Packages confirmed at their first install, see [`crate::trust`]
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub trusted: BTreeSet<String>,
+    /// Local install statistics, see `gleam-pkg stats`
+    #[serde(default)]
+    pub stats: Stats,
+    /// The last operations changing the installation, oldest first, see [`crate::history`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<Operation>,
+    /// Escripts built before, by what went into them, see [`crate::buildcache`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub builds: BTreeMap<String, InstalledVersion>,
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Database {
+            schema: SCHEMA,
+            packages: BTreeMap::new(),
+            trusted: BTreeSet::new(),
+            stats: Stats::default(),
+            history: Vec::new(),
+            builds: BTreeMap::new(),
+        }
+    }
+}
+
+impl Database {
+    /// Loads the database from `path`, starting empty if it does not exist yet
+    ///
+    /// A database of an earlier schema is migrated, see [`migrate`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` or `GleamPkgError::DatabaseStore` if it exists but cannot be
+    /// read, `GleamPkgError::DatabaseError` if it cannot be parsed,
+    /// `GleamPkgError::DatabaseTooNew` if a newer gleam-pkg wrote it, or
+    /// `GleamPkgError::DatabaseMigrationFailed` if it cannot be migrated
+    pub fn load(path: &Path) -> Result<Self, GleamPkgError> {
+        Ok(Database::load_stored(path)?.0)
+    }
+
+    /// Loads the database like [`Database::load`], along with the schema it is stored in
+    ///
+    /// # Errors
+    ///
+    /// As [`Database::load`]
+    pub fn load_stored(path: &Path) -> Result<(Self, u32), GleamPkgError> {
+        let Some(mut doc) = storage(path).read()? else {
+            return Ok((Database::default(), SCHEMA));
+        };
+        let stored = schema_of(&doc);
+        if stored > SCHEMA {
+            return Err(GleamPkgError::DatabaseTooNew {
+                path: path.to_path_buf(),
+                schema: stored,
+            });
+        }
+        migrate(&mut doc).map_err(|reason| GleamPkgError::DatabaseMigrationFailed {
+            path: path.to_path_buf(),
+            schema: stored,
+            reason,
+        })?;
+        let db = serde_json::from_value(doc).map_err(|source| GleamPkgError::DatabaseError {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok((db, stored))
+    }
+
+    /// Writes the database to `path`, completely or not at all
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` or `GleamPkgError::DatabaseStore` if the database cannot be
+    /// written
+    pub fn save(&self, path: &Path) -> Result<(), GleamPkgError> {
+        let doc = serde_json::to_value(self).map_err(|e| GleamPkgError::Io {
+            action: "write package database",
+            path: path.to_path_buf(),
+            source: std::io::Error::other(e),
+        })?;
+        storage(path).write(&doc)
+    }
+
+    /// What does not match between the database and the installation at `paths`: versions
+    /// whose wrapper or build is missing, defaults that are not installed, missing links
+    pub fn check(&self, paths: &Paths) -> Vec<String> {
+        let store = Store::new(&paths.store());
+        let exists = |path: &Path| path.symlink_metadata().is_ok();
+        let mut problems = Vec::new();
+        for (package, installed) in &self.packages {
+            if !installed.versions.contains_key(&installed.default_version) {
+                problems.push(format!(
+                    "{}: the default version {} is not installed",
+                    package, installed.default_version
+                ));
+            } else if !installed.unlinked && !exists(&paths.apps().join(package)) {
+                problems.push(format!("{}: the wrapper {} is missing", package, package));
+            }
+            for alias in &installed.aliases {
+                if !exists(&paths.apps().join(alias)) {
+                    problems.push(format!("{}: the alias {} is missing", package, alias));
+                }
+            }
+            for (version, entry) in &installed.versions {
+                let name = format!("{}-{}", package, version);
+                if !exists(&paths.apps().join(&name)) {
+                    problems.push(format!("{}: the wrapper {} is missing", package, name));
+                }
+                let build = match &entry.blob {
+                    Some(blob) => store.path(blob).is_file(),
+                    None => paths.lib().join(&name).is_dir(),
+                };
+                if !build {
+                    problems.push(format!("{}: the build of {} is missing", package, version));
+                }
+                if entry.erts.is_some() && !paths.lib().join(&name).join("erts").is_dir() {
+                    problems.push(format!(
+                        "{}: the Erlang/OTP installation embedded in {} is missing",
+                        package, version
+                    ));
+                }
+            }
+        }
+        problems
+    }
+
+    /// The installed package providing the command `name`, as its name, an alias or the binary
+    /// of its default version, spelled the same up to case, see [`paths::same_name`]
+    pub fn command_owner(&self, name: &str) -> Option<&str> {
+        self.command_owners(name).next()
+    }
+
+    /// Every installed package providing the command `name`, see [`Database::command_owner`]
+    pub fn command_owners<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a str> {
+        self.packages
+            .iter()
+            .filter(move |(package, installed)| {
+                paths::same_name(package, name)
+                    || installed
+                        .aliases
+                        .iter()
+                        .any(|alias| paths::same_name(alias, name))
+                    || paths::same_name(installed.default_entry().1.command(package), name)
+            })
+            .map(|(package, _)| package.as_str())
+    }
+
+    /// The checksum of the tarball of `package` at `version` from `source` recorded the last time
+    /// it was installed, by the installed version or, after `uninstall`, by a build kept of it,
+    /// see [`crate::buildcache`]
+    pub fn recorded_checksum(&self, source: &Source, package: &str, version: &str) -> Option<&str> {
+        let installed = self
+            .packages
+            .get(package)
+            .filter(|installed| installed.source == *source)
+            .and_then(|installed| installed.versions.get(version));
+        // build keys start with the release, see `buildcache::BuildKey`
+        let release = format!("{}@{} ", source.qualify(package), version);
+        let kept = self
+            .builds
+            .iter()
+            .filter(|(key, _)| key.starts_with(&release))
+            .map(|(_, build)| build);
+        installed
+            .into_iter()
+            .chain(kept)
+            .filter_map(|recorded| recorded.provenance.as_ref())
+            .max_by_key(|provenance| provenance.installed_at)
+            .map(|provenance| provenance.checksum.as_str())
+    }
+
+    /// Looks up an installed package, failing if it is not installed
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::PackageNotInstalled` if `package` is not in the database
+    pub fn installed_mut(&mut self, package: &str) -> Result<&mut InstalledPackage, GleamPkgError> {
+        self.packages
+            .get_mut(package)
+            .ok_or_else(|| GleamPkgError::PackageNotInstalled {
+                package: package.to_string(),
+                version: None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_databases_of_earlier_schemas() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metadata.json");
+        fs::write(
+            &path,
+            r#"{"packages": {"hello": {"version": "1.0.0", "target": "erlang", "pinned": true}}}"#,
+        )
+        .unwrap();
+        let (db, stored) = Database::load_stored(&path).unwrap();
+        assert_eq!((stored, db.schema), (0, SCHEMA));
+        let hello = &db.packages["hello"];
+        assert_eq!(hello.default_entry().0, "1.0.0");
+        assert!(hello.pinned);
+
+        db.save(&path).unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(schema_of(&saved), SCHEMA);
+        assert!(migrate(&mut saved.clone()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn refuses_what_it_cannot_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metadata.json");
+        fs::write(&path, format!(r#"{{"schema": {}}}"#, SCHEMA + 1)).unwrap();
+        assert!(matches!(
+            Database::load(&path),
+            Err(GleamPkgError::DatabaseTooNew { .. })
+        ));
+        fs::write(&path, r#"{"packages": {"hello": {"target": "erlang"}}}"#).unwrap();
+        assert!(matches!(
+            Database::load(&path),
+            Err(GleamPkgError::DatabaseMigrationFailed { schema: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn finds_versions_that_are_not_installed() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = Paths::new(dir.path());
+        let mut db = Database::default();
+        db.packages.insert(
+            "hello".to_string(),
+            InstalledPackage {
+                versions: BTreeMap::from([(
+                    "1.0.0".to_string(),
+                    InstalledVersion {
+                        target: Target::Node,
+                        otp_release: None,
+                        blob: None,
+                        binary: None,
+                        erts: None,
+                        wrapper: None,
+                        provenance: None,
+                        dependencies: None,
+                    },
+                )]),
+                default_version: "1.0.0".to_string(),
+                pinned: false,
+                aliases: BTreeSet::new(),
+                unlinked: false,
+                source: Source::default(),
+            },
+        );
+        assert_eq!(db.check(&paths).len(), 3);
+
+        fs::create_dir_all(paths.lib().join("hello-1.0.0")).unwrap();
+        fs::create_dir_all(paths.apps()).unwrap();
+        fs::write(paths.apps().join("hello-1.0.0"), "").unwrap();
+        std::os::unix::fs::symlink("hello-1.0.0", paths.apps().join("hello")).unwrap();
+        assert!(db.check(&paths).is_empty());
+
+        // the same file on case-insensitive filesystems
+        db.installed_mut("hello")
+            .unwrap()
+            .aliases
+            .insert("hi".to_string());
+        assert_eq!(db.command_owner("Hello"), Some("hello"));
+        assert_eq!(db.command_owner("HI"), Some("hello"));
+        assert_eq!(db.command_owner("hello_cli"), None);
+        assert_eq!(db.command_owners("hi").collect::<Vec<_>>(), ["hello"]);
+    }
+
+    #[test]
+    fn remembers_checksums_of_uninstalled_releases() {
+        let build = InstalledVersion {
+            target: Target::Erlang,
+            otp_release: Some(27),
+            blob: Some("abc".to_string()),
+            binary: None,
+            erts: None,
+            wrapper: None,
+            provenance: Some(Provenance {
+                tarball_url: "https://repo.hex.pm/tarballs/hello-1.0.0.tar".to_string(),
+                checksum: "c0ffee".to_string(),
+                verified: true,
+                installed_at: 1000,
+                toolchain: BTreeMap::new(),
+            }),
+            dependencies: None,
+        };
+        let mut db = Database::default();
+        db.builds
+            .insert("hello@1.0.0 auto otp-27 gleam-1.6.2".to_string(), build);
+        let hexpm = Source::default();
+        assert_eq!(
+            db.recorded_checksum(&hexpm, "hello", "1.0.0"),
+            Some("c0ffee")
+        );
+        assert_eq!(db.recorded_checksum(&hexpm, "hello", "1.0.1"), None);
+        assert_eq!(db.recorded_checksum(&hexpm, "hell", "1.0.0"), None);
+        let internal = Source {
+            repo: Some("internal".to_string()),
+            organization: None,
+        };
+        assert_eq!(db.recorded_checksum(&internal, "hello", "1.0.0"), None);
+    }
+}
@@ -0,0 +1,76 @@
+//! Building inside a container
+//!
+//! With `--build-in-docker` (or `docker.enabled` in the config file) `gleam` and `erl` run in an
+//! official Gleam image instead of on the host, so packages can be built on machines without
+//! the BEAM toolchain. The extracted package is mounted at the same path inside the container,
+//! which writes its build output straight into the mounted directory as the calling user; the
+//! escript is then assembled from that output on the host as usual.
+
+use crate::limits::BuildLimits;
+use std::path::Path;
+use std::process::Command;
+
+/// Image builds run in unless configured otherwise
+pub const DEFAULT_IMAGE: &str = "ghcr.io/gleam-lang/gleam:v1.6.3-erlang-alpine";
+
+/// Builds a `docker run` command running `program` in `image`
+///
+/// Resource limits and network isolation are enforced by the container. The returned limits
+/// only carry the timeout and are the ones to run the docker client under, as the client
+/// itself cannot run under an address-space limit. The program is also wrapped in `timeout`
+/// so the container stops even when the client is killed.
+///
+/// # Arguments
+///
+/// * `image` - The image to run
+/// * `limits` - The limits of the build step
+/// * `mount` - A directory to mount at the same path inside the container
+/// * `workdir` - The working directory inside the container
+/// * `program` - The program to run in the container
+///
+/// # Returns
+///
+/// The command, to which the program's arguments can be added, and the limits to run it with
+///
+pub fn command(
+    image: &str,
+    limits: &BuildLimits,
+    mount: Option<&Path>,
+    workdir: Option<&Path>,
+    program: &str,
+) -> (Command, BuildLimits) {
+    // SAFETY: plain syscalls without preconditions
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let mut cmd = Command::new("docker");
+    cmd.args(["run", "--rm", "--init"])
+        .args(["--user", &format!("{}:{}", uid, gid)])
+        .args(["--env", "HOME=/tmp"]);
+    if let Some(mount) = mount {
+        cmd.arg("--volume")
+            .arg(format!("{}:{}", mount.display(), mount.display()));
+    }
+    if let Some(workdir) = workdir {
+        cmd.arg("--workdir").arg(workdir);
+    }
+    if let Some(bytes) = limits.memory_limit {
+        cmd.arg("--memory").arg(format!("{}b", bytes));
+    }
+    if let Some(secs) = limits.cpu_limit {
+        cmd.arg("--ulimit").arg(format!("cpu={}", secs));
+    }
+    if limits.isolation.as_ref().is_some_and(|i| !i.network) {
+        cmd.args(["--network", "none"]);
+    }
+    cmd.arg(image)
+        .args(["timeout", "-s", "KILL"])
+        .arg(limits.timeout.as_secs().to_string())
+        .arg(program);
+
+    let client_limits = BuildLimits {
+        timeout: limits.timeout,
+        memory_limit: None,
+        cpu_limit: None,
+        isolation: None,
+    };
+    (cmd, client_limits)
+}
@@ -0,0 +1,378 @@
+//! Pruning the download cache
+//!
+//! Installs keep the tarball and the extracted sources of every release they download:
+//!
+//! ```text
+//! download/<pkg>-<ver>.tar   the tarball
+//! download/<pkg>-<ver>/      its sources
+//! ```
+//!
+//! Installs record the dependencies of each version in the package database, so `audit --deps`,
+//! `licenses --deps` and `sbom` only read the sources of versions installed before that.
+//!
+//! `gleam-pkg gc` removes those of versions no longer installed. The policies of
+//! `[cache.downloads]` in `config.toml` prune the others too, with `gleam-pkg prune-downloads`,
+//! whose flags override them, or after every install with `prune_after_install = true`:
+//!
+//! ```toml
+//! [cache.downloads]
+//! keep_latest = 2
+//! max_size_mib = 500
+//! max_age_days = 30
+//! ```
+//!
+//! `keep_latest` keeps the downloads of the newest versions of each package, `max_age_days`
+//! prunes those not used for that long, and `max_size_mib` prunes the least recently used ones
+//! until the rest fit. None is set by default.
+//!
+//! The downloads of the default version of an installed package are never pruned, nor those of
+//! installed versions whose dependencies were not recorded but are in their sources, or of a
+//! release an install is using: it holds a shared lock of `download/<pkg>-<ver>.lock` from the
+//! download until it is recorded, see [`in_use`]. Only entries named `<package>-<semver>` are
+//! releases, the temporary directories of bundles are left alone.
+
+use crate::config::DownloadsConfig;
+use crate::db::Database;
+use crate::error::GleamPkgError;
+use crate::gc::{self, Garbage, Kind};
+use crate::manifest::Dependencies;
+use crate::paths::Paths;
+use crate::plan::disk_usage;
+use semver::Version;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::os::fd::AsRawFd;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Which downloads are pruned
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Policy {
+    /// How many versions of each package the downloads are kept of, the newest
+    pub keep_latest: Option<usize>,
+    /// How many bytes the downloads may take up together
+    pub max_size: Option<u64>,
+    /// How long a download is kept after it was last used
+    pub max_age: Option<Duration>,
+}
+
+impl Policy {
+    /// The policy `[cache.downloads]` sets
+    pub fn new(config: &DownloadsConfig) -> Self {
+        Policy {
+            keep_latest: config.keep_latest,
+            max_size: config.max_size_mib.map(|mib| mib * 1024 * 1024),
+            max_age: config.max_age_days.map(days),
+        }
+    }
+
+    /// Whether the policy prunes nothing
+    pub fn is_empty(&self) -> bool {
+        *self == Policy::default()
+    }
+}
+
+/// Prefixes of the temporary directories `install --file` and `bundle` unpack to in the
+/// download directory
+const TEMP_PREFIXES: [&str; 2] = ["bundle-", "bundle-otp-"];
+
+/// The lock of the downloads of a release an install is using, released when dropped
+pub struct InUse {
+    _file: Option<File>,
+}
+
+/// Marks the downloads of `package` at `version` in use until the guard is dropped, so
+/// [`find`] does not prune them, in this process or another one
+///
+/// When the lock file cannot be opened, e.g. before the download directory exists, nothing is
+/// marked.
+pub fn in_use(paths: &Paths, package: &str, version: &str) -> InUse {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path(paths, &format!("{}-{}", package, version)))
+        .ok()
+        .filter(|file| unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH) } == 0);
+    InUse { _file: file }
+}
+
+/// Whether an install holds the lock of the downloads of `release`, see [`in_use`]
+fn used_by_install(paths: &Paths, release: &str) -> bool {
+    let Ok(file) = OpenOptions::new()
+        .write(true)
+        .open(lock_path(paths, release))
+    else {
+        return false;
+    };
+    // the lock goes with the file descriptor, closing it unlocks
+    unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) != 0 }
+}
+
+fn lock_path(paths: &Paths, release: &str) -> PathBuf {
+    paths.download().join(format!("{}.lock", release))
+}
+
+/// The package and version of a download named `release`, `None` if it is not named
+/// `<package>-<semver>`, like the temporary directories of bundles
+fn parse_release(release: &str) -> Option<(&str, &str)> {
+    if TEMP_PREFIXES
+        .iter()
+        .any(|prefix| release.starts_with(prefix))
+    {
+        return None;
+    }
+    // package names have no dashes, versions may
+    let (package, version) = release.split_once('-')?;
+    let valid = package.starts_with(|c: char| c.is_ascii_lowercase())
+        && package
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    (valid && Version::parse(version).is_ok()).then_some((package, version))
+}
+
+/// `count` days
+pub fn days(count: u64) -> Duration {
+    Duration::from_secs(count * 24 * 60 * 60)
+}
+
+/// The downloads of one release
+#[derive(Debug)]
+struct Release {
+    package: String,
+    version: String,
+    /// Its tarball, an unfinished download of it and its sources
+    paths: Vec<PathBuf>,
+    size: u64,
+    /// When one of them was last modified
+    used_at: SystemTime,
+    /// Whether it is the default version of an installed package, an installed version whose
+    /// dependencies are only in its sources, or used by an install
+    protected: bool,
+}
+
+/// Finds the downloads `policy` prunes
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::DatabaseError` if the database cannot be read, or
+/// `GleamPkgError::Io` if the download directory cannot be listed
+pub fn find(paths: &Paths, policy: &Policy) -> Result<Vec<Garbage>, GleamPkgError> {
+    let db = Database::load(&paths.db_file())?;
+    let kept: BTreeSet<String> = db
+        .packages
+        .iter()
+        .flat_map(|(name, installed)| {
+            installed
+                .versions
+                .iter()
+                .filter(move |(version, entry)| {
+                    let extract_dir = paths.download().join(format!("{}-{}", name, version));
+                    let unrecorded = || {
+                        entry.dependencies.is_none()
+                            && Dependencies::load(&extract_dir, name).is_ok_and(|d| d.is_some())
+                    };
+                    **version == installed.default_version || unrecorded()
+                })
+                .map(move |(version, _)| format!("{}-{}", name, version))
+        })
+        .collect();
+    let mut releases: BTreeMap<String, Release> = BTreeMap::new();
+    for path in gc::list(&paths.download())? {
+        let name = gc::file_name(&path);
+        let release = name
+            .strip_suffix(".tar")
+            .or_else(|| name.strip_suffix(".tar.part"))
+            .unwrap_or(&name);
+        let Some((package, version)) = parse_release(release) else {
+            continue;
+        };
+        let used_at = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .unwrap_or(UNIX_EPOCH);
+        let entry = releases
+            .entry(release.to_string())
+            .or_insert_with(|| Release {
+                package: package.to_string(),
+                version: version.to_string(),
+                paths: Vec::new(),
+                size: 0,
+                used_at,
+                protected: kept.contains(release) || used_by_install(paths, release),
+            });
+        entry.size += disk_usage(&path);
+        entry.used_at = entry.used_at.max(used_at);
+        entry.paths.push(path);
+    }
+    let pruned = select(releases.into_values().collect(), policy, SystemTime::now());
+    Ok(pruned
+        .into_iter()
+        .flat_map(|release| release.paths)
+        .map(|path| Garbage {
+            kind: match path.is_dir() {
+                true => Kind::Sources,
+                false => Kind::Tarball,
+            },
+            size: disk_usage(&path),
+            path,
+        })
+        .collect())
+}
+
+/// The releases of `releases` whose downloads `policy` prunes at `now`
+fn select(releases: Vec<Release>, policy: &Policy, now: SystemTime) -> Vec<Release> {
+    let mut pruned = vec![false; releases.len()];
+    if let Some(keep) = policy.keep_latest {
+        let mut by_package: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (i, release) in releases.iter().enumerate() {
+            by_package.entry(&release.package).or_default().push(i);
+        }
+        for indexes in by_package.values_mut() {
+            // versions that are not semver sort before every other one
+            indexes.sort_by_key(|&i| std::cmp::Reverse(Version::parse(&releases[i].version).ok()));
+            for &i in indexes.iter().skip(keep) {
+                pruned[i] = true;
+            }
+        }
+    }
+    if let Some(max_age) = policy.max_age {
+        for (i, release) in releases.iter().enumerate() {
+            let age = now.duration_since(release.used_at).unwrap_or_default();
+            pruned[i] |= age > max_age;
+        }
+    }
+    for (i, release) in releases.iter().enumerate() {
+        pruned[i] &= !release.protected;
+    }
+    if let Some(max_size) = policy.max_size {
+        let mut size: u64 = (0..releases.len())
+            .filter(|&i| !pruned[i])
+            .map(|i| releases[i].size)
+            .sum();
+        let mut least_recently_used: Vec<usize> = (0..releases.len())
+            .filter(|&i| !pruned[i] && !releases[i].protected)
+            .collect();
+        least_recently_used.sort_by_key(|&i| releases[i].used_at);
+        for i in least_recently_used {
+            if size <= max_size {
+                break;
+            }
+            pruned[i] = true;
+            size -= releases[i].size;
+        }
+    }
+    releases
+        .into_iter()
+        .zip(pruned)
+        .filter_map(|(release, pruned)| pruned.then_some(release))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(package: &str, version: &str, size: u64, days_ago: u64) -> Release {
+        Release {
+            package: package.to_string(),
+            version: version.to_string(),
+            paths: Vec::new(),
+            size,
+            used_at: UNIX_EPOCH + days(100 - days_ago),
+            protected: false,
+        }
+    }
+
+    fn pruned(releases: Vec<Release>, policy: Policy) -> Vec<String> {
+        select(releases, &policy, UNIX_EPOCH + days(100))
+            .into_iter()
+            .map(|release| format!("{}-{}", release.package, release.version))
+            .collect()
+    }
+
+    fn releases() -> Vec<Release> {
+        let mut default = release("hello", "1.0.0", 50, 60);
+        default.protected = true;
+        vec![
+            default,
+            release("hello", "1.10.0", 10, 1),
+            release("hello", "1.2.0", 20, 10),
+            release("hello", "2.0.0-rc.1", 30, 40),
+            release("wisp", "0.1.0", 40, 20),
+        ]
+    }
+
+    #[test]
+    fn keeps_the_newest_versions() {
+        let policy = Policy {
+            keep_latest: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(pruned(releases(), policy), ["hello-1.2.0"]);
+        let policy = Policy {
+            keep_latest: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            pruned(releases(), policy),
+            [
+                "hello-1.10.0",
+                "hello-1.2.0",
+                "hello-2.0.0-rc.1",
+                "wisp-0.1.0"
+            ]
+        );
+    }
+
+    #[test]
+    fn prunes_old_downloads_then_the_least_recently_used() {
+        let policy = Policy {
+            max_age: Some(days(30)),
+            ..Default::default()
+        };
+        assert_eq!(pruned(releases(), policy), ["hello-2.0.0-rc.1"]);
+        let policy = Policy {
+            max_size: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(
+            pruned(releases(), policy),
+            ["hello-2.0.0-rc.1", "wisp-0.1.0"]
+        );
+        assert!(pruned(releases(), Policy::default()).is_empty());
+    }
+
+    #[test]
+    fn finds_the_downloads_of_pruned_releases() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = Paths::new(root.path());
+        paths.create_dirs().unwrap();
+        fs::write(paths.download().join("hello-1.0.0.tar"), "tar").unwrap();
+        fs::create_dir_all(paths.download().join("hello-1.0.0").join("src")).unwrap();
+        fs::write(paths.download().join("hello-1.1.0.tar.part"), "ta").unwrap();
+        fs::create_dir_all(paths.download().join("bundle-42")).unwrap();
+        fs::create_dir_all(paths.download().join("bundle-otp-42")).unwrap();
+        fs::write(paths.download().join("notes-for-me.txt"), "").unwrap();
+        fs::write(paths.download().join("wisp-0.1.0.tar"), "tar").unwrap();
+        fs::write(paths.download().join("wisp-0.2.0.tar"), "tar").unwrap();
+        let _wisp = in_use(&paths, "wisp", "0.1.0");
+
+        let policy = Policy {
+            keep_latest: Some(1),
+            ..Default::default()
+        };
+        let mut found: Vec<_> = find(&paths, &policy)
+            .unwrap()
+            .into_iter()
+            .map(|g| (g.kind, gc::file_name(&g.path)))
+            .collect();
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                (Kind::Tarball, "hello-1.0.0.tar".to_string()),
+                (Kind::Sources, "hello-1.0.0".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Introspection of the Erlang runtime
+//!
+//! Builds, the toolchain checks and the wrappers need to know which Erlang/OTP they run on.
+//! Everything gleam-pkg asks the runtime is a [`Fact`], printed by an `io:format` expression
+//! built here rather than written out by hand at every call site:
+//!
+//! - the runtime of the build toolchain, see [`crate::toolchain::erl_command`], is asked for
+//!   all of its facts at once, the first time one is needed, and the answer is cached for the
+//!   rest of the command: [`otp_release`], [`erts_version`] and [`system_version`]
+//! - any other `erl`, e.g. one the wrapper of an escript might pick, is asked for its release
+//!   by [`release_of`], or by the shell running [`release_probe`]
+//!
+//! Values spliced into expressions are written as Erlang literals with [`string`], escaping
+//! quotes, backslashes, control and non-ASCII characters.
+
+use crate::error::GleamPkgError;
+use crate::limits::BuildLimits;
+use crate::runner::Runner;
+use crate::toolchain;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+/// Something the runtime is asked about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fact {
+    /// The major release of OTP, e.g. `27`
+    OtpRelease,
+    /// The version of the runtime system, e.g. `15.1.2`
+    ErtsVersion,
+    /// The banner of the runtime, e.g. `Erlang/OTP 27 [erts-15.1.2] [64-bit] ...`
+    SystemVersion,
+}
+
+impl Fact {
+    /// The expression evaluating to the fact, as a string
+    fn term(self) -> &'static str {
+        match self {
+            Fact::OtpRelease => "erlang:system_info(otp_release)",
+            Fact::ErtsVersion => "erlang:system_info(version)",
+            Fact::SystemVersion => "string:trim(erlang:system_info(system_version))",
+        }
+    }
+}
+
+/// Every fact, in the order the runtime prints them
+const FACTS: [Fact; 3] = [Fact::OtpRelease, Fact::ErtsVersion, Fact::SystemVersion];
+
+/// What the runtime of the build toolchain told about itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Runtime {
+    otp_release: u32,
+    erts_version: String,
+    system_version: String,
+}
+
+/// The `erl` a runtime is asked through and the build container it runs in, if any
+type Asked = (PathBuf, Option<String>);
+
+/// The runtimes asked so far
+static RUNTIMES: Mutex<Vec<(Asked, Runtime)>> = Mutex::new(Vec::new());
+
+/// The major OTP release of the build runtime, e.g. `27`
+///
+/// # Errors
+///
+/// See [`runtime`]
+pub fn otp_release(limits: &BuildLimits) -> Result<u32, GleamPkgError> {
+    Ok(runtime(limits)?.otp_release)
+}
+
+/// The version of the runtime system of the build runtime, e.g. `15.1.2`
+///
+/// # Errors
+///
+/// See [`runtime`]
+pub fn erts_version(limits: &BuildLimits) -> Result<String, GleamPkgError> {
+    Ok(runtime(limits)?.erts_version)
+}
+
+/// The banner of the build runtime, e.g. `Erlang/OTP 27 [erts-15.1.2] [64-bit]`, which escripts
+/// are labelled with
+///
+/// # Errors
+///
+/// See [`runtime`]
+pub fn system_version(limits: &BuildLimits) -> Result<String, GleamPkgError> {
+    Ok(runtime(limits)?.system_version)
+}
+
+/// Asks the build runtime about itself, unless it was already asked
+///
+/// # Errors
+///
+/// Returns the errors of running `erl`, see [`Runner::run_checked`], or
+/// `GleamPkgError::ToolchainMissing` if what it prints is not an Erlang runtime describing
+/// itself
+fn runtime(limits: &BuildLimits) -> Result<Runtime, GleamPkgError> {
+    let key = (toolchain::erl(), toolchain::docker_image());
+    let mut runtimes = RUNTIMES.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((_, runtime)) = runtimes.iter().find(|(asked, _)| *asked == key) {
+        return Ok(runtime.clone());
+    }
+    let output = eval(&format!("{}.", print(&FACTS)), limits)?;
+    let runtime = parse_runtime(&output).ok_or_else(|| GleamPkgError::ToolchainMissing {
+        tool: "erl".to_string(),
+        required: "an Erlang/OTP runtime".to_string(),
+        found: Some(output.trim().to_string()),
+    })?;
+    tracing::debug!("{:?} runs on {:?}", key.0, runtime);
+    runtimes.push((key, runtime.clone()));
+    Ok(runtime)
+}
+
+/// Reads the answer to [`FACTS`], one per line
+fn parse_runtime(output: &str) -> Option<Runtime> {
+    let mut lines = output.lines().map(str::trim);
+    let runtime = Runtime {
+        otp_release: lines.next()?.parse().ok()?,
+        erts_version: lines.next()?.to_string(),
+        system_version: lines.next()?.to_string(),
+    };
+    lines.next().is_none().then_some(runtime)
+}
+
+/// Asks the `erl` at `erl` for its OTP release
+///
+/// # Returns
+///
+/// The release, or `None` if it does not run or prints something else
+pub fn release_of(erl: &Path, limits: &BuildLimits) -> Option<u32> {
+    let output = Runner::new(Command::new(erl))
+        .args(["-noshell", "-eval", &release_probe()])
+        .limits(limits)
+        .describe(format!("`{} -noshell`", erl.display()))
+        .capture()
+        .run()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// The expression printing the OTP release of the runtime evaluating it and halting it, for
+/// `erl -noshell -eval`
+pub fn release_probe() -> String {
+    format!("{}, halt().", print(&[Fact::OtpRelease]))
+}
+
+/// Evaluates `expr` in the build runtime, a sequence of expressions ending with a full stop
+///
+/// # Errors
+///
+/// Returns the errors of [`Runner::run_checked`]
+///
+/// # Returns
+///
+/// What it printed
+fn eval(expr: &str, limits: &BuildLimits) -> Result<String, GleamPkgError> {
+    //  erl -noshell -eval 'expr' -s init stop
+    let (cmd, limits) = toolchain::erl_command(limits);
+    let output = Runner::new(cmd)
+        .args(["-noshell", "-eval", expr, "-s", "init", "stop"])
+        .limits(&limits)
+        .describe("erl eval")
+        .run_checked()?;
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// An expression printing `facts` to stdout, one per line
+fn print(facts: &[Fact]) -> String {
+    let terms: Vec<_> = facts.iter().map(|fact| fact.term()).collect();
+    format!(
+        "io:format(standard_io, {}, [{}])",
+        string(&"~ts~n".repeat(facts.len())),
+        terms.join(", ")
+    )
+}
+
+/// `value` as an Erlang string literal
+pub fn string(value: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            ' '..='~' => literal.push(c),
+            c => literal.push_str(&format!("\\x{{{:X}}}", c as u32)),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_expressions_printing_facts() {
+        assert_eq!(
+            release_probe(),
+            "io:format(standard_io, \"~ts~n\", [erlang:system_info(otp_release)]), halt()."
+        );
+        assert_eq!(
+            string("say \"hi\"\\\n\tjürgen\u{1}"),
+            r#""say \"hi\"\\\n\tj\x{FC}rgen\x{1}""#
+        );
+    }
+
+    #[test]
+    fn reads_what_the_runtime_tells() {
+        let runtime = parse_runtime("27\n15.1.2\nErlang/OTP 27 [erts-15.1.2] [64-bit]\n").unwrap();
+        assert_eq!(
+            runtime,
+            Runtime {
+                otp_release: 27,
+                erts_version: "15.1.2".to_string(),
+                system_version: "Erlang/OTP 27 [erts-15.1.2] [64-bit]".to_string(),
+            }
+        );
+        assert!(parse_runtime("27\n15.1.2\n").is_none());
+        assert!(parse_runtime("Segmentation fault\n\n\n").is_none());
+    }
+}
@@ -1,40 +1,655 @@
+use crate::i18n::{self, Message};
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
 use thiserror::Error;
 
 /// Custom error type for the `gleam-pkg` package manager
 ///
 /// This enum defines all possible errors that may occur while using the Gleam package manager.
-/// Each variant includes a message describing the specific error.
+/// Variants carry the values involved (URLs, paths, packages, versions) as typed fields, and the
+/// error that caused them as their `source`, so callers can match on causes instead of messages.
 #[derive(Error, Debug)]
 pub enum GleamPkgError {
-    /// Error indicating a failure to start a program
-    ///
-    /// # Example
-    /// This error might occur if a required Gleam program cannot be executed.
-    #[error("Failed to start program: {0}")]
-    ProgramError(String),
+    /// Error indicating the home directory of the user cannot be determined
+    #[error("Unable to locate home directory")]
+    HomeDirNotFound,
 
     /// Error indicating a failure to create necessary directories
     ///
     /// # Example
     /// This error might occur if there are insufficient permissions to create directories.
-    #[error("Failed to create directories: {0}")]
-    DirectoryCreationError(String),
+    #[error("Failed to create directory: {}", .path.display())]
+    DirectoryCreationError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// Error indicating an HTTP request did not get a response
+    #[error("Request to {url} failed")]
+    RequestFailed {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// Error indicating the HTTP client could not be set up
+    #[error("Failed to set up the HTTP client")]
+    HttpClient {
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// Error indicating a request was needed with `--offline` or `offline = true`
+    #[error("Offline, and what is needed is not cached; run again without --offline")]
+    Offline,
+
+    /// Error indicating the configured CA bundle holds no usable PEM certificates
+    #[error("Invalid CA bundle: {}", .path.display())]
+    InvalidCertificate {
+        path: PathBuf,
+        #[source]
+        source: Option<reqwest::Error>,
+    },
+
+    /// Error indicating the registry answered with an unsuccessful status code
+    #[error("{url} responded with status {status}")]
+    HttpStatus { url: String, status: u16 },
+
+    /// Error indicating every mirror of a repository failed `config mirrors test`
+    #[error("No mirror of {} answered", repos.join(", "))]
+    MirrorsFailed { repos: Vec<String> },
+
+    /// Error indicating a downloaded tarball is not the one the registry published
+    #[error(
+        "The tarball of {package} {version} has checksum {actual}, but the registry published \
+         {expected}; it was deleted, try again"
+    )]
+    ChecksumMismatch {
+        package: String,
+        version: String,
+        expected: String,
+        actual: String,
+    },
+
+    /// Error indicating the registry publishes another tarball for a release installed before
+    #[error(
+        "The registry now publishes checksum {published} for {package} {version}, but it was \
+         installed with {recorded}; the release was republished, pass --accept-new-checksum to \
+         install it anyway"
+    )]
+    ChecksumChanged {
+        package: String,
+        version: String,
+        recorded: String,
+        published: String,
+    },
+
+    /// Error indicating a downloaded tarball is not the one `.gleam-tools.lock` locks
+    #[error(
+        "The tarball of {package} {version} has checksum {actual}, but .gleam-tools.lock locks \
+         {locked}; it was deleted"
+    )]
+    LockedChecksumMismatch {
+        package: String,
+        version: String,
+        locked: String,
+        actual: String,
+    },
+
+    /// Error indicating `project install --locked` found `.gleam-tools.lock` out of date
+    #[error(
+        ".gleam-tools.lock does not match .gleam-tools.toml for {}, run `gleam-pkg project \
+         install` without --locked to update it",
+        .tools.join(", ")
+    )]
+    LockfileOutdated { tools: Vec<String> },
+
+    /// Error indicating `gleam-pkg ci-verify` found tools that cannot be installed as locked
+    #[error(
+        "{count} tool(s) of .gleam-tools.toml cannot be installed as .gleam-tools.lock locks them"
+    )]
+    VerifyFailed { count: usize },
+
+    /// Error indicating `gleam-pkg check-publishable` found problems that would break installs
+    #[error("{count} problem(s) would break installing the package once published")]
+    NotPublishable { count: usize },
+
+    /// Error indicating no release meets the version configured for a package
+    #[error(
+        "{package} has no release meeting {requirement}, the version under [packages.{package}] \
+         in config.toml"
+    )]
+    NoConfiguredRelease {
+        package: String,
+        requirement: String,
+    },
+
+    /// Error indicating the version configured for a package is not a version requirement
+    #[error(
+        "version = \"{requirement}\" under [packages.{package}] in config.toml is not a version \
+         requirement like \"~> 2.0\" or \">= 2.1.0 and < 3.0.0\""
+    )]
+    InvalidVersionRequirement {
+        package: String,
+        requirement: String,
+    },
+
+    /// Error indicating `gleam publish` did not succeed
+    #[error("gleam publish failed ({status}), nothing was installed")]
+    PublishFailed { status: String },
+
+    /// Error indicating a release was published but installing it failed
+    #[error("{package} {version} was published, but cannot be installed")]
+    PublishedNotInstallable {
+        package: String,
+        version: String,
+        #[source]
+        source: Box<GleamPkgError>,
+    },
+
+    /// Error indicating a response body could not be read or decoded
+    #[error("Invalid response from {url}")]
+    InvalidResponse {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// Error indicating the versions index or the package names of a repository could not be
+    /// decoded, see [`crate::index`]
+    #[error("Invalid index from {url}: {message}")]
+    InvalidIndex { url: String, message: String },
 
-    #[error("Failed to download package: {0}")]
-    PackageDownloadError(String),
+    /// Error indicating a package identifier cannot be parsed
+    #[error(
+        "Invalid package: {spec}, expected [repo:][organization/]package[@version] or a \
+         https://hex.pm/packages/ URL"
+    )]
+    InvalidPackageSpec { spec: String },
 
-    #[error("IO error: {0}")]
-    IOErr(std::io::Error),
+    /// Error indicating a package name given on the command line is not one hex accepts
+    #[error(
+        "{name} is not a valid package name, names start with a letter and hold only letters, \
+         digits and _{}",
+        .suggestion.as_ref().map_or(String::new(), |s| format!("; did you mean {}?", s))
+    )]
+    InvalidPackageName {
+        name: String,
+        suggestion: Option<String>,
+    },
 
-    #[error("Package build error: {0}")]
-    PackageBuildError(String),
+    /// Error indicating the registry does not know a package
+    #[error(
+        "{url} responded with status 404, there is no package {package}{}",
+        .suggestion.as_ref().map_or(String::new(), |s| format!("; did you mean {}?", s))
+    )]
+    UnknownPackage {
+        package: String,
+        url: String,
+        suggestion: Option<String>,
+    },
 
-    #[error("Error inspecting PATH environment variable: {0}")]
-    PathError(String),
+    /// Error indicating a package names a repository missing from the configuration
+    #[error("Unknown repository: {name}, add it as [repos.{name}] to config.toml")]
+    UnknownRepository { name: String },
+
+    /// Error indicating the metadata of a package lists no releases
+    #[error("No releases of {package} found in its metadata")]
+    NoReleases { package: String },
+
+    /// Error indicating every release of a package is a pre-release, none of which is installed
+    /// unless asked for
+    #[error(
+        "{package} has only pre-releases; install the newest with {package}@{newest} or --latest"
+    )]
+    OnlyPreReleases { package: String, newest: String },
+
+    /// Error indicating every stable release of a package is retired
+    #[error("Every release of {package} is retired; install one as {package}@<version>")]
+    OnlyRetiredReleases { package: String },
+
+    /// Error indicating a requested version of a package was never published
+    #[error("{package} has no release {version}")]
+    ReleaseNotFound { package: String, version: String },
+
+    /// Error indicating a file system operation on a known path failed
+    ///
+    /// # Example
+    /// `action` describes the operation, e.g. `extract tarball` or `write wrapper script`.
+    #[error("Failed to {action}: {}", .path.display())]
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error(transparent)]
+    IOErr(#[from] io::Error),
+
+    /// Error indicating the `gleam.toml` of a package cannot be parsed
+    #[error("Invalid package manifest: {}", .path.display())]
+    InvalidManifest {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    /// Error indicating `gleam build` failed for a package
+    #[error(
+        "Failed to build {package} {version}: {status}, see the full log at {}",
+        .log.display()
+    )]
+    BuildFailed {
+        package: String,
+        version: String,
+        status: String,
+        log: PathBuf,
+    },
+
+    /// Error indicating a freshly built command crashed when run, see [`crate::smoke`]
+    #[error(
+        "{package} {version} was built, but {command} crashed ({reason}), see the full log at {}",
+        .log.display()
+    )]
+    SmokeTestFailed {
+        package: String,
+        version: String,
+        command: String,
+        reason: String,
+        log: PathBuf,
+    },
+
+    /// Error indicating an external command exited unsuccessfully
+    #[error("{command} failed ({status}): {stderr}")]
+    CommandFailed {
+        command: String,
+        status: String,
+        stderr: String,
+    },
+
+    /// Error indicating an external command could not be started or waited for
+    #[error("Failed to run {command}")]
+    SpawnFailed {
+        command: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// Error indicating a `pre_*` hook from `config.toml` failed, stopping the command
+    #[error("The {event} hook of {package} failed ({status})")]
+    HookFailed {
+        event: &'static str,
+        package: String,
+        status: String,
+    },
+
+    /// Error indicating an external build step was killed after exceeding its time limit
+    #[error("{command} did not finish within {}s", .timeout.as_secs())]
+    BuildTimeout { command: String, timeout: Duration },
+
+    /// Error indicating an Erlang build did not record the OTP release it was compiled with
+    #[error("The OTP release {package} was built with is unknown")]
+    UnknownOtpRelease { package: String },
+
+    /// Error indicating `--no-network` was requested where network namespaces are unavailable
+    #[error("Building without network access is only supported on Linux")]
+    NetworkIsolationUnsupported,
+
+    /// Error indicating a required external tool is missing or too old
+    ///
+    /// # Example
+    /// This error occurs if `gleam` is not on `PATH`, or `erl` belongs to an unsupported OTP.
+    #[error(
+        "{tool} ({required}) is required, found {}",
+        .found.as_deref().unwrap_or("nothing")
+    )]
+    ToolchainMissing {
+        tool: String,
+        required: String,
+        found: Option<String>,
+    },
+
+    /// Error indicating a release requires a newer Gleam compiler than the one installed, and no
+    /// release to fall back to was allowed or found
+    #[error(
+        "{package} {version} requires gleam {required}, found {found}; update Gleam or install \
+         an older release with {package}@<version>"
+    )]
+    GleamTooOld {
+        package: String,
+        version: String,
+        required: String,
+        found: String,
+    },
+
+    /// Error indicating a tool gleam-pkg downloads has no builds for this machine, or the one a
+    /// bundle is made for
+    #[error("No {tool} builds are published for {os} on {arch}")]
+    UnsupportedPlatform {
+        tool: String,
+        os: String,
+        arch: String,
+    },
+
+    /// Error indicating a package is built with tools gleam-pkg has no backend for, e.g.
+    /// erlang.mk
+    #[error(
+        "{package} {version} is built with {}, gleam-pkg builds packages with gleam, rebar3 or \
+         mix",
+        .tools.join(", ")
+    )]
+    UnsupportedBuildTool {
+        package: String,
+        version: String,
+        tools: Vec<String>,
+    },
+
+    /// Error indicating `--target` picked a backend that does not build packages made with the
+    /// build tools a package declares
+    #[error(
+        "{package} {version} is built with {}, which the {backend} backend does not build, \
+         leave out --target to pick a matching one",
+        .tools.join(", ")
+    )]
+    WrongBuildTool {
+        package: String,
+        version: String,
+        tools: Vec<String>,
+        backend: &'static str,
+    },
+
+    /// Error indicating a rebar3 or mix build finished without producing an escript, e.g. for
+    /// a library without an escript configuration
+    #[error(
+        "Building {package} produced no escript in {}, it may not be a command line tool",
+        .dir.display()
+    )]
+    NoEscript { package: String, dir: PathBuf },
+
+    /// Error indicating `config.toml` cannot be parsed
+    #[error("Invalid configuration: {}", .path.display())]
+    ConfigError {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    /// Error indicating a `GLEAM_PKG_*` variable overriding a setting holds a value of the wrong
+    /// type
+    #[error("Invalid {name}={value:?}, expected {expected}")]
+    InvalidEnvVar {
+        name: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+
+    /// Error indicating the `.gleam-tools.toml` of a project cannot be parsed
+    #[error("Invalid project tools: {}", .path.display())]
+    ToolsManifestError {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    /// Error indicating a directory belongs to no project with a `.gleam-tools.toml`
+    #[error(
+        "No .gleam-tools.toml in {} or its parents, create one with `gleam-pkg project init`",
+        .dir.display()
+    )]
+    NoProject { dir: PathBuf },
+
+    /// Error indicating a file given to `gleam-pkg install --file` is not a bundle it can install
+    #[error("Cannot install the bundle {}: {reason}", .path.display())]
+    InvalidBundle { path: PathBuf, reason: String },
+
+    /// Error indicating the package database cannot be parsed
+    #[error("Corrupt package database: {}", .path.display())]
+    DatabaseError {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// Error indicating the SQLite database keeping the package database failed
+    #[error("Failed to access the package database: {}", .path.display())]
+    DatabaseStore {
+        path: PathBuf,
+        #[source]
+        source: rusqlite::Error,
+    },
+
+    /// Error indicating the package database was written by a newer gleam-pkg
+    #[error(
+        "The package database {} has schema {schema}, this gleam-pkg reads up to schema {}, \
+         update gleam-pkg",
+        .path.display(),
+        crate::db::SCHEMA
+    )]
+    DatabaseTooNew { path: PathBuf, schema: u32 },
+
+    /// Error indicating the package database cannot be brought to the current schema
+    #[error(
+        "Cannot migrate the package database {} from schema {schema}: {reason}",
+        .path.display()
+    )]
+    DatabaseMigrationFailed {
+        path: PathBuf,
+        schema: u32,
+        reason: String,
+    },
+
+    /// Error indicating `gleam-pkg db check` found the database and the installation disagree
+    #[error(
+        "{count} problem(s) found in the package database, reinstall the packages concerned \
+         or run `gleam-pkg regen-wrappers`"
+    )]
+    DatabaseInconsistent { count: usize },
+
+    /// Error indicating a package, or one version of it, is not installed
+    #[error(
+        "{package}{} is not installed",
+        .version.as_ref().map(|v| format!("@{}", v)).unwrap_or_default()
+    )]
+    PackageNotInstalled {
+        package: String,
+        version: Option<String>,
+    },
+
+    /// Error indicating the build of an installed version is no longer kept
+    #[error(
+        "The build of {package} {version} is gone, reinstall it with `gleam-pkg install \
+         {package}@{version} --force`"
+    )]
+    BuildMissing { package: String, version: String },
+
+    /// Error indicating an install would replace the pinned version of a package
+    #[error("{package} is pinned at {pinned}, use --force to install {requested}")]
+    PackagePinned {
+        package: String,
+        pinned: String,
+        requested: String,
+    },
+
+    /// Error indicating an install would replace a command of another package, or a file not
+    /// managed by gleam-pkg
+    #[error(
+        "{command} is already {taken_by}, pass --overwrite to replace it{}",
+        suggest_alias(.command, .package)
+    )]
+    CommandConflict {
+        command: String,
+        package: String,
+        /// Who uses the command, e.g. `a command of wisp`
+        taken_by: String,
+    },
+
+    /// Error indicating a package installed for the first time could not be confirmed, see
+    /// [`crate::trust`]
+    #[error(
+        "{package} was never installed before and needs confirmation, run the install in a \
+         terminal or pass --yes or --trust-all"
+    )]
+    ConfirmationRequired { package: String },
+
+    /// Error indicating the user declined to install a package
+    #[error("Did not install {package}")]
+    InstallDeclined { package: String },
+
+    /// Error indicating a package, version or command cannot name a file, see
+    /// [`crate::paths::check_name`]
+    #[error("{name:?} cannot be used as a file name: {reason}")]
+    InvalidName { name: String, reason: &'static str },
+
+    /// Error indicating a package differs from an installed one only in case, so their files
+    /// would collide on case-insensitive filesystems
+    #[error(
+        "{package} differs from the installed {installed} only in case, which case-insensitive \
+         filesystems like the default one of macOS cannot tell apart"
+    )]
+    NameCollision { package: String, installed: String },
+
+    /// Error indicating an alias cannot be used as a command name
+    #[error("{alias} is not a valid command name")]
+    InvalidAlias { alias: String },
+
+    /// Error indicating an alias is taken by a package, or by a file not managed by gleam-pkg
+    #[error(
+        "{alias} is already used by {}",
+        .package.as_ref().map_or("another command".to_string(), |p| format!("package {}", p))
+    )]
+    AliasConflict {
+        alias: String,
+        package: Option<String>,
+    },
+
+    /// Error indicating `[licenses]` refuses the license of a package or one of its dependencies
+    #[error(
+        "{package} {version} is licensed under {}, which [licenses] in config.toml does not \
+         accept{}",
+        describe_licenses(.licenses),
+        .required_by.as_ref().map_or(String::new(), |p| format!(", it is a dependency of {}", p))
+    )]
+    LicenseRefused {
+        package: String,
+        version: String,
+        licenses: Vec<String>,
+        required_by: Option<String>,
+    },
+
+    /// Error indicating `gleam-pkg audit` found installed versions with known vulnerabilities
+    #[error(
+        "{count} installed package version(s) are affected by known vulnerabilities, update \
+         them to a fixed version"
+    )]
+    Vulnerable { count: usize },
+
+    /// Error indicating some packages failed to update, each reported as it failed
+    #[error("Failed to update {}", .packages.join(", "))]
+    UpdateFailed { packages: Vec<String> },
+
+    /// Error indicating `gleam-pkg prune-downloads` has no policy to prune by
+    #[error(
+        "No policy to prune the downloads by, pass --keep-latest, --max-size or --max-age or \
+         set them in [cache.downloads] of config.toml"
+    )]
+    NoPrunePolicy,
+
+    #[error("No command or help topic named: {topic}")]
+    UnknownTopic { topic: String },
+
+    /// Error indicating the shell profile to add the apps directory to is unknown
+    #[error(
+        "Unsupported shell: {shell}, pass one of bash, zsh, fish or nushell to `gleam-pkg env`, \
+         or add ~/.gleam_pkgs/apps to PATH manually"
+    )]
+    UnsupportedShell { shell: String },
+}
+
+impl GleamPkgError {
+    /// Formats the error followed by each of its causes, e.g.
+    /// `Request to https://hex.pm/api/packages/foo failed: error sending request: ...`
+    pub fn report(&self) -> String {
+        let mut report = self.localized();
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            report.push_str(": ");
+            report.push_str(cause.to_string().trim_end());
+            source = cause.source();
+        }
+        report
+    }
+
+    /// The error in the language of the user if it is in the catalog of [`crate::i18n`], and
+    /// in English otherwise
+    pub fn localized(&self) -> String {
+        match self {
+            GleamPkgError::UnknownPackage {
+                package,
+                url,
+                suggestion,
+            } => {
+                let mut message = i18n::fill(
+                    Message::UnknownPackage,
+                    &[("url", url), ("package", package)],
+                );
+                if let Some(suggestion) = suggestion {
+                    message += &i18n::fill(Message::DidYouMean, &[("suggestion", suggestion)]);
+                }
+                message
+            }
+            GleamPkgError::NoReleases { package } => {
+                i18n::fill(Message::NoReleases, &[("package", package)])
+            }
+            GleamPkgError::ReleaseNotFound { package, version } => i18n::fill(
+                Message::ReleaseNotFound,
+                &[("package", package), ("version", version)],
+            ),
+            GleamPkgError::PackageNotInstalled { package, version } => {
+                let package = match version {
+                    Some(version) => format!("{}@{}", package, version),
+                    None => package.clone(),
+                };
+                i18n::fill(Message::PackageNotInstalled, &[("package", &package)])
+            }
+            GleamPkgError::PackagePinned {
+                package,
+                pinned,
+                requested,
+            } => i18n::fill(
+                Message::PackagePinned,
+                &[
+                    ("package", package),
+                    ("pinned", pinned),
+                    ("requested", requested),
+                ],
+            ),
+            GleamPkgError::ConfirmationRequired { package } => {
+                i18n::fill(Message::ConfirmationRequired, &[("package", package)])
+            }
+            GleamPkgError::InstallDeclined { package } => {
+                i18n::fill(Message::InstallDeclined, &[("package", package)])
+            }
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// How [`GleamPkgError::CommandConflict`] can be avoided besides `--overwrite`: a command other
+/// than the package name can be left out in favor of an alias
+fn suggest_alias(command: &str, package: &str) -> String {
+    match command == package {
+        true => String::new(),
+        false => format!(" or --as NAME to run {} as NAME instead", package),
+    }
 }
 
-impl From<std::io::Error> for GleamPkgError {
-    fn from(error: std::io::Error) -> Self {
-        GleamPkgError::IOErr(error)
+/// The licenses of a package for [`GleamPkgError::LicenseRefused`], e.g. `MIT or Apache-2.0`
+fn describe_licenses(licenses: &[String]) -> String {
+    match licenses.is_empty() {
+        true => "no declared license".to_string(),
+        false => licenses.join(" or "),
     }
 }
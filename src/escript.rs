@@ -0,0 +1,83 @@
+//! Native escript assembly
+//!
+//! An escript is a short text header followed by a zip archive holding the `ebin` directories of
+//! every application it needs. After `gleam build`, the compiled `.beam` and `.app` files are
+//! collected from `build/dev/erlang/*/ebin` and written into such an archive, which is what
+//! gleescript would produce without having to add it as a dependency and run it.
+
+use crate::error::GleamPkgError;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Assembles an escript from the Erlang build output of a Gleam project
+///
+/// # Arguments
+///
+/// * `build_dir` - The `build` directory of a project that has been built with `gleam build`
+/// * `main_module` - The Erlang module whose `main/1` is the escript entry point
+/// * `comment` - A single line recorded in the comment section of the escript header
+/// * `out` - Where to write the escript
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the build output cannot be read or the escript cannot be
+/// written
+pub fn build_escript(
+    build_dir: &Path,
+    main_module: &str,
+    comment: &str,
+    out: &Path,
+) -> Result<(), GleamPkgError> {
+    let archive = archive_ebins(&build_dir.join("dev").join("erlang")).map_err(|source| {
+        GleamPkgError::Io {
+            action: "archive compiled modules",
+            path: build_dir.to_path_buf(),
+            source,
+        }
+    })?;
+
+    let mut escript = format!(
+        "#!/usr/bin/env escript\n%% {}\n%%! -escript main {}\n",
+        comment.replace('\n', " "),
+        main_module
+    )
+    .into_bytes();
+    escript.extend_from_slice(&archive);
+
+    fs::write(out, escript).map_err(|source| GleamPkgError::Io {
+        action: "write escript",
+        path: out.to_path_buf(),
+        source,
+    })
+}
+
+/// Zips `<app>/ebin/*` for every application directory under `erlang_dir`
+fn archive_ebins(erlang_dir: &Path) -> Result<Vec<u8>, std::io::Error> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut apps = fs::read_dir(erlang_dir)?.collect::<Result<Vec<_>, _>>()?;
+    apps.sort_by_key(|entry| entry.file_name());
+    for app in apps {
+        let ebin = app.path().join("ebin");
+        if !ebin.is_dir() {
+            continue;
+        }
+        let app_name = app.file_name().to_string_lossy().to_string();
+        let mut files = fs::read_dir(&ebin)?.collect::<Result<Vec<_>, _>>()?;
+        files.sort_by_key(|entry| entry.file_name());
+        for file in files {
+            if !file.file_type()?.is_file() {
+                continue;
+            }
+            let name = format!("{}/ebin/{}", app_name, file.file_name().to_string_lossy());
+            zip.start_file(name, options)?;
+            zip.write_all(&fs::read(file.path())?)?;
+        }
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
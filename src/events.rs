@@ -0,0 +1,134 @@
+//! Progress events for frontends
+//!
+//! Installs report what they are doing as typed [`Event`]s next to the human-readable output,
+//! so a frontend can render progress without parsing stdout. Frontends in this process
+//! [`subscribe`] a [`Listener`], e.g. a [`Sender`] for a channel; other processes pass
+//! `--events <FILE>` and read one JSON object per line:
+//!
+//! ```text
+//! {"event":"metadata_fetched","package":"wisp"}
+//! {"event":"download_started","package":"wisp","version":"1.2.0","url":"https://..."}
+//! {"event":"download_progress","package":"wisp","version":"1.2.0","bytes":65536,"total":81920}
+//! {"event":"build_started","package":"wisp","version":"1.2.0","backend":"erlang"}
+//! {"event":"installed","package":"wisp","version":"1.2.0"}
+//! ```
+
+use crate::error::GleamPkgError;
+use serde::Serialize;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::{Mutex, PoisonError};
+
+/// Something an install did
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The metadata of a package arrived, from the registry or the cache
+    MetadataFetched { package: String },
+    /// A tarball download from `url` started
+    DownloadStarted {
+        package: String,
+        version: String,
+        url: String,
+    },
+    /// `bytes` of a tarball are on disk, out of `total` if the repository announced it
+    DownloadProgress {
+        package: String,
+        version: String,
+        bytes: u64,
+        total: Option<u64>,
+    },
+    /// The build with `backend` started
+    BuildStarted {
+        package: String,
+        version: String,
+        backend: String,
+    },
+    /// A version was installed and is the default now
+    Installed { package: String, version: String },
+}
+
+/// Receives every emitted event
+pub trait Listener: Send {
+    fn event(&mut self, event: &Event);
+}
+
+impl Listener for Sender<Event> {
+    fn event(&mut self, event: &Event) {
+        // a frontend that stopped listening does not stop the install
+        let _ = self.send(event.clone());
+    }
+}
+
+/// Writes each event as a line of JSON
+struct JsonLines(File);
+
+impl Listener for JsonLines {
+    fn event(&mut self, event: &Event) {
+        if let Ok(json) = serde_json::to_string(event) {
+            let _ = writeln!(self.0, "{}", json);
+        }
+    }
+}
+
+static LISTENERS: Mutex<Vec<Box<dyn Listener>>> = Mutex::new(Vec::new());
+
+/// Sends every following event to `listener`
+pub fn subscribe(listener: impl Listener + 'static) {
+    let mut listeners = LISTENERS.lock().unwrap_or_else(PoisonError::into_inner);
+    listeners.push(Box::new(listener));
+}
+
+/// Writes every following event to `path` as JSON lines, see `--events`
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if `path` cannot be created
+pub fn subscribe_file(path: &Path) -> Result<(), GleamPkgError> {
+    let file = File::create(path).map_err(|source| GleamPkgError::Io {
+        action: "create events file",
+        path: path.to_path_buf(),
+        source,
+    })?;
+    subscribe(JsonLines(file));
+    Ok(())
+}
+
+/// Passes `event` to every listener
+pub fn emit(event: Event) {
+    let mut listeners = LISTENERS.lock().unwrap_or_else(PoisonError::into_inner);
+    for listener in listeners.iter_mut() {
+        listener.event(&event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn listeners_receive_events_in_order() {
+        let (tx, rx) = mpsc::channel();
+        subscribe(tx);
+        let installed = |version: &str| Event::Installed {
+            package: "events_test".to_string(),
+            version: version.to_string(),
+        };
+        emit(installed("1.0.0"));
+        emit(installed("1.1.0"));
+
+        // other tests may emit concurrently
+        let received: Vec<_> = rx
+            .try_iter()
+            .filter(|e| matches!(e, Event::Installed { package, .. } if package == "events_test"))
+            .collect();
+        assert_eq!(received, [installed("1.0.0"), installed("1.1.0")]);
+        assert_eq!(
+            serde_json::to_string(&installed("1.0.0")).unwrap(),
+            r#"{"event":"installed","package":"events_test","version":"1.0.0"}"#
+        );
+    }
+}
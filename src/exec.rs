@@ -0,0 +1,187 @@
+//! Running installed packages in a controlled environment
+//!
+//! `gleam-pkg exec <package>[@<version>] -- <args>` runs the wrapper of an installed version the
+//! way a shell would, after preparing its environment:
+//!
+//! - For escripts, the `erl` of the OTP release the escript was compiled on, or else the closest
+//!   newer compatible one, goes first on `PATH` and is passed to the wrapper as `GLEAM_PKG_ERL`.
+//!   The tool and everything it starts then use the same runtime, whichever Erlang installation
+//!   the shell would have picked.
+//! - The variables of the package in `config.toml` are set:
+//!
+//! ```toml
+//! [packages.wonderful_cli.env]
+//! WONDERFUL_CLI_THEME = "dark"
+//! ```
+//!
+//! - `GLEAM_PKG_PACKAGE` and `GLEAM_PKG_VERSION` name the version that runs.
+//!
+//! Runtimes are looked for where the wrappers look for them: `GLEAM_PKG_ERL`, `ERLANG_HOME`,
+//! `PATH`, the installations of kerl, asdf and mise, and those of `gleam-pkg toolchain
+//! install-otp`, see [`crate::toolchains`].
+
+use crate::config::Config;
+use crate::db::InstalledVersion;
+use crate::erlang;
+use crate::error::GleamPkgError;
+use crate::limits::BuildLimits;
+use crate::paths::Paths;
+use crate::toolchains;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// How many OTP releases after the one it was compiled on can load an escript
+const COMPATIBLE_RELEASES: u32 = 2;
+
+/// The command running an installed version in its environment
+///
+/// # Arguments
+///
+/// * `config` - The configuration with the variables of the package
+/// * `paths` - The installation the package belongs to
+/// * `package` - The name of the package
+/// * `version` - The installed version to run
+/// * `installed` - How the version was installed
+/// * `limits` - The limits of probing the Erlang runtimes
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::ToolchainMissing` if the version is an escript and no Erlang runtime
+/// can load it
+pub fn command(
+    config: &Config,
+    paths: &Paths,
+    package: &str,
+    version: &str,
+    installed: &InstalledVersion,
+    limits: &BuildLimits,
+) -> Result<Command, GleamPkgError> {
+    let mut cmd = Command::new(paths.apps().join(format!("{}-{}", package, version)));
+    if let Some(otp_release) = installed.otp_release {
+        let erl = find_erl(otp_release, paths, limits)?;
+        cmd.env("PATH", prepend_path(erl.parent().unwrap_or(Path::new("."))))
+            .env("GLEAM_PKG_ERL", &erl);
+    }
+    cmd.envs(config.package_env(package))
+        .env("GLEAM_PKG_PACKAGE", package)
+        .env("GLEAM_PKG_VERSION", version);
+    Ok(cmd)
+}
+
+/// Finds the `erl` escripts compiled on `otp_release` are best run with
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::ToolchainMissing` listing the runtimes found if none is compatible
+pub fn find_erl(
+    otp_release: u32,
+    paths: &Paths,
+    limits: &BuildLimits,
+) -> Result<PathBuf, GleamPkgError> {
+    let mut found = Vec::new();
+    let managed = toolchains::installed_otps(paths)
+        .into_iter()
+        .map(|(_, erl)| erl);
+    for candidate in erl_candidates().into_iter().chain(managed) {
+        if found.iter().any(|(erl, _)| *erl == candidate) {
+            continue;
+        }
+        if let Some(release) = erlang::release_of(&candidate, limits) {
+            found.push((candidate, release));
+        }
+    }
+    match pick_erl(otp_release, &found) {
+        Some(erl) => Ok(erl.clone()),
+        None => Err(GleamPkgError::ToolchainMissing {
+            tool: "erl".to_string(),
+            required: format!(
+                "OTP {} to {}",
+                otp_release,
+                otp_release + COMPATIBLE_RELEASES
+            ),
+            found: (!found.is_empty()).then(|| {
+                found
+                    .iter()
+                    .map(|(erl, release)| format!("{} (OTP {})", erl.display(), release))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }),
+        }),
+    }
+}
+
+/// Picks the runtime of `otp_release` among `found`, or else the oldest newer one that can still
+/// load it
+fn pick_erl(otp_release: u32, found: &[(PathBuf, u32)]) -> Option<&PathBuf> {
+    let compatible = otp_release..=otp_release + COMPATIBLE_RELEASES;
+    found
+        .iter()
+        .filter(|(_, release)| compatible.contains(release))
+        .min_by_key(|(_, release)| *release)
+        .map(|(erl, _)| erl)
+}
+
+/// Every `erl` that might be installed outside of gleam-pkg, explicit overrides first, in the
+/// order the wrappers try them
+fn erl_candidates() -> Vec<PathBuf> {
+    let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+    let home = var("HOME").map(PathBuf::from).unwrap_or_default();
+    let mut candidates: Vec<PathBuf> = var("GLEAM_PKG_ERL")
+        .map(PathBuf::from)
+        .into_iter()
+        .collect();
+    candidates.extend(var("ERLANG_HOME").map(|dir| Path::new(&dir).join("bin").join("erl")));
+    candidates.extend(crate::toolchain::find_executable("erl"));
+
+    let kerl = var("KERL_BASE_DIR").map_or_else(|| home.join(".kerl"), PathBuf::from);
+    if let Ok(installations) = fs::read_to_string(kerl.join("otp_installations")) {
+        candidates.extend(
+            installations
+                .lines()
+                .filter_map(|line| line.split_whitespace().nth(1))
+                .map(|dir| Path::new(dir).join("bin").join("erl")),
+        );
+    }
+    let asdf = var("ASDF_DATA_DIR").map_or_else(|| home.join(".asdf"), PathBuf::from);
+    let mise = var("MISE_DATA_DIR").map_or_else(|| home.join(".local/share/mise"), PathBuf::from);
+    for data_dir in [asdf, mise] {
+        let Ok(entries) = fs::read_dir(data_dir.join("installs").join("erlang")) else {
+            continue;
+        };
+        let mut installs: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+        installs.sort();
+        candidates.extend(installs.into_iter().map(|dir| dir.join("bin").join("erl")));
+    }
+    candidates
+}
+
+/// `PATH` with `dir` in front
+fn prepend_path(dir: &Path) -> OsString {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::join_paths(std::iter::once(dir.to_path_buf()).chain(std::env::split_paths(&path)))
+        .unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_same_or_the_closest_newer_release() {
+        let found = |releases: &[u32]| {
+            releases
+                .iter()
+                .map(|release| (PathBuf::from(format!("otp{}/bin/erl", release)), *release))
+                .collect::<Vec<_>>()
+        };
+        let pick = |releases: &[u32], compiled| {
+            pick_erl(compiled, &found(releases)).map(|erl| erl.display().to_string())
+        };
+        assert_eq!(pick(&[28, 27, 26], 27).as_deref(), Some("otp27/bin/erl"));
+        assert_eq!(pick(&[28, 25], 26).as_deref(), Some("otp28/bin/erl"));
+        assert_eq!(pick(&[29, 25], 26), None);
+        assert_eq!(pick(&[], 26), None);
+    }
+}
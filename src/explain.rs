@@ -0,0 +1,140 @@
+//! Explanations of what `install` is about to do
+//!
+//! With `install --explain`, the release is resolved as usual, then every decision taken is
+//! printed with the reason for it before anything is downloaded:
+//!
+//! ```text
+//! Installing wisp 1.3.0
+//!   version   1.3.0
+//!             the newest stable release that is not retired
+//!   metadata  https://hex.pm/api/packages/wisp
+//!             where the releases of wisp are listed
+//!   tarball   https://repo.hex.pm/tarballs/wisp-1.3.0.tar
+//!             the repository of hexpm
+//!   backend   detected after the download
+//!             from the build tools the tarball declares, and the target in its gleam.toml
+//!   wrapper   exec
+//!             the default: the wrapper runs the escript in the store
+//! Proceed with the installation? [Y/n]
+//! ```
+//!
+//! The question defaults to yes, so `--yes` or a stdin that is not a terminal only print the
+//! explanation. With `--dry-run` the plan is printed after it instead of asking.
+
+use crate::backend::WrapperMode;
+use crate::i18n::{self, Message};
+use crate::output::{self, Style};
+
+/// A decision and why it was taken
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    /// What the decision is about, e.g. `version`
+    topic: &'static str,
+    what: String,
+    why: String,
+}
+
+/// The decisions behind installing a release
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    heading: String,
+    steps: Vec<Step>,
+}
+
+impl Explanation {
+    /// An explanation of installing `package` at `version`, with no decisions yet
+    pub fn new(package: &str, version: &str) -> Self {
+        Explanation {
+            heading: i18n::fill(
+                Message::ExplainHeading,
+                &[("package", &package), ("version", &version)],
+            ),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Adds the decision `what` about `topic`, taken because of `why`
+    pub fn step(&mut self, topic: &'static str, what: impl Into<String>, why: impl Into<String>) {
+        self.steps.push(Step {
+            topic,
+            what: what.into(),
+            why: why.into(),
+        });
+    }
+
+    /// Adds how the wrapper of an escript runs it
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - How the wrapper runs the escript
+    /// * `chosen` - Where `mode` was chosen: `--wrapper`, config.toml, or `None` for the default
+    pub fn wrapper(&mut self, mode: WrapperMode, chosen: Option<&str>) {
+        let (name, how) = match mode {
+            WrapperMode::Embedded => (
+                "embedded",
+                "the wrapper holds the escript and runs a copy of it",
+            ),
+            WrapperMode::Exec => ("exec", "the wrapper runs the escript in the store"),
+            WrapperMode::Symlink => (
+                "symlink",
+                "the command links to the escript, run by the escript on PATH",
+            ),
+        };
+        let why = format!("{}: {}", chosen.unwrap_or("the default"), how);
+        self.step("wrapper", name, why);
+    }
+
+    /// The lines of the explanation below its heading, unpainted
+    fn lines(&self) -> Vec<(String, String)> {
+        let width = self.steps.iter().map(|s| s.topic.len()).max().unwrap_or(0);
+        self.steps
+            .iter()
+            .map(|step| {
+                (
+                    format!("  {:<width$}  {}", step.topic, step.what),
+                    format!("  {:<width$}  {}", "", step.why),
+                )
+            })
+            .collect()
+    }
+
+    /// Prints the explanation, the reasons dimmed
+    pub fn print(&self) {
+        println!("{}", output::paint(&self.heading, Style::Bold));
+        for (what, why) in self.lines() {
+            println!("{}", what);
+            println!("{}", output::paint(why, Style::Dim));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_the_reasons_below_the_decisions() {
+        let mut explanation = Explanation::new("wisp", "1.3.0");
+        explanation.step("version", "1.3.0", "requested as wisp@1.3.0");
+        explanation.wrapper(WrapperMode::Exec, Some("--wrapper"));
+        assert_eq!(
+            explanation.lines(),
+            [
+                (
+                    "  version  1.3.0".to_string(),
+                    "           requested as wisp@1.3.0".to_string()
+                ),
+                (
+                    "  wrapper  exec".to_string(),
+                    "           --wrapper: the wrapper runs the escript in the store".to_string()
+                ),
+            ]
+        );
+        let mut explanation = Explanation::new("wisp", "1.3.0");
+        explanation.wrapper(WrapperMode::Symlink, None);
+        assert_eq!(
+            explanation.lines()[0].1,
+            "           the default: the command links to the escript, run by the escript on PATH"
+        );
+    }
+}
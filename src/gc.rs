@@ -0,0 +1,254 @@
+//! Removing what no installed package needs anymore
+//!
+//! The database is the manifest: downloads, sources and artifacts are kept for the versions it
+//! lists and store blobs while a version records them. Everything else below the root is
+//! garbage, except build logs, which are kept until they reach a given age:
+//!
+//! ```text
+//! download/<pkg>-<ver>.tar(.part)   tarballs and unfinished downloads of other versions
+//! download/<pkg>-<ver>/             extracted sources of other versions
+//! lib/<pkg>-<ver>/                  build artifacts of other versions
+//! store/<sha256>                    blobs no version refers to
+//! logs/*.log                        logs older than the given age
+//! ```
+//!
+//! Escripts of versions no longer installed are kept for reinstalling until then, see
+//! [`crate::buildcache`].
+
+use crate::db::Database;
+use crate::error::GleamPkgError;
+use crate::paths::Paths;
+use crate::plan::disk_usage;
+use crate::store::Store;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Which kind of leftover something is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Tarball,
+    Sources,
+    Artifacts,
+    Blob,
+    Log,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Tarball => "tarballs",
+            Kind::Sources => "extracted sources",
+            Kind::Artifacts => "build artifacts",
+            Kind::Blob => "store blobs",
+            Kind::Log => "build logs",
+        }
+    }
+}
+
+/// A file or directory nothing needs anymore
+#[derive(Debug)]
+pub struct Garbage {
+    pub kind: Kind,
+    pub path: PathBuf,
+    /// Bytes freed by removing it
+    pub size: u64,
+}
+
+/// Finds everything below the root that no installed version needs
+///
+/// # Arguments
+///
+/// * `paths` - The installation to look through
+/// * `max_log_age` - How long build logs are kept
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::DatabaseError` if the database cannot be read, or
+/// `GleamPkgError::Io` if a directory cannot be listed
+pub fn find(paths: &Paths, max_log_age: Duration) -> Result<Vec<Garbage>, GleamPkgError> {
+    let db = Database::load(&paths.db_file())?;
+    let installed: BTreeSet<String> = db
+        .packages
+        .iter()
+        .flat_map(|(name, installed)| {
+            installed
+                .versions
+                .keys()
+                .map(move |version| format!("{}-{}", name, version))
+        })
+        .collect();
+    let referenced: BTreeSet<&str> = db
+        .packages
+        .values()
+        .flat_map(|installed| installed.versions.values())
+        .filter_map(|version| version.blob.as_deref())
+        .collect();
+
+    let mut garbage = Vec::new();
+    let mut add = |kind, path: PathBuf| {
+        let size = disk_usage(&path);
+        garbage.push(Garbage { kind, path, size });
+    };
+    for path in list(&paths.download())? {
+        let name = file_name(&path);
+        let (kind, release) = match name
+            .strip_suffix(".tar")
+            .or_else(|| name.strip_suffix(".tar.part"))
+        {
+            Some(release) => (Kind::Tarball, release),
+            None if path.is_dir() => (Kind::Sources, name.as_str()),
+            None => continue,
+        };
+        if !installed.contains(release) {
+            add(kind, path);
+        }
+    }
+    for path in list(&paths.lib())? {
+        if path.is_dir() && !installed.contains(&file_name(&path)) {
+            add(Kind::Artifacts, path);
+        }
+    }
+    let store = Store::new(&paths.store());
+    for (hash, _) in store.blobs()? {
+        if !referenced.contains(hash.as_str()) {
+            add(Kind::Blob, store.path(&hash));
+        }
+    }
+    let now = SystemTime::now();
+    for path in list(&paths.logs())? {
+        let age = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok());
+        if path.extension().is_some_and(|e| e == "log") && age.is_some_and(|a| a > max_log_age) {
+            add(Kind::Log, path);
+        }
+    }
+    Ok(garbage)
+}
+
+/// Removes what [`find`] found
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if something cannot be removed; what was removed before stays
+/// removed
+pub fn remove(garbage: &[Garbage]) -> Result<(), GleamPkgError> {
+    for item in garbage {
+        let result = match item.path.is_dir() {
+            true => fs::remove_dir_all(&item.path),
+            false => fs::remove_file(&item.path),
+        };
+        result.map_err(|source| GleamPkgError::Io {
+            action: "remove garbage",
+            path: item.path.clone(),
+            source,
+        })?;
+    }
+    Ok(())
+}
+
+/// The entries of `dir`, none if it does not exist
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if `dir` exists but cannot be listed
+pub fn list(dir: &Path) -> Result<Vec<PathBuf>, GleamPkgError> {
+    match fs::read_dir(dir) {
+        Ok(entries) => Ok(entries.flatten().map(|entry| entry.path()).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(source) => Err(GleamPkgError::Io {
+            action: "list directory",
+            path: dir.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+/// The last component of `path` as a string, empty if it has none
+pub fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Target;
+    use crate::db::{InstalledPackage, InstalledVersion};
+
+    #[test]
+    fn finds_what_no_installed_version_needs() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = Paths::new(root.path());
+        paths.create_dirs().unwrap();
+        let store = Store::new(&paths.store());
+        let escript = root.path().join("escript");
+        fs::write(&escript, "used escript").unwrap();
+        let used = store.put(&escript).unwrap();
+        fs::write(&escript, "old escript").unwrap();
+        let unused = store.put(&escript).unwrap();
+        for release in ["hello-1.0.0", "hello-0.9.0"] {
+            fs::write(paths.download().join(format!("{}.tar", release)), "tar").unwrap();
+            fs::create_dir_all(paths.download().join(release).join("contents")).unwrap();
+            fs::create_dir_all(paths.lib().join(release)).unwrap();
+        }
+        fs::write(paths.download().join("other-2.0.0.tar.part"), "ta").unwrap();
+        fs::write(paths.logs().join("hello-1.0.0-1.log"), "log").unwrap();
+
+        let mut db = Database::default();
+        db.packages.insert(
+            "hello".to_string(),
+            InstalledPackage {
+                versions: [(
+                    "1.0.0".to_string(),
+                    InstalledVersion {
+                        target: Target::Erlang,
+                        otp_release: Some(27),
+                        blob: Some(used.clone()),
+                        binary: None,
+                        erts: None,
+                        wrapper: None,
+                        provenance: None,
+                        dependencies: None,
+                    },
+                )]
+                .into(),
+                default_version: "1.0.0".to_string(),
+                pinned: false,
+                aliases: Default::default(),
+                unlinked: false,
+                source: Default::default(),
+            },
+        );
+        db.save(&paths.db_file()).unwrap();
+
+        let mut found: Vec<_> = find(&paths, Duration::from_secs(3600))
+            .unwrap()
+            .into_iter()
+            .map(|g| (g.kind, file_name(&g.path)))
+            .collect();
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                (Kind::Tarball, "hello-0.9.0.tar".to_string()),
+                (Kind::Tarball, "other-2.0.0.tar.part".to_string()),
+                (Kind::Sources, "hello-0.9.0".to_string()),
+                (Kind::Artifacts, "hello-0.9.0".to_string()),
+                (Kind::Blob, unused.clone()),
+            ]
+        );
+
+        let garbage = find(&paths, Duration::ZERO).unwrap();
+        assert!(garbage.iter().any(|g| g.kind == Kind::Log));
+        remove(&garbage).unwrap();
+        assert!(store.path(&used).is_file());
+        assert!(!store.path(&unused).exists());
+        assert!(paths.download().join("hello-1.0.0").is_dir());
+        assert!(!paths.lib().join("hello-0.9.0").exists());
+    }
+}
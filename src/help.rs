@@ -0,0 +1,125 @@
+//! Long-form help and man pages
+//!
+//! `gleam-pkg help <topic>` prints either the help of a subcommand or one of the guides
+//! embedded in the binary, and `gleam-pkg man` renders man pages from the clap definitions.
+
+use crate::error::GleamPkgError;
+use std::fs;
+use std::path::Path;
+
+/// A guide shown by `gleam-pkg help <name>`
+pub struct Topic {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub text: &'static str,
+}
+
+/// Every guide embedded in the binary
+pub const TOPICS: [Topic; 5] = [
+    Topic {
+        name: "registries",
+        summary: "Where packages come from and how metadata is cached",
+        text: include_str!("topics/registries.txt"),
+    },
+    Topic {
+        name: "paths",
+        summary: "What lives where under ~/.gleam_pkgs, and setting up PATH",
+        text: include_str!("topics/paths.txt"),
+    },
+    Topic {
+        name: "builds",
+        summary: "Troubleshooting builds: logs, toolchains, limits and runtimes",
+        text: include_str!("topics/builds.txt"),
+    },
+    Topic {
+        name: "scripting",
+        summary: "Stable --quiet and --porcelain output for scripts",
+        text: include_str!("topics/scripting.txt"),
+    },
+    Topic {
+        name: "environment",
+        summary: "GLEAM_PKG_* variables overriding the configuration, and --offline",
+        text: include_str!("topics/environment.txt"),
+    },
+];
+
+/// Prints the help of a subcommand or a guide, or the overall help with a list of guides
+///
+/// # Arguments
+///
+/// * `cmd` - The clap definition of `gleam-pkg`
+/// * `topic` - A subcommand or guide name
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::UnknownTopic` if `topic` is neither a subcommand nor a guide
+pub fn print_help(mut cmd: clap::Command, topic: Option<&str>) -> Result<(), GleamPkgError> {
+    let Some(topic) = topic else {
+        cmd.print_long_help()?;
+        println!("\nHelp topics:");
+        for topic in &TOPICS {
+            println!("  {:<12} {}", topic.name, topic.summary);
+        }
+        println!("\nRun `gleam-pkg help <topic>` to read one.");
+        return Ok(());
+    };
+
+    // building propagates the full `gleam-pkg <command>` name into subcommand usage lines
+    cmd.build();
+    if let Some(subcommand) = cmd.find_subcommand_mut(topic) {
+        subcommand.print_long_help()?;
+        return Ok(());
+    }
+    match TOPICS.iter().find(|t| t.name == topic) {
+        Some(topic) => {
+            print!("{}", topic.text);
+            Ok(())
+        }
+        None => Err(GleamPkgError::UnknownTopic {
+            topic: topic.to_string(),
+        }),
+    }
+}
+
+/// Renders man pages for `gleam-pkg` and each of its subcommands
+///
+/// # Arguments
+///
+/// * `cmd` - The clap definition of `gleam-pkg`
+/// * `out_dir` - Where to write `gleam-pkg.1` and one `gleam-pkg-<subcommand>.1` per
+///   subcommand; without it only the main page is written to stdout
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::IOErr` if a page cannot be written
+pub fn write_man_pages(cmd: clap::Command, out_dir: Option<&Path>) -> Result<(), GleamPkgError> {
+    let Some(out_dir) = out_dir else {
+        man(cmd).render(&mut std::io::stdout())?;
+        return Ok(());
+    };
+
+    fs::create_dir_all(out_dir)?;
+    let mut pages = vec![cmd.clone()];
+    pages.extend(
+        cmd.get_subcommands()
+            .filter(|sub| !sub.is_hide_set())
+            .map(|sub| {
+                sub.clone()
+                    .display_name(format!("{}-{}", cmd.get_name(), sub.get_name()))
+                    .bin_name(format!("{} {}", cmd.get_name(), sub.get_name()))
+            }),
+    );
+    for page in pages {
+        let name = page.get_display_name().unwrap_or(page.get_name());
+        let path = out_dir.join(format!("{}.1", name));
+        let mut buffer = Vec::new();
+        man(page).render(&mut buffer)?;
+        fs::write(&path, buffer)?;
+        println!("Man page written to: {}", path.display());
+    }
+    Ok(())
+}
+
+fn man(cmd: clap::Command) -> clap_mangen::Man {
+    clap_mangen::Man::new(cmd).source(format!("gleam-pkg {}", env!("CARGO_PKG_VERSION")))
+}
@@ -0,0 +1,194 @@
+//! The history of operations changing the installation
+//!
+//! Every install, update, uninstall and garbage collection is recorded with the versions it
+//! went between, how long it took and whether it failed, so `gleam-pkg history [package]` can
+//! tell when something changed:
+//!
+//! ```text
+//! WHEN                     OPERATION  PACKAGE  FROM   TO     TIME  RESULT
+//! 2024-12-25 17:30:00 UTC  install    wisp            1.2.0  8.1s  ok
+//! 2025-01-10 09:12:44 UTC  update     wisp     1.2.0  1.3.0  7.9s  failed: ...
+//! ```
+//!
+//! Like [`crate::stats`], operations are collected while a command runs and appended to the
+//! `history` section of the package database when it finishes; the last [`MAX_OPERATIONS`] are
+//! kept. Dry runs are not recorded.
+
+use crate::db::{self, Database};
+use crate::error::GleamPkgError;
+use crate::output::{self, Style, Table};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many operations are kept
+pub const MAX_OPERATIONS: usize = 1000;
+
+/// What an operation did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Install,
+    Update,
+    Uninstall,
+    Gc,
+}
+
+impl Kind {
+    /// The name of the operation, as the history shows it
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Install => "install",
+            Kind::Update => "update",
+            Kind::Uninstall => "uninstall",
+            Kind::Gc => "gc",
+        }
+    }
+}
+
+/// One operation changing the installation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub kind: Kind,
+    /// The package changed, none for operations on the whole installation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    /// The default version of the package before, none if it was not installed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// The default version of the package after, none if it is not installed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    /// When the operation started, in seconds since the Unix epoch
+    pub started_at: u64,
+    pub duration_ms: u64,
+    /// Why the operation failed, none if it succeeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+static SESSION: Mutex<Vec<Operation>> = Mutex::new(Vec::new());
+
+/// Records an operation that started at `started` and ends now
+///
+/// # Arguments
+///
+/// * `kind` - What the operation did
+/// * `package` - The package it changed, if it changed a single one
+/// * `from` - The default version of the package before
+/// * `to` - The default version of the package after
+/// * `started` - When it started
+/// * `error` - Why it failed, if it did
+pub fn record(
+    kind: Kind,
+    package: Option<&str>,
+    from: Option<&str>,
+    to: Option<&str>,
+    started: SystemTime,
+    error: Option<&GleamPkgError>,
+) {
+    let operation = Operation {
+        kind,
+        package: package.map(String::from),
+        from: from.map(String::from),
+        to: to.map(String::from),
+        started_at: started
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        duration_ms: started.elapsed().unwrap_or_default().as_millis() as u64,
+        error: error.map(GleamPkgError::report),
+    };
+    SESSION
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(operation);
+}
+
+/// Appends the operations recorded by this process to the database at `db_path`
+///
+/// # Errors
+///
+/// Returns the errors of [`Database::load`] and [`Database::save`]
+pub fn flush(db_path: &Path) -> Result<(), GleamPkgError> {
+    let session = std::mem::take(&mut *SESSION.lock().unwrap_or_else(PoisonError::into_inner));
+    if session.is_empty() {
+        return Ok(());
+    }
+    let _lock = db::lock(db_path);
+    let mut db = Database::load(db_path)?;
+    append(&mut db.history, session);
+    db.save(db_path)
+}
+
+fn append(history: &mut Vec<Operation>, operations: Vec<Operation>) {
+    history.extend(operations);
+    let dropped = history.len().saturating_sub(MAX_OPERATIONS);
+    history.drain(..dropped);
+}
+
+/// Prints the last `limit` operations, of `package` only if given, oldest first
+pub fn print(history: &[Operation], package: Option<&str>, limit: usize) {
+    let operations: Vec<_> = history
+        .iter()
+        .filter(|op| package.is_none() || op.package.as_deref() == package)
+        .collect();
+    if operations.is_empty() {
+        output::info("No operations recorded yet");
+        return;
+    }
+    let mut table = Table::new(&[
+        "WHEN",
+        "OPERATION",
+        "PACKAGE",
+        "FROM",
+        "TO",
+        "TIME",
+        "RESULT",
+    ])
+    .align_right(5);
+    for op in &operations[operations.len().saturating_sub(limit)..] {
+        let when = match output::porcelain() {
+            true => output::format_rfc3339(op.started_at),
+            false => output::format_timestamp(op.started_at),
+        };
+        let result = match &op.error {
+            None => ("ok".to_string(), Some(Style::Green)),
+            Some(error) => (format!("failed: {}", error), Some(Style::Red)),
+        };
+        table.styled_row(vec![
+            (when, None),
+            (op.kind.name().to_string(), None),
+            (op.package.clone().unwrap_or_default(), None),
+            (op.from.clone().unwrap_or_default(), None),
+            (op.to.clone().unwrap_or_default(), None),
+            (crate::stats::seconds(op.duration_ms), None),
+            result,
+        ]);
+    }
+    table.print();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_last_operations() {
+        let operation = |started_at| Operation {
+            kind: Kind::Install,
+            package: Some("hello".to_string()),
+            from: None,
+            to: Some("1.0.0".to_string()),
+            started_at,
+            duration_ms: 0,
+            error: None,
+        };
+        let mut history: Vec<_> = (0..MAX_OPERATIONS as u64).map(operation).collect();
+        append(&mut history, vec![operation(1000), operation(1001)]);
+        assert_eq!(history.len(), MAX_OPERATIONS);
+        assert_eq!(history[0].started_at, 2);
+        assert_eq!(history.last().unwrap().started_at, 1001);
+    }
+}
@@ -0,0 +1,201 @@
+//! Commands run around installs and uninstalls
+//!
+//! Hooks are shell commands from `config.toml`, either for every package or for a single one:
+//!
+//! ```toml
+//! [hooks]
+//! post_install = "notify-send \"installed $GLEAM_PKG_PACKAGE\""
+//!
+//! [hooks.wonderful_cli]
+//! post_install = "wonderful_cli completions install"
+//! ```
+//!
+//! A hook runs with `sh -c` and the environment of the package: `GLEAM_PKG_PACKAGE`,
+//! `GLEAM_PKG_VERSION`, `GLEAM_PKG_EVENT`, `GLEAM_PKG_ROOT`, `GLEAM_PKG_WRAPPER` (the wrapper
+//! script of the version) and `GLEAM_PKG_LIB` (its build artifacts). Global hooks run before the
+//! hooks of the package. A failing `pre_*` hook stops the install or uninstall; a failing
+//! `post_*` hook is reported, but cannot undo what already happened.
+
+use crate::config::{Hooks, HooksConfig};
+use crate::error::GleamPkgError;
+use crate::output;
+use crate::paths::{Paths, ROOT_VAR};
+use crate::runner::{Runner, describe_status};
+use std::fmt;
+use std::process::Command;
+
+/// When a hook runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    PreInstall,
+    PostInstall,
+    PreUninstall,
+    PostUninstall,
+}
+
+impl Event {
+    /// The key of the hook in `config.toml`
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::PreInstall => "pre_install",
+            Event::PostInstall => "post_install",
+            Event::PreUninstall => "pre_uninstall",
+            Event::PostUninstall => "post_uninstall",
+        }
+    }
+
+    fn command(self, hooks: &Hooks) -> Option<&str> {
+        match self {
+            Event::PreInstall => hooks.pre_install.as_deref(),
+            Event::PostInstall => hooks.post_install.as_deref(),
+            Event::PreUninstall => hooks.pre_uninstall.as_deref(),
+            Event::PostUninstall => hooks.post_uninstall.as_deref(),
+        }
+    }
+
+    /// Whether the hook runs before anything changed, so its failure can stop the command
+    fn is_pre(self) -> bool {
+        matches!(self, Event::PreInstall | Event::PreUninstall)
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The commands configured for `event` of `package`, global ones first
+pub fn commands<'a>(config: &'a HooksConfig, event: Event, package: &str) -> Vec<&'a str> {
+    let global = event.command(&config.global);
+    let own = config.packages.get(package).and_then(|h| event.command(h));
+    global.into_iter().chain(own).collect()
+}
+
+/// Runs the hooks configured for `event` of a package version
+///
+/// # Arguments
+///
+/// * `config` - The configured hooks
+/// * `event` - What is about to happen or just happened
+/// * `paths` - The installation the package belongs to
+/// * `package` - The name of the package
+/// * `version` - The version being installed or uninstalled
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::SpawnFailed` if `sh` cannot be started, or
+/// `GleamPkgError::HookFailed` if a `pre_*` hook fails. Failing `post_*` hooks only print a
+/// warning.
+pub fn run(
+    config: &HooksConfig,
+    event: Event,
+    paths: &Paths,
+    package: &str,
+    version: &str,
+) -> Result<(), GleamPkgError> {
+    for command in commands(config, event, package) {
+        output::info(format!("Running {} hook: {}", event, command));
+        let status = Runner::new(Command::new("sh"))
+            .arg("-c")
+            .arg(command)
+            .env("GLEAM_PKG_PACKAGE", package)
+            .env("GLEAM_PKG_VERSION", version)
+            .env("GLEAM_PKG_EVENT", event.name())
+            .env(ROOT_VAR, paths.root())
+            .env(
+                "GLEAM_PKG_WRAPPER",
+                paths.apps().join(format!("{}-{}", package, version)),
+            )
+            .env(
+                "GLEAM_PKG_LIB",
+                paths.lib().join(format!("{}-{}", package, version)),
+            )
+            .describe(format!("{} hook", event))
+            .changes()
+            .run()?
+            .status;
+        if status.success() {
+            continue;
+        }
+        let error = GleamPkgError::HookFailed {
+            event: event.name(),
+            package: package.to_string(),
+            status: describe_status(&status),
+        };
+        if event.is_pre() {
+            return Err(error);
+        }
+        output::warning(error.report());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn config(toml: &str) -> HooksConfig {
+        let config: crate::config::Config = toml::from_str(toml).unwrap();
+        config.hooks
+    }
+
+    #[test]
+    fn global_hooks_run_before_package_hooks() {
+        let config = config(
+            r#"
+            [hooks]
+            post_install = "global"
+
+            [hooks.wonderful_cli]
+            post_install = "own"
+            pre_uninstall = "bye"
+            "#,
+        );
+        assert_eq!(
+            commands(&config, Event::PostInstall, "wonderful_cli"),
+            ["global", "own"]
+        );
+        assert_eq!(commands(&config, Event::PostInstall, "other"), ["global"]);
+        assert!(commands(&config, Event::PreInstall, "wonderful_cli").is_empty());
+    }
+
+    #[test]
+    fn misspelled_hooks_are_rejected() {
+        let result: Result<crate::config::Config, _> =
+            toml::from_str("[hooks.wonderful_cli]\npost_instal = \"x\"\n");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn hooks_see_the_package_environment() {
+        let root = tempfile::tempdir().unwrap();
+        let out = root.path().join("out");
+        let config = config(&format!(
+            "[hooks.hello]\npost_install = 'echo \"$GLEAM_PKG_EVENT $GLEAM_PKG_PACKAGE \
+             $GLEAM_PKG_VERSION $GLEAM_PKG_WRAPPER\" > {}'\n",
+            out.display()
+        ));
+        let paths = Paths::new(root.path());
+        run(&config, Event::PostInstall, &paths, "hello", "1.0.0").unwrap();
+        assert_eq!(
+            fs::read_to_string(&out).unwrap().trim(),
+            format!(
+                "post_install hello 1.0.0 {}",
+                paths.apps().join("hello-1.0.0").display()
+            )
+        );
+    }
+
+    #[test]
+    fn only_failing_pre_hooks_are_errors() {
+        let config = config("[hooks]\npre_install = 'exit 3'\npost_install = 'exit 3'\n");
+        let paths = Paths::new(std::path::Path::new("/nonexistent"));
+        assert!(matches!(
+            run(&config, Event::PreInstall, &paths, "hello", "1.0.0"),
+            Err(GleamPkgError::HookFailed { .. })
+        ));
+        assert!(run(&config, Event::PostInstall, &paths, "hello", "1.0.0").is_ok());
+    }
+}
@@ -0,0 +1,438 @@
+//! The HTTP client used for every request to the registry
+//!
+//! Clients are built from the `[http]` section of the configuration: connect and read timeouts
+//! so a stalled registry fails the command instead of hanging it, an optional CA bundle trusted
+//! in addition to the system roots, e.g. for TLS-intercepting corporate proxies, and, as a last
+//! resort, `--insecure` to skip certificate verification altogether.
+//!
+//! With `--offline`, or `offline = true` in the configuration, no client is built: every command
+//! makes do with what is cached, see [`crate::cache`], and fails where it would have to ask.
+//!
+//! Every request goes through [`send`], which logs its method, URL, status and timing at debug
+//! level (`GLEAM_PKG_LOG=debug`), retries requests that could not connect or timed out, and
+//! counts requests and retries for `gleam-pkg stats`.
+//!
+//! Connections can be held to one IP family with `--ipv4` or `--ipv6`, or `ip_family` under
+//! `[http]`, for networks whose IPv6 or IPv4 route to the registry is broken, and host names
+//! pointed at other addresses than DNS answers with `--resolve host:port:address`, or
+//! `resolve` under `[http]`, like curl's `--resolve`.
+//!
+//! Tarball downloads can be throttled for metered or shared connections, with `--limit-rate`
+//! or `limit_rate` under `[http]`, e.g. `500K` or `1M` bytes per second. Every download of the
+//! command reads through [`throttled`], taking from one token bucket, so downloads running at
+//! the same time share the rate.
+
+use crate::config::HttpConfig;
+use crate::error::GleamPkgError;
+use crate::stats;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static INSECURE: AtomicBool = AtomicBool::new(false);
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// How often [`send`] retries a request, see [`HttpConfig::retries`]
+static RETRIES: AtomicU32 = AtomicU32::new(0);
+
+/// The wait before the first retry, doubled for each further one
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// The token bucket downloads are throttled by, `None` if they are not, see [`init`]
+static BUCKET: Mutex<Option<Bucket>> = Mutex::new(None);
+
+/// The IP family connections are held to, and the addresses of host names, see [`init`]
+static NETWORK: Mutex<(Option<IpFamily>, Vec<Resolve>)> = Mutex::new((None, Vec::new()));
+
+/// The user agent sent unless the configuration overrides it, e.g.
+/// `gleam-pkg/0.1.0 (linux; x86_64)`
+pub fn default_user_agent() -> String {
+    format!(
+        "gleam-pkg/{} ({}; {})",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+}
+
+/// Decides once whether certificates are verified and whether requests are made at all
+///
+/// # Arguments
+///
+/// * `insecure` - Whether `--insecure` was passed
+/// * `offline` - Whether `--offline` was passed or `offline` is configured
+/// * `limit_rate` - The rate passed with `--limit-rate`, which overrides the configured one
+/// * `ip_family` - The family chosen with `--ipv4` or `--ipv6`, which overrides the configured
+///   one
+/// * `resolve` - The addresses passed with `--resolve`, which replace the configured ones of
+///   the same hosts
+/// * `config` - The HTTP settings, whose `insecure` key has the same effect as `--insecure`
+pub fn init(
+    insecure: bool,
+    offline: bool,
+    limit_rate: Option<Rate>,
+    ip_family: Option<IpFamily>,
+    mut resolve: Vec<Resolve>,
+    config: &HttpConfig,
+) {
+    INSECURE.store(insecure || config.insecure, Ordering::Relaxed);
+    OFFLINE.store(offline, Ordering::Relaxed);
+    RETRIES.store(config.retries, Ordering::Relaxed);
+    let rate = limit_rate.or(config.limit_rate);
+    *BUCKET.lock().unwrap_or_else(PoisonError::into_inner) =
+        rate.map(|rate| Bucket::new(rate, Instant::now()));
+    let overridden: Vec<_> = resolve.iter().map(|r| r.host.clone()).collect();
+    resolve.extend(
+        config
+            .resolve
+            .iter()
+            .filter(|r| !overridden.contains(&r.host))
+            .cloned(),
+    );
+    *NETWORK.lock().unwrap_or_else(PoisonError::into_inner) =
+        (ip_family.or(config.ip_family), resolve);
+}
+
+/// Whether TLS certificates are accepted without verification
+pub fn insecure() -> bool {
+    INSECURE.load(Ordering::Relaxed)
+}
+
+/// Whether requests are refused, see [`init`]
+pub fn offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Builds a client with the configured timeouts and TLS settings
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Offline` when offline, `GleamPkgError::Io` if the CA bundle cannot be
+/// read, `GleamPkgError::InvalidCertificate` if it holds no valid PEM certificates, or
+/// `GleamPkgError::HttpClient` if the TLS backend cannot be initialized
+pub fn client(config: &HttpConfig) -> Result<Client, GleamPkgError> {
+    if offline() {
+        return Err(GleamPkgError::Offline);
+    }
+    let mut builder = Client::builder()
+        .user_agent(config.user_agent.clone().unwrap_or_else(default_user_agent))
+        .connect_timeout(config.connect_timeout())
+        .timeout(config.read_timeout())
+        .danger_accept_invalid_certs(insecure());
+    let (ip_family, resolve) = NETWORK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    // bound to one family, connections are only attempted to addresses of that family
+    match ip_family {
+        Some(IpFamily::Ipv4) => builder = builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        Some(IpFamily::Ipv6) => builder = builder.local_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        None => {}
+    }
+    let mut hosts: Vec<&str> = resolve.iter().map(|r| r.host.as_str()).collect();
+    hosts.sort_unstable();
+    hosts.dedup();
+    for host in hosts {
+        let addrs: Vec<_> = resolve
+            .iter()
+            .filter(|r| r.host == host)
+            .map(|r| r.addr)
+            .collect();
+        builder = builder.resolve_to_addrs(host, &addrs);
+    }
+    if let Some(path) = &config.ca_bundle {
+        let pem = fs::read(path).map_err(|source| GleamPkgError::Io {
+            action: "read CA bundle",
+            path: path.clone(),
+            source,
+        })?;
+        let certificates = reqwest::Certificate::from_pem_bundle(&pem).map_err(|source| {
+            GleamPkgError::InvalidCertificate {
+                path: path.clone(),
+                source: Some(source),
+            }
+        })?;
+        if certificates.is_empty() {
+            return Err(GleamPkgError::InvalidCertificate {
+                path: path.clone(),
+                source: None,
+            });
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    builder
+        .build()
+        .map_err(|source| GleamPkgError::HttpClient { source })
+}
+
+/// A download rate in bytes per second, written like `1048576`, `500K`, `1M` or `2G`, in
+/// multiples of 1024
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate(u64);
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (digits, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => s.split_at(i),
+            None => (s, ""),
+        };
+        let multiplier: u64 = match unit.to_ascii_uppercase().as_str() {
+            "" => 1,
+            "K" => 1024,
+            "M" => 1024 * 1024,
+            "G" => 1024 * 1024 * 1024,
+            _ => return Err(format!("{} is not a rate like 500K, 1M or 2G", s)),
+        };
+        match digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(multiplier))
+        {
+            Some(0) => Err("the rate must be more than 0".to_string()),
+            Some(bytes) => Ok(Rate(bytes)),
+            None => Err(format!("{} is not a rate like 500K, 1M or 2G", s)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Rate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The IP family connections are held to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpFamily {
+    Ipv4,
+    Ipv6,
+}
+
+/// An address a host name resolves to instead of what DNS answers, written like curl's
+/// `--resolve`: `repo.hex.pm:443:151.101.1.1` or `repo.hex.pm:443:[2a04:4e42::1]`
+///
+/// Requests to the host go to the address on the port of their URL, whatever the port given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolve {
+    host: String,
+    addr: SocketAddr,
+}
+
+impl FromStr for Resolve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "{} is not host:port:address, e.g. repo.hex.pm:443:151.101.1.1",
+                s
+            )
+        };
+        let mut parts = s.splitn(3, ':');
+        let (Some(host), Some(port), Some(addr)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let port = port.parse::<u16>().map_err(|_| invalid())?;
+        let addr = addr.trim_start_matches('[').trim_end_matches(']');
+        let addr = addr.parse::<IpAddr>().map_err(|_| invalid())?;
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Resolve {
+            host: host.to_ascii_lowercase(),
+            addr: SocketAddr::new(addr, port),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Resolve {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A token bucket holding up to a second of bytes at its rate
+///
+/// Reads take their bytes from it up front and may leave it in debt, which the next read waits
+/// out, so the rate holds however the reads are sized.
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    /// An empty bucket, filled at `rate` from `now` on
+    fn new(rate: Rate, now: Instant) -> Self {
+        Bucket {
+            rate: rate.0 as f64,
+            tokens: 0.0,
+            refilled: now,
+        }
+    }
+
+    /// Takes up to `wanted` bytes at `now`
+    ///
+    /// # Returns
+    ///
+    /// How many bytes may be read, and how long to wait before reading them
+    fn take(&mut self, wanted: usize, now: Instant) -> (usize, Duration) {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.refilled = now;
+        let taken = wanted.min((self.rate as usize).max(1));
+        self.tokens -= taken as f64;
+        let wait = Duration::from_secs_f64((-self.tokens).max(0.0) / self.rate);
+        (taken, wait)
+    }
+}
+
+/// A reader throttled to the rate of `--limit-rate`, see the [module documentation](self)
+pub struct Throttled<R> {
+    inner: R,
+}
+
+/// Reads `inner` through the token bucket downloads share, or directly if they are not
+/// throttled
+pub fn throttled<R: Read>(inner: R) -> Throttled<R> {
+    Throttled { inner }
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let taken = {
+            let mut bucket = BUCKET.lock().unwrap_or_else(PoisonError::into_inner);
+            bucket
+                .as_mut()
+                .map(|bucket| bucket.take(buf.len(), Instant::now()))
+        };
+        let Some((taken, wait)) = taken else {
+            return self.inner.read(buf);
+        };
+        thread::sleep(wait);
+        let read = self.inner.read(&mut buf[..taken])?;
+        if read < taken {
+            // return what was not read
+            if let Some(bucket) = BUCKET
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .as_mut()
+            {
+                bucket.tokens += (taken - read) as f64;
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Sends `request`, logging it and retrying it when it could not connect or timed out
+///
+/// Requests with a streaming body cannot be repeated and are sent once.
+///
+/// # Errors
+///
+/// Returns the error of the last attempt
+pub fn send(request: RequestBuilder) -> reqwest::Result<Response> {
+    let (client, request) = request.build_split();
+    let mut request = request?;
+    let retries = RETRIES.load(Ordering::Relaxed);
+    let mut attempt = 0;
+    loop {
+        let retry = match attempt < retries {
+            true => request.try_clone(),
+            false => None,
+        };
+        let method = request.method().clone();
+        let url = request.url().clone();
+        let started = Instant::now();
+        let result = client.execute(request);
+        let elapsed = started.elapsed();
+        stats::record_request(elapsed);
+        match &result {
+            Ok(response) => tracing::debug!(
+                %method,
+                %url,
+                status = response.status().as_u16(),
+                elapsed_ms = elapsed.as_millis() as u64,
+                "request"
+            ),
+            Err(e) => tracing::debug!(
+                %method,
+                %url,
+                error = %e,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "request failed"
+            ),
+        }
+        match (result, retry) {
+            (Err(e), Some(next)) if e.is_connect() || e.is_timeout() => {
+                let delay = RETRY_DELAY * 2u32.pow(attempt);
+                tracing::debug!(%url, attempt = attempt + 1, ?delay, "retrying");
+                stats::record_retry();
+                thread::sleep(delay);
+                request = next;
+                attempt += 1;
+            }
+            (result, _) => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rates_like_curl() {
+        assert_eq!("2048".parse(), Ok(Rate(2048)));
+        assert_eq!("500K".parse(), Ok(Rate(500 * 1024)));
+        assert_eq!("1m".parse(), Ok(Rate(1024 * 1024)));
+        assert!("0".parse::<Rate>().is_err());
+        assert!("fast".parse::<Rate>().is_err());
+        assert!("1.5M".parse::<Rate>().is_err());
+    }
+
+    #[test]
+    fn parses_resolve_entries_like_curl() {
+        let entry: Resolve = "Repo.hex.pm:443:151.101.1.1".parse().unwrap();
+        assert_eq!(entry.host, "repo.hex.pm");
+        assert_eq!(entry.addr, "151.101.1.1:443".parse().unwrap());
+        let entry: Resolve = "repo.hex.pm:443:[2a04:4e42::1]".parse().unwrap();
+        assert_eq!(entry.addr, "[2a04:4e42::1]:443".parse().unwrap());
+        assert!("repo.hex.pm:151.101.1.1".parse::<Resolve>().is_err());
+        assert!("repo.hex.pm:https:151.101.1.1".parse::<Resolve>().is_err());
+        assert!(":443:151.101.1.1".parse::<Resolve>().is_err());
+    }
+
+    #[test]
+    fn buckets_hold_reads_to_the_rate() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(Rate(1000), start);
+        // empty at first, and never lending more than a second of bytes
+        assert_eq!(bucket.take(4096, start), (1000, Duration::from_secs(1)));
+        let later = start + Duration::from_secs(1);
+        assert_eq!(bucket.take(500, later), (500, Duration::from_millis(500)));
+        // idle time fills it up to a second of bytes
+        let idle = later + Duration::from_secs(10);
+        assert_eq!(bucket.take(800, idle), (800, Duration::ZERO));
+    }
+}
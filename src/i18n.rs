@@ -0,0 +1,387 @@
+//! Messages in the language of the user
+//!
+//! Prompts, progress lines and the most common errors are looked up in a catalog of
+//! [`Message`]s, written in English and Chinese. The language is picked once per run:
+//!
+//! 1. `locale` in `config.toml`, or `GLEAM_PKG_LOCALE`, e.g. `locale = "zh"`
+//! 2. the first of `LC_ALL`, `LC_MESSAGES` and `LANG` that is set, e.g. `zh_CN.UTF-8`
+//! 3. English
+//!
+//! A language the catalog does not have, `C` and `POSIX` pick English. Messages name their
+//! arguments in braces, e.g. `Uninstalled {package}`, so a translation can put them in another
+//! order, see [`fill`]. Porcelain output, help topics and the errors not in the catalog stay in
+//! English.
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+static LOCALE: AtomicU8 = AtomicU8::new(Locale::English as u8);
+
+/// The languages of the catalog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    English,
+    Chinese,
+}
+
+impl Locale {
+    /// The language of a locale name like `zh_CN.UTF-8`, `zh-Hans` or `en`; `None` if the
+    /// catalog does not have it
+    pub fn parse(name: &str) -> Option<Locale> {
+        let language = name
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        match language.as_str() {
+            "en" | "c" | "posix" => Some(Locale::English),
+            "zh" => Some(Locale::Chinese),
+            _ => None,
+        }
+    }
+
+    /// The language the environment asks for, see the [module documentation](self)
+    fn from_env(env: impl Fn(&str) -> Option<String>) -> Locale {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .find_map(|name| env(name).filter(|value| !value.is_empty()))
+            .and_then(|name| Locale::parse(&name))
+            .unwrap_or(Locale::English)
+    }
+}
+
+/// Picks the language of the messages
+///
+/// # Arguments
+///
+/// * `configured` - `locale` in config.toml, which wins over the environment
+///
+/// # Errors
+///
+/// Returns `configured` back if the catalog does not have that language, which leaves the
+/// language of the environment
+pub fn init(configured: Option<&str>) -> Result<(), String> {
+    let env = Locale::from_env(|name| std::env::var(name).ok());
+    let locale = match configured {
+        Some(name) => Locale::parse(name).ok_or_else(|| name.to_string()),
+        None => Ok(env),
+    };
+    LOCALE.store(*locale.as_ref().unwrap_or(&env) as u8, Ordering::Relaxed);
+    locale.map(|_| ())
+}
+
+/// The language messages are in
+pub fn locale() -> Locale {
+    match LOCALE.load(Ordering::Relaxed) {
+        l if l == Locale::Chinese as u8 => Locale::Chinese,
+        _ => Locale::English,
+    }
+}
+
+/// The messages of the catalog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    AnswerYesOrNo,
+    Number,
+    AnswerNumber,
+    Numbers,
+    AnswerNumbers,
+    InspectingPackage,
+    DownloadingPackage,
+    TarballSaved,
+    TarballExtracted,
+    ContentsExtracted,
+    UsingGleam,
+    BuildingWith,
+    Installed,
+    AlreadyInstalled,
+    Updating,
+    UpToDate,
+    SkippingPinned,
+    AllUpToDate,
+    NoPackagesInstalled,
+    NothingToRemove,
+    Uninstalled,
+    ExplainHeading,
+    ProceedWithInstall,
+    DefaultConfiguration,
+    Error,
+    Warning,
+    Note,
+    StepOf,
+    UnknownPackage,
+    DidYouMean,
+    NoReleases,
+    ReleaseNotFound,
+    PackageNotInstalled,
+    PackagePinned,
+    ConfirmationRequired,
+    InstallDeclined,
+}
+
+impl Message {
+    /// The message in `locale`
+    pub fn text(self, locale: Locale) -> &'static str {
+        match locale {
+            Locale::English => self.english(),
+            Locale::Chinese => self.chinese(),
+        }
+    }
+
+    fn english(self) -> &'static str {
+        match self {
+            Message::AnswerYesOrNo => "Please answer y or n",
+            Message::Number => "Number",
+            Message::AnswerNumber => "Please answer a number from 1 to {count}",
+            Message::Numbers => "Numbers separated by spaces or commas, `all` or `none`",
+            Message::AnswerNumbers => "Please answer numbers from 1 to {count}",
+            Message::InspectingPackage => "Inspecting package from: {url}",
+            Message::DownloadingPackage => "Downloading package from: {url}",
+            Message::TarballSaved => "Tarball saved to: {path}",
+            Message::TarballExtracted => "Tarball extracted to: {path}",
+            Message::ContentsExtracted => "Contents extracted to: {path}",
+            Message::UsingGleam => "Using gleam {version}",
+            Message::BuildingWith => "Building with the {backend} backend",
+            Message::Installed => {
+                "Package installed successfully! You can run {package} (or {package}-{version}) \
+                 in your shell to use it now."
+            }
+            Message::AlreadyInstalled => "{package} {version} is already installed",
+            Message::Updating => "Updating {package} {from} -> {to}",
+            Message::UpToDate => "{package} is up to date ({version})",
+            Message::SkippingPinned => "Skipping {package}: pinned at {version}",
+            Message::AllUpToDate => "All packages are up to date",
+            Message::NoPackagesInstalled => "No packages installed",
+            Message::NothingToRemove => "Nothing to remove",
+            Message::Uninstalled => "Uninstalled {package}",
+            Message::ExplainHeading => "Installing {package} {version}",
+            Message::ProceedWithInstall => "Proceed with the installation?",
+            Message::DefaultConfiguration => "{error}, using the default configuration",
+            Message::Error => "error",
+            Message::Warning => "warning",
+            Message::Note => "note",
+            Message::StepOf => "{done} of {total}",
+            Message::UnknownPackage => {
+                "{url} responded with status 404, there is no package {package}"
+            }
+            Message::DidYouMean => "; did you mean {suggestion}?",
+            Message::NoReleases => "No releases of {package} found in its metadata",
+            Message::ReleaseNotFound => "{package} has no release {version}",
+            Message::PackageNotInstalled => "{package} is not installed",
+            Message::PackagePinned => {
+                "{package} is pinned at {pinned}, use --force to install {requested}"
+            }
+            Message::ConfirmationRequired => {
+                "{package} was never installed before and needs confirmation, run the install in \
+                 a terminal or pass --yes or --trust-all"
+            }
+            Message::InstallDeclined => "Did not install {package}",
+        }
+    }
+
+    fn chinese(self) -> &'static str {
+        match self {
+            Message::AnswerYesOrNo => "请回答 y 或 n",
+            Message::Number => "编号",
+            Message::AnswerNumber => "请输入 1 到 {count} 之间的编号",
+            Message::Numbers => "以空格或逗号分隔的编号，或 `all`、`none`",
+            Message::AnswerNumbers => "请输入 1 到 {count} 之间的编号",
+            Message::InspectingPackage => "正在查询软件包：{url}",
+            Message::DownloadingPackage => "正在下载软件包：{url}",
+            Message::TarballSaved => "压缩包已保存到：{path}",
+            Message::TarballExtracted => "压缩包已解压到：{path}",
+            Message::ContentsExtracted => "内容已解压到：{path}",
+            Message::UsingGleam => "使用 gleam {version}",
+            Message::BuildingWith => "正在使用 {backend} 后端构建",
+            Message::Installed => {
+                "软件包安装成功！现在可以在 shell 中运行 {package}（或 {package}-{version}）。"
+            }
+            Message::AlreadyInstalled => "{package} {version} 已安装",
+            Message::Updating => "正在更新 {package} {from} -> {to}",
+            Message::UpToDate => "{package} 已是最新版本（{version}）",
+            Message::SkippingPinned => "跳过 {package}：已固定在 {version}",
+            Message::AllUpToDate => "所有软件包均已是最新版本",
+            Message::NoPackagesInstalled => "没有已安装的软件包",
+            Message::NothingToRemove => "没有需要删除的内容",
+            Message::Uninstalled => "已卸载 {package}",
+            Message::ExplainHeading => "安装 {package} {version}",
+            Message::ProceedWithInstall => "是否继续安装？",
+            Message::DefaultConfiguration => "{error}，将使用默认配置",
+            Message::Error => "错误",
+            Message::Warning => "警告",
+            Message::Note => "提示",
+            Message::StepOf => "第 {done} 个，共 {total} 个",
+            Message::UnknownPackage => "{url} 返回状态 404，不存在软件包 {package}",
+            Message::DidYouMean => "；您是否要找 {suggestion}？",
+            Message::NoReleases => "{package} 的元数据中没有任何版本",
+            Message::ReleaseNotFound => "{package} 没有 {version} 版本",
+            Message::PackageNotInstalled => "{package} 未安装",
+            Message::PackagePinned => "{package} 已固定在 {pinned}，使用 --force 安装 {requested}",
+            Message::ConfirmationRequired => {
+                "{package} 从未安装过，需要确认；请在终端中安装，或传入 --yes 或 --trust-all"
+            }
+            Message::InstallDeclined => "未安装 {package}",
+        }
+    }
+}
+
+/// `message` in the language of the user, see [`init`]
+pub fn text(message: Message) -> &'static str {
+    message.text(locale())
+}
+
+/// `message` in the language of the user, with its arguments filled in
+///
+/// # Arguments
+///
+/// * `message` - The message
+/// * `args` - The arguments by name, e.g. `("package", &"wisp")` for `{package}`
+pub fn fill(message: Message, args: &[(&str, &dyn Display)]) -> String {
+    fill_in(message.text(locale()), args)
+}
+
+fn fill_in(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut filled = template.to_string();
+    for (name, value) in args {
+        filled = filled.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    const ALL: [Message; 36] = [
+        Message::AnswerYesOrNo,
+        Message::Number,
+        Message::AnswerNumber,
+        Message::Numbers,
+        Message::AnswerNumbers,
+        Message::InspectingPackage,
+        Message::DownloadingPackage,
+        Message::TarballSaved,
+        Message::TarballExtracted,
+        Message::ContentsExtracted,
+        Message::UsingGleam,
+        Message::BuildingWith,
+        Message::Installed,
+        Message::AlreadyInstalled,
+        Message::Updating,
+        Message::UpToDate,
+        Message::SkippingPinned,
+        Message::AllUpToDate,
+        Message::NoPackagesInstalled,
+        Message::NothingToRemove,
+        Message::Uninstalled,
+        Message::ExplainHeading,
+        Message::ProceedWithInstall,
+        Message::DefaultConfiguration,
+        Message::Error,
+        Message::Warning,
+        Message::Note,
+        Message::StepOf,
+        Message::UnknownPackage,
+        Message::DidYouMean,
+        Message::NoReleases,
+        Message::ReleaseNotFound,
+        Message::PackageNotInstalled,
+        Message::PackagePinned,
+        Message::ConfirmationRequired,
+        Message::InstallDeclined,
+    ];
+
+    fn arguments(template: &str) -> BTreeSet<&str> {
+        template
+            .split('{')
+            .skip(1)
+            .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+            .collect()
+    }
+
+    #[test]
+    fn translations_take_the_same_arguments() {
+        for message in ALL {
+            let english = message.text(Locale::English);
+            let chinese = message.text(Locale::Chinese);
+            assert_ne!(english, chinese, "{:?}", message);
+            assert_eq!(arguments(english), arguments(chinese), "{:?}", message);
+        }
+    }
+
+    #[test]
+    fn picks_the_language_of_the_environment() {
+        assert_eq!(Locale::parse("zh_CN.UTF-8"), Some(Locale::Chinese));
+        assert_eq!(Locale::parse("zh-Hans"), Some(Locale::Chinese));
+        assert_eq!(Locale::parse("C.UTF-8"), Some(Locale::English));
+        assert_eq!(Locale::parse("fr_FR"), None);
+
+        let env = |vars: &'static [(&str, &str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert_eq!(Locale::from_env(env(&[])), Locale::English);
+        assert_eq!(
+            Locale::from_env(env(&[("LANG", "zh_TW.UTF-8")])),
+            Locale::Chinese
+        );
+        assert_eq!(
+            Locale::from_env(env(&[("LANG", "zh_CN.UTF-8"), ("LC_ALL", "en_US.UTF-8")])),
+            Locale::English
+        );
+        assert_eq!(
+            Locale::from_env(env(&[("LANG", "zh_CN.UTF-8"), ("LC_ALL", "")])),
+            Locale::Chinese
+        );
+        assert_eq!(
+            Locale::from_env(env(&[("LANG", "de_DE.UTF-8")])),
+            Locale::English
+        );
+    }
+
+    #[test]
+    fn english_errors_read_like_their_display() {
+        use crate::error::GleamPkgError;
+        let package = || "wisp".to_string();
+        let errors = [
+            GleamPkgError::UnknownPackage {
+                package: package(),
+                url: "https://hex.pm/api/packages/wisp".to_string(),
+                suggestion: Some("wasp".to_string()),
+            },
+            GleamPkgError::NoReleases { package: package() },
+            GleamPkgError::ReleaseNotFound {
+                package: package(),
+                version: "9.9.9".to_string(),
+            },
+            GleamPkgError::PackageNotInstalled {
+                package: package(),
+                version: Some("1.0.0".to_string()),
+            },
+            GleamPkgError::PackagePinned {
+                package: package(),
+                pinned: "1.0.0".to_string(),
+                requested: "2.0.0".to_string(),
+            },
+            GleamPkgError::ConfirmationRequired { package: package() },
+            GleamPkgError::InstallDeclined { package: package() },
+        ];
+        // the tests never pick another language
+        for error in errors {
+            assert_eq!(error.localized(), error.to_string());
+        }
+    }
+
+    #[test]
+    fn fills_in_arguments_by_name() {
+        let template = Message::Installed.text(Locale::Chinese);
+        assert_eq!(
+            fill_in(template, &[("package", &"wisp"), ("version", &"1.3.0")]),
+            "软件包安装成功！现在可以在 shell 中运行 wisp（或 wisp-1.3.0）。"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! The versions index of a hex repository
+//!
+//! Next to the tarballs, every hex repository publishes `/versions`: the name of every package
+//! with its versions and which of them are retired, a few hundred kilobytes for hex.pm. Looking
+//! the latest versions of many packages up in it costs a single request instead of one per
+//! package, which `outdated` and `update` use before asking the API about the packages that
+//! actually changed. The decoded index is cached like package metadata:
+//!
+//! ```text
+//! cache/index/<repo>[/<organization>].json
+//! ```
+//!
+//! and used for `[cache] index_ttl_secs`, or revalidated at once with `--refresh`.
+//!
+//! The resource is a gzipped protobuf `Signed` message whose payload is a `Versions` message.
+//! Its signature is not checked: the index only decides which packages to look at, releases are
+//! still verified against the checksums of the API. The `/names` resource next to it, which only
+//! lists the names, is decoded by [`decode_names`] for [`crate::names`].
+
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Read;
+
+/// The packages of a repository with their versions
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionsIndex {
+    pub packages: BTreeMap<String, IndexedPackage>,
+}
+
+/// The versions of a package in a [`VersionsIndex`]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedPackage {
+    pub versions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retired: Vec<String>,
+}
+
+impl VersionsIndex {
+    /// Decodes the gzipped `/versions` resource
+    ///
+    /// # Errors
+    ///
+    /// Returns what is wrong with the resource if it is not a gzipped `Signed` message
+    pub fn decode(gzipped: &[u8]) -> Result<Self, String> {
+        let payload = signed_payload(gzipped)?;
+        let mut index = VersionsIndex::default();
+        for field in Fields(&payload) {
+            if let (1, Value::Bytes(package)) = field? {
+                let (name, package) = decode_package(package)?;
+                index.packages.insert(name, package);
+            }
+        }
+        Ok(index)
+    }
+
+    /// The newest stable version of `package` that is not retired, comparing as semver, like the
+    /// releases `update` picks from
+    pub fn latest(&self, package: &str) -> Option<&str> {
+        let package = self.packages.get(package)?;
+        package
+            .versions
+            .iter()
+            .filter(|v| !package.retired.contains(v))
+            .filter_map(|v| Some((semver::Version::parse(v).ok()?, v.as_str())))
+            .filter(|(version, _)| version.pre.is_empty())
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, version)| version)
+    }
+}
+
+/// Decodes the gzipped `/names` resource into the names of the packages it lists
+///
+/// # Errors
+///
+/// Returns what is wrong with the resource if it is not a gzipped `Signed` message
+pub fn decode_names(gzipped: &[u8]) -> Result<Vec<String>, String> {
+    let payload = signed_payload(gzipped)?;
+    let mut names = Vec::new();
+    for field in Fields(&payload) {
+        if let (1, Value::Bytes(package)) = field? {
+            for field in Fields(package) {
+                if let (1, Value::Bytes(name)) = field? {
+                    names.push(String::from_utf8(name.to_vec()).map_err(|e| e.to_string())?);
+                }
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// The payload of a gzipped `Signed` message
+fn signed_payload(gzipped: &[u8]) -> Result<Vec<u8>, String> {
+    let mut signed = Vec::new();
+    GzDecoder::new(gzipped)
+        .read_to_end(&mut signed)
+        .map_err(|e| format!("not gzipped: {}", e))?;
+    let mut payload = None;
+    for field in Fields(&signed) {
+        if let (1, Value::Bytes(bytes)) = field? {
+            payload = Some(bytes);
+        }
+    }
+    payload.map(<[u8]>::to_vec).ok_or("no payload".to_string())
+}
+
+/// Decodes a `Package` message: its name, versions and the indices of the retired versions
+fn decode_package(message: &[u8]) -> Result<(String, IndexedPackage), String> {
+    let text = |bytes: &[u8]| String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string());
+    let mut name = String::new();
+    let mut package = IndexedPackage::default();
+    let mut retired = Vec::new();
+    for field in Fields(message) {
+        match field? {
+            (1, Value::Bytes(bytes)) => name = text(bytes)?,
+            (2, Value::Bytes(bytes)) => package.versions.push(text(bytes)?),
+            (3, Value::Varint(index)) => retired.push(index),
+            // packed
+            (3, Value::Bytes(mut bytes)) => {
+                while !bytes.is_empty() {
+                    retired.push(varint(&mut bytes)?);
+                }
+            }
+            _ => {}
+        }
+    }
+    package.retired = retired
+        .into_iter()
+        .filter_map(|i| package.versions.get(i as usize).cloned())
+        .collect();
+    Ok((name, package))
+}
+
+/// A protobuf field value, as far as the index needs to tell them apart
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Iterates over the field numbers and values of a protobuf message
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u64, Value<'a>), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let field = (|| {
+            let key = varint(&mut self.0)?;
+            let value = match key & 7 {
+                0 => Value::Varint(varint(&mut self.0)?),
+                1 => {
+                    take(&mut self.0, 8)?;
+                    Value::Fixed
+                }
+                2 => {
+                    let length = varint(&mut self.0)? as usize;
+                    Value::Bytes(take(&mut self.0, length)?)
+                }
+                5 => {
+                    take(&mut self.0, 4)?;
+                    Value::Fixed
+                }
+                wire_type => return Err(format!("unknown wire type {}", wire_type)),
+            };
+            Ok((key >> 3, value))
+        })();
+        if field.is_err() {
+            // stop after the first error
+            self.0 = &[];
+        }
+        Some(field)
+    }
+}
+
+fn take<'a>(data: &mut &'a [u8], length: usize) -> Result<&'a [u8], String> {
+    if data.len() < length {
+        return Err("truncated message".to_string());
+    }
+    let (taken, rest) = data.split_at(length);
+    *data = rest;
+    Ok(taken)
+}
+
+fn varint(data: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let [byte, rest @ ..] = *data else {
+            return Err("truncated varint".to_string());
+        };
+        *data = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("varint too long".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    fn field(number: u8, bytes: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![number << 3 | 2, bytes.len() as u8];
+        encoded.extend_from_slice(bytes);
+        encoded
+    }
+
+    #[test]
+    fn decodes_the_versions_resource() {
+        let mut package = field(1, b"wisp");
+        for version in ["0.9.0", "1.10.0", "1.2.0", "2.0.0-rc.1", "1.11.0"] {
+            package.extend(field(2, version.as_bytes()));
+        }
+        // versions 0 and 4 are retired, packed
+        package.extend(field(3, &[0, 4]));
+        let mut payload = field(1, &package);
+        payload.extend(field(1, &field(1, b"argv")));
+        payload.extend(field(2, b"hexpm"));
+        let mut signed = field(1, &payload);
+        signed.extend(field(2, b"signature"));
+        let mut gzipped = GzEncoder::new(Vec::new(), Compression::default());
+        gzipped.write_all(&signed).unwrap();
+
+        let index = VersionsIndex::decode(&gzipped.finish().unwrap()).unwrap();
+        assert_eq!(index.packages.len(), 2);
+        assert_eq!(index.packages["wisp"].retired, ["0.9.0", "1.11.0"]);
+        assert_eq!(index.latest("wisp"), Some("1.10.0"));
+        assert_eq!(index.latest("argv"), None);
+        assert!(VersionsIndex::decode(b"not gzipped").is_err());
+    }
+
+    #[test]
+    fn decodes_the_names_resource() {
+        let mut payload = field(1, &field(1, b"wisp"));
+        // the time it was updated is skipped
+        payload.extend(field(1, &[field(1, b"argv"), field(2, &[8, 1])].concat()));
+        payload.extend(field(2, b"hexpm"));
+        let mut gzipped = GzEncoder::new(Vec::new(), Compression::default());
+        gzipped.write_all(&field(1, &payload)).unwrap();
+
+        let names = decode_names(&gzipped.finish().unwrap()).unwrap();
+        assert_eq!(names, ["wisp", "argv"]);
+        assert!(decode_names(b"not gzipped").is_err());
+    }
+}
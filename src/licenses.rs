@@ -0,0 +1,127 @@
+//! License policy for installed packages
+//!
+//! Packages declare their licenses as SPDX identifiers in their hex metadata. The `[licenses]`
+//! section of the configuration decides which are acceptable, for the installed package and
+//! every package in its dependency tree:
+//!
+//! ```toml
+//! [licenses]
+//! # a package is acceptable if one of its licenses is allowed, everything is by default
+//! allow = ["MIT", "Apache-2.0", "BSD-3-Clause"]
+//! # a package is not acceptable if any of its licenses is denied
+//! deny = ["AGPL-3.0-only"]
+//! # "warn" reports unacceptable packages, "refuse" fails the install
+//! on_violation = "warn"
+//! ```
+
+use serde::Deserialize;
+
+/// The `[licenses]` section of the configuration
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LicensePolicy {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    pub on_violation: OnViolation,
+}
+
+/// What installing a package with an unacceptable license does
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnViolation {
+    #[default]
+    Warn,
+    Refuse,
+}
+
+/// A package judged by the policy
+#[derive(Debug, Clone)]
+pub struct Checked {
+    pub package: String,
+    pub version: String,
+    pub licenses: Vec<String>,
+    pub verdict: Verdict,
+}
+
+/// Whether a package's licenses are acceptable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Allowed,
+    /// One of the licenses is denied
+    Denied(String),
+    /// None of the licenses, possibly because there are none, is allowed
+    NotAllowed,
+}
+
+impl Verdict {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Verdict::Allowed => "ok",
+            Verdict::Denied(_) => "denied",
+            Verdict::NotAllowed => "not allowed",
+        }
+    }
+}
+
+impl LicensePolicy {
+    /// Whether any license is allowed or denied, otherwise there is nothing to check
+    pub fn is_active(&self) -> bool {
+        !self.allow.is_empty() || !self.deny.is_empty()
+    }
+
+    /// Judges a package licensed under `licenses`, comparing identifiers case-insensitively
+    pub fn verdict(&self, licenses: &[String]) -> Verdict {
+        let listed = |list: &[String], license: &str| {
+            list.iter().any(|entry| entry.eq_ignore_ascii_case(license))
+        };
+        if let Some(denied) = licenses.iter().find(|l| listed(&self.deny, l)) {
+            return Verdict::Denied(denied.clone());
+        }
+        if self.allow.is_empty() || licenses.iter().any(|l| listed(&self.allow, l)) {
+            return Verdict::Allowed;
+        }
+        Verdict::NotAllowed
+    }
+}
+
+/// The SPDX identifiers in the hex metadata of a package
+pub fn from_metadata(metadata: &serde_json::Value) -> Vec<String> {
+    metadata["meta"]["licenses"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|license| license.as_str().map(String::from))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn licenses(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn denied_licenses_win_over_allowed_ones() {
+        let policy = LicensePolicy {
+            allow: licenses(&["MIT", "Apache-2.0"]),
+            deny: licenses(&["AGPL-3.0-only"]),
+            on_violation: OnViolation::Refuse,
+        };
+        assert_eq!(policy.verdict(&licenses(&["mit"])), Verdict::Allowed);
+        assert_eq!(
+            policy.verdict(&licenses(&["GPL-2.0-only", "Apache-2.0"])),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            policy.verdict(&licenses(&["MIT", "AGPL-3.0-only"])),
+            Verdict::Denied("AGPL-3.0-only".to_string())
+        );
+        assert_eq!(policy.verdict(&[]), Verdict::NotAllowed);
+        assert_eq!(
+            LicensePolicy::default().verdict(&licenses(&["WTFPL"])),
+            Verdict::Allowed
+        );
+    }
+}
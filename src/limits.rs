@@ -0,0 +1,177 @@
+//! Resource limits for external processes spawned while building a package
+//!
+//! `gleam build`, the `erl` probes, rebar3 and mix builds and smoke tests are all run by a
+//! [`crate::runner::Runner`] under [`BuildLimits`], which enforce a wall-clock timeout and,
+//! optionally, address-space and CPU-time limits via `setrlimit(2)`. Children are placed in
+//! their own process group so a timed-out step takes its whole process tree (e.g. the BEAM
+//! started by `gleam`) down with it.
+//!
+//! With `--isolated` every step also runs in a scrubbed environment with a fresh temporary
+//! `HOME`, so user-level Gleam and hex caches and settings cannot leak into the artifacts, and
+//! with `--no-network` on Linux in a network namespace of its own with no interfaces up.
+
+use crate::error::GleamPkgError;
+use crate::toolchain;
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Limits applied to every external build process
+#[derive(Debug, Clone)]
+pub struct BuildLimits {
+    /// Wall-clock time a single step may run before it is killed
+    pub timeout: Duration,
+    /// Maximum address space of the child in bytes (`RLIMIT_AS`)
+    pub memory_limit: Option<u64>,
+    /// Maximum CPU time of the child in seconds (`RLIMIT_CPU`)
+    pub cpu_limit: Option<u64>,
+    /// Run the child in a clean environment, see [`Isolation`]
+    pub isolation: Option<Isolation>,
+}
+
+/// How isolated builds are sealed off from the user's environment
+#[derive(Debug, Clone)]
+pub struct Isolation {
+    /// Whether the child may use the network, `gleam build` needs it to fetch dependencies
+    pub network: bool,
+}
+
+/// Environment variables an isolated build keeps, with a fixed value
+const ISOLATED_ENV: [(&str, &str); 2] = [("LANG", "C.UTF-8"), ("LC_ALL", "C.UTF-8")];
+
+/// A temporary `HOME` for one isolated step, removed when dropped
+///
+/// It is created exclusively and only accessible to the user, so nobody else can have put
+/// settings in it for the build to pick up.
+pub struct TempHome(TempDir);
+
+impl TempHome {
+    fn create() -> Result<Self, GleamPkgError> {
+        let io_error = |path: PathBuf| {
+            move |source| GleamPkgError::Io {
+                action: "create isolated home",
+                path,
+                source,
+            }
+        };
+        let dir = tempfile::Builder::new()
+            .prefix("gleam-pkg-home-")
+            .tempdir()
+            .map_err(io_error(std::env::temp_dir()))?;
+        fs::create_dir(dir.path().join("tmp")).map_err(io_error(dir.path().to_path_buf()))?;
+        Ok(TempHome(dir))
+    }
+
+    fn path(&self) -> &Path {
+        self.0.path()
+    }
+}
+
+impl BuildLimits {
+    /// Sets `cmd` up to run under the limits: in a process group of its own, with the rlimits,
+    /// and isolated if the build is
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of isolating it, see `isolate`
+    ///
+    /// # Returns
+    ///
+    /// The temporary home of an isolated step, to be kept until the step is done
+    pub fn prepare(&self, cmd: &mut Command) -> Result<Option<TempHome>, GleamPkgError> {
+        self.apply(cmd);
+        self.isolate(cmd)
+    }
+
+    /// Installs the process group and rlimit settings on `cmd`
+    fn apply(&self, cmd: &mut Command) {
+        cmd.process_group(0);
+        let memory_limit = self.memory_limit;
+        let cpu_limit = self.cpu_limit;
+        if memory_limit.is_none() && cpu_limit.is_none() {
+            return;
+        }
+        // SAFETY: the closure only calls `setrlimit`, which is async-signal-safe
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(bytes) = memory_limit {
+                    check(libc::setrlimit(libc::RLIMIT_AS, &rlimit(bytes)))?;
+                }
+                if let Some(secs) = cpu_limit {
+                    check(libc::setrlimit(libc::RLIMIT_CPU, &rlimit(secs)))?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// Scrubs the environment of `cmd` and points `HOME` at a fresh temporary directory when
+    /// the build is isolated, returning that directory
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` if the temporary home cannot be created, or
+    /// `GleamPkgError::NetworkIsolationUnsupported` if network isolation is requested on a
+    /// platform without network namespaces
+    fn isolate(&self, cmd: &mut Command) -> Result<Option<TempHome>, GleamPkgError> {
+        let Some(isolation) = &self.isolation else {
+            return Ok(None);
+        };
+        let home = TempHome::create()?;
+
+        // the resolved toolchain goes first, shims of version managers need the real HOME
+        let mut path = [toolchain::gleam(), toolchain::erl()]
+            .iter()
+            .filter(|tool| tool.is_absolute())
+            .filter_map(|tool| tool.parent().map(PathBuf::from))
+            .collect::<Vec<_>>();
+        if let Some(user_path) = std::env::var_os("PATH") {
+            path.extend(std::env::split_paths(&user_path));
+        }
+        let path = std::env::join_paths(path).map_err(std::io::Error::other)?;
+
+        cmd.env_clear()
+            .env("PATH", path)
+            .env("HOME", home.path())
+            .env("TMPDIR", home.path().join("tmp"))
+            .envs(ISOLATED_ENV);
+
+        if !isolation.network {
+            unshare_network(cmd)?;
+        }
+        Ok(Some(home))
+    }
+}
+
+/// Runs the child in new user and network namespaces, leaving it without network access
+#[cfg(target_os = "linux")]
+fn unshare_network(cmd: &mut Command) -> Result<(), GleamPkgError> {
+    // SAFETY: the closure only calls `unshare`, and the forked child is single threaded as
+    // CLONE_NEWUSER requires
+    unsafe {
+        cmd.pre_exec(|| check(libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET)));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unshare_network(_cmd: &mut Command) -> Result<(), GleamPkgError> {
+    Err(GleamPkgError::NetworkIsolationUnsupported)
+}
+
+fn rlimit(value: u64) -> libc::rlimit {
+    libc::rlimit {
+        rlim_cur: value as libc::rlim_t,
+        rlim_max: value as libc::rlim_t,
+    }
+}
+
+fn check(ret: libc::c_int) -> std::io::Result<()> {
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
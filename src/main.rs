@@ -18,24 +18,97 @@
 //! gleam-pkg install <package-name>
 //! ```
 
-use base64::engine::general_purpose::STANDARD;
-use base64::Engine;
-use clap::{Parser, Subcommand};
+use backend::{Artifact, Backend, BuildContext, Target, WrapperMode};
+use buildlog::BuildLog;
+use cache::{CachedMetadata, MetadataCache};
+use clap::{ArgGroup, Args, CommandFactory, Parser, Subcommand};
+use config::Config;
+use db::{Database, Storage};
 use error::*;
 use flate2::read::GzDecoder;
-use lazy_static::lazy_static;
+use hooks::Event;
+use i18n::Message;
+use index::VersionsIndex;
+use limits::BuildLimits;
+use paths::Paths;
+use plan::{Action, Plan};
+use registry::{ApiResponse, PackageSpec, Registry, Source};
+use runner::Runner;
+use sharedcache::SharedCache;
+use shell::Shell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
 use std::fs;
 use std::io::Write;
+use std::num::NonZeroUsize;
 use std::os::unix::fs::PermissionsExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use store::Store;
 
+mod audit;
+mod backend;
+mod buildcache;
+mod buildlog;
+mod bundle;
+mod cache;
+mod changelog;
+mod checksum;
+mod config;
+mod db;
+mod docker;
+mod downloads;
+mod erlang;
 mod error;
+mod escript;
+mod events;
+mod exec;
+mod explain;
+mod gc;
+mod help;
+mod history;
+mod hooks;
+mod http;
+mod i18n;
+mod index;
+mod licenses;
+mod limits;
+mod manifest;
+mod mirrors;
+mod names;
+mod output;
+mod paths;
+mod plan;
+mod project;
+mod prompt;
+mod publishable;
+mod registry;
+mod releases;
+mod runner;
+mod sbom;
+mod setup;
+mod sharedcache;
+mod shell;
+mod smoke;
+mod sqlite;
+mod stats;
+mod store;
+mod toolchain;
+mod toolchains;
+mod trust;
+mod ui;
+mod updatecheck;
 
 /// Command-line interface for `gleam-pkg`
 #[derive(Parser)]
 #[command(name = "gleam-pkg")]
 #[command(about = "Gleam package manager for installing Gleam CLI programs")]
 #[command(arg_required_else_help = true)]
+#[command(disable_help_subcommand = true)]
 struct Cli {
     /// The version of the Gleam package manager
     #[arg(
@@ -44,625 +117,6671 @@ struct Cli {
         help = "Prints the version of the Gleam package manager"
     )]
     version: bool,
+    /// Disable colored output, also implied by a non-empty `NO_COLOR` or a non-terminal stdout
+    #[arg(long, global = true)]
+    no_color: bool,
+    /// Print only results and errors, no progress
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    /// Print results in a stable tab-separated format for scripts, see `gleam-pkg help scripting`
+    #[arg(long, global = true)]
+    porcelain: bool,
+    /// Print discrete lines without color, symbols or progress bars, for screen readers and
+    /// logs; implied by a non-terminal stdout
+    #[arg(long, global = true)]
+    plain: bool,
+    /// Revalidate cached registry metadata and versions indexes regardless of their age
+    #[arg(long, global = true)]
+    refresh: bool,
+    /// Page long output of list, search and releases through $PAGER, less by default
+    #[arg(long, global = true, overrides_with = "no_pager")]
+    pager: bool,
+    /// Print long output as it is, even with pager = true in config.toml
+    #[arg(long, global = true)]
+    no_pager: bool,
+    /// Never contact a registry: use cached metadata however old, fail where none is cached
+    #[arg(long, global = true, conflicts_with = "refresh")]
+    offline: bool,
+    /// Answer yes to every question and take the default of every choice, without asking
+    #[arg(short, long, global = true)]
+    yes: bool,
+    /// Accept any TLS certificate from the registry, e.g. behind a TLS-intercepting proxy
+    #[arg(long, global = true)]
+    insecure: bool,
+    /// Download tarballs no faster than RATE bytes per second, e.g. 500K or 1M
+    #[arg(long, global = true, value_name = "RATE")]
+    limit_rate: Option<http::Rate>,
+    /// Connect to registries over IPv4 only
+    #[arg(long, global = true, conflicts_with = "ipv6")]
+    ipv4: bool,
+    /// Connect to registries over IPv6 only
+    #[arg(long, global = true)]
+    ipv6: bool,
+    /// Send requests for HOST to ADDRESS instead of what DNS answers, like curl's --resolve;
+    /// may be repeated
+    #[arg(long, global = true, value_name = "HOST:PORT:ADDRESS")]
+    resolve: Vec<http::Resolve>,
+    /// Skip the questions of the first run and set gleam-pkg up with the defaults
+    #[arg(long, global = true)]
+    defaults: bool,
+    /// Write progress events to FILE as JSON lines, for frontends rendering their own progress
+    #[arg(long, global = true, value_name = "FILE")]
+    events: Option<PathBuf>,
     /// The subcommand to execute
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+/// Resource limits for build processes, shared by every command that builds packages
+#[derive(Args)]
+struct LimitArgs {
+    /// Seconds a single build step may run before it is killed
+    #[arg(long, value_name = "SECS")]
+    timeout: Option<u64>,
+    /// Address-space limit for build processes, in MiB
+    #[arg(long, value_name = "MIB")]
+    memory_limit: Option<u64>,
+    /// CPU time limit for build processes, in seconds
+    #[arg(long, value_name = "SECS")]
+    cpu_limit: Option<u64>,
+    /// Build in a scrubbed environment with a temporary HOME, ignoring user-level caches
+    #[arg(long)]
+    isolated: bool,
+    /// Also cut isolated builds off the network (Linux only)
+    #[arg(long, requires = "isolated")]
+    no_network: bool,
+}
+
+impl LimitArgs {
+    fn limits(&self, config: &Config) -> BuildLimits {
+        BuildLimits {
+            timeout: self
+                .timeout
+                .map_or(config.build_timeout(), Duration::from_secs),
+            memory_limit: self.memory_limit.map(|mib| mib * 1024 * 1024),
+            cpu_limit: self.cpu_limit,
+            isolation: self.isolated.then_some(limits::Isolation {
+                network: !self.no_network,
+            }),
+        }
+    }
+}
+
+/// Overrides for the toolchain builds run with, shared by every command that builds packages
+#[derive(Args)]
+struct ToolchainArgs {
+    /// The gleam binary to build with, instead of the one selected by .tool-versions or PATH
+    #[arg(long, value_name = "PATH")]
+    gleam_path: Option<PathBuf>,
+    /// Build with this release of the Gleam compiler, downloaded into ~/.gleam_pkgs/toolchains
+    /// unless it is there already
+    #[arg(long, value_name = "VERSION", conflicts_with = "gleam_path")]
+    gleam_version: Option<String>,
+    /// The erl binary to build with, instead of the one selected by .tool-versions or PATH
+    #[arg(long, value_name = "PATH")]
+    erl_path: Option<PathBuf>,
+    /// Build in the configured Gleam docker image instead of with the local toolchain
+    #[arg(long, conflicts_with_all = ["gleam_path", "gleam_version", "erl_path"])]
+    build_in_docker: bool,
+}
+
+impl ToolchainArgs {
+    /// Resolves the toolchain and makes it the one every build step uses
+    ///
+    /// A compiler release selected by `--gleam-version` or `[toolchains]` is downloaded first if
+    /// needed, see [`crate::toolchains`].
+    fn install(&self, ctx: &Context) -> Result<(), GleamPkgError> {
+        let config = &ctx.config;
+        let toolchain = if self.build_in_docker || config.docker.enabled {
+            toolchain::Toolchain::docker(&config.docker.image)?
+        } else {
+            let release = match (&self.gleam_path, &self.gleam_version) {
+                (Some(_), _) => None,
+                (None, Some(version)) => Some((version.clone(), "--gleam-version")),
+                (None, None) => config
+                    .toolchains
+                    .gleam_version
+                    .clone()
+                    .map(|version| (version, "config.toml"))
+                    .or_else(|| {
+                        toolchains::default_gleam(&ctx.paths)
+                            .map(|version| (version, "`gleam-pkg toolchain default`"))
+                    }),
+            };
+            let managed = release
+                .as_ref()
+                .map(|(version, _)| {
+                    toolchains::ensure_gleam(&config.toolchains, &config.http, &ctx.paths, version)
+                })
+                .transpose()?;
+            let mut toolchain = toolchain::Toolchain::resolve(
+                managed.as_deref().or(self.gleam_path.as_deref()),
+                self.erl_path.as_deref(),
+            )?;
+            if let Some((version, origin)) = release {
+                toolchain.gleam.source = format!("gleam {} from {}", version, origin);
+            }
+            toolchain
+        };
+        for tool in [&toolchain.gleam, &toolchain.erl] {
+            if tool.source != "PATH" {
+                output::info(format!(
+                    "Using {} (from {})",
+                    tool.path.display(),
+                    tool.source
+                ));
+            }
+        }
+        toolchain.install();
+        Ok(())
+    }
+}
+
 /// Subcommands supported by `gleam-pkg`
 #[derive(Subcommand)]
 enum Commands {
     /// Install a Gleam package
+    #[command(group(ArgGroup::new("what").required(true).args(["package", "file"])))]
     Install {
-        /// The name of the package to install
+        /// The package to install as `[repo:][organization/]package[@version]`, or the URL of
+        /// its page on hex.pm
+        package: Option<String>,
+        /// Install the version a `gleam-pkg bundle` tarball holds, without downloading or
+        /// building anything
+        #[arg(
+            long,
+            value_name = "BUNDLE",
+            conflicts_with_all = ["repo", "target", "latest", "dry_run"]
+        )]
+        file: Option<PathBuf>,
+        /// The repository to install from, one of the [repos] in config.toml
+        #[arg(long, value_name = "NAME")]
+        repo: Option<String>,
+        /// The backend to build and run the package with, detected from the package by default
+        #[arg(long, value_enum)]
+        target: Option<Target>,
+        /// Replace a pinned package with a different version
+        #[arg(long)]
+        force: bool,
+        /// Also make the package available under this command name, instead of a command name,
+        /// its own included, that another package or file already provides
+        #[arg(long = "as", value_name = "NAME")]
+        alias: Option<String>,
+        /// Take over command names another package or a file not managed by gleam-pkg uses
+        #[arg(long)]
+        overwrite: bool,
+        /// Install packages never installed before without asking, see [trust] in config.toml
+        #[arg(long)]
+        trust_all: bool,
+        /// Install the latest release without asking, even if it is a pre-release or several
+        /// major versions are published
+        #[arg(long)]
+        latest: bool,
+        /// Print what would be downloaded, built and written without doing it
+        #[arg(long)]
+        dry_run: bool,
+        /// Embed the Erlang/OTP installation an escript is built on, so it runs without Erlang
+        /// installed, at the cost of its size
+        #[arg(long, conflicts_with = "file")]
+        bundle_erts: bool,
+        /// How the wrapper runs an escript, `wrapper` in config.toml by default
+        #[arg(long, value_enum, value_name = "MODE")]
+        wrapper: Option<WrapperMode>,
+        /// Build the package even if an escript of the same release built with the same
+        /// toolchain is kept
+        #[arg(long, conflicts_with = "file")]
+        rebuild: bool,
+        /// Install a release installed before even if the registry now publishes another
+        /// tarball for it
+        #[arg(long, conflicts_with = "file")]
+        accept_new_checksum: bool,
+        /// Print which release is installed, where it is fetched from and how it is built and
+        /// run, and why, then ask to proceed
+        #[arg(long, conflicts_with = "file")]
+        explain: bool,
+        #[command(flatten)]
+        limits: LimitArgs,
+        #[command(flatten)]
+        toolchain: ToolchainArgs,
+    },
+    /// Install a package unless a matching version is installed, never asking anything, for
+    /// provisioning scripts
+    Ensure {
+        /// The package as `[repo:][organization/]package[@version]`; a version like `1` or `1.2`
+        /// is met by every release it is a prefix of, no version by any
+        package: String,
+        /// The repository to install from, one of the [repos] in config.toml
+        #[arg(long, value_name = "NAME")]
+        repo: Option<String>,
+        /// The backend to build and run the package with if it is installed, detected from the
+        /// package by default
+        #[arg(long, value_enum)]
+        target: Option<Target>,
+        #[command(flatten)]
+        limits: LimitArgs,
+        #[command(flatten)]
+        toolchain: ToolchainArgs,
+    },
+    /// Install a package into a throwaway root and report what it takes up, leaving the real
+    /// installation alone, for authors checking their tool installs
+    TestInstall {
+        /// The package as `[repo:][organization/]package[@version]`
+        package: String,
+        /// The repository to install from, one of the [repos] in config.toml
+        #[arg(long, value_name = "NAME")]
+        repo: Option<String>,
+        /// The backend to build and run the package with, detected from the package by default
+        #[arg(long, value_enum)]
+        target: Option<Target>,
+        /// Keep the throwaway root after a successful install, it is always kept after a
+        /// failed one
+        #[arg(long)]
+        keep: bool,
+        #[command(flatten)]
+        limits: LimitArgs,
+        #[command(flatten)]
+        toolchain: ToolchainArgs,
+    },
+    /// Publish the project in the current directory with `gleam publish`, then install the
+    /// release just published to confirm it installs
+    ///
+    /// The project is checked like by `gleam-pkg check-publishable` first, and nothing is
+    /// published if a check finds a problem.
+    Publish {
+        /// The repository to install the release from, one of the [repos] in config.toml
+        #[arg(long, value_name = "NAME")]
+        repo: Option<String>,
+        /// The backend to build and run the release with, detected from the package by default
+        #[arg(long, value_enum)]
+        target: Option<Target>,
+        /// Publish even if a check finds a problem
+        #[arg(long)]
+        no_check: bool,
+        #[command(flatten)]
+        limits: LimitArgs,
+        #[command(flatten)]
+        toolchain: ToolchainArgs,
+        /// Arguments passed on to `gleam publish`, e.g. `-- --yes`
+        #[arg(last = true, value_name = "ARGS")]
+        args: Vec<String>,
+    },
+    /// Set up gleam-pkg and install a list of tools in one go, for container images and
+    /// devcontainer features
+    Bootstrap {
+        /// The tools to install, listed like in a .gleam-tools.toml
+        #[arg(long, value_name = "FILE")]
+        manifest: Option<PathBuf>,
+        /// Never ask anything, log every step as a record on stderr and print the code putting
+        /// the installed tools on PATH on stdout instead of offering to edit a startup file
+        #[arg(long)]
+        non_interactive: bool,
+        /// The shell to print that code for, detected from SHELL and sh by default
+        #[arg(long, value_enum, requires = "non_interactive")]
+        shell: Option<Shell>,
+        #[command(flatten)]
+        limits: LimitArgs,
+        #[command(flatten)]
+        toolchain: ToolchainArgs,
+    },
+    /// Uninstall a package, or a single version of it
+    Uninstall {
+        /// The package to uninstall, optionally with a version as `package@version`
+        package: String,
+        /// Print what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Update installed packages to their latest release
+    #[command(group(ArgGroup::new("which").required(true).args(["package", "all"])))]
+    Update {
+        /// The name of the package to update
+        package: Option<String>,
+        /// Update every installed package that is not pinned, those quickest to update first
+        #[arg(long)]
+        all: bool,
+        /// Update this many packages at once with --all, `jobs` of the configuration by default;
+        /// build output then only goes to the logs
+        #[arg(long, short, value_name = "N", requires = "all")]
+        jobs: Option<NonZeroUsize>,
+        /// Print what would be downloaded, built and removed without doing it
+        #[arg(long)]
+        dry_run: bool,
+        /// Also print what the changelogs of the updated packages say about the new versions
+        #[arg(long, conflicts_with = "dry_run")]
+        notes: bool,
+        #[command(flatten)]
+        limits: LimitArgs,
+        #[command(flatten)]
+        toolchain: ToolchainArgs,
+    },
+    /// List installed packages
+    List {
+        /// Show the dependencies each installed version was built with, as recorded from its
+        /// manifest.toml when it was installed; with --porcelain a record per dependency: the
+        /// package, its version, the depth, the dependency, its version and its source
+        #[arg(long)]
+        tree: bool,
+    },
+    /// Search hex.pm for packages, or with --offline the package names kept from it
+    Search {
+        /// What to look for; offline only names are searched, online descriptions too
+        query: String,
+    },
+    /// Show a package as published, or with --installed where its installed versions came from
+    Info {
+        /// The package as `[repo:][organization/]package`
+        package: String,
+        /// The repository to look in, one of the [repos] in config.toml
+        #[arg(long, value_name = "NAME")]
+        repo: Option<String>,
+        /// Show the installed versions, their checksums and the toolchain that built them
+        #[arg(long)]
+        installed: bool,
+    },
+    /// Show who publishes a package and how often it is downloaded
+    Owner {
+        /// The package as `[repo:][organization/]package`
+        package: String,
+        /// The repository to look in, one of the [repos] in config.toml
+        #[arg(long, value_name = "NAME")]
+        repo: Option<String>,
+    },
+    /// Check the installed versions against published vulnerabilities
+    Audit {
+        /// Also check every package in their resolved dependency trees
+        #[arg(long)]
+        deps: bool,
+    },
+    /// Tell which installed tools depend on a package, directly or through others, and on
+    /// which version of it
+    ///
+    /// Answered from the dependencies recorded when each version was installed, see
+    /// `gleam-pkg list --tree`. With --porcelain a record per installed version depending on it:
+    /// the tool, its version, the version of the package and the chain of requirements.
+    Why {
+        /// The hex package depended on, e.g. gleam_stdlib
+        package: String,
+    },
+    /// Summarize the licenses of the installed packages and check them against [licenses]
+    Licenses {
+        /// Also list every package in their resolved dependency trees
+        #[arg(long)]
+        deps: bool,
+    },
+    /// Print a software bill of materials of the installed packages and their dependencies
+    Sbom {
+        /// The document format
+        #[arg(long, value_enum, default_value = "cyclonedx")]
+        format: sbom::Format,
+        /// Write the document to this file instead of stdout
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// List installed packages that have a newer release on hex.pm
+    Outdated,
+    /// Rewrite the wrapper scripts of every installed version from the package database and the
+    /// stored builds, without downloading or rebuilding anything
+    RegenWrappers,
+    /// Run an installed package with the Erlang runtime it was built for and the environment
+    /// configured for it under [packages.<name>.env]
+    Exec {
+        /// The package to run, optionally with an installed version as `package@version`
+        package: String,
+        /// The arguments passed to the package, after `--`
+        #[arg(last = true)]
+        args: Vec<OsString>,
+    },
+    /// Pack an installed version into a tarball `gleam-pkg install --file` installs on another
+    /// machine, without a registry or a build
+    Bundle {
+        /// The package to bundle, optionally with an installed version as `package@version`
+        package: String,
+        /// Where to write the bundle, `<package>-<version>-<target>.tar.gz` by default
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+        /// Also bundle the Erlang/OTP installation the escript runs on, for machines without one
+        #[arg(long)]
+        with_erts: bool,
+        /// The machine the bundle is for, when it is not this one: a bundled Erlang/OTP
+        /// installation is downloaded for it
+        #[arg(long, value_name = "PLATFORM")]
+        target: Option<bundle::Platform>,
+    },
+    /// Choose which installed version the unversioned wrapper of a package runs
+    Default {
+        /// The name of the package
+        package: String,
+        /// The installed version to make the default
+        version: String,
+    },
+    /// Make an installed package available under another command name
+    Alias {
+        /// The name of the package
+        package: String,
+        /// The command name to add
+        name: String,
+    },
+    /// Pin a package at its installed version so `update` skips it
+    Pin {
+        /// The name of the package to pin
+        package: String,
+    },
+    /// Unpin a package so `update` upgrades it again
+    Unpin {
+        /// The name of the package to unpin
+        package: String,
+    },
+    /// List every release of a package, newest first, with the Elixir version it requires
+    ///
+    /// Hex only records the Elixir requirement of releases built with Mix; the Gleam version a
+    /// release requires is checked when it is installed, from its gleam.toml.
+    Releases {
+        /// The package as `[repo:][organization/]package`
+        package: String,
+        /// The repository to look in, one of the [repos] in config.toml
+        #[arg(long, value_name = "NAME")]
+        repo: Option<String>,
+        /// Print the releases as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the most recent build log of a package
+    Logs {
+        /// The name of the package whose log to show
         package: String,
     },
+    /// Show when packages were installed, updated and uninstalled, and which of that failed
+    History {
+        /// Only show the operations on this package
+        package: Option<String>,
+        /// Show this many of the latest operations
+        #[arg(long, short = 'n', default_value_t = 20)]
+        limit: usize,
+    },
+    /// Show where install time goes, from statistics kept on this machine only
+    Stats {
+        /// Clear the recorded statistics
+        #[arg(long)]
+        reset: bool,
+        /// Show the average time of each install phase per package instead
+        #[arg(long, conflicts_with = "reset")]
+        timings: bool,
+    },
+    /// Maintain the package database in ~/.gleam_pkgs/db/metadata.sqlite
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    /// Remove downloaded tarballs and sources by the policies of [cache.downloads] in
+    /// config.toml, or those the flags set instead
+    PruneDownloads {
+        /// Keep the downloads of this many versions of each package, the newest
+        #[arg(long, value_name = "N")]
+        keep_latest: Option<usize>,
+        /// Remove the least recently used downloads until the rest take up at most this many MiB
+        #[arg(long, value_name = "MIB")]
+        max_size: Option<u64>,
+        /// Remove the downloads not used for this many days
+        #[arg(long, value_name = "DAYS")]
+        max_age: Option<u64>,
+        /// Print what would be removed without doing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Remove downloads, sources, artifacts and escripts no installed version needs, and old logs
+    Gc {
+        /// Remove build logs older than this many days
+        #[arg(long, value_name = "DAYS", default_value_t = 30)]
+        logs_older_than: u64,
+        /// Print what would be removed without doing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Browse, install, update and uninstall packages in an interactive terminal UI
+    Ui,
+    /// Print the help of a command, or one of the guides (registries, paths, builds)
+    Help {
+        /// A command or guide name, lists the guides when omitted
+        topic: Option<String>,
+    },
+    /// Print the shell code putting installed packages on PATH, for your shell's startup file
+    ///
+    /// Add `eval "$(gleam-pkg env bash)"` to ~/.bashrc, `eval "$(gleam-pkg env zsh)"` to
+    /// ~/.zshrc or `gleam-pkg env fish | source` to config.fish. Nushell cannot evaluate it,
+    /// append the output of `gleam-pkg env nushell` to config.nu instead.
+    Env {
+        /// The shell to print the code for, detected from SHELL by default
+        #[arg(value_enum)]
+        shell: Option<Shell>,
+    },
+    /// Print the kept package names of hex.pm starting with PREFIX, for shell completion
+    #[command(hide = true)]
+    CompleteNames {
+        #[arg(default_value = "")]
+        prefix: String,
+    },
+    /// Inspect and repair the PATH lines of your shell startup files
+    Path {
+        #[command(subcommand)]
+        command: PathCommand,
+    },
+    /// Install the tools a project lists in .gleam-tools.toml into the project
+    Project {
+        #[command(subcommand)]
+        command: ProjectCommand,
+    },
+    /// Check that the tools of the current project download as .gleam-tools.lock locks them,
+    /// without building anything, and fail otherwise
+    ///
+    /// Every tool is resolved in its registry and its tarball downloaded and checked against the
+    /// published and the locked checksum. With --porcelain a record per tool is printed: the
+    /// tool, version, status, checksum and what went wrong.
+    CiVerify,
+    /// Check a Gleam project for what would break installing it with gleam-pkg once published
+    ///
+    /// gleam.toml, the target, the main module and the files `gleam publish` would put in the
+    /// tarball are checked, without building or publishing anything. With --porcelain a record
+    /// per check is printed: the check, its status and what was found.
+    CheckPublishable {
+        /// The directory of the project
+        #[arg(default_value = ".")]
+        path: PathBuf,
+        /// List the files the tarball would hold after the checks
+        #[arg(long)]
+        files: bool,
+    },
+    /// Download releases of the Gleam compiler and choose the one builds use by default
+    Toolchain {
+        #[command(subcommand)]
+        command: ToolchainCommand,
+    },
+    /// Inspect the configuration in config.toml
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Generate man pages
+    Man {
+        /// Write a page per command into this directory instead of the main page to stdout
+        #[arg(long, value_name = "DIR")]
+        out_dir: Option<PathBuf>,
+    },
+}
+
+/// Subcommands of `gleam-pkg path`
+#[derive(Subcommand)]
+enum PathCommand {
+    /// Show whether installed packages are on PATH and which startup files put them there
+    Status,
+    /// Add the `gleam-pkg env` line to a startup file, replacing stale and duplicate lines
+    Add {
+        /// The shell whose startup file to edit, detected from SHELL by default
+        #[arg(value_enum)]
+        shell: Option<Shell>,
+    },
+    /// Remove the PATH lines from every startup file
+    Remove {
+        /// Only remove stale and duplicate lines
+        #[arg(long)]
+        broken: bool,
+    },
+}
+
+/// Subcommands of `gleam-pkg db`
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Write the database in the schema of this gleam-pkg, keeping a copy of the old one
+    Migrate,
+    /// Check that every version the database records is installed, and fail otherwise
+    Check,
+    /// Print the database as this gleam-pkg reads it
+    Dump,
+}
+
+/// Subcommands of `gleam-pkg project`
+#[derive(Subcommand)]
+enum ProjectCommand {
+    /// Create a .gleam-tools.toml listing the tools of the project in the current directory
+    Init,
+    /// Install the tools listed in the nearest .gleam-tools.toml into .gleam_pkgs/ next to it
+    /// and put them on PATH with direnv
+    Install {
+        /// Install tools never installed before without asking, see [trust] in config.toml
+        #[arg(long)]
+        trust_all: bool,
+        /// Install exactly the versions and tarballs .gleam-tools.lock records, with the Gleam
+        /// compiler they were built with, and fail instead of updating it
+        #[arg(long)]
+        locked: bool,
+        #[command(flatten)]
+        limits: LimitArgs,
+        #[command(flatten)]
+        toolchain: ToolchainArgs,
+    },
+}
+
+/// Subcommands of `gleam-pkg toolchain`
+#[derive(Subcommand)]
+enum ToolchainCommand {
+    /// Download a release of the Gleam compiler into ~/.gleam_pkgs/toolchains
+    Install {
+        /// The release, e.g. 1.6.2
+        version: String,
+    },
+    /// List the downloaded releases of the Gleam compiler
+    List,
+    /// Build with this release of the Gleam compiler unless --gleam-path, --gleam-version or
+    /// config.toml select another, downloading it if needed
+    Default {
+        /// The release, e.g. 1.6.2
+        version: String,
+    },
+    /// Install a precompiled build of Erlang/OTP for wrappers and `gleam-pkg exec` to run
+    /// escripts with when no installed runtime can
+    InstallOtp {
+        /// The release, e.g. 27 for the newest 27.x, or a version like 27.1.2
+        release: String,
+    },
+}
+
+/// Subcommands of `gleam-pkg config`
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Work with the tarball repositories and their mirrors
+    Mirrors {
+        #[command(subcommand)]
+        command: MirrorsCommand,
+    },
+}
+
+/// Subcommands of `gleam-pkg config mirrors`
+#[derive(Subcommand)]
+enum MirrorsCommand {
+    /// Download the package index from every repository and mirror, and compare their speed
+    Test,
+}
+
+/// The installation a command works on: where it lives and how it is configured
+struct Context {
+    paths: Paths,
+    config: Config,
+}
+
+impl Context {
+    /// The installation at `paths`, configured by its `config.toml`
+    ///
+    /// An unreadable configuration is reported and replaced by the defaults.
+    fn load(paths: Paths) -> Self {
+        let config = Config::load(&paths.config_file()).unwrap_or_else(|e| {
+            output::warning(i18n::fill(
+                Message::DefaultConfiguration,
+                &[("error", &e.report())],
+            ));
+            Config::default()
+        });
+        Context { paths, config }
+    }
+}
+
+/// Entry point for the Gleam package manager CLI
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            output::failure(e.report());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Parses the command line and runs it, recording the statistics of the session
+fn run() -> Result<(), GleamPkgError> {
+    let args = Cli::parse();
+    // unattended bootstraps log on their own, see `bootstrap`
+    let unattended = matches!(
+        args.command,
+        Some(Commands::Bootstrap {
+            non_interactive: true,
+            ..
+        })
+    );
+    output::init(
+        args.no_color || unattended,
+        args.quiet || unattended,
+        args.porcelain,
+        args.plain,
+    );
+    runner::echo_output(!output::quiet());
+    prompt::init(args.yes || unattended);
+    // until the configuration is loaded, which may pick another language
+    let _ = i18n::init(None);
+    // e.g. GLEAM_PKG_LOG=debug logs every HTTP request
+    if let Ok(filter) = tracing_subscriber::EnvFilter::try_from_env("GLEAM_PKG_LOG") {
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(std::io::stderr)
+            .init();
+    }
+    if let Some(path) = &args.events {
+        events::subscribe_file(path)?;
+    }
+    let mut paths = Paths::home()?;
+    // commands printing help, documentation or completions are no first run
+    let first_run = !matches!(
+        args.command,
+        None | Some(
+            Commands::Help { .. }
+                | Commands::Man { .. }
+                | Commands::Env { .. }
+                | Commands::CompleteNames { .. }
+        )
+    );
+    if first_run && !args.defaults && !args.version && setup::due(&paths) && prompt::interactive() {
+        paths = setup::apply(&setup::ask(paths)?)?;
+    }
+    // flags override the configuration, which the environment overrides already
+    let ctx = Context::load(paths);
+    if let Err(locale) = i18n::init(ctx.config.locale.as_deref()) {
+        output::warning(format!(
+            "No messages in locale {}, using the language of the environment",
+            locale
+        ));
+    }
+    if !ctx.config.color {
+        output::disable_color();
+    }
+    if ctx.config.porcelain {
+        output::enable_porcelain();
+    }
+    if ctx.config.plain {
+        output::enable_plain();
+    }
+    if !ctx.config.path_prompt {
+        // as if it was offered already
+        PATH_CHECKED.store(true, Ordering::Relaxed);
+    }
+    output::init_pager(args.pager || ctx.config.pager, args.no_pager);
+    let offline = args.offline || ctx.config.offline;
+    cache::init(args.refresh, offline);
+    let ip_family = match (args.ipv4, args.ipv6) {
+        (true, _) => Some(http::IpFamily::Ipv4),
+        (_, true) => Some(http::IpFamily::Ipv6),
+        _ => None,
+    };
+    http::init(
+        args.insecure,
+        offline,
+        args.limit_rate,
+        ip_family,
+        args.resolve.clone(),
+        &ctx.config.http,
+    );
+    if http::insecure() {
+        output::warning("TLS certificate verification is disabled");
+    }
+
+    if args.version {
+        println!("Gleam Package Manager v{}", env!("CARGO_PKG_VERSION"));
+        println!("Software published under {}", env!("CARGO_PKG_LICENSE"));
+        println!("Author: {}", env!("CARGO_PKG_AUTHORS"));
+        return Ok(());
+    }
+
+    ctx.paths.create_dirs()?;
+    // commands about updates tell about them already, and completion prints nothing else
+    let notify = !matches!(
+        args.command,
+        None | Some(
+            Commands::Update { .. }
+                | Commands::Outdated
+                | Commands::Exec { .. }
+                | Commands::CompleteNames { .. }
+        )
+    );
+    let result = run_command(&ctx, args.command);
+    if plan::dry_run() {
+        return result;
+    }
+    if let Err(e) = stats::flush(&ctx.paths.db_file()) {
+        output::warning(format!("failed to record statistics: {}", e.report()));
+    }
+    if let Err(e) = history::flush(&ctx.paths.db_file()) {
+        output::warning(format!("failed to record the history: {}", e.report()));
+    }
+    if notify && result.is_ok() && updatecheck::due(&ctx.config.updates, &ctx.paths) {
+        notify_updates(&ctx);
+    }
+    result
+}
+
+/// Runs the selected subcommand
+///
+/// # Arguments
+///
+/// * `ctx` - The installation to work on
+/// * `command` - The subcommand to run
+///
+/// # Errors
+///
+/// Returns whatever error the subcommand fails with
+///
+fn run_command(ctx: &Context, command: Option<Commands>) -> Result<(), GleamPkgError> {
+    match command {
+        Some(Commands::Install {
+            package,
+            file,
+            repo,
+            target,
+            force,
+            alias,
+            overwrite,
+            trust_all,
+            latest,
+            dry_run,
+            bundle_erts,
+            wrapper,
+            rebuild,
+            accept_new_checksum,
+            explain,
+            limits,
+            toolchain,
+        }) => {
+            plan::init(dry_run);
+            let opts = InstallOptions {
+                target,
+                force,
+                overwrite,
+                renamed: alias.is_some(),
+                trust_all,
+                latest,
+                limits: limits.limits(&ctx.config),
+                checksum: None,
+                bundle_erts,
+                wrapper,
+                rebuild,
+                accept_new_checksum,
+                explain,
+            };
+            if let Some(file) = file {
+                let started = SystemTime::now();
+                let result = install_bundle(ctx, &file, &opts);
+                let package = result.as_deref().ok();
+                let to = package.and_then(|package| default_version(ctx, package));
+                let error = result.as_ref().err();
+                history::record(
+                    history::Kind::Install,
+                    package,
+                    None,
+                    to.as_deref(),
+                    started,
+                    error,
+                );
+                let package = result?;
+                if let Some(alias) = alias {
+                    add_alias(ctx, &package, &alias)?;
+                }
+                return Ok(());
+            }
+            let spec = PackageSpec::parse(&package.unwrap_or_default(), repo.as_deref())?;
+            if !dry_run {
+                toolchain.install(ctx)?;
+                // Erlang and Elixir packages are built without gleam, but only --target or the
+                // backend configured for the package tells them apart before the download
+                let backend = opts
+                    .with_package_defaults(&ctx.config, &spec.name)
+                    .target
+                    .map(Target::backend);
+                if backend.as_ref().is_none_or(|b| b.build_tool() == "gleam") {
+                    let version = toolchain::check_gleam(&opts.limits)?;
+                    output::info(i18n::fill(Message::UsingGleam, &[("version", &version)]));
+                }
+                match backend {
+                    Some(backend) => {
+                        output::info(format!("Using {}", backend.check_runtime(&opts.limits)?));
+                    }
+                    None => backend::check_any_runtime(&opts.limits)?,
+                }
+            }
+            tracked(ctx, history::Kind::Install, &spec.name, || {
+                install_package(ctx, &spec, &opts)
+            })?;
+            if let Some(alias) = alias {
+                if dry_run {
+                    println!("Would also link {} as {}", spec.name, alias);
+                } else {
+                    add_alias(ctx, &spec.name, &alias)?;
+                }
+            }
+            prune_after_install(ctx);
+        }
+        Some(Commands::Ensure {
+            package,
+            repo,
+            target,
+            limits,
+            toolchain,
+        }) => {
+            // provisioning runs unattended, every question takes its default
+            prompt::init(true);
+            let spec = PackageSpec::parse(&package, repo.as_deref())?;
+            let db = Database::load(&ctx.paths.db_file())?;
+            if let Some(version) = ensured(ctx, &db, &spec) {
+                output::info(format!("{} {} is installed", spec.name, version));
+                return Ok(());
+            }
+            toolchain.install(ctx)?;
+            let opts = InstallOptions {
+                target,
+                force: false,
+                overwrite: false,
+                renamed: false,
+                trust_all: false,
+                latest: false,
+                limits: limits.limits(&ctx.config),
+                checksum: None,
+                bundle_erts: false,
+                wrapper: None,
+                rebuild: false,
+                accept_new_checksum: false,
+                explain: false,
+            };
+            tracked(ctx, history::Kind::Install, &spec.name, || {
+                install_package(ctx, &spec, &opts)
+            })?;
+            prune_after_install(ctx);
+        }
+        Some(Commands::TestInstall {
+            package,
+            repo,
+            target,
+            keep,
+            limits,
+            toolchain,
+        }) => {
+            let spec = PackageSpec::parse(&package, repo.as_deref())?;
+            toolchain.install(ctx)?;
+            let opts = InstallOptions {
+                target,
+                force: false,
+                overwrite: false,
+                renamed: false,
+                trust_all: true,
+                latest: true,
+                limits: limits.limits(&ctx.config),
+                checksum: None,
+                bundle_erts: false,
+                wrapper: None,
+                rebuild: true,
+                accept_new_checksum: false,
+                explain: false,
+            };
+            test_install(ctx, &spec, &opts, keep)?;
+        }
+        Some(Commands::Publish {
+            repo,
+            target,
+            no_check,
+            limits,
+            toolchain,
+            args,
+        }) => {
+            toolchain.install(ctx)?;
+            let opts = InstallOptions {
+                target,
+                force: false,
+                overwrite: false,
+                renamed: false,
+                trust_all: true,
+                latest: true,
+                limits: limits.limits(&ctx.config),
+                checksum: None,
+                bundle_erts: false,
+                wrapper: None,
+                rebuild: false,
+                accept_new_checksum: false,
+                explain: false,
+            };
+            let cwd = std::env::current_dir().map_err(|source| GleamPkgError::Io {
+                action: "read the current directory",
+                path: PathBuf::from("."),
+                source,
+            })?;
+            publish(ctx, &cwd, &args, repo.as_deref(), !no_check, &opts)?;
+            prune_after_install(ctx);
+        }
+        Some(Commands::Bootstrap {
+            manifest,
+            non_interactive,
+            shell,
+            limits,
+            toolchain,
+        }) => bootstrap(
+            ctx,
+            manifest.as_deref(),
+            non_interactive
+                .then(|| shell.unwrap_or_else(|| Shell::detect().unwrap_or(Shell::Bash))),
+            &limits.limits(&ctx.config),
+            &toolchain,
+        )?,
+        Some(Commands::Uninstall { package, dry_run }) => {
+            plan::init(dry_run);
+            let (package, version) = match package.split_once('@') {
+                Some((package, version)) => (package, Some(version)),
+                None => (package.as_str(), None),
+            };
+            tracked(ctx, history::Kind::Uninstall, package, || {
+                uninstall_package(ctx, package, version)
+            })?;
+        }
+        Some(Commands::Update {
+            package,
+            all: _,
+            jobs,
+            dry_run,
+            notes,
+            limits,
+            toolchain,
+        }) => {
+            plan::init(dry_run);
+            let limits = limits.limits(&ctx.config);
+            if !dry_run {
+                toolchain.install(ctx)?;
+                let version = toolchain::check_gleam(&limits)?;
+                output::info(i18n::fill(Message::UsingGleam, &[("version", &version)]));
+            }
+            let jobs = jobs.unwrap_or(ctx.config.jobs);
+            // failed updates leave the downloads of the others to prune
+            let result = update_packages(ctx, package.as_deref(), &limits, jobs, notes);
+            prune_after_install(ctx);
+            result?;
+        }
+        Some(Commands::Search { query }) => search(ctx, &query)?,
+        Some(Commands::List { tree }) => {
+            let db = Database::load(&ctx.paths.db_file())?;
+            let _pager = output::pager();
+            if tree {
+                print_dependency_trees(&db);
+                return Ok(());
+            }
+            if output::porcelain() {
+                for (name, installed) in &db.packages {
+                    for version in installed.versions.keys() {
+                        let wrapper = ctx.paths.apps().join(format!("{}-{}", name, version));
+                        output::record(&[
+                            name,
+                            version,
+                            &installed.source.name(),
+                            &wrapper.to_string_lossy(),
+                        ]);
+                    }
+                }
+                return Ok(());
+            }
+            if db.packages.is_empty() {
+                output::info(i18n::text(Message::NoPackagesInstalled));
+                return Ok(());
+            }
+            let mut table =
+                output::Table::new(&["PACKAGE", "VERSION", "BINARY", "TARGET", "FLAGS"]);
+            for (name, installed) in &db.packages {
+                for (version, installed_version) in &installed.versions {
+                    let is_default = *version == installed.default_version;
+                    let mut flags = Vec::new();
+                    if is_default {
+                        flags.push("default".to_string());
+                        if installed.pinned {
+                            flags.push("pinned".to_string());
+                        }
+                        if !installed.aliases.is_empty() {
+                            let aliases = installed.aliases.iter().cloned().collect::<Vec<_>>();
+                            flags.push(format!("as {}", aliases.join(",")));
+                        }
+                    }
+                    table.styled_row(vec![
+                        (name.clone(), None),
+                        (version.clone(), is_default.then_some(output::Style::Green)),
+                        (installed_version.command(name).to_string(), None),
+                        (describe_target(installed_version), None),
+                        (flags.join(" "), Some(output::Style::Dim)),
+                    ]);
+                }
+            }
+            table.print();
+        }
+        Some(Commands::Info {
+            package,
+            repo,
+            installed,
+        }) => {
+            let spec = PackageSpec::parse(&package, repo.as_deref())?;
+            if installed {
+                print_installed_info(ctx, &spec.name)?;
+            } else {
+                print_info(ctx, &spec)?;
+            }
+        }
+        Some(Commands::Owner { package, repo }) => {
+            let spec = PackageSpec::parse(&package, repo.as_deref())?;
+            print_owner(ctx, &spec)?;
+        }
+        Some(Commands::Audit { deps }) => audit_packages(ctx, deps)?,
+        Some(Commands::Why { package }) => why_depends(ctx, &package)?,
+        Some(Commands::Licenses { deps }) => print_licenses(ctx, deps)?,
+        Some(Commands::Sbom { format, output }) => {
+            let db = Database::load(&ctx.paths.db_file())?;
+            let entries = sbom::inventory(&ctx.config, &ctx.paths, &db)?;
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            let json = serde_json::to_string_pretty(&sbom::document(format, &entries, now))
+                .map_err(std::io::Error::other)?;
+            match output {
+                Some(path) => {
+                    fs::write(&path, json + "\n").map_err(|source| GleamPkgError::Io {
+                        action: "write sbom",
+                        path: path.clone(),
+                        source,
+                    })?;
+                    output::success(format!("SBOM written to {}", path.display()));
+                }
+                None => println!("{}", json),
+            }
+        }
+        Some(Commands::Outdated) => print_outdated(ctx)?,
+        Some(Commands::RegenWrappers) => regen_wrappers(ctx)?,
+        Some(Commands::Exec { package, args }) => {
+            let (package, version) = match package.split_once('@') {
+                Some((package, version)) => (package, Some(version)),
+                None => (package.as_str(), None),
+            };
+            exec_package(ctx, package, version, &args)?;
+        }
+        Some(Commands::Bundle {
+            package,
+            output,
+            with_erts,
+            target,
+        }) => {
+            let (package, version) = match package.split_once('@') {
+                Some((package, version)) => (package, Some(version)),
+                None => (package.as_str(), None),
+            };
+            bundle_package(ctx, package, version, output, with_erts, target)?;
+        }
+        Some(Commands::Default { package, version }) => set_default(ctx, &package, &version)?,
+        Some(Commands::Alias { package, name }) => add_alias(ctx, &package, &name)?,
+        Some(Commands::Pin { package }) => set_pinned(ctx, &package, true)?,
+        Some(Commands::Unpin { package }) => set_pinned(ctx, &package, false)?,
+        Some(Commands::Releases {
+            package,
+            repo,
+            json,
+        }) => {
+            let PackageSpec {
+                source,
+                name: package,
+                ..
+            } = PackageSpec::parse(&package, repo.as_deref())?;
+            let metadata = fetch_api(ctx, &source, &format!("packages/{}", package), &package)?;
+            let mut infos = metadata["releases"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|release| release["version"].as_str())
+                .map(|version| {
+                    let detail = fetch_release(ctx, &source, &package, version)?;
+                    Ok(releases::ReleaseInfo::new(version, &detail))
+                })
+                .collect::<Result<Vec<_>, GleamPkgError>>()?;
+            releases::sort_descending(&mut infos);
+            let _pager = output::pager();
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&infos).map_err(std::io::Error::other)?
+                );
+            } else {
+                releases::print_table(&infos);
+            }
+        }
+        Some(Commands::Logs { package }) => {
+            match buildlog::latest_log(&ctx.paths.logs(), &package)? {
+                Some(log) => {
+                    println!("Build log: {}\n", log.display());
+                    print!("{}", fs::read_to_string(&log)?);
+                }
+                None => println!("No build logs found for package: {}", package),
+            }
+        }
+        Some(Commands::History { package, limit }) => {
+            let db = Database::load(&ctx.paths.db_file())?;
+            history::print(&db.history, package.as_deref(), limit);
+        }
+        Some(Commands::Stats { reset, timings }) => {
+            let db_path = ctx.paths.db_file();
+            let _lock = db::lock(&db_path);
+            let mut db = Database::load(&db_path)?;
+            if reset {
+                db.stats = Default::default();
+                db.save(&db_path)?;
+                println!("Statistics cleared");
+            } else if timings {
+                db.stats.print_timings();
+            } else {
+                db.stats.print();
+            }
+        }
+        Some(Commands::Db { command }) => db_command(ctx, command)?,
+        Some(Commands::PruneDownloads {
+            keep_latest,
+            max_size,
+            max_age,
+            dry_run,
+        }) => {
+            let configured = downloads::Policy::new(&ctx.config.cache.downloads);
+            let policy = match (keep_latest, max_size, max_age) {
+                (None, None, None) => configured,
+                _ => downloads::Policy {
+                    keep_latest,
+                    max_size: max_size.map(|mib| mib * 1024 * 1024),
+                    max_age: max_age.map(downloads::days),
+                },
+            };
+            prune_downloads(ctx, &policy, dry_run)?
+        }
+        Some(Commands::Gc {
+            logs_older_than,
+            dry_run,
+        }) => {
+            let started = SystemTime::now();
+            let max_log_age = Duration::from_secs(logs_older_than * 24 * 60 * 60);
+            let result = collect_garbage(ctx, max_log_age, dry_run);
+            if !dry_run {
+                let error = result.as_ref().err();
+                history::record(history::Kind::Gc, None, None, None, started, error);
+            }
+            result?
+        }
+        Some(Commands::Ui) => ui::run(ctx)?,
+        Some(Commands::Help { topic }) => help::print_help(Cli::command(), topic.as_deref())?,
+        Some(Commands::Env { shell }) => {
+            let shell = match shell {
+                Some(shell) => shell,
+                None => Shell::detect()?,
+            };
+            print!("{}", shell.env_script(&ctx.paths.apps()));
+        }
+        Some(Commands::CompleteNames { prefix }) => {
+            // never refreshed here, a keystroke does not wait for the network
+            let names = names::NamesIndex::load(&names_file(ctx, &Source::default()));
+            for name in names.iter().flat_map(|names| names.complete(&prefix)) {
+                println!("{}", name);
+            }
+        }
+        Some(Commands::Path { command }) => path_command(ctx, command)?,
+        Some(Commands::Project { command }) => project_command(ctx, command)?,
+        Some(Commands::CiVerify) => {
+            let cwd = std::env::current_dir().map_err(|source| GleamPkgError::Io {
+                action: "read the current directory",
+                path: PathBuf::from("."),
+                source,
+            })?;
+            ci_verify(ctx, &project::find(&cwd)?)?
+        }
+        Some(Commands::CheckPublishable { path, files }) => check_publishable(&path, files)?,
+        Some(Commands::Toolchain { command }) => toolchain_command(ctx, command)?,
+        Some(Commands::Config {
+            command:
+                ConfigCommand::Mirrors {
+                    command: MirrorsCommand::Test,
+                },
+        }) => test_mirrors(ctx)?,
+        Some(Commands::Man { out_dir }) => {
+            help::write_man_pages(Cli::command(), out_dir.as_deref())?
+        }
+        None => {
+            println!("No subcommand provided. Use `gleam-pkg --help` for usage information.");
+        }
+    }
+    Ok(())
+}
+
+/// Options controlling how a package is installed
+#[derive(Clone)]
+struct InstallOptions {
+    /// The backend to build with, or `None` to use the one the package declares
+    target: Option<Target>,
+    /// Whether a pinned package may be replaced by a different version
+    force: bool,
+    /// Whether command names used by other packages or unmanaged files may be taken over
+    overwrite: bool,
+    /// Whether the package gets an alias, so a taken command name it exposes can be skipped
+    renamed: bool,
+    /// Whether packages never installed before are installed without confirmation
+    trust_all: bool,
+    /// Whether the latest release is installed without offering others, see [`pick_release`]
+    latest: bool,
+    /// Resource limits for the build processes
+    limits: BuildLimits,
+    /// The checksum the tarball must have besides the published one, locked by
+    /// `.gleam-tools.lock`
+    checksum: Option<String>,
+    /// Whether an escript gets the Erlang/OTP installation it runs on embedded, see
+    /// [`build_package`]
+    bundle_erts: bool,
+    /// How the wrapper runs an escript, `wrapper` in config.toml if `None`
+    wrapper: Option<WrapperMode>,
+    /// Whether an escript built before is built again, see [`buildcache`]
+    rebuild: bool,
+    /// Whether a release installed before is installed even if the registry now publishes
+    /// another tarball for it, see [`check_republished`]
+    accept_new_checksum: bool,
+    /// Whether the decisions taken are printed and confirmed first, see [`explain_install`]
+    explain: bool,
+}
+
+impl InstallOptions {
+    /// These options with the defaults configured for `package` under [packages] in
+    /// config.toml, where no flag overrides them
+    fn with_package_defaults(&self, config: &Config, package: &str) -> InstallOptions {
+        let Some(defaults) = config.packages.get(package) else {
+            return self.clone();
+        };
+        InstallOptions {
+            target: self.target.or(defaults.backend),
+            wrapper: self.wrapper.or(defaults.wrapper),
+            ..self.clone()
+        }
+    }
+}
+
+/// Installs a release of a Gleam package, the latest one unless a version is requested
+///
+/// # Arguments
+///
+/// * `ctx` - The installation to work on
+/// * `spec` - The package to install, with the version to install or `None` for the latest
+///   release
+/// * `opts` - Options controlling the installation
+///
+/// # Errors
+///
+/// Returns `GleamPkgError` if the installation fails
+///
+fn install_package(
+    ctx: &Context,
+    spec: &PackageSpec,
+    opts: &InstallOptions,
+) -> Result<(), GleamPkgError> {
+    stats::begin_install();
+    let metadata = fetch_metadata(ctx, &spec.source, &spec.name)?;
+    let requirement = ctx.config.package_version(&spec.name);
+    let picked = match (&spec.version, requirement) {
+        (Some(version), _) => find_release(&metadata, version)?,
+        (None, Some(requirement)) => configured_release(&metadata, requirement)?,
+        (None, None) if opts.latest => newest_version(&metadata)?,
+        (None, None) => pick_release(&metadata)?,
+    };
+    let configured = opts.with_package_defaults(&ctx.config, &spec.name);
+    let version = match (&spec.version, requirement) {
+        // a requested or configured version is installed or refused, see
+        // `check_gleam_requirement`
+        (Some(_), _) | (_, Some(_)) => picked.clone(),
+        (None, None) => {
+            compatible_release(ctx, &spec.source, &metadata, picked.clone(), &configured)?
+        }
+    };
+    if opts.explain {
+        let why = version_reason(ctx, spec, &metadata, &picked, &version, opts);
+        explain_install(ctx, &spec.source, &spec.name, &version, why, opts)?;
+    }
+    install_release(ctx, &spec.source, &spec.name, &version, &configured)
 }
 
-const ROOT_DIR: &str = ".gleam_pkgs";
-const DOWNLOAD_DIR: &str = "download";
-const APPS_DIR: &str = "apps";
-const DB_DIR: &str = "db";
-const _DB_FILE: &str = "db/metadata.json";
+/// The newest release of the package `metadata` describes meeting `requirement`, the version
+/// configured for it under [packages] in config.toml, preferring releases not retired
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::InvalidVersionRequirement` if `requirement` cannot be parsed, or
+/// `GleamPkgError::NoConfiguredRelease` if no release meets it
+fn configured_release(
+    metadata: &serde_json::Value,
+    requirement: &str,
+) -> Result<String, GleamPkgError> {
+    let package = package_name(metadata);
+    let mut meeting = Vec::new();
+    for version in releases::versions(metadata) {
+        match releases::satisfies(requirement, &version) {
+            Some(true) => meeting.push(version),
+            Some(false) => {}
+            None => {
+                return Err(GleamPkgError::InvalidVersionRequirement {
+                    package,
+                    requirement: requirement.to_string(),
+                });
+            }
+        }
+    }
+    meeting
+        .iter()
+        .find(|version| !releases::retired(metadata, version))
+        .or(meeting.first())
+        .map(semver::Version::to_string)
+        .ok_or_else(|| GleamPkgError::NoConfiguredRelease {
+            package,
+            requirement: requirement.to_string(),
+        })
+}
+
+/// Why `version` of the package `spec` names is installed, for [`explain_install`]
+///
+/// # Arguments
+///
+/// * `ctx` - The installation to work on
+/// * `spec` - The package to install, with the version requested if any
+/// * `metadata` - The metadata of the package
+/// * `picked` - The release picked, see [`find_release`], [`newest_version`] and
+///   [`pick_release`]
+/// * `version` - The release installed, `picked` unless [`compatible_release`] fell back from it
+/// * `opts` - Options controlling the installation
+fn version_reason(
+    ctx: &Context,
+    spec: &PackageSpec,
+    metadata: &serde_json::Value,
+    picked: &str,
+    version: &str,
+    opts: &InstallOptions,
+) -> String {
+    if picked != version {
+        return format!(
+            "{} requires a newer gleam than the installed one, {} is the newest release that \
+             builds with it",
+            picked, version
+        );
+    }
+    match &spec.version {
+        Some(requested) if requested == version => {
+            format!("requested as {}@{}", spec.name, requested)
+        }
+        Some(requested) => format!(
+            "the release matching {}@{}, a version is a prefix of the releases it matches",
+            spec.name, requested
+        ),
+        None => match ctx.config.package_version(&spec.name) {
+            Some(requirement) => format!(
+                "the newest release meeting {}, the version under [packages.{}] in config.toml",
+                requirement, spec.name
+            ),
+            None if opts.latest => {
+                "--latest installs the newest release, pre-releases included".to_string()
+            }
+            None if releases::candidates(metadata).is_empty() => {
+                "the newest stable release that is not retired".to_string()
+            }
+            None => "picked from the releases offered, as a newer pre-release or several \
+                     major versions are published"
+                .to_string(),
+        },
+    }
+}
+
+/// Prints the decisions behind installing `version` of `package` and asks to proceed, see
+/// [`explain`]
+///
+/// # Arguments
+///
+/// * `ctx` - The installation to work on
+/// * `source` - Where the package is fetched from
+/// * `package` - The name of the package
+/// * `version` - The release to install
+/// * `why` - Why that release is installed, see [`version_reason`]
+/// * `opts` - Options controlling the installation
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::InstallDeclined` if the installation is not to proceed, or the
+/// errors of [`registry::open`] and [`prompt::confirm`]
+fn explain_install(
+    ctx: &Context,
+    source: &Source,
+    package: &str,
+    version: &str,
+    why: String,
+    opts: &InstallOptions,
+) -> Result<(), GleamPkgError> {
+    let registry = registry::open(&ctx.config, source)?;
+    let mut explanation = explain::Explanation::new(&source.qualify(package), version);
+    explanation.step("version", version, why);
+    let db = Database::load(&ctx.paths.db_file())?;
+    if let Some(installed) = db.packages.get(package) {
+        let current = &installed.default_version;
+        let why = match (installed.pinned, current == version) {
+            (_, true) => "installed again over it".to_string(),
+            (true, false) if opts.force => format!("pinned, --force replaces it with {}", version),
+            (true, false) => "pinned, only --force replaces it".to_string(),
+            (false, false) => format!("{} becomes the default version", version),
+        };
+        explanation.step("installed", current.as_str(), why);
+    }
+    explanation.step(
+        "metadata",
+        registry.api_url(&format!("packages/{}", package)),
+        format!("where the releases of {} are listed", package),
+    );
+    for (i, repository) in registry.repositories().iter().enumerate() {
+        let why = match i {
+            0 => format!("the repository of {}", source.name()),
+            _ => "a mirror, tried when the repositories before it fail".to_string(),
+        };
+        explanation.step(
+            "tarball",
+            registry::tarball_url(repository, package, version),
+            why,
+        );
+    }
+    let extract_dir = ctx
+        .paths
+        .download()
+        .join(format!("{}-{}", package, version));
+    let defaults = ctx.config.packages.get(package);
+    let chosen = opts
+        .target
+        .map(|target| (target, "chosen with --target"))
+        .or_else(|| {
+            let target = defaults?.backend?;
+            Some((target, "backend under [packages] in config.toml"))
+        });
+    let (backend, why) = match chosen {
+        Some((target, why)) => (target.backend().name().to_string(), why),
+        None => match Target::detect(&extract_dir, package, version) {
+            Ok(target) => (
+                target.backend().name().to_string(),
+                "detected from the sources downloaded before",
+            ),
+            Err(_) => (
+                "detected after the download".to_string(),
+                "from the build tools the tarball declares, and the target in its gleam.toml",
+            ),
+        },
+    };
+    explanation.step("backend", backend, why);
+    let configured = defaults.and_then(|defaults| defaults.wrapper);
+    let (mode, chosen) = match (opts.wrapper, configured) {
+        (Some(mode), _) => (mode, Some("--wrapper")),
+        (None, Some(mode)) => (mode, Some("wrapper under [packages] in config.toml")),
+        (None, None) if ctx.config.wrapper != WrapperMode::default() => {
+            (ctx.config.wrapper, Some("wrapper in config.toml"))
+        }
+        (None, None) => (WrapperMode::default(), None),
+    };
+    explanation.wrapper(mode, chosen);
+    explanation.print();
+    if plan::dry_run() || prompt::confirm(i18n::text(Message::ProceedWithInstall), true)? {
+        return Ok(());
+    }
+    Err(GleamPkgError::InstallDeclined {
+        package: package.to_string(),
+    })
+}
+
+/// The default version of the package `spec` names if it meets the version `spec` asks for, see
+/// [`find_release`], and its wrapper is in place; `None` if `gleam-pkg ensure` has to install it
+fn ensured<'a>(ctx: &Context, db: &'a Database, spec: &PackageSpec) -> Option<&'a str> {
+    let installed = db
+        .packages
+        .get(&spec.name)
+        .filter(|installed| installed.source == spec.source)?;
+    let version = installed.default_version.as_str();
+    let matches = spec.version.as_deref().is_none_or(|requested| {
+        version == requested || version.starts_with(&format!("{}.", requested))
+    });
+    let wrapper = ctx.paths.apps().join(&spec.name);
+    (matches && wrapper.exists()).then_some(version)
+}
+
+/// Sets up the installation and installs the tools listed in `manifest` into it
+///
+/// Tools whose installed default version the list accepts are kept. Run unattended, the
+/// directories, every tool and the apps directory are logged as records on stderr, see
+/// [`output::log`]:
+///
+/// ```text
+/// root       /root/.gleam_pkgs
+/// present    wisp   1.2.0
+/// installed  hello  1.0.0
+/// path       /root/.gleam_pkgs/apps
+/// ```
+///
+/// and the code putting the apps directory on `PATH` is printed on stdout, ready for a file in
+/// `/etc/profile.d`, instead of offering to add it to a startup file.
+///
+/// # Arguments
+///
+/// * `ctx` - The installation to set up
+/// * `manifest` - The file listing the tools in the format of `.gleam-tools.toml`, if any
+/// * `unattended` - The shell to print the `PATH` code for when nobody can be asked, `None` to
+///   ask instead
+/// * `limits` - The limits of the builds
+/// * `toolchain` - The toolchain options, installed before the first tool that needs it
+///
+/// # Errors
+///
+/// Returns the errors of [`project::ToolsManifest::read`], [`install_package`] and
+/// [`path_check`]; the tools installed before one fails are kept
+fn bootstrap(
+    ctx: &Context,
+    manifest: Option<&Path>,
+    unattended: Option<Shell>,
+    limits: &BuildLimits,
+    toolchain: &ToolchainArgs,
+) -> Result<(), GleamPkgError> {
+    let log = |fields: &[&str]| {
+        if unattended.is_some() {
+            output::log(fields);
+        }
+    };
+    if unattended.is_some() {
+        // printed at the end instead
+        PATH_CHECKED.store(true, Ordering::Relaxed);
+    }
+    // `run` created the directories
+    log(&["root", &ctx.paths.root().display().to_string()]);
+    let manifest = match manifest {
+        Some(path) => project::ToolsManifest::read(path)?,
+        None => project::ToolsManifest::default(),
+    };
+
+    let db = Database::load(&ctx.paths.db_file())?;
+    let mut toolchain_ready = false;
+    for (name, tool) in &manifest.tools {
+        let spec = PackageSpec::parse(&format!("{}@{}", name, tool.version()), None)?;
+        let current = db
+            .packages
+            .get(&spec.name)
+            .filter(|package| package.source == spec.source)
+            .and_then(|package| {
+                let (version, installed) = package.default_entry();
+                tool.accepts(version, installed.target).then_some(version)
+            })
+            .filter(|_| ctx.paths.apps().join(&spec.name).exists());
+        if let Some(version) = current {
+            output::info(i18n::fill(
+                Message::AlreadyInstalled,
+                &[("package", &spec.name), ("version", &version)],
+            ));
+            log(&["present", &spec.name, version]);
+            continue;
+        }
+        if !toolchain_ready {
+            toolchain.install(ctx)?;
+            toolchain_ready = true;
+        }
+        let opts = InstallOptions {
+            target: tool.target(),
+            force: false,
+            overwrite: false,
+            renamed: false,
+            trust_all: false,
+            latest: false,
+            limits: limits.clone(),
+            checksum: None,
+            bundle_erts: false,
+            wrapper: None,
+            rebuild: false,
+            accept_new_checksum: false,
+            explain: false,
+        };
+        tracked(ctx, history::Kind::Install, &spec.name, || {
+            install_package(ctx, &spec, &opts)
+        })?;
+        let db = Database::load(&ctx.paths.db_file())?;
+        let version = db
+            .packages
+            .get(&spec.name)
+            .map_or("", |package| package.default_version.as_str());
+        log(&["installed", &spec.name, version]);
+    }
 
-/// Configuration for the Gleam package manager
-struct Config {
-    api_base: String,
-    repository_base: String,
+    let apps_dir = ctx.paths.apps();
+    match unattended {
+        Some(shell) => {
+            log(&["path", &apps_dir.display().to_string()]);
+            print!("{}", shell.env_script(&apps_dir));
+            Ok(())
+        }
+        None => path_check(&ctx.paths),
+    }
 }
 
-impl Config {
-    fn new() -> Self {
-        Config {
-            api_base: "https://hex.pm/api/".to_string(),
-            repository_base: "https://repo.hex.pm/".to_string(),
+/// Picks the release to install when no version is requested
+///
+/// That is the latest release, unless it is a pre-release or several major versions are
+/// published: then the user picks one of the [`releases::candidates`], the newest stable release
+/// that is not retired by default.
+///
+/// # Errors
+///
+/// Returns the errors of [`extract_version`] if there is nothing to pick from, or
+/// `GleamPkgError::Io` if the question cannot be asked
+fn pick_release(metadata: &serde_json::Value) -> Result<String, GleamPkgError> {
+    let candidates = releases::candidates(metadata);
+    if candidates.is_empty() {
+        return extract_version(metadata);
+    }
+    let question = format!(
+        "Which release of {} should be installed? Pass --latest or <package>@<version> not to \
+         be asked",
+        package_name(metadata)
+    );
+    let picked = prompt::select(
+        &question,
+        &releases::describe_candidates(&candidates),
+        releases::default_candidate(&candidates),
+    )?;
+    Ok(candidates[picked].version.clone())
+}
+
+/// Falls back from a release requiring a newer Gleam compiler than the installed one to the
+/// newest older release that builds with it, see [`releases::older_releases`]
+///
+/// The requirement is the `gleam` one hex records in the `meta` of a release. Releases recording
+/// none, or whose metadata cannot be fetched, are taken to build with any Gleam; the
+/// `gleam.toml` of the release installed is checked again before it is built, see
+/// [`check_gleam_requirement`].
+///
+/// # Arguments
+///
+/// * `ctx` - The installation to work on
+/// * `source` - Where the package is fetched from
+/// * `metadata` - The metadata of the package
+/// * `version` - The release picked to install
+/// * `opts` - Options controlling the installation
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::GleamTooOld` if no release builds with the installed Gleam
+///
+/// # Returns
+///
+/// `version`, or the release to install instead
+fn compatible_release(
+    ctx: &Context,
+    source: &Source,
+    metadata: &serde_json::Value,
+    version: String,
+    opts: &InstallOptions,
+) -> Result<String, GleamPkgError> {
+    if opts
+        .target
+        .is_some_and(|target| target.backend().build_tool() != "gleam")
+    {
+        return Ok(version);
+    }
+    // a missing compiler is reported by the build
+    let Ok(gleam) = toolchain::check_gleam(&opts.limits) else {
+        return Ok(version);
+    };
+    let package = package_name(metadata);
+    let requirement = |version: &str| {
+        let detail = fetch_release(ctx, source, &package, version).ok()?;
+        detail["meta"]["gleam"].as_str().map(String::from)
+    };
+    let Some(required) = requirement(&version) else {
+        return Ok(version);
+    };
+    if releases::satisfies(&required, &gleam) != Some(false) {
+        return Ok(version);
+    }
+    for older in releases::older_releases(metadata, &version) {
+        let builds = requirement(&older)
+            .is_none_or(|required| releases::satisfies(&required, &gleam) != Some(false));
+        if builds {
+            output::warning(format!(
+                "{} {} requires gleam {}, found {}; installing {}, the newest release that \
+                 builds with it",
+                package, version, required, gleam, older
+            ));
+            return Ok(older);
+        }
+    }
+    Err(GleamPkgError::GleamTooOld {
+        package,
+        version,
+        required,
+        found: gleam.to_string(),
+    })
+}
+
+/// Checks that the installed Gleam compiler meets the `gleam` requirement in the `gleam.toml`
+/// of a package extracted to `extract_dir`, rather than failing in the middle of its build
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::GleamTooOld` if it does not
+fn check_gleam_requirement(
+    extract_dir: &Path,
+    package: &str,
+    version: &str,
+    limits: &BuildLimits,
+) -> Result<(), GleamPkgError> {
+    let Some(required) = backend::gleam_requirement(extract_dir) else {
+        return Ok(());
+    };
+    // a missing compiler is reported by the build
+    let Ok(gleam) = toolchain::check_gleam(limits) else {
+        return Ok(());
+    };
+    match releases::satisfies(&required, &gleam) {
+        Some(false) => Err(GleamPkgError::GleamTooOld {
+            package: package.to_string(),
+            version: version.to_string(),
+            required,
+            found: gleam.to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Installs a specific release of a Gleam package and records it in the database
+///
+/// The release is installed next to any other installed versions of the package and becomes
+/// the default one.
+///
+/// # Arguments
+///
+/// * `ctx` - The installation to work on
+/// * `source` - Where to fetch the package from
+/// * `package` - The name of the package to install
+/// * `version` - The version to install
+/// * `opts` - Options controlling the installation
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::PackagePinned` if the package is pinned at another version and
+/// `opts.force` is not set, `GleamPkgError::CommandConflict` if a command it provides is taken
+/// and `opts.overwrite` is not set, or another `GleamPkgError` if the installation fails
+///
+fn install_release(
+    ctx: &Context,
+    source: &Source,
+    package: &str,
+    version: &str,
+    opts: &InstallOptions,
+) -> Result<(), GleamPkgError> {
+    let download_dir = ctx.paths.download();
+    let db_path = ctx.paths.db_file();
+
+    let db = Database::load(&db_path)?;
+    check_names(&db, package, version)?;
+    if let Some(installed) = db.packages.get(package) {
+        if installed.pinned && installed.default_version != version && !opts.force {
+            return Err(GleamPkgError::PackagePinned {
+                package: package.to_string(),
+                pinned: installed.default_version.clone(),
+                requested: version.to_string(),
+            });
+        }
+    }
+    let link_wrapper = check_command(ctx, &db, package, package, opts)?;
+
+    if plan::dry_run() {
+        plan_install(ctx, source, package, version, opts)?.print();
+        return Ok(());
+    }
+    // pruning the download cache, in another process too, leaves the downloads alone until the
+    // version is recorded
+    let _in_use = downloads::in_use(&ctx.paths, package, version);
+
+    let republished = check_republished(ctx, &db, source, package, version, opts)?;
+    if ctx.config.trust.confirm_new && !opts.trust_all {
+        confirm_new_package(ctx, source, package)?;
+    }
+    if ctx.config.licenses.is_active() {
+        let checked = check_license(ctx, source, package, version)?;
+        enforce_license_policy(ctx, &checked, None)?;
+    }
+    hooks::run(
+        &ctx.config.hooks,
+        Event::PreInstall,
+        &ctx.paths,
+        package,
+        version,
+    )?;
+    stats::record_install_attempt();
+    // the licenses of dependencies are checked on the extracted sources, which a kept build skips
+    let build_key = match opts.bundle_erts || ctx.config.licenses.is_active() {
+        true => None,
+        false => buildcache::BuildKey::new(source, package, version, opts.target, &opts.limits),
+    };
+    // the build kept of a republished release is a build of the old tarball
+    if let Some(key) = build_key.as_ref().filter(|_| !opts.rebuild && !republished) {
+        let kept = buildcache::find(&ctx.paths, key)?.filter(|kept| {
+            let provenance = kept.provenance.as_ref();
+            opts.checksum.as_ref().is_none_or(|locked| {
+                provenance.is_some_and(|p| checksum::matches(locked, &p.checksum))
+            })
+        });
+        if let Some(kept) = kept {
+            return install_kept(ctx, source, package, version, kept, opts, link_wrapper);
+        }
+    }
+    let downloaded = stats::time("download", || {
+        download_tarball(ctx, source, package, version)
+    })?;
+    if let Some(locked) = &opts.checksum {
+        if !checksum::matches(locked, &downloaded.checksum) {
+            let _ = fs::remove_file(download_dir.join(format!("{}-{}.tar", package, version)));
+            return Err(GleamPkgError::LockedChecksumMismatch {
+                package: package.to_string(),
+                version: version.to_string(),
+                locked: locked.to_lowercase(),
+                actual: downloaded.checksum,
+            });
+        }
+    }
+    stats::time("extract", || extract(&download_dir, package, version))?;
+
+    let extract_dir = download_dir.join(format!("{}-{}", package, version));
+    let target = match opts.target {
+        Some(target) => target,
+        None => {
+            let target = Target::detect(&extract_dir, package, version)?;
+            output::info(format!(
+                "Using {}",
+                target.backend().check_runtime(&opts.limits)?
+            ));
+            target
+        }
+    };
+    let backend = target.backend();
+    backend::check_build_tools(&extract_dir, package, version, backend.as_ref())?;
+    if backend.build_tool() == "gleam" {
+        check_gleam_requirement(&extract_dir, package, version, &opts.limits)?;
+    }
+    output::info(i18n::fill(
+        Message::BuildingWith,
+        &[("backend", &backend.name())],
+    ));
+    events::emit(events::Event::BuildStarted {
+        package: package.to_string(),
+        version: version.to_string(),
+        backend: backend.name().to_string(),
+    });
+    let artifact = stats::time("build", || {
+        build_package(ctx, package, version, backend.as_ref(), opts)
+    })?;
+    // what the package exposes is only known now, the wrapper was never installed if it is taken
+    let binary = (artifact.binary != package).then(|| artifact.binary.clone());
+    let link_binary = match &binary {
+        Some(binary) => {
+            let db = Database::load(&db_path)?;
+            match check_command(ctx, &db, package, binary, opts) {
+                Ok(free) => free,
+                Err(e) => {
+                    discard_build(ctx, package, version);
+                    return Err(e);
+                }
+            }
+        }
+        None => false,
+    };
+    let mut toolchain = BTreeMap::new();
+    if backend.build_tool() == "gleam" {
+        if let Ok(gleam) = toolchain::check_gleam(&opts.limits) {
+            toolchain.insert("gleam".to_string(), gleam.to_string());
+        }
+    }
+    toolchain.insert(backend.name().to_string(), artifact.runtime.clone());
+    let dependencies = match manifest::Dependencies::load(&extract_dir, package) {
+        Ok(dependencies) => dependencies,
+        // only checking licenses needs them
+        Err(e) if !ctx.config.licenses.is_active() => {
+            tracing::debug!(
+                "not recording the dependencies of {}: {}",
+                package,
+                e.report()
+            );
+            None
+        }
+        Err(e) => return Err(e),
+    };
+    if ctx.config.licenses.is_active() {
+        let required_by = format!("{} {}", package, version);
+        for dependency in dependencies.iter().flat_map(|d| &d.packages) {
+            if dependency.source != "hex" {
+                continue;
+            }
+            let checked = check_license(
+                ctx,
+                &Source::default(),
+                &dependency.name,
+                &dependency.version,
+            )?;
+            if let Err(e) = enforce_license_policy(ctx, &checked, Some(&required_by)) {
+                discard_build(ctx, package, version);
+                return Err(e);
+            }
+        }
+    }
+    let provenance = db::Provenance {
+        tarball_url: downloaded.tarball_url,
+        checksum: downloaded.checksum,
+        verified: downloaded.verified,
+        installed_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        toolchain,
+    };
+    let installed = db::InstalledVersion {
+        target,
+        otp_release: artifact.otp_release,
+        blob: artifact.blob,
+        binary,
+        erts: artifact
+            .erts
+            .as_deref()
+            .and_then(toolchains::otp_installation_version),
+        wrapper: opts.wrapper,
+        provenance: Some(provenance),
+        dependencies,
+    };
+    if let Some(key) = &build_key {
+        buildcache::remember(&ctx.paths, key, &installed)?;
+    }
+    record_install(
+        ctx,
+        source,
+        package,
+        version,
+        installed,
+        link_wrapper,
+        link_binary,
+    )
+}
+
+/// Installs a version from the escript an earlier install built, see [`buildcache`]
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::CommandConflict` if the command it exposes is taken and
+/// `opts.overwrite` is not set, or another `GleamPkgError` if the wrapper cannot be written or
+/// the version cannot be recorded
+fn install_kept(
+    ctx: &Context,
+    source: &Source,
+    package: &str,
+    version: &str,
+    mut installed: db::InstalledVersion,
+    opts: &InstallOptions,
+    link_wrapper: bool,
+) -> Result<(), GleamPkgError> {
+    let built_at = installed.provenance.as_ref().map(|p| p.installed_at);
+    output::info(format!(
+        "Reusing the build of {} {} from {}, pass --rebuild to build it again",
+        package,
+        version,
+        built_at.map_or("an earlier install".to_string(), output::format_timestamp)
+    ));
+    installed.wrapper = opts.wrapper;
+    if let Some(provenance) = &mut installed.provenance {
+        provenance.installed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+    }
+    let artifact = installed_artifact(ctx, package, version, &installed).ok_or_else(|| {
+        GleamPkgError::BuildMissing {
+            package: package.to_string(),
+            version: version.to_string(),
+        }
+    })?;
+    restore_wrapper(ctx, package, version, &installed, &artifact)?;
+    path_check(&ctx.paths)?;
+    let link_binary = match &installed.binary {
+        Some(binary) => {
+            let db = Database::load(&ctx.paths.db_file())?;
+            match check_command(ctx, &db, package, binary, opts) {
+                Ok(free) => free,
+                Err(e) => {
+                    discard_build(ctx, package, version);
+                    return Err(e);
+                }
+            }
+        }
+        None => false,
+    };
+    record_install(
+        ctx,
+        source,
+        package,
+        version,
+        installed,
+        link_wrapper,
+        link_binary,
+    )
+}
+
+/// Records a built version in the package database and makes it the default, then links its
+/// command and reports the install
+///
+/// # Arguments
+///
+/// * `installed` - How the version was installed, its wrapper is already written
+/// * `link_wrapper` - Whether the unversioned wrapper may be linked, see [`check_command`]
+/// * `link_binary` - Whether the command the version exposes may be linked, see
+///   [`check_command`]
+///
+/// # Errors
+///
+/// Returns `GleamPkgError` if the database cannot be written, a link cannot be created or a
+/// post-install hook fails
+fn record_install(
+    ctx: &Context,
+    source: &Source,
+    package: &str,
+    version: &str,
+    installed: db::InstalledVersion,
+    link_wrapper: bool,
+    link_binary: bool,
+) -> Result<(), GleamPkgError> {
+    let db_path = ctx.paths.db_file();
+    let binary = installed.binary.clone();
+    let _lock = db::lock(&db_path);
+    let mut db = Database::load(&db_path)?;
+    // aliases taken over with --overwrite no longer belong to their package
+    for (_, other) in db
+        .packages
+        .iter_mut()
+        .filter(|(other, _)| *other != package)
+    {
+        other.aliases.remove(package);
+        if let Some(binary) = binary.as_ref().filter(|_| link_binary) {
+            other.aliases.remove(binary);
+        }
+    }
+    let package_entry =
+        db.packages
+            .entry(package.to_string())
+            .or_insert_with(|| db::InstalledPackage {
+                versions: Default::default(),
+                default_version: version.to_string(),
+                pinned: false,
+                aliases: Default::default(),
+                unlinked: false,
+                source: source.clone(),
+            });
+    package_entry.source = source.clone();
+    package_entry
+        .versions
+        .insert(version.to_string(), installed);
+    package_entry.default_version = version.to_string();
+    package_entry.unlinked = !link_wrapper;
+    let package_entry = package_entry.clone();
+    db.save(&db_path)?;
+    relink(ctx, package, &package_entry)?;
+    if let Some(binary) = binary.filter(|_| link_binary) {
+        link_command(ctx, package, &binary, &package_entry.link_target(package))?;
+    }
+    stats::record_install(package, version);
+    events::emit(events::Event::Installed {
+        package: package.to_string(),
+        version: version.to_string(),
+    });
+    hooks::run(
+        &ctx.config.hooks,
+        Event::PostInstall,
+        &ctx.paths,
+        package,
+        version,
+    )?;
+
+    output::success(i18n::fill(
+        Message::Installed,
+        &[("package", &package), ("version", &version)],
+    ));
+    Ok(())
+}
+
+/// The steps [`install_release`] would take
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::UnknownRepository` if the repository of `source` is not configured
+fn plan_install(
+    ctx: &Context,
+    source: &Source,
+    package: &str,
+    version: &str,
+    opts: &InstallOptions,
+) -> Result<Plan, GleamPkgError> {
+    let download_dir = ctx.paths.download();
+    let extract_dir = download_dir.join(format!("{}-{}", package, version));
+    let registry = registry::open(&ctx.config, source)?;
+    let url = registry.tarball_url(package, version);
+    let size = registry.tarball_size(package, version);
+    // the backend is only known up front if it was chosen, or the sources are already around
+    let target = opts
+        .target
+        .or_else(|| Target::detect(&extract_dir, package, version).ok());
+
+    let mut plan = Plan::new();
+    plan.hooks(&ctx.config.hooks, Event::PreInstall, package);
+    plan.push(Action::Download { url, size });
+    plan.push(Action::Write {
+        path: download_dir.join(format!("{}-{}.tar", package, version)),
+    });
+    plan.push(Action::Extract { path: extract_dir });
+    plan.push(Action::Build {
+        package: package.to_string(),
+        version: version.to_string(),
+        backend: target.map(|t| t.backend().name().to_string()),
+    });
+    plan.push(Action::Write {
+        path: ctx.paths.lib().join(format!("{}-{}", package, version)),
+    });
+    plan.push(Action::Write {
+        path: ctx.paths.apps().join(format!("{}-{}", package, version)),
+    });
+    plan.push(Action::Link {
+        path: ctx.paths.apps().join(package),
+        target: format!("{}-{}", package, version),
+    });
+    plan.push(Action::Record {
+        change: format!("{} {} installed as the default version", package, version),
+    });
+    plan.hooks(&ctx.config.hooks, Event::PostInstall, package);
+    Ok(plan)
+}
+
+/// Points the unversioned `<package>` wrapper at the `<package>-<version>` wrapper
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the link cannot be created
+fn link_default(ctx: &Context, package: &str, version: &str) -> Result<(), GleamPkgError> {
+    let link = ctx.paths.apps().join(package);
+    let _ = fs::remove_file(&link);
+    std::os::unix::fs::symlink(format!("{}-{}", package, version), &link).map_err(|source| {
+        GleamPkgError::Io {
+            action: "link default wrapper",
+            path: link,
+            source,
+        }
+    })
+}
+
+/// Points the unversioned wrapper of a package at its default version, or when it is
+/// [`unlinked`](db::InstalledPackage::unlinked) its aliases and command, which otherwise link
+/// to the unversioned wrapper
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if a link cannot be created
+fn relink(
+    ctx: &Context,
+    package: &str,
+    installed: &db::InstalledPackage,
+) -> Result<(), GleamPkgError> {
+    if !installed.unlinked {
+        link_default(ctx, package, &installed.default_version)?;
+    }
+    let target = installed.link_target(package);
+    let links = installed
+        .aliases
+        .iter()
+        .map(|alias| ctx.paths.apps().join(alias))
+        .chain(binary_links(ctx, package, installed));
+    for link in links {
+        if fs::read_link(&link).is_ok_and(|current| current != Path::new(&target)) {
+            let _ = fs::remove_file(&link);
+            std::os::unix::fs::symlink(&target, &link).map_err(|source| GleamPkgError::Io {
+                action: "relink",
+                path: link,
+                source,
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks that `package` at `version` can name its files, and that they do not collide with the
+/// files of another installed package on a case-insensitive filesystem
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::InvalidName` if they cannot be named, see [`paths::versioned_name`],
+/// or `GleamPkgError::NameCollision` if an installed package differs only in case
+fn check_names(db: &Database, package: &str, version: &str) -> Result<(), GleamPkgError> {
+    paths::versioned_name(package, version)?;
+    let collision = db
+        .packages
+        .keys()
+        .find(|installed| *installed != package && paths::same_name(installed, package));
+    match collision {
+        Some(installed) => Err(GleamPkgError::NameCollision {
+            package: package.to_string(),
+            installed: installed.clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Checks that the command `name` is free for `package`: neither a command of another
+/// installed package, see [`Database::command_owner`], nor a file gleam-pkg does not manage
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::CommandConflict` if it is taken, unless `opts.overwrite` takes it
+/// over, or `opts.renamed` gives the package another name, as it did when the package was
+/// installed without its own name before, see [`db::InstalledPackage::unlinked`]
+///
+/// # Returns
+///
+/// Whether the command may be linked
+///
+fn check_command(
+    ctx: &Context,
+    db: &Database,
+    package: &str,
+    name: &str,
+    opts: &InstallOptions,
+) -> Result<bool, GleamPkgError> {
+    let owner = db.command_owners(name).find(|owner| *owner != package);
+    let link = ctx.paths.apps().join(name);
+    let managed = fs::read_link(&link).is_ok_and(|target| {
+        let target = target.to_string_lossy();
+        target == package || target.starts_with(&format!("{}-", package))
+    });
+    if owner.is_none() && (managed || link.symlink_metadata().is_err()) {
+        return Ok(true);
+    }
+    let taken_by = match owner {
+        Some(owner) => format!("a command of {}", owner),
+        None => format!("{}, which gleam-pkg does not manage", link.display()),
+    };
+    if opts.overwrite {
+        output::warning(format!(
+            "{} was {}, it now runs {}",
+            name, taken_by, package
+        ));
+        return Ok(true);
+    }
+    let unlinked = db.packages.get(package).is_some_and(|p| p.unlinked);
+    if opts.renamed || unlinked {
+        output::info(format!("{} is {}, it is not linked", name, taken_by));
+        return Ok(false);
+    }
+    Err(GleamPkgError::CommandConflict {
+        command: name.to_string(),
+        package: package.to_string(),
+        taken_by,
+    })
+}
+
+/// Removes a build that is not going to be installed, so nothing runs it, unless it rebuilt a
+/// version that stays installed
+fn discard_build(ctx: &Context, package: &str, version: &str) {
+    let installed = Database::load(&ctx.paths.db_file()).is_ok_and(|db| {
+        db.packages
+            .get(package)
+            .is_some_and(|p| p.versions.contains_key(version))
+    });
+    if installed {
+        return;
+    }
+    let _ = fs::remove_file(ctx.paths.apps().join(format!("{}-{}", package, version)));
+    let _ = fs::remove_dir_all(ctx.paths.lib().join(format!("{}-{}", package, version)));
+}
+
+/// Links the command a package exposes under a name other than its own to `target`, its
+/// unversioned wrapper unless it has none, like an alias, replacing whatever [`check_command`]
+/// allowed to be replaced
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the link cannot be created
+fn link_command(
+    ctx: &Context,
+    package: &str,
+    binary: &str,
+    target: &str,
+) -> Result<(), GleamPkgError> {
+    if binary.is_empty() || binary.starts_with('.') || binary.contains('/') {
+        return Ok(());
+    }
+    let link = ctx.paths.apps().join(binary);
+    let _ = fs::remove_file(&link);
+    std::os::unix::fs::symlink(target, &link).map_err(|source| GleamPkgError::Io {
+        action: "link binary",
+        path: link,
+        source,
+    })?;
+    output::info(format!("{} is also available as {}", package, binary));
+    Ok(())
+}
+
+/// The links [`link_command`] made for the installed versions of a package
+fn binary_links(ctx: &Context, package: &str, installed: &db::InstalledPackage) -> Vec<PathBuf> {
+    let binaries: BTreeSet<_> = installed
+        .versions
+        .values()
+        .map(|version| version.command(package))
+        .filter(|binary| *binary != package)
+        .collect();
+    binaries
+        .into_iter()
+        .map(|binary| ctx.paths.apps().join(binary))
+        .filter(|link| {
+            fs::read_link(link).is_ok_and(|target| {
+                let target = target.to_string_lossy();
+                target == package || target.starts_with(&format!("{}-", package))
+            })
+        })
+        .collect()
+}
+
+/// Adds the files [`remove_version`] would delete to `plan`
+fn plan_remove_version(plan: &mut Plan, ctx: &Context, package: &str, version: &str) {
+    plan.remove(ctx.paths.apps().join(format!("{}-{}", package, version)));
+    plan.remove(ctx.paths.lib().join(format!("{}-{}", package, version)));
+    plan.push(Action::Record {
+        change: format!("{} {} no longer installed", package, version),
+    });
+}
+
+/// Removes one installed version of a package: its wrapper, its artifacts and its entry in `db`
+///
+/// Choosing another default version, or dropping the package when no versions remain, is up to
+/// the caller.
+fn remove_version(ctx: &Context, db: &mut Database, package: &str, version: &str) {
+    let _ = fs::remove_file(ctx.paths.apps().join(format!("{}-{}", package, version)));
+    let _ = fs::remove_dir_all(ctx.paths.lib().join(format!("{}-{}", package, version)));
+    if let Some(installed) = db.packages.get_mut(package) {
+        installed.versions.remove(version);
+    }
+}
+
+/// Uninstalls a package, or a single version of it
+///
+/// Removing the default version makes the newest remaining version the default. Once no
+/// versions are left, the unversioned wrapper, every alias and the database entry are removed.
+///
+/// # Arguments
+///
+/// * `ctx` - The installation to work on
+/// * `package` - The name of the package
+/// * `version` - The version to remove, or `None` to remove all of them
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::PackageNotInstalled` if the package or version is not installed
+fn uninstall_package(
+    ctx: &Context,
+    package: &str,
+    version: Option<&str>,
+) -> Result<(), GleamPkgError> {
+    let db_path = ctx.paths.db_file();
+    let mut db = Database::load(&db_path)?;
+    let installed = db.installed_mut(package)?.clone();
+    let versions = match version {
+        Some(version) if !installed.versions.contains_key(version) => {
+            return Err(GleamPkgError::PackageNotInstalled {
+                package: package.to_string(),
+                version: Some(version.to_string()),
+            });
+        }
+        Some(version) => vec![version.to_string()],
+        None => installed.versions.keys().cloned().collect(),
+    };
+    if plan::dry_run() {
+        plan_uninstall(ctx, &installed, package, &versions).print();
+        return Ok(());
+    }
+    let versions: Vec<String> = if versions.len() > 1 {
+        let question = format!("Which versions of {} should be removed?", package);
+        let picked = prompt::multi_select(&question, &versions, &vec![true; versions.len()])?;
+        picked.into_iter().map(|i| versions[i].clone()).collect()
+    } else if prompt::confirm(&format!("Remove {} {}?", package, versions[0]), true)? {
+        versions
+    } else {
+        Vec::new()
+    };
+    if versions.is_empty() {
+        output::info(i18n::text(Message::NothingToRemove));
+        return Ok(());
+    }
+    for version in &versions {
+        hooks::run(
+            &ctx.config.hooks,
+            Event::PreUninstall,
+            &ctx.paths,
+            package,
+            version,
+        )?;
+    }
+    // reloaded under the lock, records written while prompting or running hooks are kept
+    let lock = db::lock(&db_path);
+    let mut db = Database::load(&db_path)?;
+    let installed = db.installed_mut(package)?.clone();
+    let versions: Vec<String> = versions
+        .into_iter()
+        .filter(|version| installed.versions.contains_key(version))
+        .collect();
+    for version in &versions {
+        remove_version(ctx, &mut db, package, version);
+        output::info(format!("Removed {} {}", package, version));
+    }
+
+    let remaining = db.installed_mut(package)?;
+    let newest = remaining
+        .versions
+        .keys()
+        .max_by(
+            |a, b| match (semver::Version::parse(a), semver::Version::parse(b)) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            },
+        )
+        .cloned();
+    match newest {
+        Some(newest) if !remaining.versions.contains_key(&remaining.default_version) => {
+            remaining.default_version = newest.clone();
+            relink(ctx, package, remaining)?;
+            output::info(format!("{} now runs {}-{}", package, package, newest));
+        }
+        Some(_) => {}
+        None => {
+            let apps_dir = ctx.paths.apps();
+            // unless another package took the name over with --overwrite
+            let link = apps_dir.join(package);
+            if fs::read_link(&link).is_ok_and(|target| {
+                target
+                    .to_string_lossy()
+                    .starts_with(&format!("{}-", package))
+            }) {
+                let _ = fs::remove_file(link);
+            }
+            for alias in &remaining.aliases {
+                let _ = fs::remove_file(apps_dir.join(alias));
+            }
+            for link in binary_links(ctx, package, &installed) {
+                let _ = fs::remove_file(link);
+            }
+            db.packages.remove(package);
+            output::success(i18n::fill(Message::Uninstalled, &[("package", &package)]));
+        }
+    }
+    db.save(&db_path)?;
+    drop(lock);
+    for version in &versions {
+        hooks::run(
+            &ctx.config.hooks,
+            Event::PostUninstall,
+            &ctx.paths,
+            package,
+            version,
+        )?;
+    }
+    Ok(())
+}
+
+/// The steps [`uninstall_package`] would take to remove `versions` of a package
+fn plan_uninstall(
+    ctx: &Context,
+    installed: &db::InstalledPackage,
+    package: &str,
+    versions: &[String],
+) -> Plan {
+    let mut plan = Plan::new();
+    // like the hooks themselves, once per removed version
+    for _ in versions {
+        plan.hooks(&ctx.config.hooks, Event::PreUninstall, package);
+    }
+    for version in versions {
+        plan_remove_version(&mut plan, ctx, package, version);
+    }
+    let apps_dir = ctx.paths.apps();
+    let newest = installed
+        .versions
+        .keys()
+        .filter(|version| !versions.contains(version))
+        .max_by(
+            |a, b| match (semver::Version::parse(a), semver::Version::parse(b)) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            },
+        );
+    match newest {
+        Some(newest) if versions.contains(&installed.default_version) => {
+            plan.push(Action::Link {
+                path: apps_dir.join(package),
+                target: format!("{}-{}", package, newest),
+            });
+        }
+        Some(_) => {}
+        None => {
+            plan.remove(apps_dir.join(package));
+            for alias in &installed.aliases {
+                plan.remove(apps_dir.join(alias));
+            }
+            for link in binary_links(ctx, package, installed) {
+                plan.remove(link);
+            }
+            plan.push(Action::Record {
+                change: format!("{} removed", package),
+            });
+        }
+    }
+    for _ in versions {
+        plan.hooks(&ctx.config.hooks, Event::PostUninstall, package);
+    }
+    plan
+}
+
+/// Makes an installed package available under another command name
+///
+/// The alias is a link to the unversioned wrapper of the package, so it follows changes of the
+/// default version, and is recorded in the database so uninstalling the package removes it.
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::PackageNotInstalled` if the package is not installed, or
+/// `GleamPkgError::InvalidAlias` or `GleamPkgError::AliasConflict` if the name is invalid or
+/// already taken
+fn add_alias(ctx: &Context, package: &str, name: &str) -> Result<(), GleamPkgError> {
+    if name.starts_with('.') || paths::check_name(name, paths::NAME_MAX).is_err() {
+        return Err(GleamPkgError::InvalidAlias {
+            alias: name.to_string(),
+        });
+    }
+    let db_path = ctx.paths.db_file();
+    let _lock = db::lock(&db_path);
+    let mut db = Database::load(&db_path)?;
+    db.installed_mut(package)?;
+    if let Some(owner) = db.command_owner(name) {
+        return Err(GleamPkgError::AliasConflict {
+            alias: name.to_string(),
+            package: Some(owner.to_string()),
+        });
+    }
+    let link = ctx.paths.apps().join(name);
+    if link.symlink_metadata().is_ok() {
+        return Err(GleamPkgError::AliasConflict {
+            alias: name.to_string(),
+            package: None,
+        });
+    }
+    let target = db.installed_mut(package)?.link_target(package);
+    std::os::unix::fs::symlink(target, &link).map_err(|source| GleamPkgError::Io {
+        action: "create alias",
+        path: link,
+        source,
+    })?;
+    db.installed_mut(package)?.aliases.insert(name.to_string());
+    db.save(&db_path)?;
+    output::info(format!("{} is now also available as {}", package, name));
+    Ok(())
+}
+
+/// Runs an installed version of a package in its environment, see [`crate::exec`]
+///
+/// On success this does not return: the process is replaced by the package.
+///
+/// # Arguments
+///
+/// * `version` - The installed version to run, the default version if `None`
+/// * `args` - The arguments passed to the package
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::PackageNotInstalled` if the version is not installed,
+/// `GleamPkgError::ToolchainMissing` if no Erlang runtime can run it, or
+/// `GleamPkgError::SpawnFailed` if it cannot be started
+fn exec_package(
+    ctx: &Context,
+    package: &str,
+    version: Option<&str>,
+    args: &[OsString],
+) -> Result<(), GleamPkgError> {
+    use std::os::unix::process::CommandExt;
+    let db = Database::load(&ctx.paths.db_file())?;
+    let not_installed = || GleamPkgError::PackageNotInstalled {
+        package: package.to_string(),
+        version: version.map(str::to_string),
+    };
+    let installed = db.packages.get(package).ok_or_else(not_installed)?;
+    let version = version.unwrap_or(&installed.default_version);
+    let installed_version = installed.versions.get(version).ok_or_else(not_installed)?;
+    let limits = BuildLimits {
+        timeout: ctx.config.build_timeout(),
+        memory_limit: None,
+        cpu_limit: None,
+        isolation: None,
+    };
+    let command = || {
+        exec::command(
+            &ctx.config,
+            &ctx.paths,
+            package,
+            version,
+            installed_version,
+            &limits,
+        )
+    };
+    let mut cmd = match (command(), installed_version.otp_release) {
+        (Err(e @ GleamPkgError::ToolchainMissing { .. }), Some(otp_release)) => {
+            output::warning(e.to_string());
+            let question = format!("Install Erlang/OTP {} to run {}?", otp_release, package);
+            if !prompt::confirm(&question, false)? {
+                return Err(e);
+            }
+            toolchains::install_otp(
+                &ctx.config.toolchains,
+                &ctx.config.http,
+                &ctx.paths,
+                &otp_release.to_string(),
+                &limits,
+            )?;
+            command()?
+        }
+        (cmd, _) => cmd?,
+    };
+    let source = cmd.args(args).exec();
+    Err(GleamPkgError::SpawnFailed {
+        command: format!("{} {}", package, version),
+        source,
+    })
+}
+
+/// Packs an installed version into a bundle `gleam-pkg install --file` installs, see
+/// [`crate::bundle`]
+///
+/// # Arguments
+///
+/// * `version` - The installed version to bundle, the default version if `None`
+/// * `output` - Where to write the bundle, `<package>-<version>-<target>.tar.gz` if `None`, with
+///   the platform appended if it bundles Erlang/OTP
+/// * `with_erts` - Whether to bundle the Erlang/OTP installation an escript runs on
+/// * `target` - The machine the bundle is for, this one if `None`
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::PackageNotInstalled` if the version is not installed,
+/// `GleamPkgError::BuildMissing` if its build is gone, `GleamPkgError::ToolchainMissing` if
+/// `with_erts` finds no Erlang/OTP installation that can be bundled, the errors of
+/// [`toolchains::download_otp`] if one is downloaded for `target`, or `GleamPkgError::Io` if
+/// the bundle cannot be written
+fn bundle_package(
+    ctx: &Context,
+    package: &str,
+    version: Option<&str>,
+    output: Option<PathBuf>,
+    with_erts: bool,
+    target: Option<bundle::Platform>,
+) -> Result<(), GleamPkgError> {
+    let db = Database::load(&ctx.paths.db_file())?;
+    let not_installed = || GleamPkgError::PackageNotInstalled {
+        package: package.to_string(),
+        version: version.map(str::to_string),
+    };
+    let installed = db.packages.get(package).ok_or_else(not_installed)?;
+    let version = version.unwrap_or(&installed.default_version);
+    let installed_version = installed.versions.get(version).ok_or_else(not_installed)?;
+    let artifact = installed_artifact(ctx, package, version, installed_version)
+        .filter(|artifact| artifact.path.exists())
+        .ok_or_else(|| GleamPkgError::BuildMissing {
+            package: package.to_string(),
+            version: version.to_string(),
+        })?;
+    let lib = ctx.paths.lib().join(format!("{}-{}", package, version));
+    let contents = match installed_version.blob {
+        Some(_) => bundle::Contents::Escript(&artifact.path),
+        None => bundle::Contents::Lib(&lib),
+    };
+
+    let mut installed_version = installed_version.clone();
+    // the runtime of this machine does not run on another one, the build for it is downloaded
+    let foreign = target.filter(|target| Some(*target) != bundle::Platform::current());
+    let download = ctx
+        .paths
+        .download()
+        .join(format!("bundle-otp-{}", std::process::id()));
+    let release = match (&installed_version.erts, installed_version.otp_release) {
+        (Some(erts), _) => erts.split('.').next().map(String::from),
+        (None, release) => release.filter(|_| with_erts).map(|r| r.to_string()),
+    };
+    let otp = match (foreign, release) {
+        (Some(platform), Some(release)) => {
+            let version = toolchains::download_otp(
+                &ctx.config.toolchains,
+                &ctx.config.http,
+                &release,
+                platform.os(),
+                platform.arch(),
+                &download,
+            )?;
+            output::info(format!("Bundling Erlang/OTP {} for {}", version, platform));
+            if installed_version.erts.is_some() {
+                installed_version.erts = Some(version.clone());
+            }
+            Some((download.clone(), version))
+        }
+        _ => local_otp(
+            ctx,
+            package,
+            version,
+            &installed_version,
+            &artifact,
+            with_erts,
+        )?,
+    };
+
+    // escripts and JavaScript builds run anywhere, only a runtime ties the bundle to a platform
+    let platform = match &otp {
+        Some(_) => target.or_else(bundle::Platform::current),
+        None => {
+            if let Some(target) = target {
+                output::info(format!(
+                    "{} {} runs on any platform, nothing in the bundle is specific to {}",
+                    package, version, target
+                ));
+            }
+            None
+        }
+    };
+    let output = output.unwrap_or_else(|| {
+        let mut name = format!(
+            "{}-{}-{}",
+            package,
+            version,
+            installed_version.target.backend().name()
+        );
+        if let Some(platform) = platform {
+            name.push_str(&format!("-{}", platform));
+        }
+        PathBuf::from(format!("{}.tar.gz", name))
+    });
+    let manifest = bundle::BundleManifest {
+        format: bundle::FORMAT,
+        package: package.to_string(),
+        version: version.to_string(),
+        source: installed.source.clone(),
+        installed: installed_version.clone(),
+        otp: otp.as_ref().map(|(_, version)| version.clone()),
+        platform,
+    };
+    let written = bundle::write(
+        &output,
+        &manifest,
+        contents,
+        otp.as_ref().map(|(root, _)| root.as_path()),
+    );
+    let _ = fs::remove_dir_all(&download);
+    written?;
+    output::success(format!(
+        "Bundled {} {} into {}, install it with `gleam-pkg install --file {}`",
+        package,
+        version,
+        output.display(),
+        output.display()
+    ));
+    Ok(())
+}
+
+/// The Erlang/OTP installation of this machine to bundle with a version, and its version: the
+/// one embedded into it, or with `with_erts` the one its escript runs on
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::ToolchainMissing` if no installation that can be bundled is found
+fn local_otp(
+    ctx: &Context,
+    package: &str,
+    version: &str,
+    installed_version: &db::InstalledVersion,
+    artifact: &Artifact,
+    with_erts: bool,
+) -> Result<Option<(PathBuf, String)>, GleamPkgError> {
+    Ok(match (with_erts, installed_version.otp_release) {
+        // an embedded runtime is part of the build
+        _ if installed_version.erts.is_some() => {
+            artifact.erts.clone().zip(installed_version.erts.clone())
+        }
+        (false, _) => None,
+        (true, None) => {
+            output::warning(format!(
+                "{} {} does not run on the BEAM, bundling it without Erlang/OTP",
+                package, version
+            ));
+            None
+        }
+        (true, Some(otp_release)) => {
+            let limits = BuildLimits {
+                timeout: ctx.config.build_timeout(),
+                memory_limit: None,
+                cpu_limit: None,
+                isolation: None,
+            };
+            let erl = exec::find_erl(otp_release, &ctx.paths, &limits)?;
+            let found =
+                toolchains::otp_root(&erl).ok_or_else(|| GleamPkgError::ToolchainMissing {
+                    tool: "Erlang/OTP".to_string(),
+                    required: "an installation with its Install script".to_string(),
+                    found: Some(erl.display().to_string()),
+                })?;
+            output::info(format!(
+                "Bundling Erlang/OTP {} from: {}",
+                found.1,
+                found.0.display()
+            ));
+            Some(found)
+        }
+    })
+}
+
+/// Installs the version a bundle holds, see [`crate::bundle`]
+///
+/// The build is used as it is, nothing is downloaded or built. A bundled Erlang/OTP
+/// installation is kept with the toolchains, unless that version is installed already.
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::InvalidBundle` if `file` is not a bundle gleam-pkg can install,
+/// `GleamPkgError::PackagePinned` if it would replace a pinned version,
+/// `GleamPkgError::CommandConflict` if its command is taken, or `GleamPkgError` if it cannot be
+/// installed
+///
+/// # Returns
+///
+/// The name of the installed package
+fn install_bundle(
+    ctx: &Context,
+    file: &Path,
+    opts: &InstallOptions,
+) -> Result<String, GleamPkgError> {
+    let unpacked = ctx
+        .paths
+        .download()
+        .join(format!("bundle-{}", std::process::id()));
+    let installed = bundle::unpack(file, &unpacked)
+        .and_then(|manifest| install_unpacked_bundle(ctx, file, &unpacked, manifest, opts));
+    let _ = fs::remove_dir_all(&unpacked);
+    installed
+}
+
+/// Installs a bundle unpacked into `unpacked`, see [`install_bundle`]
+fn install_unpacked_bundle(
+    ctx: &Context,
+    file: &Path,
+    unpacked: &Path,
+    manifest: bundle::BundleManifest,
+    opts: &InstallOptions,
+) -> Result<String, GleamPkgError> {
+    let (package, version) = (manifest.package.as_str(), manifest.version.as_str());
+    let db = Database::load(&ctx.paths.db_file())?;
+    check_names(&db, package, version)?;
+    if let Some(installed) = db.packages.get(package) {
+        if installed.pinned && installed.default_version != version && !opts.force {
+            return Err(GleamPkgError::PackagePinned {
+                package: package.to_string(),
+                pinned: installed.default_version.clone(),
+                requested: version.to_string(),
+            });
+        }
+    }
+    let link_wrapper = check_command(ctx, &db, package, package, opts)?;
+    output::info(format!(
+        "Installing {} {} from: {}",
+        package,
+        version,
+        file.display()
+    ));
+    hooks::run(
+        &ctx.config.hooks,
+        Event::PreInstall,
+        &ctx.paths,
+        package,
+        version,
+    )?;
+    stats::begin_install();
+    stats::record_install_attempt();
+
+    let app_dir = ctx.paths.lib().join(format!("{}-{}", package, version));
+    match (&manifest.otp, &manifest.installed.erts) {
+        (Some(_), Some(_)) => {
+            let erts = app_dir.join("erts");
+            toolchains::move_otp(&unpacked.join(bundle::OTP), &erts, &opts.limits)?;
+        }
+        (Some(otp), None) => {
+            toolchains::adopt_otp(&ctx.paths, otp, &unpacked.join(bundle::OTP), &opts.limits)?;
+        }
+        (None, _) => {}
+    }
+    match &manifest.installed.blob {
+        Some(blob) => {
+            let stored = Store::new(&ctx.paths.store()).put(&unpacked.join(bundle::ESCRIPT))?;
+            if stored != *blob {
+                return Err(GleamPkgError::InvalidBundle {
+                    path: file.to_path_buf(),
+                    reason: "the escript is not the one that was bundled".to_string(),
+                });
+            }
+        }
+        None => {
+            let _ = fs::remove_dir_all(&app_dir);
+            copy_dir_all(unpacked.join(bundle::LIB), &app_dir).map_err(|source| {
+                GleamPkgError::Io {
+                    action: "copy bundled build",
+                    path: app_dir.clone(),
+                    source,
+                }
+            })?;
+        }
+    }
+    let artifact = installed_artifact(ctx, package, version, &manifest.installed)
+        .filter(|artifact| artifact.path.exists())
+        .ok_or_else(|| GleamPkgError::InvalidBundle {
+            path: file.to_path_buf(),
+            reason: format!(
+                "{} builds cannot be installed from a bundle",
+                manifest.installed.target.backend().name()
+            ),
+        })?;
+    restore_wrapper(ctx, package, version, &manifest.installed, &artifact)?;
+    path_check(&ctx.paths)?;
+
+    let link_binary = match &manifest.installed.binary {
+        Some(binary) => {
+            let db = Database::load(&ctx.paths.db_file())?;
+            match check_command(ctx, &db, package, binary, opts) {
+                Ok(free) => free,
+                Err(e) => {
+                    discard_build(ctx, package, version);
+                    return Err(e);
+                }
+            }
+        }
+        None => false,
+    };
+    let mut installed = manifest.installed;
+    if let Some(provenance) = &mut installed.provenance {
+        provenance.installed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+    }
+    record_install(
+        ctx,
+        &manifest.source,
+        package,
+        version,
+        installed,
+        link_wrapper,
+        link_binary,
+    )?;
+    Ok(package.to_string())
+}
+
+/// Makes an installed version the default version of a package
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::PackageNotInstalled` if that version is not installed
+fn set_default(ctx: &Context, package: &str, version: &str) -> Result<(), GleamPkgError> {
+    let db_path = ctx.paths.db_file();
+    let _lock = db::lock(&db_path);
+    let mut db = Database::load(&db_path)?;
+    let installed = db.installed_mut(package)?;
+    if !installed.versions.contains_key(version) {
+        return Err(GleamPkgError::PackageNotInstalled {
+            package: package.to_string(),
+            version: Some(version.to_string()),
+        });
+    }
+    installed.default_version = version.to_string();
+    let installed = installed.clone();
+    db.save(&db_path)?;
+    relink(ctx, package, &installed)?;
+    output::info(format!("{} now runs {}-{}", package, package, version));
+    Ok(())
+}
+
+/// What `update` did to a package
+enum UpdateOutcome {
+    Updated { from: String, to: String },
+    UpToDate { current: String },
+    Pinned { current: String },
+    Failed { current: String },
+}
+
+/// Updates installed packages to their latest release
+///
+/// The latest release replaces the default version of each package, other versions installed
+/// side by side are kept. Pinned packages are skipped. When updating everything, a failure does
+/// not stop the remaining packages from being updated; failures are reported together at the end,
+/// after a table of what happened to each package. What changed in the updated packages is
+/// printed last, see [`changelog`].
+///
+/// With more than one job, packages are updated on that many threads. Their build output is
+/// only written to the build logs, and changes to the database are serialized with
+/// [`db::lock`]; downloads and builds of different packages never share files.
+///
+/// When updating everything, the packages that are quickest to update go first, see
+/// [`update_order`], and a line with how far along the update is reports each one as it
+/// finishes.
+///
+/// # Arguments
+///
+/// * `ctx` - The installation to work on
+/// * `package` - The package to update, or `None` to update every installed package
+/// * `limits` - Resource limits for the build processes
+/// * `jobs` - How many packages to update at once
+/// * `notes` - Whether to print the release notes of the updated packages
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::PackageNotInstalled` if `package` is not installed, or
+/// `GleamPkgError::UpdateFailed` if any package failed to update
+///
+fn update_packages(
+    ctx: &Context,
+    package: Option<&str>,
+    limits: &BuildLimits,
+    jobs: NonZeroUsize,
+    notes: bool,
+) -> Result<(), GleamPkgError> {
+    let mut db = Database::load(&ctx.paths.db_file())?;
+    let averages = db.stats.average_install_ms();
+    let mut packages: Vec<_> = match package {
+        Some(package) => vec![(package.to_string(), db.installed_mut(package)?.clone())],
+        None => db.packages.into_iter().collect(),
+    };
+    let sources: BTreeMap<String, Source> = packages
+        .iter()
+        .map(|(name, installed)| (name.clone(), installed.source.clone()))
+        .collect();
+
+    // dry runs print plans, which would interleave
+    let jobs = match plan::dry_run() {
+        true => 1,
+        false => jobs.get().min(packages.len()),
+    };
+    if jobs > 1 {
+        // ask before the threads start, rather than in the middle of their output
+        path_check(&ctx.paths)?;
+        runner::echo_output(false);
+        output::info(format!(
+            "Updating {} packages, {} at a time, build output goes to the logs",
+            packages.len(),
+            jobs
+        ));
+    }
+    // one request tells which packages changed at all
+    let indexed = indexed_latest(
+        ctx,
+        packages.iter().map(|(name, installed)| (name, installed)),
+    );
+    packages.sort_by_cached_key(|(name, installed)| {
+        let latest = indexed.get(name).map(String::as_str);
+        update_order(installed, latest, averages.get(name).copied())
+    });
+    let total = packages.len();
+    let queue = Mutex::new(packages.into_iter());
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                loop {
+                    let next = queue.lock().unwrap_or_else(PoisonError::into_inner).next();
+                    let Some((name, installed)) = next else {
+                        break;
+                    };
+                    let started = Instant::now();
+                    let latest = indexed.get(&name).map(String::as_str);
+                    let outcome = update_package(ctx, &name, &installed, latest, limits);
+                    let mut results = results.lock().unwrap_or_else(PoisonError::into_inner);
+                    let elapsed = started.elapsed();
+                    if package.is_none() && !plan::dry_run() {
+                        let message = match &outcome {
+                            UpdateOutcome::Updated { from, to } => {
+                                format!("{} updated {} -> {}", name, from, to)
+                            }
+                            UpdateOutcome::UpToDate { .. } => format!("{} is up to date", name),
+                            UpdateOutcome::Pinned { .. } => format!("{} is pinned", name),
+                            UpdateOutcome::Failed { .. } => format!("{} failed", name),
+                        };
+                        let done = results.len() + 1;
+                        let took = stats::seconds(elapsed.as_millis() as u64);
+                        output::progress(done, total, format!("{} in {}", message, took));
+                    }
+                    results.push((name, outcome, elapsed));
+                }
+            });
+        }
+    });
+    runner::echo_output(!output::quiet());
+    let mut results = results.into_inner().unwrap_or_else(PoisonError::into_inner);
+    results.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+
+    if package.is_none() && !plan::dry_run() {
+        print_update_summary(&results);
+    }
+    if !plan::dry_run() && !output::porcelain() {
+        changelog::print(&what_changed(ctx, &sources, &results, notes));
+    }
+    let failed: Vec<_> = results
+        .into_iter()
+        .filter(|(_, outcome, _)| matches!(outcome, UpdateOutcome::Failed { .. }))
+        .map(|(name, ..)| name)
+        .collect();
+    if !failed.is_empty() {
+        return Err(GleamPkgError::UpdateFailed { packages: failed });
+    }
+    Ok(())
+}
+
+/// Where `installed` goes in the order of `update --all`: packages with nothing to build first,
+/// then those whose installs took the least time before, then those never timed, see
+/// [`stats::Stats::average_install_ms`]
+///
+/// # Arguments
+///
+/// * `installed` - The installed package
+/// * `indexed_latest` - Its latest version in the versions index, if it is known
+/// * `average_ms` - How long its installs took on average, if they were timed
+fn update_order(
+    installed: &db::InstalledPackage,
+    indexed_latest: Option<&str>,
+    average_ms: Option<u64>,
+) -> (bool, u64) {
+    let current = installed.default_entry().0;
+    if installed.pinned || indexed_latest.is_some_and(|latest| !is_newer(latest, current)) {
+        return (false, 0);
+    }
+    match average_ms {
+        Some(ms) => (false, ms.max(1)),
+        None => (true, 0),
+    }
+}
+
+/// Updates one installed package, reporting a failure right away
+///
+/// A package whose latest version in the versions index, `indexed_latest`, is not newer than
+/// the installed one is up to date without asking the API.
+fn update_package(
+    ctx: &Context,
+    name: &str,
+    installed: &db::InstalledPackage,
+    indexed_latest: Option<&str>,
+    limits: &BuildLimits,
+) -> UpdateOutcome {
+    let (current, installed_version) = installed.default_entry();
+    if installed.pinned {
+        output::info(i18n::fill(
+            Message::SkippingPinned,
+            &[("package", &name), ("version", &current)],
+        ));
+        return UpdateOutcome::Pinned {
+            current: current.to_string(),
+        };
+    }
+    if indexed_latest.is_some_and(|latest| !is_newer(latest, current)) {
+        output::info(i18n::fill(
+            Message::UpToDate,
+            &[("package", &name), ("version", &current)],
+        ));
+        return UpdateOutcome::UpToDate {
+            current: current.to_string(),
+        };
+    }
+    let recorded = InstallOptions {
+        target: Some(installed_version.target),
+        force: false,
+        overwrite: false,
+        renamed: false,
+        trust_all: false,
+        latest: true,
+        limits: limits.clone(),
+        checksum: None,
+        // an embedded runtime is kept across updates
+        bundle_erts: installed_version.erts.is_some(),
+        wrapper: installed_version.wrapper,
+        rebuild: false,
+        accept_new_checksum: false,
+        explain: false,
+    };
+    // what is configured for the package now wins over what it was installed with
+    let defaults = ctx.config.packages.get(name);
+    let opts = InstallOptions {
+        target: defaults.and_then(|d| d.backend).or(recorded.target),
+        wrapper: defaults.and_then(|d| d.wrapper).or(recorded.wrapper),
+        ..recorded
+    };
+    stats::begin_install();
+    let started = SystemTime::now();
+    let result = fetch_metadata(ctx, &installed.source, name)
+        .and_then(|metadata| match ctx.config.package_version(name) {
+            Some(requirement) => configured_release(&metadata, requirement),
+            None => {
+                let latest = extract_version(&metadata)?;
+                compatible_release(ctx, &installed.source, &metadata, latest, &opts)
+            }
+        })
+        .and_then(|latest| {
+            if !is_newer(&latest, current) {
+                output::info(i18n::fill(
+                    Message::UpToDate,
+                    &[("package", &name), ("version", &current)],
+                ));
+                return Ok(UpdateOutcome::UpToDate {
+                    current: current.to_string(),
+                });
+            }
+            output::updating(i18n::fill(
+                Message::Updating,
+                &[("package", &name), ("from", &current), ("to", &latest)],
+            ));
+            if plan::dry_run() {
+                let mut plan = plan_install(ctx, &installed.source, name, &latest, &opts)?;
+                plan_remove_version(&mut plan, ctx, name, current);
+                plan.print();
+            } else {
+                install_release(ctx, &installed.source, name, &latest, &opts)?;
+                let db_path = ctx.paths.db_file();
+                let _lock = db::lock(&db_path);
+                let mut db = Database::load(&db_path)?;
+                remove_version(ctx, &mut db, name, current);
+                db.save(&db_path)?;
+            }
+            Ok(UpdateOutcome::Updated {
+                from: current.to_string(),
+                to: latest,
+            })
+        });
+    if !plan::dry_run() {
+        let record = |to, error| {
+            let kind = history::Kind::Update;
+            history::record(kind, Some(name), Some(current), Some(to), started, error)
+        };
+        match &result {
+            Ok(UpdateOutcome::Updated { to, .. }) => record(to, None),
+            Err(e) => record(current, Some(e)),
+            Ok(_) => {}
+        }
+    }
+    result.unwrap_or_else(|e| {
+        output::failure(format!("Failed to update {}: {}", name, e.report()));
+        UpdateOutcome::Failed {
+            current: current.to_string(),
+        }
+    })
+}
+
+/// Runs `f`, which changes `package`, and records it in the history with the default version of
+/// the package before and after, see [`history`]
+fn tracked<T>(
+    ctx: &Context,
+    kind: history::Kind,
+    package: &str,
+    f: impl FnOnce() -> Result<T, GleamPkgError>,
+) -> Result<T, GleamPkgError> {
+    if plan::dry_run() {
+        return f();
+    }
+    let from = default_version(ctx, package);
+    let started = SystemTime::now();
+    let result = f();
+    let to = default_version(ctx, package);
+    let error = result.as_ref().err();
+    history::record(
+        kind,
+        Some(package),
+        from.as_deref(),
+        to.as_deref(),
+        started,
+        error,
+    );
+    result
+}
+
+/// The default version of `package`, none if it is not installed
+fn default_version(ctx: &Context, package: &str) -> Option<String> {
+    let db = Database::load(&ctx.paths.db_file()).ok()?;
+    Some(db.packages.get(package)?.default_version.clone())
+}
+
+/// Prints which installed versions depend on `package`, at which version, and through which
+/// requirements, see [`manifest::Dependencies::chain_to`]
+///
+/// # Errors
+///
+/// Returns `GleamPkgError` if the database cannot be loaded
+fn why_depends(ctx: &Context, package: &str) -> Result<(), GleamPkgError> {
+    let db = Database::load(&ctx.paths.db_file())?;
+    let mut table = output::Table::new(&["TOOL", "VERSION", "USES", "THROUGH"]);
+    let mut found = 0;
+    let mut unknown = 0;
+    for (name, installed) in &db.packages {
+        for (version, installed_version) in &installed.versions {
+            let Some(dependencies) = &installed_version.dependencies else {
+                unknown += 1;
+                continue;
+            };
+            let (Some(dependency), Some(chain)) =
+                (dependencies.find(package), dependencies.chain_to(package))
+            else {
+                continue;
+            };
+            found += 1;
+            let through = std::iter::once(name.as_str())
+                .chain(chain)
+                .collect::<Vec<_>>();
+            table.styled_row(vec![
+                (name.clone(), None),
+                (version.clone(), None),
+                (dependency.version.clone(), Some(output::Style::Bold)),
+                (through.join(" > "), Some(output::Style::Dim)),
+            ]);
+        }
+    }
+    if unknown > 0 {
+        output::notice(format!(
+            "{} installed version(s) have no recorded dependencies, reinstall them to include \
+             them",
+            unknown
+        ));
+    }
+    if found == 0 {
+        output::info(format!("No installed tool depends on {}", package));
+        return Ok(());
+    }
+    table.print();
+    Ok(())
+}
+
+/// Prints the dependencies of every installed version as a tree below it, see
+/// [`manifest::Dependencies::tree`]
+fn print_dependency_trees(db: &Database) {
+    for (name, installed) in &db.packages {
+        for (version, installed_version) in &installed.versions {
+            let dependencies = installed_version.dependencies.as_ref();
+            if output::porcelain() {
+                for node in dependencies.iter().flat_map(|d| d.tree()) {
+                    output::record(&[
+                        name,
+                        version,
+                        &node.branches.len().to_string(),
+                        &node.package.name,
+                        &node.package.version,
+                        &node.package.source,
+                    ]);
+                }
+                continue;
+            }
+            println!("{} {}", output::paint(name, output::Style::Bold), version);
+            let Some(dependencies) = dependencies else {
+                println!(
+                    "{}",
+                    output::paint(
+                        "  dependencies unknown, reinstall it to record them",
+                        output::Style::Dim
+                    )
+                );
+                continue;
+            };
+            for node in dependencies.tree() {
+                let mut line = String::new();
+                let (parents, this) = node.branches.split_at(node.branches.len() - 1);
+                for last in parents {
+                    line += match (output::plain(), last) {
+                        (true, _) => "  ",
+                        (false, true) => "    ",
+                        (false, false) => "│   ",
+                    };
+                }
+                line += match (output::plain(), this[0]) {
+                    (true, _) => "  ",
+                    (false, true) => "└── ",
+                    (false, false) => "├── ",
+                };
+                line += &format!("{} {}", node.package.name, node.package.version);
+                if node.package.source != "hex" {
+                    line += &format!(" ({})", node.package.source);
+                }
+                if node.repeated {
+                    line += &output::paint(" (*)", output::Style::Dim);
+                }
+                println!("{}", line);
+            }
+        }
+    }
+}
+
+/// Prints a table of what `update` did to each package, and how long it took
+fn print_update_summary(results: &[(String, UpdateOutcome, Duration)]) {
+    if results.is_empty() {
+        return;
+    }
+    println!();
+    let mut table = output::Table::new(&["PACKAGE", "FROM", "TO", "RESULT", "TIME"]).align_right(4);
+    for (name, outcome, elapsed) in results {
+        let (from, to, result, style) = match outcome {
+            UpdateOutcome::Updated { from, to } => {
+                (from, to.as_str(), "updated", output::Style::Green)
+            }
+            UpdateOutcome::UpToDate { current } => (current, "", "up to date", output::Style::Dim),
+            UpdateOutcome::Pinned { current } => (current, "", "pinned", output::Style::Dim),
+            UpdateOutcome::Failed { current } => (current, "", "failed", output::Style::Red),
+        };
+        table.styled_row(vec![
+            (name.clone(), None),
+            (from.clone(), None),
+            (to.to_string(), None),
+            (result.to_string(), Some(style)),
+            (stats::seconds(elapsed.as_millis() as u64), None),
+        ]);
+    }
+    table.print();
+}
+
+/// What changed in the packages `results` tells were updated, see [`changelog`]
+///
+/// The update fetched their metadata, so it comes from the cache. Release notes that cannot be
+/// fetched are reported and left out.
+///
+/// # Arguments
+///
+/// * `ctx` - The installation that was updated
+/// * `sources` - Where each package comes from
+/// * `results` - What happened to each package
+/// * `notes` - Whether to fetch the changelogs for their release notes
+fn what_changed(
+    ctx: &Context,
+    sources: &BTreeMap<String, Source>,
+    results: &[(String, UpdateOutcome, Duration)],
+    notes: bool,
+) -> Vec<changelog::Change> {
+    let mut changes = Vec::new();
+    for (name, outcome, _) in results {
+        let (UpdateOutcome::Updated { from, to }, Some(source)) = (outcome, sources.get(name))
+        else {
+            continue;
+        };
+        let metadata = fetch_api(ctx, source, &format!("packages/{}", name), name)
+            .inspect_err(|e| tracing::debug!("no links to what changed in {}: {}", name, e))
+            .unwrap_or_default();
+        let mut change = changelog::Change::new(source, name, from, to, &metadata);
+        if let Some(url) = change.changelog.as_deref().filter(|_| notes) {
+            match changelog::fetch(&ctx.config.http, url) {
+                Ok(text) => {
+                    change.notes = changelog::notes(&text, from, to)
+                        .into_iter()
+                        .map(String::from)
+                        .collect();
+                }
+                Err(e) => output::warning(format!(
+                    "failed to fetch the changelog of {}: {}",
+                    name,
+                    e.report()
+                )),
+            }
+        }
+        changes.push(change);
+    }
+    changes
+}
+
+/// Removes what no installed version needs, see [`gc`], and prints how much space that freed
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::DatabaseError` if the database cannot be read, or
+/// `GleamPkgError::Io` if something cannot be listed or removed
+fn collect_garbage(
+    ctx: &Context,
+    max_log_age: Duration,
+    dry_run: bool,
+) -> Result<(), GleamPkgError> {
+    let garbage = gc::find(&ctx.paths, max_log_age)?;
+    if dry_run {
+        let mut plan = Plan::new();
+        for item in garbage {
+            plan.push(Action::Remove {
+                path: item.path,
+                size: item.size,
+            });
+        }
+        plan.print();
+        return Ok(());
+    }
+    if garbage.is_empty() {
+        output::info(i18n::text(Message::NothingToRemove));
+        return Ok(());
+    }
+    gc::remove(&garbage)?;
+    buildcache::prune(&ctx.paths)?;
+    print_reclaimed(&garbage);
+    Ok(())
+}
+
+/// Removes the downloads `policy` prunes, see [`downloads`], and prints how much space that
+/// freed
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::NoPrunePolicy` if `policy` prunes nothing, or the errors of
+/// [`downloads::find`] and [`gc::remove`]
+fn prune_downloads(
+    ctx: &Context,
+    policy: &downloads::Policy,
+    dry_run: bool,
+) -> Result<(), GleamPkgError> {
+    if policy.is_empty() {
+        return Err(GleamPkgError::NoPrunePolicy);
+    }
+    let garbage = downloads::find(&ctx.paths, policy)?;
+    if dry_run {
+        let mut plan = Plan::new();
+        for item in garbage {
+            plan.push(Action::Remove {
+                path: item.path,
+                size: item.size,
+            });
+        }
+        plan.print();
+        return Ok(());
+    }
+    if garbage.is_empty() {
+        output::info(i18n::text(Message::NothingToRemove));
+        return Ok(());
+    }
+    gc::remove(&garbage)?;
+    print_reclaimed(&garbage);
+    Ok(())
+}
+
+/// Prunes the downloads by the policies of `[cache.downloads]` once an install or update is
+/// done, with `prune_after_install` set; a failure is only reported
+fn prune_after_install(ctx: &Context) {
+    let config = &ctx.config.cache.downloads;
+    let policy = downloads::Policy::new(config);
+    if !config.prune_after_install || policy.is_empty() || plan::dry_run() {
+        return;
+    }
+    let pruned = downloads::find(&ctx.paths, &policy).and_then(|garbage| {
+        gc::remove(&garbage)?;
+        Ok(garbage.iter().map(|item| item.size).sum::<u64>())
+    });
+    match pruned {
+        Ok(0) => {}
+        Ok(freed) => output::info(format!(
+            "Pruned the download cache, reclaimed {}",
+            output::format_size(freed)
+        )),
+        Err(e) => output::warning(format!("failed to prune the downloads: {}", e.report())),
+    }
+}
+
+/// Prints a table of what was removed of each kind, and how much space that freed
+fn print_reclaimed(garbage: &[gc::Garbage]) {
+    let mut table = output::Table::new(&["REMOVED", "COUNT", "SIZE"])
+        .align_right(1)
+        .align_right(2);
+    let mut kinds: Vec<_> = garbage.iter().map(|item| item.kind).collect();
+    kinds.sort();
+    kinds.dedup();
+    for kind in kinds {
+        let items = garbage.iter().filter(|item| item.kind == kind);
+        table.styled_row(vec![
+            (kind.name().to_string(), None),
+            (items.clone().count().to_string(), None),
+            (output::format_size(items.map(|item| item.size).sum()), None),
+        ]);
+    }
+    table.print();
+    let freed = garbage.iter().map(|item| item.size).sum();
+    output::success(format!("Reclaimed {}", output::format_size(freed)));
+}
+
+/// Benchmarks every configured tarball repository and mirror, printing a table of how fast they
+/// answered and how downloads from them went so far
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::UnknownRepository` if a repository cannot be opened, or
+/// `GleamPkgError::MirrorsFailed` if none of the mirrors of a repository works
+fn test_mirrors(ctx: &Context) -> Result<(), GleamPkgError> {
+    let sources =
+        std::iter::once(Source::default()).chain(ctx.config.repos.keys().map(|name| Source {
+            repo: Some(name.clone()),
+            organization: None,
+        }));
+    let health = ctx.paths.mirror_health();
+    let mut results = Vec::new();
+    let mut failed = Vec::new();
+    for source in sources {
+        let repo = source
+            .repo
+            .as_deref()
+            .unwrap_or(registry::HEXPM)
+            .to_string();
+        let registry = registry::open(&ctx.config, &source)?;
+        let mut working = false;
+        for repository in registry.repositories() {
+            output::info(format!("Testing {}", repository));
+            let result = registry.benchmark(repository);
+            mirrors::record(&health, repository, result.as_ref().map(|_| ()));
+            working |= result.is_ok();
+            results.push((repo.clone(), repository.clone(), result));
+        }
+        if !working {
+            failed.push(repo);
+        }
+    }
+
+    let record = mirrors::HealthRecord::load(&health);
+    println!();
+    let mut table = output::Table::new(&["REPO", "URL", "LATENCY", "SPEED", "HISTORY"])
+        .align_right(2)
+        .align_right(3);
+    for (repo, repository, result) in results {
+        let (latency, speed) = match &result {
+            Ok(benchmark) => {
+                let per_second = benchmark.bytes as f64 / benchmark.total.as_secs_f64().max(0.001);
+                (
+                    (
+                        format!("{} ms", benchmark.latency.as_millis()),
+                        Some(output::Style::Green),
+                    ),
+                    format!("{}/s", output::format_size(per_second as u64)),
+                )
+            }
+            Err(e) => ((e.to_string(), Some(output::Style::Red)), String::new()),
+        };
+        let history = record
+            .repositories
+            .get(&repository)
+            .map(|h| format!("{} ok, {} failed", h.successes, h.failures))
+            .unwrap_or_default();
+        table.styled_row(vec![
+            (repo, None),
+            (repository, None),
+            latency,
+            (speed, None),
+            (history, Some(output::Style::Dim)),
+        ]);
+    }
+    table.print();
+
+    if !failed.is_empty() {
+        return Err(GleamPkgError::MirrorsFailed { repos: failed });
+    }
+    Ok(())
+}
+
+/// Describes how an installed version was built, e.g. `erlang (OTP 27)`
+fn describe_target(installed: &db::InstalledVersion) -> String {
+    let name = installed.target.backend().name();
+    match installed.otp_release {
+        Some(otp_release) => format!("{} (OTP {})", name, otp_release),
+        None => name.to_string(),
+    }
+}
+
+/// Prints a package as published: its description, latest release, licenses and links, and
+/// which versions of it are installed
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or
+/// `GleamPkgError::InvalidResponse` if the metadata cannot be fetched
+fn print_info(ctx: &Context, spec: &PackageSpec) -> Result<(), GleamPkgError> {
+    let metadata = fetch_api(
+        ctx,
+        &spec.source,
+        &format!("packages/{}", spec.name),
+        &spec.name,
+    )?;
+    let meta = &metadata["meta"];
+    println!(
+        "{} {}",
+        output::paint(spec.source.qualify(&spec.name), output::Style::Bold),
+        // a package with nothing but pre-releases is still shown
+        extract_version(&metadata).or_else(|_| newest_version(&metadata))?
+    );
+    if let Some(description) = meta["description"].as_str() {
+        println!("{}", description);
+    }
+    println!();
+    let licenses: Vec<_> = meta["licenses"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|license| license.as_str())
+        .collect();
+    if !licenses.is_empty() {
+        println!("  {:<12}{}", "licenses", licenses.join(", "));
+    }
+    for (name, url) in meta["links"].as_object().into_iter().flatten() {
+        println!(
+            "  {:<12}{}",
+            name.to_lowercase(),
+            url.as_str().unwrap_or_default()
+        );
+    }
+    let db = Database::load(&ctx.paths.db_file())?;
+    if let Some(installed) = db.packages.get(&spec.name) {
+        let versions: Vec<_> = installed.versions.keys().cloned().collect();
+        println!("  {:<12}{}", "installed", versions.join(", "));
+    }
+    Ok(())
+}
+
+/// Fetches who publishes a package and how often it is downloaded
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or
+/// `GleamPkgError::InvalidResponse` if the metadata or owners cannot be fetched
+fn fetch_publisher(
+    ctx: &Context,
+    source: &Source,
+    package: &str,
+) -> Result<trust::Publisher, GleamPkgError> {
+    let metadata = fetch_api(ctx, source, &format!("packages/{}", package), package)?;
+    let owners = fetch_api(
+        ctx,
+        source,
+        &format!("packages/{}/owners", package),
+        &format!("{}/owners", package),
+    )?;
+    Ok(trust::Publisher::new(&metadata, &owners))
+}
+
+/// Prints the owners of a package and its downloads
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or
+/// `GleamPkgError::InvalidResponse` if they cannot be fetched
+fn print_owner(ctx: &Context, spec: &PackageSpec) -> Result<(), GleamPkgError> {
+    let publisher = fetch_publisher(ctx, &spec.source, &spec.name)?;
+    let mut table = output::Table::new(&["OWNER", "EMAIL", "LEVEL"]);
+    for owner in &publisher.owners {
+        table.styled_row(vec![
+            (owner.username.clone(), Some(output::Style::Bold)),
+            (owner.email.clone().unwrap_or_default(), None),
+            (
+                owner.level.clone().unwrap_or_default(),
+                Some(output::Style::Dim),
+            ),
+        ]);
+    }
+    table.print();
+    println!();
+    println!("Downloads: {}", publisher.describe_downloads());
+    Ok(())
+}
+
+/// Asks before installing a package that was never installed nor trusted before, showing who
+/// publishes it, and records it as trusted once confirmed
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::ConfirmationRequired` if stdin is not a terminal to ask on,
+/// `GleamPkgError::InstallDeclined` if the answer is no, or another `GleamPkgError` if the
+/// publisher cannot be fetched
+fn confirm_new_package(ctx: &Context, source: &Source, package: &str) -> Result<(), GleamPkgError> {
+    let db_path = ctx.paths.db_file();
+    let db = Database::load(&db_path)?;
+    if db.packages.contains_key(package) || db.trusted.contains(package) {
+        return Ok(());
+    }
+    if !prompt::assume_yes() && !prompt::interactive() {
+        return Err(GleamPkgError::ConfirmationRequired {
+            package: package.to_string(),
+        });
+    }
+    let publisher = fetch_publisher(ctx, source, package)?;
+    let question = format!(
+        "{} was never installed before\n  {:<12}{}\n  {:<12}{}\nInstall it?",
+        output::paint(source.qualify(package), output::Style::Bold),
+        "owners",
+        publisher.describe_owners(),
+        "downloads",
+        publisher.describe_downloads()
+    );
+    if !prompt::confirm(&question, false)? {
+        return Err(GleamPkgError::InstallDeclined {
+            package: package.to_string(),
+        });
+    }
+    let _lock = db::lock(&db_path);
+    let mut db = Database::load(&db_path)?;
+    db.trusted.insert(package.to_string());
+    db.save(&db_path)
+}
+
+/// Looks up the installed versions, and with `deps` their dependencies, in the vulnerability
+/// database and prints the advisories affecting them, see [`audit`]
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Vulnerable` if any version is affected, or
+/// `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or
+/// `GleamPkgError::InvalidResponse` if the database cannot be asked
+fn audit_packages(ctx: &Context, deps: bool) -> Result<(), GleamPkgError> {
+    let db = Database::load(&ctx.paths.db_file())?;
+    // each version to look up, with the installs depending on it
+    let mut queries: BTreeMap<audit::Query, Vec<String>> = BTreeMap::new();
+    for (name, installed) in &db.packages {
+        for (version, installed_version) in &installed.versions {
+            let query = audit::Query {
+                package: name.clone(),
+                version: version.clone(),
+            };
+            queries.entry(query).or_default();
+            if !deps {
+                continue;
+            }
+            let Some(dependencies) =
+                installed_version.load_dependencies(&ctx.paths, name, version)?
+            else {
+                output::warning(format!(
+                    "{} {} has no dependency manifest, reinstall it to check its dependencies",
+                    name, version
+                ));
+                continue;
+            };
+            for package in dependencies.packages.iter().filter(|p| p.source == "hex") {
+                let query = audit::Query {
+                    package: package.name.clone(),
+                    version: package.version.clone(),
+                };
+                queries
+                    .entry(query)
+                    .or_default()
+                    .push(format!("{}@{}", name, version));
+            }
+        }
+    }
+    if queries.is_empty() {
+        output::info(i18n::text(Message::NoPackagesInstalled));
+        return Ok(());
+    }
+
+    let (queries, required_by): (Vec<_>, Vec<_>) = queries.into_iter().unzip();
+    let results = audit::check(&ctx.config.audit, &ctx.config.http, &queries)?;
+    let mut headers = vec!["PACKAGE", "VERSION", "ADVISORY", "SEVERITY", "FIXED IN"];
+    if deps {
+        headers.push("REQUIRED BY");
+    }
+    let mut table = output::Table::new(&headers);
+    let mut advisories = BTreeMap::new();
+    let mut affected = 0;
+    for ((query, required_by), found) in queries.iter().zip(&required_by).zip(&results) {
+        affected += usize::from(!found.is_empty());
+        for advisory in found {
+            let mut row = vec![
+                (query.package.clone(), None),
+                (query.version.clone(), Some(output::Style::Red)),
+                (advisory.id.clone(), None),
+                (advisory.severity.clone().unwrap_or_default(), None),
+                (
+                    advisory.fixed_in.clone().unwrap_or("no fix".to_string()),
+                    Some(output::Style::Green),
+                ),
+            ];
+            if deps {
+                row.push((required_by.join(", "), Some(output::Style::Dim)));
+            }
+            table.styled_row(row);
+            advisories.insert(advisory.id.clone(), advisory);
+        }
+    }
+    if affected == 0 {
+        output::success(format!(
+            "No known vulnerabilities in {} package version(s)",
+            queries.len()
+        ));
+        return Ok(());
+    }
+    table.print();
+    println!();
+    for (id, advisory) in advisories {
+        let mut names = vec![id];
+        names.extend(advisory.aliases.iter().cloned());
+        println!("{}  {}", names.join(" "), advisory.summary);
+    }
+    println!();
+    Err(GleamPkgError::Vulnerable { count: affected })
+}
+
+/// Judges the licenses `package` declares on `source` by the `[licenses]` policy
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or
+/// `GleamPkgError::InvalidResponse` if the metadata of the package cannot be fetched
+fn check_license(
+    ctx: &Context,
+    source: &Source,
+    package: &str,
+    version: &str,
+) -> Result<licenses::Checked, GleamPkgError> {
+    let metadata = fetch_api(ctx, source, &format!("packages/{}", package), package)?;
+    let licenses = licenses::from_metadata(&metadata);
+    Ok(licenses::Checked {
+        package: package.to_string(),
+        version: version.to_string(),
+        verdict: ctx.config.licenses.verdict(&licenses),
+        licenses,
+    })
+}
+
+/// Reports a package whose licenses the `[licenses]` policy does not accept, failing if the
+/// policy refuses such packages
+///
+/// # Arguments
+///
+/// * `ctx` - The installation, whose configuration holds the policy
+/// * `checked` - The judged package
+/// * `required_by` - The package being installed, if `checked` is one of its dependencies
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::LicenseRefused` if the license is not accepted and
+/// `on_violation = "refuse"`
+fn enforce_license_policy(
+    ctx: &Context,
+    checked: &licenses::Checked,
+    required_by: Option<&str>,
+) -> Result<(), GleamPkgError> {
+    if checked.verdict == licenses::Verdict::Allowed {
+        return Ok(());
+    }
+    let error = GleamPkgError::LicenseRefused {
+        package: checked.package.clone(),
+        version: checked.version.clone(),
+        licenses: checked.licenses.clone(),
+        required_by: required_by.map(String::from),
+    };
+    match ctx.config.licenses.on_violation {
+        licenses::OnViolation::Refuse => Err(error),
+        licenses::OnViolation::Warn => {
+            output::warning(error);
+            Ok(())
+        }
+    }
+}
+
+/// Prints the licenses of the installed packages, and with `deps` of their dependencies, with
+/// how the `[licenses]` policy judges them
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or
+/// `GleamPkgError::InvalidResponse` if the metadata of a package cannot be fetched
+fn print_licenses(ctx: &Context, deps: bool) -> Result<(), GleamPkgError> {
+    let db = Database::load(&ctx.paths.db_file())?;
+    if db.packages.is_empty() {
+        output::info(i18n::text(Message::NoPackagesInstalled));
+        return Ok(());
+    }
+    let mut checked = Vec::new();
+    let mut dependencies = BTreeMap::new();
+    for (name, installed) in &db.packages {
+        checked.push(check_license(
+            ctx,
+            &installed.source,
+            name,
+            &installed.default_version,
+        )?);
+        if !deps {
+            continue;
+        }
+        for (version, installed_version) in &installed.versions {
+            for package in installed_version
+                .load_dependencies(&ctx.paths, name, version)?
+                .into_iter()
+                .flat_map(|d| d.packages)
+                .filter(|p| p.source == "hex" && !db.packages.contains_key(&p.name))
+            {
+                dependencies.insert(package.name, package.version);
+            }
+        }
+    }
+    for (package, version) in &dependencies {
+        checked.push(check_license(ctx, &Source::default(), package, version)?);
+    }
+
+    let policy = ctx.config.licenses.is_active();
+    let mut headers = vec!["PACKAGE", "VERSION", "LICENSES"];
+    if policy {
+        headers.push("POLICY");
+    }
+    let mut table = output::Table::new(&headers);
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for checked in &checked {
+        let mut row = vec![
+            (checked.package.clone(), None),
+            (checked.version.clone(), None),
+            (checked.licenses.join(", "), None),
+        ];
+        if policy {
+            let style = match checked.verdict {
+                licenses::Verdict::Allowed => output::Style::Green,
+                _ => output::Style::Red,
+            };
+            row.push((checked.verdict.name().to_string(), Some(style)));
+        }
+        table.styled_row(row);
+        if checked.licenses.is_empty() {
+            *counts.entry("none declared").or_default() += 1;
+        }
+        for license in &checked.licenses {
+            *counts.entry(license).or_default() += 1;
+        }
+    }
+    table.print();
+    let counts: Vec<_> = counts
+        .iter()
+        .map(|(license, count)| format!("{} ({})", license, count))
+        .collect();
+    println!("\n{}", counts.join(", "));
+    Ok(())
+}
+
+/// Prints every installed version of a package with where it came from and what built it
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::PackageNotInstalled` if the package is not installed
+fn print_installed_info(ctx: &Context, package: &str) -> Result<(), GleamPkgError> {
+    let db = Database::load(&ctx.paths.db_file())?;
+    let installed = db
+        .packages
+        .get(package)
+        .ok_or_else(|| GleamPkgError::PackageNotInstalled {
+            package: package.to_string(),
+            version: None,
+        })?;
+    let mut flags = Vec::new();
+    if installed.pinned {
+        flags.push("pinned".to_string());
+    }
+    if !installed.aliases.is_empty() {
+        let aliases: Vec<_> = installed.aliases.iter().cloned().collect();
+        flags.push(format!("as {}", aliases.join(",")));
+    }
+    println!(
+        "{} {}",
+        output::paint(installed.source.qualify(package), output::Style::Bold),
+        output::paint(flags.join(" "), output::Style::Dim)
+    );
+    for (version, installed_version) in &installed.versions {
+        let default = match *version == installed.default_version {
+            true => " (default)",
+            false => "",
+        };
+        println!("\n{}{}", version, default);
+        println!("  {:<12}{}", "target", describe_target(installed_version));
+        let Some(provenance) = &installed_version.provenance else {
+            println!("  {:<12}not recorded, reinstall to record it", "provenance");
+            continue;
+        };
+        println!("  {:<12}{}", "tarball", provenance.tarball_url);
+        let verified = match provenance.verified {
+            true => "matches the registry",
+            false => "not verified, the registry published none",
+        };
+        println!("  {:<12}{} ({})", "sha256", provenance.checksum, verified);
+        println!(
+            "  {:<12}{}",
+            "installed",
+            output::format_timestamp(provenance.installed_at)
+        );
+        for (tool, tool_version) in &provenance.toolchain {
+            println!("  {:<12}{}", tool, tool_version);
+        }
+    }
+    Ok(())
+}
+
+/// Prints a table of installed packages whose latest release is newer than their default version
+///
+/// # Arguments
+///
+/// * `ctx` - The installation to work on
+///
+/// # Errors
+///
+/// Returns `GleamPkgError` if the database cannot be read or metadata cannot be fetched
+///
+fn print_outdated(ctx: &Context) -> Result<(), GleamPkgError> {
+    let db = Database::load(&ctx.paths.db_file())?;
+    let mut table = output::Table::new(&["PACKAGE", "CURRENT", "LATEST", "FLAGS"]);
+    let mut outdated = 0;
+    let indexed = indexed_latest(ctx, &db.packages);
+    for (name, installed) in &db.packages {
+        let latest = match indexed.get(name) {
+            Some(latest) => latest.clone(),
+            None => {
+                let path = format!("packages/{}", name);
+                let metadata = fetch_api(ctx, &installed.source, &path, name)?;
+                extract_version(&metadata).or_else(|_| newest_version(&metadata))?
+            }
+        };
+        if !is_newer(&latest, &installed.default_version) {
+            continue;
+        }
+        outdated += 1;
+        table.styled_row(vec![
+            (name.clone(), None),
+            (
+                installed.default_version.clone(),
+                Some(output::Style::Yellow),
+            ),
+            (latest, Some(output::Style::Green)),
+            (
+                if installed.pinned { "pinned" } else { "" }.to_string(),
+                Some(output::Style::Dim),
+            ),
+        ]);
+    }
+    if outdated == 0 {
+        output::success(i18n::text(Message::AllUpToDate));
+    } else {
+        table.print();
+    }
+    Ok(())
+}
+
+/// Tells on stderr how many installed packages that are not pinned have updates, according to
+/// the versions indexes, see [`updatecheck`]
+fn notify_updates(ctx: &Context) {
+    let Ok(db) = Database::load(&ctx.paths.db_file()) else {
+        return;
+    };
+    let unpinned: Vec<_> = db
+        .packages
+        .iter()
+        .filter(|(_, installed)| !installed.pinned)
+        .collect();
+    let latest = indexed_latest(ctx, unpinned.iter().copied());
+    let count = unpinned
+        .iter()
+        .filter(|(name, installed)| {
+            latest
+                .get(*name)
+                .is_some_and(|latest| is_newer(latest, &installed.default_version))
+        })
+        .count();
+    if let Some(notice) = updatecheck::notice(count) {
+        output::notice(notice);
+    }
+}
+
+/// Whether `candidate` is a newer version than `installed`, comparing as semver when both parse
+fn is_newer(candidate: &str, installed: &str) -> bool {
+    match (
+        semver::Version::parse(candidate),
+        semver::Version::parse(installed),
+    ) {
+        (Ok(candidate), Ok(installed)) => candidate > installed,
+        _ => candidate != installed,
+    }
+}
+
+/// Sets or clears the pin of an installed package
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::PackageNotInstalled` if the package is not installed
+fn set_pinned(ctx: &Context, package: &str, pinned: bool) -> Result<(), GleamPkgError> {
+    let db_path = ctx.paths.db_file();
+    let _lock = db::lock(&db_path);
+    let mut db = Database::load(&db_path)?;
+    let installed = db.installed_mut(package)?;
+    installed.pinned = pinned;
+    if pinned {
+        println!("Pinned {} at {}", package, installed.default_version);
+    } else {
+        println!("Unpinned {}", package);
+    }
+    db.save(&db_path)
+}
+
+/// Runs a `gleam-pkg db` subcommand
+///
+/// # Errors
+///
+/// Returns the errors of [`Database::load`], `GleamPkgError::Io` if the database or the copy of
+/// the old one cannot be written, or `GleamPkgError::DatabaseInconsistent` if `check` finds
+/// problems
+fn db_command(ctx: &Context, command: DbCommand) -> Result<(), GleamPkgError> {
+    let db_path = ctx.paths.db_file();
+    match command {
+        DbCommand::Migrate => {
+            let _lock = db::lock(&db_path);
+            let (db, stored) = Database::load_stored(&db_path)?;
+            if stored == db::SCHEMA {
+                output::info(format!("The database is in schema {} already", stored));
+                return Ok(());
+            }
+            // the document as stored, which the old gleam-pkg can read again
+            let backup = db_path.with_file_name(format!("metadata.schema-{}.json", stored));
+            if let Some(doc) = db::storage(&db_path).read()? {
+                db::JsonFile::new(&backup).write(&doc)?;
+            }
+            db.save(&db_path)?;
+            for migration in db::MIGRATIONS.iter().filter(|m| m.to > stored) {
+                output::info(format!(
+                    "Schema {}: {}",
+                    migration.to, migration.description
+                ));
+            }
+            output::success(format!(
+                "Migrated the database from schema {} to {}, the old one is in {}",
+                stored,
+                db::SCHEMA,
+                backup.display()
+            ));
+        }
+        DbCommand::Check => {
+            let (db, stored) = Database::load_stored(&db_path)?;
+            if stored != db::SCHEMA {
+                output::info(format!(
+                    "The database is in schema {}, `gleam-pkg db migrate` writes it in schema {}",
+                    stored,
+                    db::SCHEMA
+                ));
+            }
+            let problems = db.check(&ctx.paths);
+            if problems.is_empty() {
+                output::success(format!(
+                    "{} package(s) recorded, all installed",
+                    db.packages.len()
+                ));
+                return Ok(());
+            }
+            for problem in &problems {
+                if output::porcelain() {
+                    output::record(&[problem]);
+                } else {
+                    output::warning(problem);
+                }
+            }
+            return Err(GleamPkgError::DatabaseInconsistent {
+                count: problems.len(),
+            });
+        }
+        DbCommand::Dump => {
+            let db = Database::load(&db_path)?;
+            let json = serde_json::to_string_pretty(&db).map_err(|e| GleamPkgError::Io {
+                action: "print package database",
+                path: db_path,
+                source: std::io::Error::other(e),
+            })?;
+            println!("{}", json);
+        }
+    }
+    Ok(())
+}
+
+/// Fetches the hex metadata of a package
+///
+/// # Arguments
+///
+/// * `ctx` - The installation holding the cache
+/// * `source` - The repository and organization of the package
+/// * `package` - The name of the package
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::UnknownRepository` if the repository is not configured, or
+/// `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or
+/// `GleamPkgError::InvalidResponse` if the metadata cannot be fetched
+///
+fn fetch_metadata(
+    ctx: &Context,
+    source: &Source,
+    package: &str,
+) -> Result<serde_json::Value, GleamPkgError> {
+    let path = format!("packages/{}", package);
+    let url = registry::open(&ctx.config, source)?.api_url(&path);
+    output::info(i18n::fill(Message::InspectingPackage, &[("url", &url)]));
+    let metadata = stats::time("metadata", || fetch_api(ctx, source, &path, package)).map_err(
+        |e| match e {
+            GleamPkgError::HttpStatus { url, status: 404 } => GleamPkgError::UnknownPackage {
+                package: package.to_string(),
+                url,
+                suggestion: names_index(ctx, source)
+                    .inspect_err(|e| tracing::debug!("no package names to suggest: {}", e))
+                    .ok()
+                    .and_then(|names| names.suggest(package).map(String::from)),
+            },
+            e => e,
+        },
+    )?;
+    events::emit(events::Event::MetadataFetched {
+        package: package.to_string(),
+    });
+    Ok(metadata)
+}
+
+/// Fetches the hex metadata of a single release of a package
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or
+/// `GleamPkgError::InvalidResponse` if the metadata cannot be fetched
+fn fetch_release(
+    ctx: &Context,
+    source: &Source,
+    package: &str,
+    version: &str,
+) -> Result<serde_json::Value, GleamPkgError> {
+    fetch_api(
+        ctx,
+        source,
+        &format!("packages/{}/releases/{}", package, version),
+        &format!("{}@{}", package, version),
+    )
+}
+
+/// Prints the packages of hex.pm matching `query`
+///
+/// Online the API is searched, and the package names kept for `--offline` and completion are
+/// refreshed if they are stale; offline those names are searched instead, see [`names`].
+///
+/// # Errors
+///
+/// Returns the errors of [`search_packages`] online, and of [`names_index`] offline
+fn search(ctx: &Context, query: &str) -> Result<(), GleamPkgError> {
+    let source = Source::default();
+    if http::offline() {
+        let names = names_index(ctx, &source)?;
+        let found = names.search(query);
+        if found.is_empty() {
+            output::info(format!("No package names match {}", query));
+            return Ok(());
+        }
+        let mut table = output::Table::new(&["NAME"]);
+        for name in found {
+            table.styled_row(vec![(name.to_string(), None)]);
+        }
+        let _pager = output::pager();
+        table.print();
+        return Ok(());
+    }
+
+    if let Err(e) = names_index(ctx, &source) {
+        tracing::debug!("package names not refreshed: {}", e);
+    }
+    let results = search_packages(ctx, query)?;
+    if results.is_empty() {
+        output::info(format!("No packages match {}", query));
+        return Ok(());
+    }
+    let mut table = output::Table::new(&["NAME", "VERSION", "DESCRIPTION"]);
+    for package in &results {
+        let field = |value: &serde_json::Value| value.as_str().unwrap_or("").to_string();
+        let version = match package["latest_stable_version"].is_string() {
+            true => &package["latest_stable_version"],
+            false => &package["latest_version"],
+        };
+        table.styled_row(vec![
+            (field(&package["name"]), Some(output::Style::Bold)),
+            (field(version), None),
+            (field(&package["meta"]["description"]), None),
+        ]);
+    }
+    let _pager = output::pager();
+    table.print();
+    Ok(())
+}
+
+/// The package names of the repository serving `source`, fetched again when the kept ones are
+/// older than `[cache] names_ttl_secs`, see [`names`]
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::UnknownRepository` if the repository is not configured, or the
+/// errors of [`Registry::fetch_names`] if the names cannot be fetched and none are kept
+fn names_index(ctx: &Context, source: &Source) -> Result<names::NamesIndex, GleamPkgError> {
+    let path = names_file(ctx, source);
+    match names::NamesIndex::load(&path) {
+        Some(kept) if cache::is_fresh(kept.fetched_at, ctx.config.names_ttl()) => Ok(kept),
+        kept => match registry::open(&ctx.config, source)?.fetch_names() {
+            Ok(names) => {
+                let fetched_at = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default();
+                let index = names::NamesIndex::new(names, fetched_at);
+                if !plan::dry_run() {
+                    if let Err(e) = index.save(&path) {
+                        output::warning(format!("failed to keep the package names: {}", e));
+                    }
+                }
+                Ok(index)
+            }
+            Err(e) => kept.ok_or(e),
+        },
+    }
+}
+
+/// Where the package names of `source` are kept
+fn names_file(ctx: &Context, source: &Source) -> PathBuf {
+    ctx.paths
+        .cache()
+        .join("names")
+        .join(format!("{}.gz", source.name()))
+}
+
+/// Searches hex.pm for packages matching a query
+///
+/// Search results are not cached, every call contacts hex.pm.
+///
+/// # Arguments
+///
+/// * `ctx` - The installation whose API base to search
+/// * `query` - The search terms
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or
+/// `GleamPkgError::InvalidResponse` if the search fails
+///
+/// # Returns
+///
+/// The matching packages as returned by the API, most downloaded first
+///
+fn search_packages(ctx: &Context, query: &str) -> Result<Vec<serde_json::Value>, GleamPkgError> {
+    let client = http::client(&ctx.config.http)?;
+    let url = format!("{}packages", ctx.config.api_base);
+    let request = client
+        .get(&url)
+        .query(&[("search", query), ("sort", "downloads")])
+        .header("accept", "application/json");
+    let response = http::send(request).map_err(|source| GleamPkgError::RequestFailed {
+        url: url.clone(),
+        source,
+    })?;
+
+    if !response.status().is_success() {
+        return Err(GleamPkgError::HttpStatus {
+            url,
+            status: response.status().as_u16(),
+        });
+    }
+
+    response
+        .json::<Vec<serde_json::Value>>()
+        .map_err(|source| GleamPkgError::InvalidResponse { url, source })
+}
+
+/// Fetches a JSON document from a hex API, going through the metadata cache
+///
+/// A cached response younger than the configured TTL is returned without contacting the
+/// registry. An older one is revalidated with `If-None-Match`/`If-Modified-Since` and reused
+/// when the server answers `304 Not Modified`.
+///
+/// # Arguments
+///
+/// * `ctx` - The installation holding the cache
+/// * `source` - The repository and organization to ask
+/// * `path` - The API path below the API base of `source`
+/// * `cache_key` - The name the response is cached under, qualified with `source`
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::UnknownRepository` if the repository is not configured, or
+/// `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or
+/// `GleamPkgError::InvalidResponse` if the document cannot be fetched
+///
+fn fetch_api(
+    ctx: &Context,
+    source: &Source,
+    path: &str,
+    cache_key: &str,
+) -> Result<serde_json::Value, GleamPkgError> {
+    let registry = registry::open(&ctx.config, source)?;
+    let cache = MetadataCache::new(&ctx.paths.cache().join("metadata"));
+    fetch_cached(
+        &cache,
+        registry.as_ref(),
+        path,
+        &source.qualify(cache_key),
+        ctx.config.metadata_ttl(),
+    )
+}
+
+/// The caching behind [`fetch_api`], for any registry and cache
+///
+/// # Arguments
+///
+/// * `cache` - The metadata cache
+/// * `registry` - The registry to ask when the cache cannot answer
+/// * `path` - The API path below the API base of `registry`
+/// * `cache_key` - The name the response is cached under
+/// * `ttl` - How long a cached response is used without revalidation
+///
+/// # Errors
+///
+/// Returns whatever error `registry` fails with
+///
+fn fetch_cached(
+    cache: &MetadataCache,
+    registry: &dyn Registry,
+    path: &str,
+    cache_key: &str,
+    ttl: Duration,
+) -> Result<serde_json::Value, GleamPkgError> {
+    cached_document(cache, cache_key, ttl, &registry.api_url(path), |cached| {
+        registry.fetch_json(path, cached)
+    })
+}
+
+/// Returns the document cached under `cache_key` while it is fresh, and otherwise fetches it
+/// conditional on the cached copy and caches the result
+///
+/// # Arguments
+///
+/// * `cache` - The cache holding the document
+/// * `cache_key` - The name the document is cached under
+/// * `ttl` - How long a cached document is used without revalidation
+/// * `url` - Where the document comes from, for errors
+/// * `fetch` - Fetches the document, conditional on the cached copy if given
+///
+/// # Errors
+///
+/// Returns whatever error `fetch` fails with
+///
+fn cached_document(
+    cache: &MetadataCache,
+    cache_key: &str,
+    ttl: Duration,
+    url: &str,
+    fetch: impl FnOnce(Option<&CachedMetadata>) -> Result<ApiResponse, GleamPkgError>,
+) -> Result<serde_json::Value, GleamPkgError> {
+    let cached = cache.get(cache_key);
+    if let Some(cached) = cached.as_ref().filter(|c| c.is_fresh(ttl)) {
+        stats::record_cache(true);
+        return Ok(cached.body.clone());
+    }
+
+    let (body, etag, last_modified) = match (fetch(cached.as_ref())?, cached) {
+        (ApiResponse::NotModified, Some(mut cached)) => {
+            stats::record_cache(true);
+            cached.touch();
+            if !plan::dry_run() {
+                let _ = cache.put(cache_key, &cached);
+            }
+            return Ok(cached.body);
+        }
+        // a registry only answers this to a request conditional on a cached copy
+        (ApiResponse::NotModified, None) => {
+            return Err(GleamPkgError::HttpStatus {
+                url: url.to_string(),
+                status: 304,
+            });
+        }
+        (
+            ApiResponse::Document {
+                body,
+                etag,
+                last_modified,
+            },
+            _,
+        ) => (body, etag, last_modified),
+    };
+
+    stats::record_cache(false);
+    if plan::dry_run() {
+        return Ok(body);
+    }
+    if let Err(e) = cache.put(
+        cache_key,
+        &CachedMetadata::new(etag, last_modified, body.clone()),
+    ) {
+        output::warning(format!("failed to cache metadata for {}: {}", cache_key, e));
+    }
+    Ok(body)
+}
+
+/// Fetches the versions index of the repository serving `source`, going through the cache
+/// like [`fetch_api`], see [`index`]
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::UnknownRepository` if the repository is not configured, or
+/// `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus`,
+/// `GleamPkgError::InvalidResponse` or `GleamPkgError::InvalidIndex` if the index cannot be
+/// fetched
+fn fetch_index(ctx: &Context, source: &Source) -> Result<VersionsIndex, GleamPkgError> {
+    let registry = registry::open(&ctx.config, source)?;
+    let cache = MetadataCache::new(&ctx.paths.cache().join("index"));
+    let url = registry::versions_url(&registry.repositories()[0]);
+    let body = cached_document(
+        &cache,
+        &source.name(),
+        ctx.config.index_ttl(),
+        &url,
+        |cached| registry.fetch_versions(cached),
+    )?;
+    serde_json::from_value(body).map_err(|e| GleamPkgError::InvalidIndex {
+        url,
+        message: e.to_string(),
+    })
+}
+
+/// The latest versions of `packages` according to the versions indexes of their sources
+///
+/// Packages missing from the index, or whose repository serves none, are left out; callers ask
+/// the API about them instead.
+fn indexed_latest<'a>(
+    ctx: &Context,
+    packages: impl IntoIterator<Item = (&'a String, &'a db::InstalledPackage)>,
+) -> BTreeMap<String, String> {
+    let mut indexes: BTreeMap<String, Option<VersionsIndex>> = BTreeMap::new();
+    let mut latest = BTreeMap::new();
+    for (name, installed) in packages {
+        let index = indexes.entry(installed.source.name()).or_insert_with(|| {
+            match fetch_index(ctx, &installed.source) {
+                Ok(index) => Some(index),
+                Err(e) => {
+                    tracing::debug!("no versions index for {}: {}", installed.source.name(), e);
+                    None
+                }
+            }
+        });
+        if let Some(version) = index.as_ref().and_then(|index| index.latest(name)) {
+            latest.insert(name.clone(), version.to_string());
+        }
+    }
+    latest
+}
+
+/// Extracts the latest version of a package from its metadata: its newest stable release by
+/// semver that is not retired
+///
+/// # Arguments
+///
+/// * `metadata` - The metadata of the package
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::NoReleases` if the metadata lists no releases,
+/// `GleamPkgError::OnlyPreReleases` if every release is a pre-release, or
+/// `GleamPkgError::OnlyRetiredReleases` if every stable release is retired
+///
+fn extract_version(metadata: &serde_json::Value) -> Result<String, GleamPkgError> {
+    let versions = releases::versions(metadata);
+    let package = package_name(metadata);
+    let mut stable = versions.iter().filter(|version| version.pre.is_empty());
+    if let Some(latest) = stable
+        .clone()
+        .find(|version| !releases::retired(metadata, version))
+    {
+        return Ok(latest.to_string());
+    }
+    match (versions.first(), stable.next()) {
+        (None, _) => Err(GleamPkgError::NoReleases { package }),
+        (Some(newest), None) => Err(GleamPkgError::OnlyPreReleases {
+            package,
+            newest: newest.to_string(),
+        }),
+        (Some(_), Some(_)) => Err(GleamPkgError::OnlyRetiredReleases { package }),
+    }
+}
+
+/// Extracts the newest version of a package from its metadata, pre-releases included, for
+/// `install --latest`; retired releases are only taken when every release is
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::NoReleases` if the metadata lists no releases
+fn newest_version(metadata: &serde_json::Value) -> Result<String, GleamPkgError> {
+    let versions = releases::versions(metadata);
+    versions
+        .iter()
+        .find(|version| !releases::retired(metadata, version))
+        .or(versions.first())
+        .map(semver::Version::to_string)
+        .ok_or_else(|| GleamPkgError::NoReleases {
+            package: package_name(metadata),
+        })
+}
+
+/// The name of the package described by `metadata`
+fn package_name(metadata: &serde_json::Value) -> String {
+    metadata["name"]
+        .as_str()
+        .unwrap_or("the package")
+        .to_string()
+}
+
+/// Finds the release a requested version names in a package's metadata
+///
+/// A version with fewer parts, e.g. `1` or `1.2`, names every release it is a prefix of; when
+/// several match, the user picks one, the newest by default, see [`prompt::select`].
+///
+/// # Arguments
+///
+/// * `metadata` - The metadata of the package
+/// * `version` - The requested version
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::NoReleases` if the metadata lists no releases, or
+/// `GleamPkgError::ReleaseNotFound` if the package has no release with that version
+///
+fn find_release(metadata: &serde_json::Value, version: &str) -> Result<String, GleamPkgError> {
+    let releases = metadata["releases"]
+        .as_array()
+        .ok_or_else(|| GleamPkgError::NoReleases {
+            package: package_name(metadata),
+        })?;
+    let versions: Vec<&str> = releases
+        .iter()
+        .filter_map(|release| release["version"].as_str())
+        .collect();
+    if versions.contains(&version) {
+        return Ok(version.to_string());
+    }
+
+    let prefix = format!("{}.", version);
+    let mut matching: Vec<String> = versions
+        .into_iter()
+        .filter(|release| release.starts_with(&prefix))
+        .map(String::from)
+        .collect();
+    match matching.len() {
+        0 => Err(GleamPkgError::ReleaseNotFound {
+            package: package_name(metadata),
+            version: version.to_string(),
+        }),
+        1 => Ok(matching.remove(0)),
+        _ => {
+            let question = format!(
+                "Several releases of {} match {}, which one should be installed?",
+                package_name(metadata),
+                version
+            );
+            let picked = prompt::select(&question, &matching, 0)?;
+            Ok(matching.swap_remove(picked))
+        }
+    }
+}
+
+/// Downloads the tarball of a release to `download/<package>-<version>.tar`
+///
+/// The tarball is downloaded to a `.part` file next to it, which is kept when the download
+/// fails so the next attempt continues where this one stopped. When the repository fails with a
+/// network or server error, its mirrors are tried in turn, see [`mirrors`]. The finished
+/// download is checked against the checksum in the release metadata before it replaces the
+/// tarball. With a shared cache configured, a tarball with that checksum is taken from it
+/// instead, and downloads are added to it, see [`sharedcache`].
+///
+/// # Arguments
+///
+/// * `ctx` - The installation to download into
+/// * `source` - The repository and organization of the package
+/// * `package` - The name of the package
+/// * `version` - The version of the package
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or `GleamPkgError::Io` if
+/// the download fails from every mirror, or `GleamPkgError::ChecksumMismatch` if the tarball is
+/// not the published one
+///
+/// # Returns
+///
+/// Where the tarball came from, for the provenance of the install
+///
+fn download_tarball(
+    ctx: &Context,
+    source: &Source,
+    package: &str,
+    version: &str,
+) -> Result<Downloaded, GleamPkgError> {
+    let registry = registry::open(&ctx.config, source)?;
+    let release = fetch_release(ctx, source, package, version)?;
+    let tarball = ctx
+        .paths
+        .download()
+        .join(format!("{}-{}.tar", package, version));
+    let expected = release["checksum"].as_str();
+    let shared = ctx.config.cache.shared_dir.as_deref().map(SharedCache::new);
+    if let (Some(shared), Some(expected)) = (&shared, expected) {
+        if shared.fetch(expected, &tarball) {
+            let entry = shared.path(expected).unwrap_or_default();
+            output::info(format!("Using the tarball from: {}", entry.display()));
+            return Ok(Downloaded {
+                tarball_url: format!("file://{}", entry.display()),
+                checksum: expected.to_lowercase(),
+                verified: true,
+            });
+        }
+    }
+    let part = tarball.with_extension("tar.part");
+    let health = ctx.paths.mirror_health();
+    let repositories = mirrors::HealthRecord::load(&health).order(registry.repositories());
+    let mut repositories = repositories.iter().peekable();
+    let mut tarball_url = String::new();
+    while let Some(repository) = repositories.next() {
+        tarball_url = registry::tarball_url(repository, package, version);
+        output::info(i18n::fill(
+            Message::DownloadingPackage,
+            &[("url", &tarball_url)],
+        ));
+        events::emit(events::Event::DownloadStarted {
+            package: package.to_string(),
+            version: version.to_string(),
+            url: tarball_url.clone(),
+        });
+        let offset = fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+        if offset > 0 {
+            output::info(format!(
+                "Resuming an earlier download after {}",
+                output::format_size(offset)
+            ));
+        }
+        let result = registry.download_tarball(repository, package, version, &part);
+        mirrors::record(&health, repository, result.as_ref().map(|_| ()));
+        match (result, repositories.peek()) {
+            (Ok(()), _) => break,
+            (Err(e), Some(_)) if mirrors::should_fail_over(&e) => {
+                output::warning(format!("{}, trying the next mirror", e.report()))
+            }
+            (Err(e), _) => return Err(e),
+        }
+    }
+
+    let actual = checksum::sha256_file(&part)?;
+    match expected {
+        Some(expected) => {
+            if !checksum::matches(expected, &actual) {
+                let _ = fs::remove_file(&part);
+                return Err(GleamPkgError::ChecksumMismatch {
+                    package: package.to_string(),
+                    version: version.to_string(),
+                    expected: expected.to_lowercase(),
+                    actual,
+                });
+            }
+        }
+        None => output::warning(format!(
+            "The release metadata of {} {} has no checksum, the tarball is not verified",
+            package, version
+        )),
+    }
+    fs::rename(&part, &tarball).map_err(|source| GleamPkgError::Io {
+        action: "save tarball",
+        path: tarball.clone(),
+        source,
+    })?;
+    output::info(i18n::fill(
+        Message::TarballSaved,
+        &[("path", &tarball.display())],
+    ));
+    if let (Some(shared), Some(_)) = (&shared, expected) {
+        if let Err(e) = shared.store(&actual, &tarball) {
+            output::warning(format!("{}, the next user downloads it again", e.report()));
+        }
+    }
+    Ok(Downloaded {
+        tarball_url,
+        checksum: actual,
+        verified: expected.is_some(),
+    })
+}
+
+/// Checks that the registry still publishes the tarball a release installed before was
+/// installed from, see [`Database::recorded_checksum`]
+///
+/// A republished release is rare, and as likely a compromised account as a fixed mistake, so
+/// it is only installed with `opts.accept_new_checksum`.
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::ChecksumChanged` if the registry publishes another tarball and
+/// `opts.accept_new_checksum` is not set, or the errors of [`fetch_release`]
+///
+/// # Returns
+///
+/// Whether the release was republished and its new tarball accepted
+fn check_republished(
+    ctx: &Context,
+    db: &Database,
+    source: &Source,
+    package: &str,
+    version: &str,
+    opts: &InstallOptions,
+) -> Result<bool, GleamPkgError> {
+    let Some(recorded) = db.recorded_checksum(source, package, version) else {
+        return Ok(false);
+    };
+    let release = fetch_release(ctx, source, package, version)?;
+    let Some(published) = release["checksum"].as_str() else {
+        return Ok(false);
+    };
+    if checksum::matches(published, recorded) {
+        return Ok(false);
+    }
+    if !opts.accept_new_checksum {
+        return Err(GleamPkgError::ChecksumChanged {
+            package: package.to_string(),
+            version: version.to_string(),
+            recorded: recorded.to_string(),
+            published: published.to_lowercase(),
+        });
+    }
+    output::warning(format!(
+        "The registry republished {} {}, installing its new tarball {} in place of {}",
+        package,
+        version,
+        published.to_lowercase(),
+        recorded
+    ));
+    Ok(true)
+}
+
+/// A tarball [`download_tarball`] saved
+struct Downloaded {
+    /// The URL it was downloaded from
+    tarball_url: String,
+    /// Its SHA-256 checksum
+    checksum: String,
+    /// Whether the checksum was checked against the published one
+    verified: bool,
+}
+
+/// Extracts a tarball to disk
+///
+/// # Arguments
+///
+/// * `download_dir` - The directory where the tarball is saved
+/// * `package` - The name of the package
+/// * `version` - The version of the package
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the tarball cannot be extracted
+///
+fn extract(download_dir: &Path, package: &str, version: &str) -> Result<(), GleamPkgError> {
+    let tarball_path = download_dir.join(format!("{}-{}.tar", package, version));
+    let extract_dir = download_dir.join(format!("{}-{}", package, version));
+
+    // if extract_dir exists, remove it
+    if extract_dir.exists() {
+        fs::remove_dir_all(&extract_dir).map_err(|source| GleamPkgError::Io {
+            action: "remove extract directory",
+            path: extract_dir.clone(),
+            source,
+        })?;
+    }
+    fs::create_dir(&extract_dir).map_err(|source| GleamPkgError::Io {
+        action: "create extract directory",
+        path: extract_dir.clone(),
+        source,
+    })?;
+
+    let tar = fs::File::open(&tarball_path).map_err(|source| GleamPkgError::Io {
+        action: "open tarball",
+        path: tarball_path.clone(),
+        source,
+    })?;
+    let mut archive = tar::Archive::new(tar);
+    archive
+        .unpack(&extract_dir)
+        .map_err(|source| GleamPkgError::Io {
+            action: "extract tarball",
+            path: tarball_path.clone(),
+            source,
+        })?;
+    output::info(i18n::fill(
+        Message::TarballExtracted,
+        &[("path", &extract_dir.display())],
+    ));
+    // then enter the extracted directory and extract contents.tar.gz to contents
+    let contents_tar_gz = extract_dir.join("contents.tar.gz");
+    let contents_dir = extract_dir.join("contents");
+    let contents_tar = fs::File::open(&contents_tar_gz).map_err(|source| GleamPkgError::Io {
+        action: "open contents tarball",
+        path: contents_tar_gz.clone(),
+        source,
+    })?;
+    let decoder = GzDecoder::new(contents_tar);
+    let mut contents_tar = tar::Archive::new(decoder);
+    contents_tar
+        .unpack(&contents_dir)
+        .map_err(|source| GleamPkgError::Io {
+            action: "extract contents tarball",
+            path: contents_tar_gz.clone(),
+            source,
+        })?;
+    output::info(i18n::fill(
+        Message::ContentsExtracted,
+        &[("path", &contents_dir.display())],
+    ));
+    Ok(())
+}
+
+/// Builds a package
+/// This involves building a scratch project wrapping the package with the given backend and
+/// writing the wrapper script, leaving the extracted package sources untouched
+///
+/// # Arguments
+///
+/// * `ctx` - The installation to build into, with the package downloaded and extracted
+/// * `package` - The name of the package
+/// * `version` - The version of the package
+/// * `backend` - The backend used to build and run the package
+/// * `opts` - The resource limits applied to every spawned process, whether to embed the
+///   Erlang/OTP installation an escript runs on in `lib/<package>-<version>/erts`, and how the
+///   wrapper runs it
+///
+/// # Errors
+///
+/// Returns `GleamPkgError` if the package cannot be built, or `GleamPkgError::BuildTimeout` if
+/// a build step exceeds its time limit
+///
+/// # Returns
+///
+/// The artifact the wrapper script runs
+///
+fn build_package(
+    ctx: &Context,
+    package: &str,
+    version: &str,
+    backend: &dyn Backend,
+    opts: &InstallOptions,
+) -> Result<Artifact, GleamPkgError> {
+    let (limits, bundle_erts) = (&opts.limits, opts.bundle_erts);
+    // the package sources are left untouched: a scratch project next to them depends on the
+    // package by path, and is where everything gets built
+    let extract_dir = ctx
+        .paths
+        .download()
+        .join(format!("{}-{}", package, version));
+    let project_dir = backend.prepare(&extract_dir, package)?;
+    let mut log = BuildLog::create(&ctx.paths.logs(), package, version)?;
+
+    // first remove the existing ~/.gleam_pkgs/lib/{package}-{version} directory
+    let app_dir = ctx.paths.lib().join(format!("{}-{}", package, version));
+    let _ = fs::remove_dir_all(&app_dir);
+    fs::create_dir_all(&app_dir).map_err(|source| GleamPkgError::Io {
+        action: "create app directory",
+        path: app_dir.clone(),
+        source,
+    })?;
+
+    let store = Store::new(&ctx.paths.store());
+    let build = BuildContext {
+        package,
+        version,
+        project_dir: &project_dir,
+        app_dir: &app_dir,
+        store: &store,
+        toolchains: &ctx.paths.toolchains(),
+        limits,
+        wrapper: opts.wrapper.unwrap_or(ctx.config.wrapper),
+    };
+    let mut artifact = backend.build(&build, &mut log)?;
+    match artifact.otp_release.filter(|_| bundle_erts) {
+        Some(otp_release) => {
+            let erl = exec::find_erl(otp_release, &ctx.paths, limits)?;
+            let erts = app_dir.join("erts");
+            toolchains::embed_otp(&erl, &erts, limits)?;
+            artifact.erts = Some(erts);
+        }
+        None if bundle_erts => output::warning(format!(
+            "{} {} does not run on the BEAM, it is built without an embedded Erlang/OTP",
+            package, version
+        )),
+        None => {}
+    }
+    let wrapper_code = backend::dispatch_to_project(
+        backend::export_env(
+            backend.wrapper(&build, &artifact)?,
+            ctx.config.package_env(package),
+        ),
+        &ctx.paths.apps(),
+        package,
+        version,
+    );
+    let smoke_args = ctx.config.smoke_test_args(package);
+    if !smoke_args.is_empty() {
+        let contents =
+            wrapper_contents(build.wrapper, &artifact, wrapper_code.clone()).map_err(|source| {
+                GleamPkgError::Io {
+                    action: "read escript",
+                    path: artifact.path.clone(),
+                    source,
+                }
+            })?;
+        stats::time("smoke test", || {
+            smoke::check(
+                package,
+                version,
+                &contents,
+                smoke_args,
+                ctx.config.smoke_test_timeout(),
+                limits,
+                &mut log,
+            )
+        })?;
+    }
+    stats::time("wrapper", || {
+        install_wrapper(
+            ctx,
+            package,
+            version,
+            build.wrapper,
+            &artifact,
+            wrapper_code,
+        )
+    })?;
+    path_check(&ctx.paths)?;
+
+    Ok(artifact)
+}
+
+/// Writes the executable `apps/<package>-<version>` wrapper script
+///
+/// The wrapper runs the build artifact and is named after the version, so several versions can
+/// be installed side by side.
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the wrapper cannot be written
+fn write_wrapper(
+    ctx: &Context,
+    package: &str,
+    version: &str,
+    wrapper_code: &[u8],
+) -> Result<(), GleamPkgError> {
+    let wrapper = ctx.paths.apps().join(format!("{}-{}", package, version));
+    let _ = fs::remove_file(&wrapper);
+    let mut file = fs::File::create(&wrapper).map_err(|source| GleamPkgError::Io {
+        action: "create wrapper script",
+        path: wrapper.clone(),
+        source,
+    })?;
+
+    file.write_all(wrapper_code)
+        .map_err(|source| GleamPkgError::Io {
+            action: "write wrapper script",
+            path: wrapper.clone(),
+            source,
+        })?;
+
+    // add execute permission to the wrapper script using Unix permissions
+    let mut perms = file
+        .metadata()
+        .map_err(|source| GleamPkgError::Io {
+            action: "read wrapper script metadata",
+            path: wrapper.clone(),
+            source,
+        })?
+        .permissions();
+    perms.set_mode(0o755);
+    file.set_permissions(perms)
+        .map_err(|source| GleamPkgError::Io {
+            action: "set wrapper script permissions",
+            path: wrapper.clone(),
+            source,
+        })
+}
+
+/// Writes the `apps/<package>-<version>` wrapper of a version the way `mode` runs it, see
+/// [`WrapperMode`]: `code`, followed by the escript for embedded wrappers, or a link to the
+/// escript in the store
+///
+/// The wrappers of builds other than escripts, and of escripts with an embedded runtime, which
+/// have to choose it, run them directly whatever `mode` is.
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the wrapper cannot be written
+///
+/// # Returns
+///
+/// Whether the wrapper changed
+fn install_wrapper(
+    ctx: &Context,
+    package: &str,
+    version: &str,
+    mode: WrapperMode,
+    artifact: &Artifact,
+    code: String,
+) -> Result<bool, GleamPkgError> {
+    let wrapper = ctx.paths.apps().join(format!("{}-{}", package, version));
+    let escript = artifact.blob.as_ref().filter(|_| artifact.erts.is_none());
+    let io_error = |source| GleamPkgError::Io {
+        action: "write wrapper script",
+        path: wrapper.clone(),
+        source,
+    };
+    let is_link = wrapper.symlink_metadata().is_ok_and(|m| m.is_symlink());
+    match (mode, escript) {
+        (WrapperMode::Symlink, Some(_)) => {
+            if is_link && fs::read_link(&wrapper).is_ok_and(|target| target == artifact.path) {
+                return Ok(false);
+            }
+            // run through its `#!/usr/bin/env escript` line
+            fs::set_permissions(&artifact.path, fs::Permissions::from_mode(0o755))
+                .map_err(io_error)?;
+            let _ = fs::remove_file(&wrapper);
+            std::os::unix::fs::symlink(&artifact.path, &wrapper).map_err(io_error)?;
+        }
+        (mode, _) => {
+            let contents = wrapper_contents(mode, artifact, code).map_err(io_error)?;
+            if !is_link && fs::read(&wrapper).is_ok_and(|existing| existing == contents) {
+                return Ok(false);
+            }
+            write_wrapper(ctx, package, version, &contents)?;
+        }
+    }
+    Ok(true)
+}
+
+/// The contents of a wrapper written by [`install_wrapper`] rather than linked: `code`, followed
+/// by the escript for embedded wrappers
+///
+/// # Errors
+///
+/// Returns the error of reading the escript
+fn wrapper_contents(
+    mode: WrapperMode,
+    artifact: &Artifact,
+    code: String,
+) -> std::io::Result<Vec<u8>> {
+    let mut contents = code.into_bytes();
+    if mode == WrapperMode::Embedded && artifact.blob.is_some() && artifact.erts.is_none() {
+        contents.extend(fs::read(&artifact.path)?);
+    }
+    Ok(contents)
+}
+
+/// The artifact an installed version runs, as far as the package database records it
+///
+/// # Returns
+///
+/// The artifact, or `None` for versions built on the BEAM before their escripts were kept in the
+/// store, which cannot be run without a rebuild
+fn installed_artifact(
+    ctx: &Context,
+    package: &str,
+    version: &str,
+    installed: &db::InstalledVersion,
+) -> Option<Artifact> {
+    let path = match (&installed.blob, installed.target) {
+        (Some(blob), _) => Store::new(&ctx.paths.store()).path(blob),
+        (None, Target::Node | Target::Deno) => ctx
+            .paths
+            .lib()
+            .join(format!("{}-{}", package, version))
+            .join("main.mjs"),
+        (None, _) => return None,
+    };
+    let runtime = installed
+        .provenance
+        .as_ref()
+        .and_then(|p| p.toolchain.get(installed.target.backend().name()))
+        .cloned()
+        .unwrap_or_else(|| "unknown".to_string());
+    Some(Artifact {
+        path,
+        runtime,
+        otp_release: installed.otp_release,
+        binary: installed.command(package).to_string(),
+        blob: installed.blob.clone(),
+        erts: installed.erts.as_ref().map(|_| {
+            ctx.paths
+                .lib()
+                .join(format!("{}-{}", package, version))
+                .join("erts")
+        }),
+    })
+}
+
+/// Writes the wrapper of an installed version whose build is kept, see [`installed_artifact`]
+/// and [`install_wrapper`]
+///
+/// # Errors
+///
+/// Returns `GleamPkgError` if the backend cannot write a wrapper for the artifact, or
+/// `GleamPkgError::Io` if it cannot be written
+///
+/// # Returns
+///
+/// Whether the wrapper changed
+fn restore_wrapper(
+    ctx: &Context,
+    package: &str,
+    version: &str,
+    installed: &db::InstalledVersion,
+    artifact: &Artifact,
+) -> Result<bool, GleamPkgError> {
+    let extract_dir = ctx
+        .paths
+        .download()
+        .join(format!("{}-{}", package, version));
+    let app_dir = ctx.paths.lib().join(format!("{}-{}", package, version));
+    // writing wrappers runs nothing
+    let limits = BuildLimits {
+        timeout: ctx.config.build_timeout(),
+        memory_limit: None,
+        cpu_limit: None,
+        isolation: None,
+    };
+    let build = BuildContext {
+        package,
+        version,
+        project_dir: &extract_dir,
+        app_dir: &app_dir,
+        store: &Store::new(&ctx.paths.store()),
+        toolchains: &ctx.paths.toolchains(),
+        limits: &limits,
+        wrapper: installed.wrapper.unwrap_or(ctx.config.wrapper),
+    };
+    let code = backend::dispatch_to_project(
+        backend::export_env(
+            installed.target.backend().wrapper(&build, artifact)?,
+            ctx.config.package_env(package),
+        ),
+        &ctx.paths.apps(),
+        package,
+        version,
+    );
+    install_wrapper(ctx, package, version, build.wrapper, artifact, code)
+}
+
+/// Recreates the link `name` in the apps directory pointing at `target`, if it is missing
+///
+/// A file or link that is already there is left alone, whatever it points at.
+///
+/// # Returns
+///
+/// Whether the link was missing
+fn restore_link(ctx: &Context, name: &str, target: &str) -> Result<bool, GleamPkgError> {
+    let link = ctx.paths.apps().join(name);
+    if link.symlink_metadata().is_ok() {
+        return Ok(false);
+    }
+    std::os::unix::fs::symlink(target, &link).map_err(|source| GleamPkgError::Io {
+        action: "restore link",
+        path: link.clone(),
+        source,
+    })?;
+    output::info(format!("Restored {}", link.display()));
+    Ok(true)
+}
+
+/// Rewrites the wrapper script of every installed version from the package database, e.g. after
+/// an update of gleam-pkg changed the wrappers it writes, or `[packages.<name>.env]` changed
+///
+/// The wrappers only point at builds kept in the store, so nothing is rebuilt. Missing links,
+/// the unversioned wrapper, the commands of the versions and the aliases, are restored as well.
+///
+/// # Errors
+///
+/// Returns `GleamPkgError` if the database cannot be read or a wrapper cannot be written
+fn regen_wrappers(ctx: &Context) -> Result<(), GleamPkgError> {
+    let db = Database::load(&ctx.paths.db_file())?;
+    let (mut regenerated, mut current, mut restored) = (0, 0, 0);
+    for (package, installed) in &db.packages {
+        for (version, installed_version) in &installed.versions {
+            let Some(artifact) = installed_artifact(ctx, package, version, installed_version)
+                .filter(|artifact| artifact.path.exists())
+            else {
+                output::warning(format!(
+                    "the build of {} {} is gone, reinstall it with `gleam-pkg install {}@{} \
+                     --force`",
+                    package, version, package, version
+                ));
+                continue;
+            };
+            if !restore_wrapper(ctx, package, version, installed_version, &artifact)? {
+                current += 1;
+                continue;
+            }
+            let wrapper = ctx.paths.apps().join(format!("{}-{}", package, version));
+            output::info(format!("Regenerated {}", wrapper.display()));
+            regenerated += 1;
+        }
+
+        let default = format!("{}-{}", package, installed.default_version);
+        let mut links = Vec::new();
+        if !installed.unlinked {
+            links.push((package.as_str(), default.as_str()));
+        }
+        let target = installed.link_target(package);
+        let (_, default_version) = installed.default_entry();
+        let binary = default_version.command(package);
+        if binary != package && !binary.contains('/') {
+            links.push((binary, target.as_str()));
+        }
+        links.extend(
+            installed
+                .aliases
+                .iter()
+                .map(|alias| (alias.as_str(), target.as_str())),
+        );
+        for (name, target) in links {
+            if restore_link(ctx, name, target)? {
+                restored += 1;
+            }
         }
     }
+    output::success(format!(
+        "Regenerated {} wrappers, {} were current, restored {} links",
+        regenerated, current, restored
+    ));
+    Ok(())
 }
 
-// lazyinit a Config
-lazy_static! {
-    static ref CONFIG: Config = Config::new();
-}
-
-lazy_static! {
-    static ref HOME_ROOT_DIR: PathBuf = dirs::home_dir().unwrap().join(ROOT_DIR);
-}
-
-/// Entry point for the Gleam package manager CLI
-fn main() -> Result<(), GleamPkgError> {
-    let args = Cli::parse();
-
-    if args.version {
-        println!("Gleam Package Manager v{}", env!("CARGO_PKG_VERSION"));
-        println!("Software published under {}", env!("CARGO_PKG_LICENSE"));
-        println!("Author: {}", env!("CARGO_PKG_AUTHORS"));
-        return Ok(());
+/// Recursively copy a directory and its contents to another directory
+fn copy_dir_all(
+    src: impl AsRef<std::path::Path>,
+    dst: impl AsRef<std::path::Path>,
+) -> Result<(), std::io::Error> {
+    fs::create_dir_all(&dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let ty = entry.file_type()?;
+        if ty.is_dir() {
+            copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()))?;
+        } else {
+            fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
+        }
     }
+    Ok(())
+}
 
-    let home_dir = dirs::home_dir().ok_or_else(|| {
-        GleamPkgError::DirectoryCreationError("Unable to locate home directory".to_string())
-    })?;
-    let root_dir = home_dir.join(ROOT_DIR);
-    setup_directories(&root_dir)?;
-    match args.command {
-        Some(Commands::Install { package }) => {
-            let home_dir = dirs::home_dir().ok_or_else(|| {
-                GleamPkgError::DirectoryCreationError("Unable to locate home directory".to_string())
-            })?;
-            let root_dir = home_dir.join(ROOT_DIR);
-            setup_directories(&root_dir)?;
-            install_package(&root_dir, &package)?;
+/// Runs a `gleam-pkg toolchain` subcommand, see [`crate::toolchains`]
+///
+/// # Errors
+///
+/// Returns `GleamPkgError` if a release cannot be downloaded or the default cannot be changed
+fn toolchain_command(ctx: &Context, command: ToolchainCommand) -> Result<(), GleamPkgError> {
+    let ensure = |version: &str| {
+        let binary = toolchains::gleam_binary(&ctx.paths, version);
+        if binary.is_file() {
+            output::info(format!(
+                "gleam {} is already installed at {}",
+                version,
+                binary.display()
+            ));
         }
-        None => {
-            println!("No subcommand provided. Use `gleam-pkg --help` for usage information.");
+        toolchains::ensure_gleam(
+            &ctx.config.toolchains,
+            &ctx.config.http,
+            &ctx.paths,
+            version,
+        )
+    };
+    match command {
+        ToolchainCommand::Install { version } => {
+            ensure(&version)?;
+        }
+        ToolchainCommand::List => {
+            let gleams = toolchains::installed_gleams(&ctx.paths);
+            let otps = toolchains::installed_otps(&ctx.paths);
+            if gleams.is_empty() && otps.is_empty() {
+                output::info("No toolchains installed, see `gleam-pkg toolchain install`");
+                return Ok(());
+            }
+            let default = toolchains::default_gleam(&ctx.paths);
+            let mut table = output::Table::new(&["TOOL", "VERSION", "PATH", "FLAGS"]);
+            for version in gleams {
+                let version = version.to_string();
+                let is_default = default.as_ref() == Some(&version);
+                let path = toolchains::gleam_binary(&ctx.paths, &version);
+                table.styled_row(vec![
+                    ("gleam".to_string(), None),
+                    (version, is_default.then_some(output::Style::Green)),
+                    (path.display().to_string(), None),
+                    (
+                        if is_default { "default" } else { "" }.to_string(),
+                        Some(output::Style::Dim),
+                    ),
+                ]);
+            }
+            for (version, erl) in otps {
+                table.styled_row(vec![
+                    ("otp".to_string(), None),
+                    (version, None),
+                    (erl.display().to_string(), None),
+                    (String::new(), None),
+                ]);
+            }
+            table.print();
+        }
+        ToolchainCommand::InstallOtp { release } => {
+            toolchains::install_otp(
+                &ctx.config.toolchains,
+                &ctx.config.http,
+                &ctx.paths,
+                &release,
+                &BuildLimits {
+                    timeout: ctx.config.build_timeout(),
+                    memory_limit: None,
+                    cpu_limit: None,
+                    isolation: None,
+                },
+            )?;
+        }
+        ToolchainCommand::Default { version } => {
+            ensure(&version)?;
+            toolchains::set_default_gleam(&ctx.paths, &version)?;
+            output::success(format!("Builds now use gleam {} by default", version));
         }
     }
     Ok(())
 }
 
-/// Sets up the necessary directory structure for Gleam packages
-///
-/// # Arguments
+/// Runs a `gleam-pkg project` subcommand, see [`project`]
 ///
-/// * `root_dir` - The root directory where Gleam packages and metadata will be stored
+/// Tools are installed into the project as into `~/.gleam_pkgs`, with the global configuration.
+/// Those installed or trusted globally need no confirmation.
 ///
 /// # Errors
 ///
-/// Returns `GleamPkgError::DirectoryCreationError` if any of the directories cannot be created
-fn setup_directories(root_dir: &PathBuf) -> Result<(), GleamPkgError> {
-    let paths = [
-        root_dir.to_path_buf(),
-        root_dir.join(DOWNLOAD_DIR),
-        root_dir.join(APPS_DIR),
-        root_dir.join(DB_DIR),
-    ];
-    for path in paths {
-        if !path.exists() {
-            fs::create_dir_all(&path)
-                .map_err(|e| GleamPkgError::DirectoryCreationError(format!("{path:?}: {e}")))?;
+/// Returns `GleamPkgError::NoProject` if `install` runs outside of a project, or another
+/// `GleamPkgError` if a tool fails to install
+fn project_command(ctx: &Context, command: ProjectCommand) -> Result<(), GleamPkgError> {
+    let cwd = std::env::current_dir().map_err(|source| GleamPkgError::Io {
+        action: "read the current directory",
+        path: PathBuf::from("."),
+        source,
+    })?;
+    match command {
+        ProjectCommand::Init => {
+            if project::init(&cwd)? {
+                output::success(format!(
+                    "Created {}, list the tools of the project under [tools] and run \
+                     `gleam-pkg project install`",
+                    project::MANIFEST
+                ));
+            } else {
+                output::info(format!("{} already exists", project::MANIFEST));
+            }
         }
+        ProjectCommand::Install {
+            trust_all,
+            locked,
+            limits,
+            toolchain,
+        } => project_install(
+            ctx,
+            &project::find(&cwd)?,
+            trust_all,
+            locked,
+            &limits.limits(&ctx.config),
+            toolchain,
+        )?,
     }
     Ok(())
 }
 
-/// Installs a Gleam package
+/// Installs the tools of the project at `root` into it and updates its lockfile, see
+/// [`project`]
 ///
 /// # Arguments
 ///
-/// * `root_dir` - The root directory where packages and metadata are stored
-/// * `package` - The name of the package to install
+/// * `trust_all` - Whether tools never installed before are installed without confirmation
+/// * `locked` - Whether to install exactly what the lockfile locks, with the locked compiler
+/// * `toolchain` - The toolchain overrides, the locked Gleam compiler is used if there are none
 ///
 /// # Errors
 ///
-/// Returns `GleamPkgError` if the installation fails
-///
-fn install_package(root_dir: &PathBuf, package: &str) -> Result<(), GleamPkgError> {
-    let download_dir = root_dir.join(DOWNLOAD_DIR);
-
-    let metadata = fetch_metadata(package)?;
-    let version = extract_version(&metadata)?;
-    let tarball = download_tarball(package, &version)?;
-
-    save_tarball(&download_dir, package, &version, tarball)?;
-    extract(&download_dir, package, &version)?;
-    build_package(&download_dir, package, &version)?;
-
-    Ok(())
-}
+/// Returns `GleamPkgError::LockfileOutdated` if `locked` is set and the lockfile does not match
+/// the manifest, `GleamPkgError::LockedChecksumMismatch` if a tarball is not the locked one,
+/// `GleamPkgError::ToolchainMissing` if `locked` is set and the Gleam compiler is not the locked
+/// one, or another `GleamPkgError` if a tool fails to install
+fn project_install(
+    ctx: &Context,
+    root: &Path,
+    trust_all: bool,
+    locked: bool,
+    limits: &BuildLimits,
+    mut toolchain: ToolchainArgs,
+) -> Result<(), GleamPkgError> {
+    let manifest = project::ToolsManifest::load(root)?;
+    let lockfile = project::Lockfile::load(root)?;
+    if locked {
+        let outdated = match &lockfile {
+            Some(lockfile) => lockfile.outdated(&manifest),
+            None => manifest.tools.keys().cloned().collect(),
+        };
+        if !outdated.is_empty() {
+            return Err(GleamPkgError::LockfileOutdated { tools: outdated });
+        }
+    }
+    let lockfile = lockfile.unwrap_or_default();
+    let local = Context {
+        paths: project::paths(root),
+        config: Config::load(&ctx.paths.config_file()).unwrap_or_default(),
+    };
+    local.paths.create_dirs()?;
+    // direnv puts the apps of the project on PATH, not the shell profile
+    PATH_CHECKED.store(true, Ordering::Relaxed);
 
-fn fetch_metadata(package: &str) -> Result<serde_json::Value, GleamPkgError> {
-    let client = reqwest::blocking::Client::new();
-    let url = format!("{}packages/{}", CONFIG.api_base, package);
-    println!("Inspecting package from: {}", url);
+    let global = Database::load(&ctx.paths.db_file())?;
+    let installed = Database::load(&local.paths.db_file())?;
+    let mut gleam = None;
+    for (name, tool) in &manifest.tools {
+        let locked_tool = lockfile.get(name, tool);
+        let spec = PackageSpec::parse(
+            &format!(
+                "{}@{}",
+                name,
+                locked_tool.map_or(tool.version(), |l| &l.version)
+            ),
+            None,
+        )?;
+        let current = installed.packages.get(&spec.name).and_then(|package| {
+            let (version, installed_version) = package.default_entry();
+            let accepted = match locked_tool {
+                Some(l) => *version == l.version && installed_version.target == l.target,
+                None => tool.accepts(version, installed_version.target),
+            };
+            accepted.then_some(version)
+        });
+        if let Some(current) = current {
+            output::info(i18n::fill(
+                Message::AlreadyInstalled,
+                &[("package", &spec.name), ("version", &current)],
+            ));
+            continue;
+        }
 
-    let response = client
-        .get(&url)
-        .header("accept", "application/json")
-        .header("user-agent", "gleam-pkg")
-        .send()
-        .map_err(|e| {
-            GleamPkgError::PackageDownloadError(format!(
-                "Failed to fetch metadata for package: {}, {}",
-                package, e
-            ))
-        })?;
+        let locked_gleam = locked_tool.and_then(|l| l.toolchain.get("gleam"));
+        let gleam = match &gleam {
+            Some(gleam) => gleam,
+            None => {
+                if locked && toolchain.gleam_path.is_none() && !toolchain.build_in_docker {
+                    toolchain.gleam_version =
+                        toolchain.gleam_version.take().or(locked_gleam.cloned());
+                }
+                toolchain.install(ctx)?;
+                let version = toolchain::check_gleam(limits)?.to_string();
+                output::info(i18n::fill(Message::UsingGleam, &[("version", &version)]));
+                gleam.insert(version)
+            }
+        };
+        if let Some(locked_gleam) = locked_gleam.filter(|g| *g != gleam) {
+            if locked {
+                return Err(GleamPkgError::ToolchainMissing {
+                    tool: "gleam".to_string(),
+                    required: format!("{} as locked for {}", locked_gleam, name),
+                    found: Some(gleam.clone()),
+                });
+            }
+            output::warning(format!(
+                "{} was locked with gleam {}, it is built with gleam {}",
+                name, locked_gleam, gleam
+            ));
+        }
+        let trusted =
+            global.packages.contains_key(&spec.name) || global.trusted.contains(&spec.name);
+        let opts = InstallOptions {
+            target: locked_tool.map(|l| l.target).or(tool.target()),
+            force: false,
+            overwrite: false,
+            renamed: false,
+            trust_all: trust_all || trusted,
+            latest: false,
+            limits: limits.clone(),
+            checksum: locked_tool.map(|l| l.checksum.clone()),
+            bundle_erts: false,
+            wrapper: None,
+            rebuild: false,
+            accept_new_checksum: false,
+            explain: false,
+        };
+        install_package(&local, &spec, &opts)?;
+    }
 
-    if !response.status().is_success() {
-        return Err(GleamPkgError::PackageDownloadError(format!(
-            "Received non-success status code: {}",
-            response.status()
-        )));
+    if !locked {
+        let installed = Database::load(&local.paths.db_file())?;
+        let mut updated = project::Lockfile::default();
+        for name in manifest.tools.keys() {
+            let spec = PackageSpec::parse(name, None)?;
+            let Some(package) = installed.packages.get(&spec.name) else {
+                continue;
+            };
+            let (version, installed_version) = package.default_entry();
+            let Some(provenance) = &installed_version.provenance else {
+                continue;
+            };
+            updated.tools.insert(
+                name.clone(),
+                project::LockedTool {
+                    version: version.to_string(),
+                    target: installed_version.target,
+                    checksum: provenance.checksum.clone(),
+                    toolchain: provenance.toolchain.clone(),
+                },
+            );
+        }
+        if updated.tools != lockfile.tools {
+            updated.save(root)?;
+            output::info(format!(
+                "Updated {}",
+                root.join(project::LOCKFILE).display()
+            ));
+        }
     }
 
-    response.json::<serde_json::Value>().map_err(|e| {
-        GleamPkgError::PackageDownloadError(format!(
-            "Returned metadata is not valid JSON: {}, {}",
-            package, e
-        ))
-    })
+    if project::ensure_envrc(root)? {
+        output::success(format!(
+            "Added `{}` to {}, run `direnv allow` to put the tools of the project on PATH",
+            project::ENVRC_LINE,
+            root.join(".envrc").display()
+        ));
+    }
+    Ok(())
 }
 
-/// Extracts the version of a package from its metadata
+/// Installs a package into a throwaway root below the temporary directory, configured like
+/// `ctx` without its hooks, and reports the size of what the install left there
 ///
-/// # Arguments
-///
-/// * `metadata` - The metadata of the package
+/// The root is removed after a successful install unless `keep` is set, and kept after a failed
+/// one so its build log can be read.
 ///
 /// # Errors
 ///
-/// Returns `GleamPkgError` if the version cannot be extracted
-///
-fn extract_version(metadata: &serde_json::Value) -> Result<String, GleamPkgError> {
-    let releases = metadata["releases"].as_array().ok_or_else(|| {
-        GleamPkgError::PackageDownloadError("No releases found in metadata".to_string())
-    })?;
+/// Returns the `GleamPkgError` the install failed with, or `GleamPkgError::Io` if the root
+/// cannot be created
+fn test_install(
+    ctx: &Context,
+    spec: &PackageSpec,
+    opts: &InstallOptions,
+    keep: bool,
+) -> Result<(), GleamPkgError> {
+    let root = std::env::temp_dir().join(format!("gleam-pkg-test-install-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let mut config = Config::load(&ctx.paths.config_file()).unwrap_or_default();
+    // the hooks of the real installation are not meant for this one
+    config.hooks = config::HooksConfig::default();
+    let sandbox = Context {
+        paths: Paths::new(&root),
+        config,
+    };
+    sandbox.paths.create_dirs()?;
+    // the throwaway apps are not meant to be on PATH
+    PATH_CHECKED.store(true, Ordering::Relaxed);
+    output::info(format!(
+        "Test-installing {} into {}",
+        spec.name,
+        root.display()
+    ));
 
-    releases[0]["version"]
-        .as_str()
-        .map(String::from)
-        .ok_or_else(|| {
-            GleamPkgError::PackageDownloadError("No version found in metadata".to_string())
-        })
+    if let Err(e) = install_package(&sandbox, spec, opts) {
+        output::notice(format!("Kept {} to look into", root.display()));
+        return Err(e);
+    }
+    let db = Database::load(&sandbox.paths.db_file())?;
+    let (version, installed) = db.packages[&spec.name].default_entry();
+    let name = format!("{}-{}", spec.name, version);
+    let mut artifacts = vec![
+        (
+            "tarball",
+            sandbox.paths.download().join(format!("{}.tar", name)),
+        ),
+        ("sources", sandbox.paths.download().join(&name)),
+        ("build", sandbox.paths.lib().join(&name)),
+        ("wrapper", sandbox.paths.apps().join(&name)),
+    ];
+    if let Some(blob) = &installed.blob {
+        artifacts.push(("escript", Store::new(&sandbox.paths.store()).path(blob)));
+    }
+    artifacts.push(("total", root.clone()));
+
+    output::success(format!(
+        "{} {} installs and runs, as {}",
+        spec.name,
+        version,
+        installed.command(&spec.name)
+    ));
+    // sizes in bytes for scripts
+    let mut table = output::Table::new(&["ARTIFACT", "SIZE", "PATH"]).align_right(1);
+    for (artifact, path) in &artifacts {
+        let size = plan::disk_usage(path);
+        table.styled_row(vec![
+            (artifact.to_string(), None),
+            (
+                match output::porcelain() {
+                    true => size.to_string(),
+                    false => output::format_size(size),
+                },
+                None,
+            ),
+            (path.display().to_string(), Some(output::Style::Dim)),
+        ]);
+    }
+    table.print();
+    if keep {
+        output::notice(format!("Kept {}", root.display()));
+    } else {
+        let _ = fs::remove_dir_all(&root);
+    }
+    Ok(())
 }
 
-/// Downloads a tarball of a package
-///
-/// # Arguments
-///
-/// * `package` - The name of the package to download
-/// * `version` - The version of the package to download
+/// Checks the project at `dir` for what would break installing it once published, see
+/// [`publishable`]
 ///
 /// # Errors
 ///
-/// Returns `GleamPkgError` if the download fails
-///
-/// # Returns
-///
-/// The tarball as a byte array
-///
-fn download_tarball(package: &str, version: &str) -> Result<bytes::Bytes, GleamPkgError> {
-    let client = reqwest::blocking::Client::new();
-    let url = format!(
-        "{}tarballs/{}-{}.tar",
-        CONFIG.repository_base, package, version
-    );
-    println!("Downloading package from: {}", url);
-
-    let response = client
-        .get(&url)
-        .header("accept", "application/x-tar")
-        .header("user-agent", "gleam-pkg")
-        .send()
-        .map_err(|e| {
-            GleamPkgError::PackageDownloadError(format!(
-                "Failed to download package: {}, {}",
-                package, e
-            ))
-        })?;
-
-    if !response.status().is_success() {
-        return Err(GleamPkgError::PackageDownloadError(format!(
-            "Received non-success status code: {}",
-            response.status()
-        )));
+/// Returns `GleamPkgError::NotPublishable` if any check finds a problem, or `GleamPkgError::Io`
+/// if the files of the project cannot be listed
+fn check_publishable(dir: &Path, files: bool) -> Result<(), GleamPkgError> {
+    let report = publishable::check(dir)?;
+    publishable::print(&report, files);
+    let problems = report.problems();
+    if problems > 0 {
+        return Err(GleamPkgError::NotPublishable { count: problems });
     }
-
-    response.bytes().map_err(|e| {
-        GleamPkgError::PackageDownloadError(format!("Failed to read tarball bytes: {}", e))
-    })
+    output::success(format!(
+        "{} can be installed with gleam-pkg once published",
+        dir.display()
+    ));
+    Ok(())
 }
 
-/// Saves a tarball to disk
+/// How often installing a release just published is tried while the registry does not list it
+const PUBLISHED_ATTEMPTS: u32 = 5;
+
+/// How long to wait between those attempts
+const PUBLISHED_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Publishes the project at `dir` with `gleam publish`, then installs the release it published
 ///
 /// # Arguments
 ///
-/// * `download_dir` - The directory where the tarball will be saved
-/// * `package` - The name of the package
-/// * `version` - The version of the package
-/// * `tarball` - The tarball as a byte array
+/// * `dir` - The directory of the project
+/// * `args` - Arguments for `gleam publish`
+/// * `repo` - The repository to install the release from
+/// * `check` - Whether to refuse to publish a project [`publishable::check`] finds problems in
+/// * `opts` - How to install the release
 ///
 /// # Errors
 ///
-/// Returns `GleamPkgError` if the tarball cannot be saved
-///
-fn save_tarball(
-    download_dir: &PathBuf,
-    package: &str,
-    version: &str,
-    tarball: bytes::Bytes,
+/// Returns `GleamPkgError::NotPublishable` if a check finds a problem,
+/// `GleamPkgError::PublishFailed` if `gleam publish` fails, or
+/// `GleamPkgError::PublishedNotInstallable` if the release was published but installing it
+/// failed
+fn publish(
+    ctx: &Context,
+    dir: &Path,
+    args: &[String],
+    repo: Option<&str>,
+    check: bool,
+    opts: &InstallOptions,
 ) -> Result<(), GleamPkgError> {
-    let tarball_path = download_dir.join(format!("{}-{}.tar", package, version));
-    fs::write(&tarball_path, tarball).map_err(|e| {
-        GleamPkgError::PackageDownloadError(format!(
-            "Failed to save tarball to disk: {}, {}",
-            tarball_path.display(),
-            e
-        ))
+    let report = publishable::check(dir)?;
+    let problems = report.problems();
+    if check && problems > 0 {
+        publishable::print(&report, false);
+        return Err(GleamPkgError::NotPublishable { count: problems });
+    }
+    let Some((package, version)) = report.release else {
+        publishable::print(&report, false);
+        return Err(GleamPkgError::NotPublishable {
+            count: problems.max(1),
+        });
+    };
+
+    let mut cmd = Command::new(toolchain::gleam());
+    cmd.current_dir(dir);
+    let status = Runner::new(cmd)
+        .arg("publish")
+        .args(args)
+        .describe("gleam publish")
+        .run()?
+        .status;
+    if !status.success() {
+        return Err(GleamPkgError::PublishFailed {
+            status: runner::describe_status(&status),
+        });
+    }
+
+    // the metadata cached before publishing does not list the release yet
+    cache::refresh();
+    let spec = PackageSpec::parse(&format!("{}@{}", package, version), repo)?;
+    let mut attempt = 1;
+    let installed = loop {
+        let result = tracked(ctx, history::Kind::Install, &spec.name, || {
+            install_package(ctx, &spec, opts)
+        });
+        match result {
+            Err(GleamPkgError::ReleaseNotFound { .. } | GleamPkgError::UnknownPackage { .. })
+                if attempt < PUBLISHED_ATTEMPTS =>
+            {
+                output::notice(format!(
+                    "{} {} is not listed by the registry yet, trying again in {:?}",
+                    package, version, PUBLISHED_RETRY_DELAY
+                ));
+                thread::sleep(PUBLISHED_RETRY_DELAY);
+                attempt += 1;
+            }
+            result => break result,
+        }
+    };
+    installed.map_err(|e| GleamPkgError::PublishedNotInstallable {
+        package: package.clone(),
+        version: version.clone(),
+        source: Box::new(e),
     })?;
-    println!("Tarball saved to: {}", tarball_path.display());
+    output::success(format!(
+        "Published {} {}, and it installs",
+        package, version
+    ));
     Ok(())
 }
 
-/// Extracts a tarball to disk
-///
-/// # Arguments
-///
-/// * `download_dir` - The directory where the tarball is saved
-/// * `package` - The name of the package
-/// * `version` - The version of the package
+/// Verifies that the tools of the project at `root` can be installed as locked, downloading
+/// their tarballs without building them, see [`project::ToolCheck`]
 ///
 /// # Errors
 ///
-/// Returns `GleamPkgError` if the tarball cannot be extracted
-///
-fn extract(download_dir: &PathBuf, package: &str, version: &str) -> Result<(), GleamPkgError> {
-    let tarball_path = download_dir.join(format!("{}-{}.tar", package, version));
-    let extract_dir = download_dir.join(format!("{}-{}", package, version));
+/// Returns `GleamPkgError::VerifyFailed` if any tool fails a check, or another `GleamPkgError`
+/// if the manifest or lockfile cannot be read
+fn ci_verify(ctx: &Context, root: &Path) -> Result<(), GleamPkgError> {
+    let manifest = project::ToolsManifest::load(root)?;
+    let lockfile = project::Lockfile::load(root)?.unwrap_or_default();
+    let local = Context {
+        paths: project::paths(root),
+        config: Config::load(&ctx.paths.config_file()).unwrap_or_default(),
+    };
+    local.paths.create_dirs()?;
 
-    // if extract_dir exists, remove it
-    if extract_dir.exists() {
-        fs::remove_dir_all(&extract_dir).map_err(|e| {
-            GleamPkgError::PackageDownloadError(format!(
-                "Failed to remove existing extract directory: {}, {}",
-                extract_dir.display(),
-                e
-            ))
-        })?;
+    let mut checks = Vec::new();
+    for (name, tool) in &manifest.tools {
+        let locked = lockfile.get(name, tool);
+        let failed = |version: Option<&str>, status, e: GleamPkgError| project::ToolCheck {
+            tool: name.clone(),
+            version: version.map(String::from),
+            status,
+            checksum: None,
+            detail: e.report(),
+        };
+        let requested = locked.map_or(tool.version(), |l| &l.version);
+        let resolved =
+            PackageSpec::parse(&format!("{}@{}", name, requested), None).and_then(|spec| {
+                let metadata = fetch_metadata(&local, &spec.source, &spec.name)?;
+                Ok((find_release(&metadata, requested)?, spec))
+            });
+        let (version, spec) = match resolved {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                checks.push(failed(None, project::CheckStatus::Unresolved, e));
+                continue;
+            }
+        };
+        match download_tarball(&local, &spec.source, &spec.name, &version) {
+            Ok(downloaded) => checks.push(project::ToolCheck::downloaded(
+                name,
+                &version,
+                &downloaded.checksum,
+                locked,
+            )),
+            Err(e @ GleamPkgError::ChecksumMismatch { .. }) => checks.push(failed(
+                Some(&version),
+                project::CheckStatus::ChecksumMismatch,
+                e,
+            )),
+            Err(e) => checks.push(failed(
+                Some(&version),
+                project::CheckStatus::DownloadFailed,
+                e,
+            )),
+        }
+        let _ = fs::remove_file(
+            local
+                .paths
+                .download()
+                .join(format!("{}-{}.tar", spec.name, version)),
+        );
+    }
+    for (name, locked) in &lockfile.tools {
+        if !manifest.tools.contains_key(name) {
+            checks.push(project::ToolCheck {
+                tool: name.clone(),
+                version: Some(locked.version.clone()),
+                status: project::CheckStatus::Stale,
+                checksum: Some(locked.checksum.clone()),
+                detail: format!("not in {}", project::MANIFEST),
+            });
+        }
     }
-    fs::create_dir(&extract_dir).map_err(|e| {
-        GleamPkgError::PackageDownloadError(format!(
-            "Failed to create extract directory: {}, {}",
-            extract_dir.display(),
-            e
-        ))
-    })?;
 
-    let tar = fs::File::open(&tarball_path).map_err(|e| {
-        GleamPkgError::PackageDownloadError(format!(
-            "Failed to open tarball for extraction: {}, {}",
-            tarball_path.display(),
-            e
-        ))
-    })?;
-    let mut archive = tar::Archive::new(tar);
-    archive.unpack(&extract_dir).map_err(|e| {
-        GleamPkgError::PackageDownloadError(format!(
-            "Failed to extract tarball: {}, {}",
-            tarball_path.display(),
-            e
-        ))
-    })?;
-    println!("Tarball extracted to: {}", extract_dir.display());
-    // then enter the extracted directory and extract contents.tar.gz to contents
-    let contents_tar_gz = extract_dir.join("contents.tar.gz");
-    let contents_dir = extract_dir.join("contents");
-    let contents_tar = fs::File::open(&contents_tar_gz).map_err(|e| {
-        GleamPkgError::PackageDownloadError(format!(
-            "Failed to open contents tarball for extraction: {}, {}",
-            contents_tar_gz.display(),
-            e
-        ))
-    })?;
-    let decoder = GzDecoder::new(contents_tar);
-    let mut contents_tar = tar::Archive::new(decoder);
-    contents_tar.unpack(&contents_dir).map_err(|e| {
-        GleamPkgError::PackageDownloadError(format!(
-            "Failed to extract contents tarball: {}, {}",
-            contents_tar_gz.display(),
-            e
-        ))
-    })?;
-    println!("Contents extracted to: {}", contents_dir.display());
-    Ok(())
-}
-
-fn erl_eval(expr: &String) -> Result<String, GleamPkgError> {
-    //  erl -noshell -eval 'expr' -s init stop
-    let output = std::process::Command::new("erl")
-        .arg("-noshell")
-        .arg("-eval")
-        .arg(expr)
-        .arg("-s")
-        .arg("init")
-        .arg("stop")
-        .output()
-        .map_err(|e| {
-            GleamPkgError::PackageBuildError(format!("Failed to run erl eval: {}, {}", expr, e))
-        })?;
-    if !output.status.success() {
-        return Err(GleamPkgError::PackageBuildError(format!(
-            "Failed to run erl eval: {}",
-            String::from_utf8_lossy(&output.stderr)
-        )));
+    project::print_checks(&checks);
+    let failed = checks
+        .iter()
+        .filter(|check| check.status != project::CheckStatus::Ok)
+        .count();
+    if failed > 0 {
+        return Err(GleamPkgError::VerifyFailed { count: failed });
     }
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    output::success(format!(
+        "All {} tools can be installed as locked",
+        checks.len()
+    ));
+    Ok(())
 }
 
-/// Builds a package
-/// This involves running `gleam build` and `gleam export erlang-shipment` in the contents directory
+/// Whether [`path_check`] already ran
+static PATH_CHECKED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the apps directory of `paths` is on the `PATH` of this process
+fn on_path(paths: &Paths) -> bool {
+    let apps_dir = paths.apps();
+    let current_path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&current_path).any(|dir| dir == apps_dir)
+}
+
+/// Offers to set up `PATH` in the shell's startup file, unless the apps directory of `paths`
+/// already is on `PATH` or the startup file already sets it up
 ///
-/// # Arguments
+/// The startup file gets a line evaluating `gleam-pkg env`, see [`shell`].
 ///
-/// * `download_dir` - The directory where the package is downloaded
-/// * `package` - The name of the package
-/// * `version` - The version of the package
+/// # Errors
+///
+/// Returns `GleamPkgError::UnsupportedShell` if the shell cannot be set up automatically, or
+/// `GleamPkgError::Io` if the startup file cannot be written
+pub fn path_check(paths: &Paths) -> Result<(), GleamPkgError> {
+    // once per run is enough, e.g. when updating several packages
+    if PATH_CHECKED.swap(true, Ordering::Relaxed) || on_path(paths) {
+        return Ok(());
+    }
+    let apps_dir = paths.apps();
+    let shell = Shell::detect()?;
+    let home = dirs::home_dir().ok_or(GleamPkgError::HomeDirNotFound)?;
+    let profile = home.join(shell.profile());
+    let set_up = shell::scan(&home, &apps_dir)
+        .iter()
+        .any(|entry| entry.profile == profile && entry.state == shell::EntryState::Current);
+    if set_up {
+        println!(
+            "{} is set up in ~/{}, open a new shell to put it on PATH",
+            apps_dir.display(),
+            shell.profile()
+        );
+        return Ok(());
+    }
+    let question = format!(
+        "It seems that {} is not in your PATH, do you want to add\n\n  {}\n\nto ~/{}?",
+        apps_dir.display(),
+        shell.setup_line(&apps_dir),
+        shell.profile()
+    );
+    if prompt::confirm(&question, false)? {
+        shell::add(&home, shell, &apps_dir)?;
+        println!(
+            "PATH updated successfully, open a new shell to apply the changes (or `source ~/{}`)",
+            shell.profile()
+        );
+    }
+
+    Ok(())
+}
+
+/// Runs a `gleam-pkg path` subcommand
 ///
 /// # Errors
 ///
-/// Returns `GleamPkgError` if the package cannot be built
-fn build_package(
-    download_dir: &PathBuf,
-    package: &str,
-    version: &str,
-) -> Result<(), GleamPkgError> {
-    // run `gleam build` in contents directory
-    let contents_dir = download_dir.join(format!("{}-{}/contents", package, version));
-    let output = std::process::Command::new("gleam")
-        .arg("build")
-        .current_dir(&contents_dir)
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit())
-        .output()
-        .map_err(|e| {
-            GleamPkgError::PackageBuildError(format!(
-                "Failed to run `gleam build` in contents directory: {}, {}",
-                contents_dir.display(),
-                e
-            ))
-        })?;
-    if !output.status.success() {
-        return Err(GleamPkgError::PackageBuildError(format!(
-            "Failed to build package: {}",
-            String::from_utf8_lossy(&output.stderr)
-        )));
-    }
-
-    // add gleescript to the package and run it
-    // gleam add gleescript && gleam run -m gleescript -- --out=build
-    let output = std::process::Command::new("gleam")
-        .arg("add")
-        .arg("gleescript")
-        .current_dir(&contents_dir)
-        .stderr(std::process::Stdio::inherit())
-        .output()
-        .map_err(|e| {
-            GleamPkgError::PackageBuildError(format!(
-                "Failed to run `gleam add gleescript` in contents directory: {}, {}",
-                contents_dir.display(),
-                e
-            ))
-        })?;
-    if !output.status.success() {
-        return Err(GleamPkgError::PackageBuildError(format!(
-            "Failed to add gleescript: {}",
-            String::from_utf8_lossy(&output.stderr)
-        )));
-    }
-
-    let output = std::process::Command::new("gleam")
-        .arg("run")
-        .arg("-m")
-        .arg("gleescript")
-        .arg("--")
-        .arg("--out=build")
-        .current_dir(&contents_dir)
-        .stderr(std::process::Stdio::inherit())
-        .output()
-        .map_err(|e| {
-            GleamPkgError::PackageBuildError(format!(
-                "Failed to run `gleam run -m gleescript` in contents directory: {}, {}",
-                contents_dir.display(),
-                e
-            ))
-        })?;
-    if !output.status.success() {
-        return Err(GleamPkgError::PackageBuildError(format!(
-            "Failed to run gleescript: {}",
-            String::from_utf8_lossy(&output.stderr)
-        )));
+/// Returns `GleamPkgError::HomeDirNotFound` if the home directory cannot be determined,
+/// `GleamPkgError::UnsupportedShell` if `add` cannot detect the shell, or `GleamPkgError::Io` if
+/// a startup file cannot be rewritten
+fn path_command(ctx: &Context, command: PathCommand) -> Result<(), GleamPkgError> {
+    let home = dirs::home_dir().ok_or(GleamPkgError::HomeDirNotFound)?;
+    let apps_dir = ctx.paths.apps();
+    let entries = shell::scan(&home, &apps_dir);
+    let display = |profile: &Path| match profile.strip_prefix(&home) {
+        Ok(relative) => format!("~/{}", relative.display()),
+        Err(_) => profile.display().to_string(),
+    };
+    match command {
+        PathCommand::Status => {
+            if on_path(&ctx.paths) {
+                output::success(format!("{} is on PATH", apps_dir.display()));
+            } else {
+                output::warning(format!("{} is not on PATH", apps_dir.display()));
+            }
+            if entries.is_empty() {
+                println!("No startup file puts it on PATH, see `gleam-pkg path add`");
+                return Ok(());
+            }
+            let mut table = output::Table::new(&["FILE", "LINE", "STATE", "ENTRY"]).align_right(1);
+            for entry in &entries {
+                let style = match entry.state {
+                    shell::EntryState::Current => output::Style::Green,
+                    shell::EntryState::Duplicate | shell::EntryState::Stale => {
+                        output::Style::Yellow
+                    }
+                };
+                table.styled_row(vec![
+                    (display(&entry.profile), None),
+                    (entry.line.to_string(), None),
+                    (entry.state.name().to_string(), Some(style)),
+                    (entry.text.clone(), Some(output::Style::Dim)),
+                ]);
+            }
+            table.print();
+            if entries
+                .iter()
+                .any(|entry| entry.state != shell::EntryState::Current)
+            {
+                println!(
+                    "Run `gleam-pkg path remove --broken` to remove stale and duplicate lines"
+                );
+            }
+        }
+        PathCommand::Add { shell } => {
+            let shell = match shell {
+                Some(shell) => shell,
+                None => Shell::detect()?,
+            };
+            if shell::add(&home, shell, &apps_dir)? {
+                output::success(format!(
+                    "Added `{}` to ~/{}, open a new shell to apply it",
+                    shell.setup_line(&apps_dir),
+                    shell.profile()
+                ));
+            } else {
+                println!(
+                    "~/{} already puts {} on PATH",
+                    shell.profile(),
+                    apps_dir.display()
+                );
+            }
+        }
+        PathCommand::Remove { broken } => {
+            let removed: Vec<_> = entries
+                .into_iter()
+                .filter(|entry| !broken || entry.state != shell::EntryState::Current)
+                .collect();
+            if removed.is_empty() {
+                println!("Nothing to remove");
+                return Ok(());
+            }
+            shell::remove(&removed)?;
+            for entry in &removed {
+                println!(
+                    "Removed line {} of {}: {}",
+                    entry.line,
+                    display(&entry.profile),
+                    entry.text
+                );
+            }
+        }
     }
+    Ok(())
+}
 
-    // now we need to get current running erlang vm's version and other info
-    // and embed it into the comment section of the escript
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::cell::{Cell, RefCell};
 
-    let output = erl_eval(
-        &"io:format(standard_io, \"~s~n\", [erlang:system_info(system_version)]).".to_string(),
-    )?;
-    let erlang_version = output.trim();
-    println!("Erlang system version: {}", erlang_version);
-
-    // let output = erl_eval(&format!(
-    //     "io:format(\"~p~n\", [escript:extract(\"{}\", [])]).",
-    //     contents_dir.join("build").join(package).display()
-    // ))?;
-
-    // println!(
-    //     "Escript generated successfully: {} ...",
-    //     &output[0..300],
-    // );
-
-    // first remove the existing ~/.gleam_pkgs/apps/{package}-{version} directory
-    let _ = fs::remove_dir_all(
-        HOME_ROOT_DIR
-            .join(APPS_DIR)
-            .join(format!("{}-{}", package, version)),
-    );
+    /// A registry answering from fixtures, recording what it was asked
+    #[derive(Default)]
+    struct FixtureRegistry {
+        body: serde_json::Value,
+        etag: Option<String>,
+        not_modified: bool,
+        requests: Cell<usize>,
+        conditional_on: RefCell<Option<String>>,
+    }
 
-    let _ = fs::remove_file(HOME_ROOT_DIR.join(APPS_DIR).join(package));
-
-    // now we create another shell script to wrap the binary escript
-    // this wrapper will detect os, exam erlang version compatibility
-    // and eventually run the escript bundled inside the shell script
-    let wrapper = HOME_ROOT_DIR.join(APPS_DIR).join(package);
-    let mut file = fs::File::create(&wrapper).map_err(|e| {
-        GleamPkgError::PackageBuildError(format!(
-            "Failed to create wrapper script: {}, {}",
-            wrapper.display(),
-            e
-        ))
-    })?;
+    impl Registry for FixtureRegistry {
+        fn api_url(&self, path: &str) -> String {
+            format!("fixture://{}", path)
+        }
 
-    // read binary escript's content in Vec<u8>
-    let escript = fs::read(contents_dir.join("build").join(package)).map_err(|e| {
-        GleamPkgError::PackageBuildError(format!(
-            "Failed to read escript: {}, {}",
-            contents_dir.join("build").join(package).display(),
-            e
-        ))
-    })?;
+        fn repositories(&self) -> &[String] {
+            &[]
+        }
+
+        fn tarball_url(&self, package: &str, version: &str) -> String {
+            format!("fixture://tarballs/{}-{}.tar", package, version)
+        }
 
-    let escript_base64 = STANDARD.encode(&escript);
+        fn fetch_json(
+            &self,
+            _path: &str,
+            cached: Option<&cache::CachedMetadata>,
+        ) -> Result<ApiResponse, GleamPkgError> {
+            self.requests.set(self.requests.get() + 1);
+            *self.conditional_on.borrow_mut() = cached.and_then(|c| c.etag.clone());
+            if self.not_modified {
+                return Ok(ApiResponse::NotModified);
+            }
+            Ok(ApiResponse::Document {
+                body: self.body.clone(),
+                etag: self.etag.clone(),
+                last_modified: None,
+            })
+        }
 
-    let wrapper_code = format!(
-        r#"#!/bin/sh
-# This is a wrapper script for the escript generated by gleam-pkg
+        fn download_tarball(
+            &self,
+            _repository: &str,
+            _package: &str,
+            _version: &str,
+            _part: &Path,
+        ) -> Result<(), GleamPkgError> {
+            Ok(())
+        }
 
-COMPILED_ERLANG_VERSION="{erlang_version}"
+        fn fetch_versions(
+            &self,
+            _cached: Option<&cache::CachedMetadata>,
+        ) -> Result<ApiResponse, GleamPkgError> {
+            Err(GleamPkgError::HttpStatus {
+                url: "fixture://versions".to_string(),
+                status: 404,
+            })
+        }
 
-# Check if the Erlang version is the same
-CURRENT_ERLANG_VERSION=$(erl -noshell -eval 'io:format("~s", [erlang:system_info(system_version)]).' -s init stop)
+        fn fetch_names(&self) -> Result<Vec<String>, GleamPkgError> {
+            Err(GleamPkgError::HttpStatus {
+                url: "fixture://names".to_string(),
+                status: 404,
+            })
+        }
 
-if [ "$CURRENT_ERLANG_VERSION" != "$COMPILED_ERLANG_VERSION" ]; then
-    echo "Erlang version mismatch: compiled with $COMPILED_ERLANG_VERSION, running $CURRENT_ERLANG_VERSION"
-    echo "Please recompile the package with the correct Erlang version"
-    exit 1
-fi
+        fn benchmark(&self, _repository: &str) -> Result<registry::Benchmark, GleamPkgError> {
+            Err(GleamPkgError::HttpStatus {
+                url: "fixture://names".to_string(),
+                status: 404,
+            })
+        }
 
-# Decode base64 content to a temporary file
-TEMP_DIR=$(mktemp -d)
-ESCRIPT_PATH="$TEMP_DIR/escript"
-echo "{escript_base64}" | base64 -d > "$ESCRIPT_PATH"
+        fn tarball_size(&self, _package: &str, _version: &str) -> Option<u64> {
+            None
+        }
+    }
 
-# Make it executable
-chmod +x "$ESCRIPT_PATH"
+    const TTL: Duration = Duration::from_secs(300);
 
-# Run the escript
-"$ESCRIPT_PATH" "$@"
+    /// An installation in a temporary root, with `hello` 1.0.0 and 1.1.0 installed
+    fn installation(root: &Path) -> Context {
+        let ctx = Context {
+            paths: Paths::new(root),
+            config: Config::default(),
+        };
+        ctx.paths.create_dirs().unwrap();
+        let mut db = Database::default();
+        let version = db::InstalledVersion {
+            target: Target::Node,
+            otp_release: None,
+            blob: None,
+            binary: None,
+            erts: None,
+            wrapper: None,
+            provenance: None,
+            dependencies: None,
+        };
+        db.packages.insert(
+            "hello".to_string(),
+            db::InstalledPackage {
+                versions: [
+                    ("1.0.0".to_string(), version.clone()),
+                    ("1.1.0".to_string(), version),
+                ]
+                .into(),
+                default_version: "1.1.0".to_string(),
+                pinned: false,
+                aliases: Default::default(),
+                unlinked: false,
+                source: Source::default(),
+            },
+        );
+        db.save(&ctx.paths.db_file()).unwrap();
+        for version in ["1.0.0", "1.1.0"] {
+            fs::write(ctx.paths.apps().join(format!("hello-{}", version)), "").unwrap();
+        }
+        link_default(&ctx, "hello", "1.1.0").unwrap();
+        ctx
+    }
 
-# Clean up
-rm -rf "$TEMP_DIR"
-"#
-    );
+    #[test]
+    fn updates_what_is_quickest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = installation(dir.path());
+        let db = Database::load(&ctx.paths.db_file()).unwrap();
+        let mut hello = db.packages["hello"].clone();
+        let up_to_date = update_order(&hello, Some("1.1.0"), Some(60_000));
+        let quick = update_order(&hello, Some("1.2.0"), Some(2_000));
+        let slow = update_order(&hello, None, Some(60_000));
+        let untimed = update_order(&hello, Some("1.2.0"), None);
+        assert!(up_to_date < quick && quick < slow && slow < untimed);
+        hello.pinned = true;
+        assert_eq!(update_order(&hello, Some("1.2.0"), None), up_to_date);
+    }
 
-    file.write_all(wrapper_code.as_bytes()).map_err(|e| {
-        GleamPkgError::PackageBuildError(format!(
-            "Failed to write to wrapper script: {}, {}",
-            wrapper.display(),
-            e
-        ))
-    })?;
+    #[test]
+    fn fresh_cache_entries_skip_the_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MetadataCache::new(dir.path());
+        let registry = FixtureRegistry {
+            body: json!({"name": "hello"}),
+            ..Default::default()
+        };
 
-    // add execute permission to the wrapper script using Unix permissions
-    let mut perms = file.metadata().map_err(|e| {
-        GleamPkgError::PackageBuildError(format!(
-            "Failed to get metadata for wrapper script: {}, {}",
-            wrapper.display(),
-            e
-        ))
-    })?.permissions();
-    perms.set_mode(0o755);
-    file.set_permissions(perms).map_err(|e| {
-        GleamPkgError::PackageBuildError(format!(
-            "Failed to set permissions for wrapper script: {}, {}",
-            wrapper.display(),
-            e
-        ))
-    })?;
+        let first = fetch_cached(&cache, &registry, "packages/hello", "hello", TTL).unwrap();
+        let second = fetch_cached(&cache, &registry, "packages/hello", "hello", TTL).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(registry.requests.get(), 1);
+    }
 
-    path_check()?;
+    #[test]
+    fn stale_cache_entries_are_revalidated() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MetadataCache::new(dir.path());
+        let mut stale = cache::CachedMetadata::new(Some("\"v1\"".into()), None, json!({"v": 1}));
+        stale.fetched_at = 0;
+        cache.put("hello", &stale).unwrap();
+        let registry = FixtureRegistry {
+            not_modified: true,
+            ..Default::default()
+        };
 
-    println!(
-        "Package installed successfully! You can run {} in your shell to use it now.",
-        package
-    );
+        let body = fetch_cached(&cache, &registry, "packages/hello", "hello", TTL).unwrap();
+        assert_eq!(body, json!({"v": 1}));
+        assert_eq!(registry.conditional_on.borrow().as_deref(), Some("\"v1\""));
+        assert!(cache.get("hello").unwrap().is_fresh(TTL));
+    }
 
-    Ok(())
-}
+    #[test]
+    fn not_modified_without_a_cached_copy_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MetadataCache::new(dir.path());
+        let registry = FixtureRegistry {
+            not_modified: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            fetch_cached(&cache, &registry, "packages/hello", "hello", TTL),
+            Err(GleamPkgError::HttpStatus { status: 304, .. })
+        ));
+    }
 
-/// Recursively copy a directory and its contents to another directory
-#[allow(dead_code)]
-fn copy_dir_all(
-    src: impl AsRef<std::path::Path>,
-    dst: impl AsRef<std::path::Path>,
-) -> Result<(), std::io::Error> {
-    fs::create_dir_all(&dst)?;
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let ty = entry.file_type()?;
-        if ty.is_dir() {
-            copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()))?;
-        } else {
-            fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
-        }
+    #[test]
+    fn organization_keys_are_cached_in_subdirectories() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MetadataCache::new(dir.path());
+        let registry = FixtureRegistry {
+            body: json!({"name": "hello"}),
+            ..Default::default()
+        };
+        fetch_cached(&cache, &registry, "packages/hello", "myorg/hello", TTL).unwrap();
+        assert!(dir.path().join("myorg").join("hello.json").is_file());
     }
-    Ok(())
-}
 
-/// check whether ~/.gleam_pkgs/apps is in the PATH
-/// if not, show a prompt to ask user whether to add it to the shell profile
-/// now support bash and zsh, other shells will need to add PATH manually
-pub fn path_check() -> Result<(), GleamPkgError> {
-    let user_shell = std::env::var("SHELL").unwrap();
-    let user_shell = user_shell.split('/').last().unwrap();
-    let user_shell = user_shell.split('.').next().unwrap();
-    let user_shell = user_shell.to_lowercase();
-    let profile = match user_shell.as_str() {
-        "bash" => ".bashrc",
-        "zsh" => ".zshrc",
-        _ => {
-            return Err(GleamPkgError::PathError(format!(
-                "Unsupported shell: {}",
-                user_shell
-            )));
+    #[test]
+    fn commands_work_on_the_given_root() {
+        let root = tempfile::tempdir().unwrap();
+        let ctx = installation(root.path());
+
+        add_alias(&ctx, "hello", "hi").unwrap();
+        assert_eq!(
+            fs::read_link(ctx.paths.apps().join("hi")).unwrap(),
+            Path::new("hello")
+        );
+        set_default(&ctx, "hello", "1.0.0").unwrap();
+        assert_eq!(
+            fs::read_link(ctx.paths.apps().join("hello")).unwrap(),
+            Path::new("hello-1.0.0")
+        );
+
+        uninstall_package(&ctx, "hello", Some("1.0.0")).unwrap();
+        let db = Database::load(&ctx.paths.db_file()).unwrap();
+        assert_eq!(db.packages["hello"].default_version, "1.1.0");
+        assert!(!ctx.paths.apps().join("hello-1.0.0").exists());
+
+        uninstall_package(&ctx, "hello", None).unwrap();
+        let db = Database::load(&ctx.paths.db_file()).unwrap();
+        assert!(db.packages.is_empty());
+        assert!(fs::read_dir(ctx.paths.apps()).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn wrappers_are_written_the_way_the_mode_asks() {
+        let root = tempfile::tempdir().unwrap();
+        let ctx = installation(root.path());
+        let escript = root.path().join("blob");
+        fs::write(&escript, "escript").unwrap();
+        let artifact = |blob: Option<&str>| Artifact {
+            path: escript.clone(),
+            runtime: "Erlang/OTP 27".to_string(),
+            otp_release: Some(27),
+            binary: "hello".to_string(),
+            blob: blob.map(String::from),
+            erts: None,
+        };
+        let wrapper = ctx.paths.apps().join("hello-1.0.0");
+        let install = |mode, blob| {
+            install_wrapper(
+                &ctx,
+                "hello",
+                "1.0.0",
+                mode,
+                &artifact(blob),
+                "code\n".into(),
+            )
+            .unwrap()
+        };
+
+        assert!(install(WrapperMode::Symlink, Some("blob")));
+        assert_eq!(fs::read_link(&wrapper).unwrap(), escript);
+        assert!(!install(WrapperMode::Symlink, Some("blob")));
+        assert!(install(WrapperMode::Embedded, Some("blob")));
+        assert_eq!(fs::read_to_string(&wrapper).unwrap(), "code\nescript");
+        // the escript behind the former link is left alone
+        assert_eq!(fs::read_to_string(&escript).unwrap(), "escript");
+        assert!(install(WrapperMode::Exec, Some("blob")));
+        assert_eq!(fs::read_to_string(&wrapper).unwrap(), "code\n");
+        assert!(!install(WrapperMode::Exec, Some("blob")));
+        // only escripts in the store can be linked or embedded
+        assert!(!install(WrapperMode::Symlink, None));
+        assert!(!install(WrapperMode::Embedded, None));
+    }
+
+    #[test]
+    fn extracts_the_latest_stable_release() {
+        // not in the order hex lists them
+        let metadata = json!({
+            "name": "hello",
+            "releases": [
+                {"version": "1.9.0"},
+                {"version": "2.0.0-rc.1"},
+                {"version": "1.10.0"},
+                {"version": "1.11.0"},
+                {"version": "not-semver"},
+            ],
+            "retirements": {"1.11.0": {"reason": "security"}},
+        });
+        assert_eq!(extract_version(&metadata).unwrap(), "1.10.0");
+        assert_eq!(newest_version(&metadata).unwrap(), "2.0.0-rc.1");
+
+        let metadata = json!({
+            "name": "hello",
+            "releases": [{"version": "1.0.0-rc.2"}, {"version": "1.0.0-rc.10"}],
+        });
+        match extract_version(&metadata) {
+            Err(GleamPkgError::OnlyPreReleases { package, newest }) => {
+                assert_eq!(package, "hello");
+                assert_eq!(newest, "1.0.0-rc.10");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(newest_version(&metadata).unwrap(), "1.0.0-rc.10");
+
+        let metadata = json!({
+            "name": "hello",
+            "releases": [{"version": "1.0.0"}, {"version": "1.1.0-rc.1"}],
+            "retirements": {"1.0.0": {"reason": "invalid"}, "1.1.0-rc.1": {"reason": "other"}},
+        });
+        assert!(matches!(
+            extract_version(&metadata),
+            Err(GleamPkgError::OnlyRetiredReleases { .. })
+        ));
+        assert_eq!(newest_version(&metadata).unwrap(), "1.1.0-rc.1");
+
+        for metadata in [
+            json!({"name": "hello"}),
+            json!({"name": "hello", "releases": null}),
+            json!({"name": "hello", "releases": [{"version": "nightly"}, {}]}),
+        ] {
+            assert!(matches!(
+                extract_version(&metadata),
+                Err(GleamPkgError::NoReleases { .. })
+            ));
+            assert!(newest_version(&metadata).is_err());
         }
-    };
-    let profile_path = dirs::home_dir().unwrap().join(profile);
-    let current_path = std::env::var("PATH").unwrap();
-    // println!("Current PATH: {}", current_path);
-    let keywords = ".gleam_pkgs/apps";
-    if current_path.contains(keywords) {
-        return Ok(());
     }
-    println!(
-        "It seems that ~/.gleam_pkgs/apps is not in your PATH, \
-do you want to add it to ~/{}? (y/n)",
-        profile
-    );
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input).unwrap();
-    if input.trim() == "y" {
-        let mut file = fs::OpenOptions::new()
-            .append(true)
-            .open(&profile_path)
-            .map_err(|e| GleamPkgError::PathError(format!("Failed to open profile: {}", e)))?;
-        file.write_all(b"\nexport PATH=$PATH:~/.gleam_pkgs/apps\n")
-            .map_err(|e| GleamPkgError::PathError(format!("Failed to write to profile: {}", e)))?;
-        println!(
-            "PATH updated successfully please run `source ~/{}` to apply the changes",
-            profile
-        );
+
+    #[test]
+    fn finds_releases_in_metadata() {
+        let metadata = json!({
+            "name": "hello",
+            "releases": [{"version": "1.1.0"}, {"version": "1.0.0"}],
+        });
+        assert_eq!(extract_version(&metadata).unwrap(), "1.1.0");
+        assert_eq!(find_release(&metadata, "1.0.0").unwrap(), "1.0.0");
+        // nobody is asked in tests, so the newest matching release is picked
+        assert_eq!(find_release(&metadata, "1").unwrap(), "1.1.0");
+        assert_eq!(find_release(&metadata, "1.0").unwrap(), "1.0.0");
+        assert!(matches!(
+            find_release(&metadata, "2.0.0"),
+            Err(GleamPkgError::ReleaseNotFound { .. })
+        ));
+        assert!(matches!(
+            extract_version(&json!({"name": "hello", "releases": []})),
+            Err(GleamPkgError::NoReleases { .. })
+        ));
     }
 
-    Ok(())
+    #[test]
+    fn compares_versions() {
+        assert!(is_newer("1.10.0", "1.9.0"));
+        assert!(!is_newer("1.0.0", "1.0.0"));
+        assert!(!is_newer("1.0.0-rc.1", "1.0.0"));
+        assert!(is_newer("nightly", "1.0.0"));
+    }
 }
@@ -0,0 +1,274 @@
+//! The resolved dependencies of an installed version
+//!
+//! `gleam build` records the dependency tree it resolved in a `manifest.toml`. Packages that
+//! publish theirs ship it in the tarball; for the others, the one written by the scratch build
+//! project is used:
+//!
+//! ```text
+//! download/<pkg>-<ver>/contents/manifest.toml
+//! download/<pkg>-<ver>/gleam_pkg_build/manifest.toml
+//! ```
+//!
+//! Installs record the dependencies they read there with the version installed, see
+//! `crate::db::InstalledVersion`, so `gleam-pkg list --tree` shows them after the download is
+//! pruned.
+
+use crate::backend::BUILD_PROJECT;
+use crate::error::GleamPkgError;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, VecDeque};
+use std::fs;
+use std::path::Path;
+
+/// A package in a `manifest.toml`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    /// `hex`, `git` or `local`
+    pub source: String,
+    /// The names of the packages it depends on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requirements: Vec<String>,
+    /// The SHA-256 checksum of its hex tarball
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outer_checksum: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    packages: Vec<Package>,
+}
+
+/// The resolved dependency tree of a version extracted to `extract_dir`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dependencies {
+    /// The names of the packages the version itself depends on
+    pub direct: Vec<String>,
+    /// Every package in the tree
+    pub packages: Vec<Package>,
+}
+
+impl Dependencies {
+    /// Reads the dependencies of `package` extracted to `extract_dir`, `None` if it has no
+    /// manifest
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` if a manifest cannot be read, or
+    /// `GleamPkgError::InvalidManifest` if it cannot be parsed
+    pub fn load(extract_dir: &Path, package: &str) -> Result<Option<Self>, GleamPkgError> {
+        for path in [
+            extract_dir.join("contents").join("manifest.toml"),
+            extract_dir.join(BUILD_PROJECT).join("manifest.toml"),
+        ] {
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(source) => {
+                    return Err(GleamPkgError::Io {
+                        action: "read dependency manifest",
+                        path,
+                        source,
+                    });
+                }
+            };
+            let manifest: Manifest =
+                toml::from_str(&content).map_err(|source| GleamPkgError::InvalidManifest {
+                    path: path.clone(),
+                    source,
+                })?;
+            return Ok(Some(Dependencies::from_manifest(manifest, package)));
+        }
+        Ok(None)
+    }
+
+    fn from_manifest(manifest: Manifest, package: &str) -> Self {
+        // the build project's manifest holds the package itself as a local one, and the
+        // project depending on it
+        let (local, packages): (Vec<_>, Vec<_>) = manifest
+            .packages
+            .into_iter()
+            .filter(|p| p.name != BUILD_PROJECT)
+            .partition(|p| p.name == package);
+        let direct = match local.into_iter().next() {
+            Some(package) => package.requirements,
+            None => packages.iter().map(|p| p.name.clone()).collect(),
+        };
+        Dependencies { direct, packages }
+    }
+
+    /// The shortest chain of requirements from the version to the package `name`, e.g.
+    /// `["glint", "snag", "gleam_stdlib"]`, `None` if it does not depend on it
+    pub fn chain_to(&self, name: &str) -> Option<Vec<&str>> {
+        let mut queue: VecDeque<Vec<&str>> = self.direct.iter().map(|d| vec![d.as_str()]).collect();
+        let mut visited = BTreeSet::new();
+        while let Some(chain) = queue.pop_front() {
+            let last = *chain.last()?;
+            if last == name {
+                return Some(chain);
+            }
+            if !visited.insert(last) {
+                continue;
+            }
+            let requirements = self
+                .packages
+                .iter()
+                .find(|p| p.name == last)
+                .map_or(&[][..], |p| &p.requirements);
+            for requirement in requirements {
+                let mut next = chain.clone();
+                next.push(requirement);
+                queue.push_back(next);
+            }
+        }
+        None
+    }
+
+    /// The package `name` in the tree, if the version depends on it
+    pub fn find(&self, name: &str) -> Option<&Package> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+
+    /// The tree the dependencies form below the version, depth first in the order of the
+    /// requirements; a package required again is listed again, but not its dependencies
+    pub fn tree(&self) -> Vec<TreeNode<'_>> {
+        let mut nodes = Vec::new();
+        let mut expanded = BTreeSet::new();
+        self.walk(&self.direct, &mut Vec::new(), &mut expanded, &mut nodes);
+        nodes
+    }
+
+    fn walk<'a>(
+        &'a self,
+        names: &[String],
+        branches: &mut Vec<bool>,
+        expanded: &mut BTreeSet<&'a str>,
+        nodes: &mut Vec<TreeNode<'a>>,
+    ) {
+        let children: Vec<_> = names
+            .iter()
+            .filter_map(|name| self.packages.iter().find(|p| p.name == *name))
+            .collect();
+        for (i, package) in children.iter().enumerate() {
+            branches.push(i + 1 == children.len());
+            let repeated = !expanded.insert(&package.name);
+            nodes.push(TreeNode {
+                package,
+                branches: branches.clone(),
+                repeated: repeated && !package.requirements.is_empty(),
+            });
+            if !repeated {
+                self.walk(&package.requirements, branches, expanded, nodes);
+            }
+            branches.pop();
+        }
+    }
+}
+
+/// A package in the tree of [`Dependencies::tree`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeNode<'a> {
+    pub package: &'a Package,
+    /// Whether the node and each of its ancestors are the last child of their parent, from the
+    /// top; as many as the node is deep
+    pub branches: Vec<bool>,
+    /// Whether the package was listed before with its dependencies, which are left out here
+    pub repeated: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_build_project_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join(BUILD_PROJECT);
+        fs::create_dir_all(&project).unwrap();
+        fs::write(
+            project.join("manifest.toml"),
+            r#"
+packages = [
+  { name = "argv", version = "1.0.2", requirements = [], source = "hex", outer_checksum = "BA1F" },
+  { name = "gleam_stdlib", version = "0.40.0", requirements = [], source = "hex" },
+  { name = "hello", version = "1.0.0", requirements = ["argv"], source = "local" },
+  { name = "gleam_pkg_build", version = "1.0.0", requirements = ["hello"], source = "local" },
+]
+
+[requirements]
+hello = { path = "../contents" }
+"#,
+        )
+        .unwrap();
+
+        let dependencies = Dependencies::load(dir.path(), "hello").unwrap().unwrap();
+        assert_eq!(dependencies.direct, ["argv"]);
+        let names: Vec<_> = dependencies.packages.iter().map(|p| &p.name).collect();
+        assert_eq!(names, ["argv", "gleam_stdlib"]);
+        assert!(
+            Dependencies::load(&dir.path().join("missing"), "hello")
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    fn package(name: &str, requirements: &[&str]) -> Package {
+        Package {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            source: "hex".to_string(),
+            requirements: requirements.iter().map(|r| r.to_string()).collect(),
+            outer_checksum: None,
+        }
+    }
+
+    #[test]
+    fn finds_the_shortest_chain_to_a_dependency() {
+        let dependencies = Dependencies {
+            direct: vec!["glint".to_string(), "argv".to_string()],
+            packages: vec![
+                package("argv", &[]),
+                package("gleam_stdlib", &[]),
+                package("glint", &["snag"]),
+                package("snag", &["gleam_stdlib"]),
+            ],
+        };
+        assert_eq!(dependencies.chain_to("argv"), Some(vec!["argv"]));
+        assert_eq!(
+            dependencies.chain_to("gleam_stdlib"),
+            Some(vec!["glint", "snag", "gleam_stdlib"])
+        );
+        assert_eq!(dependencies.chain_to("simplifile"), None);
+    }
+
+    #[test]
+    fn lays_out_the_tree_depth_first() {
+        let dependencies = Dependencies {
+            direct: vec!["argv".to_string(), "glint".to_string()],
+            packages: vec![
+                package("argv", &["gleam_stdlib"]),
+                package("gleam_stdlib", &[]),
+                package("glint", &["argv", "snag"]),
+                package("snag", &["gleam_stdlib"]),
+            ],
+        };
+        let tree: Vec<_> = dependencies
+            .tree()
+            .into_iter()
+            .map(|node| (node.package.name.as_str(), node.branches, node.repeated))
+            .collect();
+        assert_eq!(
+            tree,
+            [
+                ("argv", vec![false], false),
+                ("gleam_stdlib", vec![false, true], false),
+                ("glint", vec![true], false),
+                ("argv", vec![true, false], true),
+                ("snag", vec![true, true], false),
+                ("gleam_stdlib", vec![true, true, true], false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Health of tarball repositories and their mirrors
+//!
+//! A repository can list mirrors serving the same tarballs, see [`crate::config`]. Downloads try
+//! the repository first and then each mirror in order, moving on after network errors and `5xx`
+//! responses. Every attempt is recorded in `~/.gleam_pkgs/cache/mirrors.json`; a repository that
+//! failed recently, and has not worked since, is tried after the others until
+//! [`COOL_DOWN`] has passed.
+//!
+//! The record is advisory: when it cannot be read or written, downloads go ahead in the
+//! configured order.
+
+use crate::error::GleamPkgError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long a failed repository is tried last
+pub const COOL_DOWN: Duration = Duration::from_secs(10 * 60);
+
+/// Serializes updates of the record between threads
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// What happened to the downloads from one repository
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Health {
+    pub successes: u64,
+    pub failures: u64,
+    /// Unix time of the last success
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<u64>,
+    /// Unix time of the last failure
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_failure: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+impl Health {
+    /// Whether the repository failed within [`COOL_DOWN`] of `now` and has not worked since
+    pub fn is_cooling_down(&self, now: u64) -> bool {
+        match self.last_failure {
+            Some(failure) => {
+                self.last_success.is_none_or(|success| success < failure)
+                    && now.saturating_sub(failure) < COOL_DOWN.as_secs()
+            }
+            None => false,
+        }
+    }
+}
+
+/// The health of every repository gleam-pkg downloaded from, by repository URL
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HealthRecord {
+    pub repositories: BTreeMap<String, Health>,
+}
+
+impl HealthRecord {
+    /// Reads the record at `path`, treating a missing or unreadable one as empty
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// `repositories` in the order to try them: as given, except that the ones cooling down
+    /// come last
+    pub fn order(&self, repositories: &[String]) -> Vec<String> {
+        let now = now();
+        let cooling_down = |repository: &String| {
+            self.repositories
+                .get(repository)
+                .is_some_and(|health| health.is_cooling_down(now))
+        };
+        let (later, first): (Vec<_>, Vec<_>) = repositories.iter().cloned().partition(cooling_down);
+        first.into_iter().chain(later).collect()
+    }
+}
+
+/// Records a download attempt from `repository` in the record at `path`
+///
+/// # Arguments
+///
+/// * `path` - The health record
+/// * `repository` - The repository URL
+/// * `result` - The outcome of the attempt
+pub fn record(path: &Path, repository: &str, result: Result<(), &GleamPkgError>) {
+    let _lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut record = HealthRecord::load(path);
+    let health = record
+        .repositories
+        .entry(repository.to_string())
+        .or_default();
+    match result {
+        Ok(()) => {
+            health.successes += 1;
+            health.last_success = Some(now());
+        }
+        Err(e) => {
+            health.failures += 1;
+            health.last_failure = Some(now());
+            health.last_error = Some(e.to_string());
+        }
+    }
+    let tmp = path.with_extension("json.tmp");
+    let _ = serde_json::to_string_pretty(&record)
+        .map_err(std::io::Error::other)
+        .and_then(|json| fs::write(&tmp, json))
+        .and_then(|_| fs::rename(&tmp, path));
+}
+
+/// Whether a download failing with `error` may succeed from another mirror: network errors and
+/// server errors may, a missing tarball or a bad checksum will not
+pub fn should_fail_over(error: &GleamPkgError) -> bool {
+    match error {
+        GleamPkgError::RequestFailed { .. } => true,
+        GleamPkgError::HttpStatus { status, .. } => *status >= 500,
+        GleamPkgError::Io { action, .. } => *action == "download tarball",
+        _ => false,
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repositories() -> Vec<String> {
+        ["https://a/", "https://b/", "https://c/"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    #[test]
+    fn failed_repositories_are_tried_last() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mirrors.json");
+        let error = GleamPkgError::HttpStatus {
+            url: "https://a/tarballs/x-1.0.0.tar".to_string(),
+            status: 503,
+        };
+        record(&path, "https://a/", Err(&error));
+        record(&path, "https://b/", Ok(()));
+
+        let record = HealthRecord::load(&path);
+        assert_eq!(
+            record.order(&repositories()),
+            ["https://b/", "https://c/", "https://a/"]
+        );
+        assert_eq!(record.repositories["https://a/"].failures, 1);
+        assert!(should_fail_over(&error));
+    }
+
+    #[test]
+    fn failures_cool_down() {
+        let now = now();
+        let health = Health {
+            last_failure: Some(now - COOL_DOWN.as_secs() - 1),
+            ..Health::default()
+        };
+        assert!(!health.is_cooling_down(now));
+        let recovered = Health {
+            last_failure: Some(now - 10),
+            last_success: Some(now - 5),
+            ..Health::default()
+        };
+        assert!(!recovered.is_cooling_down(now));
+    }
+}
@@ -0,0 +1,182 @@
+//! The package names of a repository, kept for looking them up offline
+//!
+//! `search --offline`, shell completion and the suggestions for misspelled packages look names
+//! up in the `/names` resource of the repository, which lists every package it serves, see
+//! [`crate::index::decode_names`]. It is kept gzipped, with the time it was fetched on the first
+//! line and a name on every line after it:
+//!
+//! ```text
+//! cache/names/<repo>[/<organization>].gz
+//! ```
+//!
+//! `search` and installing a package the registry does not know refresh it after
+//! `[cache] names_ttl_secs`, a day by default, or at once with `--refresh`. Completion only reads
+//! it, so a keystroke never waits for the network. With `--offline`, or when the repository
+//! cannot be reached, the names fetched last are used however old they are.
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// The package names of a repository, sorted
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NamesIndex {
+    /// Unix time the names were fetched
+    pub fetched_at: u64,
+    names: Vec<String>,
+}
+
+impl NamesIndex {
+    /// An index of `names`, fetched at `fetched_at`
+    pub fn new(mut names: Vec<String>, fetched_at: u64) -> Self {
+        names.sort();
+        names.dedup();
+        NamesIndex { fetched_at, names }
+    }
+
+    /// Reads the index at `path`, treating an unreadable one as missing
+    pub fn load(path: &Path) -> Option<Self> {
+        let mut text = String::new();
+        GzDecoder::new(fs::File::open(path).ok()?)
+            .read_to_string(&mut text)
+            .ok()?;
+        let mut lines = text.lines();
+        let fetched_at = lines.next()?.parse().ok()?;
+        Some(NamesIndex::new(
+            lines.map(String::from).collect(),
+            fetched_at,
+        ))
+    }
+
+    /// Writes the index to `path`, replacing the one there at once
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut gzipped = GzEncoder::new(Vec::new(), Compression::default());
+        writeln!(gzipped, "{}", self.fetched_at)?;
+        for name in &self.names {
+            writeln!(gzipped, "{}", name)?;
+        }
+        let part = path.with_extension("gz.part");
+        fs::write(&part, gzipped.finish()?)?;
+        fs::rename(part, path)
+    }
+
+    /// The names starting with `prefix`, in order
+    pub fn complete<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> {
+        let start = self.names.partition_point(|name| name.as_str() < prefix);
+        self.names[start..]
+            .iter()
+            .take_while(move |name| name.starts_with(prefix))
+            .map(String::as_str)
+    }
+
+    /// The names containing `query`, those starting with it first
+    pub fn search(&self, query: &str) -> Vec<&str> {
+        let query = query.trim().to_lowercase();
+        let (mut found, contained): (Vec<&str>, Vec<&str>) = self
+            .names
+            .iter()
+            .map(String::as_str)
+            .filter(|name| name.contains(&query))
+            .partition(|name| name.starts_with(&query));
+        found.extend(contained);
+        found
+    }
+
+    /// The name closest to `name`, if it is close enough for `name` to be a misspelling of it:
+    /// one edit in four characters, at least one
+    pub fn suggest(&self, name: &str) -> Option<&str> {
+        let length = name.chars().count();
+        let max = (length / 4).max(1);
+        self.names
+            .iter()
+            .filter(|candidate| {
+                candidate.as_str() != name && candidate.chars().count().abs_diff(length) <= max
+            })
+            .map(|candidate| (distance(name, candidate), candidate.as_str()))
+            .filter(|(distance, _)| *distance <= max)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, candidate)| candidate)
+    }
+}
+
+/// How many characters have to be inserted, removed, replaced or swapped with the next one to
+/// turn `a` into `b`, the optimal string alignment distance
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in table.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in table[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let replaced = table[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut best = replaced.min(table[i - 1][j] + 1).min(table[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(table[i - 2][j - 2] + 1);
+            }
+            table[i][j] = best;
+        }
+    }
+    table[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> NamesIndex {
+        let names = [
+            "wisp",
+            "gleam_json",
+            "argv",
+            "gleam_stdlib",
+            "json_schema",
+            "wisp",
+        ];
+        NamesIndex::new(names.map(String::from).to_vec(), 1000)
+    }
+
+    #[test]
+    fn round_trips_through_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("names").join("hexpm.gz");
+        assert!(NamesIndex::load(&path).is_none());
+        index().save(&path).unwrap();
+        let loaded = NamesIndex::load(&path).unwrap();
+        assert_eq!(loaded, index());
+        assert_eq!(loaded.complete("").count(), 5);
+        assert!(!dir.path().join("names").join("hexpm.gz.part").exists());
+    }
+
+    #[test]
+    fn completes_and_searches_names() {
+        let index = index();
+        assert_eq!(
+            index.complete("gleam_").collect::<Vec<_>>(),
+            ["gleam_json", "gleam_stdlib"]
+        );
+        assert_eq!(index.complete("x").count(), 0);
+        assert_eq!(index.search("JSON"), ["json_schema", "gleam_json"]);
+    }
+
+    #[test]
+    fn suggests_names_a_few_edits_away() {
+        let index = index();
+        assert_eq!(index.suggest("wsip"), Some("wisp"));
+        assert_eq!(index.suggest("gleam_jsno"), Some("gleam_json"));
+        assert_eq!(index.suggest("gleam_stdlb"), Some("gleam_stdlib"));
+        assert_eq!(index.suggest("wisp"), None);
+        assert_eq!(index.suggest("lustre"), None);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+}
@@ -0,0 +1,612 @@
+//! Console output helpers
+//!
+//! Status lines carry a marker (`✓` installed, `✗` failed, `↻` updating, `!` warning) that is
+//! colored when color is enabled, and tabular output goes through [`Table`] so columns line up
+//! regardless of content. Color is turned off by `--no-color`, by a non-empty `NO_COLOR`
+//! environment variable, by `color = false` in the configuration, or when stdout is not a
+//! terminal.
+//!
+//! `--plain` prints discrete lines for screen readers and logging systems: no color, no escape
+//! sequences, words instead of markers (`error:`, `warning:`, `note:`), steps counted as
+//! `3 of 12` instead of drawn as a bar, and tables only narrowed to `COLUMNS`, never paged. It
+//! is selected when stdout is not a terminal, or with `plain = true` in the configuration.
+//!
+//! `--quiet` drops progress and status lines, leaving the results and errors. `--porcelain`
+//! also replaces the human text of results with a format that is stable across releases, for
+//! scripts: one record per line, fields separated by tabs, no header, no color.
+//!
+//! Tables fit the width of the terminal, or `COLUMNS` if it is set: the widest columns are
+//! narrowed, their cells cut short with `…`, except for the last column, which wraps onto more
+//! lines. Output into a pipe or a file is never narrowed. Long output of `list`, `search` and
+//! `releases` goes through `$PAGER`, `less` by default, with `--pager` or `pager = true` in the
+//! configuration, unless `--no-pager` is passed.
+
+use crate::i18n::{self, Message};
+use std::fmt::Display;
+use std::io::{IsTerminal, Write};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+static PORCELAIN: AtomicBool = AtomicBool::new(false);
+static PLAIN: AtomicBool = AtomicBool::new(false);
+static PAGER: AtomicBool = AtomicBool::new(false);
+/// The width tables fit, 0 for any
+static WIDTH: AtomicUsize = AtomicUsize::new(0);
+
+/// The narrowest a column is made to fit the terminal
+const MIN_COLUMN: usize = 8;
+
+/// ANSI styles used by the console output
+#[derive(Debug, Clone, Copy)]
+pub enum Style {
+    Bold,
+    Dim,
+    Green,
+    Red,
+    Yellow,
+    Cyan,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Style::Bold => "1",
+            Style::Dim => "2",
+            Style::Green => "32",
+            Style::Red => "31",
+            Style::Yellow => "33",
+            Style::Cyan => "36",
+        }
+    }
+}
+
+/// Decides once whether output is colored, quiet, porcelain and plain
+///
+/// # Arguments
+///
+/// * `no_color` - Whether `--no-color` was passed
+/// * `quiet` - Whether `--quiet` was passed
+/// * `porcelain` - Whether `--porcelain` was passed, which implies the other two
+/// * `plain` - Whether `--plain` was passed, implied by a stdout that is not a terminal
+pub fn init(no_color: bool, quiet: bool, porcelain: bool, plain: bool) {
+    let terminal = std::io::stdout().is_terminal();
+    let plain = plain || !terminal;
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let enabled = !no_color && !porcelain && !plain && !no_color_env;
+    COLOR.store(enabled, Ordering::Relaxed);
+    QUIET.store(quiet || porcelain, Ordering::Relaxed);
+    PORCELAIN.store(porcelain, Ordering::Relaxed);
+    PLAIN.store(plain, Ordering::Relaxed);
+    // measured before a pager takes stdout over
+    let width = match plain {
+        true => columns(),
+        false => terminal_width(),
+    };
+    WIDTH.store(width.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// `COLUMNS` if it is set
+fn columns() -> Option<usize> {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.trim().parse().ok())
+        .filter(|c| *c > 0)
+}
+
+/// [`columns`] if it is set, and otherwise the width of the terminal on stdout
+fn terminal_width() -> Option<usize> {
+    if let Some(columns) = columns() {
+        return Some(columns);
+    }
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    (ok && size.ws_col > 0).then_some(usize::from(size.ws_col))
+}
+
+/// Decides whether long output is paged, after [`init`] like [`disable_color`]
+///
+/// # Arguments
+///
+/// * `pager` - Whether `--pager` was passed or `pager = true` is configured
+/// * `no_pager` - Whether `--no-pager` was passed, which wins
+pub fn init_pager(pager: bool, no_pager: bool) {
+    PAGER.store(pager && !no_pager, Ordering::Relaxed);
+}
+
+/// Stdout piped through the pager, until it is dropped
+pub struct Pager {
+    child: Child,
+    /// Stdout before it was piped
+    stdout: OwnedFd,
+}
+
+/// Pipes stdout through `$PAGER`, `less` by default, if paging is on and stdout is a terminal
+///
+/// Porcelain output is never paged, and output is printed as it is when the pager cannot be
+/// started. Quitting the pager before the end of the output ends gleam-pkg as well, like any
+/// command writing into a closed pipe.
+pub fn pager() -> Option<Pager> {
+    if !PAGER.load(Ordering::Relaxed) || porcelain() || plain() || !std::io::stdout().is_terminal()
+    {
+        return None;
+    }
+    let command = std::env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| "less".to_string());
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        // quit at once when it fits the screen, keep colors, leave the screen alone
+        .env(
+            "LESS",
+            std::env::var("LESS").unwrap_or_else(|_| "FRX".to_string()),
+        )
+        .stdin(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let pipe = child.stdin.take()?;
+    let stdout = std::io::stdout().as_fd().try_clone_to_owned().ok()?;
+    let _ = std::io::stdout().flush();
+    unsafe {
+        libc::dup2(pipe.as_raw_fd(), libc::STDOUT_FILENO);
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+    Some(Pager { child, stdout })
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = std::io::stdout().flush();
+        // closing the last end of the pipe tells the pager the output is complete
+        unsafe { libc::dup2(self.stdout.as_raw_fd(), libc::STDOUT_FILENO) };
+        let _ = self.child.wait();
+    }
+}
+
+/// Turns color off after [`init`], for `color = false` in the configuration, which is read later
+pub fn disable_color() {
+    COLOR.store(false, Ordering::Relaxed);
+}
+
+/// Turns the porcelain format on after [`init`], for `porcelain = true` in the configuration
+pub fn enable_porcelain() {
+    PORCELAIN.store(true, Ordering::Relaxed);
+    QUIET.store(true, Ordering::Relaxed);
+    COLOR.store(false, Ordering::Relaxed);
+}
+
+/// Turns plain output on after [`init`], for `plain = true` in the configuration
+pub fn enable_plain() {
+    PLAIN.store(true, Ordering::Relaxed);
+    COLOR.store(false, Ordering::Relaxed);
+    WIDTH.store(columns().unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Whether output is colored
+pub fn color_enabled() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// Whether progress and status lines are dropped
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Whether results are printed in the porcelain format
+pub fn porcelain() -> bool {
+    PORCELAIN.load(Ordering::Relaxed)
+}
+
+/// Whether output is plain, see the [module documentation](self)
+pub fn plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+/// Wraps `text` in the escape sequences for `style`, or returns it unchanged without color
+pub fn paint(text: impl Display, style: Style) -> String {
+    if color_enabled() {
+        format!("\x1b[{}m{}\x1b[0m", style.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Reports progress, e.g. where a tarball is downloaded from
+pub fn info(message: impl Display) {
+    if !quiet() {
+        println!("{}", message);
+    }
+}
+
+/// Reports a completed step, e.g. an installed package
+pub fn success(message: impl Display) {
+    if !quiet() {
+        println!("{}", marked("✓", Style::Green, None, message));
+    }
+}
+
+/// Reports a step that is in progress, e.g. a package being updated
+pub fn updating(message: impl Display) {
+    if !quiet() {
+        println!("{}", marked("↻", Style::Cyan, None, message));
+    }
+}
+
+/// `message` after `marker`, or after `word` and a colon in plain output; the messages of
+/// markers without a word say what happened well enough on their own
+fn marked(marker: &str, style: Style, word: Option<Message>, message: impl Display) -> String {
+    match (plain(), word) {
+        (false, _) => format!("{} {}", paint(marker, style), message),
+        (true, Some(word)) => format!("{}: {}", i18n::text(word), message),
+        (true, None) => message.to_string(),
+    }
+}
+
+/// Reports that `done` of `total` steps finished, the last one as `message`, after a bar of how
+/// far along they are, e.g. the packages of `update --all`
+pub fn progress(done: usize, total: usize, message: impl Display) {
+    if quiet() {
+        return;
+    }
+    match plain() {
+        true => println!(
+            "{}: {}",
+            i18n::fill(Message::StepOf, &[("done", &done), ("total", &total)]),
+            message
+        ),
+        false => println!(
+            "{} {}",
+            paint(progress_bar(done, total), Style::Cyan),
+            message
+        ),
+    }
+}
+
+/// How many characters wide the bar of [`progress`] is, without its brackets
+const PROGRESS_WIDTH: usize = 20;
+
+fn progress_bar(done: usize, total: usize) -> String {
+    let filled = (done * PROGRESS_WIDTH)
+        .checked_div(total)
+        .unwrap_or(PROGRESS_WIDTH);
+    let filled = filled.min(PROGRESS_WIDTH);
+    let width = total.to_string().len();
+    format!(
+        "[{}{}] {:>width$}/{}",
+        "#".repeat(filled),
+        "-".repeat(PROGRESS_WIDTH - filled),
+        done,
+        total
+    )
+}
+
+/// Reports something besides what the command did on stderr, e.g. that updates are available
+pub fn notice(message: impl Display) {
+    eprintln!("{}", marked("»", Style::Cyan, Some(Message::Note), message));
+}
+
+/// Reports a failed step on stderr
+pub fn failure(message: impl Display) {
+    eprintln!("{}", marked("✗", Style::Red, Some(Message::Error), message));
+}
+
+/// Reports a warning on stderr
+pub fn warning(message: impl Display) {
+    eprintln!(
+        "{}",
+        marked("!", Style::Yellow, Some(Message::Warning), message)
+    );
+}
+
+/// Prints a porcelain record, with tabs and line breaks inside fields turned into spaces
+pub fn record(fields: &[&str]) {
+    println!("{}", format_record(fields));
+}
+
+fn format_record(fields: &[&str]) -> String {
+    fields
+        .iter()
+        .map(|field| field.replace(['\t', '\n', '\r'], " "))
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+/// Prints a porcelain record on stderr, even with `--quiet`, for logs read by machines while
+/// stdout carries what the command prints, e.g. `gleam-pkg bootstrap --non-interactive`
+pub fn log(fields: &[&str]) {
+    eprintln!("{}", format_record(fields));
+}
+
+/// Formats a byte count for humans, e.g. `12.3 KiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Formats a Unix time as a UTC date and time, e.g. `2024-12-25 17:30:00 UTC`
+pub fn format_timestamp(secs: u64) -> String {
+    let (year, month, day) = civil_date(secs);
+    let time = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Formats a Unix time as an RFC 3339 timestamp, e.g. `2024-12-25T17:30:00Z`
+pub fn format_rfc3339(secs: u64) -> String {
+    let (year, month, day) = civil_date(secs);
+    let time = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// The UTC year, month and day of a Unix time
+fn civil_date(secs: u64) -> (i64, i64, i64) {
+    let days = (secs / 86400) as i64;
+    // civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// A table printed with aligned columns and a bold header row
+pub struct Table {
+    headers: Vec<String>,
+    right_aligned: Vec<bool>,
+    rows: Vec<Vec<(String, Option<Style>)>>,
+}
+
+impl Table {
+    /// Creates a table with the given column headers
+    pub fn new(headers: &[&str]) -> Self {
+        Table {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            right_aligned: vec![false; headers.len()],
+            rows: Vec::new(),
+        }
+    }
+
+    /// Right-aligns the column at `index`, for numbers
+    pub fn align_right(mut self, index: usize) -> Self {
+        self.right_aligned[index] = true;
+        self
+    }
+
+    /// Appends a row whose cells may each carry a style
+    pub fn styled_row(&mut self, cells: Vec<(String, Option<Style>)>) {
+        self.rows.push(cells);
+    }
+
+    /// Prints the table to stdout, as porcelain records without the header with `--porcelain`
+    pub fn print(&self) {
+        if porcelain() {
+            for row in &self.rows {
+                record(
+                    &row.iter()
+                        .map(|(cell, _)| cell.as_str())
+                        .collect::<Vec<_>>(),
+                );
+            }
+            return;
+        }
+        let mut widths = self
+            .headers
+            .iter()
+            .map(|h| h.chars().count())
+            .collect::<Vec<_>>();
+        for row in &self.rows {
+            for (width, (cell, _)) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        match WIDTH.load(Ordering::Relaxed) {
+            0 => {}
+            max => fit(&mut widths, max),
+        }
+
+        let header = self
+            .headers
+            .iter()
+            .map(|h| (h.clone(), Some(Style::Bold)))
+            .collect::<Vec<_>>();
+        self.print_row(&header, &widths);
+        for row in &self.rows {
+            self.print_row(row, &widths);
+        }
+    }
+
+    fn print_row(&self, cells: &[(String, Option<Style>)], widths: &[usize]) {
+        let last = cells.len().saturating_sub(1);
+        // the last column wraps, the others are cut short
+        let lines: Vec<Vec<String>> = cells
+            .iter()
+            .enumerate()
+            .map(|(i, (cell, _))| match i == last && !self.right_aligned[i] {
+                true => wrap(cell, widths[i]),
+                false => vec![truncate(cell, widths[i])],
+            })
+            .collect();
+        let height = lines.iter().map(Vec::len).max().unwrap_or(1);
+        for n in 0..height {
+            let line = cells
+                .iter()
+                .zip(&lines)
+                .enumerate()
+                .map(|(i, ((_, style), lines))| {
+                    let cell = lines.get(n).map_or("", String::as_str);
+                    let padding = " ".repeat(widths[i].saturating_sub(cell.chars().count()));
+                    let text = match style {
+                        Some(style) if !cell.is_empty() => paint(cell, *style),
+                        _ => cell.to_string(),
+                    };
+                    if self.right_aligned[i] {
+                        format!("{}{}", padding, text)
+                    } else if i == last {
+                        text
+                    } else {
+                        format!("{}{}", text, padding)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("  ");
+            println!("{}", line.trim_end());
+        }
+    }
+}
+
+/// Narrows the widest columns until rows of `widths`, two spaces apart, fit in `max` characters,
+/// or every column is down to [`MIN_COLUMN`]
+fn fit(widths: &mut [usize], max: usize) {
+    let gaps = 2 * widths.len().saturating_sub(1);
+    while widths.iter().sum::<usize>() + gaps > max {
+        let Some(widest) = widths.iter_mut().max().filter(|w| **w > MIN_COLUMN) else {
+            return;
+        };
+        *widest -= 1;
+    }
+}
+
+/// `cell` cut short to `width` characters, ending in `…` if it was cut
+fn truncate(cell: &str, width: usize) -> String {
+    if cell.chars().count() <= width {
+        return cell.to_string();
+    }
+    let mut cut: String = cell.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+/// `cell` broken into lines of at most `width` characters, between words where possible
+fn wrap(cell: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = vec![String::new()];
+    for word in cell.split(' ') {
+        let mut word: Vec<char> = word.chars().collect();
+        let line = lines.last_mut().expect("there is a line");
+        let length = line.chars().count();
+        if length > 0 && length + 1 + word.len() <= width {
+            line.push(' ');
+        } else if length > 0 {
+            lines.push(String::new());
+        }
+        // words longer than a line are broken
+        while word.len() > width {
+            let rest = word.split_off(width);
+            lines.last_mut().expect("there is a line").extend(&word);
+            lines.push(String::new());
+            word = rest;
+        }
+        lines.last_mut().expect("there is a line").extend(&word);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+        assert_eq!(format_size(u64::MAX), "16777216.0 TiB");
+    }
+
+    #[test]
+    fn draws_how_far_along_steps_are() {
+        assert_eq!(progress_bar(0, 4), "[--------------------] 0/4");
+        assert_eq!(progress_bar(3, 12), "[#####---------------]  3/12");
+        assert_eq!(progress_bar(12, 12), "[####################] 12/12");
+        assert_eq!(progress_bar(0, 0), "[####################] 0/0");
+    }
+
+    #[test]
+    fn porcelain_records_keep_one_field_per_column() {
+        assert_eq!(
+            format_record(&[
+                "wisp",
+                "1.2.0",
+                "hexpm",
+                "/home/me/.gleam_pkgs/apps/wisp-1.2.0"
+            ]),
+            "wisp\t1.2.0\thexpm\t/home/me/.gleam_pkgs/apps/wisp-1.2.0"
+        );
+        assert_eq!(
+            format_record(&["a\tb", "multi\nline", ""]),
+            "a b\tmulti line\t"
+        );
+    }
+
+    #[test]
+    fn fits_tables_into_the_terminal() {
+        let mut widths = [7, 5, 60];
+        fit(&mut widths, 40);
+        assert_eq!(widths, [7, 5, 24]);
+        let mut widths = [20, 20, 20];
+        fit(&mut widths, 10);
+        assert_eq!(widths, [MIN_COLUMN; 3]);
+
+        assert_eq!(truncate("wisp", 4), "wisp");
+        assert_eq!(truncate("gleam_stdlib", 8), "gleam_s…");
+        assert_eq!(
+            wrap("A practical web framework for Gleam", 12),
+            ["A practical", "web", "framework", "for Gleam"]
+        );
+        assert_eq!(
+            wrap("gleam_community_ansi", 8),
+            ["gleam_co", "mmunity_", "ansi"]
+        );
+        assert_eq!(wrap("", 8), [""]);
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951782400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(1735147800), "2024-12-25 17:30:00 UTC");
+        assert_eq!(format_rfc3339(1735147800), "2024-12-25T17:30:00Z");
+    }
+}
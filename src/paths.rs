@@ -0,0 +1,274 @@
+//! The layout of the installation root
+//!
+//! Everything gleam-pkg keeps lives below one root directory, `~/.gleam_pkgs` by default:
+//!
+//! ```text
+//! apps/          wrapper scripts, the directory to put on PATH
+//! lib/           build artifacts, one directory per installed version
+//! download/      release tarballs and their extracted sources
+//! db/            the package database
+//! logs/          build logs
+//! cache/         cached registry metadata
+//! store/         escripts by checksum, see [`crate::store`]
+//! toolchains/    Gleam compilers gleam-pkg downloaded, see [`crate::toolchains`]
+//! config.toml    the configuration, see [`crate::config`]
+//! ```
+//!
+//! Without `~/.gleam_pkgs`, an installation at `$XDG_DATA_HOME/gleam-pkg`, by default
+//! `~/.local/share/gleam-pkg`, is used instead; the first-run setup offers to create the root
+//! there, see [`crate::setup`].
+//!
+//! `GLEAM_PKG_ROOT` moves the root elsewhere, e.g. `GLEAM_PKG_ROOT=/opt/gleam-pkg`. Hooks run
+//! with it set to the root they were run for, see [`crate::hooks`], so a `gleam-pkg` they run
+//! works on the same installation.
+//!
+//! The root is passed around as a [`Paths`] rather than looked up globally, so commands and
+//! tests can work on any directory. Paths below it are kept as `OsStr`, so a root with spaces or
+//! in a legacy, non-UTF-8 locale works; the files of a version are named `<package>-<version>`,
+//! which [`versioned_name`] checks is a single file name of a length every filesystem accepts.
+//! Names that differ only in case, like `Foo` and `foo`, name the same file on the default
+//! filesystems of macOS and Windows, so [`same_name`] tells them apart nowhere.
+
+use crate::error::GleamPkgError;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the root directory below the home directory
+pub const ROOT_DIR: &str = ".gleam_pkgs";
+
+/// The environment variable overriding the root directory when set to a non-empty path
+pub const ROOT_VAR: &str = "GLEAM_PKG_ROOT";
+
+/// The name of the root directory below the XDG data directory
+pub const XDG_ROOT_DIR: &str = "gleam-pkg";
+
+/// The longest file name common filesystems accept, in bytes
+pub const NAME_MAX: usize = 255;
+
+/// What the files named after a version add to `<package>-<version>` at most, e.g. the
+/// `-<timestamp>.log` of build logs
+const SUFFIX_MAX: usize = 32;
+
+/// Checks that `name` is a single file name, of at most `max` bytes
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::InvalidName` if it is empty, `.` or `..`, holds a `/` or a NUL byte,
+/// or is longer
+pub fn check_name(name: &str, max: usize) -> Result<(), GleamPkgError> {
+    let reason = match name {
+        "" => "it is empty",
+        "." | ".." => "it names a directory",
+        _ if name.contains(['/', '\0']) => "it holds a path separator or a NUL byte",
+        _ if name.len() > max => "it is too long for the filesystem",
+        _ => return Ok(()),
+    };
+    Err(GleamPkgError::InvalidName {
+        name: name.to_string(),
+        reason,
+    })
+}
+
+/// The name of the wrapper, build and download of `package` at `version`, `<package>-<version>`
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::InvalidName` if it cannot name a file, see [`check_name`], with room
+/// for the suffixes of the files named after it
+pub fn versioned_name(package: &str, version: &str) -> Result<String, GleamPkgError> {
+    check_name(package, NAME_MAX)?;
+    check_name(version, NAME_MAX)?;
+    let name = format!("{}-{}", package, version);
+    check_name(&name, NAME_MAX - SUFFIX_MAX)?;
+    Ok(name)
+}
+
+/// Whether `a` and `b` name the same file on a case-insensitive filesystem
+pub fn same_name(a: &str, b: &str) -> bool {
+    a == b || a.to_lowercase() == b.to_lowercase()
+}
+
+/// The directories and files of an installation root
+#[derive(Debug, Clone)]
+pub struct Paths {
+    root: PathBuf,
+}
+
+impl Paths {
+    /// The layout below `root`
+    pub fn new(root: &Path) -> Self {
+        Paths {
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// The layout below `$GLEAM_PKG_ROOT`, or if it is not set `~/.gleam_pkgs`, unless only the
+    /// XDG root exists, see [`Paths::xdg`]
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::HomeDirNotFound` if the home directory cannot be determined
+    pub fn home() -> Result<Self, GleamPkgError> {
+        if let Some(root) = Paths::from_env() {
+            return Ok(root);
+        }
+        let home_dir = dirs::home_dir().ok_or(GleamPkgError::HomeDirNotFound)?;
+        let root = home_dir.join(ROOT_DIR);
+        match Paths::xdg() {
+            Some(xdg) if !root.exists() && xdg.root.exists() => Ok(xdg),
+            _ => Ok(Paths::new(&root)),
+        }
+    }
+
+    /// The layout below `$GLEAM_PKG_ROOT`, if it is set
+    pub fn from_env() -> Option<Self> {
+        let root = std::env::var_os(ROOT_VAR).filter(|root| !root.is_empty())?;
+        // wrappers refer to the root, which must not depend on where they run
+        let root = std::path::absolute(&root).unwrap_or_else(|_| root.into());
+        Some(Paths::new(&root))
+    }
+
+    /// The layout below `$XDG_DATA_HOME/gleam-pkg`, or `~/.local/share/gleam-pkg` if it is not
+    /// set to an absolute path
+    pub fn xdg() -> Option<Self> {
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| Some(dirs::home_dir()?.join(".local").join("share")))?;
+        Some(Paths::new(&data_home.join(XDG_ROOT_DIR)))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Wrapper scripts and links, the directory to put on `PATH`
+    pub fn apps(&self) -> PathBuf {
+        self.root.join("apps")
+    }
+
+    /// Build artifacts, one directory per installed version
+    pub fn lib(&self) -> PathBuf {
+        self.root.join("lib")
+    }
+
+    /// Release tarballs and their extracted sources
+    pub fn download(&self) -> PathBuf {
+        self.root.join("download")
+    }
+
+    pub fn logs(&self) -> PathBuf {
+        self.root.join("logs")
+    }
+
+    pub fn cache(&self) -> PathBuf {
+        self.root.join("cache")
+    }
+
+    /// Build artifacts by checksum, see [`crate::store`]
+    pub fn store(&self) -> PathBuf {
+        self.root.join("store")
+    }
+
+    /// Gleam compilers by version, see [`crate::toolchains`]
+    pub fn toolchains(&self) -> PathBuf {
+        self.root.join("toolchains")
+    }
+
+    /// How downloads from each repository and mirror went, see [`crate::mirrors`]
+    pub fn mirror_health(&self) -> PathBuf {
+        self.cache().join("mirrors.json")
+    }
+
+    pub fn db_file(&self) -> PathBuf {
+        self.root.join("db").join("metadata.sqlite")
+    }
+
+    pub fn config_file(&self) -> PathBuf {
+        self.root.join("config.toml")
+    }
+
+    /// Creates the root and every directory below it that does not exist yet
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::DirectoryCreationError` if any of the directories cannot be
+    /// created
+    pub fn create_dirs(&self) -> Result<(), GleamPkgError> {
+        let dirs = [
+            self.root.clone(),
+            self.download(),
+            self.apps(),
+            self.lib(),
+            self.root.join("db"),
+            self.logs(),
+            self.cache(),
+            self.store(),
+            self.toolchains(),
+        ];
+        for path in dirs {
+            if !path.exists() {
+                fs::create_dir_all(&path)
+                    .map_err(|source| GleamPkgError::DirectoryCreationError { path, source })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn versions_name_single_files() {
+        assert_eq!(versioned_name("hello", "1.0.0").unwrap(), "hello-1.0.0");
+        assert_eq!(
+            versioned_name("grüße", "1.0.0-rc.1+build.5").unwrap(),
+            "grüße-1.0.0-rc.1+build.5"
+        );
+        for (package, version) in [
+            ("", "1.0.0"),
+            ("..", "1.0.0"),
+            ("../../etc", "1.0.0"),
+            ("hello", "1.0.0/../../x"),
+            ("hello", "1.0.0\0"),
+        ] {
+            assert!(matches!(
+                versioned_name(package, version),
+                Err(GleamPkgError::InvalidName { .. })
+            ));
+        }
+        // within the limit only with the suffixes of logs and tarballs
+        let long = "a".repeat(NAME_MAX - 10);
+        assert!(check_name(&long, NAME_MAX).is_ok());
+        assert!(versioned_name(&long, "1.0.0").is_err());
+        // counted in bytes, as filesystems do
+        let wide = "ü".repeat(120);
+        assert!(versioned_name(&wide, "1.0.0").is_err());
+        assert!(versioned_name(&wide[..200], "1.0.0").is_ok());
+    }
+
+    #[test]
+    fn names_differing_in_case_are_the_same() {
+        assert!(same_name("foo", "foo"));
+        assert!(same_name("Foo", "foo"));
+        assert!(same_name("ÄRGER", "ärger"));
+        assert!(!same_name("foo", "foo_cli"));
+    }
+
+    #[test]
+    fn roots_need_not_be_unicode() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir
+            .path()
+            .join(OsStr::from_bytes(b"caf\xe9 root"))
+            .join(ROOT_DIR);
+        let paths = Paths::new(&root);
+        paths.create_dirs().unwrap();
+        assert!(paths.apps().is_dir());
+        assert!(paths.db_file().starts_with(&root));
+    }
+}
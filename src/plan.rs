@@ -0,0 +1,163 @@
+//! Dry runs of mutating commands
+//!
+//! With `--dry-run`, `install`, `update` and `uninstall` resolve what they would do as usual
+//! but collect the steps into a [`Plan`] instead of performing them, and print it. Nothing is
+//! written: metadata fetched for the plan is not cached, and no statistics are recorded.
+
+use crate::config::HooksConfig;
+use crate::hooks::{self, Event};
+use crate::output::{self, Style, Table};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Decides once whether this is a dry run
+pub fn init(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+/// Whether commands only print what they would do
+pub fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// One step a command would take
+#[derive(Debug)]
+pub enum Action {
+    /// Fetch a release tarball, with its size if the server announced it
+    Download { url: String, size: Option<u64> },
+    /// Unpack a tarball into a directory
+    Extract { path: PathBuf },
+    /// Compile a package, with the backend if it is known before downloading
+    Build {
+        package: String,
+        version: String,
+        backend: Option<String>,
+    },
+    /// Create or replace a file or directory
+    Write { path: PathBuf },
+    /// Point a link at another wrapper
+    Link { path: PathBuf, target: String },
+    /// Delete a file or directory, with the space it takes up
+    Remove { path: PathBuf, size: u64 },
+    /// Change the package database
+    Record { change: String },
+    /// Run a hook from `config.toml`
+    Hook { event: Event, command: String },
+}
+
+/// The steps of a dry run, in order
+#[derive(Debug, Default)]
+pub struct Plan {
+    actions: Vec<Action>,
+}
+
+impl Plan {
+    pub fn new() -> Self {
+        Plan::default()
+    }
+
+    pub fn push(&mut self, action: Action) {
+        self.actions.push(action);
+    }
+
+    /// Adds the hooks configured for `event` of `package`
+    pub fn hooks(&mut self, config: &HooksConfig, event: Event, package: &str) {
+        for command in hooks::commands(config, event, package) {
+            self.push(Action::Hook {
+                event,
+                command: command.to_string(),
+            });
+        }
+    }
+
+    /// Adds the removal of `path`, if it exists
+    pub fn remove(&mut self, path: PathBuf) {
+        if path.symlink_metadata().is_ok() {
+            let size = disk_usage(&path);
+            self.push(Action::Remove { path, size });
+        }
+    }
+
+    /// Prints the steps as a table, followed by the total download and removal sizes
+    pub fn print(&self) {
+        if self.actions.is_empty() {
+            println!("Nothing to do");
+            return;
+        }
+        let mut table = Table::new(&["ACTION", "PATH", "SIZE"]).align_right(2);
+        let mut downloaded = 0;
+        let mut removed = 0;
+        for action in &self.actions {
+            let (name, style, subject, size) = match action {
+                Action::Download { url, size } => {
+                    downloaded += size.unwrap_or(0);
+                    ("download", Style::Cyan, url.clone(), *size)
+                }
+                Action::Extract { path } => ("extract", Style::Cyan, display(path), None),
+                Action::Build {
+                    package,
+                    version,
+                    backend,
+                } => {
+                    let backend = backend.as_deref().unwrap_or("detected after download");
+                    let subject = format!("{} {} ({})", package, version, backend);
+                    ("build", Style::Cyan, subject, None)
+                }
+                Action::Write { path } => ("write", Style::Green, display(path), None),
+                Action::Link { path, target } => {
+                    let subject = format!("{} -> {}", display(path), target);
+                    ("link", Style::Green, subject, None)
+                }
+                Action::Remove { path, size } => {
+                    removed += size;
+                    ("remove", Style::Red, display(path), Some(*size))
+                }
+                Action::Record { change } => ("record", Style::Dim, change.clone(), None),
+                Action::Hook { event, command } => {
+                    ("hook", Style::Cyan, format!("{}: {}", event, command), None)
+                }
+            };
+            table.styled_row(vec![
+                (name.to_string(), Some(style)),
+                (subject, None),
+                (size.map(output::format_size).unwrap_or_default(), None),
+            ]);
+        }
+        table.print();
+        if downloaded == 0 && removed > 0 {
+            println!(
+                "\nWould free {}, nothing was changed (--dry-run)",
+                output::format_size(removed)
+            );
+            return;
+        }
+        println!(
+            "\nWould download {} and free {}, nothing was changed (--dry-run)",
+            output::format_size(downloaded),
+            output::format_size(removed)
+        );
+    }
+}
+
+fn display(path: &Path) -> String {
+    path.display().to_string()
+}
+
+/// The bytes taken up by `path` and, for a directory, everything below it
+pub fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| disk_usage(&entry.path()))
+        .sum()
+}
@@ -0,0 +1,518 @@
+//! Tools installed per project
+//!
+//! Like the local binaries of npm, a project can pin the tools it is developed with in a
+//! `.gleam-tools.toml` at its root, created by `gleam-pkg project init`:
+//!
+//! ```toml
+//! [tools]
+//! wonderful_cli = "1.2.0"
+//! # a prefix installs the latest matching release
+//! lustre_dev_tools = "1"
+//! # --target, detected from the package otherwise
+//! hello_node = { version = "0.3", target = "node" }
+//! ```
+//!
+//! `gleam-pkg project install` installs them into `.gleam_pkgs/` next to the file, an
+//! installation root laid out like `~/.gleam_pkgs` (see [`crate::paths`]) but configured by the
+//! global `config.toml`. It also makes sure the `.envrc` of the project contains
+//!
+//! ```text
+//! PATH_add .gleam_pkgs/apps
+//! ```
+//!
+//! so that direnv puts the tools of the project first on `PATH` in every shell inside it.
+//! Without direnv, the global wrappers dispatch to the project's installation themselves, see
+//! [`crate::backend::dispatch_to_project`].
+//!
+//! What got installed is recorded in `.gleam-tools.lock`, meant to be committed with the
+//! manifest:
+//!
+//! ```toml
+//! [tools.wonderful_cli]
+//! version = "1.2.0"
+//! target = "erlang"
+//! checksum = "8a1c…"
+//!
+//! [tools.wonderful_cli.toolchain]
+//! erlang = "27.1.2"
+//! gleam = "1.6.2"
+//! ```
+//!
+//! Later installs keep the locked version of every tool whose manifest entry still accepts it,
+//! and refuse tarballs whose checksum differs from the locked one. `project install --locked`
+//! also refuses to change the lockfile, and builds with the locked Gleam compiler, so it
+//! reproduces the same tools on every machine or fails.
+//!
+//! `gleam-pkg ci-verify` checks the same without installing anything: every tool must be locked
+//! at a version the manifest accepts, resolve in its registry and download with both the
+//! published and the locked checksum, see [`ToolCheck`].
+
+use crate::backend::Target;
+use crate::error::GleamPkgError;
+use crate::output::{Style, Table};
+use crate::paths::{self, Paths};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The file listing the tools of a project
+pub const MANIFEST: &str = ".gleam-tools.toml";
+
+/// The file recording the installed tools of a project
+pub const LOCKFILE: &str = ".gleam-tools.lock";
+
+/// The line of `.envrc` putting the tools of the project on `PATH`
+pub const ENVRC_LINE: &str = "PATH_add .gleam_pkgs/apps";
+
+const TEMPLATE: &str = "\
+# The tools this project is developed with, installed into .gleam_pkgs/ by
+# `gleam-pkg project install`. Versions may be prefixes such as \"1\" or \"1.2\".
+[tools]
+# wonderful_cli = \"1.2.0\"
+# hello_node = { version = \"0.3\", target = \"node\" }
+";
+
+/// The contents of a `.gleam-tools.toml`
+#[derive(Debug, Default, Deserialize)]
+pub struct ToolsManifest {
+    /// Every tool by package name, `[repo:][organization/]package`
+    #[serde(default)]
+    pub tools: BTreeMap<String, Tool>,
+}
+
+/// A tool in a [`ToolsManifest`], its version or a table with its version and target
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Tool {
+    Version(String),
+    Table {
+        version: String,
+        #[serde(default)]
+        target: Option<Target>,
+    },
+}
+
+impl ToolsManifest {
+    /// Reads the manifest of the project at `project`
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` if the file cannot be read, or
+    /// `GleamPkgError::ToolsManifestError` if it cannot be parsed
+    pub fn load(project: &Path) -> Result<Self, GleamPkgError> {
+        Self::read(&project.join(MANIFEST))
+    }
+
+    /// Reads a list of tools in the format of a manifest from `path`, e.g. the `--manifest` of
+    /// `gleam-pkg bootstrap`
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` if the file cannot be read, or
+    /// `GleamPkgError::ToolsManifestError` if it cannot be parsed
+    pub fn read(path: &Path) -> Result<Self, GleamPkgError> {
+        let content = fs::read_to_string(path).map_err(|source| GleamPkgError::Io {
+            action: "read project tools",
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&content).map_err(|source| GleamPkgError::ToolsManifestError {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+impl Tool {
+    /// The requested version, possibly a prefix
+    pub fn version(&self) -> &str {
+        match self {
+            Tool::Version(version) | Tool::Table { version, .. } => version,
+        }
+    }
+
+    /// The requested `--target`, `None` to detect it from the package
+    pub fn target(&self) -> Option<Target> {
+        match self {
+            Tool::Version(_) => None,
+            Tool::Table { target, .. } => *target,
+        }
+    }
+
+    /// Whether `version`, built for `target`, is what the manifest asks for
+    pub fn accepts(&self, version: &str, target: Target) -> bool {
+        let requested = self.version();
+        (version == requested || version.starts_with(&format!("{}.", requested)))
+            && self.target().is_none_or(|t| t == target)
+    }
+}
+
+/// The contents of a `.gleam-tools.lock`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    /// Every installed tool by its name in the manifest
+    #[serde(default)]
+    pub tools: BTreeMap<String, LockedTool>,
+}
+
+/// A tool in a [`Lockfile`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedTool {
+    pub version: String,
+    pub target: Target,
+    /// The SHA-256 checksum of the release tarball
+    pub checksum: String,
+    /// The versions of the tools that built it, e.g. `gleam` and the runtime
+    #[serde(default)]
+    pub toolchain: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    /// Reads the lockfile of the project at `project`, `None` if it has none
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` if the file cannot be read, or
+    /// `GleamPkgError::ToolsManifestError` if it cannot be parsed
+    pub fn load(project: &Path) -> Result<Option<Self>, GleamPkgError> {
+        let path = project.join(LOCKFILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => {
+                return Err(GleamPkgError::Io {
+                    action: "read project lockfile",
+                    path,
+                    source,
+                });
+            }
+        };
+        toml::from_str(&content)
+            .map(Some)
+            .map_err(|source| GleamPkgError::ToolsManifestError { path, source })
+    }
+
+    /// Writes the lockfile of the project at `project`
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` if the file cannot be written
+    pub fn save(&self, project: &Path) -> Result<(), GleamPkgError> {
+        let path = project.join(LOCKFILE);
+        let content = toml::to_string(self).map_err(std::io::Error::other);
+        content
+            .and_then(|content| {
+                fs::write(
+                    &path,
+                    format!(
+                        "# Written by `gleam-pkg project install`, commit it with {}\n\n{}",
+                        MANIFEST, content
+                    ),
+                )
+            })
+            .map_err(|source| GleamPkgError::Io {
+                action: "write project lockfile",
+                path,
+                source,
+            })
+    }
+
+    /// The locked version of `name`, if `tool` still accepts it
+    pub fn get(&self, name: &str, tool: &Tool) -> Option<&LockedTool> {
+        self.tools
+            .get(name)
+            .filter(|locked| tool.accepts(&locked.version, locked.target))
+    }
+
+    /// The tools whose entries differ between `manifest` and the lockfile: those the lockfile
+    /// has no acceptable version of, and those the manifest no longer lists
+    pub fn outdated(&self, manifest: &ToolsManifest) -> Vec<String> {
+        let unlocked = manifest
+            .tools
+            .iter()
+            .filter(|(name, tool)| self.get(name, tool).is_none())
+            .map(|(name, _)| name);
+        let removed = self
+            .tools
+            .keys()
+            .filter(|name| !manifest.tools.contains_key(*name));
+        unlocked.chain(removed).cloned().collect()
+    }
+}
+
+/// The outcome of verifying a tool with `gleam-pkg ci-verify`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// It downloads with the locked checksum
+    Ok,
+    /// The lockfile has no version of it the manifest accepts
+    Unlocked,
+    /// The lockfile has it, but the manifest no longer lists it
+    Stale,
+    /// Its registry does not know the version
+    Unresolved,
+    /// Its tarball cannot be downloaded
+    DownloadFailed,
+    /// Its tarball is not the published one, or not the locked one
+    ChecksumMismatch,
+}
+
+impl CheckStatus {
+    /// The name of the status in the output, stable for scripts
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Unlocked => "unlocked",
+            CheckStatus::Stale => "stale",
+            CheckStatus::Unresolved => "unresolved",
+            CheckStatus::DownloadFailed => "download-failed",
+            CheckStatus::ChecksumMismatch => "checksum-mismatch",
+        }
+    }
+}
+
+/// The verification of a tool of the manifest or lockfile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolCheck {
+    pub tool: String,
+    /// The version checked, if it was resolved
+    pub version: Option<String>,
+    pub status: CheckStatus,
+    /// The checksum of the downloaded tarball
+    pub checksum: Option<String>,
+    /// What went wrong
+    pub detail: String,
+}
+
+impl ToolCheck {
+    /// The check of a tool whose tarball was downloaded with `checksum`, against the version
+    /// the lockfile locks if any
+    pub fn downloaded(
+        tool: &str,
+        version: &str,
+        checksum: &str,
+        locked: Option<&LockedTool>,
+    ) -> Self {
+        let (status, detail) = match locked {
+            None => (CheckStatus::Unlocked, format!("not locked in {}", LOCKFILE)),
+            Some(locked) if !locked.checksum.eq_ignore_ascii_case(checksum) => (
+                CheckStatus::ChecksumMismatch,
+                format!("{} locks {}", LOCKFILE, locked.checksum),
+            ),
+            Some(_) => (CheckStatus::Ok, String::new()),
+        };
+        ToolCheck {
+            tool: tool.to_string(),
+            version: Some(version.to_string()),
+            status,
+            checksum: Some(checksum.to_string()),
+            detail,
+        }
+    }
+}
+
+/// Prints the checks of `gleam-pkg ci-verify`, a record per tool with `--porcelain`:
+/// `tool version status checksum detail`
+pub fn print_checks(checks: &[ToolCheck]) {
+    let mut table = Table::new(&["TOOL", "VERSION", "STATUS", "CHECKSUM", "DETAIL"]);
+    for check in checks {
+        let style = match check.status {
+            CheckStatus::Ok => Style::Green,
+            _ => Style::Red,
+        };
+        table.styled_row(vec![
+            (check.tool.clone(), None),
+            (check.version.clone().unwrap_or_default(), None),
+            (check.status.as_str().to_string(), Some(style)),
+            (check.checksum.clone().unwrap_or_default(), Some(Style::Dim)),
+            (check.detail.clone(), None),
+        ]);
+    }
+    table.print();
+}
+
+/// The project `dir` belongs to: the nearest of it and its parents with a `.gleam-tools.toml`
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::NoProject` if none of them has one
+pub fn find(dir: &Path) -> Result<PathBuf, GleamPkgError> {
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(MANIFEST).is_file())
+        .map(Path::to_path_buf)
+        .ok_or_else(|| GleamPkgError::NoProject {
+            dir: dir.to_path_buf(),
+        })
+}
+
+/// The installation root of the tools of the project at `project`
+pub fn paths(project: &Path) -> Paths {
+    Paths::new(&project.join(paths::ROOT_DIR))
+}
+
+/// Creates an empty `.gleam-tools.toml` in `project`
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the file cannot be written
+///
+/// # Returns
+///
+/// Whether the file was created, `false` if it already existed
+pub fn init(project: &Path) -> Result<bool, GleamPkgError> {
+    let path = project.join(MANIFEST);
+    if path.exists() {
+        return Ok(false);
+    }
+    fs::write(&path, TEMPLATE).map_err(|source| GleamPkgError::Io {
+        action: "write project tools",
+        path,
+        source,
+    })?;
+    Ok(true)
+}
+
+/// Adds [`ENVRC_LINE`] to the `.envrc` of `project`, creating it if needed
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if `.envrc` cannot be read or written
+///
+/// # Returns
+///
+/// Whether `.envrc` changed, `false` if it already had the line
+pub fn ensure_envrc(project: &Path) -> Result<bool, GleamPkgError> {
+    let path = project.join(".envrc");
+    let mut content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(source) => {
+            return Err(GleamPkgError::Io {
+                action: "read .envrc",
+                path,
+                source,
+            });
+        }
+    };
+    if content.lines().any(|line| line.trim() == ENVRC_LINE) {
+        return Ok(false);
+    }
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(ENVRC_LINE);
+    content.push('\n');
+    fs::write(&path, content).map_err(|source| GleamPkgError::Io {
+        action: "write .envrc",
+        path,
+        source,
+    })?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_nearest_project_and_its_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("src").join("app");
+        fs::create_dir_all(&nested).unwrap();
+        assert!(matches!(
+            find(&nested),
+            Err(GleamPkgError::NoProject { .. })
+        ));
+
+        assert!(init(dir.path()).unwrap());
+        assert!(!init(dir.path()).unwrap());
+        assert_eq!(find(&nested).unwrap(), dir.path());
+        assert!(ToolsManifest::load(dir.path()).unwrap().tools.is_empty());
+
+        fs::write(
+            dir.path().join(MANIFEST),
+            "[tools]\nwonderful_cli = \"1.2\"\n\
+             \"hex:argv\" = { version = \"1.0.2\", target = \"node\" }\n",
+        )
+        .unwrap();
+        let manifest = ToolsManifest::load(dir.path()).unwrap();
+        let argv = &manifest.tools["hex:argv"];
+        assert_eq!(
+            (argv.version(), argv.target()),
+            ("1.0.2", Some(Target::Node))
+        );
+        let cli = &manifest.tools["wonderful_cli"];
+        assert!(cli.accepts("1.2.3", Target::Erlang));
+        assert!(!cli.accepts("1.20.0", Target::Erlang));
+        assert!(!argv.accepts("1.0.2", Target::Deno));
+        fs::write(dir.path().join(MANIFEST), "[tools]\nwonderful_cli = 1\n").unwrap();
+        assert!(matches!(
+            ToolsManifest::load(dir.path()),
+            Err(GleamPkgError::ToolsManifestError { .. })
+        ));
+    }
+
+    #[test]
+    fn tells_which_tools_the_lockfile_does_not_match() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(Lockfile::load(dir.path()).unwrap().is_none());
+        let locked = |version: &str| LockedTool {
+            version: version.to_string(),
+            target: Target::Node,
+            checksum: "abc".to_string(),
+            toolchain: BTreeMap::from([("gleam".to_string(), "1.6.0".to_string())]),
+        };
+        let lockfile = Lockfile {
+            tools: BTreeMap::from([
+                ("hello".to_string(), locked("1.0.0")),
+                ("gone".to_string(), locked("0.1.0")),
+                ("bumped".to_string(), locked("1.9.0")),
+            ]),
+        };
+        lockfile.save(dir.path()).unwrap();
+        let lockfile = Lockfile::load(dir.path()).unwrap().unwrap();
+        assert_eq!(lockfile.tools["hello"], locked("1.0.0"));
+
+        let manifest: ToolsManifest =
+            toml::from_str("[tools]\nhello = \"1\"\nbumped = \"2\"\nadded = \"1\"\n").unwrap();
+        assert_eq!(lockfile.outdated(&manifest), ["added", "bumped", "gone"]);
+        assert!(lockfile.get("hello", &manifest.tools["hello"]).is_some());
+    }
+
+    #[test]
+    fn checks_downloads_against_the_lockfile() {
+        let locked = LockedTool {
+            version: "1.0.0".to_string(),
+            target: Target::Node,
+            checksum: "ABC".to_string(),
+            toolchain: BTreeMap::new(),
+        };
+        let status = |checksum, locked| ToolCheck::downloaded("hello", "1.0.0", checksum, locked);
+        assert_eq!(status("abc", Some(&locked)).status, CheckStatus::Ok);
+        assert_eq!(
+            status("def", Some(&locked)).status,
+            CheckStatus::ChecksumMismatch
+        );
+        assert_eq!(status("abc", None).status, CheckStatus::Unlocked);
+        assert_eq!(CheckStatus::DownloadFailed.as_str(), "download-failed");
+    }
+
+    #[test]
+    fn adds_the_path_line_to_envrc_once() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(ensure_envrc(dir.path()).unwrap());
+        assert!(!ensure_envrc(dir.path()).unwrap());
+        assert_eq!(
+            fs::read_to_string(dir.path().join(".envrc")).unwrap(),
+            "PATH_add .gleam_pkgs/apps\n"
+        );
+
+        fs::write(dir.path().join(".envrc"), "use nix").unwrap();
+        assert!(ensure_envrc(dir.path()).unwrap());
+        assert_eq!(
+            fs::read_to_string(dir.path().join(".envrc")).unwrap(),
+            "use nix\nPATH_add .gleam_pkgs/apps\n"
+        );
+    }
+}
@@ -0,0 +1,373 @@
+//! Asking the user questions
+//!
+//! Confirmations, selections and multi-selections are written to stderr, so they do not mix
+//! with results on stdout, and answered on stdin one line at a time. Every question has a
+//! default answer, taken when the answer is empty and whenever nobody can be asked: stdin is not
+//! a terminal, or `--yes` was passed, which also answers yes to every confirmation.
+
+use crate::error::GleamPkgError;
+use crate::i18n::{self, Message};
+use crate::output::{self, Style};
+use std::io::{BufRead, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Decides once whether questions are answered without asking
+///
+/// # Arguments
+///
+/// * `yes` - Whether `--yes` was passed
+pub fn init(yes: bool) {
+    ASSUME_YES.store(yes, Ordering::Relaxed);
+}
+
+/// Whether `--yes` answers every question
+pub fn assume_yes() -> bool {
+    ASSUME_YES.load(Ordering::Relaxed)
+}
+
+/// Whether questions are asked, rather than answered with their default
+pub fn interactive() -> bool {
+    // tests run from a terminal must not wait for answers
+    !assume_yes() && !cfg!(test) && std::io::stdin().is_terminal()
+}
+
+/// Asks a yes or no question
+///
+/// # Arguments
+///
+/// * `question` - The question, possibly spanning several lines
+/// * `default` - The answer to an empty line or when nobody can be asked, `--yes` answers yes
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the terminal cannot be read or written
+pub fn confirm(question: &str, default: bool) -> Result<bool, GleamPkgError> {
+    if assume_yes() {
+        return Ok(true);
+    }
+    if !interactive() {
+        return Ok(default);
+    }
+    Ok(ask_confirm(
+        &mut std::io::stdin().lock(),
+        &mut std::io::stderr(),
+        question,
+        default,
+    )?)
+}
+
+/// Asks to pick one of `items`
+///
+/// # Arguments
+///
+/// * `question` - The question, shown above the numbered items
+/// * `items` - What to pick from
+/// * `default` - The index picked on an empty line or when nobody can be asked
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the terminal cannot be read or written
+///
+/// # Returns
+///
+/// The index of the picked item
+pub fn select(question: &str, items: &[String], default: usize) -> Result<usize, GleamPkgError> {
+    if !interactive() {
+        return Ok(default);
+    }
+    Ok(ask_select(
+        &mut std::io::stdin().lock(),
+        &mut std::io::stderr(),
+        question,
+        items,
+        default,
+    )?)
+}
+
+/// Asks to pick any number of `items`
+///
+/// # Arguments
+///
+/// * `question` - The question, shown above the numbered items
+/// * `items` - What to pick from
+/// * `defaults` - Which items are picked on an empty line or when nobody can be asked
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the terminal cannot be read or written
+///
+/// # Returns
+///
+/// The indices of the picked items, in ascending order
+pub fn multi_select(
+    question: &str,
+    items: &[String],
+    defaults: &[bool],
+) -> Result<Vec<usize>, GleamPkgError> {
+    if !interactive() {
+        return Ok(picked(defaults));
+    }
+    Ok(ask_multi_select(
+        &mut std::io::stdin().lock(),
+        &mut std::io::stderr(),
+        question,
+        items,
+        defaults,
+    )?)
+}
+
+/// Asks for a line of text, e.g. a URL
+///
+/// # Arguments
+///
+/// * `question` - The question
+/// * `default` - The answer to an empty line or when nobody can be asked
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the terminal cannot be read or written
+pub fn input(question: &str, default: &str) -> Result<String, GleamPkgError> {
+    if !interactive() {
+        return Ok(default.to_string());
+    }
+    Ok(ask_input(
+        &mut std::io::stdin().lock(),
+        &mut std::io::stderr(),
+        question,
+        default,
+    )?)
+}
+
+fn picked(flags: &[bool]) -> Vec<usize> {
+    (0..flags.len()).filter(|&i| flags[i]).collect()
+}
+
+/// Reads an answer, `None` at the end of the input
+fn read_answer(input: &mut impl BufRead) -> std::io::Result<Option<String>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_lowercase()))
+}
+
+fn ask_confirm(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: &str,
+    default: bool,
+) -> std::io::Result<bool> {
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+    loop {
+        write!(output, "{} {} ", question, output::paint(hint, Style::Dim))?;
+        output.flush()?;
+        match read_answer(input)?.as_deref() {
+            None | Some("") => return Ok(default),
+            Some("y" | "yes") => return Ok(true),
+            Some("n" | "no") => return Ok(false),
+            Some(_) => writeln!(output, "{}", i18n::text(Message::AnswerYesOrNo))?,
+        }
+    }
+}
+
+fn ask_input(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: &str,
+    default: &str,
+) -> std::io::Result<String> {
+    match default {
+        "" => write!(output, "{} ", question)?,
+        _ => write!(
+            output,
+            "{} {} ",
+            question,
+            output::paint(format!("[{}]", default), Style::Dim)
+        )?,
+    }
+    output.flush()?;
+    // read as typed, answers like URLs are case-sensitive
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    Ok(match line.trim() {
+        "" => default.to_string(),
+        answer => answer.to_string(),
+    })
+}
+
+fn write_items(
+    output: &mut impl Write,
+    question: &str,
+    items: &[String],
+    marked: impl Fn(usize) -> bool,
+) -> std::io::Result<()> {
+    writeln!(output, "{}", question)?;
+    for (i, item) in items.iter().enumerate() {
+        let marker = if marked(i) { "*" } else { " " };
+        writeln!(output, "{} {:>3}) {}", marker, i + 1, item)?;
+    }
+    Ok(())
+}
+
+/// Parses a 1-based item number
+fn item_number(answer: &str, items: usize) -> Option<usize> {
+    answer
+        .parse::<usize>()
+        .ok()
+        .filter(|n| (1..=items).contains(n))
+        .map(|n| n - 1)
+}
+
+fn ask_select(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: &str,
+    items: &[String],
+    default: usize,
+) -> std::io::Result<usize> {
+    write_items(output, question, items, |i| i == default)?;
+    loop {
+        write!(
+            output,
+            "{} {} ",
+            i18n::text(Message::Number),
+            output::paint("[*]", Style::Dim)
+        )?;
+        output.flush()?;
+        let Some(answer) = read_answer(input)? else {
+            return Ok(default);
+        };
+        if answer.is_empty() {
+            return Ok(default);
+        }
+        match item_number(&answer, items.len()) {
+            Some(index) => return Ok(index),
+            None => writeln!(
+                output,
+                "{}",
+                i18n::fill(Message::AnswerNumber, &[("count", &items.len())])
+            )?,
+        }
+    }
+}
+
+fn ask_multi_select(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: &str,
+    items: &[String],
+    defaults: &[bool],
+) -> std::io::Result<Vec<usize>> {
+    write_items(output, question, items, |i| defaults[i])?;
+    loop {
+        write!(
+            output,
+            "{} {} ",
+            i18n::text(Message::Numbers),
+            output::paint("[*]", Style::Dim)
+        )?;
+        output.flush()?;
+        let Some(answer) = read_answer(input)? else {
+            return Ok(picked(defaults));
+        };
+        match answer.as_str() {
+            "" => return Ok(picked(defaults)),
+            "all" => return Ok((0..items.len()).collect()),
+            "none" => return Ok(Vec::new()),
+            _ => {}
+        }
+        let numbers: Option<Vec<_>> = answer
+            .split([',', ' '])
+            .filter(|n| !n.is_empty())
+            .map(|n| item_number(n, items.len()))
+            .collect();
+        match numbers {
+            Some(mut numbers) => {
+                numbers.sort_unstable();
+                numbers.dedup();
+                return Ok(numbers);
+            }
+            None => writeln!(
+                output,
+                "{}",
+                i18n::fill(Message::AnswerNumbers, &[("count", &items.len())])
+            )?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items() -> Vec<String> {
+        ["1.0.0", "1.1.0", "2.0.0-rc1"]
+            .iter()
+            .map(|i| i.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn answers_fall_back_to_the_default() {
+        let mut out = Vec::new();
+        let confirm = |answers: &str, out: &mut Vec<u8>, default| {
+            ask_confirm(&mut answers.as_bytes(), out, "Go?", default).unwrap()
+        };
+        assert!(confirm("y\n", &mut out, false));
+        assert!(!confirm("No\n", &mut out, true));
+        assert!(confirm("\n", &mut out, true));
+        assert!(!confirm("", &mut out, false));
+        assert!(confirm("maybe\nyes\n", &mut out, false));
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains("Please answer y or n")
+        );
+    }
+
+    #[test]
+    fn selections_take_item_numbers() {
+        let mut out = Vec::new();
+        let select = |answers: &str, out: &mut Vec<u8>| {
+            ask_select(&mut answers.as_bytes(), out, "Which?", &items(), 1).unwrap()
+        };
+        assert_eq!(select("3\n", &mut out), 2);
+        assert_eq!(select("\n", &mut out), 1);
+        assert_eq!(select("0\n4\n1\n", &mut out), 0);
+        assert!(String::from_utf8(out).unwrap().contains("*   2) 1.1.0"));
+
+        let mut out = Vec::new();
+        let mut multi = |answers: &str| {
+            let defaults = [true, false, true];
+            ask_multi_select(
+                &mut answers.as_bytes(),
+                &mut out,
+                "Which?",
+                &items(),
+                &defaults,
+            )
+            .unwrap()
+        };
+        assert_eq!(multi("\n"), [0, 2]);
+        assert_eq!(multi("2, 1 2\n"), [0, 1]);
+        assert_eq!(multi("all\n"), [0, 1, 2]);
+        assert_eq!(multi("none\n"), [] as [usize; 0]);
+        assert_eq!(multi("7\n3\n"), [2]);
+    }
+
+    #[test]
+    fn inputs_are_read_as_typed() {
+        let mut out = Vec::new();
+        let mut input = |answers: &str| {
+            ask_input(&mut answers.as_bytes(), &mut out, "URL?", "https://a/").unwrap()
+        };
+        assert_eq!(
+            input("  https://Mirror.example/Hex/ \n"),
+            "https://Mirror.example/Hex/"
+        );
+        assert_eq!(input("\n"), "https://a/");
+        assert_eq!(input(""), "https://a/");
+    }
+}
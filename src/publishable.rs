@@ -0,0 +1,501 @@
+//! Checks of a Gleam project about to be published, for the authors of command-line tools
+//!
+//! `gleam-pkg check-publishable [DIR]` reads the project in `DIR` the way an install reads the
+//! package once hex serves it, without building or publishing anything:
+//!
+//! - `gleam.toml` parses and names the package with a name hex and gleam-pkg accept
+//! - the target is one gleam-pkg builds, and which backend it picks for it
+//! - the main module, `src/<name>.gleam`, has the `pub fn main()` the install calls
+//! - the tarball `gleam publish` would make, see [`layout`], holds every module the published
+//!   modules import from the project, and is within the size hex accepts
+//! - escripts cannot read files from `priv/`, and installs under a license policy refuse
+//!   packages without licences, which are warned about
+//!
+//! Problems fail the command, warnings do not.
+
+use crate::backend::{BUILD_PROJECT, Target};
+use crate::error::GleamPkgError;
+use crate::output::{self, Style, Table};
+use crate::paths;
+use crate::plan::disk_usage;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The largest uncompressed tarball hex accepts
+const MAX_UNCOMPRESSED: u64 = 128 * 1024 * 1024;
+
+/// How bad a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// Works, but not everywhere or not as the author may expect
+    Warning,
+    /// Breaks `gleam-pkg install`
+    Problem,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Problem => "problem",
+        }
+    }
+}
+
+/// The outcome of one check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// What was checked, e.g. `main module`
+    pub check: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+/// What checking a project found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+    /// The files the tarball would hold, relative to the project
+    pub files: Vec<PathBuf>,
+    /// Their size, uncompressed
+    pub size: u64,
+    /// The name and version the release would be published as, if both are valid
+    pub release: Option<(String, String)>,
+}
+
+impl Report {
+    fn push(&mut self, check: &'static str, status: Status, detail: impl Into<String>) {
+        self.findings.push(Finding {
+            check,
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    /// How many findings are problems
+    pub fn problems(&self) -> usize {
+        self.findings
+            .iter()
+            .filter(|f| f.status == Status::Problem)
+            .count()
+    }
+}
+
+/// Checks the project in `dir`, see the [module documentation](self)
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the files of the project cannot be listed
+pub fn check(dir: &Path) -> Result<Report, GleamPkgError> {
+    let mut report = Report::default();
+    let manifest_path = dir.join("gleam.toml");
+    let manifest = match fs::read_to_string(&manifest_path) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            report.push(
+                "gleam.toml",
+                Status::Problem,
+                format!("cannot read {}: {}", manifest_path.display(), e),
+            );
+            return Ok(report);
+        }
+    };
+    let manifest = match manifest.parse::<toml::Table>() {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            report.push("gleam.toml", Status::Problem, e.message().to_string());
+            return Ok(report);
+        }
+    };
+    let field = |key: &str| manifest.get(key).and_then(|v| v.as_str());
+    report.push("gleam.toml", Status::Ok, "");
+
+    let Some(name) = field("name") else {
+        report.push("name", Status::Problem, "gleam.toml names no package");
+        return Ok(report);
+    };
+    let (status, detail) = match name_problem(name) {
+        Some(problem) => (Status::Problem, problem),
+        None => (Status::Ok, name.to_string()),
+    };
+    report.push("name", status, detail);
+
+    match field("version").map(semver::Version::parse) {
+        Some(Ok(version)) => {
+            if status == Status::Ok {
+                report.release = Some((name.to_string(), version.to_string()));
+            }
+            report.push("version", Status::Ok, version.to_string())
+        }
+        Some(Err(e)) => report.push("version", Status::Problem, e.to_string()),
+        None => report.push("version", Status::Problem, "gleam.toml has no version"),
+    }
+
+    let target = match field("target") {
+        None | Some("erlang") => Some(Target::Erlang),
+        Some("javascript") => Some(Target::Node),
+        Some(other) => {
+            report.push(
+                "target",
+                Status::Problem,
+                format!("{} is neither erlang nor javascript", other),
+            );
+            None
+        }
+    };
+    match target {
+        Some(Target::Node) => report.push(
+            "target",
+            Status::Ok,
+            "javascript, installed for Node.js unless --target deno",
+        ),
+        Some(_) => report.push("target", Status::Ok, "erlang, installed as an escript"),
+        None => {}
+    }
+
+    report.push_main_module(dir, name);
+
+    match field("gleam") {
+        Some(requirement) => report.push("gleam", Status::Ok, requirement),
+        None => report.push(
+            "gleam",
+            Status::Warning,
+            "no gleam requirement, installs cannot tell an older compiler cannot build it",
+        ),
+    }
+    let licences = manifest
+        .get("licences")
+        .and_then(|v| v.as_array())
+        .is_some_and(|licences| !licences.is_empty());
+    if !licences {
+        report.push(
+            "licences",
+            Status::Warning,
+            "none, installs under a [licenses] policy refuse the package",
+        );
+    }
+
+    report.files = layout(dir)?;
+    report.size = report.files.iter().map(|f| disk_usage(&dir.join(f))).sum();
+    report.push_imports(dir);
+    let has_priv = report.files.iter().any(|f| f.starts_with("priv"));
+    if has_priv && target == Some(Target::Erlang) {
+        report.push(
+            "priv",
+            Status::Warning,
+            "escripts cannot read files from priv/, unless installed for JavaScript",
+        );
+    }
+    let (status, detail) = match report.size > MAX_UNCOMPRESSED {
+        true => (Status::Problem, "larger than hex accepts"),
+        false => (Status::Ok, ""),
+    };
+    report.push(
+        "tarball",
+        status,
+        format!(
+            "{} files, {} {}",
+            report.files.len(),
+            output::format_size(report.size),
+            detail
+        )
+        .trim_end()
+        .to_string(),
+    );
+    Ok(report)
+}
+
+/// Why hex or gleam-pkg would not take `name` as a package name, `None` if they would
+fn name_problem(name: &str) -> Option<String> {
+    let valid = name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        return Some(format!(
+            "{} is not a hex package name: a lowercase letter, then lowercase letters, digits \
+             and underscores",
+            name
+        ));
+    }
+    if name == BUILD_PROJECT {
+        return Some(format!(
+            "{} is the name of the project installs build in",
+            name
+        ));
+    }
+    paths::versioned_name(name, "0.0.0")
+        .err()
+        .map(|e| e.report())
+}
+
+impl Report {
+    /// Checks that the main module has the `main` an install calls
+    fn push_main_module(&mut self, dir: &Path, name: &str) {
+        let module = PathBuf::from("src").join(format!("{}.gleam", name));
+        let Ok(source) = fs::read_to_string(dir.join(&module)) else {
+            self.push(
+                "main module",
+                Status::Problem,
+                format!(
+                    "{} is missing, installs run its main function",
+                    module.display()
+                ),
+            );
+            return;
+        };
+        let main = source.lines().map(str::trim_start).find_map(|line| {
+            let rest = line.strip_prefix("pub fn main")?;
+            Some(
+                rest.trim_start()
+                    .strip_prefix('(')?
+                    .trim_start()
+                    .starts_with(')'),
+            )
+        });
+        match main {
+            Some(true) => self.push("main module", Status::Ok, module.display().to_string()),
+            Some(false) => self.push(
+                "main module",
+                Status::Problem,
+                format!(
+                    "main in {} takes arguments, installs call main()",
+                    module.display()
+                ),
+            ),
+            None => self.push(
+                "main module",
+                Status::Problem,
+                format!("{} has no pub fn main()", module.display()),
+            ),
+        }
+    }
+
+    /// Checks that the published modules import no module of the project left out of the
+    /// tarball, those in `dev/` and `test/`
+    fn push_imports(&mut self, dir: &Path) {
+        let mut missing = Vec::new();
+        for file in self
+            .files
+            .iter()
+            .filter(|f| f.extension().is_some_and(|e| e == "gleam"))
+        {
+            let Ok(source) = fs::read_to_string(dir.join(file)) else {
+                continue;
+            };
+            for module in imports(&source) {
+                let path = format!("{}.gleam", module);
+                let published = dir.join("src").join(&path).is_file();
+                let unpublished = ["dev", "test"]
+                    .iter()
+                    .find(|d| dir.join(d).join(&path).is_file());
+                if let (false, Some(unpublished)) = (published, unpublished) {
+                    missing.push(format!(
+                        "{} imports {}, which is only in {}/",
+                        file.display(),
+                        module,
+                        unpublished
+                    ));
+                }
+            }
+        }
+        match missing.is_empty() {
+            true => self.push("imports", Status::Ok, ""),
+            false => self.push("imports", Status::Problem, missing.join("; ")),
+        }
+    }
+}
+
+/// The modules `source` imports, e.g. `gleam/io` for `import gleam/io.{println}`
+fn imports(source: &str) -> Vec<&str> {
+    source
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix("import "))
+        .filter_map(|rest| {
+            rest.split(|c: char| c.is_whitespace() || c == '.')
+                .find(|part| !part.is_empty())
+        })
+        .collect()
+}
+
+/// The files `gleam publish` would put in the tarball of the project in `dir`, relative to it:
+/// `gleam.toml`, the readme, licence and changelog files, and everything in `src/` and `priv/`
+/// but hidden files
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if a directory cannot be read
+pub fn layout(dir: &Path) -> Result<Vec<PathBuf>, GleamPkgError> {
+    let mut files = Vec::new();
+    let entries = fs::read_dir(dir).map_err(|source| GleamPkgError::Io {
+        action: "read project directory",
+        path: dir.to_path_buf(),
+        source,
+    })?;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let upper = name.to_ascii_uppercase();
+        let top_level = name == "gleam.toml"
+            || ["README", "LICENSE", "LICENCE", "CHANGELOG"]
+                .iter()
+                .any(|prefix| upper.starts_with(prefix));
+        if top_level && entry.path().is_file() {
+            files.push(PathBuf::from(name));
+        }
+    }
+    for sub in ["src", "priv"] {
+        walk(dir, Path::new(sub), &mut files)?;
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn walk(root: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> Result<(), GleamPkgError> {
+    let path = root.join(relative);
+    let entries = match fs::read_dir(&path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(source) => {
+            return Err(GleamPkgError::Io {
+                action: "read project directory",
+                path,
+                source,
+            });
+        }
+    };
+    for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let relative = relative.join(entry.file_name());
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => walk(root, &relative, files)?,
+            Ok(_) => files.push(relative),
+            Err(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Prints the findings, a record per check with `--porcelain`: `check status detail`, and with
+/// `files` the files of the tarball after them
+pub fn print(report: &Report, files: bool) {
+    let mut table = Table::new(&["CHECK", "STATUS", "DETAIL"]);
+    for finding in &report.findings {
+        let style = match finding.status {
+            Status::Ok => Style::Green,
+            Status::Warning => Style::Yellow,
+            Status::Problem => Style::Red,
+        };
+        table.styled_row(vec![
+            (finding.check.to_string(), None),
+            (finding.status.as_str().to_string(), Some(style)),
+            (finding.detail.clone(), None),
+        ]);
+    }
+    table.print();
+    if files {
+        if !output::porcelain() {
+            println!();
+        }
+        for file in &report.files {
+            println!("{}", file.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, contents) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn statuses(report: &Report) -> Vec<(&str, Status)> {
+        report
+            .findings
+            .iter()
+            .map(|f| (f.check, f.status))
+            .collect()
+    }
+
+    #[test]
+    fn passes_an_installable_tool() {
+        let dir = project(&[
+            (
+                "gleam.toml",
+                "name = \"wonderful_cli\"\nversion = \"1.0.0\"\ngleam = \">= 1.4.0\"\n\
+                 licences = [\"MIT\"]\n",
+            ),
+            ("README.md", "# wonderful_cli"),
+            (
+                "src/wonderful_cli.gleam",
+                "import wonderful_cli/args\n\npub fn main() {\n}\n",
+            ),
+            ("src/wonderful_cli/args.gleam", "pub fn parse() {}\n"),
+            ("src/.DS_Store", ""),
+            ("test/wonderful_cli_test.gleam", "pub fn main() {}\n"),
+            ("build/dev/erlang/x.beam", ""),
+        ]);
+        let report = check(dir.path()).unwrap();
+        assert_eq!(report.problems(), 0, "{:?}", report);
+        assert!(statuses(&report).iter().all(|(_, s)| *s == Status::Ok));
+        assert_eq!(
+            report.files,
+            [
+                "README.md",
+                "gleam.toml",
+                "src/wonderful_cli/args.gleam",
+                "src/wonderful_cli.gleam"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn finds_what_breaks_an_install() {
+        let dir = project(&[
+            (
+                "gleam.toml",
+                "name = \"wonderful_cli\"\nversion = \"1.0\"\n",
+            ),
+            (
+                "src/wonderful_cli.gleam",
+                "import gleam/io\nimport fixtures.{sample}\n\npub fn main(args) {\n}\n",
+            ),
+            ("dev/fixtures.gleam", "pub const sample = 1\n"),
+            ("priv/logo.txt", "*"),
+        ]);
+        let report = check(dir.path()).unwrap();
+        assert_eq!(
+            statuses(&report),
+            [
+                ("gleam.toml", Status::Ok),
+                ("name", Status::Ok),
+                ("version", Status::Problem),
+                ("target", Status::Ok),
+                ("main module", Status::Problem),
+                ("gleam", Status::Warning),
+                ("licences", Status::Warning),
+                ("imports", Status::Problem),
+                ("priv", Status::Warning),
+                ("tarball", Status::Ok),
+            ]
+        );
+        assert_eq!(
+            report.findings[7].detail,
+            "src/wonderful_cli.gleam imports fixtures, which is only in dev/"
+        );
+        assert!(name_problem("Wonderful-CLI").is_some());
+        assert!(name_problem(BUILD_PROJECT).is_some());
+    }
+}
@@ -0,0 +1,782 @@
+//! Where packages are fetched from
+//!
+//! Packages come from hex.pm unless their identifier says otherwise:
+//!
+//! ```text
+//! mytool                  hex.pm
+//! hexpm:mytool            hex.pm, spelled out
+//! myorg/mytool            a package private to the hex.pm organization `myorg`
+//! internal:mytool         the repository configured under `[repos.internal]`
+//! internal:myorg/mytool   an organization on that repository
+//! ```
+//!
+//! Organization packages live below `repos/<org>/` of both the API and the tarball repository,
+//! and need an API key with access to the organization.
+//!
+//! Tarballs can also be downloaded from mirrors of the tarball repository, see
+//! [`crate::mirrors`]; the API is only ever asked at its configured base.
+
+use crate::cache::CachedMetadata;
+use crate::config::{Config, HttpConfig};
+use crate::error::GleamPkgError;
+use crate::events::{self, Event};
+use crate::http;
+use crate::index::{self, VersionsIndex};
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// The name of the built-in hex.pm repository
+pub const HEXPM: &str = "hexpm";
+
+/// The repository and organization a package is fetched from
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    /// A repository configured under `[repos.<name>]`, `None` for hex.pm
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// The organization the package is private to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>,
+}
+
+impl Source {
+    /// Whether this is a public package on hex.pm
+    pub fn is_hexpm(&self) -> bool {
+        *self == Source::default()
+    }
+
+    /// The repository, followed by the organization if any, e.g. `hexpm` or `internal/myorg`
+    pub fn name(&self) -> String {
+        let repo = self.repo.as_deref().unwrap_or(HEXPM);
+        match &self.organization {
+            Some(organization) => format!("{}/{}", repo, organization),
+            None => repo.to_string(),
+        }
+    }
+
+    /// The identifier of `package` in this source, e.g. `internal:myorg/mytool`
+    pub fn qualify(&self, package: &str) -> String {
+        let mut id = String::new();
+        if let Some(repo) = &self.repo {
+            id.push_str(repo);
+            id.push(':');
+        }
+        if let Some(organization) = &self.organization {
+            id.push_str(organization);
+            id.push('/');
+        }
+        id.push_str(package);
+        id
+    }
+}
+
+/// A package identifier as given on the command line
+#[derive(Debug, Clone)]
+pub struct PackageSpec {
+    pub source: Source,
+    pub name: String,
+    pub version: Option<String>,
+}
+
+impl PackageSpec {
+    /// Parses `[repo:][org/]package[@version]`, or the URL of a package page on hex.pm like
+    /// `https://hex.pm/packages/[org/]package[/version]`
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - The identifier to parse
+    /// * `repo` - The repository given with `--repo`, used when `spec` names none
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::InvalidPackageSpec` if a part of the identifier is empty, `spec`
+    /// is a URL of anything but a package page on hex.pm, or `spec` names a different
+    /// repository than `repo`, or `GleamPkgError::InvalidPackageName` if the package cannot
+    /// be named so, see [`normalize_name`]
+    pub fn parse(spec: &str, repo: Option<&str>) -> Result<Self, GleamPkgError> {
+        let invalid = || GleamPkgError::InvalidPackageSpec {
+            spec: spec.to_string(),
+        };
+        if spec.starts_with("https://") || spec.starts_with("http://") {
+            let page = hexpm_page(spec).ok_or_else(invalid)?;
+            return PackageSpec::parse(&page, repo).map_err(|e| match e {
+                GleamPkgError::InvalidPackageName { .. } => e,
+                _ => invalid(),
+            });
+        }
+        let (rest, version) = match spec.split_once('@') {
+            Some((rest, version)) => (rest, Some(version)),
+            None => (spec, None),
+        };
+        let (spec_repo, rest) = match rest.split_once(':') {
+            Some((repo, rest)) => (Some(repo), rest),
+            None => (None, rest),
+        };
+        let (organization, name) = match rest.split_once('/') {
+            Some((organization, name)) => (Some(organization), name),
+            None => (None, rest),
+        };
+        let repo = match (spec_repo, repo) {
+            (Some(a), Some(b)) if a != b => return Err(invalid()),
+            (a, b) => a.or(b),
+        };
+        let parts = [repo, organization, Some(name), version];
+        if parts.iter().flatten().any(|part| part.is_empty()) {
+            return Err(invalid());
+        }
+        Ok(PackageSpec {
+            source: Source {
+                repo: repo.filter(|r| *r != HEXPM).map(String::from),
+                organization: organization.map(|o| o.to_ascii_lowercase()),
+            },
+            name: normalize_name(name)?,
+            version: version.map(String::from),
+        })
+    }
+}
+
+/// Normalizes a package name given on the command line to the form hex accepts, lowercase, so
+/// that an invalid one is refused before anything is asked of a registry
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::InvalidPackageName` unless the name starts with a letter and holds
+/// only letters, digits and `_`, suggesting the name it may stand for, e.g. `gleam_json` for
+/// `gleam-json`
+pub fn normalize_name(name: &str) -> Result<String, GleamPkgError> {
+    let valid = |name: &str| {
+        name.starts_with(|c: char| c.is_ascii_lowercase())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    };
+    let normalized = name.trim().to_ascii_lowercase();
+    if valid(&normalized) {
+        return Ok(normalized);
+    }
+    let suggestion = normalized.replace(['-', '.', ' '], "_");
+    Err(GleamPkgError::InvalidPackageName {
+        name: name.to_string(),
+        suggestion: Some(suggestion).filter(|s| valid(s)),
+    })
+}
+
+/// The identifier of the package a hex.pm page shows, `hexpm:[org/]package[@version]`, none if
+/// `url` is not the URL of a package page
+fn hexpm_page(url: &str) -> Option<String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let rest = rest.strip_prefix("www.").unwrap_or(rest);
+    let path = rest.strip_prefix("hex.pm/packages/")?;
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let segments: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    // package names start with a letter, versions with a digit
+    let version = |s: &str| s.starts_with(|c: char| c.is_ascii_digit());
+    let (organization, name, version) = match segments[..] {
+        [name] => (None, name, None),
+        [name, v] if version(v) => (None, name, Some(v)),
+        [organization, name] => (Some(organization), name, None),
+        [organization, name, v] if version(v) => (Some(organization), name, Some(v)),
+        _ => return None,
+    };
+    Some(format!(
+        "{}:{}{}{}",
+        HEXPM,
+        organization.map(|o| format!("{}/", o)).unwrap_or_default(),
+        name,
+        version.map(|v| format!("@{}", v)).unwrap_or_default()
+    ))
+}
+
+/// A repository configured under `[repos.<name>]`
+#[derive(Debug, Clone, Deserialize)]
+pub struct RepoConfig {
+    /// The hex HTTP API of the repository
+    pub api_base: String,
+    /// Where the repository serves release tarballs
+    pub repository_base: String,
+    /// Sent as the `authorization` header of every request to the repository
+    pub api_key: Option<String>,
+    /// Mirrors of `repository_base`, tried in order when it fails
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+}
+
+/// A JSON document fetched from a registry API
+#[derive(Debug)]
+pub enum ApiResponse {
+    /// The cached copy the request was conditional on is still current
+    NotModified,
+    /// A new document, with the validators to revalidate it later
+    Document {
+        body: serde_json::Value,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+}
+
+/// Access to the packages of one source
+///
+/// [`HexRegistry`] implements it over the hex HTTP API; keeping the rest of gleam-pkg behind
+/// this trait lets tests serve fixture metadata and tarballs instead.
+pub trait Registry {
+    /// The URL of `path` below the API base
+    fn api_url(&self, path: &str) -> String;
+
+    /// The tarball repository followed by its mirrors, as base URLs ending in `/`
+    fn repositories(&self) -> &[String];
+
+    /// The URL of the tarball of a release in the first repository
+    fn tarball_url(&self, package: &str, version: &str) -> String {
+        tarball_url(&self.repositories()[0], package, version)
+    }
+
+    /// Fetches the JSON document at `path` below the API base, conditional on the validators
+    /// of `cached` if given
+    fn fetch_json(
+        &self,
+        path: &str,
+        cached: Option<&CachedMetadata>,
+    ) -> Result<ApiResponse, GleamPkgError>;
+
+    /// Downloads the tarball of a release from `repository` into `part`, continuing after the
+    /// bytes already in it if the repository supports range requests, and starting over
+    /// otherwise
+    ///
+    /// Whatever arrived is left in `part` when the download fails.
+    fn download_tarball(
+        &self,
+        repository: &str,
+        package: &str,
+        version: &str,
+        part: &Path,
+    ) -> Result<(), GleamPkgError>;
+
+    /// Fetches the versions index of the first repository, decoded into a JSON document,
+    /// conditional on the validators of `cached` if given, see [`crate::index`]
+    fn fetch_versions(&self, cached: Option<&CachedMetadata>)
+    -> Result<ApiResponse, GleamPkgError>;
+
+    /// Fetches the names of the packages in the first repository, see [`crate::names`]
+    fn fetch_names(&self) -> Result<Vec<String>, GleamPkgError>;
+
+    /// Times downloading the package name index of `repository`, a few hundred kilobytes on
+    /// hex.pm
+    fn benchmark(&self, repository: &str) -> Result<Benchmark, GleamPkgError>;
+
+    /// The size of the tarball of a release, if the registry announces it
+    fn tarball_size(&self, package: &str, version: &str) -> Option<u64>;
+}
+
+/// The URL of the tarball of a release in `repository`
+pub fn tarball_url(repository: &str, package: &str, version: &str) -> String {
+    format!("{}tarballs/{}-{}.tar", repository, package, version)
+}
+
+/// The URL of the versions index of `repository`
+pub fn versions_url(repository: &str) -> String {
+    format!("{}versions", repository)
+}
+
+/// The URL of the package names of `repository`
+pub fn names_url(repository: &str) -> String {
+    format!("{}names", repository)
+}
+
+/// How fast a repository answered, see [`Registry::benchmark`]
+#[derive(Debug, Clone, Copy)]
+pub struct Benchmark {
+    /// Until the response headers arrived
+    pub latency: Duration,
+    /// Until the whole body arrived
+    pub total: Duration,
+    pub bytes: u64,
+}
+
+/// Opens the registry serving `source`
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::UnknownRepository` if `source` names a repository that is not
+/// configured
+pub fn open(config: &Config, source: &Source) -> Result<Box<dyn Registry>, GleamPkgError> {
+    Ok(Box::new(HexRegistry::new(config, source)?))
+}
+
+/// A registry speaking the hex HTTP API, like hex.pm
+#[derive(Debug, Clone)]
+pub struct HexRegistry {
+    api_base: String,
+    /// The repository base followed by its mirrors
+    repositories: Vec<String>,
+    api_key: Option<String>,
+    http: HttpConfig,
+}
+
+impl HexRegistry {
+    /// Looks up the endpoints and credentials of `source` in the configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::UnknownRepository` if `source` names a repository that is not
+    /// configured
+    pub fn new(config: &Config, source: &Source) -> Result<Self, GleamPkgError> {
+        let (api_base, repository_base, mirrors, api_key) = match &source.repo {
+            None => (
+                &config.api_base,
+                &config.repository_base,
+                &config.mirrors,
+                &config.api_key,
+            ),
+            Some(name) => {
+                let repo = config
+                    .repos
+                    .get(name)
+                    .ok_or_else(|| GleamPkgError::UnknownRepository { name: name.clone() })?;
+                (
+                    &repo.api_base,
+                    &repo.repository_base,
+                    &repo.mirrors,
+                    &repo.api_key,
+                )
+            }
+        };
+        let mut registry = HexRegistry {
+            api_base: api_base.clone(),
+            repositories: std::iter::once(repository_base)
+                .chain(mirrors)
+                .map(|base| match base.ends_with('/') {
+                    true => base.clone(),
+                    false => format!("{}/", base),
+                })
+                .collect(),
+            api_key: api_key.clone(),
+            http: config.http.clone(),
+        };
+        if let Some(organization) = &source.organization {
+            registry.api_base = format!("{}repos/{}/", registry.api_base, organization);
+            for repository in &mut registry.repositories {
+                *repository = format!("{}repos/{}/", repository, organization);
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Starts a request to `url`, carrying the API key of the repository
+    fn request(&self, method: Method, url: &str) -> Result<RequestBuilder, GleamPkgError> {
+        let request = http::client(&self.http)?.request(method, url);
+        Ok(match &self.api_key {
+            Some(key) => request.header("authorization", key),
+            None => request,
+        })
+    }
+
+    /// Makes `request` conditional on the validators of `cached`
+    fn conditional(request: RequestBuilder, cached: Option<&CachedMetadata>) -> RequestBuilder {
+        let Some(cached) = cached else {
+            return request;
+        };
+        let mut request = request;
+        if let Some(etag) = &cached.etag {
+            request = request.header("if-none-match", etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header("if-modified-since", last_modified);
+        }
+        request
+    }
+
+    /// The validators of `response`, its `ETag` and `Last-Modified` headers
+    fn validators(response: &Response) -> (Option<String>, Option<String>) {
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from)
+        };
+        (header("etag"), header("last-modified"))
+    }
+
+    /// Sends `request`, failing on anything but a success or `304 Not Modified`
+    fn send(&self, request: RequestBuilder, url: &str) -> Result<Response, GleamPkgError> {
+        let response = http::send(request).map_err(|source| GleamPkgError::RequestFailed {
+            url: url.to_string(),
+            source,
+        })?;
+        let status = response.status();
+        if !status.is_success() && status != StatusCode::NOT_MODIFIED {
+            return Err(GleamPkgError::HttpStatus {
+                url: url.to_string(),
+                status: status.as_u16(),
+            });
+        }
+        Ok(response)
+    }
+}
+
+impl Registry for HexRegistry {
+    fn api_url(&self, path: &str) -> String {
+        format!("{}{}", self.api_base, path)
+    }
+
+    fn repositories(&self) -> &[String] {
+        &self.repositories
+    }
+
+    fn fetch_json(
+        &self,
+        path: &str,
+        cached: Option<&CachedMetadata>,
+    ) -> Result<ApiResponse, GleamPkgError> {
+        let url = self.api_url(path);
+        let request = self
+            .request(Method::GET, &url)?
+            .header("accept", "application/json");
+        let response = self.send(Self::conditional(request, cached), &url)?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(ApiResponse::NotModified);
+        }
+
+        let (etag, last_modified) = Self::validators(&response);
+        let body = response
+            .json::<serde_json::Value>()
+            .map_err(|source| GleamPkgError::InvalidResponse { url, source })?;
+        Ok(ApiResponse::Document {
+            body,
+            etag,
+            last_modified,
+        })
+    }
+
+    fn download_tarball(
+        &self,
+        repository: &str,
+        package: &str,
+        version: &str,
+        part: &Path,
+    ) -> Result<(), GleamPkgError> {
+        let url = tarball_url(repository, package, version);
+        let offset = fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+        let mut request = self
+            .request(Method::GET, &url)?
+            .header("accept", "application/x-tar");
+        if offset > 0 {
+            request = request.header("range", format!("bytes={}-", offset));
+        }
+        let response = match self.send(request, &url) {
+            // nothing after the offset: the part already holds the whole tarball
+            Err(GleamPkgError::HttpStatus { status: 416, .. }) if offset > 0 => return Ok(()),
+            result => result?,
+        };
+        // a repository ignoring the range sends the whole tarball again
+        let resumed = response.status() == StatusCode::PARTIAL_CONTENT;
+        let io_error = |source| GleamPkgError::Io {
+            action: "download tarball",
+            path: part.to_path_buf(),
+            source,
+        };
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(part)
+            .map_err(io_error)?;
+        let mut bytes = if resumed { offset } else { 0 };
+        let total = response.content_length().map(|length| bytes + length);
+        let mut response = http::throttled(response);
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = response.read(&mut buffer).map_err(io_error)?;
+            if read == 0 {
+                return Ok(());
+            }
+            file.write_all(&buffer[..read]).map_err(io_error)?;
+            bytes += read as u64;
+            events::emit(Event::DownloadProgress {
+                package: package.to_string(),
+                version: version.to_string(),
+                bytes,
+                total,
+            });
+        }
+    }
+
+    fn fetch_versions(
+        &self,
+        cached: Option<&CachedMetadata>,
+    ) -> Result<ApiResponse, GleamPkgError> {
+        let url = versions_url(&self.repositories[0]);
+        let request = self.request(Method::GET, &url)?;
+        let response = self.send(Self::conditional(request, cached), &url)?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(ApiResponse::NotModified);
+        }
+
+        let (etag, last_modified) = Self::validators(&response);
+        let bytes = response
+            .bytes()
+            .map_err(|source| GleamPkgError::InvalidResponse {
+                url: url.clone(),
+                source,
+            })?;
+        let invalid = |message| GleamPkgError::InvalidIndex {
+            url: url.clone(),
+            message,
+        };
+        let index = VersionsIndex::decode(&bytes).map_err(invalid)?;
+        let body = serde_json::to_value(index).map_err(|e| invalid(e.to_string()))?;
+        Ok(ApiResponse::Document {
+            body,
+            etag,
+            last_modified,
+        })
+    }
+
+    fn fetch_names(&self) -> Result<Vec<String>, GleamPkgError> {
+        let url = names_url(&self.repositories[0]);
+        let response = self.send(self.request(Method::GET, &url)?, &url)?;
+        let bytes = response
+            .bytes()
+            .map_err(|source| GleamPkgError::InvalidResponse {
+                url: url.clone(),
+                source,
+            })?;
+        index::decode_names(&bytes).map_err(|message| GleamPkgError::InvalidIndex { url, message })
+    }
+
+    fn benchmark(&self, repository: &str) -> Result<Benchmark, GleamPkgError> {
+        let url = names_url(repository);
+        let started = Instant::now();
+        let response = self.send(self.request(Method::GET, &url)?, &url)?;
+        let latency = started.elapsed();
+        let body = response
+            .bytes()
+            .map_err(|source| GleamPkgError::InvalidResponse { url, source })?;
+        Ok(Benchmark {
+            latency,
+            total: started.elapsed(),
+            bytes: body.len() as u64,
+        })
+    }
+
+    fn tarball_size(&self, package: &str, version: &str) -> Option<u64> {
+        let url = self.tarball_url(package, version);
+        let response = self
+            .send(self.request(Method::HEAD, &url).ok()?, &url)
+            .ok()?;
+        response
+            .headers()
+            .get("content-length")?
+            .to_str()
+            .ok()?
+            .parse()
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(spec: &str) -> PackageSpec {
+        PackageSpec::parse(spec, None).unwrap()
+    }
+
+    #[test]
+    fn parses_plain_package() {
+        let spec = parse("mytool");
+        assert_eq!(spec.name, "mytool");
+        assert_eq!(spec.version, None);
+        assert!(spec.source.is_hexpm());
+    }
+
+    #[test]
+    fn parses_every_part() {
+        let spec = parse("internal:myorg/mytool@1.2.0");
+        assert_eq!(spec.source.repo.as_deref(), Some("internal"));
+        assert_eq!(spec.source.organization.as_deref(), Some("myorg"));
+        assert_eq!(spec.name, "mytool");
+        assert_eq!(spec.version.as_deref(), Some("1.2.0"));
+    }
+
+    #[test]
+    fn spelled_out_hexpm_is_hexpm() {
+        assert!(parse("hexpm:mytool").source.is_hexpm());
+        let spec = PackageSpec::parse("mytool", Some(HEXPM)).unwrap();
+        assert!(spec.source.is_hexpm());
+    }
+
+    #[test]
+    fn repo_flag_applies_unless_spec_disagrees() {
+        let spec = PackageSpec::parse("mytool", Some("internal")).unwrap();
+        assert_eq!(spec.source.repo.as_deref(), Some("internal"));
+        assert!(PackageSpec::parse("internal:mytool", Some("internal")).is_ok());
+        assert!(PackageSpec::parse("other:mytool", Some("internal")).is_err());
+    }
+
+    #[test]
+    fn parses_hexpm_urls_and_uris() {
+        let parts = |spec: &str| {
+            let spec = parse(spec);
+            assert_eq!(spec.source.repo, None);
+            (spec.source.organization, spec.name, spec.version)
+        };
+        let mytool = |organization: Option<&str>, version: Option<&str>| {
+            (
+                organization.map(String::from),
+                "mytool".to_string(),
+                version.map(String::from),
+            )
+        };
+        assert_eq!(
+            parts("https://hex.pm/packages/mytool/1.2.3"),
+            mytool(None, Some("1.2.3"))
+        );
+        assert_eq!(parts("https://hex.pm/packages/mytool"), mytool(None, None));
+        assert_eq!(
+            parts("http://www.hex.pm/packages/mytool/?tab=readme#usage"),
+            mytool(None, None)
+        );
+        assert_eq!(
+            parts("https://hex.pm/packages/myorg/mytool/1.2.3-rc.1"),
+            mytool(Some("myorg"), Some("1.2.3-rc.1"))
+        );
+        assert_eq!(
+            parts("https://hex.pm/packages/myorg/mytool"),
+            mytool(Some("myorg"), None)
+        );
+        assert_eq!(parts("hexpm:mytool@1.2.3"), mytool(None, Some("1.2.3")));
+
+        for spec in [
+            "https://hex.pm/packages/",
+            "https://hex.pm/packages/mytool/1.2.3/extra/path",
+            "https://hexdocs.pm/mytool/1.2.3",
+            "https://example.com/packages/mytool",
+        ] {
+            assert!(PackageSpec::parse(spec, None).is_err(), "{}", spec);
+        }
+        assert!(PackageSpec::parse("https://hex.pm/packages/mytool", Some("internal")).is_err());
+    }
+
+    #[test]
+    fn normalizes_package_names() {
+        assert_eq!(parse("MyTool").name, "mytool");
+        assert_eq!(parse("hexpm:MyOrg/my_tool2@1.0.0").name, "my_tool2");
+        assert_eq!(
+            parse("MyOrg/mytool").source.organization.as_deref(),
+            Some("myorg")
+        );
+        assert_eq!(parse("https://hex.pm/packages/MyTool").name, "mytool");
+
+        let suggested = |spec: &str| match PackageSpec::parse(spec, None) {
+            Err(GleamPkgError::InvalidPackageName { suggestion, .. }) => suggestion,
+            other => panic!("{} parsed as {:?}", spec, other),
+        };
+        assert_eq!(suggested("gleam-json").as_deref(), Some("gleam_json"));
+        assert_eq!(suggested("Gleam.JSON@1.0.0").as_deref(), Some("gleam_json"));
+        assert_eq!(suggested("1password"), None);
+        assert_eq!(suggested("_private"), None);
+        assert_eq!(suggested("grüße"), None);
+        assert_eq!(suggested("my tool!"), None);
+        assert_eq!(
+            suggested("https://hex.pm/packages/my-tool").as_deref(),
+            Some("my_tool")
+        );
+    }
+
+    #[test]
+    fn rejects_empty_parts() {
+        for spec in ["", "mytool@", ":mytool", "/mytool", "myorg/", "internal:"] {
+            assert!(
+                matches!(
+                    PackageSpec::parse(spec, None),
+                    Err(GleamPkgError::InvalidPackageSpec { .. })
+                ),
+                "{:?} should be rejected",
+                spec
+            );
+        }
+    }
+
+    #[test]
+    fn qualifies_packages() {
+        assert_eq!(Source::default().qualify("mytool"), "mytool");
+        assert_eq!(
+            parse("internal:myorg/mytool").source.qualify("x"),
+            "internal:myorg/x"
+        );
+        assert_eq!(Source::default().name(), "hexpm");
+        assert_eq!(parse("myorg/mytool").source.name(), "hexpm/myorg");
+        assert_eq!(parse("internal:mytool").source.name(), "internal");
+    }
+
+    #[test]
+    fn organization_urls_live_below_repos() {
+        let config = Config::default();
+        let registry = HexRegistry::new(&config, &parse("myorg/mytool").source).unwrap();
+        assert_eq!(
+            registry.api_url("packages/mytool"),
+            "https://hex.pm/api/repos/myorg/packages/mytool"
+        );
+        assert_eq!(
+            registry.tarball_url("mytool", "1.0.0"),
+            "https://repo.hex.pm/repos/myorg/tarballs/mytool-1.0.0.tar"
+        );
+    }
+
+    #[test]
+    fn named_repositories_must_be_configured() {
+        let mut config = Config::default();
+        let source = parse("internal:mytool").source;
+        assert!(matches!(
+            HexRegistry::new(&config, &source),
+            Err(GleamPkgError::UnknownRepository { .. })
+        ));
+
+        config.repos.insert(
+            "internal".to_string(),
+            RepoConfig {
+                api_base: "https://hex.example.com/api/".to_string(),
+                repository_base: "https://repo.example.com".to_string(),
+                api_key: None,
+                mirrors: vec!["https://mirror.example.com/hex/".to_string()],
+            },
+        );
+        let registry = HexRegistry::new(&config, &source).unwrap();
+        assert_eq!(
+            registry.tarball_url("mytool", "1.0.0"),
+            "https://repo.example.com/tarballs/mytool-1.0.0.tar"
+        );
+        assert_eq!(
+            registry.repositories(),
+            [
+                "https://repo.example.com/",
+                "https://mirror.example.com/hex/"
+            ]
+        );
+    }
+
+    #[test]
+    fn mirrors_serve_organizations_too() {
+        let config = Config {
+            mirrors: vec!["https://mirror.example.com/hex".to_string()],
+            ..Config::default()
+        };
+        let registry = HexRegistry::new(&config, &parse("myorg/mytool").source).unwrap();
+        assert_eq!(
+            registry.repositories(),
+            [
+                "https://repo.hex.pm/repos/myorg/",
+                "https://mirror.example.com/hex/repos/myorg/"
+            ]
+        );
+    }
+}
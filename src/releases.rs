@@ -0,0 +1,420 @@
+//! Release listings of hex packages
+//!
+//! Turns the per-release documents of the hex.pm API into rows for `gleam-pkg releases`, and
+//! picks the releases `gleam-pkg install` offers when the latest one is not an obvious choice.
+
+use crate::output::{Style, Table};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// Toolchain requirements hex records in a release's `meta`; only Mix writes one, Gleam and OTP
+/// requirements are in the release's own files, not in the API
+const REQUIREMENT_KEYS: [&str; 1] = ["elixir"];
+
+/// Why a release was retired
+#[derive(Debug, Serialize)]
+pub struct Retirement {
+    pub reason: String,
+    pub message: Option<String>,
+}
+
+/// What is known about a single release
+#[derive(Debug, Serialize)]
+pub struct ReleaseInfo {
+    pub version: String,
+    /// Publication date, `YYYY-MM-DD`
+    pub published: Option<String>,
+    pub downloads: Option<u64>,
+    pub retirement: Option<Retirement>,
+    /// Toolchain version requirements hex records, only ever `elixir => "~> 1.14"`
+    pub requires: BTreeMap<String, String>,
+    pub build_tools: Vec<String>,
+}
+
+impl ReleaseInfo {
+    /// Builds the info of `version` from its `/packages/<package>/releases/<version>` document
+    pub fn new(version: &str, detail: &serde_json::Value) -> Self {
+        let meta = &detail["meta"];
+        ReleaseInfo {
+            version: version.to_string(),
+            published: detail["inserted_at"]
+                .as_str()
+                .map(|at| at.split('T').next().unwrap_or(at).to_string()),
+            downloads: detail["downloads"].as_u64(),
+            retirement: detail["retirement"].as_object().map(|r| Retirement {
+                reason: r["reason"].as_str().unwrap_or("other").to_string(),
+                message: r.get("message").and_then(|m| m.as_str()).map(String::from),
+            }),
+            requires: REQUIREMENT_KEYS
+                .iter()
+                .filter_map(|key| Some((key.to_string(), meta[*key].as_str()?.to_string())))
+                .collect(),
+            build_tools: meta["build_tools"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|tool| tool.as_str().map(String::from))
+                .collect(),
+        }
+    }
+}
+
+/// Sorts releases newest first by semver, with unparsable versions last
+pub fn sort_descending(releases: &mut [ReleaseInfo]) {
+    releases.sort_by(|a, b| {
+        match (
+            semver::Version::parse(&a.version),
+            semver::Version::parse(&b.version),
+        ) {
+            (Ok(a), Ok(b)) => b.cmp(&a),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => b.version.cmp(&a.version),
+        }
+    });
+}
+
+/// A release offered by `install` when the latest release is not an obvious choice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub version: String,
+    /// Publication date, `YYYY-MM-DD`
+    pub published: Option<String>,
+    /// Why the release was retired
+    pub retired: Option<String>,
+    pub prerelease: bool,
+}
+
+impl Candidate {
+    /// Whether the candidate is installed unless another one is picked
+    fn preferred(&self) -> bool {
+        !self.prerelease && self.retired.is_none()
+    }
+}
+
+/// The releases to choose from when installing the latest release of a package
+///
+/// The latest release is the obvious choice unless it is a pre-release or releases of several
+/// major versions are published. Then the candidates are the newest pre-release, if it is newer
+/// than every stable release, and the newest release of each major version, newest first.
+///
+/// # Arguments
+///
+/// * `metadata` - The `/packages/<package>` document, listing its releases newest first
+///
+/// # Returns
+///
+/// The candidates, empty if the latest release is the obvious choice
+pub fn candidates(metadata: &serde_json::Value) -> Vec<Candidate> {
+    let mut versions: Vec<(semver::Version, &serde_json::Value)> = metadata["releases"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|release| Some((release["version"].as_str()?.parse().ok()?, release)))
+        .collect();
+    versions.sort_by(|(a, _), (b, _)| b.cmp(a));
+    let candidate = |(version, release): &(semver::Version, &serde_json::Value)| Candidate {
+        version: version.to_string(),
+        published: release["inserted_at"]
+            .as_str()
+            .map(|at| at.split('T').next().unwrap_or(at).to_string()),
+        retired: metadata["retirements"][version.to_string()]["reason"]
+            .as_str()
+            .map(String::from),
+        prerelease: !version.pre.is_empty(),
+    };
+
+    let mut candidates = Vec::new();
+    if let Some(newest) = versions
+        .first()
+        .filter(|(version, _)| !version.pre.is_empty())
+    {
+        candidates.push(candidate(newest));
+    }
+    let mut major = None;
+    for release in versions
+        .iter()
+        .filter(|(version, _)| version.pre.is_empty())
+    {
+        if major != Some(release.0.major) {
+            major = Some(release.0.major);
+            candidates.push(candidate(release));
+        }
+    }
+    if candidates.len() < 2 {
+        return Vec::new();
+    }
+    candidates
+}
+
+/// The candidate installed unless another one is picked: the newest stable release that is not
+/// retired, or the newest candidate if there is none
+pub fn default_candidate(candidates: &[Candidate]) -> usize {
+    candidates
+        .iter()
+        .position(Candidate::preferred)
+        .unwrap_or(0)
+}
+
+/// Describes each candidate on one line, with aligned columns
+pub fn describe_candidates(candidates: &[Candidate]) -> Vec<String> {
+    let width = candidates
+        .iter()
+        .map(|c| c.version.chars().count())
+        .max()
+        .unwrap_or(0);
+    candidates
+        .iter()
+        .map(|candidate| {
+            let mut line = format!(
+                "{:<width$}  {:<10}",
+                candidate.version,
+                candidate.published.as_deref().unwrap_or("-"),
+            );
+            if candidate.prerelease {
+                line.push_str("  pre-release");
+            }
+            if let Some(reason) = &candidate.retired {
+                line.push_str(&format!("  retired: {}", reason));
+            }
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+/// The versions of the releases in a `/packages/<package>` document, newest first by semver
+///
+/// hex lists releases newest first, but nothing relies on it; versions that are not semver are
+/// left out.
+pub fn versions(metadata: &serde_json::Value) -> Vec<semver::Version> {
+    let mut versions: Vec<semver::Version> = metadata["releases"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|release| release["version"].as_str()?.parse().ok())
+        .collect();
+    versions.sort_by(|a, b| b.cmp(a));
+    versions
+}
+
+/// Whether the release `version` is retired according to a `/packages/<package>` document
+pub fn retired(metadata: &serde_json::Value, version: &semver::Version) -> bool {
+    !metadata["retirements"][version.to_string()].is_null()
+}
+
+/// The stable releases older than `version` that are not retired, newest first, to fall back to
+/// when `version` cannot be installed
+///
+/// # Arguments
+///
+/// * `metadata` - The `/packages/<package>` document
+/// * `version` - The release that cannot be installed
+pub fn older_releases(metadata: &serde_json::Value, version: &str) -> Vec<String> {
+    let Ok(version) = semver::Version::parse(version) else {
+        return Vec::new();
+    };
+    versions(metadata)
+        .into_iter()
+        .filter(|release| release.pre.is_empty() && *release < version)
+        .filter(|release| !retired(metadata, release))
+        .map(|release| release.to_string())
+        .collect()
+}
+
+/// Whether `version` meets a hex version requirement, like the `gleam` one of a release, e.g.
+/// `>= 1.4.0`, `>= 1.4.0 and < 2.0.0` or `~> 1.4`
+///
+/// # Returns
+///
+/// `None` if the requirement cannot be parsed
+pub fn satisfies(requirement: &str, version: &semver::Version) -> Option<bool> {
+    let mut satisfied = false;
+    for alternative in requirement.split(" or ") {
+        let mut all = true;
+        for clause in alternative.split(" and ") {
+            all &= clause_satisfied(clause.trim(), version)?;
+        }
+        satisfied |= all;
+    }
+    Some(satisfied)
+}
+
+/// Whether `version` meets a single clause of a requirement, e.g. `>= 1.4.0`
+fn clause_satisfied(clause: &str, version: &semver::Version) -> Option<bool> {
+    let (operator, required) = clause.split_at(clause.find(|c: char| c.is_ascii_digit())?);
+    if operator.trim() == "~>" {
+        // `~> 1.4` allows 1.4.0 up to 2.0.0, `~> 1.4.2` allows 1.4.2 up to 1.5.0
+        let parts = required.split(['-', '+']).next()?.split('.').count();
+        let (lowest, below) = match parts {
+            2 => {
+                let lowest = semver::Version::parse(&format!("{}.0", required)).ok()?;
+                let below = semver::Version::new(lowest.major + 1, 0, 0);
+                (lowest, below)
+            }
+            3 => {
+                let lowest = semver::Version::parse(required).ok()?;
+                let below = semver::Version::new(lowest.major, lowest.minor + 1, 0);
+                (lowest, below)
+            }
+            _ => return None,
+        };
+        return Some(*version >= lowest && *version < below);
+    }
+    let required = semver::Version::parse(required).ok()?;
+    Some(match operator.trim() {
+        "" | "==" => *version == required,
+        "!=" => *version != required,
+        ">" => *version > required,
+        ">=" => *version >= required,
+        "<" => *version < required,
+        "<=" => *version <= required,
+        _ => return None,
+    })
+}
+
+/// Prints releases as an aligned table
+pub fn print_table(releases: &[ReleaseInfo]) {
+    let mut table = Table::new(&[
+        "VERSION",
+        "PUBLISHED",
+        "DOWNLOADS",
+        "REQUIRES ELIXIR",
+        "RETIRED",
+    ])
+    .align_right(2);
+    for release in releases {
+        let requires = release
+            .requires
+            .values()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ");
+        let retired = release.retirement.as_ref().map(|r| match &r.message {
+            Some(message) => format!("{}: {}", r.reason, message),
+            None => r.reason.clone(),
+        });
+        let version_style = retired.is_some().then_some(Style::Dim);
+        table.styled_row(vec![
+            (release.version.clone(), version_style),
+            (release.published.clone().unwrap_or("-".to_string()), None),
+            (
+                release.downloads.map_or("-".to_string(), |d| d.to_string()),
+                None,
+            ),
+            (
+                if requires.is_empty() {
+                    "-".to_string()
+                } else {
+                    requires
+                },
+                None,
+            ),
+            (retired.unwrap_or_default(), Some(Style::Red)),
+        ]);
+    }
+    table.print();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_release_details() {
+        let detail = json!({
+            "inserted_at": "2024-05-01T12:00:00Z",
+            "downloads": 42,
+            "retirement": {"reason": "security", "message": "use 1.0.1"},
+            "meta": {"elixir": "~> 1.14", "app": "hello", "build_tools": ["mix"]},
+        });
+        let info = ReleaseInfo::new("1.0.0", &detail);
+        assert_eq!(info.published.as_deref(), Some("2024-05-01"));
+        assert_eq!(info.downloads, Some(42));
+        assert_eq!(info.retirement.unwrap().reason, "security");
+        assert_eq!(info.requires["elixir"], "~> 1.14");
+        assert_eq!(info.requires.len(), 1);
+        assert_eq!(info.build_tools, ["mix"]);
+    }
+
+    #[test]
+    fn offers_the_newest_release_of_each_major_version() {
+        let metadata = json!({
+            "releases": [
+                { "version": "2.0.0-rc.1", "inserted_at": "2024-06-01T00:00:00Z" },
+                { "version": "1.4.2", "inserted_at": "2024-05-01T00:00:00Z" },
+                { "version": "1.4.1" },
+                { "version": "0.9.0" },
+            ],
+            "retirements": { "1.4.2": { "reason": "security" } },
+        });
+        let candidates = candidates(&metadata);
+        let versions: Vec<_> = candidates.iter().map(|c| c.version.as_str()).collect();
+        assert_eq!(versions, ["2.0.0-rc.1", "1.4.2", "0.9.0"]);
+        // neither the pre-release nor the retired release is the default
+        assert_eq!(default_candidate(&candidates), 2);
+        assert_eq!(
+            describe_candidates(&candidates),
+            [
+                "2.0.0-rc.1  2024-06-01  pre-release",
+                "1.4.2       2024-05-01  retired: security",
+                "0.9.0       -",
+            ]
+        );
+
+        let obvious = json!({ "releases": [{ "version": "1.1.0" }, { "version": "1.0.0" }] });
+        assert!(super::candidates(&obvious).is_empty());
+    }
+
+    #[test]
+    fn checks_version_requirements() {
+        let gleam = |version: &str| semver::Version::parse(version).unwrap();
+        assert_eq!(satisfies(">= 1.4.0", &gleam("1.4.0")), Some(true));
+        assert_eq!(satisfies(">= 1.4.0", &gleam("1.3.9")), Some(false));
+        assert_eq!(satisfies(">=1.4.0", &gleam("1.5.0")), Some(true));
+        let range = ">= 1.4.0 and < 2.0.0";
+        assert_eq!(satisfies(range, &gleam("1.9.1")), Some(true));
+        assert_eq!(satisfies(range, &gleam("2.0.0")), Some(false));
+        let either = "== 1.2.0 or >= 1.6.0";
+        assert_eq!(satisfies(either, &gleam("1.2.0")), Some(true));
+        assert_eq!(satisfies(either, &gleam("1.4.0")), Some(false));
+        assert_eq!(satisfies("~> 1.4", &gleam("1.9.0")), Some(true));
+        assert_eq!(satisfies("~> 1.4", &gleam("2.0.0")), Some(false));
+        assert_eq!(satisfies("~> 1.4.2", &gleam("1.4.9")), Some(true));
+        assert_eq!(satisfies("~> 1.4.2", &gleam("1.5.0")), Some(false));
+        assert_eq!(satisfies("1.4.0", &gleam("1.4.0")), Some(true));
+        assert_eq!(satisfies("recent", &gleam("1.4.0")), None);
+        assert_eq!(satisfies("=> 1.4.0", &gleam("1.4.0")), None);
+    }
+
+    #[test]
+    fn falls_back_to_older_stable_releases() {
+        let metadata = json!({
+            "releases": [
+                {"version": "3.0.0"},
+                {"version": "3.0.0-rc.1"},
+                {"version": "2.1.0"},
+                {"version": "2.0.0"},
+                {"version": "1.0.0"},
+            ],
+            "retirements": {"2.0.0": {"reason": "security"}},
+        });
+        assert_eq!(older_releases(&metadata, "3.0.0"), ["2.1.0", "1.0.0"]);
+        assert!(older_releases(&metadata, "1.0.0").is_empty());
+    }
+
+    #[test]
+    fn sorts_newest_first_with_unparsable_last() {
+        let mut releases: Vec<_> = ["0.9.0", "nightly", "1.10.0", "1.2.0", "1.10.0-rc.1"]
+            .iter()
+            .map(|version| ReleaseInfo::new(version, &serde_json::Value::Null))
+            .collect();
+        sort_descending(&mut releases);
+        let versions: Vec<_> = releases.iter().map(|r| r.version.as_str()).collect();
+        assert_eq!(
+            versions,
+            ["1.10.0", "1.10.0-rc.1", "1.2.0", "0.9.0", "nightly"]
+        );
+    }
+}
@@ -0,0 +1,367 @@
+//! Running external programs
+//!
+//! Every program gleam-pkg runs to completion goes through a [`Runner`]: the build steps, the
+//! probes of the toolchain, the `Install` script of Erlang/OTP and the hooks. A runner
+//!
+//! - logs the command line and how it ended, e.g. with `GLEAM_PKG_LOG=debug`
+//! - applies the [`BuildLimits`] of the step, if it has any: a wall-clock timeout after which
+//!   its whole process group is killed, rlimits and isolation, see [`crate::limits`]
+//! - leaves stdout and stderr to the terminal, captures them, or tees them: captures them and
+//!   echoes them as they arrive, unless parallel builds turned that off, see [`echo_output`]
+//! - skips steps that [change](Runner::changes) something in a dry run, logging them instead
+//! - reports failures as errors: `GleamPkgError::SpawnFailed` if it cannot be started,
+//!   `GleamPkgError::BuildTimeout` if it runs out of time, and with [`Runner::run_checked`]
+//!   `GleamPkgError::CommandFailed` with its stderr if it fails
+//!
+//! The wrappers run by `gleam-pkg exec`, the pager and the children of `gleam-pkg ui` outlive
+//! a single step and are started directly.
+
+use crate::error::GleamPkgError;
+use crate::limits::BuildLimits;
+use crate::plan;
+use std::ffi::OsStr;
+use std::io::{Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a running child is polled for completion
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Whether teed output is echoed, see [`echo_output`]
+static ECHO: AtomicBool = AtomicBool::new(true);
+
+/// Decides whether teed output is echoed to the terminal; parallel builds turn it off so their
+/// output does not interleave, it still ends up in the build logs
+pub fn echo_output(enabled: bool) {
+    ECHO.store(enabled, Ordering::Relaxed);
+}
+
+/// Where the output of a program goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Streams {
+    /// To the terminal, as set up on the command
+    Inherit,
+    /// Into the [`Output`]
+    Capture,
+    /// Into the [`Output`] and to the terminal
+    Tee,
+}
+
+/// A program to run to completion, see the [module documentation](self)
+pub struct Runner {
+    cmd: Command,
+    /// How the step is named in logs and errors
+    description: String,
+    limits: Option<BuildLimits>,
+    streams: Streams,
+    /// Whether the step is skipped, as one that changes something in a dry run
+    dry_run: bool,
+}
+
+impl Runner {
+    /// A runner of `cmd`, described by its command line, without limits, leaving its output to
+    /// the terminal
+    pub fn new(cmd: Command) -> Self {
+        let description = command_line(&cmd);
+        Runner {
+            cmd,
+            description,
+            limits: None,
+            streams: Streams::Inherit,
+            dry_run: false,
+        }
+    }
+
+    /// Names the step `description` in logs and errors instead of its command line
+    pub fn describe(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Adds an argument
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.cmd.arg(arg);
+        self
+    }
+
+    /// Adds arguments
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.cmd.args(args);
+        self
+    }
+
+    /// Sets an environment variable, unless an isolated step scrubs the environment
+    pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.cmd.env(key, value);
+        self
+    }
+
+    /// Runs the program under `limits`
+    pub fn limits(mut self, limits: &BuildLimits) -> Self {
+        self.limits = Some(limits.clone());
+        self
+    }
+
+    /// Captures stdout and stderr into the [`Output`]
+    pub fn capture(mut self) -> Self {
+        self.streams = Streams::Capture;
+        self
+    }
+
+    /// Captures stdout and stderr into the [`Output`] and echoes them as they arrive, see
+    /// [`echo_output`]
+    pub fn tee(mut self) -> Self {
+        self.streams = Streams::Tee;
+        self
+    }
+
+    /// Marks the step as one that changes something, which a dry run skips
+    pub fn changes(mut self) -> Self {
+        self.dry_run = plan::dry_run();
+        self
+    }
+
+    /// Runs the program to completion
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::SpawnFailed` if the program cannot be started or waited for,
+    /// `GleamPkgError::BuildTimeout` if it outlives the timeout of its limits, or the errors of
+    /// [`BuildLimits::prepare`]
+    ///
+    /// # Returns
+    ///
+    /// How it ended, with the output captured; a success without output for a skipped step
+    pub fn run(mut self) -> Result<Output, GleamPkgError> {
+        if self.dry_run {
+            tracing::info!("would run {}", self.description);
+            return Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            });
+        }
+        let tee = match self.streams {
+            Streams::Inherit => false,
+            Streams::Capture => false,
+            Streams::Tee => ECHO.load(Ordering::Relaxed),
+        };
+        if self.streams != Streams::Inherit {
+            self.cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        // kept alive until the child is done with it
+        let _home = match &self.limits {
+            Some(limits) => limits.prepare(&mut self.cmd)?,
+            None => None,
+        };
+        tracing::debug!("running {}", self.description);
+        let started = Instant::now();
+        let spawn_failed = |source| GleamPkgError::SpawnFailed {
+            command: self.description.clone(),
+            source,
+        };
+        let mut child = self.cmd.spawn().map_err(spawn_failed)?;
+
+        // drain pipes on separate threads so a chatty child cannot block on a full pipe
+        let stdout = child
+            .stdout
+            .take()
+            .map(|out| thread::spawn(move || drain(out, tee.then(std::io::stdout))));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|err| thread::spawn(move || drain(err, tee.then(std::io::stderr))));
+
+        let deadline = self.limits.as_ref().map(|limits| started + limits.timeout);
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                    kill_group(child.id());
+                    let _ = child.wait();
+                    tracing::debug!("killed {} after its timeout", self.description);
+                    return Err(GleamPkgError::BuildTimeout {
+                        command: self.description.clone(),
+                        timeout: self.limits.map(|limits| limits.timeout).unwrap_or_default(),
+                    });
+                }
+                Ok(None) => thread::sleep(POLL_INTERVAL),
+                Err(e) => {
+                    if self.limits.is_some() {
+                        kill_group(child.id());
+                    }
+                    return Err(spawn_failed(e));
+                }
+            }
+        };
+        tracing::debug!(
+            "{} ended with {} after {:?}",
+            self.description,
+            describe_status(&status),
+            started.elapsed()
+        );
+
+        Ok(Output {
+            status,
+            stdout: stdout
+                .map(|h| h.join().unwrap_or_default())
+                .unwrap_or_default(),
+            stderr: stderr
+                .map(|h| h.join().unwrap_or_default())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Runs the program to completion, capturing its output unless it is teed, and fails if
+    /// it does
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::CommandFailed` with the captured stderr if the program does not
+    /// succeed, or the errors of [`Runner::run`]
+    pub fn run_checked(mut self) -> Result<Output, GleamPkgError> {
+        if self.streams == Streams::Inherit {
+            self.streams = Streams::Capture;
+        }
+        let description = self.description.clone();
+        let output = self.run()?;
+        if !output.status.success() {
+            return Err(GleamPkgError::CommandFailed {
+                command: description,
+                status: describe_status(&output.status),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        Ok(output)
+    }
+}
+
+/// The command line of `cmd`, e.g. `gleam build --target erlang`
+fn command_line(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Reads `src` to the end, optionally copying every chunk to `echo` as it arrives
+fn drain(mut src: impl Read, mut echo: Option<impl Write>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 8192];
+    loop {
+        match src.read(&mut chunk) {
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                if let Some(echo) = echo.as_mut() {
+                    let _ = echo.write_all(&chunk[..n]);
+                    let _ = echo.flush();
+                }
+                buf.extend_from_slice(&chunk[..n]);
+            }
+        }
+    }
+    buf
+}
+
+/// Kills the process group led by `pid`
+fn kill_group(pid: u32) {
+    // SAFETY: plain syscall, a stale pid only results in ESRCH
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+/// Describes how a process terminated, mentioning the signal when it was killed by one
+pub fn describe_status(status: &ExitStatus) -> String {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => format!("exit code {}", code),
+        (None, Some(libc::SIGXCPU)) => "CPU time limit exceeded".to_string(),
+        (None, Some(signal)) => format!("killed by signal {}", signal),
+        (None, None) => "unknown status".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Runner {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script);
+        Runner::new(cmd)
+    }
+
+    fn limits(timeout: Duration) -> BuildLimits {
+        BuildLimits {
+            timeout,
+            memory_limit: None,
+            cpu_limit: None,
+            isolation: None,
+        }
+    }
+
+    #[test]
+    fn captures_output_and_reports_failures() {
+        let output = sh("echo out; echo err >&2").capture().run().unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+
+        let error = sh("echo broken >&2; exit 3")
+            .env("UNUSED", "1")
+            .run_checked()
+            .unwrap_err();
+        assert!(
+            matches!(
+                &error,
+                GleamPkgError::CommandFailed { command, status, stderr }
+                    if command == "sh -c echo broken >&2; exit 3"
+                        && status == "exit code 3"
+                        && stderr == "broken"
+            ),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn kills_steps_that_run_out_of_time() {
+        let started = Instant::now();
+        let error = sh("sleep 10")
+            .describe("a slow step")
+            .limits(&limits(Duration::from_millis(200)))
+            .run()
+            .unwrap_err();
+        assert!(
+            matches!(&error, GleamPkgError::BuildTimeout { command, .. } if command == "a slow step"),
+            "{:?}",
+            error
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn dry_runs_skip_steps_that_change_something() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("ran");
+        let mut runner = sh("touch \"$MARKER\"").env("MARKER", &marker).changes();
+        runner.dry_run = true;
+        assert!(runner.run().unwrap().status.success());
+        assert!(!marker.exists());
+        sh("touch \"$MARKER\"")
+            .env("MARKER", &marker)
+            .changes()
+            .run_checked()
+            .unwrap();
+        assert!(marker.exists());
+    }
+}
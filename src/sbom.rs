@@ -0,0 +1,428 @@
+//! Software bills of materials of the installed packages
+//!
+//! `gleam-pkg sbom` describes every installed version, and the packages in its resolved
+//! dependency tree (see [`crate::manifest`]), as a CycloneDX 1.5 or SPDX 2.3 JSON document.
+//! Packages are identified by their package URL and, when the provenance of an install was
+//! recorded, come with the checksum of its tarball, where it was downloaded from and the
+//! toolchain that built it:
+//!
+//! ```text
+//! pkg:hex/mytool@1.2.0                               hex.pm
+//! pkg:hex/myorg/mytool@1.2.0                         the hex.pm organization `myorg`
+//! pkg:hex/mytool@1.2.0?repository_url=<base>         a repository configured under [repos]
+//! ```
+
+use crate::config::Config;
+use crate::db::{Database, InstalledVersion};
+use crate::error::GleamPkgError;
+use crate::manifest::{self, Dependencies};
+use crate::output;
+use crate::paths::Paths;
+use crate::registry::Source;
+use clap::ValueEnum;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// The document format `gleam-pkg sbom` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// CycloneDX 1.5 JSON
+    Cyclonedx,
+    /// SPDX 2.3 JSON
+    Spdx,
+}
+
+/// An installed version as the bill of materials lists it
+#[derive(Debug)]
+pub struct Entry<'a> {
+    pub name: &'a str,
+    pub version: &'a str,
+    pub purl: String,
+    pub installed: &'a InstalledVersion,
+    /// Its resolved dependency tree, `None` if its manifest is gone
+    pub dependencies: Option<Dependencies>,
+}
+
+/// The package URL of `package` at `version`, see <https://github.com/package-url/purl-spec>
+pub fn purl(config: &Config, source: &Source, package: &str, version: &str) -> String {
+    let mut purl = String::from("pkg:hex/");
+    if let Some(organization) = &source.organization {
+        purl.push_str(&organization.to_lowercase());
+        purl.push('/');
+    }
+    purl.push_str(&format!("{}@{}", package.to_lowercase(), version));
+    if let Some(repo) = source.repo.as_ref().and_then(|repo| config.repos.get(repo)) {
+        purl.push_str("?repository_url=");
+        purl.push_str(&repo.repository_base);
+    }
+    purl
+}
+
+/// Every installed version in `db` with its dependency tree
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` or `GleamPkgError::InvalidManifest` if the manifest of a
+/// version that has no recorded dependencies cannot be read
+pub fn inventory<'a>(
+    config: &Config,
+    paths: &Paths,
+    db: &'a Database,
+) -> Result<Vec<Entry<'a>>, GleamPkgError> {
+    let mut entries = Vec::new();
+    for (name, installed) in &db.packages {
+        for (version, installed_version) in &installed.versions {
+            entries.push(Entry {
+                name,
+                version,
+                purl: purl(config, &installed.source, name, version),
+                installed: installed_version,
+                dependencies: installed_version.load_dependencies(paths, name, version)?,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// The bill of materials of `entries` in `format`, created at Unix time `now`
+pub fn document(format: Format, entries: &[Entry], now: u64) -> Value {
+    match format {
+        Format::Cyclonedx => cyclonedx(entries, now),
+        Format::Spdx => spdx(entries, now),
+    }
+}
+
+/// How a dependency is referred to: its package URL if it comes from hex, otherwise its source,
+/// name and version
+fn dependency_ref(package: &manifest::Package) -> String {
+    match package.source.as_str() {
+        "hex" => format!(
+            "pkg:hex/{}@{}",
+            package.name.to_lowercase(),
+            package.version
+        ),
+        source => format!("{}:{}@{}", source, package.name, package.version),
+    }
+}
+
+/// The references of the packages named in `names`, looked up in `dependencies`
+fn refs(dependencies: &Dependencies, names: &[String]) -> Vec<String> {
+    names
+        .iter()
+        .filter_map(|name| dependencies.packages.iter().find(|p| p.name == *name))
+        .map(dependency_ref)
+        .collect()
+}
+
+/// Every dependency of `entries` once, by reference, with the references it depends on
+fn dependency_graph<'a>(
+    entries: &'a [Entry],
+) -> BTreeMap<String, (&'a manifest::Package, Vec<String>)> {
+    let mut graph = BTreeMap::new();
+    for dependencies in entries.iter().filter_map(|e| e.dependencies.as_ref()) {
+        for package in &dependencies.packages {
+            graph
+                .entry(dependency_ref(package))
+                .or_insert_with(|| (package, refs(dependencies, &package.requirements)));
+        }
+    }
+    graph
+}
+
+fn cyclonedx(entries: &[Entry], now: u64) -> Value {
+    let graph = dependency_graph(entries);
+    let mut components: Vec<_> = entries.iter().map(cyclonedx_component).collect();
+    let mut dependencies: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            let depends_on = match &entry.dependencies {
+                Some(dependencies) => refs(dependencies, &dependencies.direct),
+                None => Vec::new(),
+            };
+            json!({ "ref": entry.purl, "dependsOn": depends_on })
+        })
+        .collect();
+    for (reference, (package, depends_on)) in &graph {
+        let mut component = json!({
+            "type": "library",
+            "bom-ref": reference,
+            "name": package.name,
+            "version": package.version,
+        });
+        if package.source == "hex" {
+            component["purl"] = json!(reference);
+        }
+        if let Some(checksum) = &package.outer_checksum {
+            component["hashes"] = json!([{ "alg": "SHA-256", "content": checksum.to_lowercase() }]);
+        }
+        components.push(component);
+        dependencies.push(json!({ "ref": reference, "dependsOn": depends_on }));
+    }
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "serialNumber": format!("urn:uuid:{}", uuid(entries, now)),
+        "version": 1,
+        "metadata": {
+            "timestamp": output::format_rfc3339(now),
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+        },
+        "components": components,
+        "dependencies": dependencies,
+    })
+}
+
+fn cyclonedx_component(entry: &Entry) -> Value {
+    let mut properties = vec![json!({
+        "name": "gleam-pkg:target",
+        "value": entry.installed.target.backend().name(),
+    })];
+    let mut component = json!({
+        "type": "application",
+        "bom-ref": entry.purl,
+        "name": entry.name,
+        "version": entry.version,
+        "purl": entry.purl,
+    });
+    if let Some(provenance) = &entry.installed.provenance {
+        component["hashes"] = json!([{ "alg": "SHA-256", "content": provenance.checksum }]);
+        component["externalReferences"] = json!([{
+            "type": "distribution",
+            "url": provenance.tarball_url,
+        }]);
+        for (tool, tool_version) in &provenance.toolchain {
+            properties.push(json!({
+                "name": format!("gleam-pkg:toolchain:{}", tool),
+                "value": tool_version,
+            }));
+        }
+    }
+    component["properties"] = Value::Array(properties);
+    component
+}
+
+fn spdx(entries: &[Entry], now: u64) -> Value {
+    let graph = dependency_graph(entries);
+    let mut packages = Vec::new();
+    let mut relationships = Vec::new();
+    for entry in entries {
+        let id = spdx_id(&entry.purl);
+        let mut package = spdx_package(&id, entry.name, entry.version, Some(&entry.purl));
+        if let Some(provenance) = &entry.installed.provenance {
+            package["downloadLocation"] = json!(provenance.tarball_url);
+            package["checksums"] =
+                json!([{ "algorithm": "SHA256", "checksumValue": provenance.checksum }]);
+        }
+        packages.push(package);
+        relationships.push(spdx_relationship("SPDXRef-DOCUMENT", "DESCRIBES", &id));
+        if let Some(dependencies) = &entry.dependencies {
+            for reference in refs(dependencies, &dependencies.direct) {
+                relationships.push(spdx_relationship(&id, "DEPENDS_ON", &spdx_id(&reference)));
+            }
+        }
+    }
+    for (reference, (dependency, depends_on)) in &graph {
+        let id = spdx_id(reference);
+        let purl = (dependency.source == "hex").then_some(reference.as_str());
+        let mut package = spdx_package(&id, &dependency.name, &dependency.version, purl);
+        if let Some(checksum) = &dependency.outer_checksum {
+            package["checksums"] =
+                json!([{ "algorithm": "SHA256", "checksumValue": checksum.to_lowercase() }]);
+        }
+        packages.push(package);
+        for other in depends_on {
+            relationships.push(spdx_relationship(&id, "DEPENDS_ON", &spdx_id(other)));
+        }
+    }
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": "gleam-pkg installed packages",
+        "documentNamespace": format!(
+            "https://spdx.org/spdxdocs/gleam-pkg-{}",
+            uuid(entries, now)
+        ),
+        "creationInfo": {
+            "created": output::format_rfc3339(now),
+            "creators": [format!("Tool: {}-{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
+
+fn spdx_package(id: &str, name: &str, version: &str, purl: Option<&str>) -> Value {
+    let mut package = json!({
+        "SPDXID": id,
+        "name": name,
+        "versionInfo": version,
+        "downloadLocation": "NOASSERTION",
+        "filesAnalyzed": false,
+        "licenseConcluded": "NOASSERTION",
+        "licenseDeclared": "NOASSERTION",
+        "copyrightText": "NOASSERTION",
+    });
+    if let Some(purl) = purl {
+        package["externalRefs"] = json!([{
+            "referenceCategory": "PACKAGE-MANAGER",
+            "referenceType": "purl",
+            "referenceLocator": purl,
+        }]);
+    }
+    package
+}
+
+fn spdx_relationship(from: &str, kind: &str, to: &str) -> Value {
+    json!({ "spdxElementId": from, "relationshipType": kind, "relatedSpdxElement": to })
+}
+
+/// An SPDX element id for the package referred to as `reference`, which may only contain
+/// letters, digits, `.` and `-`
+fn spdx_id(reference: &str) -> String {
+    let reference = reference.strip_prefix("pkg:").unwrap_or(reference);
+    let id: String = reference
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                true => c,
+                false => '-',
+            },
+        )
+        .collect();
+    format!("SPDXRef-{}", id)
+}
+
+/// A UUID naming the document, derived from what it lists and when it was created
+fn uuid(entries: &[Entry], now: u64) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(now.to_be_bytes());
+    for entry in entries {
+        hasher.update(entry.purl.as_bytes());
+    }
+    let hex = format!("{:x}", hasher.finalize());
+    // shaped as a version 8 UUID, for custom ones
+    format!(
+        "{}-{}-8{}-a{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[13..16],
+        &hex[17..20],
+        &hex[20..32]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Target;
+    use crate::db::Provenance;
+    use crate::registry::RepoConfig;
+
+    #[test]
+    fn package_urls_name_the_organization_and_repository() {
+        let mut config = Config::default();
+        config.repos.insert(
+            "internal".to_string(),
+            RepoConfig {
+                api_base: "https://hex.example.com/api/".to_string(),
+                repository_base: "https://repo.example.com/".to_string(),
+                api_key: None,
+                mirrors: Vec::new(),
+            },
+        );
+        let source = Source {
+            repo: Some("internal".to_string()),
+            organization: Some("MyOrg".to_string()),
+        };
+        assert_eq!(
+            purl(&config, &Source::default(), "mytool", "1.2.0"),
+            "pkg:hex/mytool@1.2.0"
+        );
+        assert_eq!(
+            purl(&config, &source, "mytool", "1.2.0"),
+            "pkg:hex/myorg/mytool@1.2.0?repository_url=https://repo.example.com/"
+        );
+    }
+
+    fn package(name: &str, requirements: &[&str]) -> manifest::Package {
+        manifest::Package {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            source: "hex".to_string(),
+            requirements: requirements.iter().map(|r| r.to_string()).collect(),
+            outer_checksum: Some("ABC".to_string()),
+        }
+    }
+
+    #[test]
+    fn documents_list_installs_and_their_dependencies() {
+        let installed = InstalledVersion {
+            target: Target::Node,
+            otp_release: None,
+            blob: None,
+            binary: None,
+            erts: None,
+            wrapper: None,
+            provenance: Some(Provenance {
+                tarball_url: "https://repo.hex.pm/tarballs/hello-1.0.0.tar".to_string(),
+                checksum: "abc123".to_string(),
+                verified: true,
+                installed_at: 0,
+                toolchain: [("gleam".to_string(), "1.6.0".to_string())].into(),
+            }),
+            dependencies: None,
+        };
+        let entries = [Entry {
+            name: "hello",
+            version: "1.0.0",
+            purl: "pkg:hex/hello@1.0.0".to_string(),
+            installed: &installed,
+            dependencies: Some(Dependencies {
+                direct: vec!["argv".to_string()],
+                packages: vec![
+                    package("argv", &["gleam_stdlib"]),
+                    package("gleam_stdlib", &[]),
+                ],
+            }),
+        }];
+
+        let bom = document(Format::Cyclonedx, &entries, 0);
+        let hello = &bom["components"][0];
+        assert_eq!(hello["hashes"][0]["content"], "abc123");
+        assert_eq!(hello["properties"][1]["value"], "1.6.0");
+        assert_eq!(bom["components"][1]["purl"], "pkg:hex/argv@1.0.0");
+        assert_eq!(bom["components"][1]["hashes"][0]["content"], "abc");
+        assert_eq!(
+            bom["dependencies"][0]["dependsOn"],
+            json!(["pkg:hex/argv@1.0.0"])
+        );
+        assert_eq!(
+            bom["dependencies"][1]["dependsOn"],
+            json!(["pkg:hex/gleam_stdlib@1.0.0"])
+        );
+
+        let spdx = document(Format::Spdx, &entries, 0);
+        assert_eq!(spdx["packages"].as_array().unwrap().len(), 3);
+        assert_eq!(
+            spdx["packages"][2]["SPDXID"],
+            "SPDXRef-hex-gleam-stdlib-1.0.0"
+        );
+        assert_eq!(
+            spdx["relationships"][1],
+            json!({
+                "spdxElementId": "SPDXRef-hex-hello-1.0.0",
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": "SPDXRef-hex-argv-1.0.0",
+            })
+        );
+        assert_eq!(spdx["creationInfo"]["created"], "1970-01-01T00:00:00Z");
+    }
+}
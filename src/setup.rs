@@ -0,0 +1,263 @@
+//! The first-run setup
+//!
+//! The first time gleam-pkg runs without an installation root, and someone can be asked, it
+//! offers to set the installation up:
+//!
+//! 1. where the root goes: `~/.gleam_pkgs`, or `~/.local/share/gleam-pkg` after the XDG base
+//!    directory specification, see [`crate::paths`]; not asked when `GLEAM_PKG_ROOT` is set
+//! 2. whether the apps directory is added to the startup file of the shell now, offered at the
+//!    first install, or never offered
+//! 3. how results are printed: colored, plain for screen readers, or in the porcelain format
+//!    for scripts
+//! 4. a mirror to download tarballs from when repo.hex.pm fails, see [`crate::mirrors`]
+//!
+//! The answers that differ from the defaults are written to `config.toml` in the new root, so
+//! the file can be edited, or deleted to go back to the defaults, later. `--defaults` skips the
+//! questions, as do `--yes` and a stdin that is not a terminal: the root is then created at
+//! `~/.gleam_pkgs` with no configuration, as if there was no setup.
+
+use crate::error::GleamPkgError;
+use crate::output;
+use crate::paths::Paths;
+use crate::prompt;
+use crate::shell::{self, Shell};
+use std::fs;
+
+/// A known mirror of repo.hex.pm, offered by the setup
+const MIRROR: &str = "https://hexpm.upyun.com/";
+
+/// What is done about the apps directory not being on PATH
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathSetup {
+    /// Added to the startup file of the shell during the setup
+    AddNow,
+    /// Offered at the first install, the default
+    Offer,
+    /// Never offered, `path_prompt = false`
+    Never,
+}
+
+/// How results are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStyle {
+    /// Colored on a terminal, the default
+    Color,
+    /// Plain lines for screen readers, `plain = true`
+    Plain,
+    /// In the porcelain format, `porcelain = true`
+    Porcelain,
+}
+
+/// The answers to the setup
+#[derive(Debug, Clone)]
+pub struct Answers {
+    pub paths: Paths,
+    pub path: PathSetup,
+    pub output: OutputStyle,
+    /// A mirror of repo.hex.pm
+    pub mirror: Option<String>,
+}
+
+impl Answers {
+    /// The configuration the answers make, with only the settings that differ from the
+    /// defaults; empty if none does
+    pub fn config(&self) -> String {
+        let mut settings = Vec::new();
+        match self.output {
+            OutputStyle::Color => {}
+            OutputStyle::Plain => settings.push("plain = true".to_string()),
+            OutputStyle::Porcelain => settings.push("porcelain = true".to_string()),
+        }
+        if self.path == PathSetup::Never {
+            settings.push("path_prompt = false".to_string());
+        }
+        if let Some(mirror) = &self.mirror {
+            settings.push(format!(
+                "mirrors = [{}]",
+                toml::Value::String(mirror.clone())
+            ));
+        }
+        if settings.is_empty() {
+            return String::new();
+        }
+        format!(
+            "# written by the first-run setup, see `gleam-pkg help environment`\n{}\n",
+            settings.join("\n")
+        )
+    }
+}
+
+/// Whether the setup is due: there is no installation at `paths` yet
+pub fn due(paths: &Paths) -> bool {
+    !paths.root().exists()
+}
+
+/// Asks the questions of the setup, see the [module documentation](self)
+///
+/// # Arguments
+///
+/// * `paths` - The installation to set up, unless another root is chosen
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the terminal cannot be read or written
+pub fn ask(paths: Paths) -> Result<Answers, GleamPkgError> {
+    output::notice(
+        "gleam-pkg is not set up yet, a few questions set it up; pass --defaults to skip them",
+    );
+    let paths = match Paths::from_env().is_some() {
+        true => paths,
+        false => ask_root(paths)?,
+    };
+    let apps = paths.apps();
+    let path = match prompt::select(
+        &format!("How should {} get on PATH?", apps.display()),
+        &[
+            "Add it to the startup file of my shell now".to_string(),
+            "Offer it at the first install".to_string(),
+            "I put it on PATH myself, never offer it".to_string(),
+        ],
+        1,
+    )? {
+        0 => PathSetup::AddNow,
+        1 => PathSetup::Offer,
+        _ => PathSetup::Never,
+    };
+    let output = match prompt::select(
+        "How should results be printed?",
+        &[
+            "Colored on a terminal".to_string(),
+            "Plain lines without color or symbols, for screen readers".to_string(),
+            "In the porcelain format for scripts, see `gleam-pkg help scripting`".to_string(),
+        ],
+        0,
+    )? {
+        0 => OutputStyle::Color,
+        1 => OutputStyle::Plain,
+        _ => OutputStyle::Porcelain,
+    };
+    let mirror = match prompt::select(
+        "Download tarballs from a mirror when repo.hex.pm fails?",
+        &[
+            "No".to_string(),
+            MIRROR.to_string(),
+            "Another mirror".to_string(),
+        ],
+        0,
+    )? {
+        0 => None,
+        1 => Some(MIRROR.to_string()),
+        _ => Some(prompt::input("Base URL of the mirror:", "")?).filter(|url| !url.is_empty()),
+    };
+    Ok(Answers {
+        paths,
+        path,
+        output,
+        // mirrors are base URLs, see `crate::registry`
+        mirror: mirror.map(|url| match url.ends_with('/') {
+            true => url,
+            false => format!("{}/", url),
+        }),
+    })
+}
+
+/// Asks where the root goes, `paths` or the XDG root
+fn ask_root(paths: Paths) -> Result<Paths, GleamPkgError> {
+    let Some(xdg) = Paths::xdg() else {
+        return Ok(paths);
+    };
+    let roots = [
+        paths.root().display().to_string(),
+        format!("{} (XDG)", xdg.root().display()),
+    ];
+    match prompt::select("Where should gleam-pkg install packages?", &roots, 0)? {
+        0 => Ok(paths),
+        _ => Ok(xdg),
+    }
+}
+
+/// Creates the installation `answers` describe, with its configuration, and adds the apps
+/// directory to PATH if that was asked for
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the root or its configuration cannot be written
+///
+/// # Returns
+///
+/// The installation
+pub fn apply(answers: &Answers) -> Result<Paths, GleamPkgError> {
+    let paths = &answers.paths;
+    paths.create_dirs()?;
+    let config = answers.config();
+    if !config.is_empty() {
+        let path = paths.config_file();
+        fs::write(&path, config).map_err(|source| GleamPkgError::Io {
+            action: "write configuration",
+            path: path.clone(),
+            source,
+        })?;
+        output::success(format!("Wrote {}", path.display()));
+    }
+    if answers.path == PathSetup::AddNow {
+        let added = dirs::home_dir()
+            .ok_or(GleamPkgError::HomeDirNotFound)
+            .and_then(|home| {
+                let shell = Shell::detect()?;
+                shell::add(&home, shell, &paths.apps())?;
+                Ok(shell)
+            });
+        match added {
+            Ok(shell) => output::success(format!(
+                "Added {} to ~/{}, open a new shell to put it on PATH",
+                paths.apps().display(),
+                shell.profile()
+            )),
+            Err(e) => output::warning(format!("{}, the first install offers it again", e.report())),
+        }
+    }
+    output::success(format!("Set up {}", paths.root().display()));
+    Ok(paths.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn answers(path: PathSetup, output: OutputStyle, mirror: Option<&str>) -> Answers {
+        Answers {
+            paths: Paths::new(std::path::Path::new("/home/me/.gleam_pkgs")),
+            path,
+            output,
+            mirror: mirror.map(String::from),
+        }
+    }
+
+    #[test]
+    fn writes_only_what_differs_from_the_defaults() {
+        let defaults = answers(PathSetup::Offer, OutputStyle::Color, None);
+        assert_eq!(defaults.config(), "");
+        let added = answers(PathSetup::AddNow, OutputStyle::Color, None);
+        assert_eq!(added.config(), "");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let chosen = answers(
+            PathSetup::Never,
+            OutputStyle::Porcelain,
+            Some("https://mirror.example.com/hex/"),
+        );
+        fs::write(&path, chosen.config()).unwrap();
+        let config = Config::load(&path).unwrap();
+        assert!(config.porcelain);
+        assert!(!config.path_prompt);
+        assert_eq!(config.mirrors, ["https://mirror.example.com/hex/"]);
+
+        let plain = answers(PathSetup::Offer, OutputStyle::Plain, None);
+        fs::write(&path, plain.config()).unwrap();
+        let config = Config::load(&path).unwrap();
+        assert!(config.plain);
+        assert!(config.path_prompt);
+    }
+}
@@ -0,0 +1,170 @@
+//! A tarball cache shared by every user of a machine
+//!
+//! With `[cache] shared_dir` set, release tarballs are looked up by their published SHA-256
+//! checksum before they are downloaded, and every verified download is added:
+//!
+//! ```text
+//! <shared_dir>/tarballs/<sha256>.tar
+//! ```
+//!
+//! Several users or CI jobs then download an identical tarball only once. Nothing in the cache
+//! is trusted: a tarball is only used when its contents have the checksum the registry
+//! published, so a user able to write to the directory cannot substitute another one. Entries
+//! are made read-only and moved into place complete; the directory gleam-pkg creates is
+//! group-writable and setgid, so members of its group can share it. A cache that cannot be read
+//! or written only costs a download.
+
+use crate::checksum;
+use crate::error::GleamPkgError;
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// Permissions of the tarball directory: group-writable, group inherited by new entries
+const DIR_MODE: u32 = 0o2775;
+
+/// Permissions of an entry: readable by everyone, writable by nobody
+const ENTRY_MODE: u32 = 0o444;
+
+/// The shared tarball cache below a directory
+#[derive(Debug, Clone)]
+pub struct SharedCache {
+    dir: PathBuf,
+}
+
+impl SharedCache {
+    /// The cache in `<shared_dir>/tarballs`
+    pub fn new(shared_dir: &Path) -> Self {
+        SharedCache {
+            dir: shared_dir.join("tarballs"),
+        }
+    }
+
+    /// Where the tarball with `checksum` is kept, `None` unless `checksum` is a SHA-256 in hex
+    pub fn path(&self, checksum: &str) -> Option<PathBuf> {
+        let valid = checksum.len() == 64 && checksum.chars().all(|c| c.is_ascii_hexdigit());
+        valid.then(|| {
+            self.dir
+                .join(format!("{}.tar", checksum.to_ascii_lowercase()))
+        })
+    }
+
+    /// Copies the tarball with `checksum` to `dest`, if the cache has it and it is intact
+    ///
+    /// # Returns
+    ///
+    /// Whether `dest` now holds the tarball
+    pub fn fetch(&self, checksum: &str, dest: &Path) -> bool {
+        let Some(entry) = self.path(checksum) else {
+            return false;
+        };
+        // a link could point anywhere, only plain files are entries
+        if !fs::symlink_metadata(&entry).is_ok_and(|m| m.is_file()) {
+            return false;
+        }
+        // entries are read-only, their copies must not be
+        let _ = fs::remove_file(dest);
+        let copied = fs::copy(&entry, dest)
+            .and_then(|_| fs::set_permissions(dest, fs::Permissions::from_mode(0o644)));
+        if copied.is_err() {
+            let _ = fs::remove_file(dest);
+            return false;
+        }
+        if checksum::sha256_file(dest).is_ok_and(|actual| checksum::matches(checksum, &actual)) {
+            return true;
+        }
+        let _ = fs::remove_file(dest);
+        // not our entry to remove unless we own the directory, which is fine either way
+        let _ = fs::remove_file(&entry);
+        false
+    }
+
+    /// Adds the tarball at `file`, whose checksum was verified to be `checksum`
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` if the cache directory or the entry cannot be written
+    pub fn store(&self, checksum: &str, file: &Path) -> Result<(), GleamPkgError> {
+        let Some(entry) = self.path(checksum) else {
+            return Ok(());
+        };
+        if entry.is_file() {
+            return Ok(());
+        }
+        let io_error = |source| GleamPkgError::Io {
+            action: "add to shared cache",
+            path: entry.clone(),
+            source,
+        };
+        if !self.dir.is_dir() {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(DIR_MODE)
+                .create(&self.dir)
+                // the umask takes the group bits away
+                .and_then(|_| fs::set_permissions(&self.dir, fs::Permissions::from_mode(DIR_MODE)))
+                .map_err(io_error)?;
+        }
+        // copied under a name of this process first, so an entry is either complete or missing
+        let tmp = self
+            .dir
+            .join(format!(".{}.{}.tmp", checksum, std::process::id()));
+        let _ = fs::remove_file(&tmp);
+        let result = fs::copy(file, &tmp)
+            .and_then(|_| fs::set_permissions(&tmp, fs::Permissions::from_mode(ENTRY_MODE)))
+            .and_then(|_| fs::rename(&tmp, &entry));
+        if let Err(source) = result {
+            let _ = fs::remove_file(&tmp);
+            // another user added it in the meantime
+            if entry.is_file() {
+                return Ok(());
+            }
+            return Err(io_error(source));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_verified_tarballs_by_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SharedCache::new(&dir.path().join("shared"));
+        let tarball = dir.path().join("hello-1.0.0.tar");
+        fs::write(&tarball, "tarball").unwrap();
+        let checksum = checksum::sha256_file(&tarball).unwrap();
+        let dest = dir.path().join("copy.tar");
+        assert!(!cache.fetch(&checksum, &dest));
+
+        cache.store(&checksum, &tarball).unwrap();
+        let entry = cache.path(&checksum).unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode(&entry), ENTRY_MODE);
+        assert_eq!(mode(entry.parent().unwrap()), DIR_MODE);
+        assert!(cache.fetch(&checksum.to_uppercase(), &dest));
+        assert!(cache.fetch(&checksum, &dest));
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "tarball");
+        assert_eq!(mode(&dest), 0o644);
+
+        assert!(cache.path("../../etc/passwd").is_none());
+        assert!(!cache.fetch("../../etc/passwd", &dest));
+    }
+
+    #[test]
+    fn ignores_entries_with_other_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SharedCache::new(dir.path());
+        let checksum = "a".repeat(64);
+        let entry = cache.path(&checksum).unwrap();
+        fs::create_dir_all(entry.parent().unwrap()).unwrap();
+        fs::write(&entry, "planted").unwrap();
+
+        let dest = dir.path().join("copy.tar");
+        assert!(!cache.fetch(&checksum, &dest));
+        assert!(!dest.exists());
+        assert!(!entry.exists());
+    }
+}
@@ -0,0 +1,406 @@
+//! Shell integration: putting the apps directory on `PATH`
+//!
+//! `gleam-pkg env` prints a snippet that adds the apps directory to `PATH` for the current
+//! shell, meant to be evaluated from the shell's startup file, like `rustup` and `pyenv` do:
+//!
+//! ```text
+//! bash, zsh   eval "$(gleam-pkg env bash)"
+//! fish        gleam-pkg env fish | source
+//! nushell     the output of `gleam-pkg env nushell` itself, nushell cannot evaluate it
+//! ```
+//!
+//! The snippets leave `PATH` alone when the directory is already on it, so evaluating them
+//! twice, e.g. in a nested shell, does not grow `PATH`.
+//!
+//! `gleam-pkg path` finds the lines putting the apps directory on `PATH` in the startup files,
+//! both the ones above and literal `export PATH=...` lines older versions wrote or users added.
+//! A literal line naming another apps directory is stale, e.g. after the root moved; a second
+//! line in the same file is a duplicate.
+
+use crate::error::GleamPkgError;
+use crate::toolchain;
+use clap::ValueEnum;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Startup files searched for `PATH` entries, relative to the home directory
+const STARTUP_FILES: [&str; 9] = [
+    ".bashrc",
+    ".bash_profile",
+    ".profile",
+    ".zshrc",
+    ".zprofile",
+    ".zshenv",
+    ".config/fish/config.fish",
+    ".config/nushell/config.nu",
+    ".config/nushell/env.nu",
+];
+
+/// A shell `gleam-pkg env` can set up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Nushell,
+}
+
+impl Shell {
+    /// The shell named by the `SHELL` environment variable
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::UnsupportedShell` if `SHELL` is unset or names another shell
+    pub fn detect() -> Result<Shell, GleamPkgError> {
+        let shell = std::env::var("SHELL").unwrap_or_default();
+        let name = Path::new(&shell)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+            .split('.')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        match name.as_str() {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            "nu" | "nushell" => Ok(Shell::Nushell),
+            "" => Err(GleamPkgError::UnsupportedShell {
+                shell: "unknown".to_string(),
+            }),
+            _ => Err(GleamPkgError::UnsupportedShell { shell: name }),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Nushell => "nushell",
+        }
+    }
+
+    /// The startup file of the shell, relative to the home directory
+    pub fn profile(self) -> &'static str {
+        match self {
+            Shell::Bash => ".bashrc",
+            Shell::Zsh => ".zshrc",
+            Shell::Fish => ".config/fish/config.fish",
+            Shell::Nushell => ".config/nushell/config.nu",
+        }
+    }
+
+    /// The snippet adding `apps_dir` to `PATH`, as printed by `gleam-pkg env`
+    pub fn env_script(self, apps_dir: &Path) -> String {
+        let dir = apps_dir.display();
+        match self {
+            Shell::Bash | Shell::Zsh => format!(
+                r#"case ":$PATH:" in
+  *":{dir}:"*) ;;
+  *) export PATH="$PATH:{dir}" ;;
+esac
+"#
+            ),
+            Shell::Fish => format!(
+                r#"if not contains -- "{dir}" $PATH
+    set -gx PATH $PATH "{dir}"
+end
+"#
+            ),
+            Shell::Nushell => {
+                format!("$env.PATH = ($env.PATH | split row (char esep) | append '{dir}' | uniq)\n")
+            }
+        }
+    }
+
+    /// The line to add to the startup file so every new shell evaluates the snippet
+    pub fn setup_line(self, apps_dir: &Path) -> String {
+        let exe = self_command();
+        match self {
+            Shell::Bash | Shell::Zsh => {
+                format!("eval \"$({} env {})\"", exe.display(), self.name())
+            }
+            Shell::Fish => format!("{} env fish | source", exe.display()),
+            Shell::Nushell => self.env_script(apps_dir).trim_end().to_string(),
+        }
+    }
+}
+
+/// A line of a startup file putting an apps directory on `PATH`
+#[derive(Debug, Clone)]
+pub struct PathEntry {
+    pub profile: PathBuf,
+    /// The line number, starting at 1
+    pub line: usize,
+    pub text: String,
+    pub state: EntryState,
+}
+
+/// Whether a [`PathEntry`] does its job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryState {
+    /// Puts the current apps directory on `PATH`
+    Current,
+    /// Repeats an earlier entry of the same file
+    Duplicate,
+    /// Names an apps directory that is not the current one
+    Stale,
+}
+
+impl EntryState {
+    pub fn name(self) -> &'static str {
+        match self {
+            EntryState::Current => "ok",
+            EntryState::Duplicate => "duplicate",
+            EntryState::Stale => "stale",
+        }
+    }
+}
+
+/// Finds the `PATH` entries in the startup files below `home`
+///
+/// # Arguments
+///
+/// * `home` - The home directory
+/// * `apps_dir` - The current apps directory
+pub fn scan(home: &Path, apps_dir: &Path) -> Vec<PathEntry> {
+    let mut entries = Vec::new();
+    for file in STARTUP_FILES {
+        let profile = home.join(file);
+        let Ok(content) = fs::read_to_string(&profile) else {
+            continue;
+        };
+        let mut seen = false;
+        for (index, text) in content.lines().enumerate() {
+            let Some(current) = classify(text, home, apps_dir) else {
+                continue;
+            };
+            let state = match (current, seen) {
+                (false, _) => EntryState::Stale,
+                (true, true) => EntryState::Duplicate,
+                (true, false) => EntryState::Current,
+            };
+            seen |= current;
+            entries.push(PathEntry {
+                profile: profile.clone(),
+                line: index + 1,
+                text: text.trim().to_string(),
+                state,
+            });
+        }
+    }
+    entries
+}
+
+/// Whether `line` is a `PATH` entry, and if so whether it names the current apps directory
+fn classify(line: &str, home: &Path, apps_dir: &Path) -> Option<bool> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return None;
+    }
+    // `gleam-pkg env` always prints the current directory
+    let setup_line = |shell: &Shell| line == shell.setup_line(apps_dir);
+    if line.contains("gleam-pkg env") || Shell::value_variants().iter().any(setup_line) {
+        return Some(true);
+    }
+    let mut spellings = vec![apps_dir.display().to_string()];
+    if let Ok(relative) = apps_dir.strip_prefix(home) {
+        spellings.push(format!("~/{}", relative.display()));
+        spellings.push(format!("$HOME/{}", relative.display()));
+    }
+    if spellings.iter().any(|dir| names_dir(line, dir)) {
+        return Some(true);
+    }
+    // a literal entry for another root, e.g. one the installation moved away from
+    if line.contains("PATH") && line.contains("gleam_pkgs/apps") {
+        return Some(false);
+    }
+    None
+}
+
+/// Whether `line` mentions `dir` itself rather than a directory below it or with a longer name
+fn names_dir(line: &str, dir: &str) -> bool {
+    line.match_indices(dir).any(|(at, _)| {
+        line[at + dir.len()..]
+            .chars()
+            .next()
+            .is_none_or(|c| !c.is_alphanumeric() && c != '_' && c != '-' && c != '/')
+    })
+}
+
+/// Adds the setup line of `shell` to its startup file, unless the file already has a current
+/// entry; stale and duplicate entries of the file are removed first
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the startup file cannot be read or written
+///
+/// # Returns
+///
+/// Whether the line was added
+pub fn add(home: &Path, shell: Shell, apps_dir: &Path) -> Result<bool, GleamPkgError> {
+    let profile = home.join(shell.profile());
+    let entries: Vec<_> = scan(home, apps_dir)
+        .into_iter()
+        .filter(|entry| entry.profile == profile)
+        .collect();
+    let broken: Vec<_> = entries
+        .iter()
+        .filter(|entry| entry.state != EntryState::Current)
+        .cloned()
+        .collect();
+    remove(&broken)?;
+    if entries
+        .iter()
+        .any(|entry| entry.state == EntryState::Current)
+    {
+        return Ok(false);
+    }
+
+    let append = || -> std::io::Result<()> {
+        use std::io::Write;
+        if let Some(dir) = profile.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&profile)?;
+        file.write_all(format!("\n{}\n", shell.setup_line(apps_dir)).as_bytes())
+    };
+    append().map_err(|source| GleamPkgError::Io {
+        action: "write shell profile",
+        path: profile.clone(),
+        source,
+    })?;
+    Ok(true)
+}
+
+/// Deletes the lines of `entries` from their startup files
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if a startup file cannot be read or written
+pub fn remove(entries: &[PathEntry]) -> Result<(), GleamPkgError> {
+    let mut profiles: Vec<&Path> = entries.iter().map(|e| e.profile.as_path()).collect();
+    profiles.dedup();
+    for profile in profiles {
+        let io_error = |source| GleamPkgError::Io {
+            action: "rewrite shell profile",
+            path: profile.to_path_buf(),
+            source,
+        };
+        let content = fs::read_to_string(profile).map_err(io_error)?;
+        let mut kept = content
+            .lines()
+            .enumerate()
+            .filter(|(index, _)| {
+                !entries
+                    .iter()
+                    .any(|e| e.profile == profile && e.line == index + 1)
+            })
+            .map(|(_, line)| line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        if content.ends_with('\n') && !kept.is_empty() {
+            kept.push('\n');
+        }
+        fs::write(profile, kept).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+/// How the startup file should call gleam-pkg: by name if it is on `PATH`, by its full path
+/// otherwise, e.g. when running from a build directory
+fn self_command() -> PathBuf {
+    if toolchain::find_executable("gleam-pkg").is_some() {
+        return PathBuf::from("gleam-pkg");
+    }
+    std::env::current_exe().unwrap_or_else(|_| PathBuf::from("gleam-pkg"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn posix_snippet_adds_the_directory_once() {
+        let script = Shell::Bash.env_script(Path::new("/opt/gleam pkgs/apps"));
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(format!(
+                "PATH=/usr/bin:/bin\n{script}{script}echo \"$PATH\""
+            ))
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            "/usr/bin:/bin:/opt/gleam pkgs/apps"
+        );
+    }
+
+    #[test]
+    fn nushell_is_set_up_with_the_snippet_itself() {
+        let apps = Path::new("/home/me/.gleam_pkgs/apps");
+        assert_eq!(
+            Shell::Nushell.setup_line(apps),
+            Shell::Nushell.env_script(apps).trim_end()
+        );
+    }
+
+    #[test]
+    fn finds_current_duplicate_and_stale_entries() {
+        let home = tempfile::tempdir().unwrap();
+        let apps = home.path().join(".local/share/gleam_pkg/apps");
+        fs::write(
+            home.path().join(".bashrc"),
+            "alias ll='ls -l'\n\
+             export PATH=\"$PATH:$HOME/.gleam_pkgs/apps\"\n\
+             # eval \"$(gleam-pkg env bash)\"\n\
+             eval \"$(gleam-pkg env bash)\"\n\
+             export PATH=\"$PATH:$HOME/.local/share/gleam_pkg/apps\"\n\
+             export PATH=\"$PATH:$HOME/.local/share/gleam_pkg/apps2\"\n",
+        )
+        .unwrap();
+
+        let entries = scan(home.path(), &apps);
+        let found: Vec<_> = entries.iter().map(|e| (e.line, e.state)).collect();
+        assert_eq!(
+            found,
+            [
+                (2, EntryState::Stale),
+                (4, EntryState::Current),
+                (5, EntryState::Duplicate)
+            ]
+        );
+    }
+
+    #[test]
+    fn adding_replaces_broken_entries_once() {
+        let home = tempfile::tempdir().unwrap();
+        let apps = home.path().join(".gleam_pkgs/apps");
+        let bashrc = home.path().join(".bashrc");
+        fs::write(
+            &bashrc,
+            "set -o vi\nexport PATH=/old/.gleam_pkgs/apps:$PATH\n",
+        )
+        .unwrap();
+
+        assert!(add(home.path(), Shell::Bash, &apps).unwrap());
+        assert!(!add(home.path(), Shell::Bash, &apps).unwrap());
+
+        let content = fs::read_to_string(&bashrc).unwrap();
+        assert!(content.starts_with("set -o vi\n"), "{}", content);
+        assert!(!content.contains("/old/"), "{}", content);
+        let entries = scan(home.path(), &apps);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].state, EntryState::Current);
+
+        remove(&entries).unwrap();
+        assert!(scan(home.path(), &apps).is_empty());
+        assert!(fs::read_to_string(&bashrc).unwrap().contains("set -o vi"));
+    }
+}
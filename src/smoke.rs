@@ -0,0 +1,189 @@
+//! Smoke tests of freshly built commands
+//!
+//! A build that succeeds can still produce a command that crashes as soon as it starts, e.g. an
+//! escript missing a module, or compiled for a runtime the wrapper cannot find. Before the
+//! wrapper of a new build is installed over a working one, the command is run once through the
+//! new wrapper, written to a private temporary directory it also runs in, with stdin closed:
+//!
+//! ```toml
+//! [smoke_test]
+//! args = ["--help"]
+//! timeout_secs = 30
+//!
+//! # for one package, `[]` skips its smoke test
+//! [packages.wonderful_cli]
+//! smoke_test = ["--version"]
+//! ```
+//!
+//! The command may fail, plenty of tools exit with an error on `--help`, but it must not crash:
+//! be killed by a signal, exit with 127 as escripts do on an uncaught exception, print what the
+//! BEAM, Node.js or Deno print when they crash, or outlive the timeout. Its output is recorded
+//! in the build log.
+
+use crate::buildlog::BuildLog;
+use crate::error::GleamPkgError;
+use crate::limits::BuildLimits;
+use crate::runner::{Runner, describe_status};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+
+/// What the runtimes print on stderr when a program crashes
+const CRASH_MARKERS: [&str; 6] = [
+    // uncaught exceptions in escripts, e.g. an undefined function
+    "escript: exception error",
+    "init terminating in do_boot",
+    "Runtime terminating during boot",
+    "Crash dump is being written",
+    // the last line Node.js prints after an uncaught exception
+    "\nNode.js v",
+    "error: Uncaught",
+];
+
+/// The exit code of an escript that raised
+const ESCRIPT_EXCEPTION: i32 = 127;
+
+/// Runs a freshly built command through its wrapper, and records the run in the build log
+///
+/// # Arguments
+///
+/// * `package` and `version` - The release built
+/// * `wrapper` - The wrapper about to be installed, see `install_wrapper`
+/// * `args` - What to pass the command, e.g. `--help`
+/// * `timeout` - How long the command may run
+/// * `limits` - The limits of the build, whose timeout is replaced by `timeout`
+/// * `log` - The build log
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::SmokeTestFailed` if the command crashes, or `GleamPkgError::Io` if
+/// the wrapper cannot be written or the log cannot be written to
+pub fn check(
+    package: &str,
+    version: &str,
+    wrapper: &[u8],
+    args: &[String],
+    timeout: Duration,
+    limits: &BuildLimits,
+    log: &mut BuildLog,
+) -> Result<(), GleamPkgError> {
+    // created exclusively and only accessible to the user, nobody can slip a script in
+    let scratch = tempfile::Builder::new()
+        .prefix("gleam-pkg-smoke-")
+        .tempdir()
+        .map_err(|source| GleamPkgError::Io {
+            action: "create smoke test directory",
+            path: std::env::temp_dir(),
+            source,
+        })?;
+    let script = scratch.path().join(package);
+    write_script(&script, wrapper)?;
+
+    let mut cmd = Command::new(&script);
+    cmd.current_dir(scratch.path()).stdin(Stdio::null());
+    let limits = BuildLimits {
+        timeout,
+        ..limits.clone()
+    };
+    let description = format!("`{} {}`", package, args.join(" "));
+    let log_path = log.path().to_path_buf();
+    let failed = |reason: String| GleamPkgError::SmokeTestFailed {
+        package: package.to_string(),
+        version: version.to_string(),
+        command: description.clone(),
+        reason,
+        log: log_path.clone(),
+    };
+    let output = match Runner::new(cmd)
+        .args(args)
+        .limits(&limits)
+        .describe(&description)
+        .capture()
+        .run()
+    {
+        Ok(output) => output,
+        Err(GleamPkgError::BuildTimeout { .. }) => {
+            return Err(failed(format!("did not finish within {:?}", timeout)));
+        }
+        Err(e) => return Err(e),
+    };
+    let crash = crash(&output);
+    log.record(&format!("smoke test {}", description), &output)?;
+    match crash {
+        Some(reason) => Err(failed(reason)),
+        None => Ok(()),
+    }
+}
+
+/// Writes the executable `script`, creating its directory
+fn write_script(script: &Path, contents: &[u8]) -> Result<(), GleamPkgError> {
+    let io_error = |source| GleamPkgError::Io {
+        action: "write smoke test wrapper",
+        path: script.to_path_buf(),
+        source,
+    };
+    fs::write(script, contents).map_err(io_error)?;
+    fs::set_permissions(script, fs::Permissions::from_mode(0o755)).map_err(io_error)
+}
+
+/// How a run crashed, or `None` if it did not
+fn crash(output: &Output) -> Option<String> {
+    if output
+        .status
+        .code()
+        .is_none_or(|code| code == ESCRIPT_EXCEPTION)
+    {
+        return Some(describe_status(&output.status));
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    CRASH_MARKERS
+        .iter()
+        .any(|marker| stderr.contains(marker))
+        .then(|| format!("{} after a crash", describe_status(&output.status)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    fn output(code: i32, stderr: &str) -> Output {
+        Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn tells_crashes_from_failures() {
+        assert_eq!(crash(&output(0, "")), None);
+        assert_eq!(crash(&output(2, "error: unknown flag --help\n")), None);
+        assert_eq!(
+            crash(&output(
+                127,
+                "escript: exception error: undefined function\n"
+            )),
+            Some("exit code 127".to_string())
+        );
+        assert_eq!(
+            crash(&output(
+                1,
+                "file:///main.mjs:1\nthrow new Error(\"boom\")\n\nError: boom\n\nNode.js v20.11.0\n"
+            )),
+            Some("exit code 1 after a crash".to_string())
+        );
+        let killed = Output {
+            status: ExitStatus::from_raw(libc::SIGSEGV),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        };
+        assert_eq!(
+            crash(&killed),
+            Some(format!("killed by signal {}", libc::SIGSEGV))
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! The package database in SQLite
+//!
+//! `~/.gleam_pkgs/db/metadata.sqlite` keeps the document of [`crate::db`] in two tables, a row
+//! per package and a row per other section of it, like `trusted` and `stats`:
+//!
+//! ```text
+//! packages (name TEXT PRIMARY KEY, entry TEXT)    the entry of the package, as JSON
+//! sections (name TEXT PRIMARY KEY, value TEXT)    the section, as JSON
+//! ```
+//!
+//! Rows hold JSON, so the schema migrations of [`crate::db`] apply to them unchanged. A write
+//! replaces the document in one transaction, and the database runs in WAL mode: readers never
+//! see half of a write and are not blocked by one, and a process killed while writing leaves
+//! the previous document behind.
+//!
+//! The first time it is read, the database imports the `metadata.json` next to it, where
+//! gleam-pkg kept the document before, and renames that file to `metadata.json.imported`.
+
+use crate::db::{JsonFile, Storage};
+use crate::error::GleamPkgError;
+use rusqlite::{Connection, Transaction, TransactionBehavior};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const TABLES: &str = "
+    CREATE TABLE IF NOT EXISTS packages (name TEXT PRIMARY KEY, entry TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS sections (name TEXT PRIMARY KEY, value TEXT NOT NULL);
+";
+
+/// How long to wait for another process writing the database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A database kept in SQLite
+pub struct SqliteFile {
+    path: PathBuf,
+}
+
+impl SqliteFile {
+    pub fn new(path: &Path) -> Self {
+        SqliteFile {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Where gleam-pkg kept the database before, `metadata.json` for `metadata.sqlite`
+    fn legacy(&self) -> PathBuf {
+        self.path.with_extension("json")
+    }
+
+    fn store_error(&self) -> impl Fn(rusqlite::Error) -> GleamPkgError + '_ {
+        |source| GleamPkgError::DatabaseStore {
+            path: self.path.clone(),
+            source,
+        }
+    }
+
+    /// Opens the database, creating it and its tables if needed
+    fn open(&self) -> Result<Connection, GleamPkgError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|source| GleamPkgError::DirectoryCreationError {
+                path: dir.to_path_buf(),
+                source,
+            })?;
+        }
+        let conn = Connection::open(&self.path).map_err(self.store_error())?;
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .and_then(|_| conn.pragma_update(None, "journal_mode", "WAL"))
+            .and_then(|_| conn.execute_batch(TABLES))
+            .map_err(self.store_error())?;
+        Ok(conn)
+    }
+
+    /// Imports the document at `legacy` unless a document is stored already, then moves the file
+    /// out of the way
+    fn import(&self, conn: &mut Connection, legacy: &Path) -> Result<(), GleamPkgError> {
+        // taking the write lock first, so concurrent imports run one after the other
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(self.store_error())?;
+        if !stored(&tx).map_err(self.store_error())? {
+            if let Some(doc) = JsonFile::new(legacy).read()? {
+                self.write_document(&tx, &doc)?;
+            }
+        }
+        tx.commit().map_err(self.store_error())?;
+        let mut imported = legacy.as_os_str().to_owned();
+        imported.push(".imported");
+        // another process may have moved it already
+        let _ = fs::rename(legacy, imported);
+        Ok(())
+    }
+
+    fn write_document(&self, tx: &Transaction, doc: &Value) -> Result<(), GleamPkgError> {
+        let invalid = |reason: &str| GleamPkgError::Io {
+            action: "write package database",
+            path: self.path.clone(),
+            source: std::io::Error::other(reason),
+        };
+        let doc = doc
+            .as_object()
+            .ok_or_else(|| invalid("it is not a JSON object"))?;
+        let mut sections = doc.clone();
+        let packages = match sections.remove("packages") {
+            Some(Value::Object(packages)) => packages,
+            Some(_) => return Err(invalid("its packages are not a JSON object")),
+            None => Map::new(),
+        };
+        // the schema marks that a document is stored, documents without one are of schema 0
+        sections.entry("schema").or_insert(Value::from(0));
+        let written = (|| {
+            tx.execute("DELETE FROM packages", [])?;
+            tx.execute("DELETE FROM sections", [])?;
+            let mut insert = tx.prepare("INSERT INTO packages (name, entry) VALUES (?1, ?2)")?;
+            for (name, entry) in &packages {
+                insert.execute((name, entry.to_string()))?;
+            }
+            let mut insert = tx.prepare("INSERT INTO sections (name, value) VALUES (?1, ?2)")?;
+            for (name, value) in &sections {
+                insert.execute((name, value.to_string()))?;
+            }
+            Ok(())
+        })();
+        written.map_err(self.store_error())
+    }
+}
+
+impl Storage for SqliteFile {
+    fn read(&self) -> Result<Option<Value>, GleamPkgError> {
+        let legacy = self.legacy();
+        if !self.path.exists() && !legacy.exists() {
+            return Ok(None);
+        }
+        let mut conn = self.open()?;
+        if legacy.exists() {
+            self.import(&mut conn, &legacy)?;
+        }
+        // both tables from the same snapshot
+        let tx = conn.transaction().map_err(self.store_error())?;
+        if !stored(&tx).map_err(self.store_error())? {
+            return Ok(None);
+        }
+        let rows = |query: &str| -> rusqlite::Result<Vec<(String, String)>> {
+            let mut statement = tx.prepare(query)?;
+            statement
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect()
+        };
+        let sections = rows("SELECT name, value FROM sections").map_err(self.store_error())?;
+        let packages = rows("SELECT name, entry FROM packages").map_err(self.store_error())?;
+        let parse = |rows: Vec<(String, String)>| -> Result<Map<String, Value>, GleamPkgError> {
+            rows.into_iter()
+                .map(|(name, json)| Ok((name, serde_json::from_str(&json)?)))
+                .collect::<Result<_, serde_json::Error>>()
+                .map_err(|source| GleamPkgError::DatabaseError {
+                    path: self.path.clone(),
+                    source,
+                })
+        };
+        let mut doc = parse(sections)?;
+        doc.insert("packages".to_string(), Value::Object(parse(packages)?));
+        Ok(Some(Value::Object(doc)))
+    }
+
+    fn write(&self, doc: &Value) -> Result<(), GleamPkgError> {
+        let mut conn = self.open()?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(self.store_error())?;
+        self.write_document(&tx, doc)?;
+        tx.commit().map_err(self.store_error())
+    }
+}
+
+/// Whether a document is stored, it always has a schema section
+fn stored(tx: &Transaction) -> rusqlite::Result<bool> {
+    tx.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sections WHERE name = 'schema')",
+        [],
+        |row| row.get(0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn documents_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SqliteFile::new(&dir.path().join("db").join("metadata.sqlite"));
+        assert!(db.read().unwrap().is_none());
+
+        let doc = json!({
+            "schema": 1,
+            "packages": {"hello": {"versions": {}, "default_version": "1.0.0"}},
+            "trusted": ["hello"],
+        });
+        db.write(&doc).unwrap();
+        assert_eq!(db.read().unwrap().unwrap(), doc);
+        let emptied = json!({"schema": 1, "packages": {}});
+        db.write(&emptied).unwrap();
+        assert_eq!(db.read().unwrap().unwrap(), emptied);
+    }
+
+    #[test]
+    fn imports_the_json_document_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("metadata.json");
+        fs::write(&legacy, r#"{"packages": {"hello": {"version": "1.0.0"}}}"#).unwrap();
+        let db = SqliteFile::new(&dir.path().join("metadata.sqlite"));
+
+        let imported = json!({"schema": 0, "packages": {"hello": {"version": "1.0.0"}}});
+        assert_eq!(db.read().unwrap().unwrap(), imported);
+        assert!(!legacy.exists());
+        assert!(dir.path().join("metadata.json.imported").is_file());
+
+        // a stale file appearing again does not replace what is stored
+        fs::write(&legacy, r#"{"packages": {}}"#).unwrap();
+        assert_eq!(db.read().unwrap().unwrap(), imported);
+    }
+}
@@ -0,0 +1,422 @@
+//! Local install statistics
+//!
+//! Install counts, the time spent in each install phase and the metadata cache hit rate are
+//! accumulated while a command runs and merged into the `stats` section of the package database
+//! when it finishes. Nothing is ever sent off the machine; `gleam-pkg stats` shows the totals.
+//!
+//! Phases are exclusive: the time of a phase nested in another, like the escript step of a
+//! build, is not counted for the outer one. The phases of the last [`MAX_TIMINGS`] installs are
+//! also kept one by one, `gleam-pkg stats --timings` averages them per package.
+
+use crate::db::{self, Database};
+use crate::error::GleamPkgError;
+use crate::output::Table;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How many installs the phases are kept of
+pub const MAX_TIMINGS: usize = 500;
+
+/// The phases of an install in the order they run, others are reported after them
+const PHASES: [&str; 6] = [
+    "metadata", "download", "extract", "build", "escript", "wrapper",
+];
+
+/// Accumulated time spent in one install phase
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct PhaseStats {
+    /// How often the phase ran
+    pub runs: u64,
+    /// Total time spent in the phase, in milliseconds
+    pub total_ms: u64,
+}
+
+/// The time spent in each phase of one completed install
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallTimings {
+    pub package: String,
+    pub version: String,
+    /// When the install completed, in seconds since the Unix epoch
+    pub completed_at: u64,
+    /// Milliseconds per phase, keyed by phase name
+    pub phases: BTreeMap<String, u64>,
+}
+
+/// Statistics stored in the package database
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Stats {
+    /// Installs that were started, including updates
+    #[serde(default)]
+    pub install_attempts: u64,
+    /// Installs that completed
+    #[serde(default)]
+    pub installs: u64,
+    /// Time spent per phase, keyed by phase name
+    #[serde(default)]
+    pub phases: BTreeMap<String, PhaseStats>,
+    /// Metadata requests answered from the cache, including revalidated entries
+    #[serde(default)]
+    pub cache_hits: u64,
+    /// Metadata requests that had to download a fresh document
+    #[serde(default)]
+    pub cache_misses: u64,
+    /// HTTP requests sent, including retries
+    #[serde(default)]
+    pub requests: u64,
+    /// Total time until their responses arrived, in milliseconds
+    #[serde(default)]
+    pub request_ms: u64,
+    /// Requests repeated after they could not connect or timed out
+    #[serde(default)]
+    pub retries: u64,
+    /// The phases of the last installs, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timings: Vec<InstallTimings>,
+}
+
+/// What this command recorded so far, `None` until it records something
+static SESSION: Mutex<Option<Stats>> = Mutex::new(None);
+
+thread_local! {
+    /// The phases of the install running on this thread, see [`begin_install`]
+    static CURRENT: RefCell<Option<BTreeMap<String, u64>>> = const { RefCell::new(None) };
+    /// Time spent in phases that completed within the phase running on this thread
+    static NESTED: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+fn with_session(f: impl FnOnce(&mut Stats)) {
+    if let Ok(mut session) = SESSION.lock() {
+        f(session.get_or_insert_with(Stats::default));
+    }
+}
+
+/// Runs `f` and records how long it took as a run of `phase`, without the phases `f` runs
+pub fn time<T>(phase: &str, f: impl FnOnce() -> T) -> T {
+    let outer = NESTED.replace(Duration::ZERO);
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    let nested = NESTED.replace(outer + elapsed);
+    record_phase(phase, elapsed.saturating_sub(nested));
+    result
+}
+
+/// Records one run of `phase` that took `elapsed`
+pub fn record_phase(phase: &str, elapsed: Duration) {
+    let ms = elapsed.as_millis() as u64;
+    with_session(|stats| {
+        let entry = stats.phases.entry(phase.to_string()).or_default();
+        entry.runs += 1;
+        entry.total_ms += ms;
+    });
+    CURRENT.with_borrow_mut(|current| {
+        if let Some(current) = current {
+            *current.entry(phase.to_string()).or_default() += ms;
+        }
+    });
+}
+
+/// Starts collecting the phases of an install on this thread, until [`record_install`]
+pub fn begin_install() {
+    CURRENT.set(Some(BTreeMap::new()));
+}
+
+/// Records the start of an install
+pub fn record_install_attempt() {
+    with_session(|stats| stats.install_attempts += 1);
+}
+
+/// Records a completed install, with the phases collected since [`begin_install`]
+pub fn record_install(package: &str, version: &str) {
+    let phases = CURRENT.take().unwrap_or_default();
+    let completed_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    with_session(|stats| {
+        stats.installs += 1;
+        stats.timings.push(InstallTimings {
+            package: package.to_string(),
+            version: version.to_string(),
+            completed_at,
+            phases,
+        });
+    });
+}
+
+/// Records whether a metadata request was answered from the cache
+pub fn record_cache(hit: bool) {
+    with_session(|stats| {
+        if hit {
+            stats.cache_hits += 1;
+        } else {
+            stats.cache_misses += 1;
+        }
+    });
+}
+
+/// Records an HTTP request whose response took `elapsed` to arrive
+pub fn record_request(elapsed: Duration) {
+    with_session(|stats| {
+        stats.requests += 1;
+        stats.request_ms += elapsed.as_millis() as u64;
+    });
+}
+
+/// Records that a request is sent again
+pub fn record_retry() {
+    with_session(|stats| stats.retries += 1);
+}
+
+/// Merges the statistics gathered by this process into the database at `db_path`
+///
+/// Nothing is written when the command did not record anything.
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` or `GleamPkgError::DatabaseError` if the database cannot be read
+/// or written
+pub fn flush(db_path: &Path) -> Result<(), GleamPkgError> {
+    let session = match SESSION.lock() {
+        Ok(mut session) => session.take(),
+        Err(_) => return Ok(()),
+    };
+    let Some(session) = session.filter(|session| !session.is_empty()) else {
+        return Ok(());
+    };
+    let _lock = db::lock(db_path);
+    let mut db = Database::load(db_path)?;
+    db.stats.merge(&session);
+    db.save(db_path)
+}
+
+impl Stats {
+    fn is_empty(&self) -> bool {
+        self.install_attempts == 0
+            && self.phases.is_empty()
+            && self.cache_hits == 0
+            && self.cache_misses == 0
+            && self.requests == 0
+    }
+
+    fn merge(&mut self, other: &Stats) {
+        self.install_attempts += other.install_attempts;
+        self.installs += other.installs;
+        self.cache_hits += other.cache_hits;
+        self.cache_misses += other.cache_misses;
+        self.requests += other.requests;
+        self.request_ms += other.request_ms;
+        self.retries += other.retries;
+        for (phase, phase_stats) in &other.phases {
+            let entry = self.phases.entry(phase.clone()).or_default();
+            entry.runs += phase_stats.runs;
+            entry.total_ms += phase_stats.total_ms;
+        }
+        self.timings.extend(other.timings.iter().cloned());
+        let dropped = self.timings.len().saturating_sub(MAX_TIMINGS);
+        self.timings.drain(..dropped);
+    }
+
+    /// The average time the kept installs of each package took, in milliseconds
+    pub fn average_install_ms(&self) -> BTreeMap<String, u64> {
+        let mut totals: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+        for timings in &self.timings {
+            let (count, total) = totals.entry(&timings.package).or_default();
+            *count += 1;
+            *total += timings.phases.values().sum::<u64>();
+        }
+        totals
+            .into_iter()
+            .map(|(package, (count, total))| (package.to_string(), total / count))
+            .collect()
+    }
+
+    /// Prints the average time of each phase per package, over the kept installs of it
+    pub fn print_timings(&self) {
+        if self.timings.is_empty() {
+            println!("No install timings recorded yet");
+            return;
+        }
+        let mut phases: Vec<&str> = PHASES.to_vec();
+        for timings in &self.timings {
+            for phase in timings.phases.keys() {
+                if !phases.contains(&phase.as_str()) {
+                    phases.push(phase);
+                }
+            }
+        }
+        let mut per_package: BTreeMap<&str, Vec<&InstallTimings>> = BTreeMap::new();
+        for timings in &self.timings {
+            per_package
+                .entry(&timings.package)
+                .or_default()
+                .push(timings);
+        }
+
+        let mut header = vec!["PACKAGE".to_string(), "INSTALLS".to_string()];
+        header.extend(phases.iter().map(|phase| phase.to_uppercase()));
+        header.push("TOTAL".to_string());
+        let mut table = Table::new(&header.iter().map(String::as_str).collect::<Vec<_>>());
+        for column in 1..header.len() {
+            table = table.align_right(column);
+        }
+        for (package, installs) in &per_package {
+            let count = installs.len() as u64;
+            let average = |phase: &str| {
+                installs
+                    .iter()
+                    .map(|timings| timings.phases.get(phase).copied().unwrap_or_default())
+                    .sum::<u64>()
+                    / count
+            };
+            let mut row = vec![(package.to_string(), None), (count.to_string(), None)];
+            row.extend(phases.iter().map(|phase| (seconds(average(phase)), None)));
+            let total = installs
+                .iter()
+                .map(|timings| timings.phases.values().sum::<u64>())
+                .sum::<u64>()
+                / count;
+            row.push((seconds(total), None));
+            table.styled_row(row);
+        }
+        table.print();
+    }
+
+    /// Prints the statistics, with each phase's share of the total install time
+    pub fn print(&self) {
+        if self.is_empty() {
+            println!("No statistics recorded yet");
+            return;
+        }
+        println!(
+            "Installs: {} completed, {} failed",
+            self.installs,
+            self.install_attempts.saturating_sub(self.installs)
+        );
+
+        let total_ms = self.phases.values().map(|p| p.total_ms).sum::<u64>();
+        if total_ms > 0 {
+            println!();
+            let mut table = Table::new(&["PHASE", "RUNS", "TOTAL", "AVERAGE", "SHARE"])
+                .align_right(1)
+                .align_right(2)
+                .align_right(3)
+                .align_right(4);
+            let mut phases = self.phases.iter().collect::<Vec<_>>();
+            phases.sort_by_key(|(_, p)| std::cmp::Reverse(p.total_ms));
+            for (phase, p) in phases {
+                table.styled_row(vec![
+                    (phase.clone(), None),
+                    (p.runs.to_string(), None),
+                    (seconds(p.total_ms), None),
+                    (seconds(p.total_ms / p.runs.max(1)), None),
+                    (format!("{}%", p.total_ms * 100 / total_ms), None),
+                ]);
+            }
+            table.print();
+        }
+
+        let requests = self.cache_hits + self.cache_misses;
+        if requests > 0 {
+            println!();
+            println!(
+                "Metadata cache: {} hits, {} misses ({}% hit rate)",
+                self.cache_hits,
+                self.cache_misses,
+                self.cache_hits * 100 / requests
+            );
+        }
+        if self.requests > 0 {
+            if requests == 0 {
+                println!();
+            }
+            println!(
+                "HTTP: {} requests, {} ms on average, {} retried",
+                self.requests,
+                self.request_ms / self.requests,
+                self.retries
+            );
+        }
+        if let Some((phase, p)) = self.phases.iter().max_by_key(|(_, p)| p.total_ms) {
+            if total_ms > 0 {
+                println!();
+                println!(
+                    "{} takes {}% of install time",
+                    phase,
+                    p.total_ms * 100 / total_ms
+                );
+            }
+        }
+    }
+}
+
+/// Formats a duration in milliseconds as seconds, e.g. `1.5s`
+pub fn seconds(ms: u64) -> String {
+    format!("{:.1}s", ms as f64 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_exclusive_phases_per_install() {
+        begin_install();
+        time("build", || {
+            std::thread::sleep(Duration::from_millis(30));
+            time("escript", || std::thread::sleep(Duration::from_millis(60)));
+        });
+        let phases = CURRENT.with_borrow(|current| current.clone().unwrap());
+        assert!(phases["escript"] >= 60);
+        assert!((30..60).contains(&phases["build"]));
+
+        record_install("hello", "1.0.0");
+        assert!(CURRENT.with_borrow(Option::is_none));
+    }
+
+    #[test]
+    fn keeps_the_timings_of_the_last_installs() {
+        let timings = |count: usize| Stats {
+            timings: (0..count)
+                .map(|i| InstallTimings {
+                    package: "hello".to_string(),
+                    version: format!("1.0.{}", i),
+                    completed_at: i as u64,
+                    phases: BTreeMap::from([("build".to_string(), 100)]),
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut stats = timings(MAX_TIMINGS - 1);
+        stats.merge(&timings(3));
+        assert_eq!(stats.timings.len(), MAX_TIMINGS);
+        assert_eq!(stats.timings[0].version, "1.0.2");
+        assert_eq!(stats.timings.last().unwrap().version, "1.0.2");
+    }
+
+    #[test]
+    fn averages_the_installs_of_each_package() {
+        let install = |package: &str, build: u64| InstallTimings {
+            package: package.to_string(),
+            version: "1.0.0".to_string(),
+            completed_at: 0,
+            phases: BTreeMap::from([("download".to_string(), 100), ("build".to_string(), build)]),
+        };
+        let stats = Stats {
+            timings: vec![
+                install("hello", 900),
+                install("wisp", 0),
+                install("hello", 1900),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            stats.average_install_ms(),
+            BTreeMap::from([("hello".to_string(), 1500), ("wisp".to_string(), 100)])
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Content-addressed storage of build artifacts
+//!
+//! Escripts are kept once in `~/.gleam_pkgs/store/<sha256>`, named after the SHA-256 checksum of
+//! their contents, and wrappers run them from there. Rebuilding a version into the same bytes
+//! reuses the blob instead of writing another copy. The database records the blob of every
+//! installed version, which is how `gleam-pkg gc` tells the blobs still in use from the rest.
+
+use crate::checksum;
+use crate::error::GleamPkgError;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The blob directory
+#[derive(Debug, Clone)]
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    pub fn new(dir: &Path) -> Self {
+        Store {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Where the blob `hash` is stored
+    pub fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(hash)
+    }
+
+    /// Copies `file` into the store, unless a blob with the same contents is already there
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` if `file` cannot be read or the blob cannot be written
+    ///
+    /// # Returns
+    ///
+    /// The checksum naming the blob
+    pub fn put(&self, file: &Path) -> Result<String, GleamPkgError> {
+        let hash = checksum::sha256_file(file)?;
+        let blob = self.path(&hash);
+        if blob.is_file() {
+            return Ok(hash);
+        }
+        // copied under a temporary name first, so a blob is either complete or missing
+        let tmp = self.dir.join(format!("{}.tmp", hash));
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::copy(file, &tmp))
+            .and_then(|_| fs::rename(&tmp, &blob))
+            .map_err(|source| GleamPkgError::Io {
+                action: "store build artifact",
+                path: blob.clone(),
+                source,
+            })?;
+        Ok(hash)
+    }
+
+    /// Every blob in the store with its size in bytes
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::Io` if the store cannot be listed
+    pub fn blobs(&self) -> Result<Vec<(String, u64)>, GleamPkgError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(source) => {
+                return Err(GleamPkgError::Io {
+                    action: "list store",
+                    path: self.dir.clone(),
+                    source,
+                });
+            }
+        };
+        let mut blobs: Vec<_> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let size = entry.metadata().ok().filter(|m| m.is_file())?.len();
+                Some((entry.file_name().into_string().ok()?, size))
+            })
+            .collect();
+        blobs.sort();
+        Ok(blobs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_files_share_a_blob() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(&dir.path().join("store"));
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        fs::write(&a, "escript").unwrap();
+        fs::write(&b, "escript").unwrap();
+
+        let hash = store.put(&a).unwrap();
+        assert_eq!(store.put(&b).unwrap(), hash);
+        assert_eq!(fs::read_to_string(store.path(&hash)).unwrap(), "escript");
+        assert_eq!(store.blobs().unwrap(), [(hash, 7)]);
+    }
+}
@@ -0,0 +1,485 @@
+//! Detection of the external toolchain needed to build packages
+//!
+//! Installing a package needs `gleam` to compile it and `erl` both to build the escript and to
+//! run it later. Both are checked up front so a missing or outdated tool is reported before
+//! anything is downloaded, instead of surfacing as an obscure failure in the middle of a build.
+//!
+//! Which `gleam` and `erl` binaries are used is decided once per command by [`Toolchain::resolve`]:
+//! an explicit `--gleam-path`/`--erl-path` wins, then a version selected by an asdf/mise
+//! `.tool-versions` file, then whatever is on `PATH`, with asdf and mise shims resolved to the
+//! binary they would run from the current directory. Builds run in a scratch project outside the
+//! user's working directory, where a shim would otherwise pick a different version. When
+//! building in docker both tools run in the build container instead, see [`crate::docker`].
+
+use crate::error::GleamPkgError;
+use crate::limits::BuildLimits;
+use crate::runner::{Runner, describe_status};
+use crate::{docker, erlang};
+use semver::Version;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+/// Oldest Gleam compiler able to build packages the way `build_package` does
+pub const MIN_GLEAM_VERSION: Version = Version::new(1, 0, 0);
+
+/// Oldest OTP release supported by Gleam 1.x
+pub const MIN_OTP_RELEASE: u32 = 26;
+
+/// File listing tool versions for asdf, also read by mise
+const TOOL_VERSIONS: &str = ".tool-versions";
+
+static TOOLCHAIN: OnceLock<Toolchain> = OnceLock::new();
+
+/// The `gleam` and `erl` binaries builds run with
+#[derive(Debug, Clone)]
+pub struct Toolchain {
+    pub gleam: ResolvedTool,
+    pub erl: ResolvedTool,
+    /// The image both tools run in when building in docker
+    pub docker_image: Option<String>,
+}
+
+/// A tool binary and how it was chosen
+#[derive(Debug, Clone)]
+pub struct ResolvedTool {
+    pub path: PathBuf,
+    /// Where the choice came from, e.g. `--gleam-path` or `/home/me/project/.tool-versions`
+    pub source: String,
+}
+
+impl Toolchain {
+    /// Resolves the `gleam` and `erl` binaries to build with
+    ///
+    /// # Arguments
+    ///
+    /// * `gleam_path` - An explicit `gleam` binary, from `--gleam-path`
+    /// * `erl_path` - An explicit `erl` binary, from `--erl-path`
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::ToolchainMissing` if an explicit path is not an executable, or if
+    /// `.tool-versions` selects a version that is not installed
+    pub fn resolve(
+        gleam_path: Option<&Path>,
+        erl_path: Option<&Path>,
+    ) -> Result<Toolchain, GleamPkgError> {
+        let tool_versions = find_tool_versions();
+        Ok(Toolchain {
+            gleam: resolve_tool("gleam", "gleam", gleam_path, tool_versions.as_deref())?,
+            erl: resolve_tool("erl", "erlang", erl_path, tool_versions.as_deref())?,
+            docker_image: None,
+        })
+    }
+
+    /// A toolchain running `gleam` and `erl` in `image`
+    ///
+    /// # Errors
+    ///
+    /// Returns `GleamPkgError::ToolchainMissing` if `docker` is not on `PATH`
+    pub fn docker(image: &str) -> Result<Toolchain, GleamPkgError> {
+        if find_executable("docker").is_none() {
+            return Err(GleamPkgError::ToolchainMissing {
+                tool: "docker".to_string(),
+                required: "any version".to_string(),
+                found: None,
+            });
+        }
+        let in_image = |binary: &str| ResolvedTool {
+            path: PathBuf::from(binary),
+            source: format!("docker image {}", image),
+        };
+        Ok(Toolchain {
+            gleam: in_image("gleam"),
+            erl: in_image("erl"),
+            docker_image: Some(image.to_string()),
+        })
+    }
+
+    /// Makes this the toolchain used by every later build step of the command
+    pub fn install(self) {
+        let _ = TOOLCHAIN.set(self);
+    }
+}
+
+/// The `gleam` binary to run
+pub fn gleam() -> PathBuf {
+    TOOLCHAIN
+        .get()
+        .map_or_else(|| PathBuf::from("gleam"), |t| t.gleam.path.clone())
+}
+
+/// The `erl` binary to run
+pub fn erl() -> PathBuf {
+    TOOLCHAIN
+        .get()
+        .map_or_else(|| PathBuf::from("erl"), |t| t.erl.path.clone())
+}
+
+/// The image builds run in, if building in docker
+pub fn docker_image() -> Option<String> {
+    TOOLCHAIN.get().and_then(|t| t.docker_image.clone())
+}
+
+/// A command running `gleam`, on the host or in the build container
+///
+/// # Arguments
+///
+/// * `project_dir` - The project to run in; when building in docker its parent directory is
+///   mounted, so path dependencies next to the project are visible
+/// * `limits` - The limits of the step
+///
+/// # Returns
+///
+/// The command and the limits to run it with
+///
+pub fn gleam_command(project_dir: Option<&Path>, limits: &BuildLimits) -> (Command, BuildLimits) {
+    match docker_image() {
+        Some(image) => {
+            let mount = project_dir.and_then(|dir| dir.parent());
+            docker::command(&image, limits, mount, project_dir, "gleam")
+        }
+        None => {
+            let mut cmd = Command::new(gleam());
+            if let Some(dir) = project_dir {
+                cmd.current_dir(dir);
+            }
+            (cmd, limits.clone())
+        }
+    }
+}
+
+/// A command running the build tool of an Erlang or Elixir package, `rebar3` or `mix`, in its
+/// sources, on the host or in the build container
+///
+/// # Returns
+///
+/// The command and the limits to run it with
+///
+pub fn build_tool_command(
+    tool: &str,
+    project_dir: &Path,
+    limits: &BuildLimits,
+) -> (Command, BuildLimits) {
+    match docker_image() {
+        Some(image) => docker::command(
+            &image,
+            limits,
+            project_dir.parent(),
+            Some(project_dir),
+            tool,
+        ),
+        None => {
+            let mut cmd = Command::new(tool);
+            cmd.current_dir(project_dir);
+            (cmd, limits.clone())
+        }
+    }
+}
+
+/// A command running `erl`, on the host or in the build container
+///
+/// # Returns
+///
+/// The command and the limits to run it with
+///
+pub fn erl_command(limits: &BuildLimits) -> (Command, BuildLimits) {
+    match docker_image() {
+        Some(image) => docker::command(&image, limits, None, None, "erl"),
+        None => (Command::new(erl()), limits.clone()),
+    }
+}
+
+/// Whether `tool` can be run, which inside the build container is left to the run itself
+fn available(tool: &Path) -> bool {
+    docker_image().is_some() || is_executable(tool)
+}
+
+/// Picks the binary for one tool
+///
+/// # Arguments
+///
+/// * `binary` - The executable name, e.g. `erl`
+/// * `plugin` - The asdf/mise plugin providing it, e.g. `erlang`
+/// * `explicit` - A path given on the command line
+/// * `tool_versions` - The `.tool-versions` file in effect, if any
+fn resolve_tool(
+    binary: &str,
+    plugin: &str,
+    explicit: Option<&Path>,
+    tool_versions: Option<&Path>,
+) -> Result<ResolvedTool, GleamPkgError> {
+    if let Some(path) = explicit {
+        if !is_executable(path) {
+            return Err(GleamPkgError::ToolchainMissing {
+                tool: binary.to_string(),
+                required: format!("an executable at {}", path.display()),
+                found: None,
+            });
+        }
+        return Ok(ResolvedTool {
+            path: path.to_path_buf(),
+            source: format!("--{}-path", binary),
+        });
+    }
+
+    if let Some(file) = tool_versions {
+        let versions = read_tool_versions(file, plugin);
+        // `system` hands the choice back to PATH
+        if !versions.is_empty() && !versions.iter().any(|v| v == "system") {
+            return versions
+                .iter()
+                .flat_map(|version| install_dirs(plugin, version))
+                .map(|dir| dir.join("bin").join(binary))
+                .find(|path| is_executable(path))
+                .map(|path| ResolvedTool {
+                    path,
+                    source: file.display().to_string(),
+                })
+                .ok_or_else(|| GleamPkgError::ToolchainMissing {
+                    tool: binary.to_string(),
+                    required: format!(
+                        "{} {} from {}",
+                        plugin,
+                        versions.join(" or "),
+                        file.display()
+                    ),
+                    found: None,
+                });
+        }
+    }
+
+    match find_executable(binary) {
+        Some(path) if is_shim(&path) => Ok(match which_through_manager(&path, binary) {
+            Some(resolved) => ResolvedTool {
+                path: resolved,
+                source: format!("shim {}", path.display()),
+            },
+            None => ResolvedTool {
+                path,
+                source: "PATH".to_string(),
+            },
+        }),
+        Some(path) => Ok(ResolvedTool {
+            path,
+            source: "PATH".to_string(),
+        }),
+        // left to the version checks to report as missing
+        None => Ok(ResolvedTool {
+            path: PathBuf::from(binary),
+            source: "PATH".to_string(),
+        }),
+    }
+}
+
+/// Finds the `.tool-versions` in effect: the closest one above the current directory, else the
+/// one in the home directory
+fn find_tool_versions() -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(TOOL_VERSIONS))
+        .chain(dirs::home_dir().map(|home| home.join(TOOL_VERSIONS)))
+        .find(|file| file.is_file())
+}
+
+/// Reads the versions listed for `plugin`, in order of preference
+fn read_tool_versions(file: &Path, plugin: &str) -> Vec<String> {
+    fs::read_to_string(file)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            (fields.next()? == plugin).then(|| fields.map(String::from).collect::<Vec<_>>())
+        })
+        .next()
+        .unwrap_or_default()
+}
+
+/// Directories asdf and mise install `plugin` at `version` into
+fn install_dirs(plugin: &str, version: &str) -> Vec<PathBuf> {
+    let home = dirs::home_dir().unwrap_or_default();
+    let asdf = std::env::var_os("ASDF_DATA_DIR").map_or_else(|| home.join(".asdf"), PathBuf::from);
+    let mise = std::env::var_os("MISE_DATA_DIR")
+        .map_or_else(|| home.join(".local/share/mise"), PathBuf::from);
+    [asdf, mise]
+        .iter()
+        .map(|root| root.join("installs").join(plugin).join(version))
+        .collect()
+}
+
+/// Whether `path` is an asdf or mise shim rather than a real binary
+fn is_shim(path: &Path) -> bool {
+    path.parent().is_some_and(|dir| dir.ends_with("shims"))
+}
+
+/// Asks the version manager owning the shim at `shim` which binary it runs here
+fn which_through_manager(shim: &Path, binary: &str) -> Option<PathBuf> {
+    let manager = if shim.to_string_lossy().contains("mise") {
+        "mise"
+    } else {
+        "asdf"
+    };
+    let mut cmd = Command::new(manager);
+    cmd.stdin(Stdio::null());
+    let output = Runner::new(cmd)
+        .args(["which", binary])
+        .capture()
+        .run()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let path = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    is_executable(&path).then_some(path)
+}
+
+/// Whether `path` names an executable, looking it up on `PATH` when it is a bare name
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    if path.components().count() == 1 {
+        return find_executable(&path.to_string_lossy()).is_some();
+    }
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// Checks that `gleam` is installed and recent enough
+///
+/// # Arguments
+///
+/// * `limits` - Resource limits for the version probe
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::ToolchainMissing` if gleam is missing, its version cannot be
+/// determined, or it is older than required
+pub fn check_gleam(limits: &BuildLimits) -> Result<Version, GleamPkgError> {
+    let gleam = gleam_version(limits)?;
+    if gleam < MIN_GLEAM_VERSION {
+        return Err(GleamPkgError::ToolchainMissing {
+            tool: "gleam".to_string(),
+            required: format!(">= {}", MIN_GLEAM_VERSION),
+            found: Some(gleam.to_string()),
+        });
+    }
+    Ok(gleam)
+}
+
+/// Checks that `erl` is installed and belongs to a supported OTP release
+///
+/// # Arguments
+///
+/// * `limits` - Resource limits for the version probe
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::ToolchainMissing` if erl is missing, its release cannot be
+/// determined, or it is older than required
+pub fn check_otp(limits: &BuildLimits) -> Result<u32, GleamPkgError> {
+    let otp_release = otp_release(limits)?;
+    if otp_release < MIN_OTP_RELEASE {
+        return Err(GleamPkgError::ToolchainMissing {
+            tool: "erl".to_string(),
+            required: format!("OTP >= {}", MIN_OTP_RELEASE),
+            found: Some(format!("OTP {}", otp_release)),
+        });
+    }
+    Ok(otp_release)
+}
+
+/// Checks that a runtime without a version requirement is installed, e.g. `node`
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::ToolchainMissing` if `tool` is not on `PATH` or fails to report its
+/// version with `--version`
+///
+/// # Returns
+///
+/// The first line `tool --version` printed
+///
+pub fn check_executable(tool: &str, limits: &BuildLimits) -> Result<String, GleamPkgError> {
+    let missing = |found: Option<String>| GleamPkgError::ToolchainMissing {
+        tool: tool.to_string(),
+        required: "any version".to_string(),
+        found,
+    };
+    if find_executable(tool).is_none() {
+        return Err(missing(None));
+    }
+    let output = Runner::new(Command::new(tool))
+        .arg("--version")
+        .limits(limits)
+        .describe(format!("`{} --version`", tool))
+        .capture()
+        .run()?;
+    if !output.status.success() {
+        return Err(missing(Some(format!(
+            "a {} that fails to run ({})",
+            tool,
+            describe_status(&output.status)
+        ))));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string())
+}
+
+/// Runs `gleam --version` and parses its `gleam X.Y.Z` output
+fn gleam_version(limits: &BuildLimits) -> Result<Version, GleamPkgError> {
+    let missing = |found: Option<String>| GleamPkgError::ToolchainMissing {
+        tool: "gleam".to_string(),
+        required: format!(">= {}", MIN_GLEAM_VERSION),
+        found,
+    };
+    if !available(&gleam()) {
+        return Err(missing(None));
+    }
+    let (cmd, limits) = gleam_command(None, limits);
+    let output = Runner::new(cmd)
+        .arg("--version")
+        .limits(&limits)
+        .describe("`gleam --version`")
+        .capture()
+        .run()?;
+    if !output.status.success() {
+        return Err(missing(Some(format!(
+            "a gleam that fails to run ({})",
+            describe_status(&output.status)
+        ))));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = stdout.split_whitespace().last().unwrap_or_default();
+    Version::parse(version).map_err(|_| missing(Some(stdout.trim().to_string())))
+}
+
+/// Asks the Erlang VM for its OTP release
+fn otp_release(limits: &BuildLimits) -> Result<u32, GleamPkgError> {
+    let missing = |found: Option<String>| GleamPkgError::ToolchainMissing {
+        tool: "erl".to_string(),
+        required: format!("OTP >= {}", MIN_OTP_RELEASE),
+        found,
+    };
+    if !available(&erl()) {
+        return Err(missing(None));
+    }
+    erlang::otp_release(limits)
+}
+
+/// Looks up an executable on `PATH`
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| {
+            candidate
+                .metadata()
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+}
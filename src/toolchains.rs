@@ -0,0 +1,884 @@
+//! Gleam compilers managed by gleam-pkg
+//!
+//! `--gleam-version 1.6.2`, or a default in `config.toml`, builds with that release of the
+//! compiler instead of the `gleam` on `PATH`. Each release is downloaded once from the release
+//! assets the Gleam project publishes for every platform, checked against their SHA-256
+//! checksums, and kept in:
+//!
+//! ```text
+//! toolchains/gleam-<version>/gleam
+//! ```
+//!
+//! ```toml
+//! [toolchains]
+//! gleam_version = "1.6.2"
+//! gleam_releases = "https://github.com/gleam-lang/gleam/releases/download/"
+//! ```
+//!
+//! `gleam-pkg toolchain install <version>` downloads a release ahead of time, `gleam-pkg
+//! toolchain default <version>` makes one the compiler builds use unless `--gleam-path`,
+//! `--gleam-version` or `config.toml` select another, so packages can be installed on machines
+//! without a Gleam compiler. The default is the link `toolchains/gleam` to the directory of the
+//! release.
+//!
+//! Which compiler built an installed version is recorded in its provenance either way, see
+//! `gleam-pkg info --installed`.
+//!
+//! Erlang/OTP is kept next to the compilers when no installed runtime can run an escript:
+//! `gleam-pkg toolchain install-otp 27` installs the newest 27.x of the precompiled builds
+//! hex.pm publishes for Ubuntu into `toolchains/otp-<version>`, where the wrappers and `gleam-pkg
+//! exec` look for runtimes after the ones installed elsewhere:
+//!
+//! ```toml
+//! [toolchains]
+//! otp_builds = "https://builds.hex.pm/builds/otp/"
+//! # detected from /etc/os-release, builds for Ubuntu run on most glibc distributions
+//! otp_platform = "ubuntu-22.04"
+//! ```
+
+use crate::config::{HttpConfig, ToolchainsConfig};
+use crate::error::GleamPkgError;
+use crate::limits::BuildLimits;
+use crate::paths::Paths;
+use crate::runner::{Runner, describe_status};
+use crate::{checksum, http, output};
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The Ubuntu releases hex.pm builds OTP for, the last one is assumed on other distributions
+const OTP_PLATFORMS: [&str; 3] = ["ubuntu-20.04", "ubuntu-24.04", "ubuntu-22.04"];
+
+/// The target triple of the compiler builds running on this machine, if any are published
+pub fn platform() -> Option<&'static str> {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => Some("x86_64-unknown-linux-musl"),
+        ("linux", "aarch64") => Some("aarch64-unknown-linux-musl"),
+        ("macos", "x86_64") => Some("x86_64-apple-darwin"),
+        ("macos", "aarch64") => Some("aarch64-apple-darwin"),
+        _ => None,
+    }
+}
+
+/// The name of the release asset of the compiler at `version` for `platform`
+fn asset(version: &str, platform: &str) -> String {
+    format!("gleam-v{}-{}.tar.gz", version, platform)
+}
+
+/// Where the compiler at `version` is kept
+pub fn gleam_binary(paths: &Paths, version: &str) -> PathBuf {
+    paths
+        .toolchains()
+        .join(format!("gleam-{}", version))
+        .join("gleam")
+}
+
+/// The versions of the compilers kept, newest first
+pub fn installed_gleams(paths: &Paths) -> Vec<semver::Version> {
+    let mut versions: Vec<_> = fs::read_dir(paths.toolchains())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let version = semver::Version::parse(name.to_str()?.strip_prefix("gleam-")?).ok()?;
+            gleam_binary(paths, &version.to_string())
+                .is_file()
+                .then_some(version)
+        })
+        .collect();
+    versions.sort_unstable_by(|a, b| b.cmp(a));
+    versions
+}
+
+/// The version of the default compiler, if one was chosen and is still kept
+pub fn default_gleam(paths: &Paths) -> Option<String> {
+    let target = fs::read_link(default_link(paths)).ok()?;
+    let version = target.to_str()?.strip_prefix("gleam-")?.to_string();
+    gleam_binary(paths, &version).is_file().then_some(version)
+}
+
+/// Makes the kept compiler at `version` the default
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::Io` if the link cannot be written
+pub fn set_default_gleam(paths: &Paths, version: &str) -> Result<(), GleamPkgError> {
+    let link = default_link(paths);
+    let _ = fs::remove_file(&link);
+    std::os::unix::fs::symlink(format!("gleam-{}", version), &link).map_err(|source| {
+        GleamPkgError::Io {
+            action: "link default gleam",
+            path: link,
+            source,
+        }
+    })
+}
+
+fn default_link(paths: &Paths) -> PathBuf {
+    paths.toolchains().join("gleam")
+}
+
+/// The compiler at `version`, downloaded first unless it is already kept
+///
+/// # Arguments
+///
+/// * `config` - Where compiler releases are downloaded from
+/// * `http` - The HTTP settings
+/// * `paths` - The installation keeping the compilers
+/// * `version` - The release, e.g. `1.6.2`
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::ReleaseNotFound` if there is no such release,
+/// `GleamPkgError::UnsupportedPlatform` if none is published for this machine,
+/// `GleamPkgError::RequestFailed` or `GleamPkgError::HttpStatus` if the download fails,
+/// `GleamPkgError::ChecksumMismatch` if it is not the published build, or `GleamPkgError::Io`
+/// if it cannot be unpacked
+pub fn ensure_gleam(
+    config: &ToolchainsConfig,
+    http: &HttpConfig,
+    paths: &Paths,
+    version: &str,
+) -> Result<PathBuf, GleamPkgError> {
+    let not_found = || GleamPkgError::ReleaseNotFound {
+        package: "gleam".to_string(),
+        version: version.to_string(),
+    };
+    // also keeps the version from naming any other directory
+    semver::Version::parse(version).map_err(|_| not_found())?;
+    let binary = gleam_binary(paths, version);
+    if binary.is_file() {
+        return Ok(binary);
+    }
+    let platform = platform().ok_or_else(|| GleamPkgError::UnsupportedPlatform {
+        tool: "gleam".to_string(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+    })?;
+
+    let base = match config.gleam_releases.ends_with('/') {
+        true => config.gleam_releases.clone(),
+        false => format!("{}/", config.gleam_releases),
+    };
+    let url = format!("{}v{}/{}", base, version, asset(version, platform));
+    output::info(format!("Downloading gleam {} from: {}", version, url));
+    let client = http::client(http)?;
+    let tarball = download(&client, &url, not_found)?;
+    let published = download(&client, &format!("{}.sha256", url), not_found)?;
+    let expected = String::from_utf8_lossy(&published)
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let actual = format!("{:x}", Sha256::digest(&tarball));
+    if !checksum::matches(&expected, &actual) {
+        return Err(GleamPkgError::ChecksumMismatch {
+            package: "gleam".to_string(),
+            version: version.to_string(),
+            expected,
+            actual,
+        });
+    }
+
+    let dir = binary.parent().unwrap_or(Path::new("."));
+    unpack(&tarball, dir)?;
+    output::success(format!("Installed gleam {} to {}", version, dir.display()));
+    Ok(binary)
+}
+
+/// Downloads `url` into memory
+///
+/// # Errors
+///
+/// Returns the error of `not_found` if there is nothing at `url`, or
+/// `GleamPkgError::RequestFailed` or `GleamPkgError::HttpStatus` if the download fails
+fn download(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    not_found: impl Fn() -> GleamPkgError,
+) -> Result<Vec<u8>, GleamPkgError> {
+    let response = http::send(client.get(url)).map_err(|source| GleamPkgError::RequestFailed {
+        url: url.to_string(),
+        source,
+    })?;
+    match response.status().as_u16() {
+        404 => return Err(not_found()),
+        status if !(200..300).contains(&status) => {
+            return Err(GleamPkgError::HttpStatus {
+                url: url.to_string(),
+                status,
+            });
+        }
+        _ => {}
+    }
+    response
+        .bytes()
+        .map(|bytes| bytes.to_vec())
+        .map_err(|source| GleamPkgError::RequestFailed {
+            url: url.to_string(),
+            source,
+        })
+}
+
+/// Where OTP `version` is kept
+pub fn otp_dir(paths: &Paths, version: &str) -> PathBuf {
+    paths.toolchains().join(format!("otp-{}", version))
+}
+
+/// The `erl` of every kept OTP installation, newest first
+pub fn installed_otps(paths: &Paths) -> Vec<(String, PathBuf)> {
+    let mut installed: Vec<_> = fs::read_dir(paths.toolchains())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let version = name.to_str()?.strip_prefix("otp-")?;
+            let erl = entry.path().join("bin").join("erl");
+            (otp_version(version).is_some() && erl.is_file()).then(|| (version.to_string(), erl))
+        })
+        .collect();
+    installed.sort_unstable_by_key(|(version, _)| std::cmp::Reverse(otp_version(version)));
+    installed
+}
+
+/// The numbers of an OTP version like `27.1.2`, `None` for pre-releases and anything else
+fn otp_version(version: &str) -> Option<Vec<u32>> {
+    version.split('.').map(|n| n.parse().ok()).collect()
+}
+
+/// The platform directory of the OTP builds that run on `os` and `arch`, e.g.
+/// `arm64/ubuntu-22.04`
+///
+/// The distribution is `otp_platform` in `config.toml`, or detected when the builds are for this
+/// machine.
+fn otp_platform(config: &ToolchainsConfig, os: &str, arch: &str) -> Result<String, GleamPkgError> {
+    let unsupported = || GleamPkgError::UnsupportedPlatform {
+        tool: "Erlang/OTP".to_string(),
+        os: os.to_string(),
+        arch: arch.to_string(),
+    };
+    if os != "linux" {
+        return Err(unsupported());
+    }
+    let arch_dir = match arch {
+        "x86_64" => "",
+        "aarch64" => "arm64/",
+        _ => return Err(unsupported()),
+    };
+    let here = os == std::env::consts::OS && arch == std::env::consts::ARCH;
+    let distribution = config.otp_platform.clone().unwrap_or_else(|| {
+        if !here {
+            return OTP_PLATFORMS[OTP_PLATFORMS.len() - 1].to_string();
+        }
+        let os_release = fs::read_to_string("/etc/os-release").unwrap_or_default();
+        let detected = os_release
+            .lines()
+            .find_map(|line| line.strip_prefix("VERSION_ID="))
+            .map(|id| format!("ubuntu-{}", id.trim_matches('"')))
+            .filter(|platform| {
+                os_release.lines().any(|line| line == "ID=ubuntu")
+                    && OTP_PLATFORMS.contains(&platform.as_str())
+            });
+        detected.unwrap_or_else(|| OTP_PLATFORMS[OTP_PLATFORMS.len() - 1].to_string())
+    });
+    Ok(format!("{}{}", arch_dir, distribution))
+}
+
+/// Picks the newest build of `release` from a `builds.txt` index, whose lines start with the
+/// name of a build, e.g. `OTP-27.1.2`, and end with the SHA-256 checksum of its tarball
+///
+/// # Returns
+///
+/// The version and the checksum, if the index has one
+fn pick_otp_build(index: &str, release: &str) -> Option<(String, Option<String>)> {
+    index
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let version = fields.next()?.strip_prefix("OTP-")?;
+            let numbers = otp_version(version)?;
+            let matches = version == release || version.starts_with(&format!("{}.", release));
+            let checksum = fields
+                .last()
+                .filter(|c| c.len() == 64 && c.chars().all(|c| c.is_ascii_hexdigit()));
+            matches.then(|| {
+                (
+                    numbers,
+                    version.to_string(),
+                    checksum.map(str::to_lowercase),
+                )
+            })
+        })
+        .max_by(|(a, ..), (b, ..)| a.cmp(b))
+        .map(|(_, version, checksum)| (version, checksum))
+}
+
+/// A precompiled OTP build, see [`find_otp_build`]
+struct OtpBuild {
+    /// Where the builds for its platform are published
+    base: String,
+    url: String,
+    version: String,
+    /// The SHA-256 checksum of its tarball, if the index has one
+    checksum: Option<String>,
+}
+
+/// Finds the newest build of `release` for `os` and `arch` in the index of the builds
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::ReleaseNotFound` if no build of `release` is published,
+/// `GleamPkgError::UnsupportedPlatform` if none is published for the platform, or
+/// `GleamPkgError::RequestFailed` or `GleamPkgError::HttpStatus` if the index cannot be fetched
+fn find_otp_build(
+    config: &ToolchainsConfig,
+    client: &reqwest::blocking::Client,
+    release: &str,
+    os: &str,
+    arch: &str,
+) -> Result<OtpBuild, GleamPkgError> {
+    let platform = otp_platform(config, os, arch)?;
+    let base = match config.otp_builds.ends_with('/') {
+        true => config.otp_builds.clone(),
+        false => format!("{}/", config.otp_builds),
+    };
+    let index = download(client, &format!("{}{}/builds.txt", base, platform), || {
+        GleamPkgError::UnsupportedPlatform {
+            tool: "Erlang/OTP".to_string(),
+            os: os.to_string(),
+            arch: arch.to_string(),
+        }
+    })?;
+    let (version, checksum) = pick_otp_build(&String::from_utf8_lossy(&index), release)
+        .ok_or_else(|| GleamPkgError::ReleaseNotFound {
+            package: "Erlang/OTP".to_string(),
+            version: release.to_string(),
+        })?;
+    Ok(OtpBuild {
+        url: format!("{}{}/OTP-{}.tar.gz", base, platform, version),
+        base,
+        version,
+        checksum,
+    })
+}
+
+/// Downloads the tarball of `build` and checks it against the published checksum
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::RequestFailed`, `GleamPkgError::HttpStatus` or
+/// `GleamPkgError::ReleaseNotFound` if the download fails, or `GleamPkgError::ChecksumMismatch`
+/// if it is not the published build
+fn download_otp_build(
+    client: &reqwest::blocking::Client,
+    build: &OtpBuild,
+) -> Result<Vec<u8>, GleamPkgError> {
+    output::info(format!(
+        "Downloading Erlang/OTP {} from: {}",
+        build.version, build.url
+    ));
+    let tarball = download(client, &build.url, || GleamPkgError::ReleaseNotFound {
+        package: "Erlang/OTP".to_string(),
+        version: build.version.clone(),
+    })?;
+    let actual = format!("{:x}", Sha256::digest(&tarball));
+    match &build.checksum {
+        Some(expected) if !checksum::matches(expected, &actual) => {
+            return Err(GleamPkgError::ChecksumMismatch {
+                package: "Erlang/OTP".to_string(),
+                version: build.version.clone(),
+                expected: expected.clone(),
+                actual,
+            });
+        }
+        Some(_) => {}
+        None => output::warning(format!(
+            "The index of {} has no checksum for OTP {}, the build is not verified",
+            build.base, build.version
+        )),
+    }
+    Ok(tarball)
+}
+
+/// Installs the newest precompiled build of an OTP release, unless it is already kept
+///
+/// # Arguments
+///
+/// * `config` - Where the builds are published and for which platform
+/// * `http` - The HTTP settings
+/// * `paths` - The installation keeping the toolchains
+/// * `release` - The release, e.g. `27`, or a version like `27.1.2`
+/// * `limits` - The limits of running the `Install` script of the build
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::ReleaseNotFound` if no build of `release` is published,
+/// `GleamPkgError::UnsupportedPlatform` if none is published for this machine,
+/// `GleamPkgError::RequestFailed` or `GleamPkgError::HttpStatus` if the download fails,
+/// `GleamPkgError::ChecksumMismatch` if it is not the published build,
+/// `GleamPkgError::CommandFailed` if it cannot be set up, or `GleamPkgError::Io` if it cannot
+/// be unpacked
+///
+/// # Returns
+///
+/// The installed version and its `erl`
+pub fn install_otp(
+    config: &ToolchainsConfig,
+    http: &HttpConfig,
+    paths: &Paths,
+    release: &str,
+    limits: &BuildLimits,
+) -> Result<(String, PathBuf), GleamPkgError> {
+    let client = http::client(http)?;
+    let build = find_otp_build(
+        config,
+        &client,
+        release,
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+    )?;
+    let dir = otp_dir(paths, &build.version);
+    let erl = dir.join("bin").join("erl");
+    if erl.is_file() {
+        output::info(format!(
+            "Erlang/OTP {} is already installed at {}",
+            build.version,
+            dir.display()
+        ));
+        return Ok((build.version, erl));
+    }
+
+    let tarball = download_otp_build(&client, &build)?;
+    let _ = fs::remove_dir_all(&dir);
+    let installed = unpack_otp(&tarball, &dir).and_then(|()| run_install_script(&dir, limits));
+    if let Err(e) = installed {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(e);
+    }
+    output::success(format!(
+        "Installed Erlang/OTP {} to {}",
+        build.version,
+        dir.display()
+    ));
+    Ok((build.version, erl))
+}
+
+/// Downloads the newest precompiled build of an OTP release for another machine into `dir`,
+/// without setting it up: [`move_otp`] does that where it ends up, see `bundle --target`
+///
+/// # Arguments
+///
+/// * `config` - Where the builds are published, and for which distribution
+/// * `http` - The HTTP settings
+/// * `release` - The release, e.g. `27`, or a version like `27.1.2`
+/// * `os` - The operating system of the machine, as `std::env::consts::OS` names it
+/// * `arch` - The architecture of the machine, as `std::env::consts::ARCH` names it
+/// * `dir` - Where to unpack the build, replacing whatever is there
+///
+/// # Errors
+///
+/// Returns the errors of [`install_otp`], except that nothing is set up
+///
+/// # Returns
+///
+/// The downloaded version
+pub fn download_otp(
+    config: &ToolchainsConfig,
+    http: &HttpConfig,
+    release: &str,
+    os: &str,
+    arch: &str,
+    dir: &Path,
+) -> Result<String, GleamPkgError> {
+    let client = http::client(http)?;
+    let build = find_otp_build(config, &client, release, os, arch)?;
+    let tarball = download_otp_build(&client, &build)?;
+    let _ = fs::remove_dir_all(dir);
+    if let Err(e) = unpack_otp(&tarball, dir) {
+        let _ = fs::remove_dir_all(dir);
+        return Err(e);
+    }
+    Ok(build.version)
+}
+
+/// Keeps the OTP installation unpacked at `unpacked`, e.g. from a bundle, as `version` unless
+/// that version is installed already
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::CommandFailed` if it cannot be set up, or `GleamPkgError::Io` if it
+/// cannot be moved
+///
+/// # Returns
+///
+/// The `erl` of the installation
+pub fn adopt_otp(
+    paths: &Paths,
+    version: &str,
+    unpacked: &Path,
+    limits: &BuildLimits,
+) -> Result<PathBuf, GleamPkgError> {
+    let dir = otp_dir(paths, version);
+    let erl = dir.join("bin").join("erl");
+    if erl.is_file() {
+        output::info(format!(
+            "Erlang/OTP {} is already installed at {}",
+            version,
+            dir.display()
+        ));
+        return Ok(erl);
+    }
+    move_otp(unpacked, &dir, limits)?;
+    output::success(format!(
+        "Installed Erlang/OTP {} to {}",
+        version,
+        dir.display()
+    ));
+    Ok(erl)
+}
+
+/// Moves the OTP installation at `from` to `dir` and sets it up there, replacing whatever is
+/// there
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::CommandFailed` if it cannot be set up, or `GleamPkgError::Io` if it
+/// cannot be moved
+pub fn move_otp(from: &Path, dir: &Path, limits: &BuildLimits) -> Result<(), GleamPkgError> {
+    let _ = fs::remove_dir_all(dir);
+    let moved = fs::create_dir_all(dir.parent().unwrap_or(Path::new(".")))
+        .and_then(|()| fs::rename(from, dir))
+        .map_err(|source| GleamPkgError::Io {
+            action: "move Erlang/OTP installation",
+            path: dir.to_path_buf(),
+            source,
+        })
+        .and_then(|()| run_install_script(dir, limits));
+    if moved.is_err() {
+        let _ = fs::remove_dir_all(dir);
+    }
+    moved
+}
+
+/// Copies the OTP installation `erl` belongs to into `dir` and sets it up there, so a build
+/// runs on it wherever other runtimes are installed, see `install --bundle-erts`
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::ToolchainMissing` if the installation has no `Install` script,
+/// `GleamPkgError::CommandFailed` if the copy cannot be set up, or `GleamPkgError::Io` if it
+/// cannot be copied
+///
+/// # Returns
+///
+/// The version of the installation
+pub fn embed_otp(erl: &Path, dir: &Path, limits: &BuildLimits) -> Result<String, GleamPkgError> {
+    let (root, version) = otp_root(erl).ok_or_else(|| GleamPkgError::ToolchainMissing {
+        tool: "Erlang/OTP".to_string(),
+        required: "an installation with its Install script".to_string(),
+        found: Some(erl.display().to_string()),
+    })?;
+    output::info(format!(
+        "Embedding Erlang/OTP {} from: {}",
+        version,
+        root.display()
+    ));
+    let _ = fs::remove_dir_all(dir);
+    let embedded = copy_tree(&root, dir)
+        .map_err(|source| GleamPkgError::Io {
+            action: "copy Erlang/OTP installation",
+            path: dir.to_path_buf(),
+            source,
+        })
+        .and_then(|()| run_install_script(dir, limits));
+    if let Err(e) = embedded {
+        let _ = fs::remove_dir_all(dir);
+        return Err(e);
+    }
+    Ok(version)
+}
+
+/// Copies a directory tree, keeping links as links and the permissions of files
+fn copy_tree(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &dest)?;
+        } else if file_type.is_dir() {
+            copy_tree(&entry.path(), &dest)?;
+        } else {
+            fs::copy(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
+
+/// The root and the version of the OTP installation an `erl` belongs to, if it has the
+/// `Install` script it can be set up elsewhere with
+pub fn otp_root(erl: &Path) -> Option<(PathBuf, String)> {
+    // `erl` on `PATH` links to `<root>/bin/erl` or `<root>/erts-<version>/bin/erl`
+    let root = fs::canonicalize(erl)
+        .ok()?
+        .ancestors()
+        .skip(2)
+        .take(2)
+        .find(|dir| dir.join("Install").is_file())?
+        .to_path_buf();
+    let version = otp_installation_version(&root)?;
+    Some((root, version))
+}
+
+/// The version of the OTP installation in `root`, from its `releases/<release>/OTP_VERSION`
+pub fn otp_installation_version(root: &Path) -> Option<String> {
+    fs::read_dir(root.join("releases"))
+        .ok()?
+        .flatten()
+        .filter_map(|entry| fs::read_to_string(entry.path().join("OTP_VERSION")).ok())
+        .map(|version| version.trim().to_string())
+        .filter(|version| otp_version(version).is_some())
+        .max_by_key(|version| otp_version(version))
+}
+
+/// Runs the `Install` script of the OTP installation in `dir`, which writes its location into
+/// the start scripts
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::CommandFailed` if it fails or leaves no `bin/erl`
+fn run_install_script(dir: &Path, limits: &BuildLimits) -> Result<(), GleamPkgError> {
+    let mut cmd = Command::new(dir.join("Install"));
+    cmd.current_dir(dir);
+    let output = Runner::new(cmd)
+        .arg("-minimal")
+        .arg(dir)
+        .limits(limits)
+        .describe("the Install script of Erlang/OTP")
+        .run()?;
+    match output.status.success() && dir.join("bin").join("erl").is_file() {
+        true => Ok(()),
+        false => Err(GleamPkgError::CommandFailed {
+            command: format!("{} -minimal", dir.join("Install").display()),
+            status: describe_status(&output.status),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }),
+    }
+}
+
+/// Unpacks an OTP build into `dir`, leaving out the directory the build is wrapped in
+fn unpack_otp(tarball: &[u8], dir: &Path) -> Result<(), GleamPkgError> {
+    let io_error = |source| GleamPkgError::Io {
+        action: "unpack Erlang/OTP build",
+        path: dir.to_path_buf(),
+        source,
+    };
+    fs::create_dir_all(dir).map_err(io_error)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(tarball));
+    for entry in archive.entries().map_err(io_error)? {
+        let mut entry = entry.map_err(io_error)?;
+        let path = entry.path().map_err(io_error)?.into_owned();
+        let mut components = path.components();
+        components.next();
+        // nothing may land outside `dir`
+        if components
+            .clone()
+            .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            continue;
+        }
+        let relative = components.as_path();
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        let target = dir.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        entry.unpack(&target).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+/// Unpacks a compiler release into `dir`, which only appears once it holds the compiler
+fn unpack(tarball: &[u8], dir: &Path) -> Result<(), GleamPkgError> {
+    let mut part = dir.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| GleamPkgError::Io {
+            action: "unpack gleam release",
+            path,
+            source,
+        }
+    };
+    let _ = fs::remove_dir_all(&part);
+    fs::create_dir_all(&part).map_err(io_error(&part))?;
+    tar::Archive::new(GzDecoder::new(tarball))
+        .unpack(&part)
+        .map_err(io_error(&part))?;
+    let gleam = part.join("gleam");
+    let mut permissions = fs::metadata(&gleam)
+        .map_err(io_error(&gleam))?
+        .permissions();
+    permissions.set_mode(0o755);
+    fs::set_permissions(&gleam, permissions).map_err(io_error(&gleam))?;
+    let _ = fs::remove_dir_all(dir);
+    fs::rename(&part, dir).map_err(io_error(dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_release_assets_and_their_location() {
+        assert_eq!(
+            asset("1.6.2", "x86_64-unknown-linux-musl"),
+            "gleam-v1.6.2-x86_64-unknown-linux-musl.tar.gz"
+        );
+        let paths = Paths::new(Path::new("/home/me/.gleam_pkgs"));
+        assert_eq!(
+            gleam_binary(&paths, "1.6.2"),
+            Path::new("/home/me/.gleam_pkgs/toolchains/gleam-1.6.2/gleam")
+        );
+    }
+
+    #[test]
+    fn finds_the_otp_builds_of_other_machines() {
+        let config = ToolchainsConfig {
+            otp_platform: Some("ubuntu-20.04".to_string()),
+            ..ToolchainsConfig::default()
+        };
+        assert_eq!(
+            otp_platform(&config, "linux", "aarch64").unwrap(),
+            "arm64/ubuntu-20.04"
+        );
+        assert_eq!(
+            otp_platform(&config, "linux", "x86_64").unwrap(),
+            "ubuntu-20.04"
+        );
+        assert!(matches!(
+            otp_platform(&config, "macos", "aarch64"),
+            Err(GleamPkgError::UnsupportedPlatform { os, .. }) if os == "macos"
+        ));
+    }
+
+    #[test]
+    fn picks_the_newest_otp_build_of_a_release() {
+        let checksum = "ab".repeat(32);
+        let index = format!(
+            "OTP-26.2.5 1a2b 2024-05-02T10:00:00Z {checksum}\n\
+             OTP-27.0 3c4d 2024-05-20T10:00:00Z {checksum}\n\
+             OTP-27.1.2 5e6f 2024-10-17T10:00:00Z {checksum}\n\
+             OTP-27.10 7a8b 2025-01-01T10:00:00Z\n\
+             OTP-28.0-rc1 9c0d 2025-02-01T10:00:00Z {checksum}\n\
+             maint 1a2b 2025-02-01T10:00:00Z {checksum}\n"
+        );
+        assert_eq!(
+            pick_otp_build(&index, "27"),
+            Some(("27.10".to_string(), None))
+        );
+        assert_eq!(
+            pick_otp_build(&index, "27.1"),
+            Some(("27.1.2".to_string(), Some(checksum.clone())))
+        );
+        assert_eq!(pick_otp_build(&index, "2"), None);
+        assert_eq!(pick_otp_build(&index, "28"), None);
+    }
+
+    #[test]
+    fn lists_kept_compilers_and_the_default() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = Paths::new(root.path());
+        for version in ["1.10.0", "1.6.2"] {
+            let binary = gleam_binary(&paths, version);
+            fs::create_dir_all(binary.parent().unwrap()).unwrap();
+            fs::write(binary, "").unwrap();
+        }
+        fs::create_dir_all(paths.toolchains().join("gleam-1.7.0.part")).unwrap();
+        assert_eq!(
+            installed_gleams(&paths),
+            [
+                semver::Version::new(1, 10, 0),
+                semver::Version::new(1, 6, 2)
+            ]
+        );
+
+        assert_eq!(default_gleam(&paths), None);
+        set_default_gleam(&paths, "1.6.2").unwrap();
+        assert_eq!(default_gleam(&paths).as_deref(), Some("1.6.2"));
+        set_default_gleam(&paths, "1.7.0").unwrap();
+        assert_eq!(default_gleam(&paths), None);
+
+        for version in ["26.2.5", "27.1.2", "27.0"] {
+            let erl = otp_dir(&paths, version).join("bin/erl");
+            fs::create_dir_all(erl.parent().unwrap()).unwrap();
+            fs::write(erl, "").unwrap();
+        }
+        let otps: Vec<_> = installed_otps(&paths).into_iter().map(|(v, _)| v).collect();
+        assert_eq!(otps, ["27.1.2", "27.0", "26.2.5"]);
+    }
+
+    #[test]
+    fn embeds_a_copy_of_an_otp_installation() {
+        let root = tempfile::tempdir().unwrap();
+        let otp = root.path().join("otp");
+        for dir in ["erts-15.1.2/bin", "releases/27"] {
+            fs::create_dir_all(otp.join(dir)).unwrap();
+        }
+        fs::write(otp.join("releases/27/OTP_VERSION"), "27.1.2\n").unwrap();
+        fs::write(otp.join("erts-15.1.2/bin/erl"), "").unwrap();
+        std::os::unix::fs::symlink("erts-15.1.2/bin", otp.join("bin")).unwrap();
+        // the real one writes the location into the start scripts
+        let install = otp.join("Install");
+        fs::write(&install, "#!/bin/sh\necho \"$2\" > \"$2/installed\"\n").unwrap();
+        fs::set_permissions(&install, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let limits = BuildLimits {
+            timeout: std::time::Duration::from_secs(10),
+            memory_limit: None,
+            cpu_limit: None,
+            isolation: None,
+        };
+        let dir = root.path().join("lib/hello-1.0.0/erts");
+        let version = embed_otp(&otp.join("bin/erl"), &dir, &limits).unwrap();
+        assert_eq!(version, "27.1.2");
+        assert_eq!(
+            fs::read_link(dir.join("bin")).unwrap(),
+            Path::new("erts-15.1.2/bin")
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("installed")).unwrap().trim(),
+            dir.display().to_string()
+        );
+        assert!(!otp.join("installed").exists());
+    }
+
+    #[test]
+    fn finds_the_root_of_an_otp_installation() {
+        let root = tempfile::tempdir().unwrap();
+        let otp = root.path().join("lib/erlang");
+        for dir in ["bin", "erts-15.1.2/bin", "releases/27"] {
+            fs::create_dir_all(otp.join(dir)).unwrap();
+        }
+        fs::write(otp.join("erts-15.1.2/bin/erl"), "").unwrap();
+        fs::write(otp.join("releases/27/OTP_VERSION"), "27.1.2\n").unwrap();
+        let link = root.path().join("erl");
+        std::os::unix::fs::symlink(otp.join("erts-15.1.2/bin/erl"), &link).unwrap();
+        assert_eq!(otp_root(&link), None);
+
+        fs::write(otp.join("Install"), "").unwrap();
+        let found = Some((fs::canonicalize(&otp).unwrap(), "27.1.2".to_string()));
+        assert_eq!(otp_root(&link), found);
+        fs::write(otp.join("bin/erl"), "").unwrap();
+        assert_eq!(otp_root(&otp.join("bin/erl")), found);
+    }
+}
@@ -0,0 +1,120 @@
+//! Confirming packages before their first install
+//!
+//! A package name one typo away from a popular one is an easy way to get code run on someone's
+//! machine. With first-install confirmation on, installing a package that was never installed
+//! nor trusted before shows who publishes it and how often it is downloaded, and asks before
+//! going ahead:
+//!
+//! ```toml
+//! [trust]
+//! confirm_new = true
+//! ```
+//!
+//! Confirmed packages are recorded as trusted in the package database and not asked about
+//! again; `install --trust-all` skips the question altogether.
+
+use serde::Deserialize;
+
+/// The `[trust]` section of the configuration
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TrustConfig {
+    /// Whether installing a package for the first time asks for confirmation
+    pub confirm_new: bool,
+}
+
+/// Someone allowed to publish a package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Owner {
+    pub username: String,
+    pub email: Option<String>,
+    /// `full` or `maintainer`
+    pub level: Option<String>,
+}
+
+/// Who publishes a package and how popular it is
+#[derive(Debug, Clone, Default)]
+pub struct Publisher {
+    pub owners: Vec<Owner>,
+    /// Downloads of every release ever
+    pub downloads: Option<u64>,
+    /// Downloads in the last 90 days
+    pub recent_downloads: Option<u64>,
+}
+
+impl Publisher {
+    /// Reads the publisher from the `packages/<package>` and `packages/<package>/owners`
+    /// documents of the hex API
+    pub fn new(metadata: &serde_json::Value, owners: &serde_json::Value) -> Self {
+        Publisher {
+            owners: owners
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|owner| {
+                    Some(Owner {
+                        username: owner["username"].as_str()?.to_string(),
+                        email: owner["email"].as_str().map(String::from),
+                        level: owner["level"].as_str().map(String::from),
+                    })
+                })
+                .collect(),
+            downloads: metadata["downloads"]["all"].as_u64(),
+            recent_downloads: metadata["downloads"]["recent"].as_u64(),
+        }
+    }
+
+    /// The owners as `name <email>`, joined by commas
+    pub fn describe_owners(&self) -> String {
+        if self.owners.is_empty() {
+            return "nobody listed".to_string();
+        }
+        self.owners
+            .iter()
+            .map(|owner| match &owner.email {
+                Some(email) => format!("{} <{}>", owner.username, email),
+                None => owner.username.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The downloads as `N in total, M recently`
+    pub fn describe_downloads(&self) -> String {
+        match (self.downloads, self.recent_downloads) {
+            (Some(all), Some(recent)) => format!("{} in total, {} recently", all, recent),
+            (Some(all), None) => format!("{} in total", all),
+            _ => "unknown".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_owners_and_downloads() {
+        let publisher = Publisher::new(
+            &json!({ "name": "wisp", "downloads": { "all": 120345, "recent": 9876 } }),
+            &json!([
+                { "username": "lpil", "email": "louis@example.com", "level": "full" },
+                { "username": "someone", "level": "maintainer" },
+            ]),
+        );
+        assert_eq!(publisher.owners.len(), 2);
+        assert_eq!(
+            publisher.describe_owners(),
+            "lpil <louis@example.com>, someone"
+        );
+        assert_eq!(
+            publisher.describe_downloads(),
+            "120345 in total, 9876 recently"
+        );
+        assert_eq!(
+            Publisher::new(&json!({}), &json!([])).describe_owners(),
+            "nobody listed"
+        );
+    }
+}
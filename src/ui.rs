@@ -0,0 +1,433 @@
+//! Interactive terminal UI for browsing and managing packages
+//!
+//! `gleam-pkg ui` shows the installed packages next to hex.pm search results. Install, update
+//! and uninstall actions run `gleam-pkg` itself as a child process, so they go through exactly
+//! the same pipeline as the command line, and their output is streamed into the output pane
+//! while the operation is in progress.
+
+use crate::db::Database;
+use crate::error::GleamPkgError;
+use crate::{Context, search_packages};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait for a key press before redrawing with new operation output
+const TICK: Duration = Duration::from_millis(100);
+
+/// Maximum number of output lines kept for the output pane
+const OUTPUT_LINES: usize = 500;
+
+/// Which list receives navigation and action keys
+#[derive(Clone, Copy, PartialEq)]
+enum Focus {
+    Installed,
+    Search,
+}
+
+/// Progress reported by a running operation
+enum OpEvent {
+    Line(String),
+    Done(bool),
+}
+
+/// An installed package as shown in the installed pane
+struct InstalledRow {
+    name: String,
+    version: String,
+    pinned: bool,
+}
+
+/// A hex.pm search result as shown in the search pane
+struct SearchRow {
+    name: String,
+    version: String,
+    description: String,
+}
+
+struct App<'a> {
+    ctx: &'a Context,
+    focus: Focus,
+    installed: Vec<InstalledRow>,
+    installed_state: ListState,
+    query: String,
+    editing: bool,
+    results: Vec<SearchRow>,
+    results_state: ListState,
+    output: Vec<String>,
+    running: Option<(String, Receiver<OpEvent>)>,
+    status: String,
+    quit: bool,
+}
+
+/// Runs the interactive UI until the user quits
+///
+/// # Arguments
+///
+/// * `ctx` - The installation to manage
+///
+/// # Errors
+///
+/// Returns `GleamPkgError::IOErr` if the terminal cannot be driven, and
+/// `GleamPkgError::DatabaseError` if the package database cannot be parsed
+pub fn run(ctx: &Context) -> Result<(), GleamPkgError> {
+    let mut app = App::new(ctx)?;
+    let mut terminal = ratatui::init();
+    let result = app.event_loop(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl<'a> App<'a> {
+    fn new(ctx: &'a Context) -> Result<Self, GleamPkgError> {
+        let mut app = App {
+            ctx,
+            focus: Focus::Installed,
+            installed: Vec::new(),
+            installed_state: ListState::default(),
+            query: String::new(),
+            editing: false,
+            results: Vec::new(),
+            results_state: ListState::default(),
+            output: Vec::new(),
+            running: None,
+            status: String::new(),
+            quit: false,
+        };
+        app.reload()?;
+        Ok(app)
+    }
+
+    /// Re-reads the installed packages from the database
+    fn reload(&mut self) -> Result<(), GleamPkgError> {
+        let db = Database::load(&self.ctx.paths.db_file())?;
+        self.installed = db
+            .packages
+            .into_iter()
+            .map(|(name, installed)| InstalledRow {
+                name,
+                version: installed.default_version,
+                pinned: installed.pinned,
+            })
+            .collect();
+        clamp(&mut self.installed_state, self.installed.len());
+        Ok(())
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<(), GleamPkgError> {
+        while !self.quit {
+            self.poll_operation()?;
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(TICK)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.handle_key(key.code, terminal)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(
+        &mut self,
+        code: KeyCode,
+        terminal: &mut DefaultTerminal,
+    ) -> Result<(), GleamPkgError> {
+        if self.editing {
+            match code {
+                KeyCode::Enter => {
+                    self.editing = false;
+                    self.status = format!("Searching hex.pm for {}...", self.query);
+                    terminal.draw(|frame| self.draw(frame))?;
+                    self.search();
+                }
+                KeyCode::Esc => self.editing = false,
+                KeyCode::Backspace => {
+                    self.query.pop();
+                }
+                KeyCode::Char(c) => self.query.push(c),
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::Installed => Focus::Search,
+                    Focus::Search => Focus::Installed,
+                }
+            }
+            KeyCode::Char('/') => {
+                self.focus = Focus::Search;
+                self.editing = true;
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.step(1),
+            KeyCode::Up | KeyCode::Char('k') => self.step(-1),
+            KeyCode::Char('i') | KeyCode::Enter if self.focus == Focus::Search => {
+                if let Some(row) = selected(&self.results, &self.results_state) {
+                    let package = row.name.clone();
+                    self.start(format!("install {}", package), &["install", &package]);
+                }
+            }
+            KeyCode::Char('u') if self.focus == Focus::Installed => {
+                if let Some(row) = selected(&self.installed, &self.installed_state) {
+                    let package = row.name.clone();
+                    self.start(format!("update {}", package), &["update", &package]);
+                }
+            }
+            KeyCode::Char('U') => self.start("update --all".to_string(), &["update", "--all"]),
+            KeyCode::Char('x') | KeyCode::Delete if self.focus == Focus::Installed => {
+                if let Some(row) = selected(&self.installed, &self.installed_state) {
+                    let package = row.name.clone();
+                    self.start(format!("uninstall {}", package), &["uninstall", &package]);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Moves the selection of the focused list by `delta` rows
+    fn step(&mut self, delta: isize) {
+        let (state, len) = match self.focus {
+            Focus::Installed => (&mut self.installed_state, self.installed.len()),
+            Focus::Search => (&mut self.results_state, self.results.len()),
+        };
+        if len == 0 {
+            return;
+        }
+        let current = state.selected().unwrap_or(0) as isize;
+        state.select(Some((current + delta).rem_euclid(len as isize) as usize));
+    }
+
+    fn search(&mut self) {
+        match search_packages(self.ctx, &self.query) {
+            Ok(results) => {
+                self.results = results
+                    .iter()
+                    .filter_map(|package| {
+                        Some(SearchRow {
+                            name: package["name"].as_str()?.to_string(),
+                            version: package["latest_stable_version"]
+                                .as_str()
+                                .or_else(|| package["latest_version"].as_str())
+                                .unwrap_or("")
+                                .to_string(),
+                            description: package["meta"]["description"]
+                                .as_str()
+                                .unwrap_or("")
+                                .to_string(),
+                        })
+                    })
+                    .collect();
+                self.results_state = ListState::default();
+                clamp(&mut self.results_state, self.results.len());
+                self.status = format!("{} packages found", self.results.len());
+            }
+            Err(e) => self.status = format!("Search failed: {}", e.report()),
+        }
+    }
+
+    /// Starts `gleam-pkg <args>` in the background, unless another operation is still running
+    fn start(&mut self, title: String, args: &[&str]) {
+        if let Some((running, _)) = &self.running {
+            self.status = format!("Wait for `{}` to finish first", running);
+            return;
+        }
+        let exe = match std::env::current_exe() {
+            Ok(exe) => exe,
+            Err(e) => {
+                self.status = format!("Failed to locate gleam-pkg: {}", e);
+                return;
+            }
+        };
+        let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || run_operation(exe, args, tx));
+
+        self.output.clear();
+        self.output.push(format!("$ gleam-pkg {}", title));
+        self.status = format!("Running `{}`...", title);
+        self.running = Some((title, rx));
+    }
+
+    /// Moves output of the running operation into the output pane
+    fn poll_operation(&mut self) -> Result<(), GleamPkgError> {
+        let Some((title, rx)) = &self.running else {
+            return Ok(());
+        };
+        let mut finished = None;
+        while let Ok(event) = rx.try_recv() {
+            match event {
+                OpEvent::Line(line) => self.output.push(line),
+                OpEvent::Done(success) => finished = Some(success),
+            }
+        }
+        if self.output.len() > OUTPUT_LINES {
+            self.output.drain(..self.output.len() - OUTPUT_LINES);
+        }
+        if let Some(success) = finished {
+            self.status = if success {
+                format!("`{}` finished", title)
+            } else {
+                format!("`{}` failed", title)
+            };
+            self.running = None;
+            self.reload()?;
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, output, status] = Layout::vertical([
+            Constraint::Percentage(60),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [installed_area, search_area] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main);
+
+        let items = self
+            .installed
+            .iter()
+            .map(|row| {
+                let pinned = if row.pinned { " (pinned)" } else { "" };
+                ListItem::new(format!("{} {}{}", row.name, row.version, pinned))
+            })
+            .collect::<Vec<_>>();
+        let list = List::new(items)
+            .block(pane("Installed", self.focus == Focus::Installed))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, installed_area, &mut self.installed_state);
+
+        let [query_area, results_area] =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(search_area);
+        let cursor = if self.editing { "_" } else { "" };
+        let query = Paragraph::new(format!("{}{}", self.query, cursor))
+            .block(pane("Search hex.pm", self.editing));
+        frame.render_widget(query, query_area);
+
+        let items = self
+            .results
+            .iter()
+            .map(|row| {
+                ListItem::new(vec![
+                    Line::from(format!("{} {}", row.name, row.version)),
+                    Line::styled(
+                        format!("  {}", row.description),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let list = List::new(items)
+            .block(pane(
+                "Results",
+                self.focus == Focus::Search && !self.editing,
+            ))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, results_area, &mut self.results_state);
+
+        // keep the tail of the output in view
+        let visible = output.height.saturating_sub(2) as usize;
+        let start = self.output.len().saturating_sub(visible);
+        let lines = self.output[start..]
+            .iter()
+            .map(|line| Line::from(line.as_str()))
+            .collect::<Vec<_>>();
+        frame.render_widget(Paragraph::new(lines).block(pane("Output", false)), output);
+
+        let hints = match self.focus {
+            _ if self.editing => "enter search  esc cancel",
+            Focus::Installed => "tab switch  / search  u update  U update all  x uninstall  q quit",
+            Focus::Search => "tab switch  / search  i install  U update all  q quit",
+        };
+        let status_line = if self.status.is_empty() {
+            hints.to_string()
+        } else {
+            format!("{}  |  {}", self.status, hints)
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+    }
+}
+
+fn pane(title: &str, focused: bool) -> Block<'_> {
+    let style = if focused {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default()
+    };
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(style)
+        .title(title)
+}
+
+fn selected<'a, T>(rows: &'a [T], state: &ListState) -> Option<&'a T> {
+    state.selected().and_then(|i| rows.get(i))
+}
+
+/// Keeps the selection of a list within its `len` rows
+fn clamp(state: &mut ListState, len: usize) {
+    match (state.selected(), len) {
+        (_, 0) => state.select(None),
+        (None, _) => state.select(Some(0)),
+        (Some(i), _) if i >= len => state.select(Some(len - 1)),
+        _ => {}
+    }
+}
+
+/// Runs `exe args` to completion, sending every output line and finally the outcome over `tx`
+fn run_operation(exe: PathBuf, args: Vec<String>, tx: Sender<OpEvent>) {
+    let child = Command::new(exe)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            let _ = tx.send(OpEvent::Line(format!("Failed to start gleam-pkg: {}", e)));
+            let _ = tx.send(OpEvent::Done(false));
+            return;
+        }
+    };
+
+    let readers = [
+        child.stdout.take().map(|out| forward(out, tx.clone())),
+        child.stderr.take().map(|err| forward(err, tx.clone())),
+    ];
+    for reader in readers.into_iter().flatten() {
+        let _ = reader.join();
+    }
+    let success = child.wait().is_ok_and(|status| status.success());
+    let _ = tx.send(OpEvent::Done(success));
+}
+
+/// Forwards `src` line by line over `tx` from a new thread
+fn forward(src: impl Read + Send + 'static, tx: Sender<OpEvent>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for line in BufReader::new(src).lines().map_while(Result::ok) {
+            // progress output rewrites its line with carriage returns, keep the last state
+            let line = line.rsplit('\r').next().unwrap_or("").to_string();
+            if tx.send(OpEvent::Line(line)).is_err() {
+                break;
+            }
+        }
+    })
+}
@@ -0,0 +1,118 @@
+//! Notices of available updates
+//!
+//! With notices enabled in `config.toml`:
+//!
+//! ```toml
+//! [updates]
+//! notify = true
+//! check_interval_secs = 86400
+//! ```
+//!
+//! a command that succeeds looks the installed packages up in the versions indexes of their
+//! repositories, see [`crate::index`], at most once per interval, and prints a line on stderr
+//! when some of them have newer releases:
+//!
+//! ```text
+//! 2 installed tools have updates; run gleam-pkg update
+//! ```
+//!
+//! Pinned packages are not counted, `update` skips them. Nothing is checked with `--quiet` or
+//! `--porcelain`, `--offline`, when stderr is not a terminal, or when `GLEAM_PKG_NO_UPDATE_CHECK`
+//! is set, whatever the configuration says. The time of the last check is kept in
+//! `cache/update-check`.
+
+use crate::config::UpdatesConfig;
+use crate::http;
+use crate::output;
+use crate::paths::Paths;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Disables the check when set to anything but an empty string
+pub const DISABLE_VAR: &str = "GLEAM_PKG_NO_UPDATE_CHECK";
+
+fn stamp(paths: &Paths) -> PathBuf {
+    paths.cache().join("update-check")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Whether updates are to be looked for now, and if so records that they were
+pub fn due(config: &UpdatesConfig, paths: &Paths) -> bool {
+    let disabled = std::env::var_os(DISABLE_VAR).is_some_and(|value| !value.is_empty());
+    if !config.notify
+        || disabled
+        || output::quiet()
+        || output::porcelain()
+        || http::offline()
+        || !std::io::stderr().is_terminal()
+    {
+        return false;
+    }
+    due_at(config, paths, now())
+}
+
+fn due_at(config: &UpdatesConfig, paths: &Paths, now: u64) -> bool {
+    let last = fs::read_to_string(stamp(paths))
+        .ok()
+        .and_then(|stamp| stamp.trim().parse::<u64>().ok());
+    // a clock set back counts as due
+    if last.is_some_and(|last| last <= now && now - last < config.check_interval_secs) {
+        return false;
+    }
+    // recorded before checking, so a check that fails is not retried by every command
+    let _ = fs::write(stamp(paths), now.to_string());
+    true
+}
+
+/// The notice for `count` packages with updates, none for 0
+pub fn notice(count: usize) -> Option<String> {
+    match count {
+        0 => None,
+        1 => Some("1 installed tool has an update; run gleam-pkg update".to_string()),
+        _ => Some(format!(
+            "{} installed tools have updates; run gleam-pkg update",
+            count
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_once_per_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = Paths::new(dir.path());
+        fs::create_dir_all(paths.cache()).unwrap();
+        let config = UpdatesConfig {
+            notify: true,
+            check_interval_secs: 100,
+        };
+        assert!(due_at(&config, &paths, 1000));
+        assert!(!due_at(&config, &paths, 1099));
+        assert!(due_at(&config, &paths, 1100));
+        assert!(due_at(&config, &paths, 500));
+    }
+
+    #[test]
+    fn counts_the_packages_with_updates() {
+        assert_eq!(notice(0), None);
+        assert_eq!(
+            notice(1).as_deref(),
+            Some("1 installed tool has an update; run gleam-pkg update")
+        );
+        assert_eq!(
+            notice(2).as_deref(),
+            Some("2 installed tools have updates; run gleam-pkg update")
+        );
+    }
+}
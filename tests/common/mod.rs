@@ -0,0 +1,419 @@
+//! A mock hex server and a throwaway home directory for running gleam-pkg end to end
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use httptest::matchers::{matches, request};
+use httptest::responders::status_code;
+use httptest::{Expectation, Server};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// Printed by the `main` of the fixture package
+pub const GREETING: &str = "Hello from the fixture package";
+
+/// A hex tarball of a package with `gleam.toml` and `src/<package>.gleam`
+pub fn hex_tarball(package: &str, version: &str) -> Vec<u8> {
+    hex_tarball_built_with(package, version, "gleam")
+}
+
+/// A [`hex_tarball`] whose `metadata.config` declares `build_tool`
+pub fn hex_tarball_built_with(package: &str, version: &str, build_tool: &str) -> Vec<u8> {
+    tarball(package, version, package, build_tool)
+}
+
+/// A [`hex_tarball`] whose `gleam.toml` names it `binary`, the command it exposes
+pub fn hex_tarball_exposing(package: &str, version: &str, binary: &str) -> Vec<u8> {
+    tarball(package, version, binary, "gleam")
+}
+
+fn tarball(package: &str, version: &str, name: &str, build_tool: &str) -> Vec<u8> {
+    let gleam_toml = format!("name = \"{name}\"\nversion = \"{version}\"\n");
+    let module = format!("pub fn main() {{\n  io.println(\"{GREETING}\")\n}}\n");
+
+    let mut contents = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    append(&mut contents, "gleam.toml", gleam_toml.as_bytes());
+    append(
+        &mut contents,
+        &format!("src/{package}.gleam"),
+        module.as_bytes(),
+    );
+    let contents = contents.into_inner().unwrap().finish().unwrap();
+
+    let mut outer = tar::Builder::new(Vec::new());
+    append(&mut outer, "VERSION", b"3");
+    let metadata = format!("{{<<\"build_tools\">>,[<<\"{build_tool}\">>]}}.\n");
+    append(&mut outer, "metadata.config", metadata.as_bytes());
+    append(&mut outer, "contents.tar.gz", &contents);
+    outer.into_inner().unwrap()
+}
+
+fn append<W: std::io::Write>(builder: &mut tar::Builder<W>, path: &str, data: &[u8]) {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, data).unwrap();
+}
+
+/// The license every fixture package declares
+pub const LICENSE: &str = "Apache-2.0";
+
+/// The packages API document of a package with the given releases, newest first
+pub fn package_metadata(package: &str, versions: &[&str]) -> serde_json::Value {
+    let releases: Vec<_> = versions
+        .iter()
+        .map(|version| serde_json::json!({ "version": version }))
+        .collect();
+    serde_json::json!({
+        "name": package,
+        "meta": { "licenses": [LICENSE] },
+        "releases": releases,
+    })
+}
+
+/// Serves the metadata and tarball of `package` at `version` like hex.pm does
+pub fn serve_package(server: &Server, package: &str, version: &str) {
+    serve_releases(server, package, &[version]);
+}
+
+/// The lowercase hex SHA-256 checksum of `data`, as the release API publishes it
+pub fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Serves the release API document of `package` at `version`, announcing `checksum`
+pub fn serve_release(server: &Server, package: &str, version: &str, checksum: &str) {
+    server.expect(
+        Expectation::matching(request::method_path(
+            "GET",
+            format!("/api/packages/{package}/releases/{version}"),
+        ))
+        .times(..)
+        .respond_with(
+            status_code(200)
+                .insert_header("content-type", "application/json")
+                .body(serde_json::json!({ "version": version, "checksum": checksum }).to_string()),
+        ),
+    );
+}
+
+/// A protobuf length-delimited field
+fn field(number: u8, bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![number << 3 | 2];
+    let mut length = bytes.len();
+    while length >= 0x80 {
+        encoded.push(length as u8 | 0x80);
+        length >>= 7;
+    }
+    encoded.push(length as u8);
+    encoded.extend_from_slice(bytes);
+    encoded
+}
+
+/// Serves `payload` at `path` as a gzipped `Signed` message, like the repository resources
+fn serve_signed(server: &Server, path: &'static str, payload: &[u8]) {
+    let mut gzipped = GzEncoder::new(Vec::new(), Compression::default());
+    gzipped.write_all(&field(1, payload)).unwrap();
+    server.expect(
+        Expectation::matching(request::method_path("GET", path))
+            .times(..)
+            .respond_with(status_code(200).body(gzipped.finish().unwrap())),
+    );
+}
+
+/// Serves the package names of the repository
+pub fn serve_names(server: &Server, names: &[&str]) {
+    let mut payload = Vec::new();
+    for name in names {
+        payload.extend(field(1, &field(1, name.as_bytes())));
+    }
+    payload.extend(field(2, b"hexpm"));
+    serve_signed(server, "/repo/names", &payload);
+}
+
+/// Serves the versions index of the repository, listing `packages` with their versions
+pub fn serve_versions_index(server: &Server, packages: &[(&str, &[&str])]) {
+    let mut payload = Vec::new();
+    for (name, versions) in packages {
+        let mut package = field(1, name.as_bytes());
+        for version in *versions {
+            package.extend(field(2, version.as_bytes()));
+        }
+        payload.extend(field(1, &package));
+    }
+    payload.extend(field(2, b"hexpm"));
+    serve_signed(server, "/repo/versions", &payload);
+}
+
+/// Serves the metadata of `package` with several releases, newest first, and their tarballs
+pub fn serve_releases(server: &Server, package: &str, versions: &[&str]) {
+    serve_releases_with(
+        server,
+        package,
+        versions,
+        package_metadata(package, versions),
+    );
+}
+
+/// Like [`serve_releases`], with the metadata `metadata`
+pub fn serve_releases_with(
+    server: &Server,
+    package: &str,
+    versions: &[&str],
+    metadata: serde_json::Value,
+) {
+    server.expect(
+        Expectation::matching(request::method_path(
+            "GET",
+            format!("/api/packages/{package}"),
+        ))
+        .times(..)
+        .respond_with(
+            status_code(200)
+                .insert_header("content-type", "application/json")
+                .body(metadata.to_string()),
+        ),
+    );
+    for version in versions {
+        let tarball = hex_tarball(package, version);
+        serve_release(server, package, version, &sha256(&tarball));
+        server.expect(
+            // GET downloads it, HEAD tells a dry run its size
+            Expectation::matching(request::path(format!(
+                "/repo/tarballs/{package}-{version}.tar"
+            )))
+            .times(..)
+            .respond_with(status_code(200).body(tarball)),
+        );
+    }
+}
+
+/// A home directory whose gleam-pkg configuration points at a mock server
+pub struct Sandbox {
+    pub home: TempDir,
+    /// A fake `gleam` that "compiles" any package into a module printing [`GREETING`], then
+    /// `FIXTURE_ECHO` and its arguments if that is set, or throwing if `FIXTURE_CRASH` is, and
+    /// "publishes" by writing its arguments to `published`; builds write `FIXTURE_MANIFEST` to
+    /// the `manifest.toml` of the project if it is set
+    pub gleam: PathBuf,
+}
+
+impl Sandbox {
+    pub fn new(server: &Server) -> Sandbox {
+        let home = tempfile::tempdir().unwrap();
+        let root = home.path().join(".gleam_pkgs");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(
+            root.join("config.toml"),
+            format!(
+                "api_base = \"{}\"\nrepository_base = \"{}\"\n",
+                server.url_str("/api/"),
+                server.url_str("/repo/")
+            ),
+        )
+        .unwrap();
+
+        let gleam = home.path().join("bin").join("gleam");
+        fs::create_dir_all(gleam.parent().unwrap()).unwrap();
+        fs::write(
+            &gleam,
+            format!(
+                r#"#!/bin/sh
+case "$1" in
+  --version) echo "gleam 1.6.0" ;;
+  build)
+    mkdir -p build/dev/javascript/gleam_pkg_build
+    echo 'export function main() {{
+  console.log("{GREETING}");
+  if (process.env.FIXTURE_CRASH) throw new Error("crashed");
+  const echo = process.env.FIXTURE_ECHO;
+  if (echo) console.log(echo, ...process.argv.slice(2));
+}}' \
+      > build/dev/javascript/gleam_pkg_build/gleam_pkg_build.mjs
+    if [ -n "$FIXTURE_MANIFEST" ]; then printf '%s\n' "$FIXTURE_MANIFEST" > manifest.toml; fi
+    ;;
+  publish) echo "$@" > published ;;
+  *) exit 1 ;;
+esac
+"#
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&gleam, fs::Permissions::from_mode(0o755)).unwrap();
+        Sandbox { home, gleam }
+    }
+
+    /// Appends `toml` to the configuration
+    pub fn configure(&self, toml: &str) {
+        let path = self.root().join("config.toml");
+        let config = fs::read_to_string(&path).unwrap();
+        fs::write(path, config + toml).unwrap();
+    }
+
+    pub fn root(&self) -> PathBuf {
+        self.home.path().join(".gleam_pkgs")
+    }
+
+    pub fn apps(&self) -> PathBuf {
+        self.root().join("apps")
+    }
+
+    /// Runs gleam-pkg with this home, and the apps directory already on `PATH`
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.run_in(&std::env::current_dir().unwrap(), args)
+    }
+
+    /// Like [`Sandbox::run`], in the working directory `dir`
+    pub fn run_in(&self, dir: &Path, args: &[&str]) -> Output {
+        self.command(dir, args).output().unwrap()
+    }
+
+    /// Like [`Sandbox::run`], with the environment variables `vars` set
+    pub fn run_with_env(&self, args: &[&str], vars: &[(&str, &str)]) -> Output {
+        let mut command = self.command(&std::env::current_dir().unwrap(), args);
+        command.envs(vars.iter().copied()).output().unwrap()
+    }
+
+    fn command(&self, dir: &Path, args: &[&str]) -> Command {
+        let path = format!(
+            "{}:{}",
+            self.apps().display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+        let mut command = Command::new(env!("CARGO_BIN_EXE_gleam-pkg"));
+        command
+            .args(args)
+            .current_dir(dir)
+            .env("HOME", self.home.path())
+            .env("PATH", path)
+            .env("SHELL", "/bin/bash")
+            .env("NO_COLOR", "1")
+            .env_remove("GLEAM_PKG_ROOT");
+        // messages are in English whatever the language of the machine running the tests
+        for name in ["LANG", "LC_ALL", "LC_MESSAGES", "GLEAM_PKG_LOCALE"] {
+            command.env_remove(name);
+        }
+        command
+    }
+
+    /// Installs `package` for Node.js, built with the fake `gleam`
+    pub fn install(&self, package: &str) -> Output {
+        self.install_with(package, &[])
+    }
+
+    /// Like [`Sandbox::install`], with extra `install` arguments
+    pub fn install_with(&self, package: &str, args: &[&str]) -> Output {
+        let gleam = self.gleam.to_str().unwrap();
+        let mut install = vec![
+            "install",
+            package,
+            "--target",
+            "node",
+            "--gleam-path",
+            gleam,
+        ];
+        install.extend_from_slice(args);
+        self.run(&install)
+    }
+
+    /// Publishes the fake `gleam` as compiler release `version` on `server`, and points the
+    /// configuration at it
+    pub fn serve_gleam_release(&self, server: &Server, version: &str) {
+        let script = fs::read_to_string(&self.gleam)
+            .unwrap()
+            .replace("gleam 1.6.0", &format!("gleam {version}"));
+        let mut release = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        append(&mut release, "gleam", script.as_bytes());
+        let release = release.into_inner().unwrap().finish().unwrap();
+        let asset = format!("^/gleam/v{version}/gleam-v{version}-[a-z0-9_-]+\\.tar\\.gz");
+        server.expect(
+            Expectation::matching(request::path(matches(format!("{asset}$"))))
+                .respond_with(status_code(200).body(release.clone())),
+        );
+        server.expect(
+            Expectation::matching(request::path(matches(format!("{asset}\\.sha256$"))))
+                .respond_with(
+                    status_code(200).body(format!("{}  gleam.tar.gz\n", sha256(&release))),
+                ),
+        );
+        self.configure(&format!(
+            "[toolchains]\ngleam_releases = \"{}\"\n",
+            server.url_str("/gleam/")
+        ));
+    }
+
+    /// Publishes a fake precompiled build of OTP `version` on `server`, whose `erl` only reports
+    /// its release, and points the configuration at it
+    pub fn serve_otp_build(&self, server: &Server, version: &str) {
+        let release = version.split('.').next().unwrap();
+        let install = format!(
+            r#"#!/bin/sh
+mkdir -p "$2/bin"
+printf '#!/bin/sh\necho {release}\n' > "$2/bin/erl"
+chmod +x "$2/bin/erl"
+"#
+        );
+        let mut build = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(install.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        build
+            .append_data(
+                &mut header,
+                format!("OTP-{version}/Install"),
+                install.as_bytes(),
+            )
+            .unwrap();
+        let build = build.into_inner().unwrap().finish().unwrap();
+        let index = format!(
+            "OTP-{version} 1a2b 2024-05-02T10:00:00Z {}\n",
+            sha256(&build)
+        );
+        server.expect(
+            Expectation::matching(request::path(matches(
+                "^/otp/([a-z0-9]+/)?ubuntu-22.04/builds.txt$",
+            )))
+            .times(..)
+            .respond_with(status_code(200).body(index)),
+        );
+        server.expect(
+            Expectation::matching(request::path(matches(format!(
+                "^/otp/([a-z0-9]+/)?ubuntu-22.04/OTP-{version}\\.tar\\.gz$"
+            ))))
+            .respond_with(status_code(200).body(build)),
+        );
+        self.configure(&format!(
+            "[toolchains]\notp_builds = \"{}\"\notp_platform = \"ubuntu-22.04\"\n",
+            server.url_str("/otp/")
+        ));
+    }
+
+    /// The package database, as `gleam-pkg db dump` prints it
+    pub fn database(&self) -> serde_json::Value {
+        let dump = self.run(&["db", "dump"]);
+        assert_success(&dump);
+        serde_json::from_slice(&dump.stdout).unwrap()
+    }
+}
+
+/// Whether `program` is on `PATH`
+pub fn has_program(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// Panics with the output of gleam-pkg unless it succeeded
+pub fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "gleam-pkg failed\nstdout:\n{}\nstderr:\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
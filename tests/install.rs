@@ -0,0 +1,2251 @@
+//! Installing and uninstalling against a mock hex server
+
+mod common;
+
+use common::{
+    GREETING, LICENSE, Sandbox, assert_success, has_program, hex_tarball, hex_tarball_built_with,
+    hex_tarball_exposing, package_metadata, serve_names, serve_package, serve_release,
+    serve_releases, serve_releases_with, serve_versions_index, sha256,
+};
+use httptest::matchers::{contains, matches, request, url_decoded};
+use httptest::responders::status_code;
+use httptest::{Expectation, Server, all_of};
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output};
+
+#[test]
+fn installs_runs_and_uninstalls_a_package() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+
+    let output = sandbox.install("hello");
+    assert_success(&output);
+
+    let apps = sandbox.apps();
+    assert!(apps.join("hello-1.0.0").is_file());
+    assert_eq!(
+        std::fs::read_link(apps.join("hello")).unwrap(),
+        std::path::Path::new("hello-1.0.0")
+    );
+    let run = Command::new(apps.join("hello")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout).trim(), GREETING);
+
+    let db = sandbox.database();
+    let hello = &db["packages"]["hello"];
+    assert_eq!(hello["default_version"], "1.0.0");
+    assert_eq!(hello["versions"]["1.0.0"]["target"], "node");
+
+    assert_success(&sandbox.run(&["uninstall", "hello"]));
+    assert!(!apps.join("hello").exists());
+    assert!(!apps.join("hello-1.0.0").exists());
+    assert!(sandbox.database()["packages"].get("hello").is_none());
+
+    let history = sandbox.run(&["--porcelain", "history", "hello"]);
+    assert_success(&history);
+    let operations: Vec<Vec<String>> = String::from_utf8_lossy(&history.stdout)
+        .lines()
+        .map(|line| line.split('\t').map(String::from).collect())
+        .collect();
+    let summary: Vec<_> = operations
+        .iter()
+        .map(|fields| [&fields[1], &fields[2], &fields[3], &fields[4], &fields[6]])
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ["install", "hello", "", "1.0.0", "ok"],
+            ["uninstall", "hello", "1.0.0", "", "ok"]
+        ]
+    );
+}
+
+#[test]
+fn builds_with_a_downloaded_gleam_release() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    sandbox.serve_gleam_release(&server, "1.6.2");
+
+    let output = sandbox.run(&[
+        "install",
+        "hello",
+        "--target",
+        "node",
+        "--gleam-version",
+        "1.6.2",
+    ]);
+    assert_success(&output);
+    assert!(
+        sandbox
+            .root()
+            .join("toolchains/gleam-1.6.2/gleam")
+            .is_file()
+    );
+    let provenance = &sandbox.database()["packages"]["hello"]["versions"]["1.0.0"]["provenance"];
+    assert_eq!(provenance["toolchain"]["gleam"], "1.6.2");
+
+    let output = sandbox.run(&["install", "hello", "--gleam-version", "1.6.x"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("gleam has no release 1.6.x"), "{}", stderr);
+}
+
+#[test]
+fn builds_with_the_default_toolchain() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    sandbox.serve_gleam_release(&server, "1.6.2");
+
+    assert_success(&sandbox.run(&["toolchain", "install", "1.6.2"]));
+    // already downloaded
+    assert_success(&sandbox.run(&["toolchain", "default", "1.6.2"]));
+    let list = sandbox.run(&["--porcelain", "toolchain", "list"]);
+    assert_success(&list);
+    let gleam = sandbox.root().join("toolchains/gleam-1.6.2/gleam");
+    assert_eq!(
+        String::from_utf8_lossy(&list.stdout),
+        format!("gleam\t1.6.2\t{}\tdefault\n", gleam.display())
+    );
+
+    // no gleam on PATH or on the command line
+    assert_success(&sandbox.run(&["install", "hello", "--target", "node"]));
+    let provenance = &sandbox.database()["packages"]["hello"]["versions"]["1.0.0"]["provenance"];
+    assert_eq!(provenance["toolchain"]["gleam"], "1.6.2");
+}
+
+#[test]
+fn exec_offers_to_install_a_missing_otp_release() {
+    let server = Server::run();
+    let sandbox = Sandbox::new(&server);
+    sandbox.serve_otp_build(&server, "24.3.4");
+    // an escript built on OTP 24, whose wrapper shows the runtime it was given
+    let wrapper = sandbox.apps().join("legacy-1.0.0");
+    std::fs::create_dir_all(sandbox.apps()).unwrap();
+    std::fs::write(&wrapper, "#!/bin/sh\necho \"$GLEAM_PKG_ERL\"\n").unwrap();
+    std::fs::set_permissions(&wrapper, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::create_dir_all(sandbox.root().join("db")).unwrap();
+    std::fs::write(
+        sandbox.root().join("db/metadata.json"),
+        r#"{"packages": {"legacy": {
+            "versions": {"1.0.0": {"target": "erlang", "otp_release": 24}},
+            "default_version": "1.0.0"
+        }}}"#,
+    )
+    .unwrap();
+
+    let output = sandbox.run(&["exec", "legacy"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("erl (OTP 24 to 26) is required"),
+        "{}",
+        stderr
+    );
+
+    let output = sandbox.run(&["--yes", "exec", "legacy"]);
+    assert_success(&output);
+    let erl = sandbox.root().join("toolchains/otp-24.3.4/bin/erl");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout.lines().last(),
+        Some(erl.to_str().unwrap()),
+        "{}",
+        stdout
+    );
+
+    // already installed, nothing is downloaded again
+    assert_success(&sandbox.run(&["toolchain", "install-otp", "24"]));
+    let list = sandbox.run(&["--porcelain", "toolchain", "list"]);
+    assert_eq!(
+        String::from_utf8_lossy(&list.stdout),
+        format!("otp\t24.3.4\t{}\t\n", erl.display())
+    );
+}
+
+#[test]
+fn republished_releases_are_refused() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    assert_success(&sandbox.install("hello"));
+
+    // as if it was installed from a tarball the registry no longer publishes
+    let checksum = sha256(&hex_tarball("hello", "1.0.0"));
+    let earlier = "0".repeat(64);
+    let recorded = sandbox.database().to_string();
+    let db = sandbox.root().join("db");
+    std::fs::remove_file(db.join("metadata.sqlite")).unwrap();
+    std::fs::write(
+        db.join("metadata.json"),
+        recorded.replace(&checksum, &earlier),
+    )
+    .unwrap();
+
+    let refused = sandbox.install("hello@1.0.0");
+    assert!(!refused.status.success());
+    let stderr = String::from_utf8_lossy(&refused.stderr);
+    assert!(stderr.contains(&format!(
+        "The registry now publishes checksum {} for hello 1.0.0, but it was installed with {}",
+        checksum, earlier
+    )));
+    assert!(stderr.contains("--accept-new-checksum"));
+
+    let accepted = sandbox.install_with("hello@1.0.0", &["--accept-new-checksum"]);
+    assert_success(&accepted);
+    assert!(String::from_utf8_lossy(&accepted.stderr).contains("republished hello 1.0.0"));
+    let provenance = &sandbox.database()["packages"]["hello"]["versions"]["1.0.0"]["provenance"];
+    assert_eq!(provenance["checksum"], checksum.as_str());
+    assert_success(&sandbox.install("hello@1.0.0"));
+}
+
+#[test]
+fn installs_record_their_provenance() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    assert_success(&sandbox.install("hello"));
+
+    let checksum = sha256(&hex_tarball("hello", "1.0.0"));
+    let provenance = &sandbox.database()["packages"]["hello"]["versions"]["1.0.0"]["provenance"];
+    assert_eq!(provenance["checksum"], checksum.as_str());
+    assert_eq!(provenance["verified"], true);
+    assert_eq!(provenance["toolchain"]["gleam"], "1.6.0");
+
+    let info = sandbox.run(&["info", "--installed", "hello"]);
+    assert_success(&info);
+    let info = String::from_utf8_lossy(&info.stdout);
+    assert!(info.contains(&format!("{} (matches the registry)", checksum)));
+    assert!(info.contains("/repo/tarballs/hello-1.0.0.tar"));
+
+    let sbom = sandbox.run(&["sbom"]);
+    assert_success(&sbom);
+    let sbom: serde_json::Value = serde_json::from_slice(&sbom.stdout).unwrap();
+    assert_eq!(sbom["bomFormat"], "CycloneDX");
+    assert_eq!(sbom["components"][0]["purl"], "pkg:hex/hello@1.0.0");
+    assert_eq!(
+        sbom["components"][0]["hashes"][0]["content"],
+        checksum.as_str()
+    );
+
+    let spdx = sandbox.home.path().join("sbom.spdx.json");
+    let spdx_arg = spdx.to_str().unwrap();
+    assert_success(&sandbox.run(&["sbom", "--format", "spdx", "--output", spdx_arg]));
+    let spdx: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(spdx).unwrap()).unwrap();
+    assert_eq!(spdx["spdxVersion"], "SPDX-2.3");
+    assert_eq!(spdx["packages"][0]["SPDXID"], "SPDXRef-hex-hello-1.0.0");
+    assert_eq!(
+        spdx["packages"][0]["checksums"][0]["checksumValue"],
+        checksum.as_str()
+    );
+}
+
+#[test]
+fn installs_emit_progress_events() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    let events = sandbox.home.path().join("events.jsonl");
+    let events_arg = events.to_str().unwrap();
+    let gleam = sandbox.gleam.to_str().unwrap();
+    assert_success(&sandbox.run(&[
+        "install",
+        "hello",
+        "--target",
+        "node",
+        "--gleam-path",
+        gleam,
+        "--events",
+        events_arg,
+    ]));
+
+    let events: Vec<serde_json::Value> = std::fs::read_to_string(events)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let kinds: Vec<_> = events
+        .iter()
+        .map(|e| e["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds.first(), Some(&"metadata_fetched"));
+    assert!(kinds.contains(&"download_started"));
+    assert_eq!(kinds.last(), Some(&"installed"));
+    let size = hex_tarball("hello", "1.0.0").len();
+    let progress = events
+        .iter()
+        .rfind(|e| e["event"] == "download_progress")
+        .unwrap();
+    assert_eq!(progress["bytes"], size);
+    let build = events
+        .iter()
+        .find(|e| e["event"] == "build_started")
+        .unwrap();
+    assert_eq!(build["backend"], "node");
+    assert_eq!(events.last().unwrap()["version"], "1.0.0");
+}
+
+#[test]
+fn license_policies_refuse_or_warn() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    sandbox.configure("[licenses]\nallow = [\"MIT\"]\non_violation = \"refuse\"\n");
+
+    let output = sandbox.install("hello");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("licensed under {}", LICENSE)),
+        "{}",
+        stderr
+    );
+    assert!(!sandbox.apps().join("hello-1.0.0").exists());
+
+    let config = sandbox.root().join("config.toml");
+    let refusing = std::fs::read_to_string(&config).unwrap();
+    std::fs::write(&config, refusing.replace("refuse", "warn")).unwrap();
+    let output = sandbox.install("hello");
+    assert_success(&output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("does not accept"));
+
+    let output = sandbox.run(&["licenses"]);
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout
+            .lines()
+            .any(|line| line.starts_with("hello") && line.ends_with("not allowed")),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains(&format!("{} (1)", LICENSE)), "{}", stdout);
+}
+
+#[test]
+fn new_packages_need_confirmation() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/api/packages/hello/owners"))
+            .times(..)
+            .respond_with(
+                status_code(200).body(
+                    r#"[{"username": "lucy", "email": "lucy@example.com", "level": "full"}]"#,
+                ),
+            ),
+    );
+    let sandbox = Sandbox::new(&server);
+    sandbox.configure("[trust]\nconfirm_new = true\n");
+
+    // without a terminal to ask on
+    let output = sandbox.install("hello");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("needs confirmation"), "{}", stderr);
+    assert!(!sandbox.apps().join("hello").exists());
+
+    assert_success(&sandbox.install_with("hello", &["--trust-all"]));
+    // installed packages are not new anymore
+    assert_success(&sandbox.install("hello"));
+    // but skipping the question did not trust it
+    assert_success(&sandbox.run(&["uninstall", "hello"]));
+    assert!(!sandbox.install("hello").status.success());
+
+    assert_success(&sandbox.install_with("hello", &["--yes"]));
+    assert_eq!(sandbox.database()["trusted"], serde_json::json!(["hello"]));
+
+    let owner = sandbox.run(&["owner", "hello"]);
+    assert_success(&owner);
+    let owner = String::from_utf8_lossy(&owner.stdout);
+    assert!(owner.contains("lucy@example.com"), "{}", owner);
+    assert!(owner.contains("Downloads: unknown"), "{}", owner);
+}
+
+#[test]
+fn hooks_run_around_install_and_uninstall() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    let log = sandbox.home.path().join("hooks.log");
+    sandbox.configure(&format!(
+        r#"
+[hooks]
+pre_install = 'echo "$GLEAM_PKG_EVENT $GLEAM_PKG_PACKAGE" >> {log}'
+post_uninstall = 'echo "$GLEAM_PKG_EVENT $GLEAM_PKG_PACKAGE" >> {log}'
+
+[hooks.hello]
+post_install = '"$GLEAM_PKG_WRAPPER" >> {log}'
+"#,
+        log = log.display()
+    ));
+
+    let output = sandbox.install("hello");
+    assert_success(&output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Running post_install hook"));
+    assert_success(&sandbox.run(&["uninstall", "hello"]));
+
+    assert_eq!(
+        std::fs::read_to_string(&log).unwrap(),
+        format!("pre_install hello\n{}\npost_uninstall hello\n", GREETING)
+    );
+}
+
+#[test]
+fn failing_pre_install_hooks_stop_the_install() {
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    sandbox.configure("[hooks]\npre_install = 'exit 1'\n");
+
+    let output = sandbox.install("hello");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("pre_install hook of hello failed"));
+    assert!(
+        !sandbox
+            .root()
+            .join("download")
+            .join("hello-1.0.0.tar")
+            .exists()
+    );
+}
+
+#[test]
+fn dry_run_changes_nothing() {
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+
+    let output = sandbox.run(&["install", "hello", "--target", "node", "--dry-run"]);
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("tarballs/hello-1.0.0.tar"), "{}", stdout);
+    // the size comes from a HEAD request
+    assert!(!stdout.contains("Would download 0 B"), "{}", stdout);
+    assert!(
+        !sandbox
+            .root()
+            .join("download")
+            .join("hello-1.0.0.tar")
+            .exists()
+    );
+    assert!(!sandbox.apps().join("hello").exists());
+}
+
+#[test]
+fn explain_narrates_the_install() {
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+
+    let args = [
+        "install",
+        "hello",
+        "--target",
+        "node",
+        "--explain",
+        "--dry-run",
+    ];
+    let output = sandbox.run(&args);
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for expected in [
+        "Installing hello 1.0.0",
+        "the newest stable release that is not retired",
+        "packages/hello",
+        "tarballs/hello-1.0.0.tar",
+        "chosen with --target",
+        "the default: the wrapper runs the escript in the store",
+    ] {
+        assert!(stdout.contains(expected), "{}\n{}", expected, stdout);
+    }
+    assert!(!sandbox.apps().join("hello").exists());
+}
+
+#[test]
+fn output_into_a_pipe_is_plain() {
+    let server = Server::run();
+    let sandbox = Sandbox::new(&server);
+
+    let output = sandbox.run(&["uninstall", "hello"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("error: hello is not installed"),
+        "{}",
+        stderr
+    );
+    let output = sandbox.run_with_env(&["uninstall", "hello"], &[("LANG", "zh_CN.UTF-8")]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("错误: hello 未安装"), "{}", stderr);
+}
+
+#[test]
+fn messages_follow_the_locale() {
+    let server = Server::run();
+    let sandbox = Sandbox::new(&server);
+
+    let chinese = [("LANG", "zh_CN.UTF-8")];
+    let output = sandbox.run_with_env(&["uninstall", "hello"], &chinese);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("hello 未安装"), "{}", stderr);
+
+    sandbox.configure("locale = \"en\"\n");
+    let output = sandbox.run_with_env(&["uninstall", "hello"], &chinese);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("hello is not installed"), "{}", stderr);
+}
+
+#[test]
+fn updates_packages_in_parallel() {
+    let server = Server::run();
+    for package in ["hello", "world", "again"] {
+        serve_releases(&server, package, &["1.1.0", "1.0.0"]);
+    }
+    let versions: &[&str] = &["1.0.0", "1.1.0"];
+    serve_versions_index(
+        &server,
+        &[
+            ("hello", versions),
+            ("world", versions),
+            ("again", versions),
+        ],
+    );
+    let sandbox = Sandbox::new(&server);
+    for package in ["hello@1.0.0", "world@1.0.0", "again@1.1.0"] {
+        assert_success(&sandbox.install(package));
+    }
+
+    let gleam = sandbox.gleam.to_str().unwrap();
+    let output = sandbox.run(&["update", "--all", "--jobs", "3", "--gleam-path", gleam]);
+    assert_success(&output);
+
+    let db = sandbox.database();
+    for package in ["hello", "world", "again"] {
+        let installed = &db["packages"][package];
+        assert_eq!(installed["default_version"], "1.1.0", "{}", package);
+        assert!(installed["versions"].get("1.0.0").is_none(), "{}", package);
+        assert!(sandbox.apps().join(format!("{package}-1.1.0")).is_file());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // each package is reported as it finishes, counted rather than drawn into a pipe
+    let progress: Vec<_> = stdout
+        .lines()
+        .filter(|line| line.contains(" of 3: "))
+        .collect();
+    assert_eq!(progress.len(), 3, "{}", stdout);
+    assert!(progress[2].starts_with("3 of 3: "));
+    for reported in ["again is up to date in", "hello updated 1.0.0 -> 1.1.0 in"] {
+        assert!(
+            progress.iter().any(|line| line.contains(reported)),
+            "{}",
+            stdout
+        );
+    }
+    let summary: Vec<_> = stdout
+        .lines()
+        .skip_while(|line| !line.starts_with("PACKAGE"))
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .collect();
+    assert_eq!(
+        summary[1][..4],
+        ["again", "1.1.0", "up", "to"],
+        "{}",
+        stdout
+    );
+    assert_eq!(summary[2][..4], ["hello", "1.0.0", "1.1.0", "updated"]);
+    assert_eq!(summary[3][..4], ["world", "1.0.0", "1.1.0", "updated"]);
+}
+
+#[test]
+fn downloads_are_pruned_by_policy() {
+    let server = Server::run();
+    serve_releases(&server, "hello", &["1.2.0", "1.1.0", "1.0.0"]);
+    let sandbox = Sandbox::new(&server);
+    for package in ["hello@1.0.0", "hello@1.1.0"] {
+        assert_success(&sandbox.install(package));
+    }
+    let download = sandbox.root().join("download");
+    assert!(download.join("hello-1.0.0.tar").is_file());
+
+    let refused = sandbox.run(&["prune-downloads"]);
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("No policy to prune"));
+
+    let plan = sandbox.run(&["prune-downloads", "--keep-latest", "1", "--dry-run"]);
+    assert_success(&plan);
+    assert!(String::from_utf8_lossy(&plan.stdout).contains("hello-1.0.0.tar"));
+    assert!(download.join("hello-1.0.0.tar").is_file());
+
+    assert_success(&sandbox.run(&["prune-downloads", "--keep-latest", "1"]));
+    assert!(!download.join("hello-1.0.0.tar").exists());
+    assert!(!download.join("hello-1.0.0").exists());
+    assert!(download.join("hello-1.1.0.tar").is_file());
+    // the installed version still runs
+    assert!(sandbox.apps().join("hello-1.0.0").is_file());
+
+    // the default version is kept whatever the policy says
+    sandbox.configure("[cache.downloads]\nkeep_latest = 0\nprune_after_install = true\n");
+    let installed = sandbox.install("hello@1.2.0");
+    assert_success(&installed);
+    assert!(String::from_utf8_lossy(&installed.stdout).contains("Pruned the download cache"));
+    assert!(!download.join("hello-1.1.0.tar").exists());
+    assert!(download.join("hello-1.2.0.tar").is_file());
+}
+
+#[test]
+fn updates_tell_what_changed() {
+    let server = Server::run();
+    let mut metadata = package_metadata("hello", &["1.1.0", "1.0.0"]);
+    metadata["meta"]["links"] = serde_json::json!({
+        "Repository": "https://github.com/someone/hello",
+        "Changelog": server.url_str("/CHANGELOG.md"),
+    });
+    serve_releases_with(&server, "hello", &["1.1.0", "1.0.0"], metadata);
+    serve_versions_index(&server, &[("hello", &["1.0.0", "1.1.0"])]);
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/CHANGELOG.md")).respond_with(
+            status_code(200)
+                .body("# Changelog\n\n## 1.1.0\n\n- Greets louder\n\n## 1.0.0\n\n- Greets\n"),
+        ),
+    );
+    let sandbox = Sandbox::new(&server);
+    assert_success(&sandbox.install("hello@1.0.0"));
+
+    let gleam = sandbox.gleam.to_str().unwrap();
+    let output = sandbox.run(&["update", "hello", "--notes", "--gleam-path", gleam]);
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let changed: Vec<_> = stdout
+        .lines()
+        .skip_while(|line| *line != "What changed")
+        .map(str::trim)
+        .collect();
+    assert_eq!(
+        changed,
+        [
+            "What changed",
+            "hello 1.0.0 → 1.1.0",
+            "diff       https://diff.hex.pm/diff/hello/1.0.0..1.1.0",
+            "release    https://github.com/someone/hello/releases/tag/v1.1.0",
+            &format!("changelog  {}", server.url_str("/CHANGELOG.md")),
+            "",
+            "1.1.0",
+            "",
+            "- Greets louder",
+        ],
+        "{}",
+        stdout
+    );
+}
+
+/// Serves the metadata of `hello` 1.0.0, leaving its release document and tarball to the test
+fn serve_metadata(server: &Server) {
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/api/packages/hello"))
+            .times(..)
+            .respond_with(status_code(200).body(package_metadata("hello", &["1.0.0"]).to_string())),
+    );
+}
+
+#[test]
+fn interrupted_downloads_are_resumed() {
+    let server = Server::run();
+    serve_metadata(&server);
+    let tarball = hex_tarball("hello", "1.0.0");
+    serve_release(&server, "hello", "1.0.0", &sha256(&tarball));
+    let split = tarball.len() / 2;
+    server.expect(
+        Expectation::matching(all_of![
+            request::method_path("GET", "/repo/tarballs/hello-1.0.0.tar"),
+            request::headers(contains(("range", format!("bytes={}-", split)))),
+        ])
+        .respond_with(status_code(206).body(tarball[split..].to_vec())),
+    );
+    let sandbox = Sandbox::new(&server);
+    let download = sandbox.root().join("download");
+    std::fs::create_dir_all(&download).unwrap();
+    std::fs::write(download.join("hello-1.0.0.tar.part"), &tarball[..split]).unwrap();
+
+    let output = sandbox.install("hello");
+    assert_success(&output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Resuming an earlier download"));
+    assert_eq!(
+        std::fs::read(download.join("hello-1.0.0.tar")).unwrap(),
+        tarball
+    );
+    assert!(!download.join("hello-1.0.0.tar.part").exists());
+}
+
+#[test]
+fn corrupt_downloads_are_rejected() {
+    let server = Server::run();
+    serve_metadata(&server);
+    let mut tarball = hex_tarball("hello", "1.0.0");
+    serve_release(&server, "hello", "1.0.0", &sha256(&tarball));
+    tarball[100] ^= 1;
+    server.expect(
+        Expectation::matching(request::method_path(
+            "GET",
+            "/repo/tarballs/hello-1.0.0.tar",
+        ))
+        .respond_with(status_code(200).body(tarball)),
+    );
+    let sandbox = Sandbox::new(&server);
+
+    let output = sandbox.install("hello");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("but the registry published"), "{}", stderr);
+    let download = sandbox.root().join("download");
+    assert!(!download.join("hello-1.0.0.tar").exists());
+    assert!(!download.join("hello-1.0.0.tar.part").exists());
+}
+
+#[test]
+fn packages_built_with_other_tools_are_refused() {
+    let server = Server::run();
+    serve_metadata(&server);
+    let tarball = hex_tarball_built_with("hello", "1.0.0", "rebar3");
+    serve_release(&server, "hello", "1.0.0", &sha256(&tarball));
+    server.expect(
+        Expectation::matching(request::method_path(
+            "GET",
+            "/repo/tarballs/hello-1.0.0.tar",
+        ))
+        .respond_with(status_code(200).body(tarball)),
+    );
+    let sandbox = Sandbox::new(&server);
+
+    let output = sandbox.install("hello");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("hello 1.0.0 is built with rebar3"),
+        "{}",
+        stderr
+    );
+    assert!(!sandbox.apps().join("hello-1.0.0").exists());
+}
+
+#[test]
+fn binaries_named_differently_are_linked_and_conflicts_refused() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_metadata(&server);
+    let tarball = hex_tarball_exposing("hello", "1.0.0", "hi");
+    serve_release(&server, "hello", "1.0.0", &sha256(&tarball));
+    server.expect(
+        Expectation::matching(request::method_path(
+            "GET",
+            "/repo/tarballs/hello-1.0.0.tar",
+        ))
+        .times(..)
+        .respond_with(status_code(200).body(tarball)),
+    );
+    serve_package(&server, "hi", "2.0.0");
+    let sandbox = Sandbox::new(&server);
+
+    assert_success(&sandbox.install("hello"));
+    assert_eq!(
+        sandbox.database()["packages"]["hello"]["versions"]["1.0.0"]["binary"],
+        "hi"
+    );
+    let apps = sandbox.apps();
+    let run = Command::new(apps.join("hi")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout).trim(), GREETING);
+    let list = sandbox.run(&["list"]);
+    assert!(String::from_utf8_lossy(&list.stdout).contains("BINARY"));
+
+    let output = sandbox.install("hi");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("hi is already a command of hello, pass --overwrite"),
+        "{}",
+        stderr
+    );
+    assert!(sandbox.database()["packages"].get("hi").is_none());
+
+    let output = sandbox.install_with("hi", &["--overwrite"]);
+    assert_success(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("hi was a command of hello"), "{}", stderr);
+    assert_eq!(
+        std::fs::read_link(apps.join("hi")).unwrap(),
+        std::path::Path::new("hi-2.0.0")
+    );
+
+    // the command hello exposes is taken now, an alias replaces it
+    assert!(!sandbox.install("hello").status.success());
+    assert!(apps.join("hello-1.0.0").exists());
+    assert_success(&sandbox.install_with("hello", &["--as", "hello_hi"]));
+    assert_eq!(
+        std::fs::read_link(apps.join("hi")).unwrap(),
+        std::path::Path::new("hi-2.0.0")
+    );
+    assert!(apps.join("hello_hi").exists());
+
+    assert_success(&sandbox.run(&["uninstall", "hello"]));
+    assert!(apps.join("hi").exists());
+    assert!(!apps.join("hello_hi").exists());
+
+    // so is the name of the package itself, the alias then links to the default version
+    std::fs::write(apps.join("hello"), "#!/bin/sh\necho mine\n").unwrap();
+    let output = sandbox.install("hello");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("which gleam-pkg does not manage"),
+        "{}",
+        stderr
+    );
+    assert_success(&sandbox.install_with("hello", &["--as", "hello_hi"]));
+    assert_eq!(
+        std::fs::read_to_string(apps.join("hello")).unwrap(),
+        "#!/bin/sh\necho mine\n"
+    );
+    assert_eq!(
+        std::fs::read_link(apps.join("hello_hi")).unwrap(),
+        std::path::Path::new("hello-1.0.0")
+    );
+    let run = Command::new(apps.join("hello_hi")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout).trim(), GREETING);
+    assert_success(&sandbox.install_with("hello", &["--force"]));
+    assert!(apps.join("hello_hi").exists());
+
+    assert_success(&sandbox.run(&["uninstall", "hello"]));
+    assert!(apps.join("hello").exists());
+    assert!(!apps.join("hello_hi").exists());
+}
+
+#[test]
+fn downloads_fail_over_to_mirrors() {
+    let server = Server::run();
+    serve_metadata(&server);
+    let tarball = hex_tarball("hello", "1.0.0");
+    serve_release(&server, "hello", "1.0.0", &sha256(&tarball));
+    server.expect(
+        Expectation::matching(request::method_path(
+            "GET",
+            "/repo/tarballs/hello-1.0.0.tar",
+        ))
+        .respond_with(status_code(503)),
+    );
+    server.expect(
+        Expectation::matching(request::method_path(
+            "GET",
+            "/mirror/tarballs/hello-1.0.0.tar",
+        ))
+        .respond_with(status_code(200).body(tarball)),
+    );
+    let sandbox = Sandbox::new(&server);
+    sandbox.configure(&format!("mirrors = [\"{}\"]\n", server.url_str("/mirror")));
+
+    let output = sandbox.install("hello");
+    assert_success(&output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("trying the next mirror"));
+    let health: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(sandbox.root().join("cache/mirrors.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        health["repositories"][server.url_str("/repo/")]["failures"],
+        1
+    );
+    assert_eq!(
+        health["repositories"][server.url_str("/mirror/")]["successes"],
+        1
+    );
+}
+
+#[test]
+fn mirrors_are_benchmarked() {
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/repo/names"))
+            .respond_with(status_code(200).body(vec![0; 4096])),
+    );
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/mirror/names"))
+            .respond_with(status_code(502)),
+    );
+    let sandbox = Sandbox::new(&server);
+    sandbox.configure(&format!("mirrors = [\"{}\"]\n", server.url_str("/mirror/")));
+
+    let output = sandbox.run(&["config", "mirrors", "test"]);
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let row = |url: String| {
+        stdout
+            .lines()
+            .find(|line| line.contains(&url) && line.starts_with("hexpm"))
+            .unwrap_or_else(|| panic!("{}", stdout))
+            .to_string()
+    };
+    assert!(row(server.url_str("/repo/")).contains(" ms"), "{}", stdout);
+    assert!(
+        row(server.url_str("/mirror/")).contains("502"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn audits_report_vulnerable_installs() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    server.expect(
+        Expectation::matching(request::method_path("POST", "/osv/querybatch")).respond_with(
+            status_code(200).body(r#"{"results":[{"vulns":[{"id":"GHSA-1234-5678-9abc"}]}]}"#),
+        ),
+    );
+    server.expect(
+        Expectation::matching(request::method_path(
+            "GET",
+            "/osv/vulns/GHSA-1234-5678-9abc",
+        ))
+        .respond_with(
+            status_code(200).body(
+                serde_json::json!({
+                    "id": "GHSA-1234-5678-9abc",
+                    "summary": "hello greets too loudly",
+                    "affected": [{
+                        "package": { "ecosystem": "Hex", "name": "hello" },
+                        "ranges": [{ "type": "SEMVER", "events": [
+                            { "introduced": "0" }, { "fixed": "1.0.1" },
+                        ]}],
+                    }],
+                })
+                .to_string(),
+            ),
+        ),
+    );
+    let sandbox = Sandbox::new(&server);
+    sandbox.configure(&format!(
+        "[audit]\nosv_api = \"{}\"\n",
+        server.url_str("/osv/")
+    ));
+    assert_success(&sandbox.install("hello"));
+
+    let output = sandbox.run(&["audit"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let row = stdout
+        .lines()
+        .find(|line| line.starts_with("hello"))
+        .unwrap_or_else(|| panic!("{}", stdout));
+    assert!(
+        row.contains("GHSA-1234-5678-9abc") && row.ends_with("1.0.1"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("hello greets too loudly"), "{}", stdout);
+}
+
+#[test]
+fn requests_identify_gleam_pkg_and_are_retried() {
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method_path("GET", "/api/packages/hello"),
+            request::headers(contains(("user-agent", matches("^gleam-pkg/[0-9.]+ \\(")))),
+        ])
+        .respond_with(status_code(200).body(package_metadata("hello", &["1.0.0"]).to_string())),
+    );
+    serve_release(&server, "hello", "1.0.0", "00");
+    let sandbox = Sandbox::new(&server);
+    assert_success(&sandbox.run(&["releases", "hello"]));
+
+    // nothing listens on port 1
+    sandbox.configure("[http]\nretries = 1\n");
+    let config = sandbox.root().join("config.toml");
+    let unreachable = std::fs::read_to_string(&config)
+        .unwrap()
+        .replace(&server.url_str("/api/"), "http://127.0.0.1:1/api/");
+    std::fs::write(&config, unreachable).unwrap();
+    assert!(!sandbox.run(&["releases", "other"]).status.success());
+    let stats = sandbox.run(&["stats"]);
+    let stdout = String::from_utf8_lossy(&stats.stdout);
+    assert!(stdout.contains("1 retried"), "{}", stdout);
+}
+
+#[test]
+fn unknown_packages_fail_with_the_status() {
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/api/packages/nope"))
+            .respond_with(status_code(404)),
+    );
+    serve_names(&server, &["hope", "wisp"]);
+    let sandbox = Sandbox::new(&server);
+
+    let output = sandbox.install("nope");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("404"), "{}", stderr);
+    assert!(stderr.contains("did you mean hope?"), "{}", stderr);
+}
+
+#[test]
+fn package_names_are_kept_for_searching_offline_and_completion() {
+    let server = Server::run();
+    serve_names(&server, &["wisp", "wisp_kv", "argv"]);
+    server.expect(
+        Expectation::matching(all_of![
+            request::method_path("GET", "/api/packages"),
+            request::query(url_decoded(contains(("search", "wisp")))),
+        ])
+        .times(2)
+        .respond_with(
+            status_code(200)
+                .insert_header("content-type", "application/json")
+                .body(
+                    serde_json::json!([{
+                        "name": "wisp",
+                        "latest_version": "1.2.0",
+                        "meta": {"description": "A practical web framework"},
+                    }])
+                    .to_string(),
+                ),
+        ),
+    );
+    let sandbox = Sandbox::new(&server);
+    let stdout = |output: Output| {
+        assert_success(&output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let found = stdout(sandbox.run(&["search", "wisp"]));
+    assert!(found.contains("A practical web framework"), "{}", found);
+    // narrowed to COLUMNS, and printed as it is into a pipe despite --pager
+    let found = stdout(sandbox.run_with_env(&["search", "wisp", "--pager"], &[("COLUMNS", "30")]));
+    assert!(
+        found.lines().all(|line| line.chars().count() <= 30),
+        "{}",
+        found
+    );
+    assert!(found.contains("framework"), "{}", found);
+    assert_eq!(
+        stdout(sandbox.run(&["complete-names", "wi"])),
+        "wisp\nwisp_kv\n"
+    );
+    let found = stdout(sandbox.run(&["search", "--offline", "--porcelain", "kv"]));
+    assert_eq!(found, "wisp_kv\n");
+}
+
+#[test]
+fn quiet_installs_print_nothing_and_list_has_a_porcelain_format() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+
+    let output = sandbox.install_with("hello", &["--quiet"]);
+    assert_success(&output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+
+    let list = sandbox.run(&["list", "--porcelain"]);
+    assert_success(&list);
+    let wrapper = sandbox.apps().join("hello-1.0.0");
+    assert_eq!(
+        String::from_utf8_lossy(&list.stdout),
+        format!("hello\t1.0.0\thexpm\t{}\n", wrapper.display())
+    );
+}
+
+#[test]
+fn releases_requiring_a_newer_gleam_fall_back_to_compatible_ones() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    let tarball = hex_tarball("hello", "1.0.0");
+    serve_release(&server, "hello", "1.0.0", &sha256(&tarball));
+    server.expect(
+        Expectation::matching(request::path("/repo/tarballs/hello-1.0.0.tar"))
+            .times(..)
+            .respond_with(status_code(200).body(tarball)),
+    );
+    // the fake gleam is 1.6.0
+    let requirements = [("3.0.0", ">= 1.9.0"), ("2.0.0", "~> 1.7")];
+    for (version, gleam) in requirements {
+        server.expect(
+            Expectation::matching(request::method_path(
+                "GET",
+                format!("/api/packages/hello/releases/{version}"),
+            ))
+            .times(..)
+            .respond_with(
+                status_code(200)
+                    .insert_header("content-type", "application/json")
+                    .body(
+                        serde_json::json!({
+                            "version": version,
+                            "meta": {"build_tools": ["gleam"], "gleam": gleam},
+                        })
+                        .to_string(),
+                    ),
+            ),
+        );
+    }
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/api/packages/hello"))
+            .times(..)
+            .respond_with(
+                status_code(200)
+                    .insert_header("content-type", "application/json")
+                    .body(package_metadata("hello", &["3.0.0", "2.0.0", "1.0.0"]).to_string()),
+            ),
+    );
+    let sandbox = Sandbox::new(&server);
+
+    let output = sandbox.install_with("hello", &["--latest"]);
+    assert_success(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("hello 3.0.0 requires gleam >= 1.9.0, found 1.6.0; installing 1.0.0"),
+        "{}",
+        stderr
+    );
+    assert_eq!(
+        sandbox.database()["packages"]["hello"]["default_version"],
+        "1.0.0"
+    );
+}
+
+#[test]
+fn pre_releases_are_only_installed_when_asked_for() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_releases(&server, "hello", &["2.0.0-rc.1", "1.0.0"]);
+    let sandbox = Sandbox::new(&server);
+
+    // nobody is asked without a terminal, so the newest stable release is installed
+    assert_success(&sandbox.install("hello"));
+    assert_eq!(
+        sandbox.database()["packages"]["hello"]["default_version"],
+        "1.0.0"
+    );
+
+    assert_success(&sandbox.install_with("hello", &["--latest"]));
+    assert_eq!(
+        sandbox.database()["packages"]["hello"]["default_version"],
+        "2.0.0-rc.1"
+    );
+}
+
+#[test]
+fn outdated_packages_are_looked_up_in_the_versions_index() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    assert_success(&sandbox.install("hello"));
+
+    // the cached metadata still says 1.0.0, only the index knows about 1.2.0
+    serve_versions_index(&server, &[("hello", &["1.0.0", "1.2.0"])]);
+    let outdated = sandbox.run(&["outdated", "--porcelain"]);
+    assert_success(&outdated);
+    assert_eq!(
+        String::from_utf8_lossy(&outdated.stdout),
+        "hello\t1.0.0\t1.2.0\t\n"
+    );
+    assert!(sandbox.root().join("cache/index/hexpm.json").is_file());
+}
+
+#[test]
+fn exec_runs_packages_with_their_configured_environment() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    assert_success(&sandbox.install("hello"));
+    sandbox.configure("[packages.hello.env]\nFIXTURE_ECHO = \"configured\"\n");
+
+    let output = sandbox.run(&["exec", "hello@1.0.0", "--", "--flag", "arg"]);
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, format!("{}\nconfigured --flag arg\n", GREETING));
+
+    let output = sandbox.run(&["exec", "hello@2.0.0"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("hello@2.0.0 is not installed"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn wrappers_export_the_configured_environment() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    sandbox.configure("[packages.hello.env]\nFIXTURE_ECHO = \"it's configured\"\n");
+    assert_success(&sandbox.install("hello"));
+
+    let run = || {
+        let output = Command::new(sandbox.apps().join("hello"))
+            .arg("arg")
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    assert_eq!(run(), format!("{}\nit's configured arg\n", GREETING));
+
+    let config = std::fs::read_to_string(sandbox.root().join("config.toml")).unwrap();
+    std::fs::write(
+        sandbox.root().join("config.toml"),
+        config.replace("it's configured", "changed"),
+    )
+    .unwrap();
+    assert_success(&sandbox.run(&["regen-wrappers"]));
+    assert_eq!(run(), format!("{}\nchanged arg\n", GREETING));
+}
+
+#[test]
+fn wrappers_are_regenerated_without_rebuilding() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    assert_success(&sandbox.install("hello"));
+
+    let wrapper = sandbox.apps().join("hello-1.0.0");
+    let original = std::fs::read_to_string(&wrapper).unwrap();
+    std::fs::write(&wrapper, "#!/bin/sh\nexit 1\n").unwrap();
+    assert_success(&sandbox.run(&["alias", "hello", "hi"]));
+    std::fs::remove_file(sandbox.apps().join("hello")).unwrap();
+    std::fs::remove_file(sandbox.apps().join("hi")).unwrap();
+    // the fake gleam is gone, so nothing can be rebuilt
+    std::fs::remove_file(&sandbox.gleam).unwrap();
+    let check = sandbox.run(&["--porcelain", "db", "check"]);
+    assert!(!check.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&check.stdout),
+        "hello: the wrapper hello is missing\nhello: the alias hi is missing\n"
+    );
+
+    let output = sandbox.run(&["regen-wrappers"]);
+    assert_success(&output);
+    assert_success(&sandbox.run(&["db", "check"]));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Regenerated 1 wrappers, 0 were current, restored 2 links"),
+        "{}",
+        stdout
+    );
+    assert_eq!(std::fs::read_to_string(&wrapper).unwrap(), original);
+    let run = Command::new(sandbox.apps().join("hello")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout).trim(), GREETING);
+    let run = Command::new(sandbox.apps().join("hi")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout).trim(), GREETING);
+
+    let output = sandbox.run(&["regen-wrappers"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Regenerated 0 wrappers, 1 were current, restored 0 links"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn installs_the_tools_of_a_project_into_it() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_releases(&server, "hello", &["1.1.0", "1.0.0"]);
+    let sandbox = Sandbox::new(&server);
+    let project = sandbox.home.path().join("project");
+    let nested = project.join("src");
+    std::fs::create_dir_all(&nested).unwrap();
+    assert_success(&sandbox.run_in(&project, &["project", "init"]));
+    let manifest = std::fs::read_to_string(project.join(".gleam-tools.toml")).unwrap();
+    std::fs::write(
+        project.join(".gleam-tools.toml"),
+        manifest + "hello = { version = \"1.0\", target = \"node\" }\n",
+    )
+    .unwrap();
+
+    let gleam = sandbox.gleam.to_str().unwrap();
+    let install = ["project", "install", "--gleam-path", gleam];
+    assert_success(&sandbox.run_in(&nested, &install));
+    let output = Command::new(project.join(".gleam_pkgs/apps/hello"))
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{}\n", GREETING)
+    );
+    assert!(!sandbox.apps().join("hello").exists());
+    assert_eq!(
+        std::fs::read_to_string(project.join(".envrc")).unwrap(),
+        "PATH_add .gleam_pkgs/apps\n"
+    );
+
+    let output = sandbox.run_in(&nested, &install);
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("hello 1.0.0 is already installed"),
+        "{}",
+        stdout
+    );
+
+    let output = sandbox.run_in(sandbox.home.path(), &["project", "install"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No .gleam-tools.toml in"), "{}", stderr);
+}
+
+#[test]
+fn global_wrappers_dispatch_to_the_version_a_project_pins() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_releases(&server, "hello", &["1.1.0", "1.0.0"]);
+    let sandbox = Sandbox::new(&server);
+    sandbox.configure("[packages.hello.env]\nFIXTURE_ECHO = \"global\"\n");
+    assert_success(&sandbox.install("hello@1.1.0"));
+
+    let project = sandbox.home.path().join("project");
+    let nested = project.join("src");
+    std::fs::create_dir_all(&nested).unwrap();
+    std::fs::write(
+        project.join(".gleam-tools.toml"),
+        "[tools]\nhello = { version = \"1.0.0\", target = \"node\" }\n",
+    )
+    .unwrap();
+    let config = std::fs::read_to_string(sandbox.root().join("config.toml")).unwrap();
+    std::fs::write(
+        sandbox.root().join("config.toml"),
+        config.replace("global", "project"),
+    )
+    .unwrap();
+    let gleam = sandbox.gleam.to_str().unwrap();
+    assert_success(&sandbox.run_in(&project, &["project", "install", "--gleam-path", gleam]));
+
+    let run = |command: &str, dir: &std::path::Path, global: bool| {
+        let mut command = Command::new(sandbox.apps().join(command));
+        if global {
+            command.env("GLEAM_PKG_GLOBAL", "1");
+        }
+        let output = command.current_dir(dir).output().unwrap();
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let ran = |echo: &str| format!("{}\n{}\n", GREETING, echo);
+    assert_eq!(run("hello", &nested, false), ran("project"));
+    assert_eq!(run("hello", sandbox.home.path(), false), ran("global"));
+    assert_eq!(run("hello", &nested, true), ran("global"));
+    assert_eq!(run("hello-1.1.0", &nested, false), ran("global"));
+    let output = Command::new(project.join(".gleam_pkgs/apps/hello"))
+        .current_dir(&nested)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), ran("project"));
+}
+
+#[test]
+fn locked_project_installs_reproduce_the_lockfile() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_releases(&server, "hello", &["1.1.0", "1.0.0"]);
+    let sandbox = Sandbox::new(&server);
+    let project = sandbox.home.path().join("project");
+    std::fs::create_dir_all(&project).unwrap();
+    let manifest = project.join(".gleam-tools.toml");
+    std::fs::write(
+        &manifest,
+        "[tools]\nhello = { version = \"1\", target = \"node\" }\n",
+    )
+    .unwrap();
+    let gleam = sandbox.gleam.to_str().unwrap();
+    let install = |locked: bool| {
+        let _ = std::fs::remove_dir_all(project.join(".gleam_pkgs"));
+        let mut args = vec!["project", "install", "--gleam-path", gleam];
+        if locked {
+            args.push("--locked");
+        }
+        sandbox.run_in(&project, &args)
+    };
+
+    let output = install(true);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("does not match .gleam-tools.toml for hello"),
+        "{}",
+        stderr
+    );
+
+    assert_success(&install(false));
+    let lockfile = std::fs::read_to_string(project.join(".gleam-tools.lock")).unwrap();
+    let checksum = sha256(&hex_tarball("hello", "1.1.0"));
+    assert!(
+        lockfile.contains("[tools.hello]\nversion = \"1.1.0\"\n"),
+        "{}",
+        lockfile
+    );
+    assert!(
+        lockfile.contains(&format!("checksum = \"{}\"", checksum)),
+        "{}",
+        lockfile
+    );
+    assert!(lockfile.contains("gleam = \"1.6.0\""), "{}", lockfile);
+
+    assert_success(&install(true));
+    assert!(project.join(".gleam_pkgs/apps/hello-1.1.0").exists());
+    let unchanged = std::fs::read_to_string(project.join(".gleam-tools.lock")).unwrap();
+    assert_eq!(unchanged, lockfile);
+
+    let tampered = lockfile.replace(&checksum, &"0".repeat(64));
+    std::fs::write(project.join(".gleam-tools.lock"), tampered).unwrap();
+    let output = install(true);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("but .gleam-tools.lock locks 0000"),
+        "{}",
+        stderr
+    );
+    assert!(!project.join(".gleam_pkgs/apps/hello-1.1.0").exists());
+
+    std::fs::write(&manifest, "[tools]\nhello = \"2\"\n").unwrap();
+    let output = install(true);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("does not match .gleam-tools.toml for hello"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn ci_verify_checks_the_locked_tools_without_building() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_releases(&server, "hello", &["1.1.0", "1.0.0"]);
+    let sandbox = Sandbox::new(&server);
+    let project = sandbox.home.path().join("project");
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(
+        project.join(".gleam-tools.toml"),
+        "[tools]\nhello = { version = \"1.1\", target = \"node\" }\n",
+    )
+    .unwrap();
+    let gleam = sandbox.gleam.to_str().unwrap();
+    assert_success(&sandbox.run_in(&project, &["project", "install", "--gleam-path", gleam]));
+    std::fs::remove_dir_all(project.join(".gleam_pkgs")).unwrap();
+
+    let verify = ["--porcelain", "ci-verify"];
+    let output = sandbox.run_in(&project, &verify);
+    assert_success(&output);
+    let checksum = sha256(&hex_tarball("hello", "1.1.0"));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("hello\t1.1.0\tok\t{}\t\n", checksum)
+    );
+    assert!(!project.join(".gleam_pkgs/apps/hello-1.1.0").exists());
+    assert!(
+        !project
+            .join(".gleam_pkgs/download/hello-1.1.0.tar")
+            .exists()
+    );
+
+    let lockfile = std::fs::read_to_string(project.join(".gleam-tools.lock")).unwrap();
+    std::fs::write(
+        project.join(".gleam-tools.lock"),
+        lockfile.replace(&checksum, &"0".repeat(64))
+            + "\n[tools.gone]\nversion = \"0.1.0\"\ntarget = \"node\"\nchecksum = \"abc\"\n",
+    )
+    .unwrap();
+    let output = sandbox.run_in(&project, &verify);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let records: Vec<Vec<&str>> = stdout.lines().map(|l| l.split('\t').collect()).collect();
+    assert_eq!(records.len(), 2, "{}", stdout);
+    assert_eq!(records[0][..3], ["hello", "1.1.0", "checksum-mismatch"]);
+    assert_eq!(records[1][..3], ["gone", "0.1.0", "stale"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("2 tool(s) of .gleam-tools.toml"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn tarballs_are_shared_between_users() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let shared = tempfile::tempdir().unwrap();
+    let configure = |sandbox: &Sandbox| {
+        sandbox.configure(&format!(
+            "[cache]\nshared_dir = \"{}\"\n",
+            shared.path().display()
+        ));
+    };
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let first = Sandbox::new(&server);
+    configure(&first);
+    assert_success(&first.install("hello"));
+    let checksum = sha256(&hex_tarball("hello", "1.0.0"));
+    let entry = shared.path().join(format!("tarballs/{}.tar", checksum));
+    assert_eq!(
+        std::fs::metadata(&entry).unwrap().permissions().mode() & 0o777,
+        0o444
+    );
+
+    // the tarball is never requested from this server
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/api/packages/hello")).respond_with(
+            status_code(200)
+                .insert_header("content-type", "application/json")
+                .body(package_metadata("hello", &["1.0.0"]).to_string()),
+        ),
+    );
+    serve_release(&server, "hello", "1.0.0", &checksum);
+    let second = Sandbox::new(&server);
+    configure(&second);
+    let output = second.install("hello");
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(&format!("Using the tarball from: {}", entry.display())),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn bundles_install_on_another_machine_without_the_registry() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let first = Sandbox::new(&server);
+    assert_success(&first.install("hello"));
+    let bundle = first.root().join("hello.tar.gz");
+    assert_success(&first.run(&["bundle", "hello@1.0.0", "-o", bundle.to_str().unwrap()]));
+    // a JavaScript build is not tied to a platform
+    let out = first.root();
+    let elsewhere = first.run_in(&out, &["bundle", "hello", "--target", "macos-aarch64"]);
+    assert_success(&elsewhere);
+    assert!(out.join("hello-1.0.0-node.tar.gz").is_file());
+
+    // nothing is requested from this server
+    let server = Server::run();
+    let second = Sandbox::new(&server);
+    assert_success(&second.run(&["install", "--file", bundle.to_str().unwrap()]));
+    let run = Command::new(second.apps().join("hello")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout).trim(), GREETING);
+    let installed = &second.database()["packages"]["hello"]["versions"]["1.0.0"];
+    assert_eq!(installed["target"], "node");
+    assert_eq!(
+        installed["provenance"]["checksum"],
+        sha256(&hex_tarball("hello", "1.0.0"))
+    );
+
+    assert!(!second.run(&["bundle", "missing"]).status.success());
+    let output = second.run(&[
+        "install",
+        "--file",
+        first.apps().join("hello").to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn offline_commands_make_do_with_cached_metadata() {
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/api/packages/hello"))
+            .times(1)
+            .respond_with(
+                status_code(200)
+                    .insert_header("content-type", "application/json")
+                    .body(package_metadata("hello", &["1.0.0"]).to_string()),
+            ),
+    );
+    let sandbox = Sandbox::new(&server);
+    let offline = sandbox.run(&["info", "hello", "--offline"]);
+    assert!(!offline.status.success());
+    assert!(String::from_utf8_lossy(&offline.stderr).contains("Offline"));
+
+    assert_success(&sandbox.run(&["info", "hello"]));
+    // however old it is, the environment overriding the configuration
+    sandbox.configure("offline = false\n[cache]\nmetadata_ttl_secs = 0\n");
+    let cached = sandbox.run_with_env(&["info", "hello"], &[("GLEAM_PKG_OFFLINE", "1")]);
+    assert_success(&cached);
+    assert!(String::from_utf8_lossy(&cached.stdout).contains("hello 1.0.0"));
+}
+
+#[test]
+fn the_root_moves_with_the_environment() {
+    let server = Server::run();
+    let sandbox = Sandbox::new(&server);
+    let root = sandbox.home.path().join("elsewhere");
+    let output = sandbox.run_with_env(&["list"], &[("GLEAM_PKG_ROOT", root.to_str().unwrap())]);
+    assert_success(&output);
+    assert!(root.join("apps").is_dir());
+    assert!(root.join("db").is_dir());
+}
+
+#[test]
+fn an_installation_in_the_xdg_data_directory_is_found() {
+    let server = Server::run();
+    let sandbox = Sandbox::new(&server);
+    let data = sandbox.home.path().join("data");
+    let xdg = data.join("gleam-pkg");
+    std::fs::create_dir_all(&data).unwrap();
+    std::fs::rename(sandbox.root(), &xdg).unwrap();
+    let env = |vars: &[(&str, &str)]| {
+        let output = sandbox.run_with_env(&["--defaults", "env", "bash"], vars);
+        assert_success(&output);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let data_home = [("XDG_DATA_HOME", data.to_str().unwrap())];
+    assert!(env(&data_home).contains(&xdg.join("apps").display().to_string()));
+    assert!(!sandbox.root().exists());
+
+    // ~/.gleam_pkgs wins when both exist
+    std::fs::create_dir_all(sandbox.root()).unwrap();
+    assert!(env(&data_home).contains(&sandbox.apps().display().to_string()));
+}
+
+#[test]
+fn invalid_package_names_are_refused_before_asking_the_registry() {
+    // nothing is requested from this server
+    let server = Server::run();
+    let sandbox = Sandbox::new(&server);
+    let output = sandbox.run(&["install", "gleam-json"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("did you mean gleam_json?"));
+    assert!(!sandbox.run(&["info", "1password"]).status.success());
+}
+
+#[test]
+fn ensure_installs_only_what_is_missing() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_releases(&server, "hello", &["1.1.0", "1.0.0"]);
+    let sandbox = Sandbox::new(&server);
+    let gleam = sandbox.gleam.to_str().unwrap();
+    let ensure = |spec: &str, api: &str| {
+        sandbox.run_with_env(
+            &["ensure", spec, "--target", "node", "--gleam-path", gleam],
+            &[("GLEAM_PKG_API_BASE", api)],
+        )
+    };
+    let api = server.url_str("/api/");
+    assert_success(&ensure("hello@1.0", &api));
+    assert_eq!(
+        sandbox.database()["packages"]["hello"]["default_version"],
+        "1.0.0"
+    );
+
+    // nothing is requested from this server
+    let unused = Server::run();
+    let offline = unused.url_str("/api/");
+    for spec in ["hello", "hello@1", "hello@1.0", "hello@1.0.0", "HELLO"] {
+        let output = ensure(spec, &offline);
+        assert_success(&output);
+        assert!(String::from_utf8_lossy(&output.stdout).contains("hello 1.0.0 is installed"));
+    }
+    assert_success(&ensure("hello@1.1", &api));
+    assert_eq!(
+        sandbox.database()["packages"]["hello"]["default_version"],
+        "1.1.0"
+    );
+}
+
+#[test]
+fn bootstrap_installs_a_list_of_tools_unattended() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_releases(&server, "hello", &["1.1.0", "1.0.0"]);
+    let sandbox = Sandbox::new(&server);
+    let manifest = sandbox.home.path().join("tools.toml");
+    std::fs::write(
+        &manifest,
+        "[tools]\nhello = { version = \"1.0\", target = \"node\" }\n",
+    )
+    .unwrap();
+    let bootstrap = || {
+        sandbox.run(&[
+            "bootstrap",
+            "--non-interactive",
+            "--manifest",
+            manifest.to_str().unwrap(),
+            "--gleam-path",
+            sandbox.gleam.to_str().unwrap(),
+        ])
+    };
+
+    let output = bootstrap();
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // nothing but the code for the profile
+    assert!(stdout.starts_with("case "));
+    assert!(stdout.contains(&format!(
+        "export PATH=\"$PATH:{}\"",
+        sandbox.apps().display()
+    )));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("root\t{}\n", sandbox.root().display())));
+    assert!(stderr.contains("installed\thello\t1.0.0\n"));
+    assert!(stderr.ends_with(&format!("path\t{}\n", sandbox.apps().display())));
+    assert!(sandbox.apps().join("hello").exists());
+
+    let output = bootstrap();
+    assert_success(&output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("present\thello\t1.0.0\n"));
+}
+
+#[test]
+fn builds_that_crash_when_run_are_not_installed() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    let gleam = sandbox.gleam.to_str().unwrap();
+    let install = || {
+        sandbox.run_with_env(
+            &[
+                "install",
+                "hello",
+                "--target",
+                "node",
+                "--gleam-path",
+                gleam,
+            ],
+            &[("FIXTURE_CRASH", "1")],
+        )
+    };
+
+    let output = install();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("hello 1.0.0 was built, but `hello --help` crashed (exit code 1"),
+        "{}",
+        stderr
+    );
+    assert!(!sandbox.apps().join("hello-1.0.0").exists());
+    assert!(!sandbox.apps().join("hello").exists());
+    let logs: Vec<_> = std::fs::read_dir(sandbox.root().join("logs"))
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+        .collect();
+    assert!(
+        logs.iter()
+            .any(|log| log.contains("==> smoke test `hello --help`"))
+    );
+
+    sandbox.configure("\n[packages.hello]\nsmoke_test = []\n");
+    assert_success(&install());
+    assert!(sandbox.apps().join("hello-1.0.0").is_file());
+}
+
+#[test]
+fn test_install_leaves_the_installation_alone() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    let gleam = sandbox.gleam.to_str().unwrap();
+
+    let output = sandbox.run(&[
+        "--porcelain",
+        "test-install",
+        "hello",
+        "--target",
+        "node",
+        "--gleam-path",
+        gleam,
+    ]);
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let artifacts: Vec<_> = stdout
+        .lines()
+        .map(|line| line.split('\t').collect::<Vec<_>>())
+        .collect();
+    let names: Vec<_> = artifacts.iter().map(|fields| fields[0]).collect();
+    assert_eq!(names, ["tarball", "sources", "build", "wrapper", "total"]);
+    assert!(
+        artifacts
+            .iter()
+            .all(|fields| fields[1].parse::<u64>().unwrap() > 0)
+    );
+    assert!(!std::path::Path::new(artifacts[4][2]).exists());
+    assert!(!sandbox.apps().join("hello-1.0.0").exists());
+    assert!(sandbox.database()["packages"].get("hello").is_none());
+
+    let output = sandbox.run_with_env(
+        &[
+            "test-install",
+            "hello",
+            "--target",
+            "node",
+            "--gleam-path",
+            gleam,
+        ],
+        &[("FIXTURE_CRASH", "1")],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let kept = stderr
+        .lines()
+        .find_map(|line| line.split_once("Kept ")?.1.strip_suffix(" to look into"))
+        .unwrap();
+    assert!(std::path::Path::new(kept).join("logs").is_dir());
+    std::fs::remove_dir_all(kept).unwrap();
+}
+
+#[test]
+fn check_publishable_finds_what_breaks_installs() {
+    let server = Server::run();
+    let sandbox = Sandbox::new(&server);
+    let project = sandbox.root().join("wonderful_cli");
+    std::fs::create_dir_all(project.join("src")).unwrap();
+    std::fs::create_dir_all(project.join("test")).unwrap();
+    std::fs::write(
+        project.join("gleam.toml"),
+        "name = \"wonderful_cli\"\nversion = \"1.0.0\"\ntarget = \"javascript\"\n\
+         gleam = \">= 1.4.0\"\nlicences = [\"MIT\"]\n",
+    )
+    .unwrap();
+    std::fs::write(
+        project.join("src/wonderful_cli.gleam"),
+        "import gleam/io\n\npub fn main() {\n  io.println(\"hi\")\n}\n",
+    )
+    .unwrap();
+    std::fs::write(project.join("test/helpers.gleam"), "pub fn fixture() {}\n").unwrap();
+
+    let output = sandbox.run_in(&project, &["check-publishable", "--files"]);
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("src/wonderful_cli.gleam"), "{}", stdout);
+    assert!(!stdout.contains("test/helpers.gleam"), "{}", stdout);
+
+    std::fs::write(
+        project.join("src/wonderful_cli.gleam"),
+        "import helpers\n\npub fn main() {\n  helpers.fixture()\n}\n",
+    )
+    .unwrap();
+    let output = sandbox.run(&[
+        "--porcelain",
+        "check-publishable",
+        project.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout
+            .lines()
+            .any(|line| line.starts_with("imports\tproblem\t")),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn publish_installs_the_release_just_published() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    let project = sandbox.root().join("hello");
+    std::fs::create_dir_all(project.join("src")).unwrap();
+    std::fs::write(
+        project.join("gleam.toml"),
+        "name = \"hello\"\nversion = \"1.0.0\"\ntarget = \"javascript\"\n",
+    )
+    .unwrap();
+    std::fs::write(project.join("src/hello.gleam"), "pub fn main() {\n}\n").unwrap();
+    let gleam = sandbox.gleam.to_str().unwrap();
+
+    let output = sandbox.run_in(
+        &project,
+        &[
+            "publish",
+            "--target",
+            "node",
+            "--gleam-path",
+            gleam,
+            "--",
+            "--yes",
+        ],
+    );
+    assert_success(&output);
+    assert_eq!(
+        std::fs::read_to_string(project.join("published")).unwrap(),
+        "publish --yes\n"
+    );
+    assert_eq!(
+        sandbox.database()["packages"]["hello"]["default_version"],
+        "1.0.0"
+    );
+
+    std::fs::remove_file(project.join("published")).unwrap();
+    std::fs::write(project.join("src/hello.gleam"), "pub fn main(args) {\n}\n").unwrap();
+    let output = sandbox.run_in(&project, &["publish", "--gleam-path", gleam]);
+    assert!(!output.status.success());
+    assert!(!project.join("published").exists());
+}
+
+#[test]
+fn packages_install_and_update_with_their_configured_defaults() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_releases(&server, "hello", &["3.0.0", "2.1.0", "2.0.0", "1.0.0"]);
+    let sandbox = Sandbox::new(&server);
+    sandbox.configure("[packages.hello]\nversion = \"~> 2.0\"\nbackend = \"nodejs\"\n");
+    let gleam = sandbox.gleam.to_str().unwrap();
+
+    // the version asked for wins over the configured one
+    assert_success(&sandbox.run(&["install", "hello@1.0.0", "--gleam-path", gleam]));
+    let hello = &sandbox.database()["packages"]["hello"];
+    assert_eq!(hello["default_version"], "1.0.0");
+    assert_eq!(hello["versions"]["1.0.0"]["target"], "node");
+
+    // updates stay within the configured version
+    serve_versions_index(&server, &[("hello", &["3.0.0", "2.1.0", "2.0.0", "1.0.0"])]);
+    assert_success(&sandbox.run(&["update", "hello", "--gleam-path", gleam]));
+    assert_eq!(
+        sandbox.database()["packages"]["hello"]["default_version"],
+        "2.1.0"
+    );
+
+    serve_package(&server, "other", "1.0.0");
+    sandbox.configure("[packages.other]\nversion = \"soon\"\n");
+    let output = sandbox.run(&["install", "other", "--gleam-path", gleam]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("is not a version requirement"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn list_tree_shows_the_dependencies_installs_were_built_with() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    let gleam = sandbox.gleam.to_str().unwrap();
+    let manifest = r#"packages = [
+  { name = "argv", version = "1.0.2", requirements = [], source = "hex" },
+  { name = "gleam_stdlib", version = "0.40.0", requirements = [], source = "hex" },
+  { name = "hello", version = "1.0.0", requirements = ["argv", "gleam_stdlib"], source = "local" },
+  { name = "gleam_pkg_build", version = "1.0.0", requirements = ["hello"], source = "local" },
+]"#;
+    let args = [
+        "install",
+        "hello",
+        "--target",
+        "node",
+        "--gleam-path",
+        gleam,
+    ];
+    assert_success(&sandbox.run_with_env(&args, &[("FIXTURE_MANIFEST", manifest)]));
+    // recorded, so pruning the download loses nothing
+    std::fs::remove_dir_all(sandbox.root().join("download/hello-1.0.0")).unwrap();
+
+    let output = sandbox.run(&["list", "--tree", "--porcelain"]);
+    assert_success(&output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "hello\t1.0.0\t1\targv\t1.0.2\thex\nhello\t1.0.0\t1\tgleam_stdlib\t0.40.0\thex\n"
+    );
+    let output = sandbox.run(&["list", "--tree"]);
+    assert_success(&output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "hello 1.0.0\n  argv 1.0.2\n  gleam_stdlib 0.40.0\n"
+    );
+    let output = sandbox.run(&["sbom"]);
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("pkg:hex/argv@1.0.2"), "{}", stdout);
+}
+
+#[test]
+fn why_tells_which_tools_depend_on_a_package() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    serve_package(&server, "other", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    let gleam = sandbox.gleam.to_str().unwrap();
+    let manifest = r#"packages = [
+  { name = "glint", version = "1.1.0", requirements = ["snag"], source = "hex" },
+  { name = "snag", version = "0.3.0", requirements = [], source = "hex" },
+  { name = "hello", version = "1.0.0", requirements = ["glint"], source = "local" },
+]"#;
+    let args = [
+        "install",
+        "hello",
+        "--target",
+        "node",
+        "--gleam-path",
+        gleam,
+    ];
+    assert_success(&sandbox.run_with_env(&args, &[("FIXTURE_MANIFEST", manifest)]));
+    assert_success(&sandbox.install("other"));
+
+    let output = sandbox.run(&["why", "snag", "--porcelain"]);
+    assert_success(&output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "hello\t1.0.0\t0.3.0\thello > glint > snag\n"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("1 installed version(s) have no recorded dependencies"),
+        "{}",
+        stderr
+    );
+
+    let output = sandbox.run(&["why", "simplifile"]);
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("No installed tool depends on simplifile"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn downloads_are_throttled_to_the_limit_rate() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+
+    let output = sandbox.install_with("hello", &["--limit-rate", "fast"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("not a rate like 500K"), "{}", stderr);
+
+    // at 2K a second, the tarball takes a second or more
+    assert!(hex_tarball("hello", "1.0.0").len() > 2048);
+    sandbox.configure("[http]\nlimit_rate = \"2K\"\n");
+    let started = std::time::Instant::now();
+    assert_success(&sandbox.install("hello"));
+    assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+}
+
+#[test]
+fn resolve_and_ip_family_decide_where_requests_connect() {
+    if !has_program("node") {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    let addr = server.addr();
+    let api_base = format!("http://hex.invalid:{}/api/", addr.port());
+    let repository_base = format!("http://hex.invalid:{}/repo/", addr.port());
+    let resolve = format!("hex.invalid:{}:[{}]", addr.port(), addr.ip());
+    let gleam = sandbox.gleam.to_str().unwrap();
+    let install = |extra: &[&str]| {
+        let mut args = vec![
+            "install",
+            "hello",
+            "--target",
+            "node",
+            "--gleam-path",
+            gleam,
+        ];
+        args.extend_from_slice(extra);
+        sandbox.run_with_env(
+            &args,
+            &[
+                ("GLEAM_PKG_API_BASE", &api_base),
+                ("GLEAM_PKG_REPOSITORY_BASE", &repository_base),
+            ],
+        )
+    };
+
+    let output = install(&["--resolve", "hex.invalid:http:127.0.0.1"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is not host:port:address"), "{}", stderr);
+    assert!(!install(&["--ipv4", "--ipv6"]).status.success());
+
+    // held to the other family, the server cannot be reached at its address
+    let other = if addr.is_ipv4() { "--ipv6" } else { "--ipv4" };
+    assert!(!install(&["--resolve", &resolve, other]).status.success());
+
+    let family = if addr.is_ipv4() { "ipv4" } else { "ipv6" };
+    sandbox.configure(&format!(
+        "[http]\nretries = 0\nip_family = \"{}\"\n",
+        family
+    ));
+    assert_success(&install(&["--resolve", &resolve]));
+}
+
+#[test]
+fn installs_without_a_runtime_stop_before_downloading() {
+    let server = Server::run();
+    let sandbox = Sandbox::new(&server);
+    let gleam = sandbox.gleam.to_str().unwrap();
+
+    // no expectations: any request to the registry fails the test
+    let output = sandbox.run_with_env(
+        &["install", "hello", "--gleam-path", gleam],
+        &[("PATH", "/nonexistent")],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("erl"), "{}", stderr);
+}
+
+#[test]
+fn installs_check_the_runtime_of_the_configured_backend() {
+    let server = Server::run();
+    serve_package(&server, "hello", "1.0.0");
+    let sandbox = Sandbox::new(&server);
+    let gleam = sandbox.gleam.to_str().unwrap();
+    // Deno is the only runtime there is
+    let bin = sandbox.root().join("fake-bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    let deno = bin.join("deno");
+    std::fs::write(&deno, "#!/bin/sh\necho deno 2.0.0\n").unwrap();
+    std::fs::set_permissions(&deno, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+    let path = bin.to_str().unwrap();
+
+    sandbox.configure("[packages.hello]\nbackend = \"deno\"\n");
+    let output = sandbox.run_with_env(
+        &["install", "hello", "--gleam-path", gleam],
+        &[("PATH", path)],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Using Deno deno 2.0.0"), "{}", stdout);
+
+    // rebar3 builds need no gleam, but rebar3
+    sandbox.configure("[packages.wonderful_cli]\nbackend = \"rebar3\"\n");
+    let output = sandbox.run_with_env(&["install", "wonderful_cli"], &[("PATH", path)]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("rebar3"), "{}", stderr);
+    assert!(!stderr.contains("gleam"), "{}", stderr);
+}